@@ -0,0 +1,112 @@
+// admin.rs - Operator moderation queue: fraud flags, disputes, held reviews and the blocklist
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::PubkeyId;
+use crate::moderation::HeldReview;
+use crate::DeliveryRequest;
+
+// `d` tag of the NIP-78 event holding the blocklist, encrypted to the system key
+pub const BLOCKLIST_IDENTIFIER: &str = "nostr-delivery/blocklist";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum AdminAction {
+    Approve,
+    Reject,
+    // Leave the item as is and record it for a senior operator
+    Escalate,
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct ActionRequest {
+    pub action: AdminAction,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QueueItem {
+    FraudFlag,
+    Dispute,
+    HeldReview,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Escalation {
+    pub item: QueueItem,
+    // Delivery ID, or review ID for held reviews
+    pub id: String,
+    pub note: Option<String>,
+    pub escalated_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BlockedPubkey {
+    // npub
+    pub pubkey: String,
+    pub reason: Option<String>,
+    pub blocked_at: i64,
+}
+
+// Pubkeys barred from bidding on or creating deliveries
+#[derive(Debug, Clone, Default)]
+pub struct Blocklist {
+    entries: Vec<BlockedPubkey>,
+}
+
+impl Blocklist {
+    // Seeded from BLOCKED_PUBKEYS (comma-separated npub or hex)
+    pub fn from_env(now: i64) -> Self {
+        let mut blocklist = Self::default();
+        for key in std::env::var("BLOCKED_PUBKEYS").unwrap_or_default().split(',') {
+            if !key.trim().is_empty() {
+                blocklist.block(key, None, now);
+            }
+        }
+        blocklist
+    }
+
+    // Entries stored before the last restart, added to the seed; they keep their reason and time
+    pub fn restore(&mut self, entries: Vec<BlockedPubkey>) {
+        for entry in entries {
+            self.entries.retain(|e| e.pubkey != entry.pubkey);
+            self.entries.push(entry);
+        }
+    }
+
+    pub fn contains(&self, pubkey: &str) -> bool {
+        let pubkey = PubkeyId::normalize(pubkey);
+        self.entries.iter().any(|e| e.pubkey == pubkey)
+    }
+
+    pub fn block(&mut self, pubkey: &str, reason: Option<String>, now: i64) -> BlockedPubkey {
+        let pubkey = PubkeyId::normalize(pubkey);
+        self.entries.retain(|e| e.pubkey != pubkey);
+        let entry = BlockedPubkey { pubkey, reason, blocked_at: now };
+        self.entries.push(entry.clone());
+        entry
+    }
+
+    // Whether the pubkey was blocked
+    pub fn unblock(&mut self, pubkey: &str) -> bool {
+        let pubkey = PubkeyId::normalize(pubkey);
+        let before = self.entries.len();
+        self.entries.retain(|e| e.pubkey != pubkey);
+        self.entries.len() != before
+    }
+
+    pub fn entries(&self) -> &[BlockedPubkey] {
+        &self.entries
+    }
+}
+
+// Everything awaiting an operator
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ModerationQueue {
+    pub flagged: Vec<DeliveryRequest>,
+    pub disputes: Vec<DeliveryRequest>,
+    pub held_reviews: Vec<HeldReview>,
+    pub blocklist: Vec<BlockedPubkey>,
+    pub escalations: Vec<Escalation>,
+}
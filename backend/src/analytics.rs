@@ -0,0 +1,607 @@
+// analytics.rs - Aggregated reports computed over delivery history
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use std::collections::HashMap;
+
+use crate::projection::PaymentState;
+use crate::{Amount, BoundingBox, DeliveryRequest, DeliveryStatus, GeoPoint, TimeWindow, UserProfile};
+
+// Whether a timestamp falls inside an optional [from, to] range
+pub fn in_range(ts: i64, from: Option<i64>, to: Option<i64>) -> bool {
+    from.is_none_or(|f| ts >= f) && to.is_none_or(|t| ts <= t)
+}
+
+// Category used for breakdowns: the size of the first package
+pub fn delivery_category(delivery: &DeliveryRequest) -> String {
+    delivery
+        .packages
+        .first()
+        .map(|p| p.size.to_lowercase())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+// Sats actually paid out for a delivery (completed, confirmed, or forfeited on cancel)
+pub fn settled_amount(delivery: &DeliveryRequest) -> Option<Amount> {
+    (PaymentState::of(&delivery.status, delivery.accepted_bid.is_some()) == PaymentState::Settled)
+        .then_some(delivery.offer_amount)
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct CategorySpending {
+    pub deliveries: u32,
+    pub sats_spent: Amount,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SpendingReport {
+    pub npub: String,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    pub deliveries_created: u32,
+    pub deliveries_settled: u32,
+    pub total_sats_spent: Amount,
+    pub pending_sats: Amount,
+    pub avg_cost_per_km: Option<f64>,
+    pub by_category: HashMap<String, CategorySpending>,
+}
+
+impl SpendingReport {
+    // Build a sender's spending report from the deliveries they created within the range
+    pub fn build(npub: &str, deliveries: &[DeliveryRequest], from: Option<i64>, to: Option<i64>) -> Self {
+        let mut report = SpendingReport {
+            npub: npub.to_string(),
+            from,
+            to,
+            deliveries_created: 0,
+            deliveries_settled: 0,
+            total_sats_spent: Amount::ZERO,
+            pending_sats: Amount::ZERO,
+            avg_cost_per_km: None,
+            by_category: HashMap::new(),
+        };
+
+        let mut priced_sats = Amount::ZERO;
+        let mut priced_km = 0.0;
+
+        for delivery in deliveries
+            .iter()
+            .filter(|d| d.sender == npub && in_range(d.created_at, from, to))
+        {
+            report.deliveries_created += 1;
+            let category = report.by_category.entry(delivery_category(delivery)).or_default();
+            category.deliveries += 1;
+
+            if let Some(amount) = settled_amount(delivery) {
+                report.deliveries_settled += 1;
+                report.total_sats_spent = report.total_sats_spent.saturating_add(amount);
+                category.sats_spent = category.sats_spent.saturating_add(amount);
+
+                if let Some(meters) = delivery.distance_meters.filter(|m| *m > 0.0) {
+                    priced_sats = priced_sats.saturating_add(amount);
+                    priced_km += meters / 1000.0;
+                }
+            } else if matches!(delivery.status, DeliveryStatus::Accepted | DeliveryStatus::InTransit) {
+                report.pending_sats = report.pending_sats.saturating_add(delivery.offer_amount);
+            }
+        }
+
+        if priced_km > 0.0 {
+            report.avg_cost_per_km = Some(priced_sats.sats() as f64 / priced_km);
+        }
+
+        report
+    }
+}
+
+// Median of a list of values (sorts in place)
+pub fn median(values: &mut [f64]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let mid = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        Some((values[mid - 1] + values[mid]) / 2.0)
+    } else {
+        Some(values[mid])
+    }
+}
+
+// Rolling windows reported by the marketplace stats endpoint
+pub const STATS_WINDOWS: &[(&str, i64)] = &[("24h", 86_400), ("7d", 604_800), ("30d", 2_592_000)];
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct WindowStats {
+    pub deliveries_created: u32,
+    pub active_couriers: u32,
+    pub median_time_to_first_bid_secs: Option<f64>,
+    pub median_accepted_price_per_km: Option<f64>,
+    pub completion_rate: Option<f64>,
+    pub dispute_rate: Option<f64>,
+}
+
+impl WindowStats {
+    // Compute stats over deliveries created at or after `since`
+    pub fn compute(deliveries: &[DeliveryRequest], since: i64) -> Self {
+        let mut stats = WindowStats::default();
+        let mut couriers = std::collections::HashSet::new();
+        let mut first_bid_delays = Vec::new();
+        let mut prices_per_km = Vec::new();
+        let mut accepted = 0u32;
+        let mut completed = 0u32;
+        let mut disputed = 0u32;
+
+        for delivery in deliveries.iter().filter(|d| d.created_at >= since) {
+            stats.deliveries_created += 1;
+
+            for bid in &delivery.bids {
+                couriers.insert(bid.courier.as_str());
+            }
+            if let Some(first_bid) = delivery.bids.iter().map(|b| b.created_at).min() {
+                first_bid_delays.push((first_bid - delivery.created_at).max(0) as f64);
+            }
+
+            if delivery.accepted_bid.is_none() {
+                continue;
+            }
+            accepted += 1;
+            match delivery.status {
+                DeliveryStatus::Completed | DeliveryStatus::Confirmed => completed += 1,
+                DeliveryStatus::Disputed => disputed += 1,
+                _ => {}
+            }
+            if let Some(meters) = delivery.distance_meters.filter(|m| *m > 0.0) {
+                prices_per_km.push(delivery.offer_amount.sats() as f64 / (meters / 1000.0));
+            }
+        }
+
+        stats.active_couriers = couriers.len() as u32;
+        stats.median_time_to_first_bid_secs = median(&mut first_bid_delays);
+        stats.median_accepted_price_per_km = median(&mut prices_per_km);
+        if accepted > 0 {
+            stats.completion_rate = Some(completed as f64 / accepted as f64);
+            stats.dispute_rate = Some(disputed as f64 / accepted as f64);
+        }
+
+        stats
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct MarketStats {
+    pub generated_at: i64,
+    pub open_deliveries: u32,
+    pub windows: std::collections::BTreeMap<String, WindowStats>,
+}
+
+impl MarketStats {
+    pub fn compute(deliveries: &[DeliveryRequest], now: i64) -> Self {
+        let open_deliveries = deliveries
+            .iter()
+            .filter(|d| d.status == DeliveryStatus::Open && d.expires_at.is_none_or(|e| e > now))
+            .count() as u32;
+
+        let windows = STATS_WINDOWS
+            .iter()
+            .map(|(name, secs)| (name.to_string(), WindowStats::compute(deliveries, now - secs)))
+            .collect();
+
+        MarketStats {
+            generated_at: now,
+            open_deliveries,
+            windows,
+        }
+    }
+}
+
+// Value at percentile `p` (0.0-1.0) of an already sorted slice
+pub fn percentile(sorted: &[f64], p: f64) -> Option<f64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let idx = ((sorted.len() - 1) as f64 * p).round() as usize;
+    sorted.get(idx).copied()
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct PriceDistribution {
+    pub samples: u32,
+    pub min: Option<f64>,
+    pub p25: Option<f64>,
+    pub median: Option<f64>,
+    pub p75: Option<f64>,
+    pub max: Option<f64>,
+}
+
+impl PriceDistribution {
+    pub fn from_values(mut values: Vec<f64>) -> Self {
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        PriceDistribution {
+            samples: values.len() as u32,
+            min: values.first().copied(),
+            p25: percentile(&values, 0.25),
+            median: percentile(&values, 0.5),
+            p75: percentile(&values, 0.75),
+            max: values.last().copied(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PriceBucket {
+    pub period_start: i64,
+    pub amount_sats: PriceDistribution,
+    pub sats_per_km: PriceDistribution,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct RoutePriceHistory {
+    pub from_geohash: String,
+    pub to_geohash: String,
+    pub interval_secs: i64,
+    pub overall: PriceBucket,
+    pub buckets: Vec<PriceBucket>,
+}
+
+impl RoutePriceHistory {
+    // Accepted prices for deliveries whose pickup/dropoff geohashes share the given prefixes
+    pub fn build(deliveries: &[DeliveryRequest], from_geohash: &str, to_geohash: &str, interval_secs: i64) -> Self {
+        let from_geohash = from_geohash.to_lowercase();
+        let to_geohash = to_geohash.to_lowercase();
+        let interval_secs = interval_secs.max(1);
+
+        let matches: Vec<&DeliveryRequest> = deliveries
+            .iter()
+            .filter(|d| d.accepted_bid.is_some())
+            .filter(|d| match (&d.pickup.coordinates, &d.dropoff.coordinates) {
+                (Some(p1), Some(p2)) => {
+                    crate::geohash_encode(p1, from_geohash.len()) == from_geohash
+                        && crate::geohash_encode(p2, to_geohash.len()) == to_geohash
+                }
+                _ => false,
+            })
+            .collect();
+
+        let bucket_for = |period_start: i64, items: &[&DeliveryRequest]| PriceBucket {
+            period_start,
+            amount_sats: PriceDistribution::from_values(items.iter().map(|d| d.offer_amount.sats() as f64).collect()),
+            sats_per_km: PriceDistribution::from_values(
+                items
+                    .iter()
+                    .filter_map(|d| d.distance_meters.filter(|m| *m > 0.0).map(|m| d.offer_amount.sats() as f64 / (m / 1000.0)))
+                    .collect(),
+            ),
+        };
+
+        let mut grouped: std::collections::BTreeMap<i64, Vec<&DeliveryRequest>> = std::collections::BTreeMap::new();
+        for delivery in &matches {
+            let period_start = delivery.created_at - delivery.created_at.rem_euclid(interval_secs);
+            grouped.entry(period_start).or_default().push(delivery);
+        }
+
+        RoutePriceHistory {
+            overall: bucket_for(0, &matches),
+            buckets: grouped.iter().map(|(start, items)| bucket_for(*start, items)).collect(),
+            from_geohash,
+            to_geohash,
+            interval_secs,
+        }
+    }
+}
+
+pub const DEFAULT_HEATMAP_ZOOM: u8 = 12;
+pub const MAX_HEATMAP_ZOOM: u8 = 18;
+
+// Web Mercator latitude limit
+const MAX_MERCATOR_LAT: f64 = 85.051_128_78;
+
+// Slippy-map tile containing a point at the given zoom
+pub fn tile_for(point: &GeoPoint, zoom: u8) -> (u32, u32) {
+    let n = 2f64.powi(zoom as i32);
+    let lat = point.lat.clamp(-MAX_MERCATOR_LAT, MAX_MERCATOR_LAT).to_radians();
+    let x = ((point.lng + 180.0) / 360.0 * n).floor();
+    let y = ((1.0 - lat.tan().asinh() / std::f64::consts::PI) / 2.0 * n).floor();
+    let max = n as u32 - 1;
+    ((x.max(0.0) as u32).min(max), (y.max(0.0) as u32).min(max))
+}
+
+fn tile_lat(y: f64, n: f64) -> f64 {
+    (std::f64::consts::PI * (1.0 - 2.0 * y / n)).sinh().atan().to_degrees()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct HeatmapCell {
+    pub x: u32,
+    pub y: u32,
+    // [min_lng, min_lat, max_lng, max_lat]
+    pub bounds: [f64; 4],
+    pub center: GeoPoint,
+    pub pickups: u32,
+    pub dropoffs: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Heatmap {
+    pub zoom: u8,
+    pub total_pickups: u32,
+    pub total_dropoffs: u32,
+    pub cells: Vec<HeatmapCell>,
+}
+
+impl Heatmap {
+    // Pickup and dropoff counts per map tile, limited to points inside `bbox`
+    pub fn build<'a, I>(deliveries: I, bbox: Option<&BoundingBox>, zoom: u8) -> Self
+    where
+        I: IntoIterator<Item = &'a DeliveryRequest>,
+    {
+        let zoom = zoom.min(MAX_HEATMAP_ZOOM);
+        let in_bbox = |point: &GeoPoint| bbox.is_none_or(|b| b.contains(point));
+
+        let mut counts: std::collections::BTreeMap<(u32, u32), (u32, u32)> = std::collections::BTreeMap::new();
+        for delivery in deliveries {
+            if let Some(point) = delivery.pickup.coordinates.as_ref().filter(|p| in_bbox(p)) {
+                counts.entry(tile_for(point, zoom)).or_default().0 += 1;
+            }
+            if let Some(point) = delivery.dropoff.coordinates.as_ref().filter(|p| in_bbox(p)) {
+                counts.entry(tile_for(point, zoom)).or_default().1 += 1;
+            }
+        }
+
+        let n = 2f64.powi(zoom as i32);
+        let cells: Vec<HeatmapCell> = counts
+            .into_iter()
+            .map(|((x, y), (pickups, dropoffs))| {
+                let min_lng = x as f64 / n * 360.0 - 180.0;
+                let max_lng = (x + 1) as f64 / n * 360.0 - 180.0;
+                let max_lat = tile_lat(y as f64, n);
+                let min_lat = tile_lat((y + 1) as f64, n);
+                HeatmapCell {
+                    x,
+                    y,
+                    bounds: [min_lng, min_lat, max_lng, max_lat],
+                    center: GeoPoint {
+                        lat: tile_lat(y as f64 + 0.5, n),
+                        lng: (min_lng + max_lng) / 2.0,
+                    },
+                    pickups,
+                    dropoffs,
+                }
+            })
+            .collect();
+
+        Heatmap {
+            zoom,
+            total_pickups: cells.iter().map(|c| c.pickups).sum(),
+            total_dropoffs: cells.iter().map(|c| c.dropoffs).sum(),
+            cells,
+        }
+    }
+}
+
+// Rolling leaderboard periods (name, seconds)
+pub const LEADERBOARD_PERIODS: [(&str, i64); 2] = [("week", 7 * 86400), ("month", 30 * 86400)];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LeaderboardMetric {
+    Deliveries,
+    Earnings,
+    Rating,
+}
+
+impl LeaderboardMetric {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "deliveries" => Some(Self::Deliveries),
+            "earnings" => Some(Self::Earnings),
+            "rating" => Some(Self::Rating),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct LeaderboardEntry {
+    pub rank: u32,
+    pub npub: String,
+    pub display_name: Option<String>,
+    pub deliveries: u32,
+    pub earnings_sats: Amount,
+    pub average_rating: Option<f32>,
+    pub ratings: u32,
+}
+
+// Per-period courier totals, ranked on request
+#[derive(Debug, Clone, Default)]
+pub struct Leaderboards {
+    periods: std::collections::BTreeMap<String, Vec<LeaderboardEntry>>,
+}
+
+impl Leaderboards {
+    // Totals over completed deliveries for couriers who opted in
+    pub fn compute(deliveries: &[DeliveryRequest], participants: &[UserProfile], now: i64) -> Self {
+        let mut periods = std::collections::BTreeMap::new();
+
+        for (name, secs) in LEADERBOARD_PERIODS {
+            let since = now - secs;
+            let mut entries: Vec<LeaderboardEntry> = Vec::new();
+
+            for profile in participants {
+                let mut entry = LeaderboardEntry {
+                    npub: profile.npub.clone(),
+                    display_name: profile.display_name.clone(),
+                    ..Default::default()
+                };
+                let mut rating_sum = 0.0;
+
+                for delivery in deliveries
+                    .iter()
+                    .filter(|d| matches!(d.status, DeliveryStatus::Completed | DeliveryStatus::Confirmed))
+                    .filter(|d| d.completed_at.unwrap_or(d.created_at) >= since)
+                    .filter(|d| d.accepted_courier() == Some(profile.npub.as_str()))
+                {
+                    entry.deliveries += 1;
+                    entry.earnings_sats = entry.earnings_sats.saturating_add(settled_amount(delivery).unwrap_or_default());
+                    if let Some(rating) = delivery.sender_rating {
+                        rating_sum += rating;
+                        entry.ratings += 1;
+                    }
+                }
+
+                if entry.ratings > 0 {
+                    entry.average_rating = Some(rating_sum / entry.ratings as f32);
+                }
+                if entry.deliveries > 0 {
+                    entries.push(entry);
+                }
+            }
+
+            periods.insert(name.to_string(), entries);
+        }
+
+        Leaderboards { periods }
+    }
+
+    // Top `limit` couriers for a period by the chosen metric; None for an unknown period
+    pub fn ranked(&self, period: &str, metric: LeaderboardMetric, limit: usize) -> Option<Vec<LeaderboardEntry>> {
+        let mut entries = self.periods.get(period)?.clone();
+
+        match metric {
+            LeaderboardMetric::Deliveries => {
+                entries.sort_by(|a, b| b.deliveries.cmp(&a.deliveries).then(b.earnings_sats.cmp(&a.earnings_sats)))
+            }
+            LeaderboardMetric::Earnings => {
+                entries.sort_by(|a, b| b.earnings_sats.cmp(&a.earnings_sats).then(b.deliveries.cmp(&a.deliveries)))
+            }
+            LeaderboardMetric::Rating => {
+                entries.retain(|e| e.average_rating.is_some());
+                entries.sort_by(|a, b| {
+                    b.average_rating
+                        .partial_cmp(&a.average_rating)
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                        .then(b.ratings.cmp(&a.ratings))
+                })
+            }
+        }
+
+        entries.truncate(limit);
+        for (i, entry) in entries.iter_mut().enumerate() {
+            entry.rank = i as u32 + 1;
+        }
+
+        Some(entries)
+    }
+}
+
+// Width of the buckets in a courier's rating trend
+pub const RATING_TREND_BUCKET_SECS: i64 = 30 * 86400;
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct RatingTrendPoint {
+    pub period_start: i64,
+    pub average_rating: f32,
+    pub ratings: u32,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct CourierStats {
+    pub npub: String,
+    pub bids_placed: u32,
+    pub jobs_accepted: u32,
+    pub acceptance_rate: Option<f64>,
+    pub jobs_completed: u32,
+    pub on_time_rate: Option<f64>,
+    // Share of completed deliveries handed over by the dropoff ETA the courier bid
+    pub eta_met_rate: Option<f64>,
+    pub average_rating: Option<f32>,
+    pub rating_trend: Vec<RatingTrendPoint>,
+    pub cancellations: u32,
+    pub disputes: u32,
+    pub average_distance_meters: Option<f64>,
+    pub earnings_sats: Amount,
+}
+
+impl CourierStats {
+    // Derived from the courier's bids and the deliveries they were accepted for
+    pub fn build(npub: &str, deliveries: &[DeliveryRequest]) -> Self {
+        let mut stats = CourierStats {
+            npub: npub.to_string(),
+            ..Default::default()
+        };
+
+        let mut on_time = 0u32;
+        let mut timed = 0u32;
+        let mut eta_met = 0u32;
+        let mut eta_promised = 0u32;
+        let mut distances = Vec::new();
+        let mut ratings: std::collections::BTreeMap<i64, Vec<f32>> = std::collections::BTreeMap::new();
+
+        for delivery in deliveries {
+            stats.bids_placed += delivery.bids.iter().filter(|b| b.courier == npub).count() as u32;
+
+            if delivery.accepted_courier() != Some(npub) {
+                continue;
+            }
+            stats.jobs_accepted += 1;
+            stats.earnings_sats = stats.earnings_sats.saturating_add(settled_amount(delivery).unwrap_or_default());
+            if let Some(meters) = delivery.distance_meters {
+                distances.push(meters);
+            }
+
+            match delivery.status {
+                DeliveryStatus::Completed | DeliveryStatus::Confirmed => {
+                    stats.jobs_completed += 1;
+                    if let (Some(completed_at), Some(end)) = (delivery.completed_at, TimeWindow::parse(&delivery.time_window).end) {
+                        timed += 1;
+                        if completed_at <= end {
+                            on_time += 1;
+                        }
+                    }
+                    let promised = delivery.bids.iter().find(|b| delivery.accepted_bid.as_ref() == Some(&b.id)).and_then(|b| b.dropoff_eta);
+                    if let (Some(completed_at), Some(eta)) = (delivery.completed_at, promised) {
+                        eta_promised += 1;
+                        if completed_at <= eta {
+                            eta_met += 1;
+                        }
+                    }
+                }
+                DeliveryStatus::Disputed => stats.disputes += 1,
+                // Cancelled after acceptance
+                DeliveryStatus::Expired => stats.cancellations += 1,
+                _ => {}
+            }
+
+            if let Some(rating) = delivery.sender_rating {
+                let at = delivery.completed_at.unwrap_or(delivery.created_at);
+                ratings.entry(at - at.rem_euclid(RATING_TREND_BUCKET_SECS)).or_default().push(rating);
+            }
+        }
+
+        if stats.bids_placed > 0 {
+            stats.acceptance_rate = Some(stats.jobs_accepted as f64 / stats.bids_placed as f64);
+        }
+        if timed > 0 {
+            stats.on_time_rate = Some(on_time as f64 / timed as f64);
+        }
+        if eta_promised > 0 {
+            stats.eta_met_rate = Some(eta_met as f64 / eta_promised as f64);
+        }
+        if !distances.is_empty() {
+            stats.average_distance_meters = Some(distances.iter().sum::<f64>() / distances.len() as f64);
+        }
+
+        let all: Vec<f32> = ratings.values().flatten().copied().collect();
+        if !all.is_empty() {
+            stats.average_rating = Some(all.iter().sum::<f32>() / all.len() as f32);
+        }
+        stats.rating_trend = ratings
+            .into_iter()
+            .map(|(period_start, values)| RatingTrendPoint {
+                period_start,
+                average_rating: values.iter().sum::<f32>() / values.len() as f32,
+                ratings: values.len() as u32,
+            })
+            .collect();
+
+        stats
+    }
+}
@@ -0,0 +1,74 @@
+// api_keys.rs - Keys for server-to-server integrations: each is scoped to one organization and a
+// set of permissions, and only its hash is stored
+use nostr::hashes::{sha256, Hash};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::codes;
+
+pub const HEADER: &str = "x-api-key";
+// `d` tag of the NIP-78 event holding every key, encrypted to the system key
+pub const APP_DATA_IDENTIFIER: &str = "nostr-delivery/api-keys";
+const SECRET_PREFIX: &str = "ndk_";
+// Characters of the secret kept to tell keys apart in listings
+const DISPLAY_PREFIX_LEN: usize = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum Permission {
+    #[serde(rename = "deliveries:create")]
+    CreateDeliveries,
+    #[serde(rename = "deliveries:update")]
+    UpdateDeliveries,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ApiKey {
+    pub id: String,
+    pub name: String,
+    // Organization whose deliveries the key creates and manages
+    pub organization: String,
+    pub permissions: Vec<Permission>,
+    // Start of the secret, to recognize the key by
+    pub prefix: String,
+    #[serde(skip_serializing_if = "String::is_empty", default)]
+    #[schema(ignore)]
+    pub secret_hash: String,
+    pub created_at: i64,
+    pub revoked_at: Option<i64>,
+}
+
+impl ApiKey {
+    // A new key and its secret, which is shown once and never stored
+    pub fn generate(id: String, name: String, organization: String, permissions: Vec<Permission>, now: i64) -> (Self, String) {
+        let bytes: [u8; 32] = nostr::secp256k1::rand::random();
+        let secret = format!("{}{}", SECRET_PREFIX, bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>());
+        let key = Self {
+            id,
+            name,
+            organization,
+            permissions,
+            prefix: secret[..DISPLAY_PREFIX_LEN].to_string(),
+            secret_hash: hash_secret(&secret),
+            created_at: now,
+            revoked_at: None,
+        };
+        (key, secret)
+    }
+
+    pub fn matches(&self, secret: &str) -> bool {
+        self.revoked_at.is_none() && codes::codes_match(&self.secret_hash, &hash_secret(secret))
+    }
+
+    pub fn allows(&self, permission: Permission) -> bool {
+        self.permissions.contains(&permission)
+    }
+
+    // The key as listed to admins, without its hash
+    pub fn redacted(&self) -> Self {
+        Self { secret_hash: String::new(), ..self.clone() }
+    }
+}
+
+pub fn hash_secret(secret: &str) -> String {
+    sha256::Hash::hash(secret.trim().as_bytes()).to_string()
+}
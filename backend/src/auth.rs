@@ -0,0 +1,238 @@
+// auth.rs - Login by signing a challenge with a Nostr key (NIP-07 `signEvent` compatible),
+// exchanged for a short-lived HS256 JWT that authenticates later requests
+use nostr::base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use nostr::base64::Engine;
+use nostr::hashes::{sha256, Hash, HashEngine, Hmac, HmacEngine};
+use nostr::{Event, JsonUtil, PublicKey, ToBech32};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use utoipa::ToSchema;
+
+use crate::codes;
+use crate::query::tag_value;
+
+// NIP-42 client authentication kind; the signed event carries the challenge in a `challenge` tag
+pub const AUTH_EVENT_KIND: u16 = 22242;
+// NIP-98 HTTP auth kind, sent per request as `Authorization: Nostr <base64 event>`
+pub const HTTP_AUTH_KIND: u16 = 27235;
+pub const HTTP_AUTH_WINDOW_SECS: i64 = 60;
+pub const CHALLENGE_TTL_SECS: i64 = 300;
+pub const DEFAULT_SESSION_TTL_SECS: i64 = 3600;
+// Unanswered challenges kept at once; the oldest are dropped beyond this
+const MAX_PENDING_CHALLENGES: usize = 10_000;
+
+#[derive(Clone)]
+pub struct AuthConfig {
+    secret: Vec<u8>,
+    pub session_ttl_secs: i64,
+    // Whether creating a delivery needs a caller to check the sender against
+    pub require_sender_auth: bool,
+}
+
+impl AuthConfig {
+    // AUTH_JWT_SECRET signs session tokens, so several instances can share them; without it the
+    // secret is derived from the code secret. AUTH_SESSION_TTL_SECS defaults to 3600.
+    // SENDER_AUTH=optional lets unauthenticated clients create deliveries for any valid sender.
+    pub fn from_env(code_secret: &[u8]) -> Self {
+        let secret = match std::env::var("AUTH_JWT_SECRET").ok().filter(|s| !s.is_empty()) {
+            Some(secret) => secret.into_bytes(),
+            None => codes::derive_token(code_secret, "session", "jwt").into_bytes(),
+        };
+        Self {
+            secret,
+            session_ttl_secs: std::env::var("AUTH_SESSION_TTL_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|ttl| *ttl > 0)
+                .unwrap_or(DEFAULT_SESSION_TTL_SECS),
+            require_sender_auth: std::env::var("SENDER_AUTH").map_or(true, |v| v != "optional"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Challenge {
+    // Nonce to put in the `challenge` tag of a kind 22242 event
+    pub challenge: String,
+    pub expires_at: i64,
+}
+
+// Challenges handed out and not yet answered, each usable once
+#[derive(Debug, Default)]
+pub struct Challenges {
+    pending: HashMap<String, i64>,
+}
+
+impl Challenges {
+    pub fn issue(&mut self, now: i64) -> Challenge {
+        self.pending.retain(|_, expires_at| *expires_at > now);
+        if self.pending.len() >= MAX_PENDING_CHALLENGES {
+            if let Some(oldest) = self.pending.iter().min_by_key(|(_, at)| **at).map(|(c, _)| c.clone()) {
+                self.pending.remove(&oldest);
+            }
+        }
+
+        let bytes: [u8; 32] = nostr::secp256k1::rand::random();
+        let challenge: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        let expires_at = now + CHALLENGE_TTL_SECS;
+        self.pending.insert(challenge.clone(), expires_at);
+        Challenge { challenge, expires_at }
+    }
+
+    // Consume a challenge; false if it was never issued, already used or expired
+    pub fn take(&mut self, challenge: &str, now: i64) -> bool {
+        self.pending.remove(challenge).is_some_and(|expires_at| expires_at > now)
+    }
+}
+
+// The challenge a login event answers, once its kind, timestamp and signature check out
+pub fn login_challenge(event: &Event, now: i64) -> Result<&str, String> {
+    if event.kind.as_u16() != AUTH_EVENT_KIND {
+        return Err(format!("Login events must be kind {}", AUTH_EVENT_KIND));
+    }
+    if (event.created_at.as_u64() as i64 - now).abs() > CHALLENGE_TTL_SECS {
+        return Err("Login event is too old or too far in the future".to_string());
+    }
+    event.verify().map_err(|_| "Invalid login event signature".to_string())?;
+    tag_value(event, "challenge").ok_or_else(|| "Login event has no challenge tag".to_string())
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Claims {
+    // Hex pubkey of the signed-in user
+    pub sub: String,
+    pub iat: i64,
+    pub exp: i64,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Session {
+    // Send as `Authorization: Bearer <token>`
+    pub token: String,
+    pub pubkey: String,
+    pub expires_at: i64,
+}
+
+fn sign(secret: &[u8], message: &str) -> [u8; 32] {
+    let mut engine = HmacEngine::<sha256::Hash>::new(secret);
+    engine.input(message.as_bytes());
+    Hmac::<sha256::Hash>::from_engine(engine).to_byte_array()
+}
+
+pub fn issue_session(config: &AuthConfig, pubkey: &PublicKey, now: i64) -> Result<Session, String> {
+    let expires_at = now + config.session_ttl_secs;
+    let claims = Claims { sub: pubkey.to_hex(), iat: now, exp: expires_at };
+
+    let header = URL_SAFE_NO_PAD.encode(r#"{"alg":"HS256","typ":"JWT"}"#);
+    let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims).map_err(|e| e.to_string())?);
+    let signing_input = format!("{}.{}", header, payload);
+    let signature = URL_SAFE_NO_PAD.encode(sign(&config.secret, &signing_input));
+
+    Ok(Session {
+        token: format!("{}.{}", signing_input, signature),
+        pubkey: pubkey.to_bech32().map_err(|e| e.to_string())?,
+        expires_at,
+    })
+}
+
+// Claims of a session token that was signed with our secret and hasn't expired
+pub fn verify_session(config: &AuthConfig, token: &str, now: i64) -> Result<Claims, String> {
+    let (signing_input, signature) = token.rsplit_once('.').ok_or("Malformed session token")?;
+    let (header, payload) = signing_input.split_once('.').ok_or("Malformed session token")?;
+
+    let expected = URL_SAFE_NO_PAD.encode(sign(&config.secret, signing_input));
+    if !codes::codes_match(&expected, signature) {
+        return Err("Invalid session token signature".to_string());
+    }
+
+    let header: serde_json::Value = URL_SAFE_NO_PAD
+        .decode(header)
+        .ok()
+        .and_then(|h| serde_json::from_slice(&h).ok())
+        .ok_or("Malformed session token")?;
+    if header["alg"] != "HS256" {
+        return Err("Unsupported session token algorithm".to_string());
+    }
+
+    let claims: Claims = URL_SAFE_NO_PAD
+        .decode(payload)
+        .ok()
+        .and_then(|p| serde_json::from_slice(&p).ok())
+        .ok_or("Malformed session token")?;
+    if claims.exp <= now {
+        return Err("Session token has expired".to_string());
+    }
+    Ok(claims)
+}
+
+// Hex sha256 of a request body, which NIP-98 events sign in their `payload` tag
+#[derive(Debug, Clone)]
+pub struct BodyHash(pub String);
+
+impl BodyHash {
+    pub fn of(body: &[u8]) -> Self {
+        Self(sha256::Hash::hash(body).to_string())
+    }
+}
+
+// A verified NIP-98 event
+#[derive(Debug, Clone)]
+pub struct HttpAuth {
+    pub pubkey: PublicKey,
+    pub event_id: String,
+    pub created_at: i64,
+}
+
+// NIP-98 events already used, each kept until it falls out of the time window, so a captured
+// token can't be sent again
+#[derive(Debug, Default)]
+pub struct HttpAuthReplays {
+    used: HashMap<String, i64>,
+}
+
+impl HttpAuthReplays {
+    // Whether this is the event's first use; later ones are replays
+    pub fn first_use(&mut self, auth: &HttpAuth, now: i64) -> bool {
+        self.used.retain(|_, until| *until >= now);
+        if self.used.contains_key(&auth.event_id) {
+            return false;
+        }
+        self.used.insert(auth.event_id.clone(), auth.created_at + HTTP_AUTH_WINDOW_SECS);
+        true
+    }
+}
+
+// A NIP-98 `Authorization: Nostr` token, once its kind, timestamp, signature, URL, method and, for
+// requests with a body, `payload` check out. `urls` are the forms the request URL may have been
+// signed in, e.g. behind a proxy; `body` is the hash of a non-empty request body.
+pub fn verify_http_auth(token: &str, urls: &[String], method: &str, body: Option<&BodyHash>, now: i64) -> Result<HttpAuth, String> {
+    let json = STANDARD.decode(token.trim()).map_err(|_| "Malformed NIP-98 token".to_string())?;
+    let event = Event::from_json(json).map_err(|_| "Malformed NIP-98 event".to_string())?;
+    if event.kind.as_u16() != HTTP_AUTH_KIND {
+        return Err(format!("NIP-98 events must be kind {}", HTTP_AUTH_KIND));
+    }
+    if (event.created_at.as_u64() as i64 - now).abs() > HTTP_AUTH_WINDOW_SECS {
+        return Err("NIP-98 event is too old or too far in the future".to_string());
+    }
+    event.verify().map_err(|_| "Invalid NIP-98 event signature".to_string())?;
+
+    let url = tag_value(&event, "u").ok_or("NIP-98 event has no u tag")?;
+    if !urls.iter().any(|candidate| candidate == url) {
+        return Err("NIP-98 event was signed for another URL".to_string());
+    }
+    let signed_method = tag_value(&event, "method").ok_or("NIP-98 event has no method tag")?;
+    if !signed_method.eq_ignore_ascii_case(method) {
+        return Err("NIP-98 event was signed for another method".to_string());
+    }
+    if let Some(body) = body {
+        let payload = tag_value(&event, "payload").ok_or("NIP-98 event has no payload tag for the request body")?;
+        if !payload.eq_ignore_ascii_case(&body.0) {
+            return Err("NIP-98 event was signed for another request body".to_string());
+        }
+    }
+    Ok(HttpAuth {
+        pubkey: event.pubkey,
+        event_id: event.id.to_hex(),
+        created_at: event.created_at.as_u64() as i64,
+    })
+}
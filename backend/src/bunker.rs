@@ -0,0 +1,52 @@
+// bunker.rs - NIP-46 remote signer (bunker) configuration for the system key
+use std::time::Duration;
+
+use nostr::nips::nip46::NostrConnectURI;
+use nostr::Keys;
+use nostr_connect::client::NostrConnect;
+
+// Default time allowed for a single signing request
+pub const DEFAULT_SIGNING_TIMEOUT_SECS: u64 = 30;
+
+#[derive(Debug, Clone)]
+pub struct BunkerConfig {
+    pub uri: NostrConnectURI,
+    // Local NIP-46 client keys; never used to sign marketplace events
+    pub app_keys: Keys,
+    pub timeout: Duration,
+}
+
+impl BunkerConfig {
+    // Read NOSTR_BUNKER_URI; None when the system key should be held locally
+    pub fn from_env(app_keys: &Keys, timeout: Duration) -> Result<Option<Self>, String> {
+        let Some(raw) = std::env::var("NOSTR_BUNKER_URI").ok().filter(|v| !v.trim().is_empty()) else {
+            return Ok(None);
+        };
+
+        let uri = NostrConnectURI::parse(raw.trim()).map_err(|e| format!("Invalid NOSTR_BUNKER_URI: {}", e))?;
+        if !uri.is_bunker() {
+            return Err("NOSTR_BUNKER_URI must be a bunker:// URI".to_string());
+        }
+
+        Ok(Some(Self {
+            uri,
+            app_keys: app_keys.clone(),
+            timeout,
+        }))
+    }
+
+    // Build a fresh remote signer connection (relays are connected lazily on first request)
+    pub fn connect(&self) -> Result<NostrConnect, String> {
+        NostrConnect::new(self.uri.clone(), self.app_keys.clone(), self.timeout, None).map_err(|e| e.to_string())
+    }
+}
+
+// Signing timeout from NOSTR_SIGNER_TIMEOUT_SECS
+pub fn signing_timeout_from_env() -> Duration {
+    Duration::from_secs(
+        std::env::var("NOSTR_SIGNER_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_SIGNING_TIMEOUT_SECS),
+    )
+}
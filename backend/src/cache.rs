@@ -0,0 +1,139 @@
+// cache.rs - Read-through cache of deliveries, bids and profiles by ID. Relay reads and the
+// indexer fill it; our own publishes update or invalidate entries so reads after a write see it
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use moka::future::Cache;
+
+use crate::{DeliveryBid, DeliveryRequest, UserProfile};
+
+// How long a publish keeps reads that started before it from filling the cache
+const WRITE_GUARD: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    // 0 disables caching
+    pub ttl_secs: u64,
+    // Per cache: deliveries, bid lists and profiles
+    pub max_entries: u64,
+}
+
+impl CacheConfig {
+    // CACHE_TTL_SECS (default 60, 0 to disable) and CACHE_MAX_ENTRIES (default 10000)
+    pub fn from_env() -> Self {
+        let ttl_secs = std::env::var("CACHE_TTL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(60);
+        let max_entries = std::env::var("CACHE_MAX_ENTRIES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(10_000);
+        Self { ttl_secs, max_entries }
+    }
+}
+
+pub struct Entries<V> {
+    values: Cache<String, V>,
+    // When our own publishes last changed each key
+    written: Cache<String, Instant>,
+    cleared: Mutex<Option<Instant>>,
+    enabled: bool,
+}
+
+impl<V: Clone + Send + Sync + 'static> Entries<V> {
+    fn new(config: &CacheConfig) -> Self {
+        Self {
+            values: Cache::builder()
+                .max_capacity(config.max_entries)
+                .time_to_live(Duration::from_secs(config.ttl_secs.max(1)))
+                .build(),
+            written: Cache::builder()
+                .max_capacity(config.max_entries)
+                .time_to_live(WRITE_GUARD)
+                .build(),
+            cleared: Mutex::new(None),
+            enabled: config.ttl_secs > 0,
+        }
+    }
+
+    pub async fn get(&self, key: &str) -> Option<V> {
+        if !self.enabled {
+            return None;
+        }
+        self.values.get(key).await
+    }
+
+    // Stores a value read from relays starting at `read_at`, unless a publish has changed it since
+    pub async fn fill(&self, key: &str, value: V, read_at: Instant) {
+        if !self.enabled {
+            return;
+        }
+        if self.changed_since(key, read_at).await {
+            return;
+        }
+        self.values.insert(key.to_string(), value).await;
+        // A publish racing the insert wins
+        if self.changed_since(key, read_at).await {
+            self.values.invalidate(key).await;
+        }
+    }
+
+    async fn changed_since(&self, key: &str, read_at: Instant) -> bool {
+        let cleared = *self.cleared.lock().unwrap_or_else(|e| e.into_inner());
+        cleared.is_some_and(|at| at > read_at) || self.written.get(key).await.is_some_and(|at| at > read_at)
+    }
+
+    // A value we just published, exactly as it reads back
+    pub async fn insert(&self, key: &str, value: V) {
+        if !self.enabled {
+            return;
+        }
+        self.written.insert(key.to_string(), Instant::now()).await;
+        self.values.insert(key.to_string(), value).await;
+    }
+
+    pub async fn invalidate(&self, key: &str) {
+        if !self.enabled {
+            return;
+        }
+        self.written.insert(key.to_string(), Instant::now()).await;
+        self.values.invalidate(key).await;
+    }
+
+    pub fn invalidate_all(&self) {
+        *self.cleared.lock().unwrap_or_else(|e| e.into_inner()) = Some(Instant::now());
+        self.values.invalidate_all();
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.entry_count() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+pub struct ReadCache {
+    // Deliveries as get_delivery_by_id returns them, by delivery ID
+    pub deliveries: Entries<DeliveryRequest>,
+    // Bids by delivery ID
+    pub bids: Entries<Vec<DeliveryBid>>,
+    // Published profiles by normalized pubkey
+    pub profiles: Entries<UserProfile>,
+}
+
+impl ReadCache {
+    pub fn new(config: &CacheConfig) -> Self {
+        Self {
+            deliveries: Entries::new(config),
+            bids: Entries::new(config),
+            profiles: Entries::new(config),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(&CacheConfig::from_env())
+    }
+}
@@ -0,0 +1,95 @@
+// classifieds.rs - NIP-99 classified listing mirror of open deliveries
+use nostr::{Event, Kind, PublicKey, Tag, TagKind};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{geohash_encode, DeliveryRequest, DeliveryStatus};
+
+pub const CLASSIFIED_LISTING_KIND: u16 = 30402;
+
+// Geohash precision published with listings (~1.2km cells)
+const LISTING_GEOHASH_PRECISION: usize = 6;
+
+fn custom_tag(name: &str, values: Vec<String>) -> Tag {
+    Tag::custom(TagKind::Custom(name.to_string().into()), values)
+}
+
+pub fn listing_title(delivery: &DeliveryRequest) -> String {
+    format!("Delivery: {} → {}", delivery.pickup.address, delivery.dropoff.address)
+}
+
+// Markdown body shown by generic marketplace clients
+pub fn listing_content(delivery: &DeliveryRequest, frontend_url: &str) -> String {
+    let mut content = format!("**{}**\n\n", listing_title(delivery));
+    content.push_str(&format!("Offer: {} sats\n\n", delivery.offer_amount));
+    if !delivery.time_window.is_empty() {
+        content.push_str(&format!("Time window: {}\n\n", delivery.time_window));
+    }
+    for package in &delivery.packages {
+        content.push_str(&format!(
+            "- {} package: {}{}\n",
+            package.size,
+            package.description,
+            if package.fragile { " (fragile)" } else { "" }
+        ));
+    }
+    content.push_str(&format!("\nBid on this delivery: {}/deliveries/{}\n", frontend_url, delivery.id));
+    content
+}
+
+// NIP-99 tags: d, title, summary, published_at, price, location, g, status, t
+pub fn listing_tags(delivery: &DeliveryRequest) -> Vec<Tag> {
+    let status = if delivery.status == DeliveryStatus::Open { "active" } else { "sold" };
+
+    let mut tags = vec![
+        custom_tag("d", vec![delivery.id.clone()]),
+        custom_tag("title", vec![listing_title(delivery)]),
+        custom_tag("summary", vec![format!("{} sats delivery request", delivery.offer_amount)]),
+        custom_tag("published_at", vec![delivery.created_at.to_string()]),
+        custom_tag("price", vec![delivery.offer_amount.to_string(), "SATS".to_string()]),
+        custom_tag("location", vec![delivery.pickup.address.clone()]),
+        custom_tag("status", vec![status.to_string()]),
+        custom_tag("t", vec!["delivery".to_string()]),
+        custom_tag("delivery_id", vec![delivery.id.clone()]),
+    ];
+    if let Some(point) = &delivery.pickup.coordinates {
+        tags.push(custom_tag("g", vec![geohash_encode(point, LISTING_GEOHASH_PRECISION)]));
+    }
+
+    tags
+}
+
+// A reply to one of our listings from a generic Nostr client
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ListingInterest {
+    pub delivery_id: String,
+    pub from_pubkey: String,
+    pub message: String,
+    pub event_id: String,
+    pub created_at: i64,
+}
+
+// Interpret a text note as interest in one of our listings (via its `a` tag coordinate)
+pub fn parse_interest(event: &Event, marketplace: &PublicKey) -> Option<ListingInterest> {
+    if event.kind != Kind::TextNote {
+        return None;
+    }
+
+    let prefix = format!("{}:{}:", CLASSIFIED_LISTING_KIND, marketplace.to_hex());
+    let delivery_id = event.tags.iter().find_map(|tag| {
+        let values = tag.as_slice();
+        (values.len() >= 2 && values[0] == "a")
+            .then(|| values[1].strip_prefix(&prefix))
+            .flatten()
+            .filter(|id| !id.is_empty())
+            .map(str::to_string)
+    })?;
+
+    Some(ListingInterest {
+        delivery_id,
+        from_pubkey: event.pubkey.to_hex(),
+        message: event.content.clone(),
+        event_id: event.id.to_hex(),
+        created_at: event.created_at.as_u64() as i64,
+    })
+}
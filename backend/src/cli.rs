@@ -0,0 +1,157 @@
+// cli.rs - Subcommands of the nostr-delivery binary
+pub const USAGE: &str = "Usage: nostr-delivery [COMMAND]
+
+Commands:
+  serve                                 Run the HTTP server (default)
+  keygen                                Print a new system key for SYSTEM_SECRET_KEY
+  rotate-key --backup <path> [--grace-days <days>]
+                                        Replace the system key, re-signing live state under the new one
+  publish-delivery --file <path>        Publish a delivery from a JSON create request
+  list [--status <statuses>]            List deliveries, e.g. --status open
+  export [--format json|csv] [--status <statuses>] [--fields <fields>] [--from <ts>] [--to <ts>]
+                                        Write deliveries to stdout
+  export --out <path>                   Write a signed snapshot of every marketplace event
+  import --file <path>                  Verify a snapshot and publish its events to the relays
+  republish --since <ts>                Re-broadcast marketplace events created since a Unix timestamp
+  compact                               Archive closed deliveries' history (COMPACTION_ARCHIVE_DIR) and
+                                        delete their superseded events from relays
+  verify-event <id>                     Fetch an event by ID (hex, note or nevent) and check its signature
+  help                                  Show this message
+
+Relays and keys are configured through the same environment variables as the server.";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Serve,
+    Keygen,
+    RotateKey { backup: String, grace_days: i64 },
+    PublishDelivery { file: String },
+    List { status: Option<String> },
+    Export {
+        format: String,
+        status: Option<String>,
+        fields: Option<String>,
+        from: Option<i64>,
+        to: Option<i64>,
+    },
+    ExportSnapshot { out: String },
+    ImportSnapshot { file: String },
+    Republish { since: i64 },
+    Compact,
+    VerifyEvent { id: String },
+    Help,
+}
+
+// Parse the arguments after the program name
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut args = args.into_iter();
+    let Some(command) = args.next() else {
+        return Ok(Command::Serve);
+    };
+    let rest: Vec<String> = args.collect();
+
+    match command.as_str() {
+        "serve" => no_options(&rest).map(|_| Command::Serve),
+        "keygen" => no_options(&rest).map(|_| Command::Keygen),
+        "help" | "--help" | "-h" => Ok(Command::Help),
+        "rotate-key" => {
+            let mut options = Options::parse(&rest, &["--backup", "--grace-days"])?;
+            let backup = options.take("--backup").ok_or("rotate-key requires --backup <path>")?;
+            let grace_days = match options.take("--grace-days") {
+                Some(days) => days.parse().ok().filter(|d| *d >= 0).ok_or("--grace-days must be a number of days")?,
+                None => 30,
+            };
+            Ok(Command::RotateKey { backup, grace_days })
+        }
+        "publish-delivery" => {
+            let mut options = Options::parse(&rest, &["--file"])?;
+            let file = options.take("--file").ok_or("publish-delivery requires --file <path>")?;
+            Ok(Command::PublishDelivery { file })
+        }
+        "list" => {
+            let mut options = Options::parse(&rest, &["--status"])?;
+            Ok(Command::List { status: options.take("--status") })
+        }
+        "export" => {
+            let mut options = Options::parse(&rest, &["--format", "--status", "--fields", "--from", "--to", "--out"])?;
+            if let Some(out) = options.take("--out") {
+                // A snapshot holds every event, so it takes no filters
+                if let Some((name, _)) = options.0.first() {
+                    return Err(format!("{} can't be combined with --out", name));
+                }
+                return Ok(Command::ExportSnapshot { out });
+            }
+            let format = options.take("--format").unwrap_or_else(|| "json".to_string());
+            if format != "json" && format != "csv" {
+                return Err("--format must be json or csv".to_string());
+            }
+            Ok(Command::Export {
+                format,
+                status: options.take("--status"),
+                fields: options.take("--fields"),
+                from: options.take_timestamp("--from")?,
+                to: options.take_timestamp("--to")?,
+            })
+        }
+        "import" => {
+            let mut options = Options::parse(&rest, &["--file"])?;
+            let file = options.take("--file").ok_or("import requires --file <path>")?;
+            Ok(Command::ImportSnapshot { file })
+        }
+        "republish" => {
+            let mut options = Options::parse(&rest, &["--since"])?;
+            let since = options.take_timestamp("--since")?.ok_or("republish requires --since <timestamp>")?;
+            Ok(Command::Republish { since })
+        }
+        "compact" => no_options(&rest).map(|_| Command::Compact),
+        "verify-event" => match rest.as_slice() {
+            [id] => Ok(Command::VerifyEvent { id: id.clone() }),
+            _ => Err("verify-event takes exactly one event ID".to_string()),
+        },
+        other => Err(format!("Unknown command: {}", other)),
+    }
+}
+
+fn no_options(rest: &[String]) -> Result<(), String> {
+    match rest.first() {
+        Some(arg) => Err(format!("Unexpected argument: {}", arg)),
+        None => Ok(()),
+    }
+}
+
+// `--name value` pairs, restricted to the names a command accepts
+struct Options(Vec<(String, String)>);
+
+impl Options {
+    fn parse(rest: &[String], allowed: &[&str]) -> Result<Self, String> {
+        let mut pairs = Vec::new();
+        let mut args = rest.iter();
+        while let Some(arg) = args.next() {
+            // Also accept --name=value
+            let (name, inline) = match arg.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (arg.as_str(), None),
+            };
+            if !allowed.contains(&name) {
+                return Err(format!("Unexpected argument: {}", arg));
+            }
+            let value = match inline {
+                Some(value) => value,
+                None => args.next().cloned().ok_or_else(|| format!("{} requires a value", name))?,
+            };
+            pairs.push((name.to_string(), value));
+        }
+        Ok(Self(pairs))
+    }
+
+    fn take(&mut self, name: &str) -> Option<String> {
+        let index = self.0.iter().rposition(|(n, _)| n == name)?;
+        Some(self.0.remove(index).1)
+    }
+
+    fn take_timestamp(&mut self, name: &str) -> Result<Option<i64>, String> {
+        self.take(name)
+            .map(|v| v.parse().map_err(|_| format!("{} must be a Unix timestamp", name)))
+            .transpose()
+    }
+}
@@ -0,0 +1,244 @@
+// client.rs - Typed client for the HTTP API, with optional NIP-98 request signing
+use nostr::base64::engine::{general_purpose, Engine};
+use nostr::hashes::{sha256, Hash};
+use nostr::nips::nip98::{HttpData, HttpMethod};
+use nostr::{EventBuilder, JsonUtil, Keys, UncheckedUrl};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::problem::Problem;
+use crate::tracking::LocationPing;
+use crate::{Amount, DeliveryBid, DeliveryRequest, Location, PackageInfo, RatingBreakdown};
+
+#[derive(Debug)]
+pub enum ClientError {
+    // Connection failures and undecodable responses
+    Http(reqwest::Error),
+    // The server answered with a problem document
+    Api(Box<Problem>),
+    // The request could not be built or signed
+    Request(String),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Http(e) => write!(f, "{}", e),
+            ClientError::Api(problem) => write!(f, "{} ({}): {}", problem.title, problem.status, problem.detail),
+            ClientError::Request(e) => write!(f, "Invalid request: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(e: reqwest::Error) -> Self {
+        ClientError::Http(e)
+    }
+}
+
+// Body of POST /api/v1/deliveries
+#[derive(Debug, Clone, Serialize)]
+pub struct NewDelivery {
+    pub sender: String,
+    pub pickup: Location,
+    pub dropoff: Location,
+    pub packages: Vec<PackageInfo>,
+    pub offer_amount: Amount,
+    pub insurance_amount: Option<Amount>,
+    pub time_window: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_courier_reputation: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_completed_deliveries: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub allowed_couriers: Vec<String>,
+    pub private: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub organization: Option<String>,
+}
+
+// Body of POST /api/v1/deliveries/{id}/bid
+#[derive(Debug, Clone, Serialize)]
+pub struct NewBid {
+    pub courier: String,
+    pub amount: Amount,
+    // Unix timestamps
+    pub pickup_eta: i64,
+    pub dropoff_eta: i64,
+    pub message: Option<String>,
+}
+
+// Body of POST /api/v1/deliveries/{id}/confirm
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Confirmation {
+    pub ratings: Option<RatingBreakdown>,
+    pub feedback: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ListFilter {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub include_federated: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct DeliveryEnvelope {
+    delivery: DeliveryRequest,
+}
+
+#[derive(Deserialize)]
+struct BidEnvelope {
+    bid: DeliveryBid,
+}
+
+#[derive(Deserialize)]
+struct ConfirmEnvelope {
+    delivery: DeliveryRequest,
+    #[serde(default)]
+    review_held: bool,
+}
+
+pub struct DeliveryClient {
+    http: reqwest::Client,
+    base_url: String,
+    // Signs every request with a NIP-98 Authorization header when set
+    keys: Option<Keys>,
+}
+
+impl DeliveryClient {
+    // `base_url` is the server root, e.g. http://localhost:8080
+    pub fn new(base_url: &str) -> Self {
+        let http = reqwest::Client::builder()
+            .user_agent(concat!("nostr-delivery-client/", env!("CARGO_PKG_VERSION")))
+            .timeout(std::time::Duration::from_secs(30))
+            .build()
+            .unwrap_or_default();
+
+        Self {
+            http,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            keys: None,
+        }
+    }
+
+    pub fn with_keys(mut self, keys: Keys) -> Self {
+        self.keys = Some(keys);
+        self
+    }
+
+    pub async fn create_delivery(&self, delivery: &NewDelivery) -> Result<DeliveryRequest, ClientError> {
+        let envelope: DeliveryEnvelope = self.post("/api/v1/deliveries", delivery).await?;
+        Ok(envelope.delivery)
+    }
+
+    pub async fn list_deliveries(&self, filter: &ListFilter) -> Result<Vec<DeliveryRequest>, ClientError> {
+        let query = query_pairs(filter);
+        self.get("/api/v1/deliveries", &query).await
+    }
+
+    pub async fn get_delivery(&self, delivery_id: &str) -> Result<DeliveryRequest, ClientError> {
+        self.get(&format!("/api/v1/deliveries/{}", delivery_id), &[]).await
+    }
+
+    pub async fn place_bid(&self, delivery_id: &str, bid: &NewBid) -> Result<DeliveryBid, ClientError> {
+        let envelope: BidEnvelope = self.post(&format!("/api/v1/deliveries/{}/bid", delivery_id), bid).await?;
+        Ok(envelope.bid)
+    }
+
+    // Accept the bid at `bid_index` in the delivery's bid list
+    pub async fn accept_bid(&self, delivery_id: &str, bid_index: usize) -> Result<DeliveryRequest, ClientError> {
+        let path = format!("/api/v1/deliveries/{}/accept/{}", delivery_id, bid_index);
+        let envelope: DeliveryEnvelope = self.post(&path, &serde_json::json!({})).await?;
+        Ok(envelope.delivery)
+    }
+
+    // Location pings, oldest first
+    pub async fn track(&self, delivery_id: &str) -> Result<Vec<LocationPing>, ClientError> {
+        self.get(&format!("/api/v1/deliveries/{}/track", delivery_id), &[]).await
+    }
+
+    // Returns the confirmed delivery and whether the feedback was held for moderation
+    pub async fn confirm(&self, delivery_id: &str, confirmation: &Confirmation) -> Result<(DeliveryRequest, bool), ClientError> {
+        let path = format!("/api/v1/deliveries/{}/confirm", delivery_id);
+        let envelope: ConfirmEnvelope = self.post(&path, confirmation).await?;
+        Ok((envelope.delivery, envelope.review_held))
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str, query: &[(String, String)]) -> Result<T, ClientError> {
+        let url = reqwest::Url::parse_with_params(&format!("{}{}", self.base_url, path), query)
+            .map_err(|e| ClientError::Request(e.to_string()))?;
+        let mut request = self.http.get(url.clone());
+        if let Some(auth) = self.authorization(url.as_str(), HttpMethod::GET, None)? {
+            request = request.header(reqwest::header::AUTHORIZATION, auth);
+        }
+        decode(request.send().await?).await
+    }
+
+    async fn post<B: Serialize, T: DeserializeOwned>(&self, path: &str, body: &B) -> Result<T, ClientError> {
+        let url = format!("{}{}", self.base_url, path);
+        let body = serde_json::to_vec(body).map_err(|e| ClientError::Request(e.to_string()))?;
+        let mut request = self.http.post(&url).header(reqwest::header::CONTENT_TYPE, "application/json");
+        if let Some(auth) = self.authorization(&url, HttpMethod::POST, Some(&body))? {
+            request = request.header(reqwest::header::AUTHORIZATION, auth);
+        }
+        decode(request.body(body).send().await?).await
+    }
+
+    // NIP-98: a signed kind 27235 event naming the URL, method and body hash
+    fn authorization(&self, url: &str, method: HttpMethod, body: Option<&[u8]>) -> Result<Option<String>, ClientError> {
+        let Some(keys) = &self.keys else {
+            return Ok(None);
+        };
+
+        let mut data = HttpData::new(UncheckedUrl::from(url), method);
+        if let Some(body) = body {
+            data = data.payload(sha256::Hash::hash(body));
+        }
+
+        let event = EventBuilder::http_auth(data)
+            .sign_with_keys(keys)
+            .map_err(|e| ClientError::Request(e.to_string()))?;
+
+        Ok(Some(format!("Nostr {}", general_purpose::STANDARD.encode(event.as_json()))))
+    }
+}
+
+async fn decode<T: DeserializeOwned>(response: reqwest::Response) -> Result<T, ClientError> {
+    if response.status().is_success() {
+        return Ok(response.json().await?);
+    }
+
+    let status = response.status().as_u16();
+    let text = response.text().await?;
+    // Fall back to a bare problem when the body isn't one, e.g. from a proxy
+    let problem: Problem = serde_json::from_str(&text).unwrap_or_else(|_| Problem {
+        type_uri: "about:blank".to_string(),
+        title: "HTTP error".to_string(),
+        status,
+        detail: text,
+        instance: None,
+        extensions: serde_json::Map::new(),
+    });
+    Err(ClientError::Api(Box::new(problem)))
+}
+
+// Query pairs for a flat struct of optional fields
+fn query_pairs<T: Serialize>(value: &T) -> Vec<(String, String)> {
+    let serde_json::Value::Object(fields) = serde_json::to_value(value).unwrap_or_default() else {
+        return Vec::new();
+    };
+
+    fields
+        .into_iter()
+        .filter_map(|(key, value)| match value {
+            serde_json::Value::Null => None,
+            serde_json::Value::String(s) => Some((key, s)),
+            other => Some((key, other.to_string())),
+        })
+        .collect()
+}
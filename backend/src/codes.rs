@@ -0,0 +1,63 @@
+// codes.rs - Verification codes and tokens derived from the code secret
+use nostr::hashes::{sha256, Hash, HashEngine, Hmac, HmacEngine};
+use std::collections::HashMap;
+
+// NIP-78 `d` tag of the code secret, kept on the relays encrypted to the system key
+pub const SECRET_APP_DATA_IDENTIFIER: &str = "nostr-delivery/code-secret";
+
+// Wrong submissions allowed for one code before it stops being accepted
+pub const MAX_ATTEMPTS: u32 = 5;
+
+fn hmac(secret: &[u8], purpose: &str, subject: &str) -> [u8; 32] {
+    let mut engine = HmacEngine::<sha256::Hash>::new(secret);
+    engine.input(purpose.as_bytes());
+    engine.input(b":");
+    engine.input(subject.as_bytes());
+    Hmac::<sha256::Hash>::from_engine(engine).to_byte_array()
+}
+
+// Numeric code of `digits` length, stable for a given secret/purpose/subject
+pub fn derive_code(secret: &[u8], purpose: &str, subject: &str, digits: u32) -> String {
+    let bytes = hmac(secret, purpose, subject);
+    let value = u64::from_be_bytes(bytes[..8].try_into().unwrap_or_default());
+    format!("{:0width$}", value % 10u64.pow(digits), width = digits as usize)
+}
+
+// Hex token, stable for a given secret/purpose/subject
+pub fn derive_token(secret: &[u8], purpose: &str, subject: &str) -> String {
+    hmac(secret, purpose, subject).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Constant-time comparison for submitted codes
+pub fn codes_match(expected: &str, submitted: &str) -> bool {
+    expected.len() == submitted.len()
+        && expected
+            .bytes()
+            .zip(submitted.bytes())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+// Wrong submissions per code subject (a delivery ID), so a six-digit code can't be guessed by
+// trying them all
+#[derive(Default)]
+pub struct Attempts {
+    failures: HashMap<String, u32>,
+}
+
+impl Attempts {
+    pub fn locked(&self, subject: &str) -> bool {
+        self.failures.get(subject).is_some_and(|n| *n >= MAX_ATTEMPTS)
+    }
+
+    // Records a wrong submission and returns how many are left
+    pub fn fail(&mut self, subject: &str) -> u32 {
+        let failures = self.failures.entry(subject.to_string()).or_insert(0);
+        *failures += 1;
+        MAX_ATTEMPTS.saturating_sub(*failures)
+    }
+
+    pub fn clear(&mut self, subject: &str) {
+        self.failures.remove(subject);
+    }
+}
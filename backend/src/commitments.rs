@@ -0,0 +1,61 @@
+// commitments.rs - Deliveries a courier has already taken on, and new bids that would clash with them
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{DeliveryRequest, DeliveryStatus, PubkeyId, TimeWindow};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConflictMode {
+    // Place the bid and report the conflicts with it
+    Warn,
+    Reject,
+}
+
+impl ConflictMode {
+    pub fn from_env() -> Self {
+        match std::env::var("BID_CONFLICT_MODE").as_deref() {
+            Ok("reject") => Self::Reject,
+            _ => Self::Warn,
+        }
+    }
+}
+
+// An accepted delivery whose pickup-to-dropoff span overlaps a new bid's
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct Conflict {
+    pub delivery_id: String,
+    pub status: DeliveryStatus,
+    pub starts_at: i64,
+    pub ends_at: i64,
+}
+
+// What the accepted courier is committed to: their bid's ETAs, or else the time window when it has
+// both bounds. None when neither says when the delivery happens.
+pub fn committed_span(delivery: &DeliveryRequest) -> Option<(i64, i64)> {
+    let accepted = delivery.accepted_bid.as_ref()?;
+    let bid = delivery.bids.iter().find(|b| &b.id == accepted)?;
+    if let (Some(pickup), Some(dropoff)) = (bid.pickup_eta, bid.dropoff_eta) {
+        return Some((pickup, dropoff));
+    }
+    let window = TimeWindow::parse(&delivery.time_window);
+    window.start.zip(window.end)
+}
+
+// The courier's accepted and in-transit deliveries that overlap `span`
+pub fn conflicts(courier: &str, deliveries: &[DeliveryRequest], span: (i64, i64)) -> Vec<Conflict> {
+    let courier = PubkeyId::normalize(courier);
+    deliveries
+        .iter()
+        .filter(|d| matches!(d.status, DeliveryStatus::Accepted | DeliveryStatus::InTransit))
+        .filter(|d| d.accepted_courier().is_some_and(|c| PubkeyId::normalize(c) == courier))
+        .filter_map(|d| {
+            let (starts_at, ends_at) = committed_span(d)?;
+            (starts_at < span.1 && span.0 < ends_at).then(|| Conflict {
+                delivery_id: d.id.clone(),
+                status: d.status.clone(),
+                starts_at,
+                ends_at,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,125 @@
+// compaction.rs - Retention for closed deliveries: their full event history is archived to disk,
+// the final state republished and superseded versions deleted (NIP-09) from relays that honor it
+use nostr::{Event, EventId, JsonUtil, PublicKey};
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::query::{self, Authors};
+use crate::{DeliveryRequest, DeliveryStatus};
+
+pub const DEFAULT_INTERVAL_SECS: u64 = 86400;
+
+#[derive(Debug, Clone, Default)]
+pub struct CompactionConfig {
+    // Compaction only runs with somewhere to archive to
+    pub archive_dir: Option<PathBuf>,
+    // 0 disables the background job; the CLI can still compact
+    pub interval_secs: u64,
+    // Relays to send deletions to; empty means every configured relay
+    pub delete_relays: Vec<String>,
+    pub delete: bool,
+}
+
+impl CompactionConfig {
+    // COMPACTION_ARCHIVE_DIR, COMPACTION_INTERVAL_SECS (default 86400) and
+    // COMPACTION_DELETE_RELAYS, a comma-separated list of relays or `none`
+    pub fn from_env() -> Self {
+        let delete_relays = std::env::var("COMPACTION_DELETE_RELAYS").unwrap_or_default();
+        Self {
+            archive_dir: std::env::var("COMPACTION_ARCHIVE_DIR").ok().filter(|d| !d.is_empty()).map(PathBuf::from),
+            interval_secs: std::env::var("COMPACTION_INTERVAL_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_INTERVAL_SECS),
+            delete: delete_relays.trim() != "none",
+            delete_relays: delete_relays
+                .split(',')
+                .map(|r| r.trim())
+                .filter(|r| !r.is_empty() && *r != "none")
+                .map(String::from)
+                .collect(),
+        }
+    }
+}
+
+// Deliveries whose state won't change again: confirmed, expired, or deleted past the grace period
+pub fn is_closed(delivery: &DeliveryRequest, now: i64, delete_grace_secs: i64) -> bool {
+    match delivery.status {
+        DeliveryStatus::Confirmed | DeliveryStatus::Expired => true,
+        DeliveryStatus::Deleted => delivery.deleted_at.is_some_and(|at| now - at > delete_grace_secs),
+        _ => false,
+    }
+}
+
+// Events by the delivery they belong to
+pub fn group_by_delivery(events: impl IntoIterator<Item = Event>) -> HashMap<String, Vec<Event>> {
+    let mut groups: HashMap<String, Vec<Event>> = HashMap::new();
+    for event in events {
+        if let Some(id) = query::delivery_id(&event) {
+            groups.entry(id.to_string()).or_default().push(event);
+        }
+    }
+    groups
+}
+
+// The final version of each replaceable event, and the IDs of the older versions `deleter`
+// can delete (NIP-09 deletions only apply to the deleter's own events)
+pub fn split_superseded(events: &[Event], authors: &Authors, deleter: &PublicKey) -> (Vec<Event>, Vec<EventId>) {
+    let latest = query::latest_per_identifier(events.iter().cloned(), authors);
+    let kept: HashSet<EventId> = latest.iter().map(|e| e.id).collect();
+    let superseded = events
+        .iter()
+        .filter(|e| !kept.contains(&e.id) && &e.pubkey == deleter && authors.contains(&e.pubkey))
+        .map(|e| e.id)
+        .collect();
+    (latest, superseded)
+}
+
+// <dir>/<delivery_id>.jsonl; deliveries with an archive have been compacted
+pub fn archive_path(dir: &Path, delivery_id: &str) -> PathBuf {
+    let file_name: String = delivery_id
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' { c } else { '_' })
+        .collect();
+    dir.join(format!("{}.jsonl", file_name))
+}
+
+// Write a delivery's events to its archive, oldest first. Events already in the archive are
+// kept, so versions deleted from relays by an earlier run aren't lost.
+pub fn archive(dir: &Path, delivery_id: &str, events: &[Event]) -> std::io::Result<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+    let path = archive_path(dir, delivery_id);
+
+    let mut archived: HashMap<EventId, Event> = HashMap::new();
+    if let Ok(contents) = std::fs::read_to_string(&path) {
+        for event in contents.lines().filter_map(|line| Event::from_json(line).ok()) {
+            archived.insert(event.id, event);
+        }
+    }
+    for event in events {
+        archived.insert(event.id, event.clone());
+    }
+
+    let mut events: Vec<Event> = archived.into_values().collect();
+    events.sort_by_key(|e| (e.created_at, e.id));
+
+    let tmp = path.with_extension("tmp");
+    {
+        let mut file = std::io::BufWriter::new(std::fs::File::create(&tmp)?);
+        for event in &events {
+            writeln!(file, "{}", event.as_json())?;
+        }
+        file.flush()?;
+    }
+    std::fs::rename(&tmp, &path)?;
+    Ok(path)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CompactionReport {
+    pub deliveries: usize,
+    pub archived_events: usize,
+    pub republished: usize,
+    pub deleted: usize,
+}
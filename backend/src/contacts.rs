@@ -0,0 +1,99 @@
+// contacts.rs - Staged reveal of stop details: who to ask for at each stop is revealed to the
+// courier once they're accepted, and access instructions (gate codes, door notes) once they're
+// on the way. Both are sealed to the marketplace key otherwise.
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{ContactDetails, DeliveryRequest, DeliveryStatus};
+
+// Contacts of both stops, as sealed in `DeliveryRequest::sealed_contacts`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct StopContacts {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pickup: Option<ContactDetails>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dropoff: Option<ContactDetails>,
+}
+
+impl StopContacts {
+    // Move plaintext contacts off the delivery's stops
+    pub fn take(delivery: &mut DeliveryRequest) -> Self {
+        Self {
+            pickup: delivery.pickup.contact.take().filter(|c| !c.is_empty()),
+            dropoff: delivery.dropoff.contact.take().filter(|c| !c.is_empty()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pickup.is_none() && self.dropoff.is_none()
+    }
+
+    // Stops given new contacts replace the old ones; the others are kept
+    pub fn merge(&mut self, newer: StopContacts) {
+        if newer.pickup.is_some() {
+            self.pickup = newer.pickup;
+        }
+        if newer.dropoff.is_some() {
+            self.dropoff = newer.dropoff;
+        }
+    }
+}
+
+// Access instructions of both stops, as sealed in `DeliveryRequest::sealed_instructions`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct StopInstructions {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pickup: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dropoff: Option<String>,
+}
+
+impl StopInstructions {
+    // Move plaintext instructions off the delivery's stops
+    pub fn take(delivery: &mut DeliveryRequest) -> Self {
+        let take = |instructions: &mut Option<String>| instructions.take().map(|i| i.trim().to_string()).filter(|i| !i.is_empty());
+        Self {
+            pickup: take(&mut delivery.pickup.instructions),
+            dropoff: take(&mut delivery.dropoff.instructions),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pickup.is_none() && self.dropoff.is_none()
+    }
+
+    pub fn merge(&mut self, newer: StopInstructions) {
+        if newer.pickup.is_some() {
+            self.pickup = newer.pickup;
+        }
+        if newer.dropoff.is_some() {
+            self.dropoff = newer.dropoff;
+        }
+    }
+}
+
+// Sealed stop details NIP-44-encrypted to the accepted courier
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Reveal {
+    // Hex pubkey of the courier who can decrypt `ciphertext`
+    pub courier: String,
+    // Hex pubkey the courier decrypts against (the marketplace key)
+    pub pubkey: String,
+    // NIP-44 payload of the JSON-encoded StopContacts or StopInstructions, or of the bare pickup code
+    pub ciphertext: String,
+}
+
+// Contacts are revealed from acceptance until the handoff, and withdrawn after
+pub fn revealable(delivery: &DeliveryRequest) -> bool {
+    matches!(delivery.status, DeliveryStatus::Accepted | DeliveryStatus::InTransit)
+}
+
+// Instructions only while the courier is on the way
+pub fn instructions_revealable(delivery: &DeliveryRequest) -> bool {
+    delivery.status == DeliveryStatus::InTransit
+}
+
+// Once the package is handed over, access instructions are discarded rather than kept sealed
+pub fn instructions_expired(delivery: &DeliveryRequest) -> bool {
+    matches!(delivery.status, DeliveryStatus::Completed | DeliveryStatus::Confirmed | DeliveryStatus::Disputed)
+}
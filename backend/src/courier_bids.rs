@@ -0,0 +1,115 @@
+// courier_bids.rs - A courier's bids with how each one turned out, and how they do overall
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{Amount, DeliveryBid, DeliveryRequest, DeliveryStatus, PubkeyId};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum BidOutcome {
+    // Accepted, whatever became of the delivery after
+    Won,
+    // Another bid was accepted, the delivery closed without one, or the courier never started
+    Lost,
+    // The delivery is still open for bids
+    Pending,
+}
+
+impl BidOutcome {
+    pub fn of(delivery: &DeliveryRequest, bid: &DeliveryBid) -> Self {
+        if delivery.no_shows.iter().any(|n| n.bid_id == bid.id) {
+            return BidOutcome::Lost;
+        }
+        match &delivery.accepted_bid {
+            Some(accepted) if *accepted == bid.id => BidOutcome::Won,
+            Some(_) => BidOutcome::Lost,
+            None if delivery.status == DeliveryStatus::Open => BidOutcome::Pending,
+            None => BidOutcome::Lost,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BidRecord {
+    pub delivery_id: String,
+    pub delivery_status: DeliveryStatus,
+    pub bid: DeliveryBid,
+    pub outcome: BidOutcome,
+    // The sender's offer the bid competed against; unknown for deliveries accepted before it was kept
+    pub offer_amount: Option<Amount>,
+    // How far below the offer the bid was, in percent (negative when above it)
+    pub discount_percent: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct BidSummary {
+    pub bids: u32,
+    pub won: u32,
+    pub lost: u32,
+    pub pending: u32,
+    // Won out of decided (won or lost) bids
+    pub win_rate: Option<f64>,
+    // Mean discount of won bids whose offer is known, in percent
+    pub average_winning_discount_percent: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BidHistory {
+    pub npub: String,
+    // Over all the courier's bids, whatever the outcome filter
+    pub summary: BidSummary,
+    // Newest first
+    pub bids: Vec<BidRecord>,
+}
+
+impl BidHistory {
+    pub fn build(npub: &str, deliveries: &[DeliveryRequest], outcome: Option<BidOutcome>) -> Self {
+        let courier = PubkeyId::normalize(npub);
+        let mut records: Vec<BidRecord> = deliveries.iter()
+            .flat_map(|delivery| delivery.bids.iter().map(move |bid| (delivery, bid)))
+            .filter(|(_, bid)| PubkeyId::normalize(&bid.courier) == courier)
+            .map(|(delivery, bid)| {
+                // Acceptance replaces offer_amount with the accepted bid's
+                let offer_amount = match delivery.accepted_bid {
+                    Some(_) => delivery.listed_amount,
+                    None => Some(delivery.offer_amount),
+                };
+                BidRecord {
+                    delivery_id: delivery.id.clone(),
+                    delivery_status: delivery.status.clone(),
+                    bid: bid.clone(),
+                    outcome: BidOutcome::of(delivery, bid),
+                    offer_amount,
+                    discount_percent: offer_amount.and_then(|offer| discount_percent(offer, bid.amount)),
+                }
+            })
+            .collect();
+        records.sort_by_key(|r| std::cmp::Reverse(r.bid.created_at));
+
+        let mut summary = BidSummary::default();
+        let mut winning_discounts = Vec::new();
+        for record in &records {
+            summary.bids += 1;
+            match record.outcome {
+                BidOutcome::Won => {
+                    summary.won += 1;
+                    winning_discounts.extend(record.discount_percent);
+                }
+                BidOutcome::Lost => summary.lost += 1,
+                BidOutcome::Pending => summary.pending += 1,
+            }
+        }
+        let decided = summary.won + summary.lost;
+        summary.win_rate = (decided > 0).then(|| summary.won as f64 / decided as f64);
+        summary.average_winning_discount_percent = (!winning_discounts.is_empty())
+            .then(|| winning_discounts.iter().sum::<f64>() / winning_discounts.len() as f64);
+
+        records.retain(|r| outcome.is_none_or(|o| r.outcome == o));
+        Self { npub: npub.to_string(), summary, bids: records }
+    }
+}
+
+fn discount_percent(offer: Amount, bid: Amount) -> Option<f64> {
+    let offer = offer.msats() as f64;
+    (offer > 0.0).then(|| (offer - bid.msats() as f64) / offer * 100.0)
+}
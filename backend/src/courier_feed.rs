@@ -0,0 +1,195 @@
+// courier_feed.rs - The items a courier's app shows in one stream: open jobs near them, deliveries
+// offered to them directly, status changes on deliveries they carry and their payouts, newest first
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::projection::PaymentState;
+use crate::sync::last_activity;
+use crate::{calculate_distance, Amount, DeliveryRequest, DeliveryStatus, DeliveryUpdate, GeoPoint, PubkeyId};
+
+pub const DEFAULT_RADIUS_KM: f64 = 10.0;
+pub const DEFAULT_PAGE_SIZE: usize = 20;
+pub const MAX_PAGE_SIZE: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedItemKind {
+    // An open public delivery with its pickup within the radius
+    NearbyJob,
+    // A private delivery the courier is allowed to bid on, or an organization assignment
+    DirectOffer,
+    // A status change on a delivery whose bid was accepted
+    StatusChange,
+    // A delivery that paid the courier out
+    Payout,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct FeedItem {
+    // Stable across pages, e.g. "job:<delivery_id>"
+    pub id: String,
+    pub kind: FeedItemKind,
+    pub delivery_id: String,
+    pub at: i64,
+    pub status: DeliveryStatus,
+    // Offer for jobs and offers, amount paid for payouts
+    pub amount: Option<Amount>,
+    // From the courier's position to the pickup, for nearby jobs
+    pub distance_meters: Option<f64>,
+    // The delivery itself, for jobs and offers
+    pub delivery: Option<DeliveryRequest>,
+}
+
+// Position in the feed: the last item of the previous page, as "<at>:<id>"
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cursor {
+    pub at: i64,
+    pub id: String,
+}
+
+impl Cursor {
+    pub fn parse(value: &str) -> Option<Self> {
+        let (at, id) = value.split_once(':')?;
+        Some(Self { at: at.parse().ok()?, id: id.to_string() })
+    }
+
+    fn of(item: &FeedItem) -> Self {
+        Self { at: item.at, id: item.id.clone() }
+    }
+
+    // Whether an item comes after the cursor in feed order
+    fn precedes(&self, item: &FeedItem) -> bool {
+        (item.at, item.id.as_str()) < (self.at, self.id.as_str())
+    }
+}
+
+impl std::fmt::Display for Cursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}:{}", self.at, self.id)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct FeedPage {
+    pub items: Vec<FeedItem>,
+    // Pass as `cursor` for the next page; none on the last page
+    pub next_cursor: Option<String>,
+}
+
+// Where the courier is looking for work
+#[derive(Debug, Clone)]
+pub struct Nearby {
+    pub point: GeoPoint,
+    pub radius_meters: f64,
+}
+
+// Every feed item for a courier, newest first. `updates` returns the status updates held for a
+// delivery, oldest first.
+pub fn build<'a, F>(courier: &str, deliveries: &[DeliveryRequest], nearby: Option<&Nearby>, updates: F) -> Vec<FeedItem>
+where
+    F: Fn(&str) -> Vec<&'a DeliveryUpdate>,
+{
+    let courier = PubkeyId::normalize(courier);
+    let mut items = Vec::new();
+
+    for delivery in deliveries {
+        if delivery.status == DeliveryStatus::Open {
+            if let Some(item) = offer_item(&courier, delivery).or_else(|| job_item(&courier, delivery, nearby?)) {
+                items.push(item);
+            }
+            continue;
+        }
+
+        if delivery.accepted_courier().is_none_or(|c| PubkeyId::normalize(c) != courier) {
+            continue;
+        }
+        let accepted_at = delivery.accepted_at.unwrap_or(delivery.created_at);
+        for update in updates(&delivery.id) {
+            if update.timestamp < accepted_at {
+                continue;
+            }
+            let kind = update.kind().map_or("other".to_string(), |k| k.to_string());
+            items.push(FeedItem {
+                id: format!("status:{}:{}", delivery.id, kind),
+                kind: FeedItemKind::StatusChange,
+                delivery_id: delivery.id.clone(),
+                at: update.timestamp,
+                status: update.status.clone(),
+                amount: None,
+                distance_meters: None,
+                delivery: None,
+            });
+        }
+        if PaymentState::of(&delivery.status, true) == PaymentState::Settled {
+            items.push(FeedItem {
+                id: format!("payout:{}", delivery.id),
+                kind: FeedItemKind::Payout,
+                delivery_id: delivery.id.clone(),
+                at: delivery.completed_at.unwrap_or_else(|| last_activity(delivery)),
+                status: delivery.status.clone(),
+                amount: Some(delivery.offer_amount),
+                distance_meters: None,
+                delivery: None,
+            });
+        }
+    }
+
+    items.sort_by(|a, b| (b.at, &b.id).cmp(&(a.at, &a.id)));
+    items
+}
+
+fn offer_item(courier: &str, delivery: &DeliveryRequest) -> Option<FeedItem> {
+    let at = match &delivery.assignment {
+        Some(assignment) if PubkeyId::normalize(&assignment.courier) == courier => assignment.assigned_at,
+        // Assigned to someone else
+        Some(_) => return None,
+        None if delivery.private && !delivery.allowed_couriers.is_empty() && delivery.allows_courier(courier) => delivery.created_at,
+        None => return None,
+    };
+    Some(FeedItem {
+        id: format!("offer:{}", delivery.id),
+        kind: FeedItemKind::DirectOffer,
+        delivery_id: delivery.id.clone(),
+        at,
+        status: delivery.status.clone(),
+        amount: Some(delivery.offer_amount),
+        distance_meters: None,
+        delivery: Some(delivery.clone()),
+    })
+}
+
+fn job_item(courier: &str, delivery: &DeliveryRequest, nearby: &Nearby) -> Option<FeedItem> {
+    if delivery.private || delivery.assignment.is_some() || !delivery.allows_courier(courier) {
+        return None;
+    }
+    // Jobs already bid on are no longer news
+    if delivery.bids.iter().any(|b| PubkeyId::normalize(&b.courier) == courier) {
+        return None;
+    }
+    let distance = calculate_distance(&nearby.point, delivery.pickup.coordinates.as_ref()?);
+    if distance > nearby.radius_meters {
+        return None;
+    }
+    Some(FeedItem {
+        id: format!("job:{}", delivery.id),
+        kind: FeedItemKind::NearbyJob,
+        delivery_id: delivery.id.clone(),
+        at: delivery.created_at,
+        status: delivery.status.clone(),
+        amount: Some(delivery.offer_amount),
+        distance_meters: Some(distance),
+        delivery: Some(delivery.clone()),
+    })
+}
+
+// The page of `items` (newest first) after `cursor`
+pub fn page(items: Vec<FeedItem>, cursor: Option<&Cursor>, limit: usize) -> FeedPage {
+    let mut items: Vec<FeedItem> = items.into_iter()
+        .filter(|item| cursor.is_none_or(|c| c.precedes(item)))
+        .take(limit + 1)
+        .collect();
+    let more = items.len() > limit;
+    items.truncate(limit);
+    let next_cursor = more.then(|| items.last().map(|item| Cursor::of(item).to_string())).flatten();
+    FeedPage { items, next_cursor }
+}
@@ -0,0 +1,106 @@
+// courier_jobs.rs - A courier's current jobs with what the app needs to work them: both stops,
+// the sealed details revealed to the courier and the next step the courier has to take
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::contacts::Reveal;
+use crate::{Amount, DeliveryRequest, DeliveryStatus, Location, PackageInfo, PubkeyId};
+
+// Listed when no status filter is given
+pub const DEFAULT_STATUSES: [DeliveryStatus; 2] = [DeliveryStatus::Accepted, DeliveryStatus::InTransit];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum NextAction {
+    // Accept or decline a dispatcher's assignment
+    AcknowledgeAssignment,
+    // Collect the package and start transit
+    PickUp,
+    // Hand the package over and complete, or report a failed attempt
+    DropOff,
+    // Bring the package back to the pickup after a failed attempt
+    ReturnToPickup,
+    // Nothing to do until the sender confirms
+    AwaitConfirmation,
+    // Nothing to do until the dispute is resolved
+    AwaitResolution,
+}
+
+impl NextAction {
+    pub fn for_delivery(delivery: &DeliveryRequest) -> Option<Self> {
+        match delivery.status {
+            DeliveryStatus::Open if delivery.assignment.is_some() => Some(NextAction::AcknowledgeAssignment),
+            DeliveryStatus::Accepted => Some(NextAction::PickUp),
+            DeliveryStatus::InTransit if delivery.returning => Some(NextAction::ReturnToPickup),
+            DeliveryStatus::InTransit => Some(NextAction::DropOff),
+            DeliveryStatus::Completed => Some(NextAction::AwaitConfirmation),
+            DeliveryStatus::Disputed => Some(NextAction::AwaitResolution),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CourierJob {
+    pub delivery_id: String,
+    pub status: DeliveryStatus,
+    // Each stop with its address, coordinates, what3words and plus codes
+    pub pickup: Location,
+    pub dropoff: Location,
+    pub packages: Vec<PackageInfo>,
+    pub amount: Amount,
+    // From the accepted bid
+    pub pickup_eta: Option<i64>,
+    pub dropoff_eta: Option<i64>,
+    pub accepted_at: Option<i64>,
+    pub returning: bool,
+    // Stop contacts and access instructions encrypted to the courier, once revealed
+    pub contact_reveal: Option<Reveal>,
+    pub instructions_reveal: Option<Reveal>,
+    // The label's pickup code encrypted to the courier, until the pickup
+    pub pickup_code_reveal: Option<Reveal>,
+    pub next_action: Option<NextAction>,
+}
+
+impl CourierJob {
+    fn from_delivery(delivery: &DeliveryRequest) -> Self {
+        let bid = delivery.accepted_bid.as_ref()
+            .and_then(|id| delivery.bids.iter().find(|b| &b.id == id));
+        Self {
+            delivery_id: delivery.id.clone(),
+            status: delivery.status.clone(),
+            pickup: delivery.pickup.clone(),
+            dropoff: delivery.dropoff.clone(),
+            packages: delivery.packages.clone(),
+            amount: delivery.offer_amount,
+            pickup_eta: bid.and_then(|b| b.pickup_eta),
+            dropoff_eta: bid.and_then(|b| b.dropoff_eta),
+            accepted_at: delivery.accepted_at,
+            returning: delivery.returning,
+            contact_reveal: delivery.contact_reveal.clone(),
+            instructions_reveal: delivery.instructions_reveal.clone(),
+            // Filled in by the handler, which holds the marketplace key
+            pickup_code_reveal: None,
+            next_action: NextAction::for_delivery(delivery),
+        }
+    }
+}
+
+// The courier's deliveries in the given statuses, longest-held first. Open deliveries count as the
+// courier's while a dispatcher's assignment to them awaits acknowledgement.
+pub fn build(courier: &str, deliveries: &[DeliveryRequest], statuses: &[DeliveryStatus]) -> Vec<CourierJob> {
+    let courier = PubkeyId::normalize(courier);
+    let mut jobs: Vec<CourierJob> = deliveries.iter()
+        .filter(|d| statuses.contains(&d.status))
+        .filter(|d| {
+            let holder = match d.status {
+                DeliveryStatus::Open => d.assignment.as_ref().map(|a| a.courier.as_str()),
+                _ => d.accepted_courier(),
+            };
+            holder.is_some_and(|c| PubkeyId::normalize(c) == courier)
+        })
+        .map(CourierJob::from_delivery)
+        .collect();
+    jobs.sort_by_key(|job| job.accepted_at.unwrap_or(i64::MAX));
+    jobs
+}
@@ -0,0 +1,90 @@
+// courier_lists.rs - Per-sender favorite and blocked couriers as NIP-51 follow sets
+use nostr::{Event, Tag, TagKind};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::PubkeyId;
+
+// NIP-51 follow set (parameterized replaceable)
+pub const FOLLOW_SET_KIND: u16 = 30000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CourierListKind {
+    Favorites,
+    Blocked,
+}
+
+impl CourierListKind {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "favorites" => Some(Self::Favorites),
+            "blocked" => Some(Self::Blocked),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Favorites => "favorite-couriers",
+            Self::Blocked => "blocked-couriers",
+        }
+    }
+
+    // Blocked couriers are kept as NIP-51 private (encrypted) items
+    pub fn is_private(&self) -> bool {
+        *self == Self::Blocked
+    }
+
+    pub fn identifier(&self, sender: &str) -> String {
+        format!("nostr-delivery/{}/{}", self.name(), sender)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct CourierLists {
+    pub favorites: Vec<String>,
+    pub blocked: Vec<String>,
+}
+
+// `p` tags carry hex keys where the courier is a valid pubkey
+pub fn courier_tags(couriers: &[String]) -> Vec<Vec<String>> {
+    couriers
+        .iter()
+        .map(|courier| {
+            vec!["p".to_string(), PubkeyId::normalize_hex(courier)]
+        })
+        .collect()
+}
+
+pub fn list_tags(kind: CourierListKind, sender: &str, public_items: Vec<Vec<String>>) -> Vec<Tag> {
+    let mut tags = vec![
+        Tag::custom(TagKind::Custom("d".into()), vec![kind.identifier(sender)]),
+        Tag::custom(TagKind::Custom("title".into()), vec![format!("{} of {}", kind.name(), sender)]),
+    ];
+    tags.extend(public_items.into_iter().filter_map(|item| {
+        let (name, values) = item.split_first()?;
+        Some(Tag::custom(TagKind::Custom(name.clone().into()), values.to_vec()))
+    }));
+    tags
+}
+
+// Couriers from `p` items, in either the event tags or decrypted private content
+pub fn couriers_from_items<'a, I>(items: I) -> Vec<String>
+where
+    I: IntoIterator<Item = &'a [String]>,
+{
+    let mut couriers: Vec<String> = Vec::new();
+    for item in items {
+        if item.len() >= 2 && item[0] == "p" {
+            let courier = PubkeyId::normalize(&item[1]);
+            if !couriers.contains(&courier) {
+                couriers.push(courier);
+            }
+        }
+    }
+    couriers
+}
+
+pub fn public_couriers(event: &Event) -> Vec<String> {
+    couriers_from_items(event.tags.iter().map(|tag| tag.as_slice()))
+}
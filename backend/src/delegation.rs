@@ -0,0 +1,46 @@
+// delegation.rs - NIP-26 delegation: users who can't sign for themselves grant the marketplace
+// key a token, and events published on their behalf carry it as proof of who they speak for
+use nostr::nips::nip26::{DelegationTag, EventProperties};
+use nostr::{Event, PublicKey, Tag};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+// A delegation from a user to the marketplace key
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Delegation {
+    // Hex pubkey of the delegating user
+    pub delegator: String,
+    // NIP-26 conditions, e.g. `kind=35001&created_at>1700000000`
+    pub conditions: String,
+    // The delegator's signature over `nostr:delegation:<delegatee>:<conditions>`
+    pub token: String,
+}
+
+impl Delegation {
+    fn parse(&self) -> Result<DelegationTag, String> {
+        let tag = vec!["delegation".to_string(), self.delegator.clone(), self.conditions.clone(), self.token.clone()];
+        DelegationTag::try_from(tag).map_err(|e| format!("Invalid delegation: {}", e))
+    }
+
+    // Check the token was signed by the delegator for `delegatee`
+    pub fn verify(&self, delegatee: &PublicKey) -> Result<(), String> {
+        let tag = self.parse()?;
+        nostr::nips::nip26::verify_delegation_signature(&tag.delegator_pubkey(), tag.signature(), delegatee, &tag.conditions())
+            .map_err(|_| "Delegation token signature is invalid".to_string())
+    }
+
+    // `delegation` tag for an event `delegatee` signs, if the conditions allow its kind and time
+    pub fn tag(&self, delegatee: &PublicKey, kind: u16, created_at: u64) -> Option<Tag> {
+        let tag = self.parse().ok()?;
+        tag.validate(delegatee, &EventProperties::new(kind, created_at)).ok()?;
+        Tag::parse(&["delegation", &self.delegator, &self.conditions, &self.token]).ok()
+    }
+}
+
+// User an event was published on behalf of: the delegator of a valid `delegation` tag
+pub fn delegator(event: &Event) -> Option<PublicKey> {
+    let values = event.tags.iter().find(|t| t.as_slice().first().is_some_and(|name| name == "delegation"))?;
+    let tag = DelegationTag::try_from(values.as_slice().to_vec()).ok()?;
+    tag.validate(&event.pubkey, &EventProperties::from_event(event)).ok()?;
+    Some(tag.delegator_pubkey())
+}
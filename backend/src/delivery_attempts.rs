@@ -0,0 +1,149 @@
+// delivery_attempts.rs - Failed dropoff attempts (recipient absent, wrong address, ...) and the
+// operator's policy for what follows: another try, a return to the pickup, or a partial payout
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{Amount, GeoPoint};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureReason {
+    RecipientAbsent,
+    WrongAddress,
+    AccessDenied,
+    Refused,
+    Unsafe,
+    Other,
+}
+
+impl FailureReason {
+    pub const ALL: [FailureReason; 6] = [
+        Self::RecipientAbsent,
+        Self::WrongAddress,
+        Self::AccessDenied,
+        Self::Refused,
+        Self::Unsafe,
+        Self::Other,
+    ];
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Self::RecipientAbsent => "recipient_absent",
+            Self::WrongAddress => "wrong_address",
+            Self::AccessDenied => "access_denied",
+            Self::Refused => "refused",
+            Self::Unsafe => "unsafe",
+            Self::Other => "other",
+        }
+    }
+
+    pub fn parse(code: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|reason| reason.code() == code.trim())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AttemptOutcome {
+    // The courier keeps the package and tries again
+    Retry,
+    // The courier takes the package back; completing the delivery at the pickup ends it
+    Return,
+    // The delivery ends here and the courier is paid a share of the offer
+    PartialPayout,
+}
+
+impl AttemptOutcome {
+    pub fn parse(code: &str) -> Option<Self> {
+        match code.trim() {
+            "retry" => Some(Self::Retry),
+            "return" => Some(Self::Return),
+            "partial_payout" => Some(Self::PartialPayout),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct FailedAttempt {
+    // 1 for the first attempt
+    pub number: u32,
+    pub reason: FailureReason,
+    pub note: Option<String>,
+    // Evidence: base64 encoded images or URLs, like proof of delivery
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub photos: Vec<String>,
+    pub location: Option<GeoPoint>,
+    pub courier: String,
+    pub outcome: AttemptOutcome,
+    pub at: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct AttemptPolicy {
+    pub max_attempts: u32,
+    // What follows the last allowed attempt
+    pub final_outcome: AttemptOutcome,
+    // Reasons that skip further tries, e.g. a refused package goes straight back
+    pub overrides: Vec<(FailureReason, AttemptOutcome)>,
+    pub payout_percent: f64,
+}
+
+impl Default for AttemptPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            final_outcome: AttemptOutcome::Return,
+            overrides: vec![(FailureReason::Refused, AttemptOutcome::Return)],
+            payout_percent: 50.0,
+        }
+    }
+}
+
+impl AttemptPolicy {
+    // MAX_DELIVERY_ATTEMPTS (default 3), ATTEMPT_FINAL_OUTCOME (return or partial_payout),
+    // ATTEMPT_OUTCOMES ("reason=outcome,..."; default refused=return) and
+    // FAILED_ATTEMPT_PAYOUT_PERCENT (default 50)
+    pub fn from_env() -> Result<Self, String> {
+        let mut policy = Self::default();
+        if let Ok(raw) = std::env::var("MAX_DELIVERY_ATTEMPTS") {
+            policy.max_attempts = raw.parse().ok().filter(|max| *max > 0)
+                .ok_or_else(|| format!("MAX_DELIVERY_ATTEMPTS must be a positive number, got '{}'", raw))?;
+        }
+        if let Ok(raw) = std::env::var("ATTEMPT_FINAL_OUTCOME") {
+            policy.final_outcome = AttemptOutcome::parse(&raw).filter(|outcome| *outcome != AttemptOutcome::Retry)
+                .ok_or_else(|| format!("ATTEMPT_FINAL_OUTCOME must be return or partial_payout, got '{}'", raw))?;
+        }
+        if let Ok(raw) = std::env::var("ATTEMPT_OUTCOMES") {
+            policy.overrides = raw
+                .split(',')
+                .filter(|entry| !entry.trim().is_empty())
+                .map(|entry| {
+                    let (reason, outcome) = entry.split_once('=').unwrap_or((entry, ""));
+                    FailureReason::parse(reason).zip(AttemptOutcome::parse(outcome))
+                        .ok_or_else(|| format!("Invalid ATTEMPT_OUTCOMES entry '{}'", entry.trim()))
+                })
+                .collect::<Result<_, _>>()?;
+        }
+        if let Ok(raw) = std::env::var("FAILED_ATTEMPT_PAYOUT_PERCENT") {
+            policy.payout_percent = raw.parse().ok().filter(|percent| (0.0..=100.0).contains(percent))
+                .ok_or_else(|| format!("FAILED_ATTEMPT_PAYOUT_PERCENT must be between 0 and 100, got '{}'", raw))?;
+        }
+        Ok(policy)
+    }
+
+    pub fn outcome(&self, reason: FailureReason, attempt: u32) -> AttemptOutcome {
+        if let Some((_, outcome)) = self.overrides.iter().find(|(r, _)| *r == reason) {
+            return *outcome;
+        }
+        if attempt < self.max_attempts {
+            AttemptOutcome::Retry
+        } else {
+            self.final_outcome
+        }
+    }
+
+    pub fn payout(&self, offer_amount: Amount) -> Amount {
+        offer_amount.percent(self.payout_percent).unwrap_or_default()
+    }
+}
@@ -0,0 +1,418 @@
+// email.rs - Optional SMTP gateway: emails senders and recipients when a delivery is accepted,
+// picked up and delivered
+use chrono::Utc;
+use nostr::base64::engine::general_purpose::STANDARD;
+use nostr::base64::Engine;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+
+use crate::i18n::{Language, Message};
+use crate::{DeliveryRequest, DeliveryStatus};
+
+pub type EmailError = Box<dyn std::error::Error + Send + Sync>;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmtpSecurity {
+    // Plain connection upgraded with STARTTLS (usually port 587)
+    StartTls,
+    // TLS from the first byte (usually port 465)
+    Tls,
+    // Unencrypted, for local relays only
+    None,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmailEvent {
+    BidAccepted,
+    OutForDelivery,
+    Delivered,
+}
+
+impl EmailEvent {
+    pub const ALL: [EmailEvent; 3] = [EmailEvent::BidAccepted, EmailEvent::OutForDelivery, EmailEvent::Delivered];
+
+    pub fn for_status(status: &DeliveryStatus) -> Option<Self> {
+        match status {
+            DeliveryStatus::Accepted => Some(Self::BidAccepted),
+            DeliveryStatus::InTransit => Some(Self::OutForDelivery),
+            DeliveryStatus::Completed => Some(Self::Delivered),
+            _ => None,
+        }
+    }
+
+    // Template file name, without `.txt`
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::BidAccepted => "bid_accepted",
+            Self::OutForDelivery => "out_for_delivery",
+            Self::Delivered => "delivered",
+        }
+    }
+
+    // Recipients aren't party to the bidding, so they only hear about the handoff
+    pub fn for_recipient(self) -> bool {
+        self != Self::BidAccepted
+    }
+}
+
+// Subject and body with `{placeholder}`s filled in from the delivery
+#[derive(Debug, Clone)]
+pub struct EmailTemplate {
+    pub subject: String,
+    pub body: String,
+}
+
+impl EmailTemplate {
+    fn builtin(event: EmailEvent, language: Language) -> Self {
+        let (subject, body) = match event {
+            EmailEvent::BidAccepted => (Message::EmailBidAcceptedSubject, Message::EmailBidAcceptedBody),
+            EmailEvent::OutForDelivery => (Message::EmailOutForDeliverySubject, Message::EmailOutForDeliveryBody),
+            EmailEvent::Delivered => (Message::EmailDeliveredSubject, Message::EmailDeliveredBody),
+        };
+        Self { subject: subject.text(language).to_string(), body: body.text(language).to_string() }
+    }
+
+    // `<dir>/<event>.<language>.txt`, else `<dir>/<event>.txt`, with a `Subject:` first line,
+    // falling back to the built-in template
+    fn load(dir: Option<&str>, event: EmailEvent, language: Language) -> Result<Self, String> {
+        let Some(dir) = dir else {
+            return Ok(Self::builtin(event, language));
+        };
+        for name in [format!("{}.{}.txt", event.name(), language.code()), format!("{}.txt", event.name())] {
+            let path = std::path::Path::new(dir).join(name);
+            let text = match std::fs::read_to_string(&path) {
+                Ok(text) => text,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+            };
+            let (first, body) = text.split_once('\n').unwrap_or((&text, ""));
+            let subject = first
+                .strip_prefix("Subject:")
+                .ok_or_else(|| format!("{} must start with a 'Subject:' line", path.display()))?;
+            return Ok(Self { subject: subject.trim().to_string(), body: body.trim_start_matches(['\r', '\n']).to_string() });
+        }
+        Ok(Self::builtin(event, language))
+    }
+
+    pub fn render(&self, delivery: &DeliveryRequest, tracking_url: &str, language: Language) -> (String, String) {
+        let proof = delivery.proof_of_delivery.as_ref();
+        let completed_at = delivery
+            .completed_at
+            .and_then(|at| chrono::DateTime::from_timestamp(at, 0))
+            .map(|at| at.format("%Y-%m-%d %H:%M UTC").to_string())
+            .unwrap_or_default();
+        let values = [
+            ("{delivery_id}", delivery.id.clone()),
+            ("{pickup}", delivery.pickup.address.clone()),
+            ("{dropoff}", delivery.dropoff.address.clone()),
+            ("{amount}", delivery.offer_amount.to_string()),
+            ("{tracking_url}", tracking_url.to_string()),
+            ("{completed_at}", completed_at),
+            ("{signature}", proof.and_then(|p| p.signature_name.as_ref()).map(|n| Message::SignedFor.render(language, &[("name", n)])).unwrap_or_default()),
+            ("{comments}", proof.and_then(|p| p.comments.as_ref()).map(|c| Message::CourierNote.render(language, &[("comments", c)])).unwrap_or_default()),
+        ];
+        let fill = |template: &str| values.iter().fold(template.to_string(), |text, (key, value)| text.replace(key, value));
+        (fill(&self.subject), fill(&self.body))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EmailConfig {
+    pub host: String,
+    pub port: u16,
+    pub security: SmtpSecurity,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub from: String,
+    templates: Vec<(EmailEvent, Language, EmailTemplate)>,
+}
+
+impl EmailConfig {
+    // SMTP_HOST enables email; SMTP_PORT (587), SMTP_SECURITY (starttls, tls or none),
+    // SMTP_USERNAME/SMTP_PASSWORD, SMTP_FROM and EMAIL_TEMPLATES_DIR configure it
+    pub fn from_env() -> Result<Option<Self>, String> {
+        let Some(host) = std::env::var("SMTP_HOST").ok().filter(|h| !h.is_empty()) else {
+            return Ok(None);
+        };
+        let security = match std::env::var("SMTP_SECURITY").unwrap_or_default().to_lowercase().as_str() {
+            "" | "starttls" => SmtpSecurity::StartTls,
+            "tls" => SmtpSecurity::Tls,
+            "none" => SmtpSecurity::None,
+            other => return Err(format!("Invalid SMTP_SECURITY '{}': use starttls, tls or none", other)),
+        };
+        let port = match std::env::var("SMTP_PORT").ok().filter(|p| !p.is_empty()) {
+            Some(port) => port.parse().map_err(|_| format!("Invalid SMTP_PORT '{}'", port))?,
+            None if security == SmtpSecurity::Tls => 465,
+            None => 587,
+        };
+        let from = std::env::var("SMTP_FROM").ok().filter(|f| !f.is_empty()).ok_or("SMTP_FROM is required when SMTP_HOST is set")?;
+        let dir = std::env::var("EMAIL_TEMPLATES_DIR").ok().filter(|d| !d.is_empty());
+        let templates = EmailEvent::ALL
+            .iter()
+            .flat_map(|event| Language::ALL.map(|language| (*event, language)))
+            .map(|(event, language)| Ok((event, language, EmailTemplate::load(dir.as_deref(), event, language)?)))
+            .collect::<Result<_, String>>()?;
+
+        Ok(Some(Self {
+            host,
+            port,
+            security,
+            username: std::env::var("SMTP_USERNAME").ok().filter(|u| !u.is_empty()),
+            password: std::env::var("SMTP_PASSWORD").ok(),
+            from,
+            templates,
+        }))
+    }
+
+    pub fn template(&self, event: EmailEvent, language: Language) -> EmailTemplate {
+        self.templates
+            .iter()
+            .find(|(e, l, _)| *e == event && *l == language)
+            .map(|(_, _, template)| template.clone())
+            .unwrap_or_else(|| EmailTemplate::builtin(event, language))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    pub filename: String,
+    pub content_type: String,
+    // Base64 encoded
+    pub data: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+    pub attachments: Vec<Attachment>,
+}
+
+impl Email {
+    // Email for a delivery event; proof-of-delivery photos are attached, or linked when they're URLs
+    pub fn for_delivery(config: &EmailConfig, event: EmailEvent, language: Language, delivery: &DeliveryRequest, tracking_url: &str, to: String) -> Self {
+        let (subject, mut body) = config.template(event, language).render(delivery, tracking_url, language);
+        let mut attachments = Vec::new();
+        if event == EmailEvent::Delivered {
+            for (i, image) in delivery.proof_of_delivery.iter().flat_map(|p| &p.images).enumerate() {
+                if image.starts_with("http://") || image.starts_with("https://") {
+                    body.push_str(&Message::PhotoLink.render(language, &[("url", image)]));
+                } else if let Some(attachment) = photo_attachment(image, i + 1) {
+                    attachments.push(attachment);
+                }
+            }
+        }
+        Self { to, subject, body, attachments }
+    }
+
+    fn to_message(&self, from: &str) -> String {
+        let boundary = format!("=_{:x}", nostr::secp256k1::rand::random::<u64>());
+        let mut message = format!(
+            "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nMIME-Version: 1.0\r\n",
+            from,
+            self.to,
+            encode_header(&self.subject),
+            Utc::now().to_rfc2822()
+        );
+        let text = format!(
+            "Content-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: base64\r\n\r\n{}\r\n",
+            wrap_base64(&STANDARD.encode(self.body.replace("\r\n", "\n").replace('\n', "\r\n")))
+        );
+        if self.attachments.is_empty() {
+            message.push_str(&text);
+            return message;
+        }
+
+        message.push_str(&format!("Content-Type: multipart/mixed; boundary=\"{}\"\r\n\r\n--{}\r\n{}", boundary, boundary, text));
+        for attachment in &self.attachments {
+            message.push_str(&format!(
+                "--{}\r\nContent-Type: {}\r\nContent-Transfer-Encoding: base64\r\nContent-Disposition: attachment; filename=\"{}\"\r\n\r\n{}\r\n",
+                boundary,
+                attachment.content_type,
+                attachment.filename,
+                wrap_base64(&attachment.data)
+            ));
+        }
+        message.push_str(&format!("--{}--\r\n", boundary));
+        message
+    }
+}
+
+// A photo given as a data URL or bare base64, which is assumed to be JPEG
+fn photo_attachment(image: &str, index: usize) -> Option<Attachment> {
+    let (content_type, data) = match image.strip_prefix("data:") {
+        Some(rest) => {
+            let (meta, data) = rest.split_once(',')?;
+            (meta.strip_suffix(";base64")?.to_string(), data)
+        }
+        None => ("image/jpeg".to_string(), image),
+    };
+    let data: String = data.split_whitespace().collect();
+    STANDARD.decode(&data).ok()?;
+    let extension = content_type.rsplit('/').next().unwrap_or("jpg").to_string();
+    Some(Attachment { filename: format!("proof-{}.{}", index, extension), content_type, data })
+}
+
+fn encode_header(value: &str) -> String {
+    if value.is_ascii() {
+        value.to_string()
+    } else {
+        format!("=?UTF-8?B?{}?=", STANDARD.encode(value))
+    }
+}
+
+fn wrap_base64(data: &str) -> String {
+    data.as_bytes().chunks(76).map(|line| String::from_utf8_lossy(line).into_owned()).collect::<Vec<_>>().join("\r\n")
+}
+
+// SMTP client sending one message per connection
+pub struct Mailer {
+    config: EmailConfig,
+    tls: TlsConnector,
+}
+
+impl Mailer {
+    pub fn new(config: EmailConfig) -> Self {
+        let roots = rustls::RootCertStore { roots: webpki_roots::TLS_SERVER_ROOTS.to_vec() };
+        let tls = rustls::ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .expect("ring supports the default protocol versions")
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        Self { config, tls: TlsConnector::from(Arc::new(tls)) }
+    }
+
+    pub fn config(&self) -> &EmailConfig {
+        &self.config
+    }
+
+    pub async fn send(&self, email: &Email) -> Result<(), EmailError> {
+        tokio::time::timeout(TIMEOUT, self.deliver(email)).await.map_err(|_| "SMTP server timed out")?
+    }
+
+    async fn deliver(&self, email: &Email) -> Result<(), EmailError> {
+        let tcp = TcpStream::connect((self.config.host.as_str(), self.config.port)).await?;
+        match self.config.security {
+            SmtpSecurity::Tls => {
+                let stream = self.tls.connect(self.server_name()?, tcp).await?;
+                let mut session = Session::new(stream);
+                session.expect(220).await?;
+                session.ehlo().await?;
+                self.transact(&mut session, email).await
+            }
+            SmtpSecurity::StartTls => {
+                let mut session = Session::new(tcp);
+                session.expect(220).await?;
+                session.ehlo().await?;
+                session.command("STARTTLS", 220).await?;
+                let stream = self.tls.connect(self.server_name()?, session.into_inner()).await?;
+                let mut session = Session::new(stream);
+                session.ehlo().await?;
+                self.transact(&mut session, email).await
+            }
+            SmtpSecurity::None => {
+                let mut session = Session::new(tcp);
+                session.expect(220).await?;
+                session.ehlo().await?;
+                self.transact(&mut session, email).await
+            }
+        }
+    }
+
+    fn server_name(&self) -> Result<rustls::pki_types::ServerName<'static>, EmailError> {
+        Ok(rustls::pki_types::ServerName::try_from(self.config.host.clone())?)
+    }
+
+    async fn transact<S: AsyncRead + AsyncWrite + Unpin>(&self, session: &mut Session<S>, email: &Email) -> Result<(), EmailError> {
+        if let Some(username) = &self.config.username {
+            let password = self.config.password.as_deref().unwrap_or_default();
+            let credentials = STANDARD.encode(format!("\0{}\0{}", username, password));
+            session.command(&format!("AUTH PLAIN {}", credentials), 235).await?;
+        }
+        session.command(&format!("MAIL FROM:<{}>", address(&self.config.from)), 250).await?;
+        session.command(&format!("RCPT TO:<{}>", address(&email.to)), 250).await?;
+        session.command("DATA", 354).await?;
+
+        // Dot-stuff lines starting with "." so they aren't taken for the end of the message
+        let message = email.to_message(&self.config.from).replace("\r\n.", "\r\n..");
+        session.write(&format!("{}.\r\n", message)).await?;
+        session.expect(250).await?;
+        session.command("QUIT", 221).await
+    }
+}
+
+// A single plain `local@domain` address, with nothing that could break out of a header or command
+pub fn is_valid_address(address: &str) -> bool {
+    let Some((local, domain)) = address.split_once('@') else {
+        return false;
+    };
+    address.len() <= 254
+        && !local.is_empty()
+        && domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+        && !address.chars().any(|c| c.is_whitespace() || c.is_control() || "<>()[],;:\\\"".contains(c))
+        && !domain.contains('@')
+}
+
+// Address part of `Name <addr>`
+fn address(mailbox: &str) -> &str {
+    match (mailbox.rfind('<'), mailbox.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &mailbox[start + 1..end],
+        _ => mailbox.trim(),
+    }
+}
+
+struct Session<S> {
+    stream: BufReader<S>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Session<S> {
+    fn new(stream: S) -> Self {
+        Self { stream: BufReader::new(stream) }
+    }
+
+    fn into_inner(self) -> S {
+        self.stream.into_inner()
+    }
+
+    async fn write(&mut self, data: &str) -> Result<(), EmailError> {
+        self.stream.get_mut().write_all(data.as_bytes()).await?;
+        self.stream.get_mut().flush().await?;
+        Ok(())
+    }
+
+    // Read a (possibly multi-line) reply and check its code
+    async fn expect(&mut self, code: u16) -> Result<(), EmailError> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.stream.read_line(&mut line).await? == 0 {
+                return Err("SMTP server closed the connection".into());
+            }
+            // Continuation lines are "250-..."; the last is "250 ..."
+            if line.as_bytes().get(3) != Some(&b'-') {
+                break;
+            }
+        }
+        match line.get(..3).and_then(|c| c.parse::<u16>().ok()) {
+            Some(reply) if reply == code => Ok(()),
+            _ => Err(format!("SMTP server replied: {}", line.trim_end()).into()),
+        }
+    }
+
+    async fn command(&mut self, command: &str, code: u16) -> Result<(), EmailError> {
+        self.write(&format!("{}\r\n", command)).await?;
+        self.expect(code).await
+    }
+
+    async fn ehlo(&mut self) -> Result<(), EmailError> {
+        self.command("EHLO nostr-delivery", 250).await
+    }
+}
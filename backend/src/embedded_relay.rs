@@ -0,0 +1,183 @@
+// embedded_relay.rs - Relay run inside the backend, persisted to an append-only event log, used as
+// the primary relay with the configured public relays as mirrors
+use async_trait::async_trait;
+use nostr_relay_builder::{LocalRelay, RelayBuilder};
+use nostr_sdk::database::{
+    Backend, DatabaseError, DatabaseEventStatus, Events, MemoryDatabase, MemoryDatabaseOptions, NostrDatabase,
+};
+use nostr_sdk::nips::nip01::Coordinate;
+use nostr_sdk::{Event, EventId, Filter, JsonUtil, Timestamp, Url};
+use std::collections::HashSet;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+pub const DEFAULT_ADDR: &str = "127.0.0.1:4869";
+
+#[derive(Debug, Clone)]
+pub struct EmbeddedRelayConfig {
+    pub path: PathBuf,
+    pub addr: SocketAddr,
+}
+
+impl EmbeddedRelayConfig {
+    // Enabled by EMBEDDED_RELAY_PATH; listens on EMBEDDED_RELAY_ADDR (default 127.0.0.1:4869)
+    pub fn from_env() -> Result<Option<Self>, String> {
+        let Some(path) = std::env::var("EMBEDDED_RELAY_PATH").ok().filter(|p| !p.is_empty()) else {
+            return Ok(None);
+        };
+        let addr = std::env::var("EMBEDDED_RELAY_ADDR").unwrap_or_else(|_| DEFAULT_ADDR.to_string());
+        let addr = addr.parse().map_err(|e| format!("Invalid EMBEDDED_RELAY_ADDR {}: {}", addr, e))?;
+        Ok(Some(Self { path: PathBuf::from(path), addr }))
+    }
+}
+
+// Events held in memory and appended to a JSON-lines file as they are stored. The file is
+// rewritten from memory on open, so superseded replaceable events don't pile up across restarts.
+#[derive(Debug)]
+pub struct FileDatabase {
+    inner: MemoryDatabase,
+    path: PathBuf,
+    log: Mutex<std::fs::File>,
+}
+
+impl FileDatabase {
+    pub async fn open(path: PathBuf) -> Result<Self, Box<dyn std::error::Error>> {
+        let inner = MemoryDatabase::with_opts(MemoryDatabaseOptions { events: true, max_events: None });
+
+        let mut skipped = 0;
+        if let Ok(contents) = std::fs::read_to_string(&path) {
+            for line in contents.lines().filter(|l| !l.trim().is_empty()) {
+                match Event::from_json(line) {
+                    Ok(event) => {
+                        inner.save_event(&event).await?;
+                    }
+                    Err(_) => skipped += 1,
+                }
+            }
+        }
+        if skipped > 0 {
+            log::warn!("Skipped {} unreadable lines in {}", skipped, path.display());
+        }
+
+        let log = rewrite(&path, &inner.query(vec![Filter::new()]).await?)?;
+        Ok(Self { inner, path, log: Mutex::new(log) })
+    }
+
+    pub async fn event_count(&self) -> Result<usize, DatabaseError> {
+        self.inner.count(vec![Filter::new()]).await
+    }
+
+    fn append(&self, event: &Event) -> Result<(), DatabaseError> {
+        let mut log = self.log.lock().unwrap_or_else(|e| e.into_inner());
+        writeln!(log, "{}", event.as_json()).map_err(DatabaseError::backend)
+    }
+
+    async fn compact(&self) -> Result<(), DatabaseError> {
+        let events = self.inner.query(vec![Filter::new()]).await?;
+        let file = rewrite(&self.path, &events).map_err(DatabaseError::backend)?;
+        *self.log.lock().unwrap_or_else(|e| e.into_inner()) = file;
+        Ok(())
+    }
+}
+
+// Replace the log with `events` and reopen it for appending
+fn rewrite(path: &PathBuf, events: &Events) -> std::io::Result<std::fs::File> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let tmp = path.with_extension("tmp");
+    {
+        let mut file = std::io::BufWriter::new(std::fs::File::create(&tmp)?);
+        for event in events.iter() {
+            writeln!(file, "{}", event.as_json())?;
+        }
+        file.flush()?;
+    }
+    std::fs::rename(&tmp, path)?;
+
+    std::fs::OpenOptions::new().append(true).open(path)
+}
+
+#[async_trait]
+impl NostrDatabase for FileDatabase {
+    fn backend(&self) -> Backend {
+        Backend::Custom("jsonl".to_string())
+    }
+
+    async fn save_event(&self, event: &Event) -> Result<bool, DatabaseError> {
+        let stored = self.inner.save_event(event).await?;
+        if stored {
+            self.append(event)?;
+        }
+        Ok(stored)
+    }
+
+    async fn check_id(&self, event_id: &EventId) -> Result<DatabaseEventStatus, DatabaseError> {
+        self.inner.check_id(event_id).await
+    }
+
+    async fn has_coordinate_been_deleted(&self, coordinate: &Coordinate, timestamp: &Timestamp) -> Result<bool, DatabaseError> {
+        self.inner.has_coordinate_been_deleted(coordinate, timestamp).await
+    }
+
+    async fn event_id_seen(&self, event_id: EventId, relay_url: Url) -> Result<(), DatabaseError> {
+        self.inner.event_id_seen(event_id, relay_url).await
+    }
+
+    async fn event_seen_on_relays(&self, event_id: &EventId) -> Result<Option<HashSet<Url>>, DatabaseError> {
+        self.inner.event_seen_on_relays(event_id).await
+    }
+
+    async fn event_by_id(&self, event_id: &EventId) -> Result<Option<Event>, DatabaseError> {
+        self.inner.event_by_id(event_id).await
+    }
+
+    async fn count(&self, filters: Vec<Filter>) -> Result<usize, DatabaseError> {
+        self.inner.count(filters).await
+    }
+
+    async fn query(&self, filters: Vec<Filter>) -> Result<Events, DatabaseError> {
+        self.inner.query(filters).await
+    }
+
+    async fn delete(&self, filter: Filter) -> Result<(), DatabaseError> {
+        self.inner.delete(filter).await?;
+        self.compact().await
+    }
+
+    async fn wipe(&self) -> Result<(), DatabaseError> {
+        self.inner.wipe().await?;
+        self.compact().await
+    }
+}
+
+pub struct EmbeddedRelay {
+    relay: LocalRelay,
+    pub database: Arc<FileDatabase>,
+}
+
+impl EmbeddedRelay {
+    pub async fn start(config: &EmbeddedRelayConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        let database = Arc::new(FileDatabase::open(config.path.clone()).await?);
+        let builder = RelayBuilder::default()
+            .addr(config.addr.ip())
+            .port(config.addr.port())
+            .database(database.clone());
+        let relay = LocalRelay::run(builder).await?;
+
+        Ok(Self { relay, database })
+    }
+
+    pub fn url(&self) -> String {
+        self.relay.url()
+    }
+}
+
+impl Drop for EmbeddedRelay {
+    fn drop(&mut self) {
+        self.relay.shutdown();
+    }
+}
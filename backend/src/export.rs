@@ -0,0 +1,156 @@
+// export.rs - Flattened delivery records for CSV/JSON exports
+use serde_json::{json, Value};
+
+use crate::{DeliveryRequest, DeliveryStatus};
+
+// Fields available in exports, in default column order
+pub const EXPORT_FIELDS: &[&str] = &[
+    "id",
+    "sender",
+    "status",
+    "created_at",
+    "expires_at",
+    "completed_at",
+    "pickup_address",
+    "pickup_lat",
+    "pickup_lng",
+    "dropoff_address",
+    "dropoff_lat",
+    "dropoff_lng",
+    "distance_meters",
+    "time_window",
+    "packages",
+    "offer_amount",
+    "insurance_amount",
+    "bid_count",
+    "bids",
+    "accepted_bid",
+    "courier",
+    "sender_rating",
+    "sender_feedback",
+    "proof_of_delivery",
+    "relisted_from",
+    "relisted_as",
+];
+
+// Resolve a comma-separated field list against EXPORT_FIELDS
+pub fn parse_fields(raw: Option<&str>) -> Result<Vec<&'static str>, String> {
+    let Some(raw) = raw.filter(|r| !r.trim().is_empty()) else {
+        return Ok(EXPORT_FIELDS.to_vec());
+    };
+
+    raw.split(',')
+        .map(str::trim)
+        .map(|name| {
+            EXPORT_FIELDS
+                .iter()
+                .copied()
+                .find(|f| *f == name)
+                .ok_or_else(|| format!("Unknown export field '{}'", name))
+        })
+        .collect()
+}
+
+// Parse a comma-separated status filter ("open,in_transit")
+pub fn parse_statuses(raw: Option<&str>) -> Result<Option<Vec<DeliveryStatus>>, String> {
+    let Some(raw) = raw.filter(|r| !r.trim().is_empty()) else {
+        return Ok(None);
+    };
+
+    raw.split(',')
+        .map(|s| {
+            let normalized = s.trim().to_lowercase().replace('_', "");
+            serde_json::from_value::<DeliveryStatus>(Value::String(normalized))
+                .map_err(|_| format!("Unknown status '{}'", s.trim()))
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map(Some)
+}
+
+pub fn field_value(delivery: &DeliveryRequest, field: &str) -> Value {
+    match field {
+        "id" => json!(delivery.id),
+        "sender" => json!(delivery.sender),
+        "status" => json!(delivery.status),
+        "created_at" => json!(delivery.created_at),
+        "expires_at" => json!(delivery.expires_at),
+        "completed_at" => json!(delivery.completed_at),
+        "pickup_address" => json!(delivery.pickup.address),
+        "pickup_lat" => json!(delivery.pickup.coordinates.as_ref().map(|c| c.lat)),
+        "pickup_lng" => json!(delivery.pickup.coordinates.as_ref().map(|c| c.lng)),
+        "dropoff_address" => json!(delivery.dropoff.address),
+        "dropoff_lat" => json!(delivery.dropoff.coordinates.as_ref().map(|c| c.lat)),
+        "dropoff_lng" => json!(delivery.dropoff.coordinates.as_ref().map(|c| c.lng)),
+        "distance_meters" => json!(delivery.distance_meters),
+        "time_window" => json!(delivery.time_window),
+        "packages" => json!(delivery.packages),
+        "offer_amount" => json!(delivery.offer_amount),
+        "insurance_amount" => json!(delivery.insurance_amount),
+        "bid_count" => json!(delivery.bids.len()),
+        "bids" => json!(delivery.bids),
+        "accepted_bid" => json!(delivery.accepted_bid),
+        "courier" => json!(delivery.accepted_courier()),
+        "sender_rating" => json!(delivery.sender_rating),
+        "sender_feedback" => json!(delivery.sender_feedback),
+        "proof_of_delivery" => json!(delivery.proof_of_delivery),
+        "relisted_from" => json!(delivery.relisted_from),
+        "relisted_as" => json!(delivery.relisted_as),
+        _ => Value::Null,
+    }
+}
+
+// JSON object with only the selected fields
+pub fn to_json_record(delivery: &DeliveryRequest, fields: &[&str]) -> Value {
+    Value::Object(
+        fields
+            .iter()
+            .map(|f| (f.to_string(), field_value(delivery, f)))
+            .collect(),
+    )
+}
+
+// CSV cell text: scalars as-is, nested values JSON-encoded
+fn csv_cell(value: Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s,
+        Value::Number(n) => n.to_string(),
+        Value::Bool(b) => b.to_string(),
+        other => other.to_string(),
+    }
+}
+
+// One CSV line (including the trailing newline)
+pub fn to_csv_line<I, S>(cells: I) -> String
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let mut writer = csv::WriterBuilder::new().from_writer(Vec::new());
+    let cells: Vec<String> = cells.into_iter().map(|c| c.as_ref().to_string()).collect();
+    let _ = writer.write_record(&cells);
+    String::from_utf8(writer.into_inner().unwrap_or_default()).unwrap_or_default()
+}
+
+pub fn to_csv_record(delivery: &DeliveryRequest, fields: &[&str]) -> String {
+    to_csv_line(fields.iter().map(|f| csv_cell(field_value(delivery, f))))
+}
+
+// Whole export in chunks, one record per chunk, so large exports can be streamed
+pub fn render(
+    deliveries: impl Iterator<Item = DeliveryRequest> + 'static,
+    fields: Vec<&'static str>,
+    as_csv: bool,
+) -> Box<dyn Iterator<Item = String>> {
+    if as_csv {
+        let header = to_csv_line(fields.iter());
+        let rows = deliveries.map(move |d| to_csv_record(&d, &fields));
+        Box::new(std::iter::once(header).chain(rows))
+    } else {
+        let rows = deliveries.enumerate().map(move |(i, d)| {
+            let separator = if i == 0 { "" } else { "," };
+            format!("{}{}", separator, to_json_record(&d, &fields))
+        });
+        Box::new(std::iter::once("[".to_string()).chain(rows).chain(std::iter::once("]".to_string())))
+    }
+}
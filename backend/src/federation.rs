@@ -0,0 +1,118 @@
+// federation.rs - Read-only deliveries aggregated from other marketplaces
+use nostr::{Event, Filter, Kind, PublicKey, ToBech32};
+use serde_json::Value;
+
+use crate::timezone::LocalTimes;
+use crate::{DeliveryOrigin, DeliveryRequest, DeliveryStatus, Location};
+
+#[derive(Debug, Clone, Default)]
+pub struct FederationConfig {
+    // Marketplace system pubkeys whose deliveries are mirrored
+    pub marketplaces: Vec<PublicKey>,
+    // Delivery kinds published by those marketplaces
+    pub kinds: Vec<Kind>,
+}
+
+impl FederationConfig {
+    // FEDERATED_MARKETPLACES: comma-separated npub/hex pubkeys
+    // FEDERATED_KINDS: comma-separated delivery kinds (default 35000)
+    pub fn from_env() -> Result<Self, String> {
+        let marketplaces = std::env::var("FEDERATED_MARKETPLACES")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|s| PublicKey::parse(s).map_err(|e| format!("Invalid federated marketplace '{}': {}", s, e)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let kinds = std::env::var("FEDERATED_KINDS")
+            .unwrap_or_else(|_| "35000".to_string())
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|s| s.parse::<u16>().map(Kind::from).map_err(|_| format!("Invalid federated kind '{}'", s)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self { marketplaces, kinds })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.marketplaces.is_empty()
+    }
+
+    pub fn is_federated(&self, author: &PublicKey) -> bool {
+        self.marketplaces.contains(author)
+    }
+
+    pub fn filter(&self) -> Filter {
+        Filter::new()
+            .authors(self.marketplaces.clone())
+            .kinds(self.kinds.clone())
+    }
+}
+
+// Cache key for a federated delivery: one entry per author and d-tag
+pub fn cache_key(event: &Event) -> String {
+    format!("{}:{}", event.pubkey, tag_value(event, "d").unwrap_or_default())
+}
+
+fn tag_value(event: &Event, name: &str) -> Option<String> {
+    event.tags.iter().find_map(|tag| {
+        let values = tag.as_slice();
+        (values.len() >= 2 && values[0] == name).then(|| values[1].clone())
+    })
+}
+
+// Normalize a foreign delivery event into a DeliveryRequest, filling gaps from tags
+pub fn normalize_event(event: &Event) -> Option<DeliveryRequest> {
+    let mut content: Value = serde_json::from_str(&event.content).ok()?;
+    let object = content.as_object_mut()?;
+
+    let fallbacks = [
+        ("id", tag_value(event, "d").map(Value::String)),
+        ("sender", Some(Value::String(tag_value(event, "sender").unwrap_or_else(|| event.pubkey.to_hex())))),
+        ("status", tag_value(event, "status").map(Value::String)),
+        ("offer_amount", tag_value(event, "amount").and_then(|a| a.parse::<u64>().ok()).map(Value::from)),
+        ("created_at", Some(Value::from(event.created_at.as_u64() as i64))),
+        ("time_window", Some(Value::String(String::new()))),
+        ("packages", Some(Value::Array(vec![]))),
+        ("bids", Some(Value::Array(vec![]))),
+    ];
+    for (field, fallback) in fallbacks {
+        if object.get(field).is_none_or(Value::is_null) {
+            if let Some(value) = fallback {
+                object.insert(field.to_string(), value);
+            }
+        }
+    }
+    for field in ["pickup", "dropoff"] {
+        if object.get(field).is_none_or(Value::is_null) {
+            object.insert(field.to_string(), serde_json::to_value(Location {
+                address: String::new(),
+                coordinates: None,
+                instructions: None,
+                contact: None,
+                what3words: None,
+                plus_code: None,
+            }).ok()?);
+        }
+    }
+    // Unknown or differently-cased statuses are normalized; anything unrecognized reads as open
+    let status = object
+        .get("status")
+        .and_then(Value::as_str)
+        .map(|s| s.to_lowercase().replace('_', ""))
+        .filter(|s| serde_json::from_value::<DeliveryStatus>(Value::String(s.clone())).is_ok())
+        .unwrap_or_else(|| "open".to_string());
+    object.insert("status".to_string(), Value::String(status));
+
+    let mut delivery: DeliveryRequest = serde_json::from_value(content).ok()?;
+    delivery.origin = Some(DeliveryOrigin {
+        marketplace: event.pubkey.to_bech32().unwrap_or_else(|_| event.pubkey.to_hex()),
+        event_id: event.id.to_hex(),
+        kind: event.kind.as_u16(),
+    });
+    delivery.local_times = Some(LocalTimes::for_delivery(&delivery));
+
+    Some(delivery)
+}
@@ -0,0 +1,42 @@
+// fieldsets.rs - Sparse fieldsets (`fields=id,status,pickup`) for listing endpoints
+use serde_json::{Map, Value};
+
+// Requested top-level fields, or None when the parameter is absent or empty
+pub fn parse(fields: Option<&str>) -> Option<Vec<String>> {
+    let fields: Vec<String> = fields?
+        .split(',')
+        .map(|f| f.trim().to_string())
+        .filter(|f| !f.is_empty())
+        .collect();
+    (!fields.is_empty()).then_some(fields)
+}
+
+// Keep only the requested keys of a JSON object; unknown names are ignored
+pub fn select(value: Value, fields: &[String]) -> Value {
+    match value {
+        Value::Object(mut object) => {
+            let mut selected = Map::new();
+            for field in fields {
+                if let Some(v) = object.remove(field) {
+                    selected.insert(field.clone(), v);
+                }
+            }
+            Value::Object(selected)
+        }
+        other => other,
+    }
+}
+
+// Serialize each item, trimmed to the requested fields when given
+pub fn project<T: serde::Serialize>(items: &[T], fields: Option<&[String]>) -> Vec<Value> {
+    items
+        .iter()
+        .map(|item| {
+            let value = serde_json::to_value(item).unwrap_or_default();
+            match fields {
+                Some(fields) => select(value, fields),
+                None => value,
+            }
+        })
+        .collect()
+}
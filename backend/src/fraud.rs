@@ -0,0 +1,238 @@
+// fraud.rs - Heuristics run on completions; flagged deliveries wait for admin review
+use nostr::hashes::{sha256, Hash};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::PubkeyId;
+use crate::tracking::{implied_speed, LocationPing};
+use crate::{calculate_distance, DeliveryRequest, GeoPoint};
+
+pub const DEFAULT_MAX_DROPOFF_DISTANCE_METERS: f64 = 500.0;
+// ~200 km/h
+pub const DEFAULT_MAX_COURIER_SPEED_MPS: f64 = 55.0;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FraudFlag {
+    // A proof image was already submitted for another delivery
+    DuplicateProofImage { other_delivery: String },
+    FarFromDropoff { distance_meters: f64 },
+    ImpossibleSpeed { speed_mps: f64, at: i64 },
+    // The sender accepted their own bid
+    SelfDealing,
+}
+
+pub fn image_hash(image: &str) -> String {
+    sha256::Hash::hash(image.as_bytes()).to_string()
+}
+
+#[derive(Debug, Clone)]
+pub struct FraudCheck {
+    pub max_dropoff_distance_meters: f64,
+    pub max_speed_mps: f64,
+}
+
+impl Default for FraudCheck {
+    fn default() -> Self {
+        Self {
+            max_dropoff_distance_meters: DEFAULT_MAX_DROPOFF_DISTANCE_METERS,
+            max_speed_mps: DEFAULT_MAX_COURIER_SPEED_MPS,
+        }
+    }
+}
+
+impl FraudCheck {
+    // Thresholds from FRAUD_MAX_DROPOFF_METERS and FRAUD_MAX_SPEED_MPS
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let env_f64 = |name: &str| std::env::var(name).ok().and_then(|v| v.parse::<f64>().ok());
+
+        Self {
+            max_dropoff_distance_meters: env_f64("FRAUD_MAX_DROPOFF_METERS").unwrap_or(defaults.max_dropoff_distance_meters),
+            max_speed_mps: env_f64("FRAUD_MAX_SPEED_MPS").unwrap_or(defaults.max_speed_mps),
+        }
+    }
+
+    // Check a just-completed delivery against its pings and the rest of the marketplace
+    pub fn check(&self, delivery: &DeliveryRequest, pings: &[LocationPing], others: &[DeliveryRequest]) -> Vec<FraudFlag> {
+        let mut flags = Vec::new();
+
+        if let Some(courier) = delivery.accepted_courier() {
+            if PubkeyId::normalize(courier) == PubkeyId::normalize(&delivery.sender) {
+                flags.push(FraudFlag::SelfDealing);
+            }
+        }
+
+        if let Some(proof) = &delivery.proof_of_delivery {
+            for image in &proof.images {
+                let hash = image_hash(image);
+                let duplicate = others.iter()
+                    .filter(|other| other.id != delivery.id)
+                    .find(|other| other.proof_of_delivery.as_ref()
+                        .is_some_and(|p| p.images.iter().any(|i| image_hash(i) == hash)));
+                if let Some(other) = duplicate {
+                    flags.push(FraudFlag::DuplicateProofImage { other_delivery: other.id.clone() });
+                    break;
+                }
+            }
+        }
+
+        // Where the courier was at handoff: the proof location, else the last ping
+        let handoff = delivery.proof_of_delivery.as_ref()
+            .and_then(|p| p.location.clone())
+            .or_else(|| pings.last().map(|p| GeoPoint { lat: p.lat, lng: p.lng }));
+        // Returned packages are handed back at the pickup
+        let destination = if delivery.returning { &delivery.pickup } else { &delivery.dropoff };
+        if let (Some(handoff), Some(dropoff)) = (handoff, &destination.coordinates) {
+            let distance = calculate_distance(&handoff, dropoff);
+            if distance > self.max_dropoff_distance_meters {
+                flags.push(FraudFlag::FarFromDropoff { distance_meters: distance.round() });
+            }
+        }
+
+        if let Some(flag) = self.speed_violation(pings) {
+            flags.push(flag);
+        }
+
+        flags
+    }
+
+    // First jump between consecutive pings faster than the courier could travel
+    fn speed_violation(&self, pings: &[LocationPing]) -> Option<FraudFlag> {
+        pings.windows(2).find_map(|pair| {
+            let (_, speed) = implied_speed(&pair[0], &pair[1])?;
+            (speed > self.max_speed_mps).then(|| FraudFlag::ImpossibleSpeed {
+                speed_mps: (speed * 10.0).round() / 10.0,
+                at: pair[1].timestamp,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{BidBuilder, DeliveryBuilder};
+    use crate::ProofOfDelivery;
+
+    const SENDER: &str = "npub_sender";
+    const COURIER: &str = "npub_courier";
+    const NOW: i64 = 1_700_000_000;
+    const PICKUP: GeoPoint = GeoPoint { lat: 40.7580, lng: -73.9855 };
+    const DROPOFF: GeoPoint = GeoPoint { lat: 40.7488, lng: -73.9857 };
+
+    // Completed by COURIER with the proof taken `meters_north` of the dropoff
+    fn delivered(meters_north: f64, images: &[&str]) -> DeliveryRequest {
+        let mut delivery = DeliveryBuilder::new(SENDER)
+            .pickup("Pickup", PICKUP)
+            .dropoff("Dropoff", DROPOFF)
+            .accepted(BidBuilder::new(COURIER).build())
+            .build();
+        let handoff = GeoPoint { lat: DROPOFF.lat + meters_north / 111_195.0, lng: DROPOFF.lng };
+        delivery.proof_of_delivery = Some(
+            ProofOfDelivery::new(NOW)
+                .location(Some(handoff))
+                .images(images.iter().map(|i| i.to_string()).collect()),
+        );
+        delivery
+    }
+
+    fn ping(lat: f64, timestamp: i64) -> LocationPing {
+        LocationPing {
+            delivery_id: "delivery_test".to_string(),
+            courier: COURIER.to_string(),
+            lat,
+            lng: DROPOFF.lng,
+            accuracy: None,
+            timestamp,
+            anomalies: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn clean_delivery_raises_no_flags() {
+        assert!(FraudCheck::default().check(&delivered(0.0, &["proof_a"]), &[], &[]).is_empty());
+    }
+
+    #[test]
+    fn sender_accepting_their_own_bid_is_self_dealing() {
+        let mut delivery = delivered(0.0, &[]);
+        delivery.sender = COURIER.to_string();
+
+        assert_eq!(FraudCheck::default().check(&delivery, &[], &[]), vec![FraudFlag::SelfDealing]);
+    }
+
+    #[test]
+    fn handoff_beyond_the_limit_is_far_from_dropoff() {
+        let check = FraudCheck::default();
+
+        assert!(check.check(&delivered(490.0, &[]), &[], &[]).is_empty());
+        match check.check(&delivered(510.0, &[]), &[], &[]).as_slice() {
+            [FraudFlag::FarFromDropoff { distance_meters }] => assert_eq!(*distance_meters, 510.0),
+            flags => panic!("unexpected flags {:?}", flags),
+        }
+    }
+
+    #[test]
+    fn handoff_falls_back_to_the_last_ping() {
+        let mut delivery = delivered(0.0, &[]);
+        delivery.proof_of_delivery = Some(ProofOfDelivery::new(NOW));
+        let far = DROPOFF.lat + 0.01;
+
+        let flags = FraudCheck::default().check(&delivery, &[ping(far, NOW)], &[]);
+        assert!(matches!(flags.as_slice(), [FraudFlag::FarFromDropoff { .. }]));
+    }
+
+    #[test]
+    fn returned_packages_are_measured_against_the_pickup() {
+        let mut delivery = delivered(0.0, &[]);
+        delivery.returning = true;
+        assert!(matches!(FraudCheck::default().check(&delivery, &[], &[]).as_slice(), [FraudFlag::FarFromDropoff { .. }]));
+
+        delivery.proof_of_delivery = Some(ProofOfDelivery::new(NOW).location(Some(PICKUP)));
+        assert!(FraudCheck::default().check(&delivery, &[], &[]).is_empty());
+    }
+
+    #[test]
+    fn proof_image_reused_from_another_delivery_is_flagged() {
+        let delivery = delivered(0.0, &["proof_a", "proof_b"]);
+        let other = delivered(0.0, &["proof_b"]);
+        let unrelated = delivered(0.0, &["proof_c"]);
+
+        assert_eq!(
+            FraudCheck::default().check(&delivery, &[], &[delivery.clone(), unrelated.clone(), other.clone()]),
+            vec![FraudFlag::DuplicateProofImage { other_delivery: other.id }]
+        );
+        // The delivery's own earlier copy doesn't count
+        assert!(FraudCheck::default().check(&delivery, &[], &[delivery.clone(), unrelated]).is_empty());
+    }
+
+    #[test]
+    fn jumps_faster_than_the_limit_are_impossible_speed() {
+        let check = FraudCheck::default();
+        let delivery = delivered(0.0, &[]);
+        let start = DROPOFF.lat - 0.01;
+
+        // ~1.1 km in 10 s
+        let flags = check.check(&delivery, &[ping(start, NOW), ping(DROPOFF.lat, NOW + 10)], &[]);
+        match flags.as_slice() {
+            [FraudFlag::ImpossibleSpeed { speed_mps, at }] => {
+                assert!(*speed_mps > DEFAULT_MAX_COURIER_SPEED_MPS);
+                assert_eq!(*at, NOW + 10);
+            }
+            flags => panic!("unexpected flags {:?}", flags),
+        }
+
+        // The same jump over ten minutes is a bike ride
+        assert!(check.check(&delivery, &[ping(start, NOW), ping(DROPOFF.lat, NOW + 600)], &[]).is_empty());
+    }
+
+    #[test]
+    fn gps_jitter_is_not_speed() {
+        // ~55 m between pings a second apart would be 55 m/s, but it's below the noise floor
+        let jitter = DROPOFF.lat + 0.0005;
+        let pings = [ping(DROPOFF.lat, NOW), ping(jitter, NOW + 1), ping(DROPOFF.lat, NOW + 1)];
+
+        assert!(FraudCheck::default().check(&delivered(0.0, &[]), &pings, &[]).is_empty());
+    }
+}
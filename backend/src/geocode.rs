@@ -0,0 +1,74 @@
+// geocode.rs - Pluggable address geocoding
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use crate::GeoPoint;
+
+pub type GeocodeError = Box<dyn std::error::Error + Send + Sync>;
+
+#[async_trait]
+pub trait Geocoder: Send + Sync {
+    // Resolve a free-form address to coordinates; Ok(None) when nothing matched
+    async fn geocode(&self, address: &str) -> Result<Option<GeoPoint>, GeocodeError>;
+}
+
+// Geocoder backed by a Nominatim-compatible search API
+pub struct NominatimGeocoder {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+#[derive(Deserialize)]
+struct NominatimPlace {
+    lat: String,
+    lon: String,
+}
+
+impl NominatimGeocoder {
+    pub fn new(base_url: &str) -> Self {
+        let client = reqwest::Client::builder()
+            .user_agent(concat!("nostr-delivery-backend/", env!("CARGO_PKG_VERSION")))
+            .timeout(std::time::Duration::from_secs(10))
+            .build()
+            .unwrap_or_default();
+
+        Self {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl Geocoder for NominatimGeocoder {
+    async fn geocode(&self, address: &str) -> Result<Option<GeoPoint>, GeocodeError> {
+        let places: Vec<NominatimPlace> = self
+            .client
+            .get(format!("{}/search", self.base_url))
+            .query(&[("q", address), ("format", "json"), ("limit", "1")])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Ok(places.first().and_then(|place| {
+            Some(GeoPoint {
+                lat: place.lat.parse().ok()?,
+                lng: place.lon.parse().ok()?,
+            })
+        }))
+    }
+}
+
+// Geocoder configured via GEOCODER_URL (defaults to the public Nominatim instance, "off" disables)
+pub fn from_env() -> Option<std::sync::Arc<dyn Geocoder>> {
+    let url = std::env::var("GEOCODER_URL")
+        .unwrap_or_else(|_| "https://nominatim.openstreetmap.org".to_string());
+
+    if url.is_empty() || url == "off" {
+        None
+    } else {
+        Some(std::sync::Arc::new(NominatimGeocoder::new(&url)))
+    }
+}
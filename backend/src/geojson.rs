@@ -0,0 +1,40 @@
+// geojson.rs - GeoJSON views of the marketplace for map frontends
+use serde_json::{json, Value};
+
+use crate::DeliveryRequest;
+
+// Pickup point feature for a delivery (None when the pickup has no coordinates)
+pub fn delivery_feature(delivery: &DeliveryRequest) -> Option<Value> {
+    let pickup = delivery.pickup.coordinates.as_ref()?;
+
+    Some(json!({
+        "type": "Feature",
+        "id": delivery.id,
+        "geometry": {
+            "type": "Point",
+            "coordinates": [pickup.lng, pickup.lat],
+        },
+        "properties": {
+            "id": delivery.id,
+            "status": delivery.status,
+            "amount": delivery.offer_amount,
+            "package_count": delivery.packages.len(),
+            "package_sizes": delivery.packages.iter().map(|p| p.size.as_str()).collect::<Vec<_>>(),
+            "fragile": delivery.packages.iter().any(|p| p.fragile),
+            "pickup_address": delivery.pickup.address,
+            "dropoff": delivery.dropoff.coordinates.as_ref().map(|c| [c.lng, c.lat]),
+            "distance_meters": delivery.distance_meters,
+            "bid_count": delivery.bids.len(),
+            "time_window": delivery.time_window,
+            "expires_at": delivery.expires_at,
+        }
+    }))
+}
+
+pub fn feature_collection<'a>(deliveries: impl IntoIterator<Item = &'a DeliveryRequest>) -> Value {
+    let features: Vec<Value> = deliveries.into_iter().filter_map(delivery_feature).collect();
+    json!({
+        "type": "FeatureCollection",
+        "features": features,
+    })
+}
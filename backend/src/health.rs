@@ -0,0 +1,127 @@
+// health.rs - Readiness checks: relays connected, indexer current, payment provider reachable
+use serde::Serialize;
+use std::time::Duration;
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct DependencyCheck {
+    pub name: String,
+    pub ok: bool,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Readiness {
+    pub ready: bool,
+    pub checks: Vec<DependencyCheck>,
+}
+
+impl Readiness {
+    pub fn from_checks(checks: Vec<DependencyCheck>) -> Self {
+        Self {
+            ready: checks.iter().all(|c| c.ok),
+            checks,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RelayHealth {
+    pub connected: bool,
+    // Connects through a SOCKS5 proxy
+    pub proxied: bool,
+    // Average round trip, once enough requests have been timed
+    pub latency_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReadinessConfig {
+    pub min_relays: usize,
+    // Slowest acceptable average round trip for direct and proxied relays
+    pub max_relay_latency_ms: u64,
+    pub max_proxied_latency_ms: u64,
+    // Oldest acceptable indexer snapshot
+    pub max_index_age_secs: i64,
+    pub payment_health_url: Option<String>,
+    client: reqwest::Client,
+}
+
+impl ReadinessConfig {
+    // READY_MIN_RELAYS (default 1), READY_MAX_RELAY_LATENCY_MS (default 3000),
+    // READY_MAX_PROXIED_LATENCY_MS (default 15000), READY_MAX_INDEX_AGE_SECS (default three
+    // indexer intervals) and PAYMENT_HEALTH_URL
+    pub fn from_env(index_interval_secs: u64) -> Self {
+        let client = reqwest::Client::builder()
+            .user_agent(concat!("nostr-delivery-backend/", env!("CARGO_PKG_VERSION")))
+            .timeout(Duration::from_secs(3))
+            .build()
+            .unwrap_or_default();
+
+        Self {
+            min_relays: std::env::var("READY_MIN_RELAYS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(1),
+            max_relay_latency_ms: std::env::var("READY_MAX_RELAY_LATENCY_MS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(3000),
+            max_proxied_latency_ms: std::env::var("READY_MAX_PROXIED_LATENCY_MS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(15000),
+            max_index_age_secs: std::env::var("READY_MAX_INDEX_AGE_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(index_interval_secs as i64 * 3),
+            payment_health_url: std::env::var("PAYMENT_HEALTH_URL").ok().filter(|u| !u.is_empty()),
+            client,
+        }
+    }
+
+    // Connected relays count unless their latency is over the limit; proxied relays (Tor in
+    // particular) get the higher limit
+    pub fn relay_check(&self, relays: &[RelayHealth]) -> DependencyCheck {
+        let connected = relays.iter().filter(|r| r.connected).count();
+        let healthy = relays
+            .iter()
+            .filter(|r| r.connected)
+            .filter(|r| {
+                let limit = if r.proxied { self.max_proxied_latency_ms } else { self.max_relay_latency_ms };
+                r.latency_ms.is_none_or(|ms| ms <= limit)
+            })
+            .count();
+
+        let mut detail = format!("{} of {} connected (need {})", healthy, relays.len(), self.min_relays);
+        if healthy < connected {
+            detail.push_str(&format!(", {} too slow", connected - healthy));
+        }
+        let proxied = relays.iter().filter(|r| r.proxied).count();
+        if proxied > 0 {
+            detail.push_str(&format!(", {} via proxy", proxied));
+        }
+
+        DependencyCheck { name: "relays".to_string(), ok: healthy >= self.min_relays, detail }
+    }
+
+    pub fn index_check(&self, indexed_at: i64, now: i64) -> DependencyCheck {
+        let (ok, detail) = if indexed_at == 0 {
+            (false, "no indexer pass has completed".to_string())
+        } else {
+            let age = now - indexed_at;
+            (age <= self.max_index_age_secs, format!("last pass {}s ago (max {}s)", age, self.max_index_age_secs))
+        };
+        DependencyCheck { name: "indexer".to_string(), ok, detail }
+    }
+
+    // Skipped when no payment provider is configured
+    pub async fn payment_check(&self) -> Option<DependencyCheck> {
+        let url = self.payment_health_url.as_ref()?;
+        let (ok, detail) = match self.client.get(url).send().await {
+            Ok(response) if response.status().is_success() => (true, format!("HTTP {}", response.status().as_u16())),
+            Ok(response) => (false, format!("HTTP {}", response.status().as_u16())),
+            Err(e) => (false, e.to_string()),
+        };
+        Some(DependencyCheck { name: "payment_provider".to_string(), ok, detail })
+    }
+}
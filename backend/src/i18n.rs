@@ -0,0 +1,185 @@
+// i18n.rs - Translated status names and notification texts (English, Spanish and German),
+// chosen from the user's settings or the request's Accept-Language
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::DeliveryStatus;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    #[default]
+    En,
+    Es,
+    De,
+}
+
+impl Language {
+    pub const ALL: [Language; 3] = [Language::En, Language::Es, Language::De];
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Self::En => "en",
+            Self::Es => "es",
+            Self::De => "de",
+        }
+    }
+
+    // A BCP 47 tag such as "de" or "es-MX"; regional variants use the base language
+    pub fn parse(tag: &str) -> Option<Self> {
+        let primary = tag.trim().split(['-', '_']).next()?.to_lowercase();
+        Self::ALL.into_iter().find(|language| language.code() == primary)
+    }
+
+    // Best supported language of an Accept-Language header, by quality
+    pub fn from_accept_language(header: &str) -> Option<Self> {
+        let mut ranges: Vec<(f32, Self)> = header
+            .split(',')
+            .filter_map(|range| {
+                let mut parts = range.split(';');
+                let language = Self::parse(parts.next()?)?;
+                let quality = parts
+                    .find_map(|p| p.trim().strip_prefix("q="))
+                    .map_or(Some(1.0), |q| q.trim().parse::<f32>().ok())?;
+                (quality > 0.0).then_some((quality, language))
+            })
+            .collect();
+        // Stable, so equal qualities keep the header's order
+        ranges.sort_by(|a, b| b.0.total_cmp(&a.0));
+        ranges.first().map(|(_, language)| *language)
+    }
+}
+
+pub fn status_label(status: &DeliveryStatus, language: Language) -> &'static str {
+    use DeliveryStatus::*;
+    use Language::*;
+    match (status, language) {
+        (Draft, En) => "Draft",
+        (Draft, Es) => "Borrador",
+        (Draft, De) => "Entwurf",
+        (Open, En) => "Open",
+        (Open, Es) => "Abierta",
+        (Open, De) => "Offen",
+        (Accepted, En) => "Accepted",
+        (Accepted, Es) => "Aceptada",
+        (Accepted, De) => "Angenommen",
+        (InTransit, En) => "In transit",
+        (InTransit, Es) => "En camino",
+        (InTransit, De) => "Unterwegs",
+        (Completed, En) => "Completed",
+        (Completed, Es) => "Entregada",
+        (Completed, De) => "Zugestellt",
+        (Confirmed, En) => "Confirmed",
+        (Confirmed, Es) => "Confirmada",
+        (Confirmed, De) => "Bestätigt",
+        (Disputed, En) => "Disputed",
+        (Disputed, Es) => "En disputa",
+        (Disputed, De) => "Beanstandet",
+        (Expired, En) => "Expired",
+        (Expired, Es) => "Caducada",
+        (Expired, De) => "Abgelaufen",
+        (Deleted, En) => "Deleted",
+        (Deleted, Es) => "Eliminada",
+        (Deleted, De) => "Gelöscht",
+    }
+}
+
+// Notification texts with `{placeholder}`s
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Message {
+    EmailBidAcceptedSubject,
+    EmailBidAcceptedBody,
+    EmailOutForDeliverySubject,
+    EmailOutForDeliveryBody,
+    EmailDeliveredSubject,
+    EmailDeliveredBody,
+    // `{signature}` and `{comments}` in the delivered email
+    SignedFor,
+    CourierNote,
+    // Proof photos given as URLs, listed in the delivered email
+    PhotoLink,
+    PrivateOfferTitle,
+    FavoriteOfferTitle,
+    OfferBody,
+    AssignedTitle,
+    AssignedBody,
+    StartReminderTitle,
+    StartReminderBody,
+    NoShowTitle,
+    NoShowBody,
+    RecipientEta,
+}
+
+impl Message {
+    pub fn text(self, language: Language) -> &'static str {
+        use Language::*;
+        use Message::*;
+        match (self, language) {
+            (EmailBidAcceptedSubject, En) => "Courier found for your delivery to {dropoff}",
+            (EmailBidAcceptedSubject, Es) => "Hemos encontrado un repartidor para tu envío a {dropoff}",
+            (EmailBidAcceptedSubject, De) => "Kurier für deine Lieferung nach {dropoff} gefunden",
+            (EmailBidAcceptedBody, En) => "A courier accepted your delivery from {pickup} to {dropoff} for {amount} sats.\n\nTrack it at {tracking_url}\n",
+            (EmailBidAcceptedBody, Es) => "Un repartidor aceptó tu envío de {pickup} a {dropoff} por {amount} sats.\n\nSíguelo en {tracking_url}\n",
+            (EmailBidAcceptedBody, De) => "Ein Kurier hat deine Lieferung von {pickup} nach {dropoff} für {amount} Sats angenommen.\n\nVerfolge sie unter {tracking_url}\n",
+            (EmailOutForDeliverySubject, En) => "Your package is on its way to {dropoff}",
+            (EmailOutForDeliverySubject, Es) => "Tu paquete va de camino a {dropoff}",
+            (EmailOutForDeliverySubject, De) => "Dein Paket ist unterwegs nach {dropoff}",
+            (EmailOutForDeliveryBody, En) => "The courier has picked up the package at {pickup} and is on the way to {dropoff}.\n\nTrack it at {tracking_url}\n",
+            (EmailOutForDeliveryBody, Es) => "El repartidor ha recogido el paquete en {pickup} y va de camino a {dropoff}.\n\nSíguelo en {tracking_url}\n",
+            (EmailOutForDeliveryBody, De) => "Der Kurier hat das Paket in {pickup} abgeholt und ist unterwegs nach {dropoff}.\n\nVerfolge es unter {tracking_url}\n",
+            (EmailDeliveredSubject, En) => "Delivered to {dropoff}",
+            (EmailDeliveredSubject, Es) => "Entregado en {dropoff}",
+            (EmailDeliveredSubject, De) => "Zugestellt in {dropoff}",
+            (EmailDeliveredBody, En) => "The package was delivered to {dropoff} at {completed_at}.\n{signature}{comments}\nProof of delivery: {tracking_url}\n",
+            (EmailDeliveredBody, Es) => "El paquete se entregó en {dropoff} el {completed_at}.\n{signature}{comments}\nComprobante de entrega: {tracking_url}\n",
+            (EmailDeliveredBody, De) => "Das Paket wurde am {completed_at} in {dropoff} zugestellt.\n{signature}{comments}\nZustellnachweis: {tracking_url}\n",
+            (SignedFor, En) => "Signed for by {name}.\n",
+            (SignedFor, Es) => "Firmado por {name}.\n",
+            (SignedFor, De) => "Unterschrieben von {name}.\n",
+            (CourierNote, En) => "Courier's note: {comments}\n",
+            (CourierNote, Es) => "Nota del repartidor: {comments}\n",
+            (CourierNote, De) => "Notiz des Kuriers: {comments}\n",
+            (PhotoLink, En) => "\nPhoto: {url}",
+            (PhotoLink, Es) | (PhotoLink, De) => "\nFoto: {url}",
+            (PrivateOfferTitle, En) => "You have been offered a private delivery",
+            (PrivateOfferTitle, Es) => "Te han ofrecido un envío privado",
+            (PrivateOfferTitle, De) => "Dir wurde eine private Lieferung angeboten",
+            (FavoriteOfferTitle, En) => "New delivery from a sender who favorited you",
+            (FavoriteOfferTitle, Es) => "Nuevo envío de un remitente que te tiene como favorito",
+            (FavoriteOfferTitle, De) => "Neue Lieferung von einem Absender, der dich favorisiert hat",
+            (OfferBody, En) => "{pickup} → {dropoff}, {amount} sats.",
+            (OfferBody, Es) => "{pickup} → {dropoff}, {amount} sats.",
+            (OfferBody, De) => "{pickup} → {dropoff}, {amount} Sats.",
+            (AssignedTitle, En) => "You have been assigned a delivery",
+            (AssignedTitle, Es) => "Se te ha asignado un envío",
+            (AssignedTitle, De) => "Dir wurde eine Lieferung zugewiesen",
+            (AssignedBody, En) => "{pickup} → {dropoff}, {amount} sats. Accept or decline it.",
+            (AssignedBody, Es) => "{pickup} → {dropoff}, {amount} sats. Acéptalo o recházalo.",
+            (AssignedBody, De) => "{pickup} → {dropoff}, {amount} Sats. Nimm sie an oder lehne sie ab.",
+            (StartReminderTitle, En) => "Your accepted delivery hasn't started",
+            (StartReminderTitle, Es) => "Tu envío aceptado no ha comenzado",
+            (StartReminderTitle, De) => "Deine angenommene Lieferung hat nicht begonnen",
+            (StartReminderBody, En) => "{pickup} → {dropoff}. Start it within {minutes} minutes or it will be offered to other couriers.",
+            (StartReminderBody, Es) => "{pickup} → {dropoff}. Inícialo en {minutes} minutos o se ofrecerá a otros repartidores.",
+            (StartReminderBody, De) => "{pickup} → {dropoff}. Starte sie innerhalb von {minutes} Minuten, sonst wird sie anderen Kurieren angeboten.",
+            (NoShowTitle, En) => "Delivery re-opened",
+            (NoShowTitle, Es) => "Envío reabierto",
+            (NoShowTitle, De) => "Lieferung wieder geöffnet",
+            (NoShowBody, En) => "{pickup} → {dropoff} was never started and is open to other couriers again. This counts as a no-show.",
+            (NoShowBody, Es) => "{pickup} → {dropoff} nunca se inició y vuelve a estar abierto a otros repartidores. Cuenta como una ausencia.",
+            (NoShowBody, De) => "{pickup} → {dropoff} wurde nie begonnen und ist wieder für andere Kuriere offen. Das zählt als Nichterscheinen.",
+            (RecipientEta, En) => "Your package is about {minutes} minutes away. Track it live: {tracking_url}",
+            (RecipientEta, Es) => "Tu paquete llegará en unos {minutes} minutos. Síguelo en directo: {tracking_url}",
+            (RecipientEta, De) => "Dein Paket ist etwa {minutes} Minuten entfernt. Live verfolgen: {tracking_url}",
+        }
+    }
+
+    pub fn render(self, language: Language, values: &[(&str, &str)]) -> String {
+        fill(self.text(language), values)
+    }
+}
+
+// Replace `{key}` placeholders
+pub fn fill(template: &str, values: &[(&str, &str)]) -> String {
+    values.iter().fold(template.to_string(), |text, (key, value)| text.replace(&format!("{{{}}}", key), value))
+}
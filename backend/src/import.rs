@@ -0,0 +1,165 @@
+// import.rs - Bulk delivery import from CSV
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{Amount, GeoPoint, Location, PackageInfo};
+
+// Maximum number of rows accepted in a single import
+pub const MAX_IMPORT_ROWS: usize = 500;
+
+// One CSV row; columns are matched by header name
+#[derive(Debug, Clone, Deserialize)]
+pub struct DeliveryCsvRow {
+    pub sender: Option<String>,
+    pub pickup_address: String,
+    pub pickup_lat: Option<f64>,
+    pub pickup_lng: Option<f64>,
+    pub pickup_instructions: Option<String>,
+    pub dropoff_address: String,
+    pub dropoff_lat: Option<f64>,
+    pub dropoff_lng: Option<f64>,
+    pub dropoff_instructions: Option<String>,
+    pub package_size: Option<String>,
+    pub package_description: String,
+    pub package_weight: Option<f32>,
+    pub fragile: Option<bool>,
+    pub requires_signature: Option<bool>,
+    pub offer_amount: Amount,
+    pub insurance_amount: Option<Amount>,
+    pub time_window: Option<String>,
+}
+
+// Validated row ready to become a delivery (coordinates may still need geocoding)
+#[derive(Debug, Clone)]
+pub struct ImportedDelivery {
+    pub sender: String,
+    pub pickup: Location,
+    pub dropoff: Location,
+    pub packages: Vec<PackageInfo>,
+    pub offer_amount: Amount,
+    pub insurance_amount: Option<Amount>,
+    pub time_window: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ImportRowResult {
+    pub row: usize,
+    pub delivery_id: Option<String>,
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct ImportReport {
+    pub total_rows: usize,
+    pub created: usize,
+    pub failed: usize,
+    pub rows: Vec<ImportRowResult>,
+}
+
+impl ImportReport {
+    pub fn push(&mut self, result: ImportRowResult) {
+        self.total_rows += 1;
+        if result.delivery_id.is_some() {
+            self.created += 1;
+        } else {
+            self.failed += 1;
+        }
+        self.rows.push(result);
+    }
+}
+
+fn coordinates(lat: Option<f64>, lng: Option<f64>) -> Result<Option<GeoPoint>, String> {
+    match (lat, lng) {
+        (Some(lat), Some(lng)) if (-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lng) => {
+            Ok(Some(GeoPoint { lat, lng }))
+        }
+        (Some(_), Some(_)) => Err("coordinates out of range".to_string()),
+        (None, None) => Ok(None),
+        _ => Err("both lat and lng must be provided".to_string()),
+    }
+}
+
+impl DeliveryCsvRow {
+    // Validate the row, falling back to `default_sender` when the row has none
+    pub fn validate(self, default_sender: Option<&str>) -> Result<ImportedDelivery, Vec<String>> {
+        let mut errors = Vec::new();
+
+        let sender = self
+            .sender
+            .filter(|s| !s.trim().is_empty())
+            .or_else(|| default_sender.map(str::to_string));
+        if sender.is_none() {
+            errors.push("sender is required".to_string());
+        }
+        if self.pickup_address.trim().is_empty() {
+            errors.push("pickup_address is required".to_string());
+        }
+        if self.dropoff_address.trim().is_empty() {
+            errors.push("dropoff_address is required".to_string());
+        }
+        if self.offer_amount.is_zero() {
+            errors.push("offer_amount must be greater than zero".to_string());
+        }
+
+        let pickup_coordinates = coordinates(self.pickup_lat, self.pickup_lng)
+            .map_err(|e| errors.push(format!("pickup {}", e)))
+            .unwrap_or(None);
+        let dropoff_coordinates = coordinates(self.dropoff_lat, self.dropoff_lng)
+            .map_err(|e| errors.push(format!("dropoff {}", e)))
+            .unwrap_or(None);
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        Ok(ImportedDelivery {
+            sender: sender.unwrap_or_default(),
+            pickup: Location {
+                address: self.pickup_address,
+                coordinates: pickup_coordinates,
+                instructions: self.pickup_instructions.filter(|s| !s.is_empty()),
+                contact: None,
+                what3words: None,
+                plus_code: None,
+            },
+            dropoff: Location {
+                address: self.dropoff_address,
+                coordinates: dropoff_coordinates,
+                instructions: self.dropoff_instructions.filter(|s| !s.is_empty()),
+                contact: None,
+                what3words: None,
+                plus_code: None,
+            },
+            packages: vec![PackageInfo {
+                size: self.package_size.filter(|s| !s.is_empty()).unwrap_or_else(|| "small".to_string()),
+                weight: self.package_weight,
+                description: self.package_description,
+                fragile: self.fragile.unwrap_or(false),
+                requires_signature: self.requires_signature.unwrap_or(false),
+            }],
+            offer_amount: self.offer_amount,
+            insurance_amount: self.insurance_amount,
+            time_window: self.time_window.unwrap_or_default(),
+        })
+    }
+}
+
+// 1-based data row number and the row's parse result
+pub type ParsedRow = (usize, Result<DeliveryCsvRow, String>);
+
+// Parse CSV text into rows
+pub fn parse_csv(data: &[u8]) -> Result<Vec<ParsedRow>, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(data);
+
+    let mut rows = Vec::new();
+    for (idx, record) in reader.deserialize::<DeliveryCsvRow>().enumerate() {
+        if idx >= MAX_IMPORT_ROWS {
+            return Err(format!("Import is limited to {} rows", MAX_IMPORT_ROWS));
+        }
+        rows.push((idx + 1, record.map_err(|e| e.to_string())));
+    }
+
+    Ok(rows)
+}
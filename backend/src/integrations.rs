@@ -0,0 +1,322 @@
+// integrations.rs - Store checkout webhooks: orders in a generic shape, or as sent by common store
+// platforms, mapped to deliveries
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::import::ImportedDelivery;
+use crate::{Amount, ContactDetails, GeoPoint, Location, PackageInfo};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum OrderSource {
+    Generic,
+    Shopify,
+    WooCommerce,
+}
+
+impl OrderSource {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "generic" => Some(Self::Generic),
+            "shopify" => Some(Self::Shopify),
+            "woocommerce" | "woo" => Some(Self::WooCommerce),
+            _ => None,
+        }
+    }
+
+    // Platforms identify their webhooks with their own headers
+    pub fn detect(header_names: impl IntoIterator<Item = String>) -> Self {
+        for name in header_names {
+            match name.to_lowercase().as_str() {
+                "x-shopify-topic" => return Self::Shopify,
+                "x-wc-webhook-source" => return Self::WooCommerce,
+                _ => {}
+            }
+        }
+        Self::Generic
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Generic => "generic",
+            Self::Shopify => "shopify",
+            Self::WooCommerce => "woocommerce",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct OrderItem {
+    pub name: String,
+    #[serde(default = "one")]
+    pub quantity: u32,
+    // Weight of one unit
+    pub weight_grams: Option<u32>,
+    #[serde(default)]
+    pub fragile: bool,
+}
+
+fn one() -> u32 {
+    1
+}
+
+// The generic order payload; platform payloads are converted to it
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct Order {
+    pub order_id: String,
+    pub sender: Option<String>,
+    // Defaults to the sender's saved default pickup
+    pub pickup: Option<Location>,
+    pub shipping_address: String,
+    pub shipping_coordinates: Option<GeoPoint>,
+    pub customer_name: Option<String>,
+    // Emailed when the package is picked up and delivered
+    pub customer_email: Option<String>,
+    // Texted when the courier is close
+    pub customer_phone: Option<String>,
+    pub note: Option<String>,
+    pub items: Vec<OrderItem>,
+    // Sats offered to couriers; defaults to the webhook's `offer_amount`
+    pub offer_amount: Option<Amount>,
+    pub time_window: Option<String>,
+    #[serde(default)]
+    pub requires_signature: bool,
+}
+
+// Shopify `orders/create` webhook (fields used)
+#[derive(Debug, Deserialize)]
+struct ShopifyOrder {
+    id: serde_json::Value,
+    email: Option<String>,
+    note: Option<String>,
+    shipping_address: Option<ShopifyAddress>,
+    #[serde(default)]
+    line_items: Vec<ShopifyLineItem>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ShopifyAddress {
+    name: Option<String>,
+    address1: Option<String>,
+    address2: Option<String>,
+    city: Option<String>,
+    province: Option<String>,
+    zip: Option<String>,
+    country: Option<String>,
+    phone: Option<String>,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ShopifyLineItem {
+    title: String,
+    #[serde(default = "one")]
+    quantity: u32,
+    grams: Option<u32>,
+}
+
+// WooCommerce `order.created` webhook (fields used)
+#[derive(Debug, Deserialize)]
+struct WooOrder {
+    id: serde_json::Value,
+    customer_note: Option<String>,
+    shipping: WooAddress,
+    billing: Option<WooBilling>,
+    #[serde(default)]
+    line_items: Vec<WooLineItem>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WooAddress {
+    first_name: Option<String>,
+    last_name: Option<String>,
+    address_1: Option<String>,
+    address_2: Option<String>,
+    city: Option<String>,
+    state: Option<String>,
+    postcode: Option<String>,
+    country: Option<String>,
+    phone: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WooBilling {
+    email: Option<String>,
+    phone: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WooLineItem {
+    name: String,
+    #[serde(default = "one")]
+    quantity: u32,
+}
+
+// Non-empty parts joined with ", "
+fn join_address(parts: &[&Option<String>]) -> String {
+    parts
+        .iter()
+        .filter_map(|p| p.as_deref().map(str::trim).filter(|p| !p.is_empty()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// IDs arrive as numbers or strings depending on the platform
+fn id_string(id: &serde_json::Value) -> String {
+    match id {
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+pub fn parse_order(source: OrderSource, body: &[u8]) -> Result<Order, String> {
+    let order = match source {
+        OrderSource::Generic => serde_json::from_slice(body).map_err(|e| format!("Invalid order: {}", e))?,
+        OrderSource::Shopify => {
+            let order: ShopifyOrder = serde_json::from_slice(body).map_err(|e| format!("Invalid Shopify order: {}", e))?;
+            let address = order.shipping_address.ok_or("Shopify order has no shipping address")?;
+            Order {
+                order_id: id_string(&order.id),
+                sender: None,
+                pickup: None,
+                shipping_address: join_address(&[&address.address1, &address.address2, &address.city, &address.province, &address.zip, &address.country]),
+                shipping_coordinates: address.latitude.zip(address.longitude).map(|(lat, lng)| GeoPoint { lat, lng }),
+                customer_name: non_empty(address.name),
+                customer_email: non_empty(order.email),
+                customer_phone: non_empty(address.phone),
+                note: non_empty(order.note),
+                items: order
+                    .line_items
+                    .into_iter()
+                    .map(|item| OrderItem { name: item.title, quantity: item.quantity, weight_grams: item.grams.filter(|g| *g > 0), fragile: false })
+                    .collect(),
+                offer_amount: None,
+                time_window: None,
+                requires_signature: false,
+            }
+        }
+        OrderSource::WooCommerce => {
+            let order: WooOrder = serde_json::from_slice(body).map_err(|e| format!("Invalid WooCommerce order: {}", e))?;
+            let address = order.shipping;
+            let billing = order.billing;
+            Order {
+                order_id: id_string(&order.id),
+                sender: None,
+                pickup: None,
+                shipping_address: join_address(&[&address.address_1, &address.address_2, &address.city, &address.state, &address.postcode, &address.country]),
+                shipping_coordinates: None,
+                customer_name: non_empty(Some(join_address(&[&address.first_name, &address.last_name]).replace(", ", " "))),
+                customer_email: non_empty(billing.as_ref().and_then(|b| b.email.clone())),
+                customer_phone: non_empty(address.phone).or_else(|| non_empty(billing.and_then(|b| b.phone))),
+                note: non_empty(order.customer_note),
+                items: order
+                    .line_items
+                    .into_iter()
+                    .map(|item| OrderItem { name: item.name, quantity: item.quantity, weight_grams: None, fragile: false })
+                    .collect(),
+                offer_amount: None,
+                time_window: None,
+                requires_signature: false,
+            }
+        }
+    };
+    Ok(order)
+}
+
+// Size class of a package by weight, as couriers see it
+fn size_for_weight(kg: Option<f32>) -> &'static str {
+    match kg {
+        Some(kg) if kg > 10.0 => "large",
+        Some(kg) if kg > 2.0 => "medium",
+        _ => "small",
+    }
+}
+
+// Webhook settings applied to orders that don't carry them
+#[derive(Debug, Clone, Default)]
+pub struct OrderDefaults {
+    pub sender: Option<String>,
+    pub pickup: Option<Location>,
+    pub offer_amount: Option<Amount>,
+}
+
+impl Order {
+    pub fn into_delivery(self, defaults: OrderDefaults) -> Result<ImportedDelivery, Vec<String>> {
+        let mut errors = Vec::new();
+
+        let sender = non_empty(self.sender).or(defaults.sender);
+        if sender.is_none() {
+            errors.push("sender is required".to_string());
+        }
+        let pickup = self.pickup.or(defaults.pickup);
+        if pickup.is_none() {
+            errors.push("pickup is required (in the order or as the sender's default pickup)".to_string());
+        }
+        if self.shipping_address.trim().is_empty() {
+            errors.push("shipping address is required".to_string());
+        }
+        if self.items.is_empty() {
+            errors.push("order has no items".to_string());
+        }
+        let offer_amount = self.offer_amount.or(defaults.offer_amount).unwrap_or_default();
+        if offer_amount.is_zero() {
+            errors.push("offer_amount must be greater than zero".to_string());
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        // The customer's name and phone go to the stop contact, the order note to the instructions
+        let contact = ContactDetails {
+            name: self.customer_name,
+            phone: self.customer_phone.filter(|phone| crate::notify::is_valid_phone(phone)),
+        };
+
+        let requires_signature = self.requires_signature;
+        let packages = self
+            .items
+            .into_iter()
+            .map(|item| {
+                let weight = item.weight_grams.map(|g| g as f32 * item.quantity as f32 / 1000.0);
+                PackageInfo {
+                    size: size_for_weight(weight).to_string(),
+                    weight,
+                    description: format!("{} × {}", item.quantity, item.name),
+                    fragile: item.fragile,
+                    requires_signature,
+                }
+            })
+            .collect();
+
+        Ok(ImportedDelivery {
+            sender: sender.unwrap_or_default(),
+            pickup: pickup.unwrap_or_else(|| Location { address: String::new(), coordinates: None, instructions: None, contact: None, what3words: None, plus_code: None }),
+            dropoff: Location {
+                address: self.shipping_address,
+                coordinates: self.shipping_coordinates,
+                instructions: self.note,
+                contact: Some(contact).filter(|c| !c.is_empty()),
+                what3words: None,
+                plus_code: None,
+            },
+            packages,
+            offer_amount,
+            insurance_amount: None,
+            time_window: self.time_window.unwrap_or_else(|| "asap".to_string()),
+        })
+    }
+}
+
+// Deliveries are keyed by organization, store and order, so a retried webhook finds the delivery
+// it created
+pub fn delivery_id(organization: &str, source: OrderSource, order_id: &str) -> String {
+    let sanitize = |value: &str| -> String {
+        value.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' }).collect()
+    };
+    format!("delivery_order_{}_{}_{}", sanitize(organization), source.as_str(), sanitize(order_id))
+}
@@ -0,0 +1,165 @@
+// jobs.rs - Periodic background work: registered jobs run on their own interval with jitter,
+// keep per-job metrics for the admin status and stop together when the server shuts down
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use tokio::sync::{watch, Mutex, RwLock};
+
+use crate::status::JobMetrics;
+
+pub type JobResult = Result<(), Box<dyn std::error::Error>>;
+
+// How long stop() waits for jobs that are mid-run
+const STOP_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub name: &'static str,
+    pub interval: Duration,
+    // Fraction of the interval added at random to each wait, so jobs don't fire in lockstep
+    pub jitter: f64,
+    // Run once at startup instead of after the first interval
+    pub immediate: bool,
+}
+
+impl Job {
+    pub fn every(name: &'static str, interval_secs: u64) -> Self {
+        Self { name, interval: Duration::from_secs(interval_secs), jitter: 0.0, immediate: false }
+    }
+
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn immediate(mut self) -> Self {
+        self.immediate = true;
+        self
+    }
+
+    fn next_wait(&self) -> Duration {
+        if self.jitter <= 0.0 {
+            return self.interval;
+        }
+        let fraction = nostr::secp256k1::rand::random::<f64>() * self.jitter;
+        self.interval + self.interval.mul_f64(fraction)
+    }
+
+    fn metrics(&self) -> JobMetrics {
+        JobMetrics {
+            name: self.name.to_string(),
+            interval_secs: self.interval.as_secs(),
+            runs: 0,
+            failures: 0,
+            running: false,
+            last_started_at: None,
+            last_duration_ms: None,
+            last_error: None,
+        }
+    }
+}
+
+pub struct Scheduler {
+    // Default jitter for jobs registered without their own
+    default_jitter: f64,
+    metrics: Arc<RwLock<Vec<JobMetrics>>>,
+    shutdown: watch::Sender<bool>,
+    handles: Mutex<Vec<tokio::task::JoinHandle<()>>>,
+}
+
+impl Scheduler {
+    // JOB_JITTER_PERCENT (default 10): random extra wait between runs, as a percent of each interval
+    pub fn from_env() -> Self {
+        let percent = std::env::var("JOB_JITTER_PERCENT")
+            .ok()
+            .and_then(|v| v.parse::<f64>().ok())
+            .filter(|p| p.is_finite() && *p >= 0.0)
+            .unwrap_or(10.0);
+        Self::new(percent / 100.0)
+    }
+
+    pub fn new(default_jitter: f64) -> Self {
+        Self {
+            default_jitter: default_jitter.clamp(0.0, 1.0),
+            metrics: Arc::new(RwLock::new(Vec::new())),
+            shutdown: watch::channel(false).0,
+            handles: Mutex::new(Vec::new()),
+        }
+    }
+
+    // Runs `run` every `job.interval` on the current (actix) runtime until stop(); a run
+    // in progress always finishes, and failures are logged and counted without ending the job
+    pub async fn register<F, Fut>(&self, mut job: Job, run: F)
+    where
+        F: Fn() -> Fut + 'static,
+        Fut: Future<Output = JobResult> + 'static,
+    {
+        if job.interval.is_zero() {
+            log::debug!("Job {} has no interval, not scheduled", job.name);
+            return;
+        }
+        if job.jitter == 0.0 {
+            job.jitter = self.default_jitter;
+        }
+        let index = {
+            let mut metrics = self.metrics.write().await;
+            metrics.push(job.metrics());
+            metrics.len() - 1
+        };
+        let metrics = self.metrics.clone();
+        let mut shutdown = self.shutdown.subscribe();
+
+        let handle = actix_rt::spawn(async move {
+            let mut wait = if job.immediate { Duration::ZERO } else { job.next_wait() };
+            loop {
+                tokio::select! {
+                    _ = tokio::time::sleep(wait) => {}
+                    _ = shutdown.changed() => break,
+                }
+                if *shutdown.borrow() {
+                    break;
+                }
+
+                let started = Instant::now();
+                {
+                    let mut metrics = metrics.write().await;
+                    metrics[index].running = true;
+                    metrics[index].last_started_at = Some(Utc::now().timestamp());
+                }
+                let result = run().await;
+                let mut metrics = metrics.write().await;
+                let entry = &mut metrics[index];
+                entry.running = false;
+                entry.runs += 1;
+                entry.last_duration_ms = Some(started.elapsed().as_millis() as u64);
+                entry.last_error = match result {
+                    Ok(()) => None,
+                    Err(e) => {
+                        log::warn!("Job {} failed: {}", job.name, e);
+                        entry.failures += 1;
+                        Some(e.to_string())
+                    }
+                };
+                drop(metrics);
+                wait = job.next_wait();
+            }
+            log::info!("Job {} stopped", job.name);
+        });
+        self.handles.lock().await.push(handle);
+    }
+
+    pub async fn metrics(&self) -> Vec<JobMetrics> {
+        self.metrics.read().await.clone()
+    }
+
+    // Stops every job, waiting for runs in progress to finish
+    pub async fn stop(&self) {
+        self.shutdown.send_replace(true);
+        let handles: Vec<_> = self.handles.lock().await.drain(..).collect();
+        if tokio::time::timeout(STOP_TIMEOUT, futures_util::future::join_all(handles)).await.is_err() {
+            log::warn!("Background jobs still running after {}s, abandoning them", STOP_TIMEOUT.as_secs());
+        }
+    }
+}
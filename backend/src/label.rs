@@ -0,0 +1,275 @@
+// label.rs - Printable 4x6" shipping labels as PDF, one page per package. Written by hand with
+// the standard Helvetica fonts, so no PDF library is needed.
+use qrcode::types::Color;
+use qrcode::QrCode;
+
+use crate::{ContactDetails, DeliveryRequest, PackageInfo};
+
+// Page size in points: 4x6 inches
+const PAGE_WIDTH: f32 = 288.0;
+const PAGE_HEIGHT: f32 = 432.0;
+const MARGIN: f32 = 18.0;
+const QR_SIZE: f32 = 112.0;
+
+// How much of a stop's contact the label prints
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContactRedaction {
+    // Name and phone
+    Full,
+    // Name only
+    Names,
+    // Neither; the address alone
+    Hidden,
+}
+
+impl ContactRedaction {
+    pub fn from_env() -> Self {
+        match std::env::var("LABEL_CONTACTS").as_deref() {
+            Ok("full") => Self::Full,
+            Ok("none") => Self::Hidden,
+            _ => Self::Names,
+        }
+    }
+
+    fn lines(&self, contact: Option<&ContactDetails>) -> Vec<String> {
+        let Some(contact) = contact else {
+            return Vec::new();
+        };
+        let mut lines = Vec::new();
+        if *self != Self::Hidden {
+            lines.extend(contact.name.clone().filter(|n| !n.trim().is_empty()));
+        }
+        if *self == Self::Full {
+            lines.extend(contact.phone.as_ref().filter(|p| !p.trim().is_empty()).map(|p| format!("Tel. {p}")));
+        }
+        lines
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ShippingLabel<'a> {
+    pub delivery: &'a DeliveryRequest,
+    // Unsealed stop contacts, printed as the redaction allows
+    pub pickup_contact: Option<&'a ContactDetails>,
+    pub dropoff_contact: Option<&'a ContactDetails>,
+    pub redaction: ContactRedaction,
+    pub tracking_url: String,
+    pub pickup_code: String,
+}
+
+impl ShippingLabel<'_> {
+    pub fn render(&self) -> Result<Vec<u8>, String> {
+        let qr = qr_modules(&self.tracking_url)?;
+        let count = self.delivery.packages.len().max(1);
+        let pages = (0..count)
+            .map(|i| self.page(self.delivery.packages.get(i), i + 1, count, &qr))
+            .collect::<Vec<_>>();
+        Ok(document(&pages))
+    }
+
+    fn page(&self, package: Option<&PackageInfo>, number: usize, count: usize, qr: &QrModules) -> String {
+        let mut page = Page::default();
+        let left = MARGIN;
+        let width = PAGE_WIDTH - 2.0 * MARGIN;
+        let mut y = PAGE_HEIGHT - MARGIN - 12.0;
+
+        page.text(left, y, 14.0, true, "NOSTR DELIVERY");
+        let counter = format!("{number} of {count}");
+        page.text(PAGE_WIDTH - MARGIN - text_width(&counter, 12.0), y, 12.0, true, &counter);
+        y -= 10.0;
+        page.rule(y);
+
+        y -= 14.0;
+        page.text(left, y, 7.0, true, "FROM");
+        y -= 10.0;
+        page.text(left, y, 7.0, false, &self.delivery.sender);
+        for line in self.redaction.lines(self.pickup_contact) {
+            y -= 10.0;
+            page.text(left, y, 9.0, false, &line);
+        }
+        for line in wrap(&self.delivery.pickup.address, 9.0, width).into_iter().take(2) {
+            y -= 11.0;
+            page.text(left, y, 9.0, false, &line);
+        }
+        y -= 8.0;
+        page.rule(y);
+
+        y -= 16.0;
+        page.text(left, y, 7.0, true, "TO");
+        for line in self.redaction.lines(self.dropoff_contact) {
+            y -= 15.0;
+            page.text(left, y, 13.0, true, &line);
+        }
+        for line in wrap(&self.delivery.dropoff.address, 13.0, width).into_iter().take(3) {
+            y -= 15.0;
+            page.text(left, y, 13.0, true, &line);
+        }
+        y -= 8.0;
+        page.rule(y);
+
+        if let Some(package) = package {
+            y -= 14.0;
+            page.text(left, y, 7.0, true, "PACKAGE");
+            y -= 12.0;
+            let weight = package.weight.map(|w| format!(", {w} kg")).unwrap_or_default();
+            page.text(left, y, 10.0, true, &format!("{}{weight}", package.size));
+            for line in wrap(&package.description, 9.0, width).into_iter().take(2) {
+                y -= 11.0;
+                page.text(left, y, 9.0, false, &line);
+            }
+            let handling: Vec<&str> = [(package.fragile, "FRAGILE"), (package.requires_signature, "SIGNATURE REQUIRED")]
+                .into_iter()
+                .filter_map(|(set, label)| set.then_some(label))
+                .collect();
+            if !handling.is_empty() {
+                y -= 14.0;
+                page.text(left, y, 11.0, true, &handling.join("  /  "));
+            }
+        }
+
+        // Tracking QR bottom left, pickup code beside it
+        let qr_top = MARGIN + 14.0 + QR_SIZE;
+        page.rule(qr_top + 8.0);
+        page.qr(left, MARGIN + 14.0, QR_SIZE, qr);
+        let code_left = left + QR_SIZE + 14.0;
+        page.text(code_left, qr_top - 12.0, 7.0, true, "PICKUP CODE");
+        page.text(code_left, qr_top - 38.0, 24.0, true, &self.pickup_code);
+        page.text(code_left, qr_top - 62.0, 7.0, true, "DELIVERY");
+        for (i, line) in wrap(&self.delivery.id, 7.0, PAGE_WIDTH - MARGIN - code_left).into_iter().take(3).enumerate() {
+            page.text(code_left, qr_top - 72.0 - 9.0 * i as f32, 7.0, false, &line);
+        }
+        page.text(left, MARGIN, 6.0, false, &self.tracking_url);
+
+        page.content
+    }
+}
+
+// Dark modules of a QR code, row by row from the top
+struct QrModules {
+    width: usize,
+    dark: Vec<bool>,
+}
+
+fn qr_modules(data: &str) -> Result<QrModules, String> {
+    let code = QrCode::new(data.as_bytes()).map_err(|e| e.to_string())?;
+    Ok(QrModules {
+        width: code.width(),
+        dark: code.to_colors().into_iter().map(|c| c == Color::Dark).collect(),
+    })
+}
+
+// A page's content stream
+#[derive(Default)]
+struct Page {
+    content: String,
+}
+
+impl Page {
+    fn text(&mut self, x: f32, y: f32, size: f32, bold: bool, text: &str) {
+        let font = if bold { "F2" } else { "F1" };
+        self.content.push_str(&format!("BT /{font} {size} Tf {x:.1} {y:.1} Td ({}) Tj ET\n", escape(text)));
+    }
+
+    fn rule(&mut self, y: f32) {
+        self.content.push_str(&format!("0.8 w {MARGIN:.1} {y:.1} m {:.1} {y:.1} l S\n", PAGE_WIDTH - MARGIN));
+    }
+
+    // The code with its four-module quiet zone fitted into a `size` square at (x, y)
+    fn qr(&mut self, x: f32, y: f32, size: f32, qr: &QrModules) {
+        let module = size / (qr.width + 8) as f32;
+        for (i, _) in qr.dark.iter().enumerate().filter(|(_, dark)| **dark) {
+            let (row, col) = (i / qr.width, i % qr.width);
+            let mx = x + (col + 4) as f32 * module;
+            let my = y + size - (row + 5) as f32 * module;
+            self.content.push_str(&format!("{mx:.2} {my:.2} {module:.2} {module:.2} re\n"));
+        }
+        self.content.push_str("f\n");
+    }
+}
+
+// Rough Helvetica width: good enough to wrap and right-align
+fn text_width(text: &str, size: f32) -> f32 {
+    text.chars().count() as f32 * size * 0.55
+}
+
+// Split text into lines that fit `width`, at spaces where possible
+fn wrap(text: &str, size: f32, width: f32) -> Vec<String> {
+    let max = ((width / (size * 0.55)) as usize).max(1);
+    let mut lines: Vec<String> = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let mut word = word.to_string();
+        while word.chars().count() > max {
+            if !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+            let rest = word.chars().skip(max).collect();
+            lines.push(word.chars().take(max).collect());
+            word = rest;
+        }
+        if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > max {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+// A PDF string literal body in WinAnsiEncoding; characters outside Latin-1 print as '?'
+fn escape(text: &str) -> String {
+    let mut out = String::new();
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                out.push('\\');
+                out.push(c);
+            }
+            ' '..='~' => out.push(c),
+            '\u{a0}'..='\u{ff}' => out.push_str(&format!("\\{:03o}", c as u32)),
+            _ => out.push('?'),
+        }
+    }
+    out
+}
+
+// A PDF with the given page content streams, on 4x6" pages
+fn document(pages: &[String]) -> Vec<u8> {
+    // 1: catalog, 2: page tree, 3-4: fonts, then a page and its content per page
+    let kids: Vec<String> = (0..pages.len()).map(|i| format!("{} 0 R", 5 + 2 * i)).collect();
+    let mut objects = vec![
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), pages.len()),
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>".to_string(),
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica-Bold /Encoding /WinAnsiEncoding >>".to_string(),
+    ];
+    for (i, content) in pages.iter().enumerate() {
+        objects.push(format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {PAGE_WIDTH} {PAGE_HEIGHT}] \
+             /Resources << /Font << /F1 3 0 R /F2 4 0 R >> >> /Contents {} 0 R >>",
+            6 + 2 * i
+        ));
+        objects.push(format!("<< /Length {} >>\nstream\n{content}endstream", content.len()));
+    }
+
+    let mut pdf = b"%PDF-1.4\n".to_vec();
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.extend_from_slice(format!("{} 0 obj\n{object}\nendobj\n", i + 1).as_bytes());
+    }
+    let xref_at = pdf.len();
+    pdf.extend_from_slice(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes());
+    for offset in offsets {
+        pdf.extend_from_slice(format!("{offset:010} 00000 n \n").as_bytes());
+    }
+    pdf.extend_from_slice(
+        format!("trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref_at}\n%%EOF\n", objects.len() + 1).as_bytes(),
+    );
+    pdf
+}
@@ -1,144 +1,1120 @@
-// lib.rs - Shared types and utilities
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
-#[serde(rename_all = "lowercase")]
-pub enum DeliveryStatus {
-    Open,
-    Accepted,
-    InTransit,
-    Completed,
-    Confirmed,
-    Disputed,
-    Expired,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Location {
-    pub address: String,
-    pub coordinates: Option<GeoPoint>,
-    pub instructions: Option<String>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct GeoPoint {
-    pub lat: f64,
-    pub lng: f64,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct PackageInfo {
-    pub size: String,
-    pub weight: Option<f32>,
-    pub description: String,
-    pub fragile: bool,
-    pub requires_signature: bool,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ProofOfDelivery {
-    pub images: Vec<String>, // base64 encoded images or URLs
-    pub signature_name: Option<String>,
-    pub timestamp: i64,
-    pub location: Option<GeoPoint>,
-    pub comments: Option<String>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct DeliveryRequest {
-    pub id: String,
-    pub sender: String,
-    pub pickup: Location,
-    pub dropoff: Location,
-    pub packages: Vec<PackageInfo>,
-    pub offer_amount: u64,
-    pub insurance_amount: Option<u64>,
-    pub time_window: String,
-    pub expires_at: Option<i64>,
-    pub status: DeliveryStatus,
-    pub bids: Vec<DeliveryBid>,
-    pub accepted_bid: Option<String>,
-    pub created_at: i64,
-    pub distance_meters: Option<f64>,
-    pub proof_of_delivery: Option<ProofOfDelivery>,
-    pub sender_feedback: Option<String>,
-    pub sender_rating: Option<f32>,
-    pub completed_at: Option<i64>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct DeliveryBid {
-    pub id: String,
-    pub courier: String,
-    pub amount: u64,
-    pub estimated_time: String,
-    pub reputation: f32,
-    pub completed_deliveries: u32,
-    pub message: Option<String>,
-    pub created_at: i64,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct UserProfile {
-    pub npub: String,
-    pub display_name: Option<String>,
-    pub reputation: f32,
-    pub completed_deliveries: u32,
-    pub total_earnings: u64,
-    pub verified_identity: bool,
-    pub lightning_address: Option<String>,
-}
-
-impl Default for UserProfile {
-    fn default() -> Self {
-        Self {
-            npub: String::new(),
-            display_name: None,
-            reputation: 0.0,
-            completed_deliveries: 0,
-            total_earnings: 0,
-            verified_identity: false,
-            lightning_address: None,
-        }
-    }
-}
-
-// Geographic distance calculation
-pub fn calculate_distance(p1: &GeoPoint, p2: &GeoPoint) -> f64 {
-    let r = 6371000.0; // Earth radius in meters
-    let lat1 = p1.lat.to_radians();
-    let lat2 = p2.lat.to_radians();
-    let delta_lat = (p2.lat - p1.lat).to_radians();
-    let delta_lng = (p2.lng - p1.lng).to_radians();
-
-    let a = (delta_lat / 2.0).sin().powi(2)
-        + lat1.cos() * lat2.cos() * (delta_lng / 2.0).sin().powi(2);
-    let c = 2.0 * a.sqrt().atan2((1.0 - a).sqrt());
-
-    r * c
-}
-
-// Reputation calculation
-pub fn calculate_new_reputation(old_rep: f32, rating: f32) -> f32 {
-    // Asymptotic approach to perfect rating
-    let decay = 0.9;
-    let target = 5.0;
-    target - (target - old_rep) * decay + (rating - old_rep) * (1.0 - decay)
-}
-
-// Delivery Update structure for status changes
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct DeliveryUpdate {
-    pub status: DeliveryStatus,
-    pub timestamp: i64,
-    pub proof_of_delivery: Option<ProofOfDelivery>,
-    pub completed_at: Option<i64>,
-    pub accepted_bid: Option<String>,
-    pub sender_rating: Option<f32>,
-    pub sender_feedback: Option<String>,
-}
-
-// In-memory storage (deprecated - using Nostr)
-pub type Storage = HashMap<String, DeliveryRequest>;
-pub type UserStorage = HashMap<String, UserProfile>;
+// lib.rs - Shared types and utilities
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use std::collections::HashMap;
+
+#[cfg(feature = "server")]
+pub mod admin;
+pub mod analytics;
+pub mod api_keys;
+pub mod auth;
+#[cfg(feature = "server")]
+pub mod bunker;
+#[cfg(feature = "server")]
+pub mod cache;
+pub mod classifieds;
+pub mod cli;
+#[cfg(feature = "client")]
+pub mod client;
+pub mod codes;
+pub mod commitments;
+pub mod contacts;
+#[cfg(feature = "server")]
+pub mod compaction;
+pub mod courier_bids;
+pub mod courier_feed;
+pub mod courier_jobs;
+pub mod courier_lists;
+pub mod delegation;
+pub mod delivery_attempts;
+#[cfg(feature = "server")]
+pub mod email;
+#[cfg(feature = "server")]
+pub mod embedded_relay;
+#[cfg(feature = "server")]
+pub mod export;
+pub mod federation;
+pub mod fieldsets;
+pub mod fraud;
+#[cfg(feature = "server")]
+pub mod geocode;
+pub mod geojson;
+#[cfg(feature = "server")]
+pub mod health;
+pub mod i18n;
+#[cfg(feature = "server")]
+pub mod import;
+#[cfg(feature = "server")]
+pub mod integrations;
+#[cfg(feature = "server")]
+pub mod jobs;
+#[cfg(feature = "server")]
+pub mod label;
+#[cfg(feature = "server")]
+pub mod location_codes;
+#[cfg(feature = "server")]
+pub mod moderation;
+pub mod native_bids;
+pub mod native_deliveries;
+pub mod nip89;
+#[cfg(feature = "server")]
+pub mod notify;
+pub mod organizations;
+pub mod outbox;
+#[cfg(feature = "server")]
+pub mod postgres;
+pub mod problem;
+pub mod projection;
+pub mod protocol;
+pub mod proxy;
+pub mod publish_log;
+#[cfg(feature = "server")]
+pub mod quotes;
+#[cfg(feature = "server")]
+pub mod push;
+#[cfg(feature = "server")]
+pub mod qr;
+pub mod query;
+pub mod rbac;
+pub mod redundancy;
+pub mod relist;
+#[cfg(feature = "server")]
+pub mod request_id;
+pub mod revisions;
+pub mod rotation;
+pub mod sender_dashboard;
+#[cfg(feature = "server")]
+pub mod service;
+pub mod service_area;
+pub mod settings;
+pub mod snapshot;
+pub mod start_timeout;
+#[cfg(feature = "server")]
+pub mod state;
+pub mod status;
+#[cfg(feature = "server")]
+pub mod stuck;
+pub mod sync;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod timeline;
+pub mod timezone;
+#[cfg(feature = "server")]
+pub mod tls;
+pub mod tracking;
+pub mod units;
+#[cfg(feature = "server")]
+pub mod uploads;
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryStatus {
+    // Prepared but not yet offered to couriers; published encrypted like private deliveries
+    Draft,
+    Open,
+    Accepted,
+    InTransit,
+    Completed,
+    Confirmed,
+    Disputed,
+    Expired,
+    // Soft-deleted; restorable until the grace period runs out
+    Deleted,
+}
+
+pub const TOMBSTONE_KIND: u16 = 35012;
+pub const DEFAULT_DELETE_GRACE_SECS: i64 = 7 * 86400;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct Location {
+    // Can be left empty when the stop is given by a what3words address or plus code
+    #[serde(default)]
+    pub address: String,
+    pub coordinates: Option<GeoPoint>,
+    // what3words address ("filled.count.soap"), resolved to coordinates when none are given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub what3words: Option<String>,
+    // Open Location Code, full ("849VCWC8+R9") or short with a locality ("CWC8+R9 Mountain View")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plus_code: Option<String>,
+    // Gate codes, door notes, ...: sealed on publish like `contact` and revealed only in transit
+    pub instructions: Option<String>,
+    // Accepted on create and edit, then sealed: published deliveries never carry it in the clear
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contact: Option<ContactDetails>,
+}
+
+// Who to ask for at a stop
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ContactDetails {
+    pub name: Option<String>,
+    pub phone: Option<String>,
+}
+
+impl ContactDetails {
+    pub fn is_empty(&self) -> bool {
+        self.name.as_deref().is_none_or(|n| n.trim().is_empty()) && self.phone.as_deref().is_none_or(|p| p.trim().is_empty())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct GeoPoint {
+    pub lat: f64,
+    pub lng: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PackageInfo {
+    pub size: String,
+    pub weight: Option<f32>,
+    pub description: String,
+    pub fragile: bool,
+    pub requires_signature: bool,
+}
+
+// Photo of the packages taken by the sender before pickup, also "before" evidence in disputes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct PackagePhoto {
+    // Hex sha256 of the image, as returned by the upload endpoint
+    pub sha256: String,
+    pub url: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ProofOfDelivery {
+    pub images: Vec<String>, // base64 encoded images or URLs
+    pub signature_name: Option<String>,
+    pub timestamp: i64,
+    pub location: Option<GeoPoint>,
+    pub comments: Option<String>,
+}
+
+impl ProofOfDelivery {
+    pub fn new(timestamp: i64) -> Self {
+        Self { images: Vec::new(), signature_name: None, timestamp, location: None, comments: None }
+    }
+
+    pub fn images(mut self, images: Vec<String>) -> Self {
+        self.images = images;
+        self
+    }
+
+    pub fn signature_name(mut self, name: Option<String>) -> Self {
+        self.signature_name = name;
+        self
+    }
+
+    pub fn location(mut self, location: Option<GeoPoint>) -> Self {
+        self.location = location;
+        self
+    }
+
+    pub fn comments(mut self, comments: Option<String>) -> Self {
+        self.comments = comments;
+        self
+    }
+}
+
+// Receipt confirmed through the recipient's tracking link, no Nostr key needed
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct RecipientConfirmation {
+    pub confirmed_at: i64,
+    // 1-5
+    pub rating: Option<f32>,
+    pub feedback: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DeliveryRequest {
+    pub id: String,
+    pub sender: String,
+    pub pickup: Location,
+    pub dropoff: Location,
+    pub packages: Vec<PackageInfo>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub package_photos: Vec<PackagePhoto>,
+    pub offer_amount: Amount,
+    pub insurance_amount: Option<Amount>,
+    pub time_window: String,
+    // IANA zone of the sender; windows given in local time are resolved in it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+    pub expires_at: Option<i64>,
+    pub status: DeliveryStatus,
+    pub bids: Vec<DeliveryBid>,
+    pub accepted_bid: Option<String>,
+    // When the current bid was accepted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accepted_at: Option<i64>,
+    // The sender's offer before acceptance replaced offer_amount with the bid's amount
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub listed_amount: Option<Amount>,
+    pub created_at: i64,
+    pub distance_meters: Option<f64>,
+    pub proof_of_delivery: Option<ProofOfDelivery>,
+    pub sender_feedback: Option<String>,
+    // Overall score, the mean of sender_ratings when those were given
+    pub sender_rating: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sender_ratings: Option<RatingBreakdown>,
+    // The courier's rating of the sender
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub courier_ratings: Option<RatingBreakdown>,
+    // The recipient's confirmation of receipt, alongside the sender's
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recipient_confirmation: Option<RecipientConfirmation>,
+    pub completed_at: Option<i64>,
+    // When the courier set off with the package
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub picked_up_at: Option<i64>,
+    // When a fraud review sent the delivery to dispute
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disputed_at: Option<i64>,
+    // Bids are only accepted from couriers meeting these thresholds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_courier_reputation: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_completed_deliveries: Option<u32>,
+    // When non-empty, only these couriers may bid
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_couriers: Vec<String>,
+    // Private deliveries are encrypted on relays and offered to allowed couriers by DM
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub private: bool,
+    // Organization a dispatcher created this delivery on behalf of
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub organization: Option<String>,
+    // Pending dispatcher assignment awaiting the courier's acknowledgement
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub assignment: Option<organizations::Assignment>,
+    // Pickup or dropoff outside the operator's service area (when flagging rather than rejecting)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub out_of_service_area: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<i64>,
+    // Status the delivery is restored to (deleted drafts come back as drafts)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_from: Option<DeliveryStatus>,
+    // Re-list as a new attempt if the delivery fails
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub auto_relist: bool,
+    // The failed attempt this delivery re-lists, and the attempt that re-listed this one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relisted_from: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relisted_as: Option<String>,
+    // Dropoff attempts that failed, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failed_attempts: Vec<delivery_attempts::FailedAttempt>,
+    // The package is on its way back to the pickup after a failed attempt
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub returning: bool,
+    // Couriers who accepted and never started, reverting the delivery to Open
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub no_shows: Vec<start_timeout::NoShow>,
+    // Completion heuristics that tripped; confirmation waits for admin review while non-empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fraud_flags: Vec<fraud::FraudFlag>,
+    // Set on read-only deliveries mirrored from a federated marketplace
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<DeliveryOrigin>,
+    // Stop contacts NIP-44-encrypted to the marketplace key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sealed_contacts: Option<String>,
+    // The same contacts encrypted to the accepted courier while the delivery is under way
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contact_reveal: Option<contacts::Reveal>,
+    // Stop access instructions NIP-44-encrypted to the marketplace key, discarded after the handoff
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sealed_instructions: Option<String>,
+    // The same instructions encrypted to the courier while the delivery is in transit
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instructions_reveal: Option<contacts::Reveal>,
+    // The timestamps above as ISO-8601 in `timezone`; filled in on read and never published
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub local_times: Option<timezone::LocalTimes>,
+}
+
+impl DeliveryRequest {
+    // Courier of the accepted bid, if any
+    pub fn accepted_courier(&self) -> Option<&str> {
+        let accepted = self.accepted_bid.as_ref()?;
+        self.bids.iter().find(|b| &b.id == accepted).map(|b| b.courier.as_str())
+    }
+
+    // Why a courier with this profile may not bid, if they fall below the delivery's bar
+    pub fn courier_requirement_error(&self, profile: &UserProfile) -> Option<String> {
+        if let Some(min) = self.min_courier_reputation {
+            if profile.reputation < min {
+                return Some(format!("Courier reputation {:.1} is below the required {:.1}", profile.reputation, min));
+            }
+        }
+        if let Some(min) = self.min_completed_deliveries {
+            if profile.completed_deliveries < min {
+                return Some(format!("Courier has {} completed deliveries, {} required", profile.completed_deliveries, min));
+            }
+        }
+        None
+    }
+
+    pub fn allows_courier(&self, courier: &str) -> bool {
+        if self.allowed_couriers.is_empty() {
+            return true;
+        }
+        let courier = PubkeyId::normalize(courier);
+        self.allowed_couriers.iter().any(|c| PubkeyId::normalize(c) == courier)
+    }
+
+    // Private deliveries are only shown to their sender and the couriers they are offered to, and
+    // drafts to their sender. `viewer` is the signed-in caller, if any.
+    pub fn visible_to(&self, viewer: Option<&str>) -> bool {
+        let is_sender = viewer.is_some_and(|v| PubkeyId::same(v, &self.sender));
+        if self.status == DeliveryStatus::Draft {
+            return is_sender;
+        }
+        !self.private || is_sender || viewer.is_some_and(|v| self.allows_courier(v))
+    }
+
+    pub fn allowlist_error(&self) -> Option<&'static str> {
+        (self.private && self.allowed_couriers.is_empty())
+            .then_some("Private deliveries need at least one allowed courier")
+    }
+
+    // Indices into `bids`, best first. Stale bids and bids without an ETA go last.
+    pub fn ranked_bids(&self, order: BidOrder) -> Vec<usize> {
+        let mut ranked: Vec<usize> = (0..self.bids.len()).collect();
+        ranked.sort_by(|&a, &b| {
+            let (a, b) = (&self.bids[a], &self.bids[b]);
+            let by_eta = || a.dropoff_eta.unwrap_or(i64::MAX).cmp(&b.dropoff_eta.unwrap_or(i64::MAX));
+            let by_price = || a.amount.cmp(&b.amount);
+            a.stale.cmp(&b.stale).then_with(|| match order {
+                BidOrder::Eta => by_eta().then_with(by_price),
+                BidOrder::Price => by_price().then_with(by_eta),
+                BidOrder::Reputation => b.reputation.total_cmp(&a.reputation).then_with(by_price),
+            })
+        });
+        ranked
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, PartialEq)]
+pub struct DeliveryOrigin {
+    pub marketplace: String,
+    pub event_id: String,
+    pub kind: u16,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DeliveryBid {
+    pub id: String,
+    pub courier: String,
+    pub amount: Amount,
+    // When the courier expects to collect and hand over the package (Unix seconds); none on
+    // dispatcher assignments and bids placed before ETAs were structured
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pickup_eta: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dropoff_eta: Option<i64>,
+    pub reputation: f32,
+    pub completed_deliveries: u32,
+    // Courier's average over the last 90 days at bid time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recent_rating: Option<f32>,
+    // Courier's per-dimension averages at bid time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ratings: Option<RatingBreakdown>,
+    pub message: Option<String>,
+    pub created_at: i64,
+    // Placed before a material edit; the courier must re-confirm before it can be accepted
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub stale: bool,
+}
+
+// How bids are ranked for the sender
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum BidOrder {
+    // Earliest dropoff first, then cheapest
+    #[default]
+    Eta,
+    // Cheapest first, then earliest dropoff
+    Price,
+    // Best reputation first, then cheapest
+    Reputation,
+}
+
+// Structured rating, each dimension on a 1-5 scale
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct RatingBreakdown {
+    pub timeliness: f32,
+    pub care: f32,
+    pub communication: f32,
+}
+
+impl RatingBreakdown {
+    // Legacy single-score ratings apply to every dimension
+    pub fn uniform(rating: f32) -> Self {
+        Self {
+            timeliness: rating,
+            care: rating,
+            communication: rating,
+        }
+    }
+
+    pub fn overall(&self) -> f32 {
+        (self.timeliness + self.care + self.communication) / 3.0
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let dimensions = [
+            ("timeliness", self.timeliness),
+            ("care", self.care),
+            ("communication", self.communication),
+        ];
+        for (name, value) in dimensions {
+            if !(1.0..=5.0).contains(&value) {
+                return Err(format!("{} rating must be between 1 and 5", name));
+            }
+        }
+        Ok(())
+    }
+
+    // Fold a new rating into a running average over `count` previous ratings
+    pub fn accumulate(average: Option<RatingBreakdown>, count: u32, rating: RatingBreakdown) -> Self {
+        match average {
+            Some(avg) if count > 0 => {
+                let n = count as f32;
+                Self {
+                    timeliness: (avg.timeliness * n + rating.timeliness) / (n + 1.0),
+                    care: (avg.care * n + rating.care) / (n + 1.0),
+                    communication: (avg.communication * n + rating.communication) / (n + 1.0),
+                }
+            }
+            _ => rating,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct UserProfile {
+    pub npub: String,
+    pub display_name: Option<String>,
+    pub reputation: f32,
+    pub completed_deliveries: u32,
+    pub total_earnings: Amount,
+    pub verified_identity: bool,
+    pub lightning_address: Option<String>,
+    // Courier chose to appear on public leaderboards
+    #[serde(default)]
+    pub leaderboard_opt_in: bool,
+    // Plain average of every rating and of those from the last 90 days; reputation
+    // itself weights recent ratings more heavily
+    #[serde(default)]
+    pub lifetime_rating: Option<f32>,
+    #[serde(default)]
+    pub recent_rating: Option<f32>,
+    // Per-dimension averages of the ratings this user has received
+    #[serde(default)]
+    pub ratings: Option<RatingBreakdown>,
+    #[serde(default)]
+    pub rating_count: u32,
+    // Accepted deliveries the courier never started
+    #[serde(default)]
+    pub no_shows: u32,
+}
+
+impl UserProfile {
+    pub fn credit_earnings(&mut self, amount: Amount) -> Result<(), String> {
+        self.total_earnings = self.total_earnings.checked_add(amount).ok_or("Courier earnings overflow")?;
+        Ok(())
+    }
+
+    pub fn apply_rating_summary(&mut self, summary: &RatingSummary) {
+        if let Some(reputation) = summary.reputation {
+            self.reputation = reputation;
+        }
+        self.lifetime_rating = summary.lifetime_rating;
+        self.recent_rating = summary.recent_rating;
+    }
+}
+
+impl Default for UserProfile {
+    fn default() -> Self {
+        Self {
+            npub: String::new(),
+            display_name: None,
+            reputation: 0.0,
+            completed_deliveries: 0,
+            total_earnings: Amount::ZERO,
+            verified_identity: false,
+            lightning_address: None,
+            leaderboard_opt_in: false,
+            lifetime_rating: None,
+            recent_rating: None,
+            ratings: None,
+            rating_count: 0,
+            no_shows: 0,
+        }
+    }
+}
+
+// Structured delivery time window. Persisted in DeliveryRequest.time_window as an
+// ISO-8601 interval ("start/end", ".." for an open end) so v1 clients keep seeing a string.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema, PartialEq)]
+pub struct TimeWindow {
+    pub start: Option<i64>,
+    pub end: Option<i64>,
+    pub label: Option<String>,
+}
+
+impl TimeWindow {
+    // Parse a stored time window; free text that isn't an interval becomes the label
+    pub fn parse(raw: &str) -> Self {
+        let parse_bound = |part: &str| -> Result<Option<i64>, ()> {
+            if part == ".." || part.is_empty() {
+                return Ok(None);
+            }
+            chrono::DateTime::parse_from_rfc3339(part)
+                .map(|dt| Some(dt.timestamp()))
+                .map_err(|_| ())
+        };
+
+        if let Some((start, end)) = raw.trim().split_once('/') {
+            if let (Ok(start), Ok(end)) = (parse_bound(start), parse_bound(end)) {
+                if start.is_some() || end.is_some() {
+                    return TimeWindow { start, end, label: None };
+                }
+            }
+        }
+
+        TimeWindow {
+            start: None,
+            end: None,
+            label: Some(raw.to_string()),
+        }
+    }
+
+    // Encode for storage in DeliveryRequest.time_window
+    pub fn to_raw(&self) -> String {
+        let format_bound = |ts: Option<i64>| {
+            ts.and_then(|t| chrono::DateTime::from_timestamp(t, 0))
+                .map(|dt| dt.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+                .unwrap_or_else(|| "..".to_string())
+        };
+
+        if self.start.is_none() && self.end.is_none() {
+            return self.label.clone().unwrap_or_default();
+        }
+        format!("{}/{}", format_bound(self.start), format_bound(self.end))
+    }
+
+    pub fn is_valid(&self) -> bool {
+        match (self.start, self.end) {
+            (Some(start), Some(end)) => start <= end,
+            _ => true,
+        }
+    }
+}
+
+// Geographic bounding box (GeoJSON order: min_lng,min_lat,max_lng,max_lat)
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ToSchema, PartialEq)]
+pub struct BoundingBox {
+    pub min_lng: f64,
+    pub min_lat: f64,
+    pub max_lng: f64,
+    pub max_lat: f64,
+}
+
+impl BoundingBox {
+    // Parse "min_lng,min_lat,max_lng,max_lat"
+    pub fn parse(raw: &str) -> Result<Self, String> {
+        let parts: Vec<f64> = raw
+            .split(',')
+            .map(|p| p.trim().parse::<f64>())
+            .collect::<Result<_, _>>()
+            .map_err(|_| "bbox must be four numbers: min_lng,min_lat,max_lng,max_lat".to_string())?;
+
+        let [min_lng, min_lat, max_lng, max_lat] = parts[..] else {
+            return Err("bbox must be four numbers: min_lng,min_lat,max_lng,max_lat".to_string());
+        };
+        if min_lat > max_lat || !(-90.0..=90.0).contains(&min_lat) || !(-90.0..=90.0).contains(&max_lat) {
+            return Err("bbox latitudes are out of range".to_string());
+        }
+
+        Ok(BoundingBox { min_lng, min_lat, max_lng, max_lat })
+    }
+
+    // Boxes crossing the antimeridian have min_lng > max_lng
+    pub fn contains(&self, point: &GeoPoint) -> bool {
+        let lng_ok = if self.min_lng <= self.max_lng {
+            point.lng >= self.min_lng && point.lng <= self.max_lng
+        } else {
+            point.lng >= self.min_lng || point.lng <= self.max_lng
+        };
+        lng_ok && point.lat >= self.min_lat && point.lat <= self.max_lat
+    }
+}
+
+// Timestamp-based ID ("<prefix>_<millis>") that stays unique within the process,
+// even when several IDs are generated in the same millisecond (e.g. bulk imports)
+pub fn unique_timestamp_id(prefix: &str) -> String {
+    use std::sync::atomic::{AtomicI64, Ordering};
+    static LAST_MILLIS: AtomicI64 = AtomicI64::new(0);
+
+    let now = chrono::Utc::now().timestamp_millis();
+    let mut last = LAST_MILLIS.load(Ordering::Relaxed);
+    loop {
+        let next = now.max(last + 1);
+        match LAST_MILLIS.compare_exchange_weak(last, next, Ordering::Relaxed, Ordering::Relaxed) {
+            Ok(_) => return format!("{}_{}", prefix, next),
+            Err(current) => last = current,
+        }
+    }
+}
+
+// A user's public key, given as an npub or hex. Stored, tagged and shown as the npub; filters
+// and encryption use the key itself. Fields of stored events stay plain strings so events
+// written before keys were checked still load; compare those with `PubkeyId::normalize`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, ToSchema)]
+#[schema(value_type = String, example = "npub1...")]
+pub struct PubkeyId(nostr::PublicKey);
+
+impl PubkeyId {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        nostr::PublicKey::parse(value.trim())
+            .map(Self)
+            .map_err(|_| format!("'{}' is not an npub or hex public key", value.trim()))
+    }
+
+    pub fn public_key(&self) -> nostr::PublicKey {
+        self.0
+    }
+
+    pub fn npub(&self) -> String {
+        use nostr::nips::nip19::ToBech32;
+        self.0.to_bech32().unwrap_or_else(|_| self.0.to_hex())
+    }
+
+    pub fn hex(&self) -> String {
+        self.0.to_hex()
+    }
+
+    // Canonical npub form so hex and bech32 spellings of the same key compare equal; anything
+    // that isn't a key is kept as given
+    pub fn normalize(value: &str) -> String {
+        Self::parse(value).map(|id| id.npub()).unwrap_or_else(|_| value.trim().to_string())
+    }
+
+    // Hex form for `p` tags and filters, or the value as given when it isn't a key
+    pub fn normalize_hex(value: &str) -> String {
+        Self::parse(value).map(|id| id.hex()).unwrap_or_else(|_| value.trim().to_string())
+    }
+
+    pub fn same(a: &str, b: &str) -> bool {
+        Self::normalize(a) == Self::normalize(b)
+    }
+}
+
+impl From<nostr::PublicKey> for PubkeyId {
+    fn from(pubkey: nostr::PublicKey) -> Self {
+        Self(pubkey)
+    }
+}
+
+impl From<PubkeyId> for nostr::PublicKey {
+    fn from(id: PubkeyId) -> Self {
+        id.0
+    }
+}
+
+impl std::ops::Deref for PubkeyId {
+    type Target = nostr::PublicKey;
+
+    fn deref(&self) -> &nostr::PublicKey {
+        &self.0
+    }
+}
+
+impl std::str::FromStr for PubkeyId {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, String> {
+        Self::parse(value)
+    }
+}
+
+impl std::fmt::Display for PubkeyId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.npub())
+    }
+}
+
+impl Serialize for PubkeyId {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.npub())
+    }
+}
+
+impl<'de> Deserialize<'de> for PubkeyId {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        Self::parse(&value).map_err(serde::de::Error::custom)
+    }
+}
+
+// A bitcoin amount. Held in millisats so Lightning amounts fit exactly, but sent and stored as
+// whole sats. Arithmetic is checked; amounts above the 21M BTC supply are refused when parsed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord, ToSchema)]
+#[schema(value_type = u64, example = 1000)]
+pub struct Amount(u64);
+
+impl Amount {
+    pub const ZERO: Amount = Amount(0);
+    pub const MAX_SATS: u64 = 21_000_000 * 100_000_000;
+
+    pub fn from_sats(sats: u64) -> Option<Self> {
+        (sats <= Self::MAX_SATS).then(|| Amount(sats * 1000))
+    }
+
+    pub fn from_msats(msats: u64) -> Self {
+        Amount(msats)
+    }
+
+    // Whole sats, rounded down
+    pub fn sats(self) -> u64 {
+        self.0 / 1000
+    }
+
+    pub fn msats(self) -> u64 {
+        self.0
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn checked_add(self, other: Amount) -> Option<Amount> {
+        self.0.checked_add(other.0).map(Amount)
+    }
+
+    pub fn checked_sub(self, other: Amount) -> Option<Amount> {
+        self.0.checked_sub(other.0).map(Amount)
+    }
+
+    pub fn checked_mul(self, factor: u64) -> Option<Amount> {
+        self.0.checked_mul(factor).map(Amount)
+    }
+
+    // `percent` of the amount, rounded to whole sats; None when negative or out of range
+    pub fn percent(self, percent: f64) -> Option<Amount> {
+        let sats = (self.0 as f64 * percent / 100.0 / 1000.0).round();
+        (sats.is_finite() && sats >= 0.0 && sats <= Self::MAX_SATS as f64).then(|| Amount(sats as u64 * 1000))
+    }
+
+    // For running totals in reports, which clamp rather than fail
+    pub fn saturating_add(self, other: Amount) -> Amount {
+        Amount(self.0.saturating_add(other.0))
+    }
+}
+
+impl std::str::FromStr for Amount {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, String> {
+        let sats: u64 = value.trim().parse().map_err(|_| format!("'{}' is not a whole number of sats", value))?;
+        Self::from_sats(sats).ok_or_else(|| format!("{} sats is more than the bitcoin supply", sats))
+    }
+}
+
+impl std::fmt::Display for Amount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.sats())
+    }
+}
+
+impl Serialize for Amount {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(self.sats())
+    }
+}
+
+impl<'de> Deserialize<'de> for Amount {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let sats = u64::deserialize(deserializer)?;
+        Self::from_sats(sats).ok_or_else(|| serde::de::Error::custom(format!("{} sats is more than the bitcoin supply", sats)))
+    }
+}
+
+// Geographic distance calculation
+pub fn calculate_distance(p1: &GeoPoint, p2: &GeoPoint) -> f64 {
+    let r = 6371000.0; // Earth radius in meters
+    let lat1 = p1.lat.to_radians();
+    let lat2 = p2.lat.to_radians();
+    let delta_lat = (p2.lat - p1.lat).to_radians();
+    let delta_lng = (p2.lng - p1.lng).to_radians();
+
+    let a = (delta_lat / 2.0).sin().powi(2)
+        + lat1.cos() * lat2.cos() * (delta_lng / 2.0).sin().powi(2);
+    let c = 2.0 * a.sqrt().atan2((1.0 - a).sqrt());
+
+    r * c
+}
+
+// Geohash encoding (base32, interleaved lng/lat bits)
+pub fn geohash_encode(point: &GeoPoint, precision: usize) -> String {
+    const BASE32: &[u8] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+    let (mut lat_range, mut lng_range) = ((-90.0, 90.0), (-180.0, 180.0));
+    let mut hash = String::with_capacity(precision);
+    let mut bits = 0u8;
+    let mut bit_count = 0;
+    let mut even = true;
+
+    while hash.len() < precision {
+        let (range, value) = if even {
+            (&mut lng_range, point.lng)
+        } else {
+            (&mut lat_range, point.lat)
+        };
+        let mid = (range.0 + range.1) / 2.0;
+        bits <<= 1;
+        if value >= mid {
+            bits |= 1;
+            range.0 = mid;
+        } else {
+            range.1 = mid;
+        }
+        even = !even;
+        bit_count += 1;
+
+        if bit_count == 5 {
+            hash.push(BASE32[bits as usize] as char);
+            bits = 0;
+            bit_count = 0;
+        }
+    }
+
+    hash
+}
+
+// Reputation calculation
+pub fn calculate_new_reputation(old_rep: f32, rating: f32) -> f32 {
+    // Asymptotic approach to perfect rating
+    let decay = 0.9;
+    let target = 5.0;
+    target - (target - old_rep) * decay + (rating - old_rep) * (1.0 - decay)
+}
+
+// Ratings lose half their weight in the reputation score every half-life
+pub const REPUTATION_HALF_LIFE_DAYS: f64 = 180.0;
+pub const RECENT_RATING_WINDOW_SECS: i64 = 90 * 86400;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct RatingSummary {
+    // Recency-weighted average
+    pub reputation: Option<f32>,
+    pub lifetime_rating: Option<f32>,
+    pub recent_rating: Option<f32>,
+    pub ratings: u32,
+}
+
+impl RatingSummary {
+    // Summarize (rating, rated_at) pairs as of `now`
+    pub fn from_ratings(ratings: &[(f32, i64)], now: i64) -> Self {
+        if ratings.is_empty() {
+            return Self::default();
+        }
+
+        let half_life_secs = REPUTATION_HALF_LIFE_DAYS * 86400.0;
+        let (mut weighted, mut weights) = (0.0f64, 0.0f64);
+        let (mut recent_sum, mut recent_count) = (0.0f32, 0u32);
+        for &(rating, at) in ratings {
+            let age = (now - at).max(0) as f64;
+            let weight = 0.5f64.powf(age / half_life_secs);
+            weighted += rating as f64 * weight;
+            weights += weight;
+            if now - at <= RECENT_RATING_WINDOW_SECS {
+                recent_sum += rating;
+                recent_count += 1;
+            }
+        }
+
+        Self {
+            reputation: Some((weighted / weights) as f32),
+            lifetime_rating: Some(ratings.iter().map(|(r, _)| r).sum::<f32>() / ratings.len() as f32),
+            recent_rating: (recent_count > 0).then(|| recent_sum / recent_count as f32),
+            ratings: ratings.len() as u32,
+        }
+    }
+
+    // Ratings senders gave this courier across their completed deliveries
+    pub fn courier_ratings(npub: &str, deliveries: &[DeliveryRequest]) -> Vec<(f32, i64)> {
+        deliveries
+            .iter()
+            .filter(|d| d.accepted_courier() == Some(npub))
+            .filter_map(|d| Some((d.sender_rating?, d.completed_at.unwrap_or(d.created_at))))
+            .collect()
+    }
+}
+
+pub const ACCEPTED_KIND: u16 = 35002;
+// Published by earlier versions for a courier starting out; read back as open
+pub const STARTED_KIND: u16 = 35003;
+pub const IN_TRANSIT_KIND: u16 = 35004;
+pub const COMPLETED_KIND: u16 = 35005;
+pub const CONFIRMED_KIND: u16 = 35006;
+pub const STATUS_UPDATE_KINDS: [u16; 5] = [ACCEPTED_KIND, STARTED_KIND, IN_TRANSIT_KIND, COMPLETED_KIND, CONFIRMED_KIND];
+
+// Delivery Update structure for status changes. Each status kind carries only its own fields;
+// build one with the constructor for its status rather than filling the struct in.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DeliveryUpdate {
+    pub status: DeliveryStatus,
+    pub timestamp: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proof_of_delivery: Option<ProofOfDelivery>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completed_at: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accepted_bid: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sender_rating: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sender_ratings: Option<RatingBreakdown>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sender_feedback: Option<String>,
+}
+
+impl DeliveryUpdate {
+    // A bare transition, e.g. for events whose content couldn't be parsed
+    pub fn new(status: DeliveryStatus, timestamp: i64) -> Self {
+        Self {
+            status,
+            timestamp,
+            proof_of_delivery: None,
+            completed_at: None,
+            accepted_bid: None,
+            sender_rating: None,
+            sender_ratings: None,
+            sender_feedback: None,
+        }
+    }
+
+    pub fn accepted(bid_id: &str, timestamp: i64) -> Self {
+        Self { accepted_bid: Some(bid_id.to_string()), ..Self::new(DeliveryStatus::Accepted, timestamp) }
+    }
+
+    pub fn in_transit(timestamp: i64) -> Self {
+        Self::new(DeliveryStatus::InTransit, timestamp)
+    }
+
+    pub fn completed(proof: Option<ProofOfDelivery>, completed_at: i64, timestamp: i64) -> Self {
+        Self {
+            proof_of_delivery: proof,
+            completed_at: Some(completed_at),
+            ..Self::new(DeliveryStatus::Completed, timestamp)
+        }
+    }
+
+    pub fn confirmed(timestamp: i64) -> Self {
+        Self::new(DeliveryStatus::Confirmed, timestamp)
+    }
+
+    // Overall rating plus the optional per-category breakdown
+    pub fn rating(mut self, rating: Option<f32>, ratings: Option<RatingBreakdown>) -> Self {
+        self.sender_rating = rating;
+        self.sender_ratings = ratings;
+        self
+    }
+
+    pub fn feedback(mut self, feedback: Option<String>) -> Self {
+        self.sender_feedback = feedback;
+        self
+    }
+
+    // Event kind a status is published under; other statuses only change the delivery event
+    pub fn kind_for(status: &DeliveryStatus) -> Option<u16> {
+        match status {
+            DeliveryStatus::Accepted => Some(ACCEPTED_KIND),
+            DeliveryStatus::InTransit => Some(IN_TRANSIT_KIND),
+            DeliveryStatus::Completed => Some(COMPLETED_KIND),
+            DeliveryStatus::Confirmed => Some(CONFIRMED_KIND),
+            _ => None,
+        }
+    }
+
+    pub fn status_for_kind(kind: u16) -> Option<DeliveryStatus> {
+        match kind {
+            ACCEPTED_KIND => Some(DeliveryStatus::Accepted),
+            STARTED_KIND => Some(DeliveryStatus::Open),
+            IN_TRANSIT_KIND => Some(DeliveryStatus::InTransit),
+            COMPLETED_KIND => Some(DeliveryStatus::Completed),
+            CONFIRMED_KIND => Some(DeliveryStatus::Confirmed),
+            _ => None,
+        }
+    }
+
+    pub fn kind(&self) -> Option<u16> {
+        Self::kind_for(&self.status)
+    }
+}
+
+// In-memory storage (deprecated - using Nostr)
+pub type Storage = HashMap<String, DeliveryRequest>;
+pub type UserStorage = HashMap<String, UserProfile>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sats(value: u64) -> Amount {
+        Amount::from_sats(value).expect("within supply")
+    }
+
+    #[test]
+    fn amounts_above_the_supply_are_refused() {
+        assert_eq!(Amount::from_sats(Amount::MAX_SATS).map(Amount::sats), Some(Amount::MAX_SATS));
+        assert_eq!(Amount::from_sats(Amount::MAX_SATS + 1), None);
+        assert_eq!(Amount::from_sats(u64::MAX), None);
+    }
+
+    #[test]
+    fn checked_arithmetic_fails_instead_of_wrapping() {
+        assert_eq!(sats(1000).checked_add(sats(500)), Some(sats(1500)));
+        assert_eq!(Amount::from_msats(u64::MAX).checked_add(Amount::from_msats(1)), None);
+
+        assert_eq!(sats(1000).checked_sub(sats(1000)), Some(Amount::ZERO));
+        assert_eq!(sats(1000).checked_sub(sats(1001)), None);
+
+        assert_eq!(sats(1000).checked_mul(3), Some(sats(3000)));
+        assert_eq!(sats(Amount::MAX_SATS).checked_mul(1000), None);
+
+        assert_eq!(Amount::from_msats(u64::MAX).saturating_add(sats(1)), Amount::from_msats(u64::MAX));
+    }
+
+    #[test]
+    fn percent_rounds_to_whole_sats_and_refuses_bad_rates() {
+        assert_eq!(sats(1000).percent(10.0), Some(sats(100)));
+        assert_eq!(sats(1001).percent(2.5), Some(sats(25)));
+        assert_eq!(sats(1000).percent(0.0), Some(Amount::ZERO));
+        assert_eq!(sats(1000).percent(-1.0), None);
+        assert_eq!(sats(1000).percent(f64::NAN), None);
+        assert_eq!(sats(Amount::MAX_SATS).percent(200.0), None);
+    }
+
+    #[test]
+    fn parses_whole_sats_within_the_supply() {
+        assert_eq!(" 4500 ".parse::<Amount>(), Ok(sats(4500)));
+        assert!("-1".parse::<Amount>().is_err());
+        assert!("1.5".parse::<Amount>().is_err());
+        assert!("".parse::<Amount>().is_err());
+        assert!((Amount::MAX_SATS + 1).to_string().parse::<Amount>().is_err());
+    }
+
+    #[test]
+    fn serializes_as_whole_sats() {
+        assert_eq!(serde_json::to_string(&Amount::from_msats(4_500_999)).unwrap(), "4500");
+        assert_eq!(serde_json::from_str::<Amount>("4500").unwrap(), sats(4500));
+        assert!(serde_json::from_str::<Amount>(&(Amount::MAX_SATS + 1).to_string()).is_err());
+        assert!(serde_json::from_str::<Amount>("-5").is_err());
+    }
+}
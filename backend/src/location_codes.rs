@@ -0,0 +1,245 @@
+// location_codes.rs - what3words addresses and Open Location Codes (plus codes) for stops without
+// a street address. Each kind of code is turned into coordinates by a pluggable resolver.
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::geocode::{GeocodeError, Geocoder};
+use crate::GeoPoint;
+
+#[async_trait]
+pub trait CodeResolver: Send + Sync {
+    // Coordinates of a normalized code; Ok(None) when no such location exists
+    async fn resolve(&self, code: &str) -> Result<Option<GeoPoint>, GeocodeError>;
+}
+
+// "///filled.count.soap" or "filled.count.soap", lowercased and without the slashes
+pub fn normalize_what3words(raw: &str) -> Option<String> {
+    let words = raw.trim().trim_start_matches('/').to_lowercase();
+    let parts: Vec<&str> = words.split('.').collect();
+    let valid = parts.len() == 3 && parts.iter().all(|word| !word.is_empty() && word.chars().all(char::is_alphabetic));
+    valid.then_some(words)
+}
+
+// what3words resolver backed by its convert-to-coordinates API
+pub struct What3WordsResolver {
+    client: reqwest::Client,
+    base_url: String,
+    api_key: String,
+}
+
+#[derive(Deserialize)]
+struct What3WordsSquare {
+    coordinates: GeoPoint,
+}
+
+impl What3WordsResolver {
+    pub fn new(base_url: &str, api_key: &str) -> Self {
+        let client = reqwest::Client::builder()
+            .user_agent(concat!("nostr-delivery-backend/", env!("CARGO_PKG_VERSION")))
+            .timeout(std::time::Duration::from_secs(10))
+            .build()
+            .unwrap_or_default();
+
+        Self {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl CodeResolver for What3WordsResolver {
+    async fn resolve(&self, words: &str) -> Result<Option<GeoPoint>, GeocodeError> {
+        let response = self
+            .client
+            .get(format!("{}/convert-to-coordinates", self.base_url))
+            .query(&[("words", words), ("key", self.api_key.as_str())])
+            .send()
+            .await?;
+
+        // Words that aren't a what3words address come back as a 400 BadWords error
+        if response.status() == reqwest::StatusCode::BAD_REQUEST {
+            return Ok(None);
+        }
+        let square: What3WordsSquare = response.error_for_status()?.json().await?;
+        Ok(Some(square.coordinates))
+    }
+}
+
+// what3words resolver configured via W3W_API_KEY (and W3W_API_URL); none without a key
+pub fn what3words_from_env() -> Option<Arc<dyn CodeResolver>> {
+    let api_key = std::env::var("W3W_API_KEY").ok().filter(|key| !key.is_empty())?;
+    let url = std::env::var("W3W_API_URL")
+        .ok()
+        .filter(|url| !url.is_empty())
+        .unwrap_or_else(|| "https://api.what3words.com/v3".to_string());
+
+    Some(Arc::new(What3WordsResolver::new(&url, &api_key)))
+}
+
+const ALPHABET: &[u8] = b"23456789CFGHJMPQRVWX";
+const SEPARATOR_POSITION: usize = 8;
+// Degrees covered by each of the five digit pairs; later digits refine a 5x4 grid
+const PAIR_RESOLUTIONS: [f64; 5] = [20.0, 1.0, 0.05, 0.0025, 0.000125];
+const MAX_DIGITS: usize = 15;
+
+fn digit(c: char) -> Option<usize> {
+    ALPHABET.iter().position(|&d| d as char == c)
+}
+
+// A plus code: either full ("849VCWC8+R9") or short with the locality it's relative to
+// ("CWC8+R9 Mountain View")
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlusCode {
+    pub code: String,
+    pub locality: Option<String>,
+}
+
+impl PlusCode {
+    pub fn parse(raw: &str) -> Option<Self> {
+        let raw = raw.trim();
+        let (code, locality) = match raw.split_once(|c: char| c.is_whitespace() || c == ',') {
+            Some((code, locality)) => (code, Some(locality.trim().trim_start_matches(',').trim().to_string())),
+            None => (raw, None),
+        };
+        let code = code.to_uppercase();
+        is_valid(&code).then(|| Self { code, locality: locality.filter(|l| !l.is_empty()) })
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.code.find('+') == Some(SEPARATOR_POSITION)
+    }
+}
+
+impl fmt::Display for PlusCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.locality {
+            Some(locality) => write!(f, "{} {}", self.code, locality),
+            None => f.write_str(&self.code),
+        }
+    }
+}
+
+fn is_valid(code: &str) -> bool {
+    let Some(separator) = code.find('+') else {
+        return false;
+    };
+    let (area, rest) = (&code[..separator], &code[separator + 1..]);
+    if separator > SEPARATOR_POSITION || separator % 2 == 1 || rest.contains('+') || rest.len() == 1 {
+        return false;
+    }
+
+    // Padding zeros only end a full code's area part, in pairs, with nothing after the separator
+    let digits = area.trim_end_matches('0');
+    if digits.len() < area.len() && (separator < SEPARATOR_POSITION || digits.is_empty() || digits.len() % 2 == 1 || !rest.is_empty()) {
+        return false;
+    }
+    if !digits.chars().chain(rest.chars()).all(|c| digit(c).is_some()) {
+        return false;
+    }
+
+    // A full code's first two digits must stay within 90°N and 180°E
+    let mut values = digits.chars().filter_map(digit);
+    separator < SEPARATOR_POSITION || (values.next().is_some_and(|lat| lat < 9) && values.next().is_some_and(|lng| lng < 18))
+}
+
+// Center of the area a full code covers
+fn decode(code: &str) -> GeoPoint {
+    let (mut lat, mut lng) = (-90.0, -180.0);
+    let (mut lat_resolution, mut lng_resolution) = (PAIR_RESOLUTIONS[0], PAIR_RESOLUTIONS[0]);
+    for (i, d) in code.chars().filter_map(digit).take(MAX_DIGITS).enumerate() {
+        if i < 10 {
+            let resolution = PAIR_RESOLUTIONS[i / 2];
+            (lat_resolution, lng_resolution) = (resolution, resolution);
+            if i % 2 == 0 {
+                lat += d as f64 * resolution;
+            } else {
+                lng += d as f64 * resolution;
+            }
+        } else {
+            lat_resolution /= 5.0;
+            lng_resolution /= 4.0;
+            lat += (d / 4) as f64 * lat_resolution;
+            lng += (d % 4) as f64 * lng_resolution;
+        }
+    }
+    GeoPoint {
+        lat: (lat + lat_resolution / 2.0).min(90.0),
+        lng: lng + lng_resolution / 2.0,
+    }
+}
+
+// The first `length` digits of the full code for `point`
+fn encode_prefix(point: &GeoPoint, length: usize) -> String {
+    let mut lat = (point.lat.clamp(-90.0, 90.0) + 90.0).min(179.999_999);
+    let mut lng = (point.lng + 180.0).rem_euclid(360.0);
+    let mut prefix = String::new();
+    for resolution in PAIR_RESOLUTIONS.iter().take(length / 2) {
+        let lat_digit = ((lat / resolution).floor() as usize).min(19);
+        let lng_digit = ((lng / resolution).floor() as usize).min(19);
+        prefix.push(ALPHABET[lat_digit] as char);
+        prefix.push(ALPHABET[lng_digit] as char);
+        lat -= lat_digit as f64 * resolution;
+        lng -= lng_digit as f64 * resolution;
+    }
+    prefix
+}
+
+// The full code nearest `reference` that ends in the short code (OLC's recoverNearest)
+fn recover(short: &str, reference: &GeoPoint) -> GeoPoint {
+    let padding = SEPARATOR_POSITION - short.find('+').unwrap_or(SEPARATOR_POSITION);
+    let resolution = 20f64.powi(2 - (padding / 2) as i32);
+    let half = resolution / 2.0;
+
+    let mut point = decode(&format!("{}{}", encode_prefix(reference, padding), short));
+    if reference.lat + half < point.lat && point.lat - resolution >= -90.0 {
+        point.lat -= resolution;
+    } else if reference.lat - half > point.lat && point.lat + resolution <= 90.0 {
+        point.lat += resolution;
+    }
+    if reference.lng + half < point.lng {
+        point.lng -= resolution;
+    } else if reference.lng - half > point.lng {
+        point.lng += resolution;
+    }
+    if point.lng > 180.0 {
+        point.lng -= 360.0;
+    } else if point.lng < -180.0 {
+        point.lng += 360.0;
+    }
+    point
+}
+
+// Decodes full codes offline; short codes are recovered near their locality, found with the geocoder
+pub struct PlusCodeResolver {
+    geocoder: Option<Arc<dyn Geocoder>>,
+}
+
+impl PlusCodeResolver {
+    pub fn new(geocoder: Option<Arc<dyn Geocoder>>) -> Self {
+        Self { geocoder }
+    }
+}
+
+#[async_trait]
+impl CodeResolver for PlusCodeResolver {
+    async fn resolve(&self, code: &str) -> Result<Option<GeoPoint>, GeocodeError> {
+        let Some(plus_code) = PlusCode::parse(code) else {
+            return Ok(None);
+        };
+        if plus_code.is_full() {
+            return Ok(Some(decode(&plus_code.code)));
+        }
+
+        let Some(locality) = &plus_code.locality else {
+            return Err("a short plus code needs a locality, e.g. 'CWC8+R9 Mountain View'".into());
+        };
+        let Some(geocoder) = &self.geocoder else {
+            return Err("short plus codes need a geocoder to find their locality".into());
+        };
+        Ok(geocoder.geocode(locality).await?.map(|reference| recover(&plus_code.code, &reference)))
+    }
+}
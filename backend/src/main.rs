@@ -1,879 +1,6224 @@
-// main.rs - Nostr-powered Delivery Backend
-use actix_web::{web, App, HttpServer, HttpResponse, Error, middleware};
-use actix_cors::Cors;
-use serde::Deserialize;
-use std::sync::Arc;
-use chrono::Utc;
-use nostr_sdk::prelude::*;
-use std::time::Duration;
-
-use nostr_delivery_backend::*;
-
-// Application State with Nostr Client
-pub struct AppState {
-    pub nostr_client: Arc<Client>,
-    pub system_keys: Keys,
-}
-
-impl AppState {
-    async fn new(relay_urls: Vec<String>) -> Result<Self, Box<dyn std::error::Error>> {
-        // Generate system keys for signing events
-        let system_keys = Keys::generate();
-
-        // Create Nostr client
-        let client = Client::new(system_keys.clone());
-
-        // Add relays
-        for url in relay_urls {
-            client.add_relay(&url).await?;
-        }
-
-        // Connect to relays
-        client.connect().await;
-
-        // Wait a bit for connections to establish
-        tokio::time::sleep(Duration::from_secs(2)).await;
-
-        println!("📡 Connected to {} relays", client.relays().await.len());
-        println!("🔑 System pubkey: {}", system_keys.public_key().to_bech32()?);
-
-        Ok(Self {
-            nostr_client: Arc::new(client),
-            system_keys,
-        })
-    }
-
-    // Helper to publish delivery request event
-    async fn publish_delivery(&self, delivery: &DeliveryRequest) -> Result<(), Box<dyn std::error::Error>> {
-        let content = serde_json::to_string(delivery)?;
-
-        let tags = vec![
-            Tag::custom(TagKind::Custom("d".into()), vec![delivery.id.clone()]),
-            Tag::custom(TagKind::Custom("sender".into()), vec![delivery.sender.clone()]),
-            Tag::custom(TagKind::Custom("status".into()), vec![format!("{:?}", delivery.status).to_lowercase()]),
-            Tag::custom(TagKind::Custom("amount".into()), vec![delivery.offer_amount.to_string()]),
-            Tag::custom(TagKind::Custom("created_at".into()), vec![delivery.created_at.to_string()]),
-        ];
-
-        let event = EventBuilder::new(Kind::Custom(35000), content, tags).sign_with_keys(&self.system_keys)?;
-        self.nostr_client.send_event(event).await?;
-
-        Ok(())
-    }
-
-    // Helper to publish bid event
-    async fn publish_bid(&self, delivery_id: &str, bid: &DeliveryBid) -> Result<(), Box<dyn std::error::Error>> {
-        let content = serde_json::to_string(bid)?;
-
-        let tags = vec![
-            Tag::custom(TagKind::Custom("d".into()), vec![bid.id.clone()]),
-            Tag::custom(TagKind::Custom("delivery_id".into()), vec![delivery_id.to_string()]),
-            Tag::custom(TagKind::Custom("courier".into()), vec![bid.courier.clone()]),
-            Tag::custom(TagKind::Custom("amount".into()), vec![bid.amount.to_string()]),
-        ];
-
-        let event = EventBuilder::new(Kind::Custom(35001), content, tags).sign_with_keys(&self.system_keys)?;
-        self.nostr_client.send_event(event).await?;
-
-        Ok(())
-    }
-
-    // Helper to publish status update event
-    async fn publish_status_update(&self, delivery_id: &str, status: &DeliveryStatus, additional_data: Option<String>) -> Result<(), Box<dyn std::error::Error>> {
-        let kind = match status {
-            DeliveryStatus::Accepted => 35002,
-            DeliveryStatus::InTransit => 35004,
-            DeliveryStatus::Completed => 35005,
-            DeliveryStatus::Confirmed => 35006,
-            _ => 35000,
-        };
-
-        let content = additional_data.unwrap_or_else(|| format!("{{\"status\": \"{:?}\"}}", status));
-
-        let tags = vec![
-            Tag::custom(TagKind::Custom("delivery_id".into()), vec![delivery_id.to_string()]),
-            Tag::custom(TagKind::Custom("status".into()), vec![format!("{:?}", status).to_lowercase()]),
-            Tag::custom(TagKind::Custom("timestamp".into()), vec![Utc::now().timestamp().to_string()]),
-        ];
-
-        let event = EventBuilder::new(Kind::Custom(kind), content, tags).sign_with_keys(&self.system_keys)?;
-        self.nostr_client.send_event(event).await?;
-
-        Ok(())
-    }
-
-    // Helper to publish user profile event
-    async fn publish_user_profile(&self, profile: &UserProfile) -> Result<(), Box<dyn std::error::Error>> {
-        let content = serde_json::to_string(profile)?;
-
-        let tags = vec![
-            Tag::custom(TagKind::Custom("d".into()), vec![profile.npub.clone()]),
-            Tag::custom(TagKind::Custom("reputation".into()), vec![profile.reputation.to_string()]),
-            Tag::custom(TagKind::Custom("completed_deliveries".into()), vec![profile.completed_deliveries.to_string()]),
-        ];
-
-        let event = EventBuilder::new(Kind::Custom(35009), content, tags).sign_with_keys(&self.system_keys)?;
-        self.nostr_client.send_event(event).await?;
-
-        Ok(())
-    }
-
-    // Query all deliveries from Nostr
-    async fn get_all_deliveries(&self) -> Result<Vec<DeliveryRequest>, Box<dyn std::error::Error>> {
-        let filter = Filter::new()
-            .kind(Kind::Custom(35000))
-            .limit(1000);
-
-        let events = self.nostr_client.fetch_events(vec![filter], Some(Duration::from_secs(5))).await?;
-
-        let mut deliveries = Vec::new();
-
-        for event in events {
-            if let Ok(mut delivery) = serde_json::from_str::<DeliveryRequest>(&event.content) {
-                // Fetch bids for this delivery
-                let bids = self.get_bids_for_delivery(&delivery.id).await.unwrap_or_default();
-                delivery.bids = bids;
-
-                // Check for status updates
-                if let Ok(updates) = self.get_status_updates(&delivery.id).await {
-                    if let Some(latest) = updates.last() {
-                        delivery.status = latest.status.clone();
-                        if latest.proof_of_delivery.is_some() {
-                            delivery.proof_of_delivery = latest.proof_of_delivery.clone();
-                        }
-                        if latest.completed_at.is_some() {
-                            delivery.completed_at = latest.completed_at;
-                        }
-                        if latest.accepted_bid.is_some() {
-                            delivery.accepted_bid = latest.accepted_bid.clone();
-                        }
-                        if latest.sender_rating.is_some() {
-                            delivery.sender_rating = latest.sender_rating;
-                        }
-                        if latest.sender_feedback.is_some() {
-                            delivery.sender_feedback = latest.sender_feedback.clone();
-                        }
-                    }
-                }
-
-                deliveries.push(delivery);
-            }
-        }
-
-        Ok(deliveries)
-    }
-
-    // Query specific delivery by ID
-    async fn get_delivery_by_id(&self, id: &str) -> Result<Option<DeliveryRequest>, Box<dyn std::error::Error>> {
-        let filter = Filter::new()
-            .kind(Kind::Custom(35000))
-            .custom_tag(SingleLetterTag::lowercase(Alphabet::D), [id]);
-
-        let events = self.nostr_client.fetch_events(vec![filter], Some(Duration::from_secs(5))).await?;
-
-        if let Some(event) = events.first() {
-            let mut delivery = serde_json::from_str::<DeliveryRequest>(&event.content)?;
-
-            // Fetch bids
-            delivery.bids = self.get_bids_for_delivery(&delivery.id).await.unwrap_or_default();
-
-            // Check for status updates
-            if let Ok(updates) = self.get_status_updates(&delivery.id).await {
-                if let Some(latest) = updates.last() {
-                    delivery.status = latest.status.clone();
-                    if latest.proof_of_delivery.is_some() {
-                        delivery.proof_of_delivery = latest.proof_of_delivery.clone();
-                    }
-                    if latest.completed_at.is_some() {
-                        delivery.completed_at = latest.completed_at;
-                    }
-                    if latest.accepted_bid.is_some() {
-                        delivery.accepted_bid = latest.accepted_bid.clone();
-                    }
-                    if latest.sender_rating.is_some() {
-                        delivery.sender_rating = latest.sender_rating;
-                    }
-                    if latest.sender_feedback.is_some() {
-                        delivery.sender_feedback = latest.sender_feedback.clone();
-                    }
-                }
-            }
-
-            Ok(Some(delivery))
-        } else {
-            Ok(None)
-        }
-    }
-
-    // Get bids for a delivery
-    async fn get_bids_for_delivery(&self, delivery_id: &str) -> Result<Vec<DeliveryBid>, Box<dyn std::error::Error>> {
-        let filter = Filter::new()
-            .kind(Kind::Custom(35001))
-            .limit(1000);
-
-        let events = self.nostr_client.fetch_events(vec![filter], Some(Duration::from_secs(5))).await?;
-
-        let mut bids = Vec::new();
-        for event in events {
-            // Check if this bid is for our delivery_id
-            let has_delivery_tag = event.tags.iter().any(|tag| {
-                let tag_vec = tag.clone().to_vec();
-                tag_vec.len() >= 2 && tag_vec[0] == "delivery_id" && tag_vec[1] == delivery_id
-            });
-
-            if has_delivery_tag {
-                if let Ok(bid) = serde_json::from_str::<DeliveryBid>(&event.content) {
-                    bids.push(bid);
-                }
-            }
-        }
-
-        bids.sort_by_key(|b| b.created_at);
-        Ok(bids)
-    }
-
-    // Get status updates for a delivery
-    async fn get_status_updates(&self, delivery_id: &str) -> Result<Vec<DeliveryUpdate>, Box<dyn std::error::Error>> {
-        let filter = Filter::new()
-            .kinds(vec![
-                Kind::Custom(35002), // Accepted
-                Kind::Custom(35003), // Started
-                Kind::Custom(35004), // InTransit
-                Kind::Custom(35005), // Completed
-                Kind::Custom(35006), // Confirmed
-            ])
-            .limit(1000);
-
-        let events = self.nostr_client.fetch_events(vec![filter], Some(Duration::from_secs(5))).await?;
-
-        let mut updates = Vec::new();
-        for event in events {
-            let has_delivery_tag = event.tags.iter().any(|tag| {
-                let tag_vec = tag.clone().to_vec();
-                tag_vec.len() >= 2 && tag_vec[0] == "delivery_id" && tag_vec[1] == delivery_id
-            });
-
-            if has_delivery_tag {
-                let status = match event.kind.as_u16() {
-                    35002 => DeliveryStatus::Accepted,
-                    35003 => DeliveryStatus::Open,
-                    35004 => DeliveryStatus::InTransit,
-                    35005 => DeliveryStatus::Completed,
-                    35006 => DeliveryStatus::Confirmed,
-                    _ => DeliveryStatus::Open,
-                };
-
-                let update: DeliveryUpdate = if let Ok(parsed) = serde_json::from_str(&event.content) {
-                    parsed
-                } else {
-                    DeliveryUpdate {
-                        status,
-                        timestamp: event.created_at.as_u64() as i64,
-                        proof_of_delivery: None,
-                        completed_at: None,
-                        accepted_bid: None,
-                        sender_rating: None,
-                        sender_feedback: None,
-                    }
-                };
-
-                updates.push(update);
-            }
-        }
-
-        updates.sort_by_key(|u| u.timestamp);
-        Ok(updates)
-    }
-
-    // Get user profile
-    async fn get_user_profile(&self, npub: &str) -> Result<UserProfile, Box<dyn std::error::Error>> {
-        let filter = Filter::new()
-            .kind(Kind::Custom(35009))
-            .custom_tag(SingleLetterTag::lowercase(Alphabet::D), [npub]);
-
-        let events = self.nostr_client.fetch_events(vec![filter], Some(Duration::from_secs(5))).await?;
-
-        if let Some(event) = events.first() {
-            let profile = serde_json::from_str::<UserProfile>(&event.content)?;
-            Ok(profile)
-        } else {
-            // Return default profile
-            Ok(UserProfile {
-                npub: npub.to_string(),
-                ..Default::default()
-            })
-        }
-    }
-}
-
-// API Handlers
-async fn health_check() -> HttpResponse {
-    HttpResponse::Ok().json(serde_json::json!({
-        "status": "healthy",
-        "backend": "nostr",
-        "timestamp": Utc::now().timestamp(),
-        "version": "2.0.0-nostr"
-    }))
-}
-
-#[derive(Deserialize)]
-struct DeliveryQuery {
-    status: Option<String>,
-}
-
-async fn get_deliveries(
-    data: web::Data<AppState>,
-    query: web::Query<DeliveryQuery>,
-) -> Result<HttpResponse, Error> {
-    let deliveries = data.get_all_deliveries().await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e.to_string()))?;
-
-    let filtered: Vec<DeliveryRequest> = if let Some(status) = &query.status {
-        deliveries.into_iter()
-            .filter(|d| {
-                let d_status = format!("{:?}", d.status).to_lowercase();
-                d_status == status.to_lowercase()
-            })
-            .collect()
-    } else {
-        deliveries
-    };
-
-    Ok(HttpResponse::Ok().json(filtered))
-}
-
-async fn get_delivery(
-    data: web::Data<AppState>,
-    id: web::Path<String>,
-) -> Result<HttpResponse, Error> {
-    let delivery = data.get_delivery_by_id(&id).await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e.to_string()))?;
-
-    if let Some(delivery) = delivery {
-        Ok(HttpResponse::Ok().json(delivery))
-    } else {
-        Ok(HttpResponse::NotFound().json(serde_json::json!({
-            "error": "Delivery not found"
-        })))
-    }
-}
-
-#[derive(Deserialize)]
-struct CreateDeliveryRequest {
-    pickup: Location,
-    dropoff: Location,
-    packages: Vec<PackageInfo>,
-    offer_amount: u64,
-    insurance_amount: Option<u64>,
-    time_window: String,
-    sender: String,
-}
-
-async fn create_delivery(
-    data: web::Data<AppState>,
-    req: web::Json<CreateDeliveryRequest>,
-) -> Result<HttpResponse, Error> {
-    let id = format!("delivery_{}", Utc::now().timestamp_millis());
-
-    let distance = if let (Some(p1), Some(p2)) = (&req.pickup.coordinates, &req.dropoff.coordinates) {
-        Some(calculate_distance(p1, p2))
-    } else {
-        None
-    };
-
-    let delivery = DeliveryRequest {
-        id: id.clone(),
-        sender: req.sender.clone(),
-        pickup: req.pickup.clone(),
-        dropoff: req.dropoff.clone(),
-        packages: req.packages.clone(),
-        offer_amount: req.offer_amount,
-        insurance_amount: req.insurance_amount,
-        time_window: req.time_window.clone(),
-        expires_at: Some(Utc::now().timestamp() + 604800),
-        status: DeliveryStatus::Open,
-        bids: vec![],
-        accepted_bid: None,
-        created_at: Utc::now().timestamp(),
-        distance_meters: distance,
-        proof_of_delivery: None,
-        sender_feedback: None,
-        sender_rating: None,
-        completed_at: None,
-    };
-
-    data.publish_delivery(&delivery).await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e.to_string()))?;
-
-    Ok(HttpResponse::Ok().json(serde_json::json!({
-        "id": id,
-        "status": "created",
-        "delivery": delivery
-    })))
-}
-
-#[derive(Deserialize)]
-struct PlaceBidRequest {
-    courier: String,
-    amount: u64,
-    estimated_time: String,
-    message: Option<String>,
-}
-
-async fn place_bid(
-    data: web::Data<AppState>,
-    delivery_id: web::Path<String>,
-    req: web::Json<PlaceBidRequest>,
-) -> Result<HttpResponse, Error> {
-    // Verify delivery exists
-    let delivery = data.get_delivery_by_id(&delivery_id).await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e.to_string()))?;
-
-    if delivery.is_none() {
-        return Ok(HttpResponse::NotFound().json(serde_json::json!({
-            "error": "Delivery not found"
-        })));
-    }
-
-    // Get courier profile
-    let courier_profile = data.get_user_profile(&req.courier).await
-        .unwrap_or_default();
-
-    let bid = DeliveryBid {
-        id: format!("bid_{}", Utc::now().timestamp_millis()),
-        courier: req.courier.clone(),
-        amount: req.amount,
-        estimated_time: req.estimated_time.clone(),
-        reputation: courier_profile.reputation,
-        completed_deliveries: courier_profile.completed_deliveries,
-        message: req.message.clone(),
-        created_at: Utc::now().timestamp(),
-    };
-
-    data.publish_bid(&delivery_id, &bid).await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e.to_string()))?;
-
-    Ok(HttpResponse::Ok().json(serde_json::json!({
-        "status": "bid_placed",
-        "bid": bid
-    })))
-}
-
-async fn accept_bid(
-    data: web::Data<AppState>,
-    path: web::Path<(String, usize)>,
-) -> Result<HttpResponse, Error> {
-    let (delivery_id, bid_index) = path.into_inner();
-
-    let mut delivery = data.get_delivery_by_id(&delivery_id).await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e.to_string()))?
-        .ok_or_else(|| actix_web::error::ErrorNotFound("Delivery not found"))?;
-
-    if bid_index >= delivery.bids.len() {
-        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
-            "error": "Invalid bid index"
-        })));
-    }
-
-    let bid = &delivery.bids[bid_index];
-    delivery.accepted_bid = Some(bid.id.clone());
-    delivery.status = DeliveryStatus::Accepted;
-    delivery.offer_amount = bid.amount;
-
-    // Publish updated delivery
-    data.publish_delivery(&delivery).await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e.to_string()))?;
-
-    // Publish acceptance event
-    let acceptance_data = serde_json::json!({
-        "status": "Accepted",
-        "accepted_bid": bid.id.clone(),
-        "timestamp": Utc::now().timestamp()
-    });
-
-    data.publish_status_update(&delivery_id, &DeliveryStatus::Accepted, Some(acceptance_data.to_string())).await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e.to_string()))?;
-
-    Ok(HttpResponse::Ok().json(serde_json::json!({
-        "status": "accepted",
-        "delivery": delivery
-    })))
-}
-
-#[derive(Deserialize)]
-struct UpdateStatusRequest {
-    status: String,
-}
-
-async fn update_delivery_status(
-    data: web::Data<AppState>,
-    delivery_id: web::Path<String>,
-    req: web::Json<UpdateStatusRequest>,
-) -> Result<HttpResponse, Error> {
-    let mut delivery = data.get_delivery_by_id(&delivery_id).await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e.to_string()))?
-        .ok_or_else(|| actix_web::error::ErrorNotFound("Delivery not found"))?;
-
-    let new_status = match req.status.to_lowercase().as_str() {
-        "accepted" => DeliveryStatus::Accepted,
-        "in_transit" | "intransit" => DeliveryStatus::InTransit,
-        "completed" => DeliveryStatus::Completed,
-        "confirmed" => DeliveryStatus::Confirmed,
-        _ => delivery.status.clone(),
-    };
-
-    delivery.status = new_status.clone();
-
-    // Publish updated delivery
-    data.publish_delivery(&delivery).await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e.to_string()))?;
-
-    // Publish status update event
-    data.publish_status_update(&delivery_id, &new_status, None).await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e.to_string()))?;
-
-    Ok(HttpResponse::Ok().json(serde_json::json!({
-        "status": "updated",
-        "delivery": delivery
-    })))
-}
-
-#[derive(Deserialize)]
-struct ConfirmDeliveryRequest {
-    rating: Option<f32>,
-    feedback: Option<String>,
-}
-
-async fn confirm_delivery(
-    data: web::Data<AppState>,
-    delivery_id: web::Path<String>,
-    req: web::Json<ConfirmDeliveryRequest>,
-) -> Result<HttpResponse, Error> {
-    let mut delivery = data.get_delivery_by_id(&delivery_id).await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e.to_string()))?
-        .ok_or_else(|| actix_web::error::ErrorNotFound("Delivery not found"))?;
-
-    delivery.status = DeliveryStatus::Confirmed;
-    delivery.sender_feedback = req.feedback.clone();
-    delivery.sender_rating = req.rating;
-
-    // Update courier reputation
-    if let Some(accepted_bid_id) = &delivery.accepted_bid {
-        if let Some(bid) = delivery.bids.iter().find(|b| &b.id == accepted_bid_id) {
-            let mut courier = data.get_user_profile(&bid.courier).await.unwrap_or_default();
-
-            if let Some(rating) = req.rating {
-                let new_rep = if courier.completed_deliveries == 0 {
-                    rating
-                } else {
-                    ((courier.reputation * courier.completed_deliveries as f32) + rating) / (courier.completed_deliveries + 1) as f32
-                };
-                courier.reputation = new_rep;
-            }
-
-            courier.completed_deliveries += 1;
-            courier.total_earnings += delivery.offer_amount;
-
-            // Publish updated courier profile
-            data.publish_user_profile(&courier).await
-                .map_err(|e| actix_web::error::ErrorInternalServerError(e.to_string()))?;
-        }
-    }
-
-    // Publish updated delivery
-    data.publish_delivery(&delivery).await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e.to_string()))?;
-
-    // Publish confirmation event
-    let confirmation_data = serde_json::json!({
-        "status": "Confirmed",
-        "sender_rating": req.rating,
-        "sender_feedback": req.feedback,
-        "timestamp": Utc::now().timestamp()
-    });
-
-    data.publish_status_update(&delivery_id, &DeliveryStatus::Confirmed, Some(confirmation_data.to_string())).await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e.to_string()))?;
-
-    Ok(HttpResponse::Ok().json(serde_json::json!({
-        "status": "confirmed",
-        "delivery": delivery
-    })))
-}
-
-#[derive(Deserialize)]
-struct UpdateDeliveryRequest {
-    pickup: Option<Location>,
-    dropoff: Option<Location>,
-    packages: Option<Vec<PackageInfo>>,
-    offer_amount: Option<u64>,
-    insurance_amount: Option<u64>,
-    time_window: Option<String>,
-}
-
-async fn update_delivery(
-    data: web::Data<AppState>,
-    delivery_id: web::Path<String>,
-    req: web::Json<UpdateDeliveryRequest>,
-) -> Result<HttpResponse, Error> {
-    let mut delivery = data.get_delivery_by_id(&delivery_id).await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e.to_string()))?
-        .ok_or_else(|| actix_web::error::ErrorNotFound("Delivery not found"))?;
-
-    if delivery.status != DeliveryStatus::Open {
-        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
-            "error": "Cannot update delivery that is not open"
-        })));
-    }
-
-    if let Some(pickup) = req.pickup.clone() {
-        delivery.pickup = pickup;
-    }
-    if let Some(dropoff) = req.dropoff.clone() {
-        delivery.dropoff = dropoff;
-    }
-    if let Some(packages) = req.packages.clone() {
-        delivery.packages = packages;
-    }
-    if let Some(offer_amount) = req.offer_amount {
-        delivery.offer_amount = offer_amount;
-    }
-    if let Some(insurance_amount) = req.insurance_amount {
-        delivery.insurance_amount = Some(insurance_amount);
-    }
-    if let Some(time_window) = req.time_window.clone() {
-        delivery.time_window = time_window;
-    }
-
-    if let (Some(p1), Some(p2)) = (&delivery.pickup.coordinates, &delivery.dropoff.coordinates) {
-        delivery.distance_meters = Some(calculate_distance(p1, p2));
-    }
-
-    // Publish updated delivery
-    data.publish_delivery(&delivery).await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e.to_string()))?;
-
-    Ok(HttpResponse::Ok().json(serde_json::json!({
-        "status": "updated",
-        "delivery": delivery
-    })))
-}
-
-async fn delete_delivery(
-    data: web::Data<AppState>,
-    delivery_id: web::Path<String>,
-) -> Result<HttpResponse, Error> {
-    let delivery = data.get_delivery_by_id(&delivery_id).await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e.to_string()))?
-        .ok_or_else(|| actix_web::error::ErrorNotFound("Delivery not found"))?;
-
-    if delivery.status != DeliveryStatus::Open {
-        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
-            "error": "Cannot delete delivery that is not open"
-        })));
-    }
-
-    // Publish deletion event (mark as expired)
-    let mut deleted_delivery = delivery.clone();
-    deleted_delivery.status = DeliveryStatus::Expired;
-
-    data.publish_delivery(&deleted_delivery).await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e.to_string()))?;
-
-    Ok(HttpResponse::Ok().json(serde_json::json!({
-        "status": "deleted",
-        "id": delivery_id.as_str()
-    })))
-}
-
-async fn cancel_delivery(
-    data: web::Data<AppState>,
-    delivery_id: web::Path<String>,
-) -> Result<HttpResponse, Error> {
-    let delivery = data.get_delivery_by_id(&delivery_id).await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e.to_string()))?
-        .ok_or_else(|| actix_web::error::ErrorNotFound("Delivery not found"))?;
-
-    if delivery.status != DeliveryStatus::Accepted && delivery.status != DeliveryStatus::InTransit {
-        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
-            "error": "Can only cancel accepted deliveries"
-        })));
-    }
-
-    // Award sats to courier
-    if let Some(accepted_bid_id) = &delivery.accepted_bid {
-        if let Some(bid) = delivery.bids.iter().find(|b| &b.id == accepted_bid_id) {
-            let mut courier = data.get_user_profile(&bid.courier).await.unwrap_or_default();
-            courier.total_earnings += delivery.offer_amount;
-
-            data.publish_user_profile(&courier).await
-                .map_err(|e| actix_web::error::ErrorInternalServerError(e.to_string()))?;
-        }
-    }
-
-    // Mark as expired
-    let mut cancelled_delivery = delivery.clone();
-    cancelled_delivery.status = DeliveryStatus::Expired;
-
-    data.publish_delivery(&cancelled_delivery).await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e.to_string()))?;
-
-    Ok(HttpResponse::Ok().json(serde_json::json!({
-        "status": "cancelled",
-        "message": "Delivery cancelled and sats forfeited to courier"
-    })))
-}
-
-#[derive(Deserialize)]
-struct CompleteDeliveryRequest {
-    images: Vec<String>,
-    signature_name: Option<String>,
-    comments: Option<String>,
-}
-
-async fn complete_delivery(
-    data: web::Data<AppState>,
-    delivery_id: web::Path<String>,
-    req: web::Json<CompleteDeliveryRequest>,
-) -> Result<HttpResponse, Error> {
-    let mut delivery = data.get_delivery_by_id(&delivery_id).await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e.to_string()))?
-        .ok_or_else(|| actix_web::error::ErrorNotFound("Delivery not found"))?;
-
-    if delivery.status != DeliveryStatus::Accepted && delivery.status != DeliveryStatus::InTransit {
-        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
-            "error": "Can only complete accepted or in-transit deliveries"
-        })));
-    }
-
-    let signature_required = delivery.packages.iter().any(|pkg| pkg.requires_signature);
-    if signature_required && req.signature_name.is_none() {
-        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
-            "error": "Signature required for this delivery"
-        })));
-    }
-
-    delivery.proof_of_delivery = Some(ProofOfDelivery {
-        images: req.images.clone(),
-        signature_name: req.signature_name.clone(),
-        timestamp: Utc::now().timestamp(),
-        location: None,
-        comments: req.comments.clone(),
-    });
-    delivery.status = DeliveryStatus::Completed;
-    delivery.completed_at = Some(Utc::now().timestamp());
-
-    // Publish updated delivery
-    data.publish_delivery(&delivery).await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e.to_string()))?;
-
-    // Publish completion event
-    let completion_data = serde_json::json!({
-        "status": "Completed",
-        "proof_of_delivery": delivery.proof_of_delivery,
-        "completed_at": delivery.completed_at,
-        "timestamp": Utc::now().timestamp()
-    });
-
-    data.publish_status_update(&delivery_id, &DeliveryStatus::Completed, Some(completion_data.to_string())).await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e.to_string()))?;
-
-    Ok(HttpResponse::Ok().json(serde_json::json!({
-        "status": "completed",
-        "delivery": delivery
-    })))
-}
-
-async fn get_user(
-    data: web::Data<AppState>,
-    npub: web::Path<String>,
-) -> Result<HttpResponse, Error> {
-    let profile = data.get_user_profile(&npub).await
-        .unwrap_or_else(|_| UserProfile {
-            npub: npub.to_string(),
-            ..Default::default()
-        });
-
-    Ok(HttpResponse::Ok().json(profile))
-}
-
-#[derive(Deserialize)]
-struct UpdateUserRequest {
-    display_name: Option<String>,
-    lightning_address: Option<String>,
-}
-
-async fn update_user(
-    data: web::Data<AppState>,
-    npub: web::Path<String>,
-    req: web::Json<UpdateUserRequest>,
-) -> Result<HttpResponse, Error> {
-    let mut profile = data.get_user_profile(&npub).await
-        .unwrap_or_else(|_| UserProfile {
-            npub: npub.to_string(),
-            ..Default::default()
-        });
-
-    if let Some(name) = &req.display_name {
-        profile.display_name = Some(name.clone());
-    }
-    if let Some(ln_addr) = &req.lightning_address {
-        profile.lightning_address = Some(ln_addr.clone());
-    }
-
-    data.publish_user_profile(&profile).await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e.to_string()))?;
-
-    Ok(HttpResponse::Ok().json(profile))
-}
-
-#[actix_web::main]
-async fn main() -> std::io::Result<()> {
-    env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
-
-    println!("🚀 Nostr Delivery Backend Starting...");
-    println!("🔌 Backend Mode: Nostr-Powered (No Database)");
-
-    // Get relay URLs from environment or use defaults
-    let relay_urls = std::env::var("NOSTR_RELAYS")
-        .unwrap_or_else(|_| "wss://relay.damus.io,wss://nos.lol,wss://relay.nostr.band".to_string())
-        .split(',')
-        .map(|s| s.trim().to_string())
-        .collect::<Vec<String>>();
-
-    println!("📡 Connecting to relays: {:?}", relay_urls);
-
-    let app_state = web::Data::new(
-        AppState::new(relay_urls).await
-            .expect("Failed to initialize Nostr client")
-    );
-
-    println!("✅ Nostr client initialized");
-    println!("🌐 Server ready on http://0.0.0.0:8080");
-
-    HttpServer::new(move || {
-        let cors = Cors::permissive();
-
-        App::new()
-            .app_data(app_state.clone())
-            .wrap(cors)
-            .wrap(middleware::Logger::default())
-            .route("/health", web::get().to(health_check))
-            .route("/api/deliveries", web::get().to(get_deliveries))
-            .route("/api/deliveries", web::post().to(create_delivery))
-            .route("/api/deliveries/{id}", web::get().to(get_delivery))
-            .route("/api/deliveries/{id}", web::patch().to(update_delivery))
-            .route("/api/deliveries/{id}", web::delete().to(delete_delivery))
-            .route("/api/deliveries/{id}/bid", web::post().to(place_bid))
-            .route("/api/deliveries/{id}/accept/{bid_idx}", web::post().to(accept_bid))
-            .route("/api/deliveries/{id}/status", web::patch().to(update_delivery_status))
-            .route("/api/deliveries/{id}/cancel", web::post().to(cancel_delivery))
-            .route("/api/deliveries/{id}/complete", web::post().to(complete_delivery))
-            .route("/api/deliveries/{id}/confirm", web::post().to(confirm_delivery))
-            .route("/api/user/{npub}", web::get().to(get_user))
-            .route("/api/user/{npub}", web::patch().to(update_user))
-    })
-    .bind(("0.0.0.0", 8080))?
-    .run()
-    .await
-}
+// main.rs - Nostr-powered Delivery Backend
+use actix_web::{web, App, HttpMessage, HttpServer, HttpRequest, HttpResponse, Error, middleware};
+use actix_cors::Cors;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, OpenApi, ToSchema};
+use chrono::Utc;
+use nostr_sdk::prelude::*;
+use std::collections::HashSet;
+use std::time::Duration;
+
+use nostr_delivery_backend::*;
+use nostr_delivery_backend::state::AppState;
+use nostr_delivery_backend::problem::{Problem, ProblemType};
+
+
+// API Handlers
+// Adopt the caller's X-Request-Id or assign one, scope it over the handler and echo it back
+async fn assign_request_id(
+    req: actix_web::dev::ServiceRequest,
+    next: middleware::Next<impl actix_web::body::MessageBody>,
+) -> Result<actix_web::dev::ServiceResponse<impl actix_web::body::MessageBody>, Error> {
+    let id = req.headers()
+        .get(request_id::HEADER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(request_id::accept)
+        .unwrap_or_else(request_id::generate);
+
+    let mut res = request_id::scope(id.clone(), next.call(req)).await?;
+    if let Ok(value) = actix_web::http::header::HeaderValue::from_str(&id) {
+        res.headers_mut().insert(actix_web::http::header::HeaderName::from_static(request_id::HEADER), value);
+    }
+    Ok(res)
+}
+
+// NIP-98 signed requests commit to their body in a `payload` tag. Read the body up front, note its
+// hash for the AuthenticatedUser extractor and hand the body on to the handler.
+async fn hash_signed_body(
+    mut req: actix_web::dev::ServiceRequest,
+    next: middleware::Next<impl actix_web::body::MessageBody>,
+) -> Result<actix_web::dev::ServiceResponse<impl actix_web::body::MessageBody>, Error> {
+    use futures_util::StreamExt;
+
+    let signed = req.headers()
+        .get(actix_web::http::header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|h| h.starts_with("Nostr "));
+    if signed {
+        let mut payload = req.take_payload();
+        let mut body = web::BytesMut::new();
+        while let Some(chunk) = payload.next().await {
+            let chunk = chunk?;
+            // No route takes more than an upload
+            if body.len() + chunk.len() > uploads::MAX_UPLOAD_BYTES {
+                return Err(problem_error(Problem::new(ProblemType::InvalidRequest, "Request body is too large")));
+            }
+            body.extend_from_slice(&chunk);
+        }
+        if !body.is_empty() {
+            req.extensions_mut().insert(auth::BodyHash::of(&body));
+        }
+        req.set_payload(body.freeze().into());
+    }
+    next.call(req).await
+}
+
+// Error response as application/problem+json, tagged with the request's ID
+fn problem_response(mut problem: Problem) -> HttpResponse {
+    if let Some(id) = request_id::current() {
+        problem = problem.with("request_id", id);
+    }
+    let status = actix_web::http::StatusCode::from_u16(problem.status)
+        .unwrap_or(actix_web::http::StatusCode::INTERNAL_SERVER_ERROR);
+    HttpResponse::build(status)
+        .content_type(problem::CONTENT_TYPE)
+        .json(problem)
+}
+
+// Problem as an actix error, for `?` and extractor error handlers
+fn problem_error(problem: Problem) -> Error {
+    let detail = problem.detail.clone();
+    actix_web::error::InternalError::from_response(detail, problem_response(problem)).into()
+}
+
+fn delivery_not_found(delivery_id: &str) -> Error {
+    problem_error(Problem::new(ProblemType::NotFound, "Delivery not found").for_delivery(delivery_id))
+}
+
+// The signed-in caller as a reader of deliveries. Drafts attributed to an organization are shared
+// with its members, so the caller's memberships among those organizations are looked up once.
+struct Viewer {
+    npub: Option<String>,
+    organizations: HashSet<String>,
+}
+
+impl Viewer {
+    async fn for_deliveries(data: &AppState, user: Option<&AuthenticatedUser>, deliveries: &[DeliveryRequest]) -> Result<Self, Error> {
+        let npub = user.map(AuthenticatedUser::npub);
+        let mut organizations = HashSet::new();
+        if let Some(npub) = &npub {
+            let draft_orgs: HashSet<&String> = deliveries.iter()
+                .filter(|d| d.status == DeliveryStatus::Draft)
+                .filter_map(|d| d.organization.as_ref())
+                .collect();
+            for org_id in draft_orgs {
+                let org = data.get_organization(org_id).await
+                    .map_err(backend_error)?;
+                if org.is_some_and(|org| org.role_of(npub).is_some()) {
+                    organizations.insert(org_id.clone());
+                }
+            }
+        }
+        Ok(Viewer { npub, organizations })
+    }
+
+    fn can_see(&self, delivery: &DeliveryRequest) -> bool {
+        delivery.visible_to(self.npub.as_deref())
+            || (delivery.status == DeliveryStatus::Draft && delivery.organization.as_ref().is_some_and(|org| self.organizations.contains(org)))
+    }
+}
+
+// A delivery the caller may read. Private deliveries and drafts are reported missing to everyone
+// else, so their existence isn't given away.
+async fn visible_delivery(data: &AppState, delivery_id: &str, user: Option<&AuthenticatedUser>) -> Result<DeliveryRequest, Error> {
+    let delivery = data.get_delivery_by_id(delivery_id).await
+        .map_err(backend_error)?
+        .ok_or_else(|| delivery_not_found(delivery_id))?;
+    if !Viewer::for_deliveries(data, user, std::slice::from_ref(&delivery)).await?.can_see(&delivery) {
+        return Err(delivery_not_found(delivery_id));
+    }
+    Ok(delivery)
+}
+
+// A refusal from the delivery service as a problem about the delivery, or a backend failure
+fn service_error(e: service::ServiceError, delivery_id: &str) -> Error {
+    match e {
+        service::ServiceError::Refused(problem) => problem_error(problem.for_delivery(delivery_id)),
+        service::ServiceError::Store(e) => backend_error(e),
+    }
+}
+
+// Failure from the relay, signing or encryption layer
+fn backend_error(e: impl std::fmt::Display + 'static) -> Error {
+    // Name the relays that refused a publish, so the caller can tell a partial publish from an outage
+    let quorum = (&e as &dyn std::any::Any)
+        .downcast_ref::<Box<dyn std::error::Error>>()
+        .and_then(|e| e.downcast_ref::<publish_log::QuorumError>());
+    if let Some(quorum) = quorum {
+        return problem_error(
+            Problem::new(ProblemType::RelayQuorum, quorum.to_string())
+                .with("event_id", &quorum.event_id)
+                .with("required", quorum.required)
+                .with("accepted", quorum.accepted)
+                .with("relays", &quorum.acks),
+        );
+    }
+    problem_error(Problem::from_backend_error(&e.to_string()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "System",
+    responses(
+        (status = 200, description = "Service health", body = Object)
+    )
+)]
+async fn health_check() -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({
+        "status": "healthy",
+        "backend": "nostr",
+        "timestamp": Utc::now().timestamp(),
+        "version": "2.0.0-nostr"
+    }))
+}
+
+// Liveness: the process is up and serving requests
+#[utoipa::path(
+    get,
+    path = "/health/live",
+    tag = "System",
+    responses(
+        (status = 200, description = "Process is up", body = Object)
+    )
+)]
+async fn health_live() -> HttpResponse {
+    health_check().await
+}
+
+// Readiness: dependencies needed to serve traffic are available
+#[utoipa::path(
+    get,
+    path = "/health/ready",
+    tag = "System",
+    responses(
+        (status = 200, description = "Ready, with per-dependency detail", body = health::Readiness),
+        (status = 503, description = "Not ready, with the failing dependencies", body = health::Readiness)
+    )
+)]
+async fn health_ready(
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let readiness = data.readiness().await;
+    if readiness.ready {
+        HttpResponse::Ok().json(readiness)
+    } else {
+        HttpResponse::ServiceUnavailable().json(readiness)
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/protocol",
+    tag = "System",
+    responses(
+        (status = 200, description = "Event kinds, tags and content schemas this instance publishes, for clients talking to relays directly", body = protocol::Protocol)
+    )
+)]
+async fn get_protocol(data: web::Data<AppState>) -> HttpResponse {
+    let authors = data.authors.keys().map(|key| key.to_hex()).collect();
+    HttpResponse::Ok().json(protocol::Protocol::describe(authors, data.classifieds_enabled))
+}
+
+#[derive(Deserialize, IntoParams)]
+struct DeliveryQuery {
+    status: Option<String>,
+    /// Include read-only deliveries from federated marketplaces (default true)
+    include_federated: Option<bool>,
+    /// Comma-separated top-level fields to return, e.g. id,status,offer_amount,pickup
+    fields: Option<String>,
+    /// metric or imperial: adds display_distance and package display_weight (defaults to the
+    /// signed-in caller's unit_system setting)
+    units: Option<settings::UnitSystem>,
+}
+
+#[derive(Deserialize, IntoParams)]
+struct UnitsQuery {
+    /// metric or imperial: adds display_distance and package display_weight (defaults to the
+    /// signed-in caller's unit_system setting)
+    units: Option<settings::UnitSystem>,
+}
+
+// How deliveries are shown to a caller: units from `units=` or the signed-in caller's settings,
+// and the language of `status_label` from their settings or Accept-Language
+struct Presentation {
+    units: Option<settings::UnitSystem>,
+    language: i18n::Language,
+}
+
+impl Presentation {
+    async fn for_request(data: &AppState, http: &HttpRequest, units: Option<settings::UnitSystem>, user: Option<AuthenticatedUser>) -> Self {
+        let user_settings = match user.map(|user| PubkeyId::from(user.pubkey).npub()) {
+            Some(npub) => data.get_user_settings(&npub).await.ok(),
+            None => None,
+        };
+        let accepted = http.headers()
+            .get(actix_web::http::header::ACCEPT_LANGUAGE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(i18n::Language::from_accept_language);
+
+        Presentation {
+            units: units.or(user_settings.as_ref().map(|s| s.unit_system)),
+            language: user_settings.and_then(|s| s.language).or(accepted).unwrap_or_default(),
+        }
+    }
+
+    // Add `status_label` and, when units were chosen, the display conversions
+    fn apply(&self, delivery: &mut serde_json::Value) {
+        let status = delivery.get("status").and_then(|s| serde_json::from_value::<DeliveryStatus>(s.clone()).ok());
+        if let (Some(status), Some(object)) = (status, delivery.as_object_mut()) {
+            object.insert("status_label".to_string(), i18n::status_label(&status, self.language).into());
+        }
+        if let Some(system) = self.units {
+            units::annotate_delivery(delivery, system);
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/deliveries",
+    tag = "Deliveries",
+    params(DeliveryQuery),
+    responses(
+        (status = 200, description = "Deliveries matching the filter", body = [DeliveryRequest])
+    )
+)]
+async fn get_deliveries(
+    http: HttpRequest,
+    data: web::Data<AppState>,
+    query: web::Query<DeliveryQuery>,
+    user: Option<AuthenticatedUser>,
+) -> Result<HttpResponse, Error> {
+    let mut deliveries = data.get_all_deliveries().await
+        .map_err(backend_error)?;
+
+    if query.include_federated.unwrap_or(true) {
+        deliveries.extend(data.federated_deliveries().await);
+    }
+
+    let viewer = Viewer::for_deliveries(&data, user.as_ref(), &deliveries).await?;
+    deliveries.retain(|d| viewer.can_see(d));
+
+    let filtered: Vec<DeliveryRequest> = if let Some(status) = &query.status {
+        deliveries.into_iter()
+            .filter(|d| {
+                let d_status = format!("{:?}", d.status).to_lowercase();
+                d_status == status.to_lowercase()
+            })
+            .collect()
+    } else {
+        // Drafts and deleted deliveries are only listed when asked for explicitly
+        deliveries.into_iter()
+            .filter(|d| !matches!(d.status, DeliveryStatus::Draft | DeliveryStatus::Deleted))
+            .collect()
+    };
+
+    let fields = fieldsets::parse(query.fields.as_deref());
+    let mut projected = fieldsets::project(&filtered, fields.as_deref());
+    let presentation = Presentation::for_request(&data, &http, query.units, user).await;
+    projected.iter_mut().for_each(|d| presentation.apply(d));
+    Ok(HttpResponse::Ok().json(projected))
+}
+
+#[derive(Deserialize, IntoParams)]
+struct SyncQuery {
+    /// Unix timestamp; returns changes after it (use next_since from the previous response)
+    since: i64,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/sync",
+    tag = "Deliveries",
+    params(SyncQuery),
+    responses(
+        (status = 200, description = "Deliveries, bids and status changes since the given time, including deletions", body = sync::SyncResponse)
+    )
+)]
+async fn get_sync(
+    data: web::Data<AppState>,
+    query: web::Query<SyncQuery>,
+    user: Option<AuthenticatedUser>,
+) -> Result<HttpResponse, Error> {
+    if data.index.read().await.indexed_at == 0 {
+        data.refresh_index().await
+            .map_err(backend_error)?;
+    }
+
+    let viewer = user.as_ref().map(AuthenticatedUser::npub);
+    let index = data.index.read().await;
+    let visible = index.deliveries.iter()
+        .filter(|d| d.status != DeliveryStatus::Draft && d.visible_to(viewer.as_deref()));
+    Ok(HttpResponse::Ok().json(sync::SyncResponse::build(&index.changes, visible, query.since, index.indexed_at)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/deliveries/{id}",
+    tag = "Deliveries",
+    params(
+        ("id" = String, Path, description = "Delivery ID"),
+        UnitsQuery,
+    ),
+    responses(
+        (status = 200, description = "Delivery", body = DeliveryRequest),
+        (status = 404, description = "Delivery not found")
+    )
+)]
+async fn get_delivery(
+    http: HttpRequest,
+    data: web::Data<AppState>,
+    id: web::Path<String>,
+    query: web::Query<UnitsQuery>,
+    user: Option<AuthenticatedUser>,
+) -> Result<HttpResponse, Error> {
+    let delivery = match data.get_delivery_by_id(&id).await
+        .map_err(backend_error)? {
+        Some(delivery) => Some(delivery),
+        None => data.get_federated_delivery(&id).await,
+    };
+    let viewer = Viewer::for_deliveries(&data, user.as_ref(), delivery.as_slice()).await?;
+
+    if let Some(delivery) = delivery.filter(|d| viewer.can_see(d)) {
+        let mut value = serde_json::to_value(&delivery).unwrap_or_default();
+        Presentation::for_request(&data, &http, query.units, user).await.apply(&mut value);
+        Ok(HttpResponse::Ok().json(value))
+    } else {
+        Ok(problem_response(Problem::new(ProblemType::NotFound, "Delivery not found").for_delivery(&id)))
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+struct PollQuery {
+    /// Unix timestamp; returns events after it (default now, i.e. wait for the next one)
+    since: Option<i64>,
+    /// Seconds to hold the connection (default and maximum 30)
+    timeout: Option<u64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/deliveries/{id}/poll",
+    tag = "Deliveries",
+    params(
+        ("id" = String, Path, description = "Delivery ID"),
+        PollQuery
+    ),
+    responses(
+        (status = 200, description = "New events and the current delivery, or no events if the wait timed out", body = sync::PollResponse),
+        (status = 404, description = "Delivery not found")
+    )
+)]
+async fn poll_delivery(
+    data: web::Data<AppState>,
+    id: web::Path<String>,
+    query: web::Query<PollQuery>,
+    user: Option<AuthenticatedUser>,
+) -> Result<HttpResponse, Error> {
+    visible_delivery(&data, &id, user.as_ref()).await?;
+
+    let since = query.since.unwrap_or_else(|| Utc::now().timestamp());
+    let wait = Duration::from_secs(query.timeout.unwrap_or(sync::MAX_LONG_POLL_SECS).min(sync::MAX_LONG_POLL_SECS));
+
+    let events = data.wait_for_delivery_events(&id, since, wait).await
+        .map_err(backend_error)?;
+
+    let delivery = if events.is_empty() {
+        None
+    } else {
+        data.get_delivery_by_id(&id).await
+            .map_err(backend_error)?
+    };
+
+    Ok(HttpResponse::Ok().json(sync::PollResponse {
+        delivery_id: id.into_inner(),
+        next_since: events.iter().map(|e| e.created_at.as_u64() as i64).max().unwrap_or(since),
+        events: events.iter().map(sync::EventSummary::from).collect(),
+        delivery,
+    }))
+}
+
+async fn save_recipient_contact(data: &AppState, delivery_id: &str, contact: &notify::RecipientContact) -> Result<(), Error> {
+    if !contact.is_empty() {
+        data.save_recipient_contact(delivery_id, contact).await
+            .map_err(backend_error)?;
+    }
+    Ok(())
+}
+
+fn invalid_email_response(address: Option<&str>) -> Option<HttpResponse> {
+    address
+        .filter(|address| !email::is_valid_address(address))
+        .map(|address| problem_response(Problem::new(ProblemType::InvalidRequest, format!("'{}' is not a valid email address", address))))
+}
+
+// Checks shared by the create endpoints; returns the error response for a rejected delivery
+async fn validate_new_delivery(data: &AppState, delivery: &mut DeliveryRequest) -> Result<Option<HttpResponse>, Error> {
+    Ok(data.new_delivery_problem(delivery).await
+        .map_err(backend_error)?
+        .map(problem_response))
+}
+
+// Replace the stops given by address book ID with the sender's saved locations
+async fn use_saved_addresses(data: &AppState, delivery: &mut DeliveryRequest, ids: [Option<String>; 2]) -> Result<Option<HttpResponse>, Error> {
+    if ids.iter().all(Option::is_none) {
+        return Ok(None);
+    }
+
+    let addresses = data.get_user_settings(&PubkeyId::normalize(&delivery.sender)).await
+        .map_err(backend_error)?
+        .addresses;
+    for ((stop, location), id) in [("pickup", &mut delivery.pickup), ("dropoff", &mut delivery.dropoff)].into_iter().zip(ids) {
+        let Some(id) = id else {
+            continue;
+        };
+        match addresses.iter().find(|address| address.id == id) {
+            Some(saved) => *location = saved.location.clone(),
+            None => return Ok(Some(problem_response(Problem::new(ProblemType::InvalidRequest, format!("The sender has no saved address '{}' for the {}", id, stop))))),
+        }
+    }
+    Ok(None)
+}
+
+// Key presented in `X-Api-Key` for a permission. Requests without one are handled as before;
+// an unknown or revoked key, or one lacking the permission, is refused.
+async fn api_key_for(http: &HttpRequest, data: &AppState, permission: api_keys::Permission) -> Result<Option<api_keys::ApiKey>, Error> {
+    let Some(secret) = http.headers().get(api_keys::HEADER).and_then(|v| v.to_str().ok()) else {
+        return Ok(None);
+    };
+
+    let key = data.find_api_key(secret).await
+        .map_err(backend_error)?
+        .ok_or_else(|| problem_error(Problem::new(ProblemType::Unauthorized, "Invalid or revoked API key")))?;
+
+    if !key.allows(permission) {
+        return Err(problem_error(Problem::new(ProblemType::Forbidden, "API key lacks the required permission")));
+    }
+    Ok(Some(key))
+}
+
+// Deliveries created with an API key belong to the key's organization
+// True when the request carries an API key
+async fn apply_api_key(http: &HttpRequest, data: &AppState, delivery: &mut DeliveryRequest) -> Result<bool, Error> {
+    let Some(key) = api_key_for(http, data, api_keys::Permission::CreateDeliveries).await? else {
+        return Ok(false);
+    };
+
+    if delivery.organization.as_ref().is_some_and(|org| org != &key.organization) {
+        return Err(problem_error(Problem::new(ProblemType::Forbidden, "API key is scoped to another organization")));
+    }
+    delivery.organization = Some(key.organization);
+    Ok(true)
+}
+
+// The sender must be the caller, signed in or signing the request with NIP-98. An organization's
+// API key speaks for its members instead; membership is checked in validate_new_delivery.
+async fn check_sender_identity(http: &HttpRequest, data: &AppState, sender: &str, api_key: bool) -> Result<(), Error> {
+    let sender = PubkeyId::parse(sender)
+        .map_err(|e| problem_error(Problem::new(ProblemType::InvalidRequest, format!("Invalid sender: {}", e))))?;
+
+    if !http.headers().contains_key(actix_web::http::header::AUTHORIZATION) {
+        if api_key || !data.auth.require_sender_auth {
+            return Ok(());
+        }
+        return Err(problem_error(Problem::new(ProblemType::Unauthorized, "Sign the request with NIP-98 or sign in through /api/auth to create deliveries")));
+    }
+
+    let caller = <AuthenticatedUser as actix_web::FromRequest>::extract(http).await?;
+    if caller.pubkey != *sender {
+        return Err(problem_error(Problem::new(ProblemType::Forbidden, "Sender does not match the authenticated caller")));
+    }
+    Ok(())
+}
+
+// Changes made with an API key are limited to the key's organization's deliveries
+async fn check_api_key_update(http: &HttpRequest, data: &AppState, delivery: &DeliveryRequest) -> Result<(), Error> {
+    let key = api_key_for(http, data, api_keys::Permission::UpdateDeliveries).await?;
+    if key.is_some_and(|key| delivery.organization.as_ref() != Some(&key.organization)) {
+        return Err(problem_error(Problem::new(ProblemType::Forbidden, "API key is scoped to another organization").for_delivery(&delivery.id)));
+    }
+    Ok(())
+}
+
+// Edits, deletions and publishing come from the sender or a member of the delivery's organization,
+// signed in, or with an API key of that organization: the people a draft is shown to. Like
+// creation, SENDER_AUTH=optional lets unauthenticated requests through.
+async fn check_sender_change(http: &HttpRequest, data: &AppState, delivery: &DeliveryRequest) -> Result<(), Error> {
+    if let Some(key) = api_key_for(http, data, api_keys::Permission::UpdateDeliveries).await? {
+        if delivery.organization.as_ref() != Some(&key.organization) {
+            return Err(problem_error(Problem::new(ProblemType::Forbidden, "API key is scoped to another organization").for_delivery(&delivery.id)));
+        }
+        return Ok(());
+    }
+    if !http.headers().contains_key(actix_web::http::header::AUTHORIZATION) && !data.auth.require_sender_auth {
+        return Ok(());
+    }
+
+    let caller = <AuthenticatedUser as actix_web::FromRequest>::extract(http).await?;
+    if caller.is(&delivery.sender) {
+        return Ok(());
+    }
+    if let Some(org_id) = &delivery.organization {
+        let org = data.get_organization(org_id).await
+            .map_err(backend_error)?;
+        if org.is_some_and(|org| org.role_of(&caller.npub()).is_some()) {
+            return Ok(());
+        }
+    }
+    Err(problem_error(Problem::new(ProblemType::Forbidden, "Only the sender or a member of the delivery's organization can change it").for_delivery(&delivery.id)))
+}
+
+#[derive(Clone, Serialize, Deserialize, ToSchema)]
+struct CreateDeliveryRequest {
+    #[serde(default)]
+    pickup: Location,
+    #[serde(default)]
+    dropoff: Location,
+    // Entries of the sender's address book to use instead of `pickup`/`dropoff`
+    #[serde(default)]
+    pickup_address_id: Option<String>,
+    #[serde(default)]
+    dropoff_address_id: Option<String>,
+    packages: Vec<PackageInfo>,
+    offer_amount: Amount,
+    insurance_amount: Option<Amount>,
+    time_window: String,
+    sender: String,
+    #[serde(default)]
+    min_courier_reputation: Option<f32>,
+    #[serde(default)]
+    min_completed_deliveries: Option<u32>,
+    #[serde(default)]
+    allowed_couriers: Vec<String>,
+    #[serde(default)]
+    private: bool,
+    // Organization the sender is dispatching for (sender must be an owner or dispatcher)
+    #[serde(default)]
+    organization: Option<String>,
+    // Emailed when the package is picked up and delivered; stored encrypted, not on the delivery
+    #[serde(default)]
+    recipient_email: Option<String>,
+    // Texted when the courier is close; stored encrypted, not on the delivery
+    #[serde(default)]
+    recipient_phone: Option<String>,
+    // Hashes of photos of the packages from POST /api/v1/uploads
+    #[serde(default)]
+    package_photos: Vec<String>,
+    // IANA zone, e.g. "America/New_York"; time window bounds without an offset are local to it
+    #[serde(default)]
+    timezone: Option<String>,
+    // Keep the delivery as a draft, hidden from couriers until POST /api/v1/deliveries/{id}/publish
+    #[serde(default)]
+    draft: bool,
+    // Re-list as a new attempt if the delivery fails after acceptance
+    #[serde(default)]
+    auto_relist: bool,
+}
+
+impl CreateDeliveryRequest {
+    // Recipient contact details, checked before anything is published
+    fn recipient_contact(&self) -> Result<notify::RecipientContact, String> {
+        let email = self.recipient_email.as_deref().map(str::trim).filter(|e| !e.is_empty());
+        if let Some(address) = email.filter(|a| !email::is_valid_address(a)) {
+            return Err(format!("'{}' is not a valid email address", address));
+        }
+        let phone = self.recipient_phone.as_deref().map(str::trim).filter(|p| !p.is_empty());
+        if let Some(phone) = phone.filter(|p| !notify::is_valid_phone(p)) {
+            return Err(format!("'{}' is not a valid phone number", phone));
+        }
+        Ok(notify::RecipientContact { email: email.map(String::from), phone: phone.map(String::from) })
+    }
+
+    fn saved_address_ids(&self) -> [Option<String>; 2] {
+        [self.pickup_address_id.clone(), self.dropoff_address_id.clone()]
+    }
+
+    // Build a new open delivery from the request
+    fn into_delivery(self) -> DeliveryRequest {
+        let distance = if let (Some(p1), Some(p2)) = (&self.pickup.coordinates, &self.dropoff.coordinates) {
+            Some(calculate_distance(p1, p2))
+        } else {
+            None
+        };
+
+        DeliveryRequest {
+            id: unique_timestamp_id("delivery"),
+            sender: self.sender,
+            pickup: self.pickup,
+            dropoff: self.dropoff,
+            packages: self.packages,
+            // Checked against the upload store and given their URLs in validate_new_delivery
+            package_photos: self.package_photos.into_iter()
+                .map(|hash| PackagePhoto { sha256: hash.trim().to_lowercase(), url: String::new() })
+                .collect(),
+            offer_amount: self.offer_amount,
+            insurance_amount: self.insurance_amount,
+            time_window: self.time_window,
+            timezone: self.timezone.map(|zone| zone.trim().to_string()).filter(|zone| !zone.is_empty()),
+            expires_at: Some(Utc::now().timestamp() + 604800),
+            status: if self.draft { DeliveryStatus::Draft } else { DeliveryStatus::Open },
+            bids: vec![],
+            accepted_bid: None,
+            accepted_at: None,
+            listed_amount: None,
+            created_at: Utc::now().timestamp(),
+            distance_meters: distance,
+            proof_of_delivery: None,
+            sender_feedback: None,
+            sender_rating: None,
+            sender_ratings: None,
+            courier_ratings: None,
+            recipient_confirmation: None,
+            completed_at: None,
+            picked_up_at: None,
+            disputed_at: None,
+            min_courier_reputation: self.min_courier_reputation,
+            min_completed_deliveries: self.min_completed_deliveries,
+            allowed_couriers: self.allowed_couriers,
+            private: self.private,
+            organization: self.organization,
+            assignment: None,
+            out_of_service_area: false,
+            deleted_at: None,
+            deleted_from: None,
+            auto_relist: self.auto_relist,
+            relisted_from: None,
+            relisted_as: None,
+            failed_attempts: vec![],
+            returning: false,
+            no_shows: vec![],
+            fraud_flags: vec![],
+            origin: None,
+            sealed_contacts: None,
+            contact_reveal: None,
+            sealed_instructions: None,
+            instructions_reveal: None,
+            local_times: None,
+        }
+    }
+}
+
+// Everything creating a delivery checks, short of publishing it: recipient contact, saved
+// addresses, API key scope and validate_new_delivery. Refusals come back as problem errors.
+async fn prepare_delivery(http: &HttpRequest, data: &AppState, req: CreateDeliveryRequest) -> Result<(DeliveryRequest, notify::RecipientContact), Error> {
+    let contact = req.recipient_contact()
+        .map_err(|e| problem_error(Problem::new(ProblemType::InvalidRequest, e)))?;
+    let saved_addresses = req.saved_address_ids();
+    let mut delivery = req.into_delivery();
+
+    let api_key = apply_api_key(http, data, &mut delivery).await?;
+    check_sender_identity(http, data, &delivery.sender, api_key).await?;
+    let rejection = match use_saved_addresses(data, &mut delivery, saved_addresses).await? {
+        Some(rejection) => Some(rejection),
+        None => validate_new_delivery(data, &mut delivery).await?,
+    };
+    if let Some(response) = rejection {
+        return Err(actix_web::error::InternalError::from_response("Delivery rejected", response).into());
+    }
+    Ok((delivery, contact))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/deliveries/quote",
+    tag = "Deliveries",
+    request_body = CreateDeliveryRequest,
+    responses(
+        (status = 200, description = "Distance, travel time and suggested price, with a token that creates the delivery as quoted", body = quotes::Quote),
+        (status = 400, description = "The delivery would be refused")
+    )
+)]
+async fn quote_delivery(
+    http: HttpRequest,
+    data: web::Data<AppState>,
+    req: web::Json<CreateDeliveryRequest>,
+) -> Result<HttpResponse, Error> {
+    let mut req = req.into_inner();
+    let (delivery, _) = prepare_delivery(&http, &data, req.clone()).await?;
+
+    let sats_per_km = data.index.read().await.market_stats.windows.get("30d")
+        .and_then(|window| window.median_accepted_price_per_km);
+    let suggested_amount = quotes::suggested_amount(delivery.distance_meters, sats_per_km);
+
+    // The token carries the request as validated: stops resolved and the window in UTC
+    req.pickup = delivery.pickup;
+    req.dropoff = delivery.dropoff;
+    req.pickup_address_id = None;
+    req.dropoff_address_id = None;
+    req.time_window = delivery.time_window;
+    if req.offer_amount.is_zero() {
+        req.offer_amount = suggested_amount;
+    }
+
+    let expires_at = Utc::now().timestamp() + quotes::QUOTE_TTL_SECS;
+    Ok(HttpResponse::Ok().json(quotes::Quote {
+        quote_token: data.seal_quote(&req, expires_at).map_err(backend_error)?,
+        expires_at,
+        distance_meters: delivery.distance_meters,
+        eta_secs: quotes::eta_secs(delivery.distance_meters),
+        suggested_amount,
+        offer_amount: req.offer_amount,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/deliveries",
+    tag = "Deliveries",
+    request_body(content = CreateDeliveryRequest, description = "The delivery, or `{\"quote_token\": \"...\"}` from POST /api/v1/deliveries/quote to create it exactly as quoted"),
+    responses(
+        (status = 200, description = "Delivery created", body = Object),
+        (status = 400, description = "Invalid delivery or quote token"),
+        (status = 410, description = "Quote has expired")
+    )
+)]
+async fn create_delivery(
+    http: HttpRequest,
+    data: web::Data<AppState>,
+    req: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, Error> {
+    let req = match req.get("quote_token").and_then(serde_json::Value::as_str) {
+        Some(token) => data.open_quote::<CreateDeliveryRequest>(token).map_err(|e| match e {
+            quotes::QuoteError::Invalid => problem_error(Problem::new(ProblemType::InvalidRequest, "Invalid quote token")),
+            quotes::QuoteError::Expired => problem_error(Problem::new(ProblemType::Gone, "Quote has expired; request a new one")),
+        })?,
+        None => serde_json::from_value(req.into_inner())
+            .map_err(|e| problem_error(Problem::new(ProblemType::InvalidRequest, e.to_string())))?,
+    };
+    let (delivery, contact) = prepare_delivery(&http, &data, req).await?;
+    let id = delivery.id.clone();
+
+    data.delivery_service().create(&delivery).await
+        .map_err(|e| service_error(e, &id))?;
+    save_recipient_contact(&data, &id, &contact).await?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "id": id,
+        "status": "created",
+        "recipient_tracking_url": data.recipient_tracking_url(&id),
+        "delivery": delivery
+    })))
+}
+
+// When the courier expects to pick up and drop off: Unix timestamps, or minutes from now
+#[derive(Deserialize, ToSchema)]
+struct BidEtas {
+    pickup_eta: Option<i64>,
+    dropoff_eta: Option<i64>,
+    pickup_eta_minutes: Option<u32>,
+    dropoff_eta_minutes: Option<u32>,
+}
+
+impl BidEtas {
+    fn resolve(&self, now: i64) -> (Option<i64>, Option<i64>) {
+        let at = |timestamp: Option<i64>, minutes: Option<u32>| timestamp.or(minutes.map(|m| now + m as i64 * 60));
+        (at(self.pickup_eta, self.pickup_eta_minutes), at(self.dropoff_eta, self.dropoff_eta_minutes))
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+struct PlaceBidRequest {
+    courier: PubkeyId,
+    amount: Amount,
+    #[serde(flatten)]
+    etas: BidEtas,
+    message: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/deliveries/{id}/bid",
+    tag = "Bids",
+    params(
+        ("id" = String, Path, description = "Delivery ID"),
+    ),
+    request_body = PlaceBidRequest,
+    responses(
+        (status = 200, description = "Bid placed, with any accepted deliveries it overlaps", body = Object),
+        (status = 404, description = "Delivery not found"),
+        (status = 409, description = "Bid overlaps an accepted delivery (BID_CONFLICT_MODE=reject)")
+    )
+)]
+async fn place_bid(
+    data: web::Data<AppState>,
+    delivery_id: web::Path<String>,
+    req: web::Json<PlaceBidRequest>,
+) -> Result<HttpResponse, Error> {
+    let courier = req.courier.npub();
+    // Verify delivery exists
+    let delivery = data.get_delivery_by_id(&delivery_id).await
+        .map_err(backend_error)?;
+
+    let Some(delivery) = delivery else {
+        return Ok(problem_response(Problem::new(ProblemType::NotFound, "Delivery not found").for_delivery(&delivery_id)));
+    };
+
+    let now = Utc::now().timestamp();
+    let (Some(pickup_eta), Some(dropoff_eta)) = req.etas.resolve(now) else {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, "A bid needs pickup_eta and dropoff_eta, as timestamps or in minutes").for_delivery(&delivery_id)));
+    };
+
+    // Reputation is filled in from the courier's profile; overlapping an accepted delivery makes
+    // it likely one of them gets cancelled later, so conflicts are returned with the bid
+    let bid = DeliveryBid {
+        id: unique_timestamp_id("bid"),
+        courier: courier.clone(),
+        amount: req.amount,
+        pickup_eta: Some(pickup_eta),
+        dropoff_eta: Some(dropoff_eta),
+        reputation: 0.0,
+        completed_deliveries: 0,
+        recent_rating: None,
+        ratings: None,
+        message: req.message.clone(),
+        created_at: now,
+        stale: false,
+    };
+    let (bid, conflicts) = data.delivery_service().bid(&delivery, bid, now).await
+        .map_err(|e| service_error(e, &delivery_id))?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "status": "bid_placed",
+        "bid": bid,
+        "conflicts": conflicts
+    })))
+}
+
+#[derive(Deserialize, IntoParams)]
+struct BidsQuery {
+    /// eta (default), price or reputation; stale bids always come last
+    sort: Option<BidOrder>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct RankedBid {
+    // Position in the delivery's `bids`, as used by the accept route
+    index: usize,
+    bid: DeliveryBid,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/deliveries/{id}/bids",
+    tag = "Bids",
+    params(
+        ("id" = String, Path, description = "Delivery ID"),
+        BidsQuery,
+    ),
+    responses(
+        (status = 200, description = "The delivery's bids, best first", body = [RankedBid]),
+        (status = 404, description = "Delivery not found")
+    )
+)]
+async fn get_bids(
+    data: web::Data<AppState>,
+    delivery_id: web::Path<String>,
+    query: web::Query<BidsQuery>,
+    user: Option<AuthenticatedUser>,
+) -> Result<HttpResponse, Error> {
+    let delivery = visible_delivery(&data, &delivery_id, user.as_ref()).await?;
+
+    let ranked: Vec<RankedBid> = delivery.ranked_bids(query.sort.unwrap_or_default())
+        .into_iter()
+        .map(|index| RankedBid { index, bid: delivery.bids[index].clone() })
+        .collect();
+    Ok(HttpResponse::Ok().json(ranked))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/deliveries/{id}/accept/{bid_idx}",
+    tag = "Bids",
+    params(
+        ("id" = String, Path, description = "Delivery ID"),
+        ("bid_idx" = usize, Path, description = "Index of the bid to accept"),
+    ),
+    responses(
+        (status = 200, description = "Bid accepted", body = Object),
+        (status = 400, description = "Invalid bid index"),
+        (status = 404, description = "Delivery not found")
+    )
+)]
+async fn accept_bid(
+    data: web::Data<AppState>,
+    path: web::Path<(String, usize)>,
+) -> Result<HttpResponse, Error> {
+    let (delivery_id, bid_index) = path.into_inner();
+
+    let delivery = data.get_delivery_by_id(&delivery_id).await
+        .map_err(backend_error)?
+        .ok_or_else(|| delivery_not_found(&delivery_id))?;
+
+    let delivery = data.delivery_service().accept(delivery, bid_index, Utc::now().timestamp()).await
+        .map_err(|e| service_error(e, &delivery_id))?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "status": "accepted",
+        "delivery": delivery
+    })))
+}
+
+#[derive(Deserialize, ToSchema)]
+struct AssignCourierRequest {
+    courier: PubkeyId,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/deliveries/{id}/assign",
+    tag = "Organizations",
+    params(
+        ("id" = String, Path, description = "Delivery ID"),
+    ),
+    request_body = AssignCourierRequest,
+    responses(
+        (status = 200, description = "Courier assigned, pending acknowledgement", body = Object),
+        (status = 400, description = "Delivery is not open or not attributed to an organization"),
+        (status = 401, description = "Missing or invalid session token"),
+        (status = 403, description = "Not a dispatcher, or courier not in the fleet"),
+        (status = 404, description = "Delivery not found")
+    )
+)]
+async fn assign_courier(
+    user: AuthenticatedUser,
+    data: web::Data<AppState>,
+    delivery_id: web::Path<String>,
+    req: web::Json<AssignCourierRequest>,
+) -> Result<HttpResponse, Error> {
+    let courier = req.courier.npub();
+    let dispatcher = PubkeyId::from(user.pubkey).npub();
+    let mut delivery = data.get_delivery_by_id(&delivery_id).await
+        .map_err(backend_error)?
+        .ok_or_else(|| delivery_not_found(&delivery_id))?;
+
+    if delivery.status != DeliveryStatus::Open {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidTransition, "Only open deliveries can be assigned").for_delivery(&delivery_id)));
+    }
+
+    let Some(org_id) = delivery.organization.clone() else {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, "Only organization deliveries can be assigned").for_delivery(&delivery_id)));
+    };
+    let org = find_organization(&data, &org_id).await?;
+
+    if !org.allows(&dispatcher, rbac::Permission::AssignCouriers) {
+        return Ok(problem_response(Problem::new(ProblemType::Forbidden, "Only the organization's dispatchers can assign couriers").for_delivery(&delivery_id)));
+    }
+    if !org.is_courier(&courier) || !delivery.allows_courier(&courier) {
+        return Ok(problem_response(Problem::new(ProblemType::Forbidden, "Courier is not in this organization's fleet").for_delivery(&delivery_id)));
+    }
+
+    let assignment = organizations::Assignment {
+        courier: courier.clone(),
+        dispatcher,
+        assigned_at: Utc::now().timestamp(),
+    };
+    delivery.assignment = Some(assignment.clone());
+
+    data.publish_delivery(&delivery).await
+        .map_err(backend_error)?;
+    data.publish_assignment_event(&delivery.id, &assignment, "assigned").await
+        .map_err(backend_error)?;
+
+    let amount = delivery.offer_amount.to_string();
+    let url = data.tracking_url(&delivery.id);
+    data.notify_courier(&assignment.courier, |language| push::PushMessage {
+        title: i18n::Message::AssignedTitle.text(language).to_string(),
+        body: i18n::Message::AssignedBody.render(language, &[
+            ("pickup", &delivery.pickup.address),
+            ("dropoff", &delivery.dropoff.address),
+            ("amount", &amount),
+        ]),
+        url: url.clone(),
+    }).await;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "status": "assigned",
+        "delivery": delivery
+    })))
+}
+
+#[derive(Deserialize, ToSchema)]
+struct AcknowledgeAssignmentRequest {
+    accept: bool,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/deliveries/{id}/assignment/acknowledge",
+    tag = "Organizations",
+    params(
+        ("id" = String, Path, description = "Delivery ID"),
+    ),
+    request_body = AcknowledgeAssignmentRequest,
+    responses(
+        (status = 200, description = "Assignment accepted (delivery Accepted) or declined (delivery stays open)", body = Object),
+        (status = 400, description = "No pending assignment"),
+        (status = 401, description = "Missing or invalid session token"),
+        (status = 403, description = "Assignment belongs to another courier"),
+        (status = 404, description = "Delivery not found")
+    )
+)]
+async fn acknowledge_assignment(
+    user: AuthenticatedUser,
+    data: web::Data<AppState>,
+    delivery_id: web::Path<String>,
+    req: web::Json<AcknowledgeAssignmentRequest>,
+) -> Result<HttpResponse, Error> {
+    let mut delivery = data.get_delivery_by_id(&delivery_id).await
+        .map_err(backend_error)?
+        .ok_or_else(|| delivery_not_found(&delivery_id))?;
+
+    let Some(assignment) = delivery.assignment.take() else {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidTransition, "Delivery has no pending assignment").for_delivery(&delivery_id)));
+    };
+    if delivery.status != DeliveryStatus::Open {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidTransition, "Delivery is no longer open").for_delivery(&delivery_id)));
+    }
+    if !user.is(&assignment.courier) {
+        return Ok(problem_response(Problem::new(ProblemType::Forbidden, "Assignment belongs to another courier").for_delivery(&delivery_id)));
+    }
+    let courier = user.npub();
+
+    if !req.accept {
+        data.publish_delivery(&delivery).await
+            .map_err(backend_error)?;
+        data.publish_assignment_event(&delivery.id, &assignment, "declined").await
+            .map_err(backend_error)?;
+
+        return Ok(HttpResponse::Ok().json(serde_json::json!({
+            "status": "declined",
+            "delivery": delivery
+        })));
+    }
+
+    // Record the assignment as a bid at the offered price so acceptance follows the usual path
+    let mut courier_profile = data.get_user_profile(&courier).await
+        .unwrap_or_default();
+    data.refresh_reputation(&mut courier_profile).await;
+    let bid = DeliveryBid {
+        id: unique_timestamp_id("bid"),
+        courier: courier.clone(),
+        amount: delivery.offer_amount,
+        pickup_eta: None,
+        dropoff_eta: None,
+        reputation: courier_profile.reputation,
+        completed_deliveries: courier_profile.completed_deliveries,
+        recent_rating: courier_profile.recent_rating,
+        ratings: courier_profile.ratings,
+        message: Some(format!("Assigned by {}", assignment.dispatcher)),
+        created_at: Utc::now().timestamp(),
+        stale: false,
+    };
+    data.publish_bid(&delivery, &bid).await
+        .map_err(backend_error)?;
+
+    delivery.bids.push(bid);
+    let bid_index = delivery.bids.len() - 1;
+    let delivery = data.delivery_service().accept(delivery, bid_index, Utc::now().timestamp()).await
+        .map_err(|e| service_error(e, &delivery_id))?;
+
+    data.publish_assignment_event(&delivery.id, &assignment, "accepted").await
+        .map_err(backend_error)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "status": "accepted",
+        "delivery": delivery
+    })))
+}
+
+#[derive(Deserialize, ToSchema)]
+struct UpdateStatusRequest {
+    status: String,
+    // Read off the shipping label at pickup; required to start transit
+    pickup_code: Option<String>,
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/v1/deliveries/{id}/status",
+    tag = "Deliveries",
+    params(
+        ("id" = String, Path, description = "Delivery ID"),
+    ),
+    request_body = UpdateStatusRequest,
+    responses(
+        (status = 200, description = "Status updated", body = Object),
+        (status = 400, description = "Unknown status, a transition the delivery's status doesn't allow, or the courier has not been seen near the pickup"),
+        (status = 403, description = "Missing or invalid pickup code"),
+        (status = 404, description = "Delivery not found"),
+        (status = 409, description = "Delivery is held for fraud review")
+    )
+)]
+async fn update_delivery_status(
+    data: web::Data<AppState>,
+    delivery_id: web::Path<String>,
+    req: web::Json<UpdateStatusRequest>,
+) -> Result<HttpResponse, Error> {
+    let mut delivery = data.get_delivery_by_id(&delivery_id).await
+        .map_err(backend_error)?
+        .ok_or_else(|| delivery_not_found(&delivery_id))?;
+
+    let new_status = match req.status.to_lowercase().as_str() {
+        "accepted" => DeliveryStatus::Accepted,
+        "in_transit" | "intransit" => DeliveryStatus::InTransit,
+        "completed" => DeliveryStatus::Completed,
+        "confirmed" => DeliveryStatus::Confirmed,
+        other => {
+            return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, format!("Unknown status: {}", other)).for_delivery(&delivery_id)));
+        }
+    };
+    if let Some(problem) = service::status_change_error(&delivery, &new_status) {
+        return Ok(problem_response(problem.for_delivery(&delivery_id)));
+    }
+
+    // Starting transit requires the label's pickup code, and a recent ping near the pickup when configured
+    if new_status == DeliveryStatus::InTransit {
+        let code = req.pickup_code.as_deref().unwrap_or_default().trim();
+        if !codes::codes_match(&data.pickup_code(&delivery.id), code) {
+            return Ok(problem_response(Problem::new(ProblemType::Forbidden, "Missing or invalid pickup code").for_delivery(&delivery_id)));
+        }
+        if let (Some(rule), Some(pickup)) = (&data.pickup_proximity, &delivery.pickup.coordinates) {
+            let pings = data.get_location_pings(&delivery.id).await
+                .map_err(backend_error)?;
+            if let Err(reason) = rule.check(pickup, &pings, Utc::now().timestamp()) {
+                return Ok(problem_response(Problem::new(ProblemType::InvalidTransition, reason).for_delivery(&delivery_id)));
+            }
+        }
+    }
+
+    if new_status == DeliveryStatus::InTransit {
+        delivery.picked_up_at = Some(Utc::now().timestamp());
+    }
+    delivery.status = new_status.clone();
+
+    // Publish updated delivery
+    data.publish_delivery(&delivery).await
+        .map_err(backend_error)?;
+
+    // Publish status update event
+    data.publish_status_update(&delivery, &DeliveryUpdate::new(new_status.clone(), Utc::now().timestamp())).await
+        .map_err(backend_error)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "status": "updated",
+        "delivery": delivery
+    })))
+}
+
+#[derive(Deserialize, ToSchema)]
+struct ConfirmDeliveryRequest {
+    // Deprecated single score, applied to every dimension when ratings is absent
+    rating: Option<f32>,
+    ratings: Option<RatingBreakdown>,
+    feedback: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/deliveries/{id}/confirm",
+    tag = "Deliveries",
+    params(
+        ("id" = String, Path, description = "Delivery ID"),
+    ),
+    request_body = ConfirmDeliveryRequest,
+    responses(
+        (status = 200, description = "Delivery confirmed", body = Object),
+        (status = 400, description = "Rating out of range"),
+        (status = 404, description = "Delivery not found"),
+        (status = 409, description = "Delivery is held for fraud review")
+    )
+)]
+async fn confirm_delivery(
+    data: web::Data<AppState>,
+    delivery_id: web::Path<String>,
+    req: web::Json<ConfirmDeliveryRequest>,
+) -> Result<HttpResponse, Error> {
+    let delivery = data.get_delivery_by_id(&delivery_id).await
+        .map_err(backend_error)?
+        .ok_or_else(|| delivery_not_found(&delivery_id))?;
+
+    // The courier's reputation and earnings are updated with the confirmation
+    let (delivery, review_held) = data.delivery_service()
+        .confirm(delivery, req.rating, req.ratings, req.feedback.as_deref(), Utc::now().timestamp()).await
+        .map_err(|e| service_error(e, &delivery_id))?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "status": "confirmed",
+        "review_held": review_held,
+        "delivery": delivery
+    })))
+}
+
+#[derive(Deserialize, ToSchema)]
+struct RecipientConfirmRequest {
+    // The `token` from the recipient's tracking link
+    token: String,
+    // 1-5
+    rating: Option<f32>,
+    feedback: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/deliveries/{id}/recipient-confirm",
+    tag = "Deliveries",
+    params(
+        ("id" = String, Path, description = "Delivery ID"),
+    ),
+    request_body = RecipientConfirmRequest,
+    responses(
+        (status = 200, description = "Receipt confirmed by the recipient", body = Object),
+        (status = 400, description = "Package not delivered yet, or rating out of range"),
+        (status = 403, description = "Invalid tracking token"),
+        (status = 404, description = "Delivery not found"),
+        (status = 409, description = "The recipient already confirmed")
+    )
+)]
+async fn recipient_confirm(
+    data: web::Data<AppState>,
+    delivery_id: web::Path<String>,
+    req: web::Json<RecipientConfirmRequest>,
+) -> Result<HttpResponse, Error> {
+    let mut delivery = data.get_delivery_by_id(&delivery_id).await
+        .map_err(backend_error)?
+        .ok_or_else(|| delivery_not_found(&delivery_id))?;
+
+    if !codes::codes_match(&data.recipient_token(&delivery.id), req.token.trim()) {
+        return Ok(problem_response(Problem::new(ProblemType::Forbidden, "Invalid tracking token").for_delivery(&delivery_id)));
+    }
+    if delivery.status != DeliveryStatus::Completed && delivery.status != DeliveryStatus::Confirmed {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidTransition, "The package hasn't been delivered yet").for_delivery(&delivery_id)));
+    }
+    if delivery.recipient_confirmation.is_some() {
+        return Ok(problem_response(Problem::new(ProblemType::Conflict, "The recipient already confirmed receipt").for_delivery(&delivery_id)));
+    }
+    if req.rating.is_some_and(|rating| !(1.0..=5.0).contains(&rating)) {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, "Rating must be between 1 and 5").for_delivery(&delivery_id)));
+    }
+
+    let feedback = match &req.feedback {
+        Some(text) => data.moderate_review(&delivery.id, moderation::RECIPIENT_AUTHOR, text).await,
+        None => None,
+    };
+    let review_held = req.feedback.is_some() && feedback.is_none();
+
+    delivery.recipient_confirmation = Some(RecipientConfirmation {
+        confirmed_at: Utc::now().timestamp(),
+        rating: req.rating,
+        feedback,
+    });
+    data.publish_delivery(&delivery).await
+        .map_err(backend_error)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "status": "confirmed",
+        "review_held": review_held,
+        "delivery": delivery
+    })))
+}
+
+#[derive(Deserialize, ToSchema)]
+struct RateSenderRequest {
+    ratings: RatingBreakdown,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/deliveries/{id}/rate-sender",
+    tag = "Deliveries",
+    params(
+        ("id" = String, Path, description = "Delivery ID"),
+    ),
+    request_body = RateSenderRequest,
+    responses(
+        (status = 200, description = "Sender rated", body = Object),
+        (status = 400, description = "Delivery not completed, already rated, or rating out of range"),
+        (status = 401, description = "Missing or invalid session token"),
+        (status = 403, description = "Only the accepted courier may rate the sender"),
+        (status = 404, description = "Delivery not found")
+    )
+)]
+async fn rate_sender(
+    user: AuthenticatedUser,
+    data: web::Data<AppState>,
+    delivery_id: web::Path<String>,
+    req: web::Json<RateSenderRequest>,
+) -> Result<HttpResponse, Error> {
+    let mut delivery = data.get_delivery_by_id(&delivery_id).await
+        .map_err(backend_error)?
+        .ok_or_else(|| delivery_not_found(&delivery_id))?;
+
+    if !delivery.accepted_courier().is_some_and(|c| user.is(c)) {
+        return Ok(problem_response(Problem::new(ProblemType::Forbidden, "Only the accepted courier may rate the sender").for_delivery(&delivery_id)));
+    }
+    if !matches!(delivery.status, DeliveryStatus::Completed | DeliveryStatus::Confirmed) {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidTransition, "Delivery has not been completed").for_delivery(&delivery_id)));
+    }
+    if delivery.courier_ratings.is_some() {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidTransition, "Sender has already been rated for this delivery").for_delivery(&delivery_id)));
+    }
+    if let Err(reason) = req.ratings.validate() {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, reason).for_delivery(&delivery_id)));
+    }
+
+    delivery.courier_ratings = Some(req.ratings);
+
+    let mut sender = data.get_user_profile(&delivery.sender).await
+        .unwrap_or_else(|_| UserProfile {
+            npub: delivery.sender.clone(),
+            ..Default::default()
+        });
+    sender.ratings = Some(RatingBreakdown::accumulate(sender.ratings, sender.rating_count, req.ratings));
+    sender.rating_count += 1;
+
+    data.publish_user_profile(&sender).await
+        .map_err(backend_error)?;
+    data.publish_delivery(&delivery).await
+        .map_err(backend_error)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "status": "rated",
+        "delivery": delivery
+    })))
+}
+
+#[derive(Deserialize, ToSchema)]
+struct UpdateDeliveryRequest {
+    pickup: Option<Location>,
+    dropoff: Option<Location>,
+    packages: Option<Vec<PackageInfo>>,
+    offer_amount: Option<Amount>,
+    insurance_amount: Option<Amount>,
+    time_window: Option<String>,
+    #[serde(default)]
+    min_courier_reputation: Option<f32>,
+    #[serde(default)]
+    min_completed_deliveries: Option<u32>,
+    #[serde(default)]
+    allowed_couriers: Option<Vec<String>>,
+}
+
+impl UpdateDeliveryRequest {
+    // Apply the provided fields to an open delivery
+    fn apply(&self, delivery: &mut DeliveryRequest) {
+        if let Some(pickup) = self.pickup.clone() {
+            delivery.pickup = pickup;
+        }
+        if let Some(dropoff) = self.dropoff.clone() {
+            delivery.dropoff = dropoff;
+        }
+        if let Some(packages) = self.packages.clone() {
+            delivery.packages = packages;
+        }
+        if let Some(offer_amount) = self.offer_amount {
+            delivery.offer_amount = offer_amount;
+        }
+        if let Some(insurance_amount) = self.insurance_amount {
+            delivery.insurance_amount = Some(insurance_amount);
+        }
+        if let Some(time_window) = self.time_window.clone() {
+            delivery.time_window = time_window;
+        }
+        if let Some(min_reputation) = self.min_courier_reputation {
+            delivery.min_courier_reputation = Some(min_reputation);
+        }
+        if let Some(min_completed) = self.min_completed_deliveries {
+            delivery.min_completed_deliveries = Some(min_completed);
+        }
+        if let Some(allowed_couriers) = self.allowed_couriers.clone() {
+            delivery.allowed_couriers = allowed_couriers;
+        }
+
+        if let (Some(p1), Some(p2)) = (&delivery.pickup.coordinates, &delivery.dropoff.coordinates) {
+            delivery.distance_meters = Some(calculate_distance(p1, p2));
+        }
+    }
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/v1/deliveries/{id}",
+    tag = "Deliveries",
+    params(
+        ("id" = String, Path, description = "Delivery ID"),
+    ),
+    request_body = UpdateDeliveryRequest,
+    responses(
+        (status = 200, description = "Delivery updated", body = Object),
+        (status = 400, description = "Delivery is not open"),
+        (status = 401, description = "Sign-in required"),
+        (status = 403, description = "Not the sender or a member of the delivery's organization"),
+        (status = 404, description = "Delivery not found")
+    )
+)]
+async fn update_delivery(
+    http: HttpRequest,
+    data: web::Data<AppState>,
+    delivery_id: web::Path<String>,
+    req: web::Json<UpdateDeliveryRequest>,
+) -> Result<HttpResponse, Error> {
+    let mut delivery = data.get_delivery_by_id(&delivery_id).await
+        .map_err(backend_error)?
+        .ok_or_else(|| delivery_not_found(&delivery_id))?;
+
+    check_sender_change(&http, &data, &delivery).await?;
+    if !matches!(delivery.status, DeliveryStatus::Open | DeliveryStatus::Draft) {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidTransition, "Cannot update delivery that is not open").for_delivery(&delivery_id)));
+    }
+
+    let before = delivery.clone();
+    req.apply(&mut delivery);
+
+    if let Some(error) = delivery.allowlist_error() {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, error).for_delivery(&delivery_id)));
+    }
+    if req.pickup.is_some() || req.dropoff.is_some() {
+        if let Some(error) = data.resolve_stops(&mut delivery).await {
+            return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, error).for_delivery(&delivery_id)));
+        }
+        // The flag follows the stops, so a delivery moved back inside the area loses it
+        delivery.out_of_service_area = false;
+        if let Err(error) = data.check_service_area(&mut delivery).await {
+            return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, error).for_delivery(&delivery_id)));
+        }
+    }
+    if req.time_window.is_some() {
+        if let Some(error) = timezone::schedule_error(&mut delivery, Utc::now().timestamp()) {
+            return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, error).for_delivery(&delivery_id)));
+        }
+    }
+    delivery.local_times = Some(timezone::LocalTimes::for_delivery(&delivery));
+
+    // Publish updated delivery
+    let revision = data.publish_delivery_edit(&before, &mut delivery).await
+        .map_err(backend_error)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "status": "updated",
+        "revision": revision,
+        "delivery": delivery
+    })))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/deliveries/{id}/revisions",
+    tag = "Deliveries",
+    params(
+        ("id" = String, Path, description = "Delivery ID"),
+    ),
+    responses(
+        (status = 200, description = "Edits to the delivery with field-level diffs, oldest first", body = [revisions::DeliveryRevision]),
+        (status = 404, description = "Delivery not found")
+    )
+)]
+async fn get_delivery_revisions(
+    data: web::Data<AppState>,
+    delivery_id: web::Path<String>,
+    user: Option<AuthenticatedUser>,
+) -> Result<HttpResponse, Error> {
+    visible_delivery(&data, &delivery_id, user.as_ref()).await?;
+
+    let revisions = data.get_revisions(&delivery_id).await
+        .map_err(backend_error)?;
+
+    Ok(HttpResponse::Ok().json(revisions))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/deliveries/{id}/state",
+    tag = "Deliveries",
+    params(
+        ("id" = String, Path, description = "Delivery ID"),
+    ),
+    responses(
+        (status = 200, description = "Current state folded from the delivery's events, with its payment state", body = projection::DeliveryProjection),
+        (status = 404, description = "Delivery not found")
+    )
+)]
+async fn get_delivery_state(
+    data: web::Data<AppState>,
+    delivery_id: web::Path<String>,
+    user: Option<AuthenticatedUser>,
+) -> Result<HttpResponse, Error> {
+    let delivery = visible_delivery(&data, &delivery_id, user.as_ref()).await?;
+
+    // A delivery read before the projections were synced already has its updates applied
+    let state = data.projections.read().await.get(&delivery_id).cloned()
+        .unwrap_or_else(|| projection::DeliveryProjection::from_delivery(&delivery, delivery.created_at));
+
+    Ok(HttpResponse::Ok().json(state))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/deliveries/{id}/timeline",
+    tag = "Deliveries",
+    params(
+        ("id" = String, Path, description = "Delivery ID"),
+    ),
+    responses(
+        (status = 200, description = "Milestones from creation to confirmation, oldest first, each with its actor and source event", body = [timeline::Milestone]),
+        (status = 404, description = "Delivery not found")
+    )
+)]
+async fn get_delivery_timeline(
+    data: web::Data<AppState>,
+    delivery_id: web::Path<String>,
+    user: Option<AuthenticatedUser>,
+) -> Result<HttpResponse, Error> {
+    let delivery = visible_delivery(&data, &delivery_id, user.as_ref()).await?;
+
+    let events = data.fetch_delivery_events(&delivery_id).await
+        .map_err(backend_error)?;
+
+    Ok(HttpResponse::Ok().json(timeline::build(&delivery, &events)))
+}
+
+#[derive(Deserialize, ToSchema)]
+struct ReconfirmBidRequest {
+    // Optionally re-price the bid for the new terms
+    amount: Option<Amount>,
+    // Optionally new ETAs, e.g. for a changed time window
+    #[serde(flatten)]
+    etas: BidEtas,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/deliveries/{id}/bids/{bid_id}/reconfirm",
+    tag = "Bids",
+    params(
+        ("id" = String, Path, description = "Delivery ID"),
+        ("bid_id" = String, Path, description = "Bid ID"),
+    ),
+    request_body = ReconfirmBidRequest,
+    responses(
+        (status = 200, description = "Bid re-confirmed against the current terms", body = Object),
+        (status = 400, description = "Delivery is not open or the bid is not stale"),
+        (status = 401, description = "Missing or invalid session token"),
+        (status = 403, description = "Bid belongs to another courier"),
+        (status = 404, description = "Delivery or bid not found")
+    )
+)]
+async fn reconfirm_bid(
+    user: AuthenticatedUser,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+    req: web::Json<ReconfirmBidRequest>,
+) -> Result<HttpResponse, Error> {
+    let (delivery_id, bid_id) = path.into_inner();
+    let delivery = data.get_delivery_by_id(&delivery_id).await
+        .map_err(backend_error)?
+        .ok_or_else(|| delivery_not_found(&delivery_id))?;
+
+    if delivery.status != DeliveryStatus::Open {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidTransition, "Delivery is no longer open").for_delivery(&delivery_id)));
+    }
+
+    let mut bid = delivery.bids.iter()
+        .find(|b| b.id == bid_id)
+        .cloned()
+        .ok_or_else(|| problem_error(Problem::new(ProblemType::NotFound, "Bid not found").for_delivery(&delivery_id)))?;
+
+    if !user.is(&bid.courier) {
+        return Ok(problem_response(Problem::new(ProblemType::Forbidden, "Bid belongs to another courier").for_delivery(&delivery_id)));
+    }
+    if !bid.stale {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidTransition, "Bid is already current").for_delivery(&delivery_id)));
+    }
+
+    bid.stale = false;
+    if let Some(amount) = req.amount {
+        bid.amount = amount;
+    }
+
+    // Kept ETAs must still fit the (possibly edited) time window
+    let now = Utc::now().timestamp();
+    let (pickup_eta, dropoff_eta) = req.etas.resolve(now);
+    bid.pickup_eta = pickup_eta.or(bid.pickup_eta);
+    bid.dropoff_eta = dropoff_eta.or(bid.dropoff_eta);
+    if let (Some(pickup_eta), Some(dropoff_eta)) = (bid.pickup_eta, bid.dropoff_eta) {
+        if let Some(error) = timezone::eta_error(pickup_eta, dropoff_eta, &TimeWindow::parse(&delivery.time_window), now) {
+            return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, error).for_delivery(&delivery_id)));
+        }
+    }
+
+    data.publish_bid(&delivery, &bid).await
+        .map_err(backend_error)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "status": "reconfirmed",
+        "bid": bid
+    })))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/deliveries/{id}",
+    tag = "Deliveries",
+    params(
+        ("id" = String, Path, description = "Delivery ID"),
+    ),
+    responses(
+        (status = 200, description = "Delivery deleted", body = Object),
+        (status = 400, description = "Delivery is not open"),
+        (status = 401, description = "Sign-in required"),
+        (status = 403, description = "Not the sender or a member of the delivery's organization"),
+        (status = 404, description = "Delivery not found")
+    )
+)]
+async fn delete_delivery(
+    http: HttpRequest,
+    data: web::Data<AppState>,
+    delivery_id: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let delivery = data.get_delivery_by_id(&delivery_id).await
+        .map_err(backend_error)?
+        .ok_or_else(|| delivery_not_found(&delivery_id))?;
+
+    check_sender_change(&http, &data, &delivery).await?;
+    if !matches!(delivery.status, DeliveryStatus::Open | DeliveryStatus::Draft) {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidTransition, "Cannot delete delivery that is not open").for_delivery(&delivery_id)));
+    }
+
+    // Tombstone the delivery; it can be restored within the grace period
+    let mut deleted_delivery = delivery.clone();
+    deleted_delivery.deleted_from = Some(delivery.status.clone());
+    deleted_delivery.status = DeliveryStatus::Deleted;
+    deleted_delivery.deleted_at = Some(Utc::now().timestamp());
+
+    data.publish_delivery(&deleted_delivery).await
+        .map_err(backend_error)?;
+    data.publish_tombstone_event(&delivery.id, "deleted").await
+        .map_err(backend_error)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "status": "deleted",
+        "id": delivery_id.as_str(),
+        "restorable_until": deleted_delivery.deleted_at.map(|at| at + data.delete_grace_secs)
+    })))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/deliveries/{id}/restore",
+    tag = "Deliveries",
+    params(
+        ("id" = String, Path, description = "Delivery ID"),
+    ),
+    responses(
+        (status = 200, description = "Delivery restored to Open, or to Draft if it was a draft", body = Object),
+        (status = 400, description = "Delivery is not deleted"),
+        (status = 401, description = "Sign-in required"),
+        (status = 403, description = "Not the sender or a member of the delivery's organization"),
+        (status = 404, description = "Delivery not found"),
+        (status = 410, description = "Grace period for restoring has passed")
+    )
+)]
+async fn restore_delivery(
+    http: HttpRequest,
+    data: web::Data<AppState>,
+    delivery_id: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let mut delivery = data.get_delivery_by_id(&delivery_id).await
+        .map_err(backend_error)?
+        .ok_or_else(|| delivery_not_found(&delivery_id))?;
+
+    check_sender_change(&http, &data, &delivery).await?;
+    if delivery.status != DeliveryStatus::Deleted {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidTransition, "Delivery is not deleted").for_delivery(&delivery_id)));
+    }
+
+    let deleted_at = delivery.deleted_at.unwrap_or(0);
+    if Utc::now().timestamp() - deleted_at > data.delete_grace_secs {
+        return Ok(problem_response(Problem::new(ProblemType::Gone, "Delivery can no longer be restored").for_delivery(&delivery_id)));
+    }
+
+    delivery.status = delivery.deleted_from.take().unwrap_or(DeliveryStatus::Open);
+    delivery.deleted_at = None;
+
+    data.publish_delivery(&delivery).await
+        .map_err(backend_error)?;
+    data.publish_tombstone_event(&delivery.id, "restored").await
+        .map_err(backend_error)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "status": "restored",
+        "delivery": delivery
+    })))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/deliveries/{id}/publish",
+    tag = "Deliveries",
+    params(
+        ("id" = String, Path, description = "Delivery ID"),
+    ),
+    responses(
+        (status = 200, description = "Draft published and open for bids", body = DeliveryRequest),
+        (status = 400, description = "Delivery is not a draft, or its time window has passed"),
+        (status = 401, description = "Sign-in required"),
+        (status = 403, description = "Not the sender or a member of the delivery's organization, or API key is scoped to another organization"),
+        (status = 404, description = "Delivery not found")
+    )
+)]
+async fn publish_draft(
+    http: HttpRequest,
+    data: web::Data<AppState>,
+    delivery_id: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let mut delivery = data.get_delivery_by_id(&delivery_id).await
+        .map_err(backend_error)?
+        .ok_or_else(|| delivery_not_found(&delivery_id))?;
+
+    check_sender_change(&http, &data, &delivery).await?;
+    if delivery.status != DeliveryStatus::Draft {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidTransition, "Delivery is not a draft").for_delivery(&delivery_id)));
+    }
+
+    let now = Utc::now().timestamp();
+    if let Some(error) = timezone::window_error(&TimeWindow::parse(&delivery.time_window), now) {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, error).for_delivery(&delivery_id)));
+    }
+
+    // Offered from now on, so the week before it expires starts now too
+    delivery.status = DeliveryStatus::Open;
+    delivery.created_at = now;
+    delivery.expires_at = Some(now + 604800);
+    delivery.local_times = Some(timezone::LocalTimes::for_delivery(&delivery));
+
+    data.publish_new_delivery(&delivery).await
+        .map_err(backend_error)?;
+
+    Ok(HttpResponse::Ok().json(delivery))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/deliveries/{id}/cancel",
+    tag = "Deliveries",
+    params(
+        ("id" = String, Path, description = "Delivery ID"),
+    ),
+    responses(
+        (status = 200, description = "Delivery cancelled", body = Object),
+        (status = 400, description = "Delivery is not accepted"),
+        (status = 404, description = "Delivery not found")
+    )
+)]
+async fn cancel_delivery(
+    http: HttpRequest,
+    data: web::Data<AppState>,
+    delivery_id: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let delivery = data.get_delivery_by_id(&delivery_id).await
+        .map_err(backend_error)?
+        .ok_or_else(|| delivery_not_found(&delivery_id))?;
+
+    check_api_key_update(&http, &data, &delivery).await?;
+
+    // The courier keeps the offer and the delivery expires
+    let mut cancelled_delivery = data.delivery_service().cancel(delivery).await
+        .map_err(|e| service_error(e, &delivery_id))?;
+    let relisted = auto_relist(&data, &mut cancelled_delivery).await?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "status": "cancelled",
+        "message": "Delivery cancelled and sats forfeited to courier",
+        "relisted_as": relisted.map(|next| next.id)
+    })))
+}
+
+// Publish the next attempt of a failed delivery and link the two
+async fn publish_relist(data: &AppState, failed: &mut DeliveryRequest, next: &mut DeliveryRequest) -> Result<(), Error> {
+    let contact = data.recipient_contact(&failed.id).await
+        .map_err(backend_error)?;
+    save_recipient_contact(data, &next.id, &contact).await?;
+
+    next.local_times = Some(timezone::LocalTimes::for_delivery(next));
+    data.publish_new_delivery(next).await
+        .map_err(backend_error)?;
+
+    failed.relisted_as = Some(next.id.clone());
+    data.publish_delivery(failed).await
+        .map_err(backend_error)?;
+    Ok(())
+}
+
+// Re-list a delivery that just failed if its sender asked for that up front; skipped once its
+// time window has passed
+async fn auto_relist(data: &AppState, failed: &mut DeliveryRequest) -> Result<Option<DeliveryRequest>, Error> {
+    if !failed.auto_relist || !relist::is_relistable(failed) {
+        return Ok(None);
+    }
+
+    let mut next = relist::next_attempt(failed, unique_timestamp_id("delivery"), Utc::now().timestamp(), data.relist_bump_percent);
+    if timezone::schedule_error(&mut next, Utc::now().timestamp()).is_some() {
+        return Ok(None);
+    }
+    publish_relist(data, failed, &mut next).await?;
+    Ok(Some(next))
+}
+
+#[derive(Deserialize, ToSchema)]
+struct RelistRequest {
+    // Percent added to the offer (default RELIST_BUMP_PERCENT)
+    bump_percent: Option<f64>,
+    // New time window for the attempt, when the failed one's has passed
+    time_window: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/deliveries/{id}/relist",
+    tag = "Deliveries",
+    params(
+        ("id" = String, Path, description = "Delivery ID of the failed attempt"),
+    ),
+    request_body = RelistRequest,
+    responses(
+        (status = 201, description = "New attempt created, open for bids and linked to the failed one", body = DeliveryRequest),
+        (status = 400, description = "Delivery did not fail after acceptance, or the time window has passed"),
+        (status = 404, description = "Delivery not found"),
+        (status = 409, description = "Delivery was already re-listed")
+    )
+)]
+async fn relist_delivery(
+    http: HttpRequest,
+    data: web::Data<AppState>,
+    delivery_id: web::Path<String>,
+    req: web::Json<RelistRequest>,
+) -> Result<HttpResponse, Error> {
+    let mut failed = data.get_delivery_by_id(&delivery_id).await
+        .map_err(backend_error)?
+        .ok_or_else(|| delivery_not_found(&delivery_id))?;
+
+    check_api_key_update(&http, &data, &failed).await?;
+    if let Some(next_id) = &failed.relisted_as {
+        return Ok(problem_response(Problem::new(ProblemType::Conflict, format!("Delivery was already re-listed as {}", next_id)).for_delivery(&delivery_id)));
+    }
+    if !relist::is_relistable(&failed) {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidTransition, "Only deliveries that failed after acceptance can be re-listed").for_delivery(&delivery_id)));
+    }
+
+    let bump_percent = req.bump_percent.unwrap_or(data.relist_bump_percent);
+    if !bump_percent.is_finite() || bump_percent < 0.0 {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, "bump_percent must not be negative").for_delivery(&delivery_id)));
+    }
+
+    let mut next = relist::next_attempt(&failed, unique_timestamp_id("delivery"), Utc::now().timestamp(), bump_percent);
+    if let Some(window) = &req.time_window {
+        next.time_window = window.clone();
+    }
+    if let Some(error) = timezone::schedule_error(&mut next, Utc::now().timestamp()) {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, error).for_delivery(&delivery_id)));
+    }
+
+    publish_relist(&data, &mut failed, &mut next).await?;
+    Ok(HttpResponse::Created().json(next))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/deliveries/{id}/attempts",
+    tag = "Deliveries",
+    params(
+        ("id" = String, Path, description = "Delivery ID of any attempt"),
+    ),
+    responses(
+        (status = 200, description = "Every attempt of the delivery, first to latest", body = [relist::Attempt]),
+        (status = 404, description = "Delivery not found")
+    )
+)]
+async fn get_delivery_attempts(
+    data: web::Data<AppState>,
+    delivery_id: web::Path<String>,
+    user: Option<AuthenticatedUser>,
+) -> Result<HttpResponse, Error> {
+    let delivery = visible_delivery(&data, &delivery_id, user.as_ref()).await?;
+
+    // The index may not have caught up with a delivery created moments ago
+    let mut deliveries = data.indexed_deliveries().await
+        .map_err(backend_error)?;
+    deliveries.retain(|d| d.id != delivery.id);
+    deliveries.push(delivery);
+
+    Ok(HttpResponse::Ok().json(relist::attempts(&delivery_id, &deliveries)))
+}
+
+#[derive(Deserialize, ToSchema)]
+struct CompleteDeliveryRequest {
+    images: Vec<String>,
+    signature_name: Option<String>,
+    comments: Option<String>,
+    // Courier's position at handoff
+    #[serde(default)]
+    location: Option<GeoPoint>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/deliveries/{id}/complete",
+    tag = "Deliveries",
+    params(
+        ("id" = String, Path, description = "Delivery ID"),
+    ),
+    request_body = CompleteDeliveryRequest,
+    responses(
+        (status = 200, description = "Delivery completed", body = Object),
+        (status = 400, description = "Delivery cannot be completed"),
+        (status = 404, description = "Delivery not found")
+    )
+)]
+async fn complete_delivery(
+    data: web::Data<AppState>,
+    delivery_id: web::Path<String>,
+    req: web::Json<CompleteDeliveryRequest>,
+) -> Result<HttpResponse, Error> {
+    let delivery = data.get_delivery_by_id(&delivery_id).await
+        .map_err(backend_error)?
+        .ok_or_else(|| delivery_not_found(&delivery_id))?;
+
+    if let Some(problem) = service::completion_error(&delivery) {
+        return Ok(problem_response(problem.for_delivery(&delivery_id)));
+    }
+    let now = Utc::now().timestamp();
+    let proof = ProofOfDelivery::new(now)
+        .images(req.images.clone())
+        .signature_name(req.signature_name.clone())
+        .location(req.location.clone())
+        .comments(req.comments.clone());
+    if let Some(problem) = service::signature_error(&delivery, &proof) {
+        return Ok(problem_response(problem.for_delivery(&delivery_id)));
+    }
+
+    let delivery = data.delivery_service().complete(delivery, proof, now).await
+        .map_err(|e| service_error(e, &delivery_id))?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "status": "completed",
+        "delivery": delivery
+    })))
+}
+
+#[derive(Deserialize, ToSchema)]
+struct AttemptFailedRequest {
+    reason: delivery_attempts::FailureReason,
+    note: Option<String>,
+    // Evidence such as the closed door or the address sign: base64 encoded images or URLs
+    #[serde(default)]
+    photos: Vec<String>,
+    // Courier's position during the attempt
+    #[serde(default)]
+    location: Option<GeoPoint>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/deliveries/{id}/attempt-failed",
+    tag = "Deliveries",
+    params(
+        ("id" = String, Path, description = "Delivery ID"),
+    ),
+    request_body = AttemptFailedRequest,
+    responses(
+        (status = 200, description = "Attempt recorded, with the outcome the policy chose: retry, return or partial_payout", body = Object),
+        (status = 400, description = "Delivery is not under way, or the package is already being returned"),
+        (status = 404, description = "Delivery not found")
+    )
+)]
+async fn attempt_failed(
+    data: web::Data<AppState>,
+    delivery_id: web::Path<String>,
+    req: web::Json<AttemptFailedRequest>,
+) -> Result<HttpResponse, Error> {
+    let mut delivery = data.get_delivery_by_id(&delivery_id).await
+        .map_err(backend_error)?
+        .ok_or_else(|| delivery_not_found(&delivery_id))?;
+
+    if delivery.status != DeliveryStatus::Accepted && delivery.status != DeliveryStatus::InTransit {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidTransition, "Can only record attempts on accepted or in-transit deliveries").for_delivery(&delivery_id)));
+    }
+    if delivery.returning {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidTransition, "The package is already being returned").for_delivery(&delivery_id)));
+    }
+
+    let req = req.into_inner();
+    let number = delivery.failed_attempts.len() as u32 + 1;
+    let attempt = delivery_attempts::FailedAttempt {
+        number,
+        reason: req.reason,
+        note: req.note,
+        photos: req.photos,
+        location: req.location,
+        courier: delivery.accepted_courier().unwrap_or_default().to_string(),
+        outcome: data.attempt_policy.outcome(req.reason, number),
+        at: Utc::now().timestamp(),
+    };
+    delivery.failed_attempts.push(attempt.clone());
+
+    let mut payout = None;
+    match attempt.outcome {
+        delivery_attempts::AttemptOutcome::Retry => {}
+        delivery_attempts::AttemptOutcome::Return => delivery.returning = true,
+        delivery_attempts::AttemptOutcome::PartialPayout => {
+            let amount = data.attempt_policy.payout(delivery.offer_amount);
+            if !attempt.courier.is_empty() {
+                let mut courier = data.get_user_profile(&attempt.courier).await.unwrap_or_default();
+                courier.credit_earnings(amount).map_err(backend_error)?;
+                data.publish_user_profile(&courier).await
+                    .map_err(backend_error)?;
+            }
+            delivery.status = DeliveryStatus::Expired;
+            payout = Some(amount);
+        }
+    }
+
+    data.publish_delivery(&delivery).await
+        .map_err(backend_error)?;
+    data.notify_failed_attempt(&delivery, &attempt).await;
+    let relisted = auto_relist(&data, &mut delivery).await?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "status": "attempt_recorded",
+        "attempt": attempt,
+        "payout": payout,
+        "relisted_as": relisted.map(|next| next.id),
+        "delivery": delivery
+    })))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/user/{npub}",
+    tag = "Users",
+    params(
+        ("npub" = String, Path, description = "User npub or hex pubkey"),
+    ),
+    responses(
+        (status = 200, description = "User profile", body = UserProfile)
+    )
+)]
+async fn get_user(
+    data: web::Data<AppState>,
+    npub: web::Path<PubkeyId>,
+) -> Result<HttpResponse, Error> {
+    let npub = npub.npub();
+    let mut profile = data.get_user_profile(&npub).await
+        .unwrap_or_else(|_| UserProfile {
+            npub: npub.to_string(),
+            ..Default::default()
+        });
+    data.refresh_reputation(&mut profile).await;
+
+    Ok(HttpResponse::Ok().json(profile))
+}
+
+#[derive(Deserialize, ToSchema)]
+struct UpdateUserRequest {
+    display_name: Option<String>,
+    lightning_address: Option<String>,
+    leaderboard_opt_in: Option<bool>,
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/v1/user/{npub}",
+    tag = "Users",
+    params(
+        ("npub" = String, Path, description = "User npub or hex pubkey"),
+    ),
+    request_body = UpdateUserRequest,
+    responses(
+        (status = 200, description = "Updated user profile", body = UserProfile)
+    )
+)]
+async fn update_user(
+    data: web::Data<AppState>,
+    npub: web::Path<PubkeyId>,
+    req: web::Json<UpdateUserRequest>,
+) -> Result<HttpResponse, Error> {
+    let npub = npub.npub();
+    let mut profile = data.get_user_profile(&npub).await
+        .unwrap_or_else(|_| UserProfile {
+            npub: npub.to_string(),
+            ..Default::default()
+        });
+
+    if let Some(name) = &req.display_name {
+        profile.display_name = Some(name.clone());
+    }
+    if let Some(ln_addr) = &req.lightning_address {
+        profile.lightning_address = Some(ln_addr.clone());
+    }
+    if let Some(opt_in) = req.leaderboard_opt_in {
+        profile.leaderboard_opt_in = opt_in;
+    }
+
+    data.publish_user_profile(&profile).await
+        .map_err(backend_error)?;
+
+    Ok(HttpResponse::Ok().json(profile))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/user/{npub}/stats",
+    tag = "Users",
+    params(
+        ("npub" = String, Path, description = "Courier npub"),
+    ),
+    responses(
+        (status = 200, description = "Courier performance derived from delivery history", body = analytics::CourierStats)
+    )
+)]
+async fn get_courier_stats(
+    data: web::Data<AppState>,
+    npub: web::Path<PubkeyId>,
+) -> Result<HttpResponse, Error> {
+    let npub = npub.npub();
+    let deliveries = data.indexed_deliveries().await
+        .map_err(backend_error)?;
+
+    Ok(HttpResponse::Ok().json(analytics::CourierStats::build(&npub, &deliveries)))
+}
+
+#[derive(Deserialize, IntoParams)]
+struct CourierFeedQuery {
+    /// From the previous page's next_cursor; omit for the newest items
+    cursor: Option<String>,
+    /// Courier's position; nearby jobs are only listed with lat and lng
+    lat: Option<f64>,
+    lng: Option<f64>,
+    /// How far from the courier a pickup may be (default 10)
+    radius_km: Option<f64>,
+    /// Page size (default 20, at most 100)
+    limit: Option<usize>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/courier/{npub}/feed",
+    tag = "Users",
+    params(
+        ("npub" = String, Path, description = "Courier npub or hex pubkey"),
+        CourierFeedQuery,
+    ),
+    responses(
+        (status = 200, description = "Nearby jobs, direct offers, status changes on the courier's deliveries and payouts, newest first", body = courier_feed::FeedPage),
+        (status = 400, description = "Malformed cursor")
+    )
+)]
+async fn get_courier_feed(
+    data: web::Data<AppState>,
+    npub: web::Path<PubkeyId>,
+    query: web::Query<CourierFeedQuery>,
+) -> Result<HttpResponse, Error> {
+    let cursor = match query.cursor.as_deref().map(courier_feed::Cursor::parse) {
+        Some(None) => return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, "Malformed cursor"))),
+        Some(cursor) => cursor,
+        None => None,
+    };
+    let nearby = match (query.lat, query.lng) {
+        (Some(lat), Some(lng)) => Some(courier_feed::Nearby {
+            point: GeoPoint { lat, lng },
+            radius_meters: query.radius_km.unwrap_or(courier_feed::DEFAULT_RADIUS_KM) * 1000.0,
+        }),
+        _ => None,
+    };
+    let deliveries = data.indexed_deliveries().await
+        .map_err(backend_error)?;
+
+    let projections = data.projections.read().await;
+    let items = courier_feed::build(&npub.npub(), &deliveries, nearby.as_ref(), |id| projections.updates(id));
+    let limit = query.limit.unwrap_or(courier_feed::DEFAULT_PAGE_SIZE).clamp(1, courier_feed::MAX_PAGE_SIZE);
+
+    Ok(HttpResponse::Ok().json(courier_feed::page(items, cursor.as_ref(), limit)))
+}
+
+#[derive(Deserialize, IntoParams)]
+struct CourierDeliveriesQuery {
+    /// Comma-separated statuses (default accepted,in_transit); open lists assignments awaiting
+    /// acknowledgement
+    status: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/courier/{npub}/deliveries",
+    tag = "Users",
+    params(
+        ("npub" = String, Path, description = "Courier npub or hex pubkey"),
+        CourierDeliveriesQuery,
+    ),
+    responses(
+        (status = 200, description = "The courier's current jobs with both stops, revealed details and the next required action", body = [courier_jobs::CourierJob]),
+        (status = 400, description = "Unknown status")
+    )
+)]
+async fn get_courier_deliveries(
+    data: web::Data<AppState>,
+    npub: web::Path<PubkeyId>,
+    query: web::Query<CourierDeliveriesQuery>,
+    user: Option<AuthenticatedUser>,
+) -> Result<HttpResponse, Error> {
+    let statuses = match export::parse_statuses(query.status.as_deref()) {
+        Ok(statuses) => statuses.unwrap_or_else(|| courier_jobs::DEFAULT_STATUSES.to_vec()),
+        Err(e) => return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, e))),
+    };
+    let mut deliveries = data.get_all_deliveries().await
+        .map_err(backend_error)?;
+    let viewer = Viewer::for_deliveries(&data, user.as_ref(), &deliveries).await?;
+    deliveries.retain(|d| viewer.can_see(d));
+
+    let mut jobs = courier_jobs::build(&npub.npub(), &deliveries, &statuses);
+    for job in &mut jobs {
+        if let Some(delivery) = deliveries.iter().find(|d| d.id == job.delivery_id) {
+            job.pickup_code_reveal = data.pickup_code_reveal(delivery).await
+                .map_err(backend_error)?;
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(jobs))
+}
+
+#[derive(Deserialize, IntoParams)]
+struct CourierBidsQuery {
+    /// won, lost or pending; the summary always covers every bid
+    outcome: Option<courier_bids::BidOutcome>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/courier/{npub}/bids",
+    tag = "Users",
+    params(
+        ("npub" = String, Path, description = "Courier npub or hex pubkey"),
+        CourierBidsQuery,
+    ),
+    responses(
+        (status = 200, description = "The courier's bids with their outcomes, win rate and average winning discount", body = courier_bids::BidHistory)
+    )
+)]
+async fn get_courier_bids(
+    data: web::Data<AppState>,
+    npub: web::Path<PubkeyId>,
+    query: web::Query<CourierBidsQuery>,
+) -> Result<HttpResponse, Error> {
+    let deliveries = data.indexed_deliveries().await
+        .map_err(backend_error)?;
+
+    Ok(HttpResponse::Ok().json(courier_bids::BidHistory::build(&npub.npub(), &deliveries, query.outcome)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/user/{npub}/settings",
+    tag = "Users",
+    params(
+        ("npub" = String, Path, description = "User npub or hex pubkey"),
+    ),
+    responses(
+        (status = 200, description = "User settings (defaults if none saved)", body = settings::UserSettings),
+        (status = 401, description = "Missing or invalid session token"),
+        (status = 403, description = "Signed in as another user")
+    )
+)]
+async fn get_user_settings(
+    user: AuthenticatedUser,
+    data: web::Data<AppState>,
+    npub: web::Path<PubkeyId>,
+) -> Result<HttpResponse, Error> {
+    user.require(&npub)?;
+    let npub = npub.npub();
+    let user_settings = data.get_user_settings(&npub).await
+        .map_err(backend_error)?;
+
+    Ok(HttpResponse::Ok().json(user_settings))
+}
+
+#[derive(Deserialize, ToSchema)]
+struct UpdateSettingsRequest {
+    notifications: Option<settings::NotificationSettings>,
+    default_pickup: Option<Location>,
+    preferred_couriers: Option<Vec<String>>,
+    unit_system: Option<settings::UnitSystem>,
+    language: Option<i18n::Language>,
+}
+
+impl UpdateSettingsRequest {
+    fn apply(&self, user_settings: &mut settings::UserSettings) {
+        if let Some(notifications) = &self.notifications {
+            user_settings.notifications = notifications.clone();
+        }
+        if let Some(pickup) = &self.default_pickup {
+            user_settings.default_pickup = Some(pickup.clone());
+        }
+        if let Some(couriers) = &self.preferred_couriers {
+            user_settings.preferred_couriers = couriers.clone();
+        }
+        if let Some(unit_system) = self.unit_system {
+            user_settings.unit_system = unit_system;
+        }
+        if let Some(language) = self.language {
+            user_settings.language = Some(language);
+        }
+    }
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/v1/user/{npub}/settings",
+    tag = "Users",
+    params(
+        ("npub" = String, Path, description = "User npub or hex pubkey"),
+    ),
+    request_body = UpdateSettingsRequest,
+    responses(
+        (status = 200, description = "Updated user settings", body = settings::UserSettings),
+        (status = 401, description = "Missing or invalid session token"),
+        (status = 403, description = "Signed in as another user")
+    )
+)]
+async fn update_user_settings(
+    user: AuthenticatedUser,
+    data: web::Data<AppState>,
+    npub: web::Path<PubkeyId>,
+    req: web::Json<UpdateSettingsRequest>,
+) -> Result<HttpResponse, Error> {
+    user.require(&npub)?;
+    let npub = npub.npub();
+    if let Some(response) = invalid_email_response(req.notifications.as_ref().and_then(|n| n.email.as_deref())) {
+        return Ok(response);
+    }
+
+    let mut user_settings = data.get_user_settings(&npub).await
+        .map_err(backend_error)?;
+
+    req.apply(&mut user_settings);
+
+    data.publish_user_settings(&npub, &user_settings).await
+        .map_err(backend_error)?;
+
+    Ok(HttpResponse::Ok().json(user_settings))
+}
+
+#[derive(Serialize, ToSchema)]
+struct DelegationResponse {
+    /// Hex pubkey to delegate to (the marketplace key)
+    delegatee: String,
+    delegation: Option<delegation::Delegation>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/user/{npub}/delegation",
+    tag = "Users",
+    params(
+        ("npub" = String, Path, description = "User npub or hex pubkey"),
+    ),
+    responses(
+        (status = 200, description = "The user's NIP-26 delegation, if any, and the key to delegate to", body = DelegationResponse)
+    )
+)]
+async fn get_user_delegation(
+    data: web::Data<AppState>,
+    npub: web::Path<PubkeyId>,
+) -> Result<HttpResponse, Error> {
+    let npub = npub.npub();
+    let delegation = data.user_delegation(&npub).await
+        .map_err(backend_error)?;
+
+    Ok(HttpResponse::Ok().json(DelegationResponse {
+        delegatee: data.system_pubkey.to_hex(),
+        delegation,
+    }))
+}
+
+#[derive(Deserialize, ToSchema)]
+struct SetDelegationRequest {
+    /// NIP-26 conditions, e.g. `kind=35001&created_at>1700000000`
+    conditions: String,
+    /// Signature by the user's key over `nostr:delegation:<delegatee>:<conditions>`
+    token: String,
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/user/{npub}/delegation",
+    tag = "Users",
+    params(
+        ("npub" = String, Path, description = "User npub or hex pubkey"),
+    ),
+    request_body = SetDelegationRequest,
+    responses(
+        (status = 200, description = "Stored delegation", body = DelegationResponse),
+        (status = 400, description = "Invalid pubkey, conditions or token signature"),
+        (status = 401, description = "Missing or invalid session token"),
+        (status = 403, description = "Signed in as another user")
+    )
+)]
+async fn set_user_delegation(
+    user: AuthenticatedUser,
+    data: web::Data<AppState>,
+    npub: web::Path<PubkeyId>,
+    req: web::Json<SetDelegationRequest>,
+) -> Result<HttpResponse, Error> {
+    let delegator = npub.into_inner();
+    user.require(&delegator)?;
+    let npub = delegator.npub();
+
+    let delegation = delegation::Delegation {
+        delegator: delegator.hex(),
+        conditions: req.conditions.trim().to_string(),
+        token: req.token.trim().to_string(),
+    };
+    if let Err(e) = delegation.verify(&data.system_pubkey) {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, e)));
+    }
+
+    data.set_user_delegation(&npub, Some(delegation.clone())).await
+        .map_err(backend_error)?;
+
+    Ok(HttpResponse::Ok().json(DelegationResponse {
+        delegatee: data.system_pubkey.to_hex(),
+        delegation: Some(delegation),
+    }))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/user/{npub}/delegation",
+    tag = "Users",
+    params(
+        ("npub" = String, Path, description = "User npub or hex pubkey"),
+    ),
+    responses(
+        (status = 204, description = "Delegation removed; events are no longer tagged with it"),
+        (status = 401, description = "Missing or invalid session token"),
+        (status = 403, description = "Signed in as another user")
+    )
+)]
+async fn remove_user_delegation(
+    user: AuthenticatedUser,
+    data: web::Data<AppState>,
+    npub: web::Path<PubkeyId>,
+) -> Result<HttpResponse, Error> {
+    user.require(&npub)?;
+    let npub = npub.npub();
+    data.set_user_delegation(&npub, None).await
+        .map_err(backend_error)?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(Deserialize, ToSchema)]
+struct RegisterDeviceRequest {
+    platform: settings::PushPlatform,
+    /// FCM registration token, or the UnifiedPush endpoint URL
+    token: String,
+    name: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/user/{npub}/devices",
+    tag = "Users",
+    params(
+        ("npub" = String, Path, description = "User npub or hex pubkey"),
+    ),
+    request_body = RegisterDeviceRequest,
+    responses(
+        (status = 201, description = "Device registered; job offers are pushed to it", body = settings::PushDevice),
+        (status = 400, description = "Invalid token, or the platform isn't configured on this server"),
+        (status = 401, description = "Missing or invalid session token"),
+        (status = 403, description = "Signed in as another user")
+    )
+)]
+async fn register_device(
+    user: AuthenticatedUser,
+    data: web::Data<AppState>,
+    npub: web::Path<PubkeyId>,
+    req: web::Json<RegisterDeviceRequest>,
+) -> Result<HttpResponse, Error> {
+    user.require(&npub)?;
+    let npub = npub.npub();
+    if let Err(e) = push::validate_token(req.platform, &req.token) {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, e)));
+    }
+    if !data.push.supports(req.platform) {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, "This server doesn't send push notifications on that platform")));
+    }
+
+    let token = req.token.trim().to_string();
+    let device = settings::PushDevice {
+        id: push::device_id(&token),
+        platform: req.platform,
+        token,
+        name: req.name.clone(),
+        registered_at: Utc::now().timestamp(),
+    };
+    data.add_push_device(&npub, device.clone()).await
+        .map_err(backend_error)?;
+
+    Ok(HttpResponse::Created().json(device))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/user/{npub}/devices",
+    tag = "Users",
+    params(
+        ("npub" = String, Path, description = "User npub or hex pubkey"),
+    ),
+    responses(
+        (status = 200, description = "Registered push devices, without their tokens", body = [settings::PushDevice]),
+        (status = 401, description = "Missing or invalid session token"),
+        (status = 403, description = "Signed in as another user")
+    )
+)]
+async fn list_devices(
+    user: AuthenticatedUser,
+    data: web::Data<AppState>,
+    npub: web::Path<PubkeyId>,
+) -> Result<HttpResponse, Error> {
+    user.require(&npub)?;
+    let npub = npub.npub();
+    let user_settings = data.get_user_settings(&PubkeyId::normalize(&npub)).await
+        .map_err(backend_error)?;
+    let devices: Vec<settings::PushDevice> = user_settings.devices.iter().map(settings::PushDevice::redacted).collect();
+
+    Ok(HttpResponse::Ok().json(devices))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/user/{npub}/devices/{id}",
+    tag = "Users",
+    params(
+        ("npub" = String, Path, description = "User npub or hex pubkey"),
+        ("id" = String, Path, description = "Device ID"),
+    ),
+    responses(
+        (status = 204, description = "Device removed"),
+        (status = 401, description = "Missing or invalid session token"),
+        (status = 403, description = "Signed in as another user"),
+        (status = 404, description = "No such device")
+    )
+)]
+async fn remove_device(
+    user: AuthenticatedUser,
+    data: web::Data<AppState>,
+    path: web::Path<(PubkeyId, String)>,
+) -> Result<HttpResponse, Error> {
+    let (npub, device_id) = path.into_inner();
+    user.require(&npub)?;
+    let npub = npub.npub();
+    if !data.remove_push_device(&npub, &device_id).await.map_err(backend_error)? {
+        return Ok(problem_response(Problem::new(ProblemType::NotFound, "No such device")));
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(Deserialize, ToSchema)]
+struct SaveAddressRequest {
+    label: String,
+    location: Location,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/user/{npub}/addresses",
+    tag = "Users",
+    params(
+        ("npub" = String, Path, description = "User npub or hex pubkey"),
+    ),
+    request_body = SaveAddressRequest,
+    responses(
+        (status = 201, description = "Address saved; use its ID as pickup_address_id or dropoff_address_id", body = settings::SavedAddress),
+        (status = 400, description = "Missing label or location, or the address book is full"),
+        (status = 401, description = "Missing or invalid session token"),
+        (status = 403, description = "Signed in as another user")
+    )
+)]
+async fn save_address(
+    user: AuthenticatedUser,
+    data: web::Data<AppState>,
+    npub: web::Path<PubkeyId>,
+    req: web::Json<SaveAddressRequest>,
+) -> Result<HttpResponse, Error> {
+    user.require(&npub)?;
+    let npub = npub.npub();
+    let SaveAddressRequest { label, mut location } = req.into_inner();
+    let label = label.trim().to_string();
+    if label.is_empty() {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, "Saved addresses need a label")));
+    }
+    if let Some(phone) = location.contact.as_ref().and_then(|c| c.phone.as_deref()).filter(|p| !notify::is_valid_phone(p)) {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, format!("'{}' is not a valid phone number", phone))));
+    }
+    if let Err(e) = data.resolve_location_code(&mut location).await {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, e)));
+    }
+    if location.address.trim().is_empty() && location.coordinates.is_none() {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, "Saved addresses need an address, coordinates, a plus code or a what3words address")));
+    }
+
+    let address = settings::SavedAddress {
+        id: unique_timestamp_id("addr"),
+        label,
+        location,
+        created_at: Utc::now().timestamp(),
+    };
+    if !data.add_saved_address(&npub, address.clone()).await.map_err(backend_error)? {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, format!("The address book is full ({} addresses)", settings::MAX_SAVED_ADDRESSES))));
+    }
+
+    Ok(HttpResponse::Created().json(address))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/user/{npub}/addresses",
+    tag = "Users",
+    params(
+        ("npub" = String, Path, description = "User npub or hex pubkey"),
+    ),
+    responses(
+        (status = 200, description = "Saved addresses, oldest first", body = [settings::SavedAddress]),
+        (status = 401, description = "Missing or invalid session token"),
+        (status = 403, description = "Signed in as another user")
+    )
+)]
+async fn list_addresses(
+    user: AuthenticatedUser,
+    data: web::Data<AppState>,
+    npub: web::Path<PubkeyId>,
+) -> Result<HttpResponse, Error> {
+    user.require(&npub)?;
+    let npub = npub.npub();
+    let user_settings = data.get_user_settings(&PubkeyId::normalize(&npub)).await
+        .map_err(backend_error)?;
+
+    Ok(HttpResponse::Ok().json(user_settings.addresses))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/user/{npub}/addresses/{id}",
+    tag = "Users",
+    params(
+        ("npub" = String, Path, description = "User npub or hex pubkey"),
+        ("id" = String, Path, description = "Saved address ID"),
+    ),
+    responses(
+        (status = 204, description = "Address removed"),
+        (status = 401, description = "Missing or invalid session token"),
+        (status = 403, description = "Signed in as another user"),
+        (status = 404, description = "No such saved address")
+    )
+)]
+async fn remove_address(
+    user: AuthenticatedUser,
+    data: web::Data<AppState>,
+    path: web::Path<(PubkeyId, String)>,
+) -> Result<HttpResponse, Error> {
+    let (npub, address_id) = path.into_inner();
+    user.require(&npub)?;
+    let npub = npub.npub();
+    if !data.remove_saved_address(&npub, &address_id).await.map_err(backend_error)? {
+        return Ok(problem_response(Problem::new(ProblemType::NotFound, "No such saved address")));
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/user/{npub}/couriers",
+    tag = "Users",
+    params(
+        ("npub" = String, Path, description = "Sender npub"),
+    ),
+    responses(
+        (status = 200, description = "Sender's favorite and blocked couriers", body = courier_lists::CourierLists)
+    )
+)]
+async fn get_courier_lists(
+    data: web::Data<AppState>,
+    npub: web::Path<PubkeyId>,
+) -> Result<HttpResponse, Error> {
+    let npub = npub.npub();
+    let lists = data.get_courier_lists(&npub).await
+        .map_err(backend_error)?;
+
+    Ok(HttpResponse::Ok().json(lists))
+}
+
+// Add (PUT) or remove (DELETE) a courier from one of the sender's lists
+async fn modify_courier_list(
+    data: &AppState,
+    npub: &str,
+    list: &str,
+    courier: &str,
+    add: bool,
+) -> Result<HttpResponse, Error> {
+    let Some(kind) = courier_lists::CourierListKind::parse(list) else {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, "List must be favorites or blocked")));
+    };
+
+    let courier = PubkeyId::normalize(courier);
+    let mut couriers = data.get_courier_list(npub, kind).await
+        .map_err(backend_error)?;
+
+    let present = couriers.contains(&courier);
+    if add && !present {
+        couriers.push(courier);
+    } else if !add && present {
+        couriers.retain(|c| *c != courier);
+    } else {
+        return Ok(HttpResponse::Ok().json(couriers));
+    }
+
+    data.publish_courier_list(npub, kind, &couriers).await
+        .map_err(backend_error)?;
+
+    Ok(HttpResponse::Ok().json(couriers))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/user/{npub}/couriers/{list}/{courier}",
+    tag = "Users",
+    params(
+        ("npub" = String, Path, description = "Sender npub"),
+        ("list" = String, Path, description = "favorites or blocked"),
+        ("courier" = String, Path, description = "Courier npub or hex pubkey"),
+    ),
+    responses(
+        (status = 200, description = "Updated list of couriers", body = [String]),
+        (status = 400, description = "Unknown list"),
+        (status = 401, description = "Missing or invalid session token"),
+        (status = 403, description = "Signed in as another user")
+    )
+)]
+async fn add_to_courier_list(
+    user: AuthenticatedUser,
+    data: web::Data<AppState>,
+    path: web::Path<(PubkeyId, String, PubkeyId)>,
+) -> Result<HttpResponse, Error> {
+    let (npub, list, courier) = path.into_inner();
+    user.require(&npub)?;
+    let (npub, courier) = (npub.npub(), courier.npub());
+    modify_courier_list(&data, &npub, &list, &courier, true).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/user/{npub}/couriers/{list}/{courier}",
+    tag = "Users",
+    params(
+        ("npub" = String, Path, description = "Sender npub"),
+        ("list" = String, Path, description = "favorites or blocked"),
+        ("courier" = String, Path, description = "Courier npub or hex pubkey"),
+    ),
+    responses(
+        (status = 200, description = "Updated list of couriers", body = [String]),
+        (status = 400, description = "Unknown list"),
+        (status = 401, description = "Missing or invalid session token"),
+        (status = 403, description = "Signed in as another user")
+    )
+)]
+async fn remove_from_courier_list(
+    user: AuthenticatedUser,
+    data: web::Data<AppState>,
+    path: web::Path<(PubkeyId, String, PubkeyId)>,
+) -> Result<HttpResponse, Error> {
+    let (npub, list, courier) = path.into_inner();
+    user.require(&npub)?;
+    let (npub, courier) = (npub.npub(), courier.npub());
+    modify_courier_list(&data, &npub, &list, &courier, false).await
+}
+
+#[derive(Deserialize, IntoParams)]
+struct TimeRangeQuery {
+    from: Option<i64>,
+    to: Option<i64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/user/{npub}/spending",
+    tag = "Users",
+    params(
+        ("npub" = String, Path, description = "User npub or hex pubkey"),
+        TimeRangeQuery,
+    ),
+    responses(
+        (status = 200, description = "Sender spending report", body = analytics::SpendingReport),
+        (status = 401, description = "Missing or invalid session token"),
+        (status = 403, description = "Signed in as another user")
+    )
+)]
+async fn get_user_spending(
+    user: AuthenticatedUser,
+    data: web::Data<AppState>,
+    npub: web::Path<PubkeyId>,
+    query: web::Query<TimeRangeQuery>,
+) -> Result<HttpResponse, Error> {
+    user.require(&npub)?;
+    let npub = npub.npub();
+    let deliveries = data.get_all_deliveries().await
+        .map_err(backend_error)?;
+
+    let report = analytics::SpendingReport::build(&npub, &deliveries, query.from, query.to);
+
+    Ok(HttpResponse::Ok().json(report))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/sender/{npub}/dashboard",
+    tag = "Users",
+    params(
+        ("npub" = String, Path, description = "Sender npub or hex pubkey"),
+    ),
+    responses(
+        (status = 200, description = "Active deliveries by status, bids awaiting a decision, completions awaiting confirmation and month-to-date spend", body = sender_dashboard::SenderDashboard),
+        (status = 401, description = "Missing or invalid session token"),
+        (status = 403, description = "Signed in as another user")
+    )
+)]
+async fn get_sender_dashboard(
+    user: AuthenticatedUser,
+    data: web::Data<AppState>,
+    npub: web::Path<PubkeyId>,
+) -> Result<HttpResponse, Error> {
+    user.require(&npub)?;
+    let npub = npub.npub();
+    let deliveries = data.get_all_deliveries().await
+        .map_err(backend_error)?;
+
+    Ok(HttpResponse::Ok().json(sender_dashboard::SenderDashboard::build(&npub, &deliveries, Utc::now().timestamp())))
+}
+
+#[derive(Deserialize, ToSchema)]
+struct CreateOrganizationRequest {
+    name: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/organizations",
+    tag = "Organizations",
+    request_body = CreateOrganizationRequest,
+    responses(
+        (status = 200, description = "Organization created", body = organizations::Organization),
+        (status = 400, description = "Missing name"),
+        (status = 401, description = "Missing or invalid session token")
+    )
+)]
+async fn create_organization(
+    user: AuthenticatedUser,
+    data: web::Data<AppState>,
+    req: web::Json<CreateOrganizationRequest>,
+) -> Result<HttpResponse, Error> {
+    if req.name.trim().is_empty() {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, "Organization name is required")));
+    }
+
+    let now = Utc::now().timestamp();
+    let mut org = organizations::Organization {
+        id: unique_timestamp_id("org"),
+        name: req.name.trim().to_string(),
+        members: vec![organizations::OrgMember {
+            npub: user.npub(),
+            role: organizations::OrgRole::Owner,
+            added_at: now,
+        }],
+        created_at: now,
+        pubkey: None,
+        permissions: rbac::RolePolicy::default(),
+    };
+
+    data.publish_organization(&mut org).await
+        .map_err(backend_error)?;
+
+    Ok(HttpResponse::Ok().json(org))
+}
+
+async fn find_organization(data: &AppState, id: &str) -> Result<organizations::Organization, Error> {
+    data.get_organization(id).await
+        .map_err(backend_error)?
+        .ok_or_else(|| problem_error(Problem::new(ProblemType::NotFound, "Organization not found")))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/organizations/{id}",
+    tag = "Organizations",
+    params(
+        ("id" = String, Path, description = "Organization ID"),
+    ),
+    responses(
+        (status = 200, description = "Organization", body = organizations::Organization),
+        (status = 404, description = "Organization not found")
+    )
+)]
+async fn get_organization(
+    data: web::Data<AppState>,
+    org_id: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let org = find_organization(&data, &org_id).await?;
+
+    Ok(HttpResponse::Ok().json(org))
+}
+
+#[derive(Deserialize, ToSchema)]
+struct SetMemberRequest {
+    role: organizations::OrgRole,
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/organizations/{id}/members/{npub}",
+    tag = "Organizations",
+    params(
+        ("id" = String, Path, description = "Organization ID"),
+        ("npub" = String, Path, description = "Member npub"),
+    ),
+    request_body = SetMemberRequest,
+    responses(
+        (status = 200, description = "Updated organization", body = organizations::Organization),
+        (status = 400, description = "Change would leave the organization without an owner"),
+        (status = 401, description = "Missing or invalid session token"),
+        (status = 403, description = "Caller may not manage members, or only an owner may make this change"),
+        (status = 404, description = "Organization not found")
+    )
+)]
+async fn set_organization_member(
+    user: AuthenticatedUser,
+    data: web::Data<AppState>,
+    path: web::Path<(String, PubkeyId)>,
+    req: web::Json<SetMemberRequest>,
+) -> Result<HttpResponse, Error> {
+    let (org_id, npub) = path.into_inner();
+    let npub = npub.npub();
+    let mut org = find_organization(&data, &org_id).await?;
+
+    if let Some(error) = org.member_change_error(&user.pubkey.to_hex(), &npub, Some(req.role)) {
+        return Ok(problem_response(Problem::new(ProblemType::Forbidden, error)));
+    }
+
+    if let Err(e) = org.set_member(&npub, req.role, Utc::now().timestamp()) {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, e)));
+    }
+
+    data.publish_organization(&mut org).await
+        .map_err(backend_error)?;
+
+    Ok(HttpResponse::Ok().json(org))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/organizations/{id}/members/{npub}",
+    tag = "Organizations",
+    params(
+        ("id" = String, Path, description = "Organization ID"),
+        ("npub" = String, Path, description = "Member npub"),
+    ),
+    responses(
+        (status = 200, description = "Updated organization", body = organizations::Organization),
+        (status = 400, description = "Not a member, or removal would leave no owner"),
+        (status = 401, description = "Missing or invalid session token"),
+        (status = 403, description = "Caller may not manage members, or only an owner may make this change"),
+        (status = 404, description = "Organization not found")
+    )
+)]
+async fn remove_organization_member(
+    user: AuthenticatedUser,
+    data: web::Data<AppState>,
+    path: web::Path<(String, PubkeyId)>,
+) -> Result<HttpResponse, Error> {
+    let (org_id, npub) = path.into_inner();
+    let npub = npub.npub();
+    let mut org = find_organization(&data, &org_id).await?;
+
+    if let Some(error) = org.member_change_error(&user.pubkey.to_hex(), &npub, None) {
+        return Ok(problem_response(Problem::new(ProblemType::Forbidden, error)));
+    }
+
+    if let Err(e) = org.remove_member(&npub) {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, e)));
+    }
+
+    data.publish_organization(&mut org).await
+        .map_err(backend_error)?;
+
+    Ok(HttpResponse::Ok().json(org))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/organizations/{id}/permissions",
+    tag = "Organizations",
+    params(
+        ("id" = String, Path, description = "Organization ID"),
+    ),
+    responses(
+        (status = 200, description = "Roles granted each permission, defaults included (owners always have every permission)", body = Object),
+        (status = 404, description = "Organization not found")
+    )
+)]
+async fn get_organization_permissions(
+    data: web::Data<AppState>,
+    org_id: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let org = find_organization(&data, &org_id).await?;
+
+    Ok(HttpResponse::Ok().json(org.permissions.effective()))
+}
+
+#[derive(Deserialize, ToSchema)]
+struct SetPermissionsRequest {
+    /// Roles to grant each listed permission; permissions left out keep their defaults
+    permissions: rbac::RolePolicy,
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/organizations/{id}/permissions",
+    tag = "Organizations",
+    params(
+        ("id" = String, Path, description = "Organization ID"),
+    ),
+    request_body = SetPermissionsRequest,
+    responses(
+        (status = 200, description = "Roles granted each permission after the change", body = Object),
+        (status = 401, description = "Missing or invalid session token"),
+        (status = 403, description = "Caller is not an owner"),
+        (status = 404, description = "Organization not found")
+    )
+)]
+async fn set_organization_permissions(
+    user: AuthenticatedUser,
+    data: web::Data<AppState>,
+    org_id: web::Path<String>,
+    req: web::Json<SetPermissionsRequest>,
+) -> Result<HttpResponse, Error> {
+    let mut org = find_organization(&data, &org_id).await?;
+
+    if org.role_of(&user.pubkey.to_hex()) != Some(organizations::OrgRole::Owner) {
+        return Ok(problem_response(Problem::new(ProblemType::Forbidden, "Only owners can change permissions")));
+    }
+
+    org.permissions = req.into_inner().permissions;
+    data.publish_organization(&mut org).await
+        .map_err(backend_error)?;
+
+    Ok(HttpResponse::Ok().json(org.permissions.effective()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/organizations/{id}/stats",
+    tag = "Organizations",
+    params(
+        ("id" = String, Path, description = "Organization ID"),
+        TimeRangeQuery,
+    ),
+    responses(
+        (status = 200, description = "Fleet earnings and performance", body = organizations::FleetStats),
+        (status = 404, description = "Organization not found")
+    )
+)]
+async fn get_fleet_stats(
+    data: web::Data<AppState>,
+    org_id: web::Path<String>,
+    query: web::Query<TimeRangeQuery>,
+) -> Result<HttpResponse, Error> {
+    let org = find_organization(&data, &org_id).await?;
+
+    let deliveries = data.indexed_deliveries().await
+        .map_err(backend_error)?;
+
+    Ok(HttpResponse::Ok().json(organizations::FleetStats::build(&org, &deliveries, query.from, query.to)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/stats",
+    tag = "Analytics",
+    responses(
+        (status = 200, description = "Marketplace statistics", body = analytics::MarketStats)
+    )
+)]
+async fn get_market_stats(
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    // Fall back to an on-demand refresh until the indexer has completed its first pass
+    if data.index.read().await.indexed_at == 0 {
+        data.refresh_index().await
+            .map_err(backend_error)?;
+    }
+
+    let index = data.index.read().await;
+    Ok(HttpResponse::Ok().json(&index.market_stats))
+}
+
+#[derive(Deserialize, IntoParams)]
+struct LeaderboardQuery {
+    /// week or month (default week)
+    period: Option<String>,
+    /// deliveries, earnings or rating (default deliveries)
+    metric: Option<String>,
+    /// Maximum entries (default 50)
+    limit: Option<usize>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/leaderboard",
+    tag = "Analytics",
+    params(LeaderboardQuery),
+    responses(
+        (status = 200, description = "Opted-in couriers ranked by the chosen metric", body = [analytics::LeaderboardEntry]),
+        (status = 400, description = "Unknown period or metric")
+    )
+)]
+async fn get_leaderboard(
+    data: web::Data<AppState>,
+    query: web::Query<LeaderboardQuery>,
+) -> Result<HttpResponse, Error> {
+    let Some(metric) = analytics::LeaderboardMetric::parse(query.metric.as_deref().unwrap_or("deliveries")) else {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, "metric must be deliveries, earnings or rating")));
+    };
+
+    if data.index.read().await.indexed_at == 0 {
+        data.refresh_index().await
+            .map_err(backend_error)?;
+    }
+
+    let index = data.index.read().await;
+    let Some(entries) = index.leaderboards.ranked(query.period.as_deref().unwrap_or("week"), metric, query.limit.unwrap_or(50)) else {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, "period must be week or month")));
+    };
+
+    Ok(HttpResponse::Ok().json(entries))
+}
+
+#[derive(Deserialize, IntoParams)]
+struct HeatmapQuery {
+    /// Bounding box: min_lng,min_lat,max_lng,max_lat
+    bbox: Option<String>,
+    /// Map zoom level; cells are the slippy-map tiles at this zoom (default 12, max 18)
+    zoom: Option<u8>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/stats/heatmap",
+    tag = "Analytics",
+    params(HeatmapQuery),
+    responses(
+        (status = 200, description = "Pickup and dropoff counts per grid cell", body = analytics::Heatmap),
+        (status = 400, description = "Invalid bounding box")
+    )
+)]
+async fn get_heatmap(
+    data: web::Data<AppState>,
+    query: web::Query<HeatmapQuery>,
+) -> Result<HttpResponse, Error> {
+    let bbox = match query.bbox.as_deref().map(BoundingBox::parse).transpose() {
+        Ok(bbox) => bbox,
+        Err(e) => {
+            return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, e)));
+        }
+    };
+
+    let deliveries = data.indexed_deliveries().await
+        .map_err(backend_error)?;
+
+    let heatmap = analytics::Heatmap::build(
+        deliveries.iter().filter(|d| !d.private),
+        bbox.as_ref(),
+        query.zoom.unwrap_or(analytics::DEFAULT_HEATMAP_ZOOM),
+    );
+
+    Ok(HttpResponse::Ok().json(heatmap))
+}
+
+#[derive(Deserialize, IntoParams)]
+struct PriceHistoryQuery {
+    from_geohash: String,
+    to_geohash: String,
+    interval: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/pricing/history",
+    tag = "Analytics",
+    params(PriceHistoryQuery),
+    responses(
+        (status = 200, description = "Accepted price distribution for the route", body = analytics::RoutePriceHistory),
+        (status = 400, description = "Invalid query")
+    )
+)]
+async fn get_price_history(
+    data: web::Data<AppState>,
+    query: web::Query<PriceHistoryQuery>,
+) -> Result<HttpResponse, Error> {
+    if query.from_geohash.is_empty() || query.to_geohash.is_empty() {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, "from_geohash and to_geohash are required")));
+    }
+
+    let interval_secs = match query.interval.as_deref().unwrap_or("month") {
+        "day" => 86_400,
+        "week" => 604_800,
+        "month" => 2_592_000,
+        _ => {
+            return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, "interval must be day, week, or month")));
+        }
+    };
+
+    let deliveries = data.indexed_deliveries().await
+        .map_err(backend_error)?;
+
+    let history = analytics::RoutePriceHistory::build(&deliveries, &query.from_geohash, &query.to_geohash, interval_secs);
+
+    Ok(HttpResponse::Ok().json(history))
+}
+
+#[derive(Deserialize, IntoParams)]
+struct ImportQuery {
+    /// Sender npub used for rows without a sender column value
+    sender: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/imports/deliveries",
+    tag = "Deliveries",
+    params(ImportQuery),
+    request_body(content = String, content_type = "text/csv", description = "CSV with a header row"),
+    responses(
+        (status = 200, description = "Per-row import report", body = import::ImportReport),
+        (status = 400, description = "Unreadable CSV or too many rows"),
+        (status = 401, description = "Invalid or revoked API key"),
+        (status = 403, description = "API key lacks the deliveries:create permission")
+    )
+)]
+async fn import_deliveries(
+    http: HttpRequest,
+    data: web::Data<AppState>,
+    query: web::Query<ImportQuery>,
+    body: web::Bytes,
+) -> Result<HttpResponse, Error> {
+    let rows = match import::parse_csv(&body) {
+        Ok(rows) => rows,
+        Err(e) => {
+            return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, e)));
+        }
+    };
+
+    // Rows go through the same checks as POST /deliveries: the sender must be the caller, or a
+    // member of the API key's organization
+    let api_key = api_key_for(&http, &data, api_keys::Permission::CreateDeliveries).await?;
+    let mut report = import::ImportReport::default();
+
+    for (row, parsed) in rows {
+        let validated = parsed
+            .map_err(|e| vec![e])
+            .and_then(|r| r.validate(query.sender.as_deref()));
+
+        let mut imported = match validated {
+            Ok(imported) => imported,
+            Err(errors) => {
+                report.push(import::ImportRowResult { row, delivery_id: None, errors });
+                continue;
+            }
+        };
+
+        let mut errors = Vec::new();
+        if let Err(e) = data.geocode_location(&mut imported.pickup).await {
+            errors.push(format!("pickup {}", e));
+        }
+        if let Err(e) = data.geocode_location(&mut imported.dropoff).await {
+            errors.push(format!("dropoff {}", e));
+        }
+        if !errors.is_empty() {
+            report.push(import::ImportRowResult { row, delivery_id: None, errors });
+            continue;
+        }
+
+        let mut delivery = CreateDeliveryRequest {
+            pickup: imported.pickup,
+            dropoff: imported.dropoff,
+            pickup_address_id: None,
+            dropoff_address_id: None,
+            packages: imported.packages,
+            offer_amount: imported.offer_amount,
+            insurance_amount: imported.insurance_amount,
+            time_window: imported.time_window,
+            sender: imported.sender,
+            min_courier_reputation: None,
+            min_completed_deliveries: None,
+            allowed_couriers: Vec::new(),
+            private: false,
+            organization: None,
+            recipient_email: None,
+            recipient_phone: None,
+            package_photos: Vec::new(),
+            timezone: None,
+            draft: false,
+            auto_relist: false,
+        }
+        .into_delivery();
+        delivery.organization = api_key.as_ref().map(|key| key.organization.clone());
+
+        if let Err(e) = check_sender_identity(&http, &data, &delivery.sender, api_key.is_some()).await {
+            report.push(import::ImportRowResult { row, delivery_id: None, errors: vec![e.to_string()] });
+            continue;
+        }
+        match data.new_delivery_problem(&mut delivery).await {
+            Ok(None) => {}
+            Ok(Some(problem)) => {
+                report.push(import::ImportRowResult { row, delivery_id: None, errors: vec![problem.detail] });
+                continue;
+            }
+            Err(e) => return Err(backend_error(e)),
+        }
+
+        match data.delivery_service().create(&delivery).await {
+            Ok(()) => report.push(import::ImportRowResult { row, delivery_id: Some(delivery.id), errors }),
+            Err(e) => report.push(import::ImportRowResult { row, delivery_id: None, errors: vec![format!("publish failed: {}", e)] }),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(report))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/uploads",
+    tag = "Deliveries",
+    request_body(content = Vec<u8>, content_type = "image/*", description = "JPEG, PNG, GIF or WebP image, up to 5 MiB"),
+    responses(
+        (status = 201, description = "Image stored; reference it by `sha256`, e.g. in `package_photos`", body = uploads::Upload),
+        (status = 400, description = "Empty, too large, or not an image"),
+        (status = 401, description = "Missing or invalid session token"),
+        (status = 429, description = "The uploader's daily quota is used up")
+    )
+)]
+async fn upload_file(
+    user: AuthenticatedUser,
+    data: web::Data<AppState>,
+    body: web::Bytes,
+) -> Result<HttpResponse, Error> {
+    match data.uploads.put(&body, &user.npub(), Utc::now().timestamp()).await {
+        Ok(upload) => Ok(HttpResponse::Created().json(upload)),
+        Err(uploads::UploadRefusal::Invalid(e)) => Ok(problem_response(Problem::new(ProblemType::InvalidRequest, e))),
+        Err(uploads::UploadRefusal::OverQuota(e)) => Ok(problem_response(Problem::new(ProblemType::TooManyRequests, e))),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/uploads/{sha256}",
+    tag = "Deliveries",
+    params(
+        ("sha256" = String, Path, description = "Hex sha256 of the image"),
+    ),
+    responses(
+        (status = 200, description = "The image", content_type = "image/*"),
+        (status = 404, description = "No upload with that hash")
+    )
+)]
+async fn get_upload(
+    data: web::Data<AppState>,
+    hash: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let Some((bytes, content_type)) = data.uploads.get(&hash).await.map_err(backend_error)? else {
+        return Ok(problem_response(Problem::new(ProblemType::NotFound, "Upload not found")));
+    };
+
+    // Content-addressed, so the bytes behind a URL never change
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header((actix_web::http::header::CACHE_CONTROL, "public, max-age=31536000, immutable"))
+        .body(bytes))
+}
+
+#[derive(Deserialize, IntoParams)]
+struct OrderWebhookQuery {
+    /// generic, shopify or woocommerce; detected from the platform's headers when omitted
+    source: Option<String>,
+    /// Sender npub for orders that don't name one
+    sender: Option<String>,
+    /// Sats offered for orders that don't set an amount
+    offer_amount: Option<Amount>,
+    /// API key, for platforms that can't send an X-Api-Key header
+    key: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/integrations/orders",
+    tag = "Integrations",
+    params(OrderWebhookQuery),
+    request_body(content = integrations::Order, description = "Generic order, or a Shopify or WooCommerce order webhook"),
+    responses(
+        (status = 200, description = "Delivery created for the order, or the one already created for it", body = Object),
+        (status = 400, description = "Unreadable order, or missing sender, pickup, address, items or amount"),
+        (status = 401, description = "Missing, invalid or revoked API key"),
+        (status = 403, description = "API key lacks deliveries:create")
+    )
+)]
+async fn ingest_order(
+    http: HttpRequest,
+    data: web::Data<AppState>,
+    query: web::Query<OrderWebhookQuery>,
+    body: web::Bytes,
+) -> Result<HttpResponse, Error> {
+    let secret = http.headers().get(api_keys::HEADER).and_then(|v| v.to_str().ok()).or(query.key.as_deref());
+    let Some(secret) = secret else {
+        return Ok(problem_response(Problem::new(ProblemType::Unauthorized, "Order webhooks need an API key")));
+    };
+    let Some(key) = data.find_api_key(secret).await.map_err(backend_error)? else {
+        return Ok(problem_response(Problem::new(ProblemType::Unauthorized, "Invalid or revoked API key")));
+    };
+    if !key.allows(api_keys::Permission::CreateDeliveries) {
+        return Ok(problem_response(Problem::new(ProblemType::Forbidden, "API key lacks the required permission")));
+    }
+
+    let source = match &query.source {
+        Some(source) => match integrations::OrderSource::parse(source) {
+            Some(source) => source,
+            None => return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, format!("Unknown order source '{}'", source)))),
+        },
+        None => integrations::OrderSource::detect(http.headers().keys().map(|name| name.to_string())),
+    };
+    let order = match integrations::parse_order(source, &body) {
+        Ok(order) => order,
+        Err(e) => return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, e))),
+    };
+
+    // Webhooks are retried until acknowledged; answer retries with the delivery already created
+    let id = integrations::delivery_id(&key.organization, source, &order.order_id);
+    if let Some(existing) = data.get_delivery_by_id(&id).await.map_err(backend_error)? {
+        return Ok(HttpResponse::Ok().json(serde_json::json!({
+            "id": id,
+            "status": "exists",
+            "delivery": existing
+        })));
+    }
+
+    let sender = order.sender.clone().or_else(|| query.sender.clone());
+    let default_pickup = match &sender {
+        Some(sender) if order.pickup.is_none() => data.get_user_settings(sender).await
+            .map_err(backend_error)?
+            .default_pickup,
+        _ => None,
+    };
+    let defaults = integrations::OrderDefaults { sender, pickup: default_pickup, offer_amount: query.offer_amount };
+    let order_id = order.order_id.clone();
+    // Bad customer contact details shouldn't lose the order
+    let contact = notify::RecipientContact {
+        email: order.customer_email.clone().filter(|address| email::is_valid_address(address)),
+        phone: order.customer_phone.clone().filter(|phone| notify::is_valid_phone(phone)),
+    };
+    let mut imported = match order.into_delivery(defaults) {
+        Ok(imported) => imported,
+        Err(errors) => {
+            return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, errors.join("; "))));
+        }
+    };
+
+    for (label, location) in [("pickup", &mut imported.pickup), ("dropoff", &mut imported.dropoff)] {
+        if let Err(e) = data.geocode_location(location).await {
+            return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, format!("{} {}", label, e))));
+        }
+    }
+
+    let mut delivery = CreateDeliveryRequest {
+        pickup: imported.pickup,
+        dropoff: imported.dropoff,
+        pickup_address_id: None,
+        dropoff_address_id: None,
+        packages: imported.packages,
+        offer_amount: imported.offer_amount,
+        insurance_amount: imported.insurance_amount,
+        time_window: imported.time_window,
+        sender: imported.sender,
+        min_courier_reputation: None,
+        min_completed_deliveries: None,
+        allowed_couriers: Vec::new(),
+        private: false,
+        organization: Some(key.organization),
+        recipient_email: None,
+        recipient_phone: None,
+        package_photos: Vec::new(),
+        timezone: None,
+        draft: false,
+        auto_relist: false,
+    }
+    .into_delivery();
+    delivery.id = id.clone();
+
+    if let Some(response) = validate_new_delivery(&data, &mut delivery).await? {
+        return Ok(response);
+    }
+
+    data.delivery_service().create(&delivery).await
+        .map_err(|e| service_error(e, &id))?;
+    save_recipient_contact(&data, &id, &contact).await?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "id": id,
+        "status": "created",
+        "order_id": order_id,
+        "delivery": delivery
+    })))
+}
+
+fn integration_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/orders", web::post().to(ingest_order));
+}
+
+#[derive(Deserialize, IntoParams)]
+struct ExportQuery {
+    /// Output format: csv or json (default json)
+    format: Option<String>,
+    /// Earliest created_at (Unix seconds)
+    from: Option<i64>,
+    /// Latest created_at (Unix seconds)
+    to: Option<i64>,
+    /// Comma-separated statuses to include
+    status: Option<String>,
+    /// Comma-separated fields to include
+    fields: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/export/deliveries",
+    tag = "Deliveries",
+    params(ExportQuery),
+    responses(
+        (status = 200, description = "Streamed export (JSON array or CSV)"),
+        (status = 400, description = "Invalid format, field, or status")
+    )
+)]
+async fn export_deliveries(
+    data: web::Data<AppState>,
+    query: web::Query<ExportQuery>,
+    user: Option<AuthenticatedUser>,
+) -> Result<HttpResponse, Error> {
+    let as_csv = match query.format.as_deref().unwrap_or("json") {
+        "csv" => true,
+        "json" => false,
+        _ => {
+            return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, "format must be csv or json")));
+        }
+    };
+
+    let (fields, statuses) = match (
+        export::parse_fields(query.fields.as_deref()),
+        export::parse_statuses(query.status.as_deref()),
+    ) {
+        (Ok(fields), Ok(statuses)) => (fields, statuses),
+        (Err(e), _) | (_, Err(e)) => {
+            return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, e)));
+        }
+    };
+
+    let deliveries = data.get_all_deliveries().await
+        .map_err(backend_error)?;
+
+    let viewer = Viewer::for_deliveries(&data, user.as_ref(), &deliveries).await?;
+    let (from, to) = (query.from, query.to);
+    let selected = deliveries.into_iter()
+        .filter(move |d| viewer.can_see(d))
+        .filter(move |d| analytics::in_range(d.created_at, from, to))
+        .filter(move |d| statuses.as_ref().is_none_or(|s| s.contains(&d.status)));
+
+    // Serialize record by record so large exports are streamed rather than buffered
+    let chunks = export::render(selected, fields, as_csv);
+    let body = futures_util::stream::iter(chunks.map(|c| Ok::<_, Error>(web::Bytes::from(c))));
+    let (content_type, filename) = if as_csv {
+        ("text/csv; charset=utf-8", "deliveries.csv")
+    } else {
+        ("application/json", "deliveries.json")
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header(("Content-Disposition", format!("attachment; filename=\"{}\"", filename)))
+        .streaming(body))
+}
+
+#[derive(Deserialize, ToSchema)]
+struct LocationPingRequest {
+    lat: f64,
+    lng: f64,
+    accuracy: Option<f32>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/deliveries/{id}/location",
+    tag = "Tracking",
+    params(
+        ("id" = String, Path, description = "Delivery ID"),
+    ),
+    request_body = LocationPingRequest,
+    responses(
+        (status = 200, description = "Location recorded", body = tracking::LocationPing),
+        (status = 400, description = "Delivery is not in progress or coordinates are invalid"),
+        (status = 401, description = "Missing or invalid session token"),
+        (status = 403, description = "Courier is not assigned to this delivery"),
+        (status = 404, description = "Delivery not found")
+    )
+)]
+async fn record_location(
+    user: AuthenticatedUser,
+    data: web::Data<AppState>,
+    delivery_id: web::Path<String>,
+    req: web::Json<LocationPingRequest>,
+) -> Result<HttpResponse, Error> {
+    let courier = user.npub();
+    let delivery = data.get_delivery_by_id(&delivery_id).await
+        .map_err(backend_error)?
+        .ok_or_else(|| delivery_not_found(&delivery_id))?;
+
+    if delivery.status != DeliveryStatus::Accepted && delivery.status != DeliveryStatus::InTransit {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidTransition, "Can only track accepted or in-transit deliveries").for_delivery(&delivery_id)));
+    }
+
+    if !(-90.0..=90.0).contains(&req.lat) || !(-180.0..=180.0).contains(&req.lng) {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, "Coordinates out of range").for_delivery(&delivery_id)));
+    }
+
+    if !delivery.accepted_courier().is_some_and(|c| user.is(c)) {
+        return Ok(problem_response(Problem::new(ProblemType::Forbidden, "Courier is not assigned to this delivery").for_delivery(&delivery_id)));
+    }
+
+    let mut ping = tracking::LocationPing {
+        delivery_id: delivery.id.clone(),
+        courier: courier.clone(),
+        lat: req.lat,
+        lng: req.lng,
+        accuracy: req.accuracy,
+        timestamp: Utc::now().timestamp(),
+        anomalies: vec![],
+    };
+
+    // Compare against the previous ping for teleports and tracking gaps
+    let previous = data.get_location_pings(&delivery.id).await.unwrap_or_default();
+    if let Some(previous) = previous.last() {
+        ping.anomalies = tracking::detect_anomalies(previous, &ping, data.fraud_check.max_speed_mps, data.tracking_max_gap_secs);
+    }
+
+    data.publish_location_ping(&ping).await
+        .map_err(backend_error)?;
+
+    if !ping.anomalies.is_empty() && data.notify_tracking_anomalies {
+        data.notify_location_anomalies(&delivery, &ping).await;
+    }
+    let mut track = previous;
+    track.push(ping.clone());
+    data.notify_recipient_eta(&delivery, &track).await;
+
+    Ok(HttpResponse::Ok().json(ping))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/deliveries/{id}/track",
+    tag = "Tracking",
+    params(
+        ("id" = String, Path, description = "Delivery ID"),
+    ),
+    responses(
+        (status = 200, description = "Location pings, oldest first", body = [tracking::LocationPing]),
+        (status = 404, description = "Delivery not found")
+    )
+)]
+async fn get_track(
+    data: web::Data<AppState>,
+    delivery_id: web::Path<String>,
+    user: Option<AuthenticatedUser>,
+) -> Result<HttpResponse, Error> {
+    visible_delivery(&data, &delivery_id, user.as_ref()).await?;
+    let pings = data.get_location_pings(&delivery_id).await
+        .map_err(backend_error)?;
+
+    Ok(HttpResponse::Ok().json(pings))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/deliveries/{id}/track.gpx",
+    tag = "Tracking",
+    params(
+        ("id" = String, Path, description = "Delivery ID"),
+    ),
+    responses(
+        (status = 200, description = "Courier track as GPX 1.1", content_type = "application/gpx+xml"),
+        (status = 404, description = "Delivery not found")
+    )
+)]
+async fn get_track_gpx(
+    data: web::Data<AppState>,
+    delivery_id: web::Path<String>,
+    user: Option<AuthenticatedUser>,
+) -> Result<HttpResponse, Error> {
+    visible_delivery(&data, &delivery_id, user.as_ref()).await?;
+    let pings = data.get_location_pings(&delivery_id).await
+        .map_err(backend_error)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/gpx+xml")
+        .insert_header(("Content-Disposition", format!("attachment; filename=\"{}.gpx\"", delivery_id)))
+        .body(tracking::to_gpx(&delivery_id, &pings)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/deliveries/{id}/track.geojson",
+    tag = "Tracking",
+    params(
+        ("id" = String, Path, description = "Delivery ID"),
+    ),
+    responses(
+        (status = 200, description = "Courier track as a GeoJSON FeatureCollection", content_type = "application/geo+json"),
+        (status = 404, description = "Delivery not found")
+    )
+)]
+async fn get_track_geojson(
+    data: web::Data<AppState>,
+    delivery_id: web::Path<String>,
+    user: Option<AuthenticatedUser>,
+) -> Result<HttpResponse, Error> {
+    visible_delivery(&data, &delivery_id, user.as_ref()).await?;
+    let pings = data.get_location_pings(&delivery_id).await
+        .map_err(backend_error)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/geo+json")
+        .json(tracking::to_geojson(&delivery_id, &pings)))
+}
+
+#[derive(Deserialize, IntoParams)]
+struct GeoJsonQuery {
+    /// Bounding box: min_lng,min_lat,max_lng,max_lat
+    bbox: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/deliveries/geojson",
+    tag = "Deliveries",
+    params(GeoJsonQuery),
+    responses(
+        (status = 200, description = "Open deliveries as a GeoJSON FeatureCollection of pickup points", content_type = "application/geo+json"),
+        (status = 400, description = "Invalid bbox")
+    )
+)]
+async fn get_deliveries_geojson(
+    data: web::Data<AppState>,
+    query: web::Query<GeoJsonQuery>,
+) -> Result<HttpResponse, Error> {
+    let bbox = match query.bbox.as_deref().map(BoundingBox::parse).transpose() {
+        Ok(bbox) => bbox,
+        Err(e) => {
+            return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, e)));
+        }
+    };
+
+    let deliveries = data.indexed_deliveries().await
+        .map_err(backend_error)?;
+
+    let area = data.service_area.read().await;
+
+    let now = Utc::now().timestamp();
+    let open = deliveries.iter()
+        .filter(|d| d.status == DeliveryStatus::Open && !d.private && d.expires_at.is_none_or(|e| e > now))
+        .filter(|d| match (area.as_ref(), &d.pickup.coordinates) {
+            (Some(area), Some(point)) => area.contains(point),
+            _ => true,
+        })
+        .filter(|d| match (&bbox, &d.pickup.coordinates) {
+            (Some(bbox), Some(point)) => bbox.contains(point),
+            (Some(_), None) => false,
+            (None, _) => true,
+        });
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/geo+json")
+        .json(geojson::feature_collection(open)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/deliveries/{id}/qr/{kind}.{format}",
+    tag = "Tracking",
+    params(
+        ("id" = String, Path, description = "Delivery ID"),
+        ("kind" = String, Path, description = "tracking or dropoff"),
+        ("format" = String, Path, description = "png or svg"),
+    ),
+    responses(
+        (status = 200, description = "QR code image"),
+        (status = 400, description = "Unknown QR kind or format"),
+        (status = 401, description = "Dropoff code requested without signing in"),
+        (status = 403, description = "Dropoff code requested by someone other than the sender"),
+        (status = 404, description = "Delivery not found")
+    )
+)]
+async fn get_qr_code(
+    http: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<(String, String, String)>,
+) -> Result<HttpResponse, Error> {
+    let (delivery_id, kind, format) = path.into_inner();
+
+    let Some(format) = qr::QrFormat::parse(&format) else {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, "format must be png or svg").for_delivery(&delivery_id)));
+    };
+
+    let delivery = data.get_delivery_by_id(&delivery_id).await
+        .map_err(backend_error)?
+        .ok_or_else(|| delivery_not_found(&delivery_id))?;
+
+    let payload = match kind.as_str() {
+        "tracking" => data.tracking_url(&delivery.id),
+        "dropoff" => {
+            // Whoever holds the code can confirm the dropoff, so it stays with the signed-in sender
+            let caller = <AuthenticatedUser as actix_web::FromRequest>::extract(&http).await?;
+            if !caller.is(&delivery.sender) {
+                return Ok(problem_response(Problem::new(ProblemType::Forbidden, "Only the sender can view the dropoff code").for_delivery(&delivery_id)));
+            }
+            data.dropoff_confirm_url(&delivery.id)
+        }
+        _ => {
+            return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, "QR kind must be tracking or dropoff").for_delivery(&delivery_id)));
+        }
+    };
+
+    let image = qr::render(&payload, format)
+        .map_err(backend_error)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .body(image))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/deliveries/{id}/label.pdf",
+    tag = "Tracking",
+    params(
+        ("id" = String, Path, description = "Delivery ID"),
+    ),
+    responses(
+        (status = 200, description = "4x6 inch PDF shipping label, one page per package", content_type = "application/pdf"),
+        (status = 401, description = "Neither signed in nor carrying an API key"),
+        (status = 403, description = "Label requested by someone other than the sender, or with another organization's API key"),
+        (status = 404, description = "Delivery not found")
+    )
+)]
+async fn get_delivery_label(
+    http: HttpRequest,
+    data: web::Data<AppState>,
+    delivery_id: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let delivery = data.get_delivery_by_id(&delivery_id).await
+        .map_err(backend_error)?
+        .ok_or_else(|| delivery_not_found(&delivery_id))?;
+
+    // The label carries the unsealed contacts and the pickup code: only the signed-in sender, or
+    // an API key of the delivery's organization, may print it
+    match api_key_for(&http, &data, api_keys::Permission::CreateDeliveries).await? {
+        Some(key) if delivery.organization.as_ref() != Some(&key.organization) => {
+            return Ok(problem_response(Problem::new(ProblemType::Forbidden, "API key is scoped to another organization").for_delivery(&delivery_id)));
+        }
+        Some(_) => {}
+        None => {
+            let caller = <AuthenticatedUser as actix_web::FromRequest>::extract(&http).await?;
+            if !caller.is(&delivery.sender) {
+                return Ok(problem_response(Problem::new(ProblemType::Forbidden, "Only the sender can print the label").for_delivery(&delivery_id)));
+            }
+        }
+    }
+
+    let contacts = data.unseal_contacts(&delivery).await
+        .map_err(backend_error)?;
+    let label = label::ShippingLabel {
+        delivery: &delivery,
+        pickup_contact: contacts.pickup.as_ref(),
+        dropoff_contact: contacts.dropoff.as_ref(),
+        redaction: data.label_contacts,
+        tracking_url: data.tracking_url(&delivery.id),
+        pickup_code: data.pickup_code(&delivery.id),
+    };
+    let pdf = label.render()
+        .map_err(backend_error)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/pdf")
+        .insert_header(("Content-Disposition", format!("inline; filename=\"label-{}.pdf\"", delivery.id)))
+        .body(pdf))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/open/{entity}",
+    tag = "Tracking",
+    params(
+        ("entity" = String, Path, description = "NIP-19 naddr, nevent or note of a delivery-related event"),
+    ),
+    responses(
+        (status = 302, description = "Redirect to the delivery's tracking page"),
+        (status = 400, description = "Not a NIP-19 event reference"),
+        (status = 404, description = "No delivery found for this event")
+    )
+)]
+async fn open_nostr_entity(
+    data: web::Data<AppState>,
+    entity: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let entity = entity.trim_start_matches("nostr:");
+    let Ok(entity) = Nip19::from_bech32(entity) else {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, "Expected a NIP-19 naddr, nevent or note")));
+    };
+
+    let delivery_id = data.resolve_nip19_delivery(entity).await
+        .map_err(backend_error)?
+        .ok_or_else(|| problem_error(Problem::new(ProblemType::NotFound, "Delivery not found")))?;
+
+    Ok(HttpResponse::Found()
+        .insert_header((actix_web::http::header::LOCATION, data.tracking_url(&delivery_id)))
+        .finish())
+}
+
+#[derive(Deserialize, ToSchema)]
+struct DropoffConfirmRequest {
+    code: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/deliveries/{id}/dropoff-confirm",
+    tag = "Tracking",
+    params(
+        ("id" = String, Path, description = "Delivery ID"),
+    ),
+    request_body = DropoffConfirmRequest,
+    responses(
+        (status = 200, description = "Handoff confirmed and delivery completed", body = Object),
+        (status = 400, description = "Delivery is not in progress"),
+        (status = 403, description = "Invalid dropoff code, or too many invalid codes were submitted"),
+        (status = 404, description = "Delivery not found")
+    )
+)]
+async fn confirm_dropoff_code(
+    data: web::Data<AppState>,
+    delivery_id: web::Path<String>,
+    req: web::Json<DropoffConfirmRequest>,
+) -> Result<HttpResponse, Error> {
+    let delivery = data.get_delivery_by_id(&delivery_id).await
+        .map_err(backend_error)?
+        .ok_or_else(|| delivery_not_found(&delivery_id))?;
+
+    if delivery.status != DeliveryStatus::Accepted && delivery.status != DeliveryStatus::InTransit {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidTransition, "Can only confirm dropoff for accepted or in-transit deliveries").for_delivery(&delivery_id)));
+    }
+
+    // Checked and counted under one lock, so parallel guesses can't get past the limit
+    {
+        let mut attempts = data.dropoff_code_attempts.write().await;
+        if attempts.locked(&delivery.id) {
+            return Ok(problem_response(Problem::new(ProblemType::Forbidden, "Too many invalid dropoff codes; the courier has to complete the delivery with proof").for_delivery(&delivery_id)));
+        }
+        if !codes::codes_match(&data.dropoff_code(&delivery.id), req.code.trim()) {
+            let left = attempts.fail(&delivery.id);
+            return Ok(problem_response(Problem::new(ProblemType::Forbidden, format!("Invalid dropoff code, {} attempts left", left)).for_delivery(&delivery_id)));
+        }
+        attempts.clear(&delivery.id);
+    }
+
+    let now = Utc::now().timestamp();
+    let proof = ProofOfDelivery::new(now)
+        .comments(Some("Recipient confirmed handoff with dropoff code".to_string()));
+    let delivery = data.delivery_service().complete(delivery, proof, now).await
+        .map_err(|e| service_error(e, &delivery_id))?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "status": "completed",
+        "delivery": delivery
+    })))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/deliveries/{id}/interest",
+    tag = "Deliveries",
+    params(
+        ("id" = String, Path, description = "Delivery ID"),
+    ),
+    responses(
+        (status = 200, description = "Replies to the delivery's NIP-99 listing", body = [classifieds::ListingInterest]),
+        (status = 404, description = "Delivery not found")
+    )
+)]
+async fn get_listing_interest(
+    data: web::Data<AppState>,
+    delivery_id: web::Path<String>,
+    user: Option<AuthenticatedUser>,
+) -> Result<HttpResponse, Error> {
+    visible_delivery(&data, &delivery_id, user.as_ref()).await?;
+    let interest: Vec<classifieds::ListingInterest> = data.listing_interest.read().await
+        .iter()
+        .filter(|i| i.delivery_id == delivery_id.as_str())
+        .cloned()
+        .collect();
+
+    Ok(HttpResponse::Ok().json(interest))
+}
+
+// Caller signed in through /api/auth, from an `Authorization: Bearer <session token>` header, or
+// signing the request itself with NIP-98 (`Authorization: Nostr <base64 event>`)
+#[derive(Clone)]
+struct AuthenticatedUser {
+    pubkey: PublicKey,
+    expires_at: i64,
+}
+
+impl AuthenticatedUser {
+    // Routes about one user's own data only serve that user
+    fn require(&self, user: &PublicKey) -> Result<(), Error> {
+        if self.pubkey != *user {
+            return Err(problem_error(Problem::new(ProblemType::Forbidden, "Signed in as another user")));
+        }
+        Ok(())
+    }
+
+    fn npub(&self) -> String {
+        PubkeyId::from(self.pubkey).npub()
+    }
+
+    // Whether the caller is the user stored as `npub` on a delivery or event
+    fn is(&self, npub: &str) -> bool {
+        PubkeyId::parse(npub).is_ok_and(|id| *id == self.pubkey)
+    }
+}
+
+impl actix_web::FromRequest for AuthenticatedUser {
+    type Error = Error;
+    type Future = std::future::Ready<Result<Self, Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut actix_web::dev::Payload) -> Self::Future {
+        let unauthorized = |detail: String| problem_error(Problem::new(ProblemType::Unauthorized, detail));
+
+        let result = (|| {
+            let data = req.app_data::<web::Data<AppState>>().ok_or_else(|| unauthorized("Authentication unavailable".to_string()))?;
+            let header = req.headers()
+                .get(actix_web::http::header::AUTHORIZATION)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or_default();
+
+            // Extracted again by helpers in the same request; a NIP-98 event is only good once
+            if let Some(user) = req.extensions().get::<AuthenticatedUser>() {
+                return Ok(user.clone());
+            }
+
+            // A NIP-98 event signed for this request, checked against the public and the direct URL
+            if let Some(token) = header.strip_prefix("Nostr ") {
+                let urls = {
+                    let info = req.connection_info();
+                    [
+                        format!("{}{}", data.public_url.trim_end_matches('/'), req.uri()),
+                        format!("{}://{}{}", info.scheme(), info.host(), req.uri()),
+                    ]
+                };
+                let now = Utc::now().timestamp();
+                let body = req.extensions().get::<auth::BodyHash>().cloned();
+                let http_auth = auth::verify_http_auth(token, &urls, req.method().as_str(), body.as_ref(), now).map_err(unauthorized)?;
+                let first_use = data.http_auth_replays.lock()
+                    .is_ok_and(|mut replays| replays.first_use(&http_auth, now));
+                if !first_use {
+                    return Err(unauthorized("NIP-98 event was already used".to_string()));
+                }
+                let user = AuthenticatedUser { pubkey: http_auth.pubkey, expires_at: now + auth::HTTP_AUTH_WINDOW_SECS };
+                req.extensions_mut().insert(user.clone());
+                return Ok(user);
+            }
+
+            let token = header
+                .strip_prefix("Bearer ")
+                .ok_or_else(|| unauthorized("Missing session token".to_string()))?;
+
+            let claims = auth::verify_session(&data.auth, token.trim(), Utc::now().timestamp()).map_err(unauthorized)?;
+            let pubkey = PublicKey::parse(&claims.sub).map_err(|e| unauthorized(e.to_string()))?;
+            Ok(AuthenticatedUser { pubkey, expires_at: claims.exp })
+        })();
+
+        std::future::ready(result)
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/auth/challenge",
+    tag = "Auth",
+    responses(
+        (status = 200, description = "Nonce to sign in a kind 22242 event's `challenge` tag", body = auth::Challenge)
+    )
+)]
+async fn auth_challenge(data: web::Data<AppState>) -> Result<HttpResponse, Error> {
+    let challenge = data.auth_challenges.write().await.issue(Utc::now().timestamp());
+    Ok(HttpResponse::Ok().json(challenge))
+}
+
+#[derive(Deserialize, ToSchema)]
+struct VerifyLoginRequest {
+    /// Kind 22242 event signed by the user (e.g. with NIP-07 `signEvent`) carrying the challenge
+    #[schema(value_type = Object)]
+    event: Event,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/auth/verify",
+    tag = "Auth",
+    request_body = VerifyLoginRequest,
+    responses(
+        (status = 200, description = "Session token for the event's pubkey", body = auth::Session),
+        (status = 401, description = "Invalid signature, or unknown, used or expired challenge")
+    )
+)]
+async fn auth_verify(
+    data: web::Data<AppState>,
+    req: web::Json<VerifyLoginRequest>,
+) -> Result<HttpResponse, Error> {
+    let now = Utc::now().timestamp();
+    let challenge = match auth::login_challenge(&req.event, now) {
+        Ok(challenge) => challenge,
+        Err(e) => return Ok(problem_response(Problem::new(ProblemType::Unauthorized, e))),
+    };
+    if !data.auth_challenges.write().await.take(challenge, now) {
+        return Ok(problem_response(Problem::new(ProblemType::Unauthorized, "Unknown, used or expired challenge")));
+    }
+
+    let session = auth::issue_session(&data.auth, &req.event.pubkey, now)
+        .map_err(backend_error)?;
+
+    Ok(HttpResponse::Ok().json(session))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/auth/session",
+    tag = "Auth",
+    responses(
+        (status = 200, description = "The signed-in pubkey and when the session expires"),
+        (status = 401, description = "Missing, invalid or expired session token")
+    )
+)]
+async fn auth_session(user: AuthenticatedUser) -> Result<HttpResponse, Error> {
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "pubkey": PubkeyId::from(user.pubkey),
+        "expires_at": user.expires_at,
+    })))
+}
+
+fn auth_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/challenge", web::post().to(auth_challenge))
+        .route("/verify", web::post().to(auth_verify))
+        .route("/session", web::get().to(auth_session));
+}
+
+// Admin endpoints require `Authorization: Bearer $ADMIN_TOKEN` and are disabled without it
+fn admin_denied(req: &HttpRequest, data: &AppState) -> Option<HttpResponse> {
+    let Some(token) = &data.admin_token else {
+        return Some(problem_response(Problem::new(ProblemType::Forbidden, "Admin API is disabled; set ADMIN_TOKEN to enable it")));
+    };
+
+    let provided = req.headers()
+        .get(actix_web::http::header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+
+    if provided.is_some_and(|p| codes::codes_match(token, p)) {
+        None
+    } else {
+        Some(problem_response(Problem::new(ProblemType::Unauthorized, "Invalid admin token")))
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/status",
+    tag = "Admin",
+    responses(
+        (status = 200, description = "Version, signing key, event kinds, relay connections, cache sizes, background jobs and uptime", body = status::ServerStatus),
+        (status = 401, description = "Invalid admin token")
+    )
+)]
+async fn get_admin_status(
+    http: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    if let Some(denied) = admin_denied(&http, &data) {
+        return Ok(denied);
+    }
+
+    let mut relays: Vec<status::RelayInfo> = data.nostr_client.relays().await
+        .iter()
+        .map(|(url, relay)| status::RelayInfo {
+            url: url.to_string(),
+            status: relay.status().to_string(),
+            proxy: match relay.connection_mode() {
+                ConnectionMode::Proxy(addr) => Some(addr.to_string()),
+                _ => None,
+            },
+        })
+        .collect();
+    relays.sort_by(|a, b| a.url.cmp(&b.url));
+
+    let index = data.index.read().await;
+    let publish_log = data.publish_log.read().await;
+    let now = Utc::now().timestamp();
+    Ok(HttpResponse::Ok().json(status::ServerStatus {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        system_pubkey: data.system_pubkey.to_bech32().map_err(backend_error)?,
+        remote_signer: data.bunker.is_some(),
+        kinds: status::published_kinds(data.classifieds_enabled),
+        federated_kinds: data.federation.kinds.iter().map(|k| k.as_u16()).collect(),
+        relays,
+        caches: status::CacheSizes {
+            indexed_deliveries: index.deliveries.len(),
+            federated_deliveries: data.federated.read().await.len(),
+            listing_interest: data.listing_interest.read().await.len(),
+            held_reviews: data.held_reviews.read().await.len(),
+            publish_log: publish_log.len(),
+            cached_deliveries: data.cache.deliveries.len(),
+            cached_bids: data.cache.bids.len(),
+            cached_profiles: data.cache.profiles.len(),
+        },
+        redundancy: data.redundancy_report.read().await.clone(),
+        publishes: publish_log.metrics(),
+        jobs: data.jobs.metrics().await,
+        indexed_at: index.indexed_at,
+        started_at: data.started_at,
+        uptime_secs: now - data.started_at,
+    }))
+}
+
+#[derive(Deserialize, IntoParams)]
+struct PublishLogQuery {
+    delivery_id: Option<String>,
+    event_id: Option<String>,
+    // Default 100
+    limit: Option<usize>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/publishes",
+    tag = "Admin",
+    params(PublishLogQuery),
+    responses(
+        (status = 200, description = "Recent publishes, newest first, with each relay's acknowledgment or rejection", body = [publish_log::PublishRecord]),
+        (status = 401, description = "Invalid admin token")
+    )
+)]
+async fn get_publish_log(
+    http: HttpRequest,
+    data: web::Data<AppState>,
+    query: web::Query<PublishLogQuery>,
+) -> Result<HttpResponse, Error> {
+    if let Some(denied) = admin_denied(&http, &data) {
+        return Ok(denied);
+    }
+
+    let records = data.publish_log.read().await.recent(
+        query.delivery_id.as_deref(),
+        query.event_id.as_deref(),
+        query.limit.unwrap_or(100),
+    );
+    Ok(HttpResponse::Ok().json(records))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/service-area",
+    tag = "Admin",
+    responses(
+        (status = 200, description = "Configured service area GeoJSON (null when unrestricted)", body = Object),
+        (status = 401, description = "Invalid admin token")
+    )
+)]
+async fn get_service_area(
+    http: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    if let Some(denied) = admin_denied(&http, &data) {
+        return Ok(denied);
+    }
+
+    let area = data.service_area.read().await;
+    Ok(HttpResponse::Ok().json(area.as_ref().map(|a| &a.geojson)))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/admin/service-area",
+    tag = "Admin",
+    request_body(content = Object, description = "GeoJSON Polygon, MultiPolygon, Feature or FeatureCollection"),
+    responses(
+        (status = 200, description = "Service area replaced", body = Object),
+        (status = 400, description = "Invalid GeoJSON"),
+        (status = 401, description = "Invalid admin token")
+    )
+)]
+async fn set_service_area(
+    http: HttpRequest,
+    data: web::Data<AppState>,
+    body: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, Error> {
+    if let Some(denied) = admin_denied(&http, &data) {
+        return Ok(denied);
+    }
+
+    let area = match service_area::ServiceArea::from_geojson(body.into_inner()) {
+        Ok(area) => area,
+        Err(e) => {
+            return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, e)));
+        }
+    };
+
+    let geojson = area.geojson.clone();
+    *data.service_area.write().await = Some(area);
+
+    Ok(HttpResponse::Ok().json(geojson))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/admin/service-area",
+    tag = "Admin",
+    responses(
+        (status = 200, description = "Service area removed; deliveries are accepted anywhere", body = Object),
+        (status = 401, description = "Invalid admin token")
+    )
+)]
+async fn clear_service_area(
+    http: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    if let Some(denied) = admin_denied(&http, &data) {
+        return Ok(denied);
+    }
+
+    *data.service_area.write().await = None;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "status": "cleared"
+    })))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/reviews",
+    tag = "Admin",
+    responses(
+        (status = 200, description = "Reviews held for approval", body = [moderation::HeldReview]),
+        (status = 401, description = "Invalid admin token")
+    )
+)]
+async fn get_held_reviews(
+    http: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    if let Some(denied) = admin_denied(&http, &data) {
+        return Ok(denied);
+    }
+
+    let held = data.held_reviews.read().await;
+    Ok(HttpResponse::Ok().json(&*held))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/reviews/{id}/approve",
+    tag = "Admin",
+    params(
+        ("id" = String, Path, description = "Held review ID"),
+    ),
+    responses(
+        (status = 200, description = "Review published on its delivery", body = Object),
+        (status = 401, description = "Invalid admin token"),
+        (status = 404, description = "Review or delivery not found")
+    )
+)]
+async fn approve_held_review(
+    http: HttpRequest,
+    data: web::Data<AppState>,
+    review_id: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    if let Some(denied) = admin_denied(&http, &data) {
+        return Ok(denied);
+    }
+
+    let review = data.held_reviews.read().await
+        .iter()
+        .find(|r| r.id == *review_id)
+        .cloned()
+        .ok_or_else(|| problem_error(Problem::new(ProblemType::NotFound, "Review not found")))?;
+
+    let mut delivery = data.get_delivery_by_id(&review.delivery_id).await
+        .map_err(backend_error)?
+        .ok_or_else(|| delivery_not_found(&review.delivery_id))?;
+
+    if review.author == moderation::RECIPIENT_AUTHOR {
+        if let Some(confirmation) = delivery.recipient_confirmation.as_mut() {
+            confirmation.feedback = Some(review.text.clone());
+        }
+    } else {
+        delivery.sender_feedback = Some(review.text.clone());
+    }
+    data.publish_delivery(&delivery).await
+        .map_err(backend_error)?;
+
+    data.held_reviews.write().await.retain(|r| r.id != review.id);
+    data.save_held_reviews().await
+        .map_err(backend_error)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "status": "approved",
+        "delivery": delivery
+    })))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/admin/reviews/{id}",
+    tag = "Admin",
+    params(
+        ("id" = String, Path, description = "Held review ID"),
+    ),
+    responses(
+        (status = 200, description = "Review discarded without publishing", body = Object),
+        (status = 401, description = "Invalid admin token"),
+        (status = 404, description = "Review not found")
+    )
+)]
+async fn reject_held_review(
+    http: HttpRequest,
+    data: web::Data<AppState>,
+    review_id: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    if let Some(denied) = admin_denied(&http, &data) {
+        return Ok(denied);
+    }
+
+    {
+        let mut held = data.held_reviews.write().await;
+        let before = held.len();
+        held.retain(|r| r.id != *review_id);
+        if held.len() == before {
+            return Err(problem_error(Problem::new(ProblemType::NotFound, "Review not found")));
+        }
+    }
+    data.save_held_reviews().await
+        .map_err(backend_error)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "status": "rejected"
+    })))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/fraud",
+    tag = "Admin",
+    responses(
+        (status = 200, description = "Deliveries held for fraud review", body = [DeliveryRequest]),
+        (status = 401, description = "Invalid admin token")
+    )
+)]
+async fn get_fraud_flagged(
+    http: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    if let Some(denied) = admin_denied(&http, &data) {
+        return Ok(denied);
+    }
+
+    let flagged: Vec<DeliveryRequest> = data.indexed_deliveries().await
+        .map_err(backend_error)?
+        .into_iter()
+        .filter(|d| !d.fraud_flags.is_empty())
+        .collect();
+
+    Ok(HttpResponse::Ok().json(flagged))
+}
+
+#[derive(Deserialize, ToSchema)]
+struct FraudReviewRequest {
+    // Clear the flags so the sender can confirm, or move the delivery to Disputed
+    approve: bool,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/deliveries/{id}/fraud-review",
+    tag = "Admin",
+    params(
+        ("id" = String, Path, description = "Delivery ID"),
+    ),
+    request_body = FraudReviewRequest,
+    responses(
+        (status = 200, description = "Review recorded", body = Object),
+        (status = 400, description = "Delivery is not flagged"),
+        (status = 401, description = "Invalid admin token"),
+        (status = 404, description = "Delivery not found")
+    )
+)]
+async fn review_fraud_flags(
+    http: HttpRequest,
+    data: web::Data<AppState>,
+    delivery_id: web::Path<String>,
+    req: web::Json<FraudReviewRequest>,
+) -> Result<HttpResponse, Error> {
+    if let Some(denied) = admin_denied(&http, &data) {
+        return Ok(denied);
+    }
+
+    let action = if req.approve { admin::AdminAction::Approve } else { admin::AdminAction::Reject };
+    act_on_fraud_flags(&data, &delivery_id, action, None).await
+}
+
+async fn act_on_fraud_flags(data: &AppState, delivery_id: &str, action: admin::AdminAction, note: Option<String>) -> Result<HttpResponse, Error> {
+    let mut delivery = data.get_delivery_by_id(delivery_id).await
+        .map_err(backend_error)?
+        .ok_or_else(|| delivery_not_found(delivery_id))?;
+
+    if delivery.fraud_flags.is_empty() {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidTransition, "Delivery is not flagged for review").for_delivery(delivery_id)));
+    }
+
+    if action == admin::AdminAction::Escalate {
+        let escalation = data.escalate(admin::QueueItem::FraudFlag, delivery_id, note).await;
+        return Ok(HttpResponse::Ok().json(serde_json::json!({
+            "status": "escalated",
+            "escalation": escalation
+        })));
+    }
+
+    let approve = action == admin::AdminAction::Approve;
+    delivery.fraud_flags.clear();
+    if !approve {
+        delivery.status = DeliveryStatus::Disputed;
+        delivery.disputed_at = Some(Utc::now().timestamp());
+    }
+
+    data.publish_delivery(&delivery).await
+        .map_err(backend_error)?;
+    data.clear_escalation(admin::QueueItem::FraudFlag, delivery_id).await;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "status": if approve { "approved" } else { "disputed" },
+        "delivery": delivery
+    })))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/fraud/{id}",
+    tag = "Admin",
+    params(
+        ("id" = String, Path, description = "Delivery ID"),
+    ),
+    request_body = admin::ActionRequest,
+    responses(
+        (status = 200, description = "Approve clears the flags, reject moves the delivery to Disputed, escalate records it", body = Object),
+        (status = 400, description = "Delivery is not flagged"),
+        (status = 401, description = "Invalid admin token"),
+        (status = 404, description = "Delivery not found")
+    )
+)]
+async fn resolve_fraud_flags(
+    http: HttpRequest,
+    data: web::Data<AppState>,
+    delivery_id: web::Path<String>,
+    req: web::Json<admin::ActionRequest>,
+) -> Result<HttpResponse, Error> {
+    if let Some(denied) = admin_denied(&http, &data) {
+        return Ok(denied);
+    }
+
+    let req = req.into_inner();
+    act_on_fraud_flags(&data, &delivery_id, req.action, req.note).await
+}
+
+async fn disputed_deliveries(data: &AppState) -> Result<Vec<DeliveryRequest>, Error> {
+    Ok(data.indexed_deliveries().await
+        .map_err(backend_error)?
+        .into_iter()
+        .filter(|d| d.status == DeliveryStatus::Disputed)
+        .collect())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/disputes",
+    tag = "Admin",
+    responses(
+        (status = 200, description = "Deliveries in dispute", body = [DeliveryRequest]),
+        (status = 401, description = "Invalid admin token")
+    )
+)]
+async fn get_disputes(
+    http: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    if let Some(denied) = admin_denied(&http, &data) {
+        return Ok(denied);
+    }
+
+    Ok(HttpResponse::Ok().json(disputed_deliveries(&data).await?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/stuck",
+    tag = "Admin",
+    responses(
+        (status = 200, description = "Deliveries in one status longer than its threshold, longest overdue first", body = [stuck::StuckDelivery]),
+        (status = 401, description = "Invalid admin token")
+    )
+)]
+async fn get_stuck_deliveries(
+    http: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    if let Some(denied) = admin_denied(&http, &data) {
+        return Ok(denied);
+    }
+
+    Ok(HttpResponse::Ok().json(data.stuck_deliveries().await.map_err(backend_error)?))
+}
+
+fn postgres_disabled() -> HttpResponse {
+    problem_response(Problem::new(ProblemType::NotFound, "Postgres storage is disabled; set DATABASE_URL to enable it"))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/reports/deliveries",
+    tag = "Admin",
+    params(postgres::DeliveryFilter),
+    responses(
+        (status = 200, description = "One page of deliveries from the Postgres mirror, newest first, with the total matching", body = postgres::DeliveryPage),
+        (status = 401, description = "Invalid admin token"),
+        (status = 404, description = "Postgres storage is disabled")
+    )
+)]
+async fn get_report_deliveries(
+    http: HttpRequest,
+    data: web::Data<AppState>,
+    query: web::Query<postgres::DeliveryFilter>,
+) -> Result<HttpResponse, Error> {
+    if let Some(denied) = admin_denied(&http, &data) {
+        return Ok(denied);
+    }
+    let Some(db) = &data.postgres else {
+        return Ok(postgres_disabled());
+    };
+
+    Ok(HttpResponse::Ok().json(db.deliveries(&query).await.map_err(backend_error)?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/reports/summary",
+    tag = "Admin",
+    params(TimeRangeQuery),
+    responses(
+        (status = 200, description = "Deliveries, offered and accepted amounts per status, plus bid and participant counts, for deliveries created in the range", body = postgres::Report),
+        (status = 401, description = "Invalid admin token"),
+        (status = 404, description = "Postgres storage is disabled")
+    )
+)]
+async fn get_report_summary(
+    http: HttpRequest,
+    data: web::Data<AppState>,
+    query: web::Query<TimeRangeQuery>,
+) -> Result<HttpResponse, Error> {
+    if let Some(denied) = admin_denied(&http, &data) {
+        return Ok(denied);
+    }
+    let Some(db) = &data.postgres else {
+        return Ok(postgres_disabled());
+    };
+
+    Ok(HttpResponse::Ok().json(db.report(query.from, query.to).await.map_err(backend_error)?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/disputes/{id}",
+    tag = "Admin",
+    params(
+        ("id" = String, Path, description = "Delivery ID"),
+    ),
+    request_body = admin::ActionRequest,
+    responses(
+        (status = 200, description = "Approve confirms the delivery for the courier, reject expires it for the sender, escalate records it", body = Object),
+        (status = 400, description = "Delivery is not in dispute"),
+        (status = 401, description = "Invalid admin token"),
+        (status = 404, description = "Delivery not found")
+    )
+)]
+async fn resolve_dispute(
+    http: HttpRequest,
+    data: web::Data<AppState>,
+    delivery_id: web::Path<String>,
+    req: web::Json<admin::ActionRequest>,
+) -> Result<HttpResponse, Error> {
+    if let Some(denied) = admin_denied(&http, &data) {
+        return Ok(denied);
+    }
+
+    let delivery = data.get_delivery_by_id(&delivery_id).await
+        .map_err(backend_error)?
+        .ok_or_else(|| delivery_not_found(&delivery_id))?;
+
+    settle_dispute(&data, delivery, req.into_inner()).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/deliveries/{id}/dispute/resolve",
+    tag = "Organizations",
+    params(
+        ("id" = String, Path, description = "Delivery ID"),
+    ),
+    request_body = admin::ActionRequest,
+    responses(
+        (status = 200, description = "Approve confirms the delivery for the courier, reject expires it for the sender, escalate records it for marketplace admins", body = Object),
+        (status = 400, description = "Delivery is not in dispute"),
+        (status = 401, description = "Missing or invalid session token"),
+        (status = 403, description = "Not an arbitrator of the delivery's organization"),
+        (status = 404, description = "Delivery not found")
+    )
+)]
+async fn resolve_organization_dispute(
+    user: AuthenticatedUser,
+    data: web::Data<AppState>,
+    delivery_id: web::Path<String>,
+    req: web::Json<admin::ActionRequest>,
+) -> Result<HttpResponse, Error> {
+    let delivery = data.get_delivery_by_id(&delivery_id).await
+        .map_err(backend_error)?
+        .ok_or_else(|| delivery_not_found(&delivery_id))?;
+
+    // Disputes on deliveries without an organization are settled by marketplace admins
+    let Some(org_id) = &delivery.organization else {
+        return Ok(problem_response(Problem::new(ProblemType::Forbidden, "Only marketplace admins resolve disputes outside organizations").for_delivery(&delivery_id)));
+    };
+    let org = find_organization(&data, org_id).await?;
+    if !org.allows(&user.pubkey.to_hex(), rbac::Permission::ResolveDisputes) {
+        return Ok(problem_response(Problem::new(ProblemType::Forbidden, "Only the organization's arbitrators can resolve its disputes").for_delivery(&delivery_id)));
+    }
+
+    settle_dispute(&data, delivery, req.into_inner()).await
+}
+
+async fn settle_dispute(data: &AppState, mut delivery: DeliveryRequest, req: admin::ActionRequest) -> Result<HttpResponse, Error> {
+    let delivery_id = delivery.id.clone();
+    if delivery.status != DeliveryStatus::Disputed {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidTransition, "Delivery is not in dispute").for_delivery(&delivery_id)));
+    }
+
+    delivery.status = match req.action {
+        admin::AdminAction::Approve => DeliveryStatus::Confirmed,
+        admin::AdminAction::Reject => DeliveryStatus::Expired,
+        admin::AdminAction::Escalate => {
+            let escalation = data.escalate(admin::QueueItem::Dispute, &delivery_id, req.note).await;
+            return Ok(HttpResponse::Ok().json(serde_json::json!({
+                "status": "escalated",
+                "escalation": escalation
+            })));
+        }
+    };
+
+    data.publish_delivery(&delivery).await
+        .map_err(backend_error)?;
+    data.clear_escalation(admin::QueueItem::Dispute, &delivery_id).await;
+    let relisted = auto_relist(data, &mut delivery).await?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "status": if req.action == admin::AdminAction::Approve { "confirmed" } else { "expired" },
+        "delivery": delivery,
+        "relisted_as": relisted.map(|next| next.id)
+    })))
+}
+
+#[derive(Deserialize, ToSchema)]
+struct EscalateRequest {
+    note: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/reviews/{id}/escalate",
+    tag = "Admin",
+    params(
+        ("id" = String, Path, description = "Held review ID"),
+    ),
+    request_body = EscalateRequest,
+    responses(
+        (status = 200, description = "Review left held and recorded for a senior operator", body = admin::Escalation),
+        (status = 401, description = "Invalid admin token"),
+        (status = 404, description = "Review not found")
+    )
+)]
+async fn escalate_held_review(
+    http: HttpRequest,
+    data: web::Data<AppState>,
+    review_id: web::Path<String>,
+    req: web::Json<EscalateRequest>,
+) -> Result<HttpResponse, Error> {
+    if let Some(denied) = admin_denied(&http, &data) {
+        return Ok(denied);
+    }
+
+    if !data.held_reviews.read().await.iter().any(|r| r.id == *review_id) {
+        return Err(problem_error(Problem::new(ProblemType::NotFound, "Review not found")));
+    }
+
+    let escalation = data.escalate(admin::QueueItem::HeldReview, &review_id, req.into_inner().note).await;
+    Ok(HttpResponse::Ok().json(escalation))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/blocklist",
+    tag = "Admin",
+    responses(
+        (status = 200, description = "Pubkeys barred from bidding and creating deliveries", body = [admin::BlockedPubkey]),
+        (status = 401, description = "Invalid admin token")
+    )
+)]
+async fn get_blocklist(
+    http: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    if let Some(denied) = admin_denied(&http, &data) {
+        return Ok(denied);
+    }
+
+    Ok(HttpResponse::Ok().json(data.blocklist.read().await.entries()))
+}
+
+#[derive(Deserialize, ToSchema)]
+struct BlockPubkeyRequest {
+    pubkey: String,
+    reason: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/blocklist",
+    tag = "Admin",
+    request_body = BlockPubkeyRequest,
+    responses(
+        (status = 200, description = "Pubkey blocked", body = admin::BlockedPubkey),
+        (status = 401, description = "Invalid admin token")
+    )
+)]
+async fn block_pubkey(
+    http: HttpRequest,
+    data: web::Data<AppState>,
+    req: web::Json<BlockPubkeyRequest>,
+) -> Result<HttpResponse, Error> {
+    if let Some(denied) = admin_denied(&http, &data) {
+        return Ok(denied);
+    }
+
+    let req = req.into_inner();
+    let entry = data.blocklist.write().await.block(&req.pubkey, req.reason, Utc::now().timestamp());
+    data.save_blocklist().await
+        .map_err(backend_error)?;
+    Ok(HttpResponse::Ok().json(entry))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/admin/blocklist/{pubkey}",
+    tag = "Admin",
+    params(
+        ("pubkey" = String, Path, description = "npub or hex pubkey"),
+    ),
+    responses(
+        (status = 200, description = "Pubkey unblocked", body = Object),
+        (status = 401, description = "Invalid admin token"),
+        (status = 404, description = "Pubkey is not blocked")
+    )
+)]
+async fn unblock_pubkey(
+    http: HttpRequest,
+    data: web::Data<AppState>,
+    pubkey: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    if let Some(denied) = admin_denied(&http, &data) {
+        return Ok(denied);
+    }
+
+    if !data.blocklist.write().await.unblock(&pubkey) {
+        return Err(problem_error(Problem::new(ProblemType::NotFound, "Pubkey is not blocked")));
+    }
+    data.save_blocklist().await
+        .map_err(backend_error)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "status": "unblocked"
+    })))
+}
+
+#[derive(Deserialize, ToSchema)]
+struct CreateApiKeyRequest {
+    name: String,
+    /// Organization the key creates and manages deliveries for
+    organization: String,
+    permissions: Vec<api_keys::Permission>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/api-keys",
+    tag = "Admin",
+    request_body = CreateApiKeyRequest,
+    responses(
+        (status = 200, description = "The new key, with its secret (shown only once)", body = Object),
+        (status = 400, description = "No permissions given"),
+        (status = 401, description = "Invalid admin token"),
+        (status = 404, description = "Organization not found")
+    )
+)]
+async fn create_api_key(
+    http: HttpRequest,
+    data: web::Data<AppState>,
+    req: web::Json<CreateApiKeyRequest>,
+) -> Result<HttpResponse, Error> {
+    if let Some(denied) = admin_denied(&http, &data) {
+        return Ok(denied);
+    }
+    if req.permissions.is_empty() {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, "An API key needs at least one permission")));
+    }
+    find_organization(&data, &req.organization).await?;
+
+    let req = req.into_inner();
+    let (key, secret) = api_keys::ApiKey::generate(unique_timestamp_id("key"), req.name, req.organization, req.permissions, Utc::now().timestamp());
+
+    let mut keys = data.api_keys().await
+        .map_err(backend_error)?;
+    keys.push(key.clone());
+    data.save_api_keys(keys).await
+        .map_err(backend_error)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "key": key.redacted(),
+        "secret": secret,
+    })))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/api-keys",
+    tag = "Admin",
+    responses(
+        (status = 200, description = "All API keys, revoked ones included", body = [api_keys::ApiKey]),
+        (status = 401, description = "Invalid admin token")
+    )
+)]
+async fn list_api_keys(
+    http: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    if let Some(denied) = admin_denied(&http, &data) {
+        return Ok(denied);
+    }
+
+    let keys: Vec<api_keys::ApiKey> = data.api_keys().await
+        .map_err(backend_error)?
+        .iter()
+        .map(api_keys::ApiKey::redacted)
+        .collect();
+
+    Ok(HttpResponse::Ok().json(keys))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/admin/api-keys/{id}",
+    tag = "Admin",
+    params(
+        ("id" = String, Path, description = "API key ID"),
+    ),
+    responses(
+        (status = 200, description = "Key revoked", body = api_keys::ApiKey),
+        (status = 401, description = "Invalid admin token"),
+        (status = 404, description = "No active key with this ID")
+    )
+)]
+async fn revoke_api_key(
+    http: HttpRequest,
+    data: web::Data<AppState>,
+    id: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    if let Some(denied) = admin_denied(&http, &data) {
+        return Ok(denied);
+    }
+
+    let mut keys = data.api_keys().await
+        .map_err(backend_error)?;
+    let Some(key) = keys.iter_mut().find(|k| k.id == id.as_str() && k.revoked_at.is_none()) else {
+        return Err(problem_error(Problem::new(ProblemType::NotFound, "No active API key with this ID")));
+    };
+    key.revoked_at = Some(Utc::now().timestamp());
+    let revoked = key.redacted();
+
+    data.save_api_keys(keys).await
+        .map_err(backend_error)?;
+
+    Ok(HttpResponse::Ok().json(revoked))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/queue",
+    tag = "Admin",
+    responses(
+        (status = 200, description = "Flagged deliveries, disputes, held reviews, blocklist and escalations", body = admin::ModerationQueue),
+        (status = 401, description = "Invalid admin token")
+    )
+)]
+async fn get_moderation_queue(
+    http: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    if let Some(denied) = admin_denied(&http, &data) {
+        return Ok(denied);
+    }
+
+    let deliveries = data.indexed_deliveries().await
+        .map_err(backend_error)?;
+
+    Ok(HttpResponse::Ok().json(admin::ModerationQueue {
+        flagged: deliveries.iter().filter(|d| !d.fraud_flags.is_empty()).cloned().collect(),
+        disputes: deliveries.iter().filter(|d| d.status == DeliveryStatus::Disputed).cloned().collect(),
+        held_reviews: data.held_reviews.read().await.clone(),
+        blocklist: data.blocklist.read().await.entries().to_vec(),
+        escalations: data.escalations.read().await.clone(),
+    }))
+}
+
+// API v2: bid acceptance by ID and structured time windows. v1 shapes are served by the
+// handlers above; the v2 adapter types below convert to and from them.
+
+// v2 JSON representation of a delivery (time_window as a structured object)
+fn delivery_v2_json(delivery: &DeliveryRequest) -> serde_json::Value {
+    let mut value = serde_json::to_value(delivery).unwrap_or_default();
+    value["time_window"] = serde_json::to_value(TimeWindow::parse(&delivery.time_window)).unwrap_or_default();
+    value
+}
+
+#[derive(Deserialize, ToSchema)]
+struct CreateDeliveryRequestV2 {
+    #[serde(default)]
+    pickup: Location,
+    #[serde(default)]
+    dropoff: Location,
+    #[serde(default)]
+    pickup_address_id: Option<String>,
+    #[serde(default)]
+    dropoff_address_id: Option<String>,
+    packages: Vec<PackageInfo>,
+    offer_amount: Amount,
+    insurance_amount: Option<Amount>,
+    time_window: TimeWindow,
+    sender: String,
+    #[serde(default)]
+    min_courier_reputation: Option<f32>,
+    #[serde(default)]
+    min_completed_deliveries: Option<u32>,
+    #[serde(default)]
+    allowed_couriers: Vec<String>,
+    #[serde(default)]
+    private: bool,
+    #[serde(default)]
+    organization: Option<String>,
+    #[serde(default)]
+    recipient_email: Option<String>,
+    #[serde(default)]
+    recipient_phone: Option<String>,
+    #[serde(default)]
+    package_photos: Vec<String>,
+    #[serde(default)]
+    timezone: Option<String>,
+    #[serde(default)]
+    draft: bool,
+    #[serde(default)]
+    auto_relist: bool,
+}
+
+impl From<CreateDeliveryRequestV2> for CreateDeliveryRequest {
+    fn from(req: CreateDeliveryRequestV2) -> Self {
+        CreateDeliveryRequest {
+            pickup: req.pickup,
+            dropoff: req.dropoff,
+            pickup_address_id: req.pickup_address_id,
+            dropoff_address_id: req.dropoff_address_id,
+            packages: req.packages,
+            offer_amount: req.offer_amount,
+            insurance_amount: req.insurance_amount,
+            time_window: req.time_window.to_raw(),
+            sender: req.sender,
+            min_courier_reputation: req.min_courier_reputation,
+            min_completed_deliveries: req.min_completed_deliveries,
+            allowed_couriers: req.allowed_couriers,
+            private: req.private,
+            organization: req.organization,
+            recipient_email: req.recipient_email,
+            recipient_phone: req.recipient_phone,
+            package_photos: req.package_photos,
+            timezone: req.timezone,
+            draft: req.draft,
+            auto_relist: req.auto_relist,
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+struct UpdateDeliveryRequestV2 {
+    pickup: Option<Location>,
+    dropoff: Option<Location>,
+    packages: Option<Vec<PackageInfo>>,
+    offer_amount: Option<Amount>,
+    insurance_amount: Option<Amount>,
+    time_window: Option<TimeWindow>,
+    #[serde(default)]
+    min_courier_reputation: Option<f32>,
+    #[serde(default)]
+    min_completed_deliveries: Option<u32>,
+    #[serde(default)]
+    allowed_couriers: Option<Vec<String>>,
+}
+
+impl From<UpdateDeliveryRequestV2> for UpdateDeliveryRequest {
+    fn from(req: UpdateDeliveryRequestV2) -> Self {
+        UpdateDeliveryRequest {
+            pickup: req.pickup,
+            dropoff: req.dropoff,
+            packages: req.packages,
+            offer_amount: req.offer_amount,
+            insurance_amount: req.insurance_amount,
+            time_window: req.time_window.map(|w| w.to_raw()),
+            min_courier_reputation: req.min_courier_reputation,
+            min_completed_deliveries: req.min_completed_deliveries,
+            allowed_couriers: req.allowed_couriers,
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v2/deliveries",
+    tag = "Deliveries (v2)",
+    params(DeliveryQuery),
+    responses(
+        (status = 200, description = "Deliveries matching the filter", body = [Object])
+    )
+)]
+async fn get_deliveries_v2(
+    http: HttpRequest,
+    data: web::Data<AppState>,
+    query: web::Query<DeliveryQuery>,
+    user: Option<AuthenticatedUser>,
+) -> Result<HttpResponse, Error> {
+    let mut deliveries = data.get_all_deliveries().await
+        .map_err(backend_error)?;
+
+    if query.include_federated.unwrap_or(true) {
+        deliveries.extend(data.federated_deliveries().await);
+    }
+
+    let viewer = Viewer::for_deliveries(&data, user.as_ref(), &deliveries).await?;
+    let fields = fieldsets::parse(query.fields.as_deref());
+    let presentation = Presentation::for_request(&data, &http, query.units, user).await;
+    let filtered: Vec<serde_json::Value> = deliveries.iter()
+        .filter(|d| viewer.can_see(d))
+        .filter(|d| match &query.status {
+            Some(status) => format!("{:?}", d.status).to_lowercase() == status.to_lowercase(),
+            None => !matches!(d.status, DeliveryStatus::Draft | DeliveryStatus::Deleted),
+        })
+        .map(delivery_v2_json)
+        .map(|d| match &fields {
+            Some(fields) => fieldsets::select(d, fields),
+            None => d,
+        })
+        .map(|mut d| {
+            presentation.apply(&mut d);
+            d
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(filtered))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v2/deliveries/{id}",
+    tag = "Deliveries (v2)",
+    params(
+        ("id" = String, Path, description = "Delivery ID"),
+        UnitsQuery,
+    ),
+    responses(
+        (status = 200, description = "Delivery", body = Object),
+        (status = 404, description = "Delivery not found")
+    )
+)]
+async fn get_delivery_v2(
+    http: HttpRequest,
+    data: web::Data<AppState>,
+    id: web::Path<String>,
+    query: web::Query<UnitsQuery>,
+    user: Option<AuthenticatedUser>,
+) -> Result<HttpResponse, Error> {
+    let delivery = match data.get_delivery_by_id(&id).await
+        .map_err(backend_error)? {
+        Some(delivery) => Some(delivery),
+        None => data.get_federated_delivery(&id).await,
+    };
+    let viewer = Viewer::for_deliveries(&data, user.as_ref(), delivery.as_slice()).await?;
+
+    if let Some(delivery) = delivery.filter(|d| viewer.can_see(d)) {
+        let mut value = delivery_v2_json(&delivery);
+        Presentation::for_request(&data, &http, query.units, user).await.apply(&mut value);
+        Ok(HttpResponse::Ok().json(value))
+    } else {
+        Ok(problem_response(Problem::new(ProblemType::NotFound, "Delivery not found").for_delivery(&id)))
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v2/deliveries",
+    tag = "Deliveries (v2)",
+    request_body = CreateDeliveryRequestV2,
+    responses(
+        (status = 200, description = "Delivery created", body = Object),
+        (status = 400, description = "Invalid time window")
+    )
+)]
+async fn create_delivery_v2(
+    http: HttpRequest,
+    data: web::Data<AppState>,
+    req: web::Json<CreateDeliveryRequestV2>,
+) -> Result<HttpResponse, Error> {
+    if !req.time_window.is_valid() {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, "Time window must end after it starts")));
+    }
+
+    let (delivery, contact) = prepare_delivery(&http, &data, CreateDeliveryRequest::from(req.into_inner())).await?;
+
+    data.delivery_service().create(&delivery).await
+        .map_err(|e| service_error(e, &delivery.id))?;
+    save_recipient_contact(&data, &delivery.id, &contact).await?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "id": delivery.id,
+        "status": "created",
+        "recipient_tracking_url": data.recipient_tracking_url(&delivery.id),
+        "delivery": delivery_v2_json(&delivery)
+    })))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/v2/deliveries/{id}",
+    tag = "Deliveries (v2)",
+    params(
+        ("id" = String, Path, description = "Delivery ID"),
+    ),
+    request_body = UpdateDeliveryRequestV2,
+    responses(
+        (status = 200, description = "Delivery updated", body = Object),
+        (status = 400, description = "Delivery is not open or time window is invalid"),
+        (status = 401, description = "Sign-in required"),
+        (status = 403, description = "Not the sender or a member of the delivery's organization"),
+        (status = 404, description = "Delivery not found")
+    )
+)]
+async fn update_delivery_v2(
+    http: HttpRequest,
+    data: web::Data<AppState>,
+    delivery_id: web::Path<String>,
+    req: web::Json<UpdateDeliveryRequestV2>,
+) -> Result<HttpResponse, Error> {
+    if req.time_window.as_ref().is_some_and(|w| !w.is_valid()) {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, "Time window must end after it starts").for_delivery(&delivery_id)));
+    }
+
+    let mut delivery = data.get_delivery_by_id(&delivery_id).await
+        .map_err(backend_error)?
+        .ok_or_else(|| delivery_not_found(&delivery_id))?;
+
+    check_sender_change(&http, &data, &delivery).await?;
+    if !matches!(delivery.status, DeliveryStatus::Open | DeliveryStatus::Draft) {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidTransition, "Cannot update delivery that is not open").for_delivery(&delivery_id)));
+    }
+
+    let before = delivery.clone();
+    let window_changed = req.time_window.is_some();
+    let stops_changed = req.pickup.is_some() || req.dropoff.is_some();
+    UpdateDeliveryRequest::from(req.into_inner()).apply(&mut delivery);
+
+    if let Some(error) = delivery.allowlist_error() {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, error).for_delivery(&delivery_id)));
+    }
+    if stops_changed {
+        if let Some(error) = data.resolve_stops(&mut delivery).await {
+            return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, error).for_delivery(&delivery_id)));
+        }
+        // The flag follows the stops, so a delivery moved back inside the area loses it
+        delivery.out_of_service_area = false;
+        if let Err(error) = data.check_service_area(&mut delivery).await {
+            return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, error).for_delivery(&delivery_id)));
+        }
+    }
+    if window_changed {
+        if let Some(error) = timezone::schedule_error(&mut delivery, Utc::now().timestamp()) {
+            return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, error).for_delivery(&delivery_id)));
+        }
+    }
+    delivery.local_times = Some(timezone::LocalTimes::for_delivery(&delivery));
+
+    let revision = data.publish_delivery_edit(&before, &mut delivery).await
+        .map_err(backend_error)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "status": "updated",
+        "revision": revision,
+        "delivery": delivery_v2_json(&delivery)
+    })))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v2/deliveries/{id}/accept/{bid_id}",
+    tag = "Bids (v2)",
+    params(
+        ("id" = String, Path, description = "Delivery ID"),
+        ("bid_id" = String, Path, description = "ID of the bid to accept"),
+    ),
+    responses(
+        (status = 200, description = "Bid accepted", body = Object),
+        (status = 400, description = "Bid not found on this delivery"),
+        (status = 404, description = "Delivery not found")
+    )
+)]
+async fn accept_bid_v2(
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, Error> {
+    let (delivery_id, bid_id) = path.into_inner();
+
+    let delivery = data.get_delivery_by_id(&delivery_id).await
+        .map_err(backend_error)?
+        .ok_or_else(|| delivery_not_found(&delivery_id))?;
+
+    let Some(bid_index) = delivery.bids.iter().position(|b| b.id == bid_id) else {
+        return Ok(problem_response(Problem::new(ProblemType::InvalidRequest, "Bid not found on this delivery").for_delivery(&delivery_id)));
+    };
+
+    let delivery = data.delivery_service().accept(delivery, bid_index, Utc::now().timestamp()).await
+        .map_err(|e| service_error(e, &delivery_id))?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "status": "accepted",
+        "delivery": delivery_v2_json(&delivery)
+    })))
+}
+
+// Routes served under /api/v1 (and the unversioned /api legacy alias)
+fn api_v1_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/deliveries", web::get().to(get_deliveries))
+        .route("/deliveries/quote", web::post().to(quote_delivery))
+        .route("/sync", web::get().to(get_sync))
+        .route("/deliveries", web::post().to(create_delivery))
+        .route("/deliveries/geojson", web::get().to(get_deliveries_geojson))
+        .route("/deliveries/{id}", web::get().to(get_delivery))
+        .route("/deliveries/{id}", web::patch().to(update_delivery))
+        .route("/deliveries/{id}/revisions", web::get().to(get_delivery_revisions))
+        .route("/deliveries/{id}/state", web::get().to(get_delivery_state))
+        .route("/deliveries/{id}/timeline", web::get().to(get_delivery_timeline))
+        .route("/deliveries/{id}/poll", web::get().to(poll_delivery))
+        .route("/deliveries/{id}/bids", web::get().to(get_bids))
+        .route("/deliveries/{id}/bids/{bid_id}/reconfirm", web::post().to(reconfirm_bid))
+        .route("/deliveries/{id}", web::delete().to(delete_delivery))
+        .route("/deliveries/{id}/restore", web::post().to(restore_delivery))
+        .route("/deliveries/{id}/publish", web::post().to(publish_draft))
+        .route("/deliveries/{id}/bid", web::post().to(place_bid))
+        .route("/deliveries/{id}/accept/{bid_idx}", web::post().to(accept_bid))
+        .route("/deliveries/{id}/status", web::patch().to(update_delivery_status))
+        .route("/deliveries/{id}/cancel", web::post().to(cancel_delivery))
+        .route("/deliveries/{id}/relist", web::post().to(relist_delivery))
+        .route("/deliveries/{id}/attempts", web::get().to(get_delivery_attempts))
+        .route("/deliveries/{id}/complete", web::post().to(complete_delivery))
+        .route("/deliveries/{id}/attempt-failed", web::post().to(attempt_failed))
+        .route("/deliveries/{id}/confirm", web::post().to(confirm_delivery))
+        .route("/deliveries/{id}/recipient-confirm", web::post().to(recipient_confirm))
+        .route("/deliveries/{id}/rate-sender", web::post().to(rate_sender))
+        .route("/deliveries/{id}/location", web::post().to(record_location))
+        .route("/deliveries/{id}/track", web::get().to(get_track))
+        .route("/deliveries/{id}/track.gpx", web::get().to(get_track_gpx))
+        .route("/deliveries/{id}/track.geojson", web::get().to(get_track_geojson))
+        .route("/deliveries/{id}/qr/{kind}.{format}", web::get().to(get_qr_code))
+        .route("/deliveries/{id}/label.pdf", web::get().to(get_delivery_label))
+        .route("/deliveries/{id}/dropoff-confirm", web::post().to(confirm_dropoff_code))
+        .route("/deliveries/{id}/interest", web::get().to(get_listing_interest))
+        .route("/deliveries/{id}/assign", web::post().to(assign_courier))
+        .route("/deliveries/{id}/dispute/resolve", web::post().to(resolve_organization_dispute))
+        .route("/deliveries/{id}/assignment/acknowledge", web::post().to(acknowledge_assignment))
+        .route("/imports/deliveries", web::post().to(import_deliveries))
+        .route("/export/deliveries", web::get().to(export_deliveries))
+        .route("/user/{npub}", web::get().to(get_user))
+        .route("/user/{npub}", web::patch().to(update_user))
+        .route("/user/{npub}/spending", web::get().to(get_user_spending))
+        .route("/sender/{npub}/dashboard", web::get().to(get_sender_dashboard))
+        .route("/user/{npub}/stats", web::get().to(get_courier_stats))
+        .route("/courier/{npub}/feed", web::get().to(get_courier_feed))
+        .route("/courier/{npub}/deliveries", web::get().to(get_courier_deliveries))
+        .route("/courier/{npub}/bids", web::get().to(get_courier_bids))
+        .route("/user/{npub}/settings", web::get().to(get_user_settings))
+        .route("/user/{npub}/settings", web::patch().to(update_user_settings))
+        .route("/user/{npub}/delegation", web::get().to(get_user_delegation))
+        .route("/user/{npub}/delegation", web::put().to(set_user_delegation))
+        .route("/user/{npub}/delegation", web::delete().to(remove_user_delegation))
+        .service(web::resource("/uploads")
+            .app_data(web::PayloadConfig::new(uploads::MAX_UPLOAD_BYTES))
+            .route(web::post().to(upload_file)))
+        .route("/uploads/{sha256}", web::get().to(get_upload))
+        .route("/user/{npub}/devices", web::get().to(list_devices))
+        .route("/user/{npub}/devices", web::post().to(register_device))
+        .route("/user/{npub}/devices/{id}", web::delete().to(remove_device))
+        .route("/user/{npub}/addresses", web::get().to(list_addresses))
+        .route("/user/{npub}/addresses", web::post().to(save_address))
+        .route("/user/{npub}/addresses/{id}", web::delete().to(remove_address))
+        .route("/user/{npub}/couriers", web::get().to(get_courier_lists))
+        .route("/user/{npub}/couriers/{list}/{courier}", web::put().to(add_to_courier_list))
+        .route("/user/{npub}/couriers/{list}/{courier}", web::delete().to(remove_from_courier_list))
+        .route("/admin/status", web::get().to(get_admin_status))
+        .route("/admin/publishes", web::get().to(get_publish_log))
+        .route("/admin/service-area", web::get().to(get_service_area))
+        .route("/admin/service-area", web::put().to(set_service_area))
+        .route("/admin/service-area", web::delete().to(clear_service_area))
+        .route("/admin/reviews", web::get().to(get_held_reviews))
+        .route("/admin/reviews/{id}/approve", web::post().to(approve_held_review))
+        .route("/admin/reviews/{id}", web::delete().to(reject_held_review))
+        .route("/admin/fraud", web::get().to(get_fraud_flagged))
+        .route("/admin/deliveries/{id}/fraud-review", web::post().to(review_fraud_flags))
+        .route("/admin/fraud/{id}", web::post().to(resolve_fraud_flags))
+        .route("/admin/disputes", web::get().to(get_disputes))
+        .route("/admin/stuck", web::get().to(get_stuck_deliveries))
+        .route("/admin/reports/deliveries", web::get().to(get_report_deliveries))
+        .route("/admin/reports/summary", web::get().to(get_report_summary))
+        .route("/admin/disputes/{id}", web::post().to(resolve_dispute))
+        .route("/admin/reviews/{id}/escalate", web::post().to(escalate_held_review))
+        .route("/admin/blocklist", web::get().to(get_blocklist))
+        .route("/admin/blocklist", web::post().to(block_pubkey))
+        .route("/admin/blocklist/{pubkey}", web::delete().to(unblock_pubkey))
+        .route("/admin/api-keys", web::get().to(list_api_keys))
+        .route("/admin/api-keys", web::post().to(create_api_key))
+        .route("/admin/api-keys/{id}", web::delete().to(revoke_api_key))
+        .route("/admin/queue", web::get().to(get_moderation_queue))
+        .route("/organizations", web::post().to(create_organization))
+        .route("/organizations/{id}", web::get().to(get_organization))
+        .route("/organizations/{id}/members/{npub}", web::put().to(set_organization_member))
+        .route("/organizations/{id}/members/{npub}", web::delete().to(remove_organization_member))
+        .route("/organizations/{id}/permissions", web::get().to(get_organization_permissions))
+        .route("/organizations/{id}/permissions", web::put().to(set_organization_permissions))
+        .route("/organizations/{id}/stats", web::get().to(get_fleet_stats))
+        .route("/stats", web::get().to(get_market_stats))
+        .route("/stats/heatmap", web::get().to(get_heatmap))
+        .route("/leaderboard", web::get().to(get_leaderboard))
+        .route("/pricing/history", web::get().to(get_price_history))
+        .route("/open/{entity}", web::get().to(open_nostr_entity));
+}
+
+// Routes served under /api/v2: v2 overrides first, everything else falls through to v1 handlers
+fn api_v2_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/deliveries", web::get().to(get_deliveries_v2))
+        .route("/deliveries", web::post().to(create_delivery_v2))
+        .route("/deliveries/geojson", web::get().to(get_deliveries_geojson))
+        .route("/deliveries/{id}", web::get().to(get_delivery_v2))
+        .route("/deliveries/{id}", web::patch().to(update_delivery_v2))
+        .route("/deliveries/{id}/accept/{bid_id}", web::post().to(accept_bid_v2))
+        .configure(api_v1_routes);
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Nostr Delivery API", description = "Decentralized peer-to-peer delivery coordination over Nostr"),
+    paths(
+        health_check,
+        health_live,
+        health_ready,
+        get_protocol,
+        get_deliveries,
+        get_sync,
+        poll_delivery,
+        create_delivery,
+        quote_delivery,
+        get_delivery,
+        update_delivery,
+        get_delivery_revisions,
+        get_delivery_state,
+        get_delivery_timeline,
+        reconfirm_bid,
+        delete_delivery,
+        restore_delivery,
+        publish_draft,
+        place_bid,
+        get_bids,
+        accept_bid,
+        update_delivery_status,
+        cancel_delivery,
+        relist_delivery,
+        get_delivery_attempts,
+        complete_delivery,
+        attempt_failed,
+        confirm_delivery,
+        recipient_confirm,
+        rate_sender,
+        get_user,
+        update_user,
+        get_user_spending,
+        get_sender_dashboard,
+        get_courier_stats,
+        get_courier_feed,
+        get_courier_deliveries,
+        get_courier_bids,
+        get_user_settings,
+        update_user_settings,
+        get_user_delegation,
+        auth_challenge,
+        auth_verify,
+        auth_session,
+        set_user_delegation,
+        remove_user_delegation,
+        register_device,
+        list_devices,
+        remove_device,
+        save_address,
+        list_addresses,
+        remove_address,
+        get_courier_lists,
+        add_to_courier_list,
+        remove_from_courier_list,
+        get_admin_status,
+        get_publish_log,
+        get_service_area,
+        set_service_area,
+        clear_service_area,
+        get_held_reviews,
+        approve_held_review,
+        reject_held_review,
+        get_fraud_flagged,
+        review_fraud_flags,
+        resolve_fraud_flags,
+        get_disputes,
+        get_stuck_deliveries,
+        get_report_deliveries,
+        get_report_summary,
+        resolve_dispute,
+        resolve_organization_dispute,
+        escalate_held_review,
+        get_blocklist,
+        block_pubkey,
+        unblock_pubkey,
+        create_api_key,
+        list_api_keys,
+        revoke_api_key,
+        get_moderation_queue,
+        create_organization,
+        get_organization,
+        set_organization_member,
+        remove_organization_member,
+        get_organization_permissions,
+        set_organization_permissions,
+        get_fleet_stats,
+        assign_courier,
+        acknowledge_assignment,
+        get_market_stats,
+        get_heatmap,
+        get_leaderboard,
+        get_price_history,
+        import_deliveries,
+        upload_file,
+        get_upload,
+        ingest_order,
+        export_deliveries,
+        get_deliveries_geojson,
+        record_location,
+        get_track,
+        get_track_gpx,
+        get_track_geojson,
+        get_qr_code,
+        get_delivery_label,
+        confirm_dropoff_code,
+        get_listing_interest,
+        open_nostr_entity,
+        get_deliveries_v2,
+        get_delivery_v2,
+        create_delivery_v2,
+        update_delivery_v2,
+        accept_bid_v2,
+    )
+)]
+struct ApiDoc;
+
+async fn openapi_json() -> HttpResponse {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
+
+// Swagger UI page rendering the generated spec (assets served from the swagger-ui-dist CDN)
+async fn swagger_ui() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(SWAGGER_UI_HTML)
+}
+
+const SWAGGER_UI_HTML: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8" />
+  <title>Nostr Delivery API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css" />
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+  <script>
+    window.onload = () => {
+      window.ui = SwaggerUIBundle({ url: "/api/openapi.json", dom_id: "#swagger-ui" });
+    };
+  </script>
+</body>
+</html>"##;
+
+// Relay URLs from NOSTR_RELAYS or the defaults
+fn relay_urls_from_env() -> Vec<String> {
+    std::env::var("NOSTR_RELAYS")
+        .unwrap_or_else(|_| "wss://relay.damus.io,wss://nos.lol,wss://relay.nostr.band".to_string())
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .collect()
+}
+
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
+
+    let command = match cli::parse(std::env::args().skip(1)) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    match command {
+        cli::Command::Serve => serve().await,
+        cli::Command::Help => {
+            println!("{}", cli::USAGE);
+            Ok(())
+        }
+        cli::Command::Keygen => {
+            let keys = Keys::generate();
+            let secret = keys.secret_key().to_bech32().map_err(std::io::Error::other)?;
+            let pubkey = keys.public_key().to_bech32().map_err(std::io::Error::other)?;
+            println!("SYSTEM_SECRET_KEY={}", secret);
+            println!("# pubkey {} ({})", pubkey, keys.public_key().to_hex());
+            Ok(())
+        }
+        command => {
+            let data = match AppState::new(relay_urls_from_env()).await {
+                Ok(data) => data,
+                Err(e) => {
+                    eprintln!("Failed to initialize Nostr client: {}", e);
+                    std::process::exit(1);
+                }
+            };
+            let result = run_command(&data, command).await;
+            let _ = data.nostr_client.disconnect().await;
+            if let Err(e) = result {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+    }
+}
+
+// Operational subcommands, run against the relays without starting the HTTP server
+async fn run_command(data: &AppState, command: cli::Command) -> Result<(), Box<dyn std::error::Error>> {
+    use std::io::Write;
+
+    match command {
+        cli::Command::RotateKey { backup, grace_days } => {
+            if data.bunker.is_some() {
+                return Err("The system key is held by a remote signer; rotate it there instead".into());
+            }
+            let password = std::env::var("KEY_BACKUP_PASSWORD").ok().filter(|p| !p.is_empty())
+                .ok_or("Set KEY_BACKUP_PASSWORD to encrypt the key backup")?;
+            let old_keys = data.system_keys.clone();
+            let new_keys = Keys::generate();
+            let now = Utc::now().timestamp();
+
+            // The backup is written before anything is published, so the new key can't be lost
+            let key_backup = rotation::KeyBackup::new(&old_keys, &new_keys, &password, now)?;
+            std::fs::write(&backup, serde_json::to_string_pretty(&key_backup)? + "\n")
+                .map_err(|e| format!("Can't write {}: {}", backup, e))?;
+
+            // Re-sign before announcing, so the new key's state is in place for readers that follow it
+            let active: std::collections::HashSet<String> = data.get_all_deliveries().await?
+                .into_iter()
+                .filter(redundancy::is_active)
+                .map(|d| d.id)
+                .collect();
+            let filter = Filter::new().kinds(snapshot::snapshot_kinds()).author(old_keys.public_key());
+            let old_key_only = query::Authors::new(old_keys.public_key(), []);
+            let latest = query::latest_per_identifier(data.fetch_all_events(filter).await?, &old_key_only);
+
+            let created_at = Timestamp::now();
+            let mut resigned = 0;
+            for event in latest.iter().filter(|e| rotation::needs_resign(e, &active)) {
+                let sent = match rotation::resign(event, &old_keys, &new_keys, created_at) {
+                    Ok(event) => data.send_event(event).await.map_err(|e| e.to_string()),
+                    Err(e) => Err(e.to_string()),
+                };
+                match sent {
+                    Ok(_) => resigned += 1,
+                    Err(e) => log::warn!("Failed to re-sign {}: {}", event.id, e),
+                }
+            }
+
+            let rotation = rotation::Rotation {
+                old: old_keys.public_key(),
+                new: new_keys.public_key(),
+                rotated_at: now,
+                grace_until: now + grace_days * 86400,
+            };
+            data.send_event(data.sign_event(rotation.builder()).await?).await?;
+
+            eprintln!(
+                "Rotated {} -> {}: re-signed {} events, old key trusted until {}. Backup written to {}.",
+                rotation.old.to_bech32()?, rotation.new.to_bech32()?, resigned, rotation.grace_until, backup
+            );
+            eprintln!("Restart with the new key:");
+            println!("SYSTEM_SECRET_KEY={}", new_keys.secret_key().to_bech32()?);
+        }
+        cli::Command::PublishDelivery { file } => {
+            let raw = std::fs::read_to_string(&file)
+                .map_err(|e| format!("Failed to read {}: {}", file, e))?;
+            let request: CreateDeliveryRequest = serde_json::from_str(&raw)
+                .map_err(|e| format!("Invalid delivery in {}: {}", file, e))?;
+
+            let contact = request.recipient_contact()?;
+            let saved_addresses = request.saved_address_ids();
+            let mut delivery = request.into_delivery();
+            let rejection = match use_saved_addresses(data, &mut delivery, saved_addresses).await? {
+                Some(rejection) => Some(rejection),
+                None => validate_new_delivery(data, &mut delivery).await?,
+            };
+            if let Some(rejection) = rejection {
+                let body = actix_web::body::to_bytes(rejection.into_body()).await.unwrap_or_default();
+                return Err(format!("Delivery rejected: {}", String::from_utf8_lossy(&body)).into());
+            }
+
+            data.delivery_service().create(&delivery).await?;
+            if !contact.is_empty() {
+                data.save_recipient_contact(&delivery.id, &contact).await?;
+            }
+            println!("{}", delivery.id);
+        }
+        cli::Command::List { status } => {
+            let statuses = export::parse_statuses(status.as_deref())?;
+            let deliveries = data.get_all_deliveries().await?;
+            for d in deliveries.iter().filter(|d| statuses.as_ref().is_none_or(|s| s.contains(&d.status))) {
+                println!(
+                    "{}\t{}\t{}\t{} -> {}",
+                    d.id,
+                    export::field_value(d, "status").as_str().unwrap_or_default(),
+                    d.offer_amount,
+                    d.pickup.address,
+                    d.dropoff.address,
+                );
+            }
+        }
+        cli::Command::Export { format, status, fields, from, to } => {
+            let fields = export::parse_fields(fields.as_deref())?;
+            let statuses = export::parse_statuses(status.as_deref())?;
+            let selected = data.get_all_deliveries().await?
+                .into_iter()
+                .filter(move |d| analytics::in_range(d.created_at, from, to))
+                .filter(move |d| statuses.as_ref().is_none_or(|s| s.contains(&d.status)));
+
+            let mut out = std::io::stdout().lock();
+            for chunk in export::render(selected, fields, format == "csv") {
+                out.write_all(chunk.as_bytes())?;
+            }
+            if format == "json" {
+                writeln!(out)?;
+            }
+        }
+        cli::Command::ExportSnapshot { out } => {
+            let filter = Filter::new()
+                .kinds(snapshot::snapshot_kinds())
+                .authors(data.authors.keys());
+            let mut events = data.fetch_all_events(filter).await?;
+            snapshot::sort_events(&mut events);
+
+            let now = Utc::now().timestamp();
+            let manifest = data.sign_event(snapshot::manifest(&events, now)).await?;
+            let snapshot = snapshot::Snapshot::new(now, manifest, events);
+
+            let file = std::fs::File::create(&out).map_err(|e| format!("Can't create {}: {}", out, e))?;
+            let mut writer = std::io::BufWriter::new(file);
+            serde_json::to_writer(&mut writer, &snapshot)?;
+            writer.flush()?;
+            eprintln!("Wrote {} events to {}", snapshot.events.len(), out);
+        }
+        cli::Command::ImportSnapshot { file } => {
+            let contents = std::fs::read_to_string(&file).map_err(|e| format!("Can't read {}: {}", file, e))?;
+            let snapshot: snapshot::Snapshot = serde_json::from_str(&contents)
+                .map_err(|e| format!("{} is not a snapshot: {}", file, e))?;
+            snapshot.verify()?;
+
+            // Events keep their original author, so a new instance only reads them if it trusts it
+            if !data.authors.contains(&snapshot.manifest.pubkey) {
+                eprintln!(
+                    "Warning: the snapshot was signed by {}, which is not a trusted publisher here. Add it to TRUSTED_PUBLISHERS or run with its SYSTEM_SECRET_KEY.",
+                    snapshot.manifest.pubkey.to_bech32()?
+                );
+            }
+
+            let total = snapshot.events.len();
+            let mut sent = 0;
+            for event in snapshot.events {
+                let id = event.id;
+                match data.send_event(event).await {
+                    Ok(_) => sent += 1,
+                    Err(e) => log::warn!("Failed to import {}: {}", id, e),
+                }
+            }
+            println!("Imported {} of {} events", sent, total);
+        }
+        cli::Command::Republish { since } => {
+            let filter = Filter::new()
+                .kinds(sync::DELIVERY_EVENT_KINDS.map(Kind::Custom))
+                .authors(data.authors.keys())
+                .since(Timestamp::from(since.max(0) as u64));
+            let events = data.nostr_client.fetch_events(vec![filter], Some(Duration::from_secs(10))).await?;
+
+            // Events are re-sent as signed, so those from trusted earlier keys keep their author
+            let total = events.len();
+            let mut sent = 0;
+            for event in events.into_iter() {
+                let id = event.id;
+                match data.send_event(event).await {
+                    Ok(_) => sent += 1,
+                    Err(e) => log::warn!("Failed to republish {}: {}", id, e),
+                }
+            }
+            println!("Republished {} of {} events", sent, total);
+        }
+        cli::Command::Compact => {
+            let report = data.compact_closed_deliveries().await?;
+            println!(
+                "Compacted {} deliveries: {} events archived, {} republished, {} deleted",
+                report.deliveries, report.archived_events, report.republished, report.deleted
+            );
+        }
+        cli::Command::VerifyEvent { id } => {
+            let event_id = match Nip19::from_bech32(&id) {
+                Ok(Nip19::Event(event)) => event.event_id,
+                _ => EventId::parse(&id).map_err(|e| format!("Invalid event ID {}: {}", id, e))?,
+            };
+            let events = data.nostr_client.fetch_events(vec![Filter::new().id(event_id)], Some(Duration::from_secs(5))).await?;
+            let event = events.into_iter().next()
+                .ok_or_else(|| format!("Event {} not found on the relays", event_id))?;
+
+            println!("id:      {}", event.id);
+            println!("kind:    {}", event.kind.as_u16());
+            println!("author:  {}{}", event.pubkey.to_bech32()?, if data.authors.contains(&event.pubkey) { " (trusted)" } else { "" });
+            println!("created: {}", event.created_at.as_u64());
+            if let Some(request_id) = query::tag_value(&event, "request_id") {
+                println!("request: {}", request_id);
+            }
+            match event.verify() {
+                Ok(()) => println!("signature: valid"),
+                Err(e) => return Err(format!("signature: INVALID ({})", e).into()),
+            }
+        }
+        cli::Command::Serve | cli::Command::Keygen | cli::Command::Help => {}
+    }
+
+    Ok(())
+}
+
+async fn serve() -> std::io::Result<()> {
+    println!("🚀 Nostr Delivery Backend Starting...");
+    println!("🔌 Backend Mode: Nostr-Powered (No Database)");
+
+    let relay_urls = relay_urls_from_env();
+
+    println!("📡 Connecting to relays: {:?}", relay_urls);
+
+    let app_state = web::Data::new(
+        AppState::new(relay_urls).await
+            .expect("Failed to initialize Nostr client")
+    );
+
+    println!("✅ Nostr client initialized");
+
+    match app_state.load_blocklist().await {
+        Ok(blocked) => log::info!("Blocklist holds {} pubkeys", blocked),
+        Err(e) => log::warn!("Failed to load the stored blocklist: {}", e),
+    }
+    match app_state.load_held_reviews().await {
+        Ok(held) => log::info!("Loaded {} held reviews", held),
+        Err(e) => log::warn!("Failed to load held reviews: {}", e),
+    }
+
+    // Announce NIP-89 handler info for our event kinds
+    let handler_state = app_state.clone();
+    actix_rt::spawn(async move {
+        if let Err(e) = handler_state.publish_handler_announcement().await {
+            log::warn!("Failed to publish NIP-89 handler announcement: {}", e);
+        }
+    });
+
+    // Fill the embedded relay with history from the mirrors
+    if app_state.embedded_relay.is_some() {
+        let backfill_state = app_state.clone();
+        actix_rt::spawn(async move {
+            match backfill_state.backfill_embedded_relay().await {
+                Ok(stored) => log::info!("Embedded relay backfilled {} events from mirrors", stored),
+                Err(e) => log::warn!("Embedded relay backfill failed: {}", e),
+            }
+        });
+    }
+
+    // Bring the Postgres mirror up to date with what the relays hold
+    if app_state.postgres.is_some() {
+        let postgres_state = app_state.clone();
+        actix_rt::spawn(async move {
+            match postgres_state.backfill_postgres().await {
+                Ok(events) => log::info!("Postgres mirror backfilled from {} relay events", events),
+                Err(e) => log::warn!("Postgres backfill failed: {}", e),
+            }
+        });
+    }
+
+    // Collect replies to NIP-99 listings
+    if app_state.classifieds_enabled {
+        let classifieds_state = app_state.clone();
+        actix_rt::spawn(async move {
+            if let Err(e) = classifieds_state.run_classified_replies().await {
+                log::error!("Classified reply listener stopped: {}", e);
+            }
+        });
+    }
+
+    // Mirror deliveries from federated marketplaces
+    if app_state.federation.is_enabled() {
+        let federation_state = app_state.clone();
+        actix_rt::spawn(async move {
+            if let Err(e) = federation_state.run_federation().await {
+                log::error!("Federation stopped: {}", e);
+            }
+        });
+    }
+
+    // Background indexer keeps the delivery snapshot and aggregate stats fresh
+    let indexer_state = app_state.clone();
+    app_state.jobs.register(jobs::Job::every("indexer", app_state.index_interval_secs).immediate(), move || {
+        let state = indexer_state.clone();
+        async move { state.refresh_index().await }
+    }).await;
+
+    // Compaction of closed deliveries, when there is an archive to keep their history in
+    if app_state.compaction.archive_dir.is_some() {
+        let compaction_state = app_state.clone();
+        app_state.jobs.register(jobs::Job::every("compaction", app_state.compaction.interval_secs), move || {
+            let state = compaction_state.clone();
+            async move {
+                let report = state.compact_closed_deliveries().await?;
+                log::info!(
+                    "Compacted {} deliveries: {} events archived, {} superseded versions deleted",
+                    report.deliveries, report.archived_events, report.deleted
+                );
+                Ok(())
+            }
+        }).await;
+    }
+
+    // Accepted deliveries the courier never starts are re-opened
+    if app_state.start_timeout.is_enabled() {
+        let start_state = app_state.clone();
+        app_state.jobs.register(jobs::Job::every("start_timeout", app_state.start_timeout.interval_secs), move || {
+            let state = start_state.clone();
+            async move {
+                let reopened = state.check_unstarted().await?;
+                if reopened > 0 {
+                    log::info!("Re-opened {} unstarted deliveries", reopened);
+                }
+                Ok(())
+            }
+        }).await;
+    }
+
+    // Dead-man alerts for deliveries stuck in one status
+    if app_state.stuck.alerts_enabled() {
+        let stuck_state = app_state.clone();
+        app_state.jobs.register(jobs::Job::every("stuck", app_state.stuck.interval_secs), move || {
+            let state = stuck_state.clone();
+            async move {
+                let alerted = state.alert_stuck().await?;
+                if alerted > 0 {
+                    log::warn!("Alerted on {} stuck deliveries", alerted);
+                }
+                Ok(())
+            }
+        }).await;
+    }
+
+    // Redundancy checker republishes state events that too few relays hold; the first
+    // pass comes after one interval, once the indexer has a snapshot
+    let redundancy_state = app_state.clone();
+    app_state.jobs.register(jobs::Job::every("redundancy", app_state.redundancy.interval_secs), move || {
+        let state = redundancy_state.clone();
+        async move {
+            let report = state.check_redundancy().await?;
+            if report.under_replicated > 0 {
+                log::warn!(
+                    "Redundancy check: {} of {} events under-replicated, {} republished",
+                    report.under_replicated, report.events_checked, report.republished
+                );
+            } else {
+                log::info!("Redundancy check: {} events on at least {} relays", report.events_checked, report.min_relays);
+            }
+            Ok(())
+        }
+    }).await;
+
+    // TCP listener (BIND_ADDR, "off" to disable), HTTPS when a certificate is configured,
+    // plus an optional Unix socket for reverse proxies
+    let bind_addr = std::env::var("BIND_ADDR").unwrap_or_else(|_| "0.0.0.0:8080".to_string());
+    let tls_config = tls::TlsConfig::from_env()
+        .expect("Invalid TLS configuration");
+    let unix_socket = std::env::var("UNIX_SOCKET_PATH").ok().filter(|p| !p.is_empty());
+
+    let jobs_state = app_state.clone();
+    let mut server = HttpServer::new(move || {
+        let cors = Cors::permissive();
+
+        App::new()
+            .app_data(app_state.clone())
+            // Malformed bodies, query strings and paths also answer with problem details
+            .app_data(web::JsonConfig::default().error_handler(|e, _| {
+                problem_error(Problem::new(ProblemType::InvalidRequest, e.to_string()))
+            }))
+            .app_data(web::QueryConfig::default().error_handler(|e, _| {
+                problem_error(Problem::new(ProblemType::InvalidRequest, e.to_string()))
+            }))
+            .app_data(web::PathConfig::default().error_handler(|e, _| {
+                problem_error(Problem::new(ProblemType::InvalidRequest, e.to_string()))
+            }))
+            .wrap(cors)
+            .wrap(middleware::Compress::default())
+            .wrap(middleware::from_fn(hash_signed_body))
+            .wrap(middleware::from_fn(assign_request_id))
+            .wrap(middleware::Logger::new(r#"%a "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T %{x-request-id}o"#))
+            .route("/health", web::get().to(health_check))
+            .route("/health/live", web::get().to(health_live))
+            .route("/health/ready", web::get().to(health_ready))
+            .route("/api/openapi.json", web::get().to(openapi_json))
+            .route("/api/protocol", web::get().to(get_protocol))
+            .route("/api/docs", web::get().to(swagger_ui))
+            .service(web::scope("/api/auth").configure(auth_routes))
+            .service(web::scope("/api/integrations").configure(integration_routes))
+            .service(web::scope("/api/v2").configure(api_v2_routes))
+            .service(web::scope("/api/v1").configure(api_v1_routes))
+            // Legacy unversioned routes, kept as an alias of v1
+            .service(
+                web::scope("/api")
+                    .wrap(middleware::DefaultHeaders::new().add(("Deprecation", "true")))
+                    .configure(api_v1_routes)
+            )
+    });
+
+    if bind_addr != "off" {
+        if let Some(tls_config) = &tls_config {
+            let acceptor = tls_config.acceptor()
+                .expect("Failed to load TLS certificate");
+            // HTTP is served on a private loopback port behind the TLS listener
+            let internal = std::net::TcpListener::bind(("127.0.0.1", 0))?;
+            let upstream = internal.local_addr()?;
+            server = server.listen(internal)?;
+
+            let public = tokio::net::TcpListener::bind(&bind_addr).await?;
+            actix_rt::spawn(tls::serve(public, acceptor, upstream));
+            println!("🔒 Server ready on https://{}", bind_addr);
+        } else {
+            server = server.bind(&bind_addr)?;
+            println!("🌐 Server ready on http://{}", bind_addr);
+        }
+    }
+
+    if let Some(path) = &unix_socket {
+        // Clear a socket left behind by a previous run
+        use std::os::unix::fs::FileTypeExt;
+        if std::fs::metadata(path).is_ok_and(|m| m.file_type().is_socket()) {
+            std::fs::remove_file(path)?;
+        }
+        server = server.bind_uds(path)?;
+        println!("🌐 Server ready on unix:{}", path);
+    }
+
+    if bind_addr == "off" && unix_socket.is_none() {
+        return Err(std::io::Error::other("BIND_ADDR is off and no UNIX_SOCKET_PATH is set"));
+    }
+
+    let result = server.run().await;
+    // Let background jobs finish what they're doing before exiting
+    jobs_state.jobs.stop().await;
+    result
+}
@@ -0,0 +1,237 @@
+// moderation.rs - Pluggable review moderation run before feedback text is published
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+pub type ModerationError = Box<dyn std::error::Error + Send + Sync>;
+
+pub const DEFAULT_MAX_REVIEW_LENGTH: usize = 1000;
+// Author of held reviews left by a delivery's recipient, who has no npub
+pub const RECIPIENT_AUTHOR: &str = "recipient";
+// `d` tag of the NIP-78 event holding the reviews awaiting approval, encrypted to the system key
+pub const HELD_REVIEWS_IDENTIFIER: &str = "nostr-delivery/held-reviews";
+const REDACTED: &str = "[redacted]";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReviewDecision {
+    // Publish the (possibly rewritten) text
+    Publish(String),
+    // Keep the text off relays until an admin approves it
+    Hold { text: String, reason: String },
+}
+
+#[async_trait]
+pub trait ReviewFilter: Send + Sync {
+    async fn review(&self, text: &str) -> Result<ReviewDecision, ModerationError>;
+}
+
+// Runs filters in order, feeding each the previous output; the first hold wins and a
+// failing filter holds the text scrubbed so far
+pub struct FilterChain {
+    filters: Vec<Arc<dyn ReviewFilter>>,
+}
+
+impl FilterChain {
+    pub fn new(filters: Vec<Arc<dyn ReviewFilter>>) -> Self {
+        Self { filters }
+    }
+}
+
+#[async_trait]
+impl ReviewFilter for FilterChain {
+    async fn review(&self, text: &str) -> Result<ReviewDecision, ModerationError> {
+        let mut current = text.to_string();
+        for filter in &self.filters {
+            match filter.review(&current).await {
+                Ok(ReviewDecision::Publish(next)) => current = next,
+                Ok(hold) => return Ok(hold),
+                Err(e) => {
+                    log::warn!("Review filter failed: {}", e);
+                    return Ok(ReviewDecision::Hold {
+                        text: current,
+                        reason: "moderation unavailable".to_string(),
+                    });
+                }
+            }
+        }
+        Ok(ReviewDecision::Publish(current))
+    }
+}
+
+// Truncates reviews to a maximum number of characters
+pub struct LengthLimit {
+    pub max_chars: usize,
+}
+
+#[async_trait]
+impl ReviewFilter for LengthLimit {
+    async fn review(&self, text: &str) -> Result<ReviewDecision, ModerationError> {
+        if text.chars().count() <= self.max_chars {
+            return Ok(ReviewDecision::Publish(text.to_string()));
+        }
+        let mut truncated: String = text.chars().take(self.max_chars.saturating_sub(1)).collect();
+        truncated.push('…');
+        Ok(ReviewDecision::Publish(truncated))
+    }
+}
+
+// Masks blocklisted words and redacts email addresses and phone numbers
+pub struct Scrubber {
+    blocklist: Vec<String>,
+}
+
+impl Scrubber {
+    pub fn new(blocklist: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            blocklist: blocklist
+                .into_iter()
+                .map(|w| w.trim().to_lowercase())
+                .filter(|w| !w.is_empty())
+                .collect(),
+        }
+    }
+
+    pub fn scrub(&self, text: &str) -> String {
+        let tokens: Vec<&str> = text.split(' ').collect();
+        let mut out: Vec<String> = Vec::with_capacity(tokens.len());
+
+        let mut i = 0;
+        while i < tokens.len() {
+            // Phone numbers may be split across tokens ("555 123 4567")
+            let mut end = i;
+            let mut digits = 0;
+            while end < tokens.len() && is_phone_fragment(tokens[end]) {
+                digits += tokens[end].chars().filter(|c| c.is_ascii_digit()).count();
+                end += 1;
+            }
+            if digits >= 7 {
+                out.push(REDACTED.to_string());
+                i = end;
+                continue;
+            }
+
+            out.push(self.scrub_token(tokens[i]));
+            i += 1;
+        }
+
+        out.join(" ")
+    }
+
+    fn scrub_token(&self, token: &str) -> String {
+        let word = token.trim_matches(|c: char| !c.is_alphanumeric() && c != '@' && c != '.' && c != '_');
+        if is_email(word.trim_end_matches('.')) {
+            return token.replacen(word.trim_end_matches('.'), REDACTED, 1);
+        }
+
+        let bare = token.trim_matches(|c: char| !c.is_alphanumeric());
+        if !bare.is_empty() && self.blocklist.contains(&bare.to_lowercase()) {
+            return token.replacen(bare, &"*".repeat(bare.chars().count()), 1);
+        }
+
+        token.to_string()
+    }
+}
+
+fn is_email(word: &str) -> bool {
+    let Some((local, domain)) = word.split_once('@') else {
+        return false;
+    };
+    !local.is_empty() && domain.contains('.') && !domain.starts_with('.') && !domain.ends_with('.')
+}
+
+fn is_phone_fragment(token: &str) -> bool {
+    let token = token.trim_end_matches([',', '.', ';']);
+    !token.is_empty()
+        && token.chars().any(|c| c.is_ascii_digit())
+        && token.chars().all(|c| c.is_ascii_digit() || "+-().".contains(c))
+}
+
+#[async_trait]
+impl ReviewFilter for Scrubber {
+    async fn review(&self, text: &str) -> Result<ReviewDecision, ModerationError> {
+        Ok(ReviewDecision::Publish(self.scrub(text)))
+    }
+}
+
+// External moderation API: POST {"text"} returning {"flagged", "reason"}
+pub struct ExternalModeration {
+    client: reqwest::Client,
+    url: String,
+}
+
+#[derive(Deserialize)]
+struct ModerationResponse {
+    flagged: bool,
+    reason: Option<String>,
+}
+
+impl ExternalModeration {
+    pub fn new(url: &str) -> Self {
+        let client = reqwest::Client::builder()
+            .user_agent(concat!("nostr-delivery-backend/", env!("CARGO_PKG_VERSION")))
+            .timeout(std::time::Duration::from_secs(10))
+            .build()
+            .unwrap_or_default();
+
+        Self {
+            client,
+            url: url.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl ReviewFilter for ExternalModeration {
+    async fn review(&self, text: &str) -> Result<ReviewDecision, ModerationError> {
+        let response: ModerationResponse = self
+            .client
+            .post(&self.url)
+            .json(&serde_json::json!({ "text": text }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        if response.flagged {
+            Ok(ReviewDecision::Hold {
+                text: text.to_string(),
+                reason: response.reason.unwrap_or_else(|| "flagged by moderation service".to_string()),
+            })
+        } else {
+            Ok(ReviewDecision::Publish(text.to_string()))
+        }
+    }
+}
+
+// A review withheld from relays pending admin approval
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct HeldReview {
+    pub id: String,
+    pub delivery_id: String,
+    pub author: String,
+    pub text: String,
+    pub reason: String,
+    pub held_at: i64,
+}
+
+// Scrubber (REVIEW_BLOCKLIST), length limit (REVIEW_MAX_LENGTH) and, when
+// MODERATION_URL is set, an external moderation API
+pub fn from_env() -> Arc<dyn ReviewFilter> {
+    let blocklist = std::env::var("REVIEW_BLOCKLIST").unwrap_or_default();
+    let max_chars = std::env::var("REVIEW_MAX_LENGTH")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_MAX_REVIEW_LENGTH);
+
+    let mut filters: Vec<Arc<dyn ReviewFilter>> = vec![
+        Arc::new(Scrubber::new(blocklist.split(',').map(str::to_string))),
+        Arc::new(LengthLimit { max_chars }),
+    ];
+    if let Some(url) = std::env::var("MODERATION_URL").ok().filter(|u| !u.is_empty()) {
+        filters.push(Arc::new(ExternalModeration::new(&url)));
+    }
+
+    Arc::new(FilterChain::new(filters))
+}
@@ -0,0 +1,90 @@
+// native_bids.rs - Bids couriers publish straight to relays (kind 35001 signed with their own key)
+// instead of through POST /bid. The indexer validates them like API bids and merges them in.
+use nostr::{Event, PublicKey};
+use serde::Deserialize;
+
+use crate::protocol::NAMESPACE;
+use crate::query::{has_tag, identifier, tag_value};
+use crate::{Amount, DeliveryBid, PubkeyId};
+
+pub const BID_KIND: u16 = 35001;
+
+// Bids on a delivery the index doesn't have yet are fetched again for this long, then dropped
+pub const UNKNOWN_DELIVERY_RETRY_SECS: i64 = 3600;
+
+// NATIVE_BIDS=off stops picking them up (default on)
+pub fn enabled_from_env() -> bool {
+    std::env::var("NATIVE_BIDS").map_or(true, |v| v != "off" && v != "false" && v != "0")
+}
+
+// Content of a courier-signed bid. The courier is the signer; the amount may come from the
+// `amount` tag instead.
+#[derive(Debug, Deserialize)]
+struct NativeBidContent {
+    amount: Option<Amount>,
+    pickup_eta: Option<i64>,
+    dropoff_eta: Option<i64>,
+    message: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct NativeBid {
+    pub event_id: String,
+    pub delivery_id: String,
+    // Reputation fields are filled in from the courier's profile once it is accepted
+    pub bid: DeliveryBid,
+}
+
+impl NativeBid {
+    pub fn parse(event: &Event) -> Result<Self, String> {
+        if event.kind.as_u16() != BID_KIND {
+            return Err(format!("Event kind {} is not a bid", event.kind.as_u16()));
+        }
+        if !has_tag(event, "L", NAMESPACE) {
+            return Err(format!("Bid is not labeled with the {} namespace", NAMESPACE));
+        }
+        let id = identifier(event);
+        if id.is_empty() {
+            return Err("Bid has no d tag".to_string());
+        }
+        let delivery_id = tag_value(event, "delivery_id").ok_or("Bid has no delivery_id tag")?;
+        let content: NativeBidContent = serde_json::from_str(&event.content)
+            .map_err(|e| format!("Bid content is not valid JSON: {}", e))?;
+        let amount = content.amount
+            .or_else(|| tag_value(event, "amount").and_then(|a| a.parse().ok()))
+            .ok_or("Bid has no amount")?;
+        let (Some(pickup_eta), Some(dropoff_eta)) = (content.pickup_eta, content.dropoff_eta) else {
+            return Err("A bid needs pickup_eta and dropoff_eta".to_string());
+        };
+
+        Ok(Self {
+            event_id: event.id.to_hex(),
+            delivery_id: delivery_id.to_string(),
+            bid: DeliveryBid {
+                id: bid_id(&event.pubkey, id),
+                courier: PubkeyId::from(event.pubkey).npub(),
+                amount,
+                pickup_eta: Some(pickup_eta),
+                dropoff_eta: Some(dropoff_eta),
+                reputation: 0.0,
+                completed_deliveries: 0,
+                recent_rating: None,
+                ratings: None,
+                message: content.message,
+                created_at: event.created_at.as_u64() as i64,
+                stale: false,
+            },
+        })
+    }
+}
+
+// Marketplace ID of a courier's bid: the d tag alone is the courier's choice and may name
+// another delivery's bid, so it's scoped to its author
+pub fn bid_id(author: &PublicKey, d: &str) -> String {
+    format!("native:{}:{}", author.to_hex(), d)
+}
+
+// Whether the delivery already holds this bid, or a newer revision of it
+pub fn already_merged(existing: &[DeliveryBid], bid: &DeliveryBid) -> bool {
+    existing.iter().any(|b| b.id == bid.id && b.courier == bid.courier && b.created_at >= bid.created_at)
+}
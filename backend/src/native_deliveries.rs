@@ -0,0 +1,114 @@
+// native_deliveries.rs - Deliveries senders publish straight to relays (kind 35000 signed with their
+// own key) instead of through POST /deliveries. The indexer validates them and lists them.
+use nostr::Event;
+use serde_json::Value;
+
+use crate::protocol::NAMESPACE;
+use crate::query::{has_tag, identifier};
+use crate::timezone;
+use crate::{calculate_distance, DeliveryRequest, DeliveryStatus, PubkeyId, TimeWindow};
+
+pub const DELIVERY_KIND: u16 = 35000;
+
+// NATIVE_DELIVERIES=off stops picking them up (default on)
+pub fn enabled_from_env() -> bool {
+    std::env::var("NATIVE_DELIVERIES").map_or(true, |v| v != "off" && v != "false" && v != "0")
+}
+
+// Kind 35000 is shared with other apps; ours carry an `["L", "nostr-delivery"]` label namespace
+pub fn in_namespace(event: &Event) -> bool {
+    has_tag(event, "L", NAMESPACE)
+}
+
+// A new open delivery from a sender-signed event. The ID is the `d` tag, the sender the signer,
+// and the fields only the marketplace sets (bids, acceptance, proof) must be absent.
+pub fn parse(event: &Event, now: i64) -> Result<DeliveryRequest, String> {
+    if event.kind.as_u16() != DELIVERY_KIND {
+        return Err(format!("Event kind {} is not a delivery", event.kind.as_u16()));
+    }
+    if !in_namespace(event) {
+        return Err(format!("Delivery is not labeled with the {} namespace", NAMESPACE));
+    }
+    if has_tag(event, "encrypted", "nip44") {
+        return Err("Private deliveries must be created through the API".to_string());
+    }
+    let id = identifier(event);
+    if id.is_empty() {
+        return Err("Delivery has no d tag".to_string());
+    }
+    let signer = PubkeyId::from(event.pubkey).npub();
+
+    let mut content: Value = serde_json::from_str(&event.content)
+        .map_err(|e| format!("Delivery content is not valid JSON: {}", e))?;
+    let object = content.as_object_mut().ok_or("Delivery content is not a JSON object")?;
+    let defaults = [
+        ("id", Value::from(id)),
+        ("sender", Value::from(signer.clone())),
+        ("status", Value::from("open")),
+        ("bids", Value::Array(vec![])),
+        ("created_at", Value::from(event.created_at.as_u64() as i64)),
+        ("expires_at", Value::from(now + 604800)),
+    ];
+    for (field, default) in defaults {
+        if object.get(field).is_none_or(Value::is_null) {
+            object.insert(field.to_string(), default);
+        }
+    }
+    let mut delivery: DeliveryRequest = serde_json::from_value(content)
+        .map_err(|e| format!("Delivery content does not match the schema: {}", e))?;
+
+    if delivery.id != id {
+        return Err(format!("Delivery ID {} does not match its d tag {}", delivery.id, id));
+    }
+    if PubkeyId::normalize(&delivery.sender) != signer {
+        return Err("Delivery names a sender other than its signer".to_string());
+    }
+    if delivery.status != DeliveryStatus::Open
+        || !delivery.bids.is_empty()
+        || delivery.accepted_bid.is_some()
+        || delivery.proof_of_delivery.is_some()
+        || delivery.completed_at.is_some()
+        || delivery.assignment.is_some()
+        || delivery.deleted_at.is_some()
+        || !delivery.failed_attempts.is_empty()
+        || !delivery.no_shows.is_empty()
+    {
+        return Err("Only new open deliveries can be published directly".to_string());
+    }
+    if delivery.organization.is_some() || delivery.private {
+        return Err("Organization and private deliveries must be created through the API".to_string());
+    }
+    if !delivery.package_photos.is_empty() {
+        return Err("Package photos must be attached through the API".to_string());
+    }
+    if delivery.packages.is_empty() {
+        return Err("Delivery has no packages".to_string());
+    }
+    for (stop, location) in [("pickup", &delivery.pickup), ("dropoff", &delivery.dropoff)] {
+        if location.address.trim().is_empty() && location.coordinates.is_none() {
+            return Err(format!("The {} needs an address or coordinates", stop));
+        }
+    }
+    if let Some(name) = &delivery.timezone {
+        delivery.time_window = timezone::normalize_window(&delivery.time_window, timezone::parse(name)?)?;
+    }
+    if let Some(error) = timezone::window_error(&TimeWindow::parse(&delivery.time_window), now) {
+        return Err(error);
+    }
+
+    // Contacts would be public on the sender's event; they're never taken from it
+    delivery.sender = signer;
+    delivery.pickup.contact = None;
+    delivery.dropoff.contact = None;
+    delivery.sealed_contacts = None;
+    delivery.sealed_instructions = None;
+    delivery.contact_reveal = None;
+    delivery.instructions_reveal = None;
+    delivery.fraud_flags.clear();
+    delivery.origin = None;
+    if let (Some(p1), Some(p2)) = (&delivery.pickup.coordinates, &delivery.dropoff.coordinates) {
+        delivery.distance_meters = Some(calculate_distance(p1, p2));
+    }
+    delivery.local_times = Some(timezone::LocalTimes::for_delivery(&delivery));
+    Ok(delivery)
+}
@@ -0,0 +1,50 @@
+// nip89.rs - NIP-89 application handler announcement
+use nostr::{Event, Tag, TagKind};
+
+pub const HANDLER_INFO_KIND: u16 = 31990;
+pub const HANDLER_IDENTIFIER: &str = "nostr-delivery";
+
+// Delivery, bid, status, location ping and profile kinds
+pub const HANDLED_KINDS: std::ops::RangeInclusive<u16> = 35000..=35009;
+
+fn custom_tag(name: &str, values: Vec<String>) -> Tag {
+    Tag::custom(TagKind::Custom(name.to_string().into()), values)
+}
+
+// Kind 0 style metadata shown by clients when suggesting this app
+pub fn handler_metadata(frontend_url: &str) -> String {
+    serde_json::json!({
+        "name": "Nostr Delivery",
+        "about": "Peer-to-peer delivery marketplace: post deliveries, bid as a courier, track and confirm dropoffs.",
+        "website": frontend_url,
+    })
+    .to_string()
+}
+
+// Clients substitute `<bech32>` with the naddr/nevent of the event being opened
+pub fn handler_tags(open_url: &str) -> Vec<Tag> {
+    let template = format!("{}/<bech32>", open_url);
+
+    let mut tags = vec![custom_tag("d", vec![HANDLER_IDENTIFIER.to_string()])];
+    tags.extend(HANDLED_KINDS.map(|kind| custom_tag("k", vec![kind.to_string()])));
+    tags.push(custom_tag("web", vec![template.clone(), "naddr".to_string()]));
+    tags.push(custom_tag("web", vec![template, "nevent".to_string()]));
+
+    tags
+}
+
+// Delivery an event belongs to: `delivery_id` tag, or the `d` tag of a delivery event
+pub fn delivery_id_for_event(event: &Event) -> Option<String> {
+    let tag_value = |name: &str| {
+        event.tags.iter().find_map(|tag| {
+            let values = tag.as_slice();
+            (values.len() >= 2 && values[0] == name).then(|| values[1].clone())
+        })
+    };
+
+    if event.kind.as_u16() == 35000 {
+        tag_value("d")
+    } else {
+        tag_value("delivery_id")
+    }
+}
@@ -0,0 +1,135 @@
+// notify.rs - Notifications for recipients, who usually aren't Nostr users: pluggable providers
+// (an SMS webhook out of the box) reaching the dropoff contact
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::tracking::{self, LocationPing};
+use crate::GeoPoint;
+
+pub type NotifyError = Box<dyn std::error::Error + Send + Sync>;
+
+// Prefix of the `d` tag of the NIP-78 event holding a delivery's recipient contact
+pub const RECIPIENT_APP_DATA_PREFIX: &str = "nostr-delivery/recipient";
+pub const DEFAULT_ETA_MINUTES: i64 = 10;
+// Assumed courier speed until the track shows movement (about 30 km/h)
+pub const DEFAULT_SPEED_MPS: f64 = 8.0;
+// Recent pings used to estimate the courier's speed
+const SPEED_SAMPLE: usize = 5;
+
+// How to reach the dropoff contact; kept off the public delivery event
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RecipientContact {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub phone: Option<String>,
+}
+
+impl RecipientContact {
+    pub fn is_empty(&self) -> bool {
+        self.email.is_none() && self.phone.is_none()
+    }
+}
+
+#[async_trait]
+pub trait NotificationProvider: Send + Sync {
+    // Short name for logs, e.g. "sms"
+    fn channel(&self) -> &'static str;
+
+    // Ok(false) when the contact can't be reached on this channel
+    async fn notify(&self, contact: &RecipientContact, message: &str) -> Result<bool, NotifyError>;
+}
+
+// Texts sent by POSTing `{"to": "<phone>", "message": "..."}` to a gateway (Twilio function,
+// SMS relay, ...), with an optional bearer token
+pub struct SmsWebhook {
+    client: reqwest::Client,
+    url: String,
+    token: Option<String>,
+}
+
+impl SmsWebhook {
+    pub fn new(url: &str, token: Option<String>) -> Self {
+        let client = reqwest::Client::builder()
+            .user_agent(concat!("nostr-delivery-backend/", env!("CARGO_PKG_VERSION")))
+            .timeout(std::time::Duration::from_secs(10))
+            .build()
+            .unwrap_or_default();
+
+        Self { client, url: url.to_string(), token }
+    }
+}
+
+#[async_trait]
+impl NotificationProvider for SmsWebhook {
+    fn channel(&self) -> &'static str {
+        "sms"
+    }
+
+    async fn notify(&self, contact: &RecipientContact, message: &str) -> Result<bool, NotifyError> {
+        let Some(phone) = &contact.phone else {
+            return Ok(false);
+        };
+        let mut request = self.client.post(&self.url).json(&serde_json::json!({ "to": phone, "message": message }));
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        request.send().await?.error_for_status()?;
+        Ok(true)
+    }
+}
+
+#[derive(Clone)]
+pub struct RecipientNotifications {
+    pub providers: Vec<Arc<dyn NotificationProvider>>,
+    // Text the recipient once the courier is estimated to be this close
+    pub eta_minutes: i64,
+}
+
+impl RecipientNotifications {
+    // SMS_WEBHOOK_URL (with optional SMS_WEBHOOK_TOKEN) enables texts; RECIPIENT_ETA_MINUTES
+    // defaults to 10. None when no provider is configured.
+    pub fn from_env() -> Option<Self> {
+        let mut providers: Vec<Arc<dyn NotificationProvider>> = Vec::new();
+        if let Some(url) = std::env::var("SMS_WEBHOOK_URL").ok().filter(|u| !u.is_empty()) {
+            let token = std::env::var("SMS_WEBHOOK_TOKEN").ok().filter(|t| !t.is_empty());
+            providers.push(Arc::new(SmsWebhook::new(&url, token)));
+        }
+        if providers.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            providers,
+            eta_minutes: std::env::var("RECIPIENT_ETA_MINUTES")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|m| *m > 0)
+                .unwrap_or(DEFAULT_ETA_MINUTES),
+        })
+    }
+}
+
+// Seconds until the courier reaches `dropoff`, from the last ping and their recent speed
+pub fn eta_secs(pings: &[LocationPing], dropoff: &GeoPoint) -> Option<i64> {
+    let last = pings.last()?;
+    let distance = crate::calculate_distance(&GeoPoint { lat: last.lat, lng: last.lng }, dropoff);
+
+    let recent = &pings[pings.len().saturating_sub(SPEED_SAMPLE)..];
+    let (moved, elapsed) = recent
+        .windows(2)
+        .filter_map(|pair| tracking::implied_speed(&pair[0], &pair[1]).map(|(d, _)| (d, (pair[1].timestamp - pair[0].timestamp).max(1))))
+        .fold((0.0, 0), |(d, t), (dd, dt)| (d + dd, t + dt));
+    let speed = if elapsed > 0 { moved / elapsed as f64 } else { DEFAULT_SPEED_MPS };
+
+    Some((distance / speed.max(1.0)).round() as i64)
+}
+
+// Digits with an optional leading `+` and common separators, 7 to 15 digits (E.164)
+pub fn is_valid_phone(phone: &str) -> bool {
+    let phone = phone.trim();
+    let digits = phone.chars().filter(char::is_ascii_digit).count();
+    (7..=15).contains(&digits)
+        && phone.strip_prefix('+').unwrap_or(phone).chars().all(|c| c.is_ascii_digit() || " -().".contains(c))
+}
@@ -0,0 +1,210 @@
+// organizations.rs - Organization accounts with member roles and fleet rollups
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::analytics::{in_range, settled_amount};
+use crate::rbac::{Permission, RolePolicy};
+use crate::{Amount, DeliveryRequest, DeliveryStatus, PubkeyId};
+
+pub const ORGANIZATION_KIND: u16 = 35010;
+pub const ASSIGNMENT_KIND: u16 = 35008;
+// An organization's signing key, NIP-44-encrypted to the system key (`d` is the org ID)
+pub const ORG_KEY_KIND: u16 = 35015;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum OrgRole {
+    Owner,
+    Admin,
+    Dispatcher,
+    Sender,
+    Courier,
+    Arbitrator,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct OrgMember {
+    pub npub: String,
+    pub role: OrgRole,
+    pub added_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Organization {
+    pub id: String,
+    pub name: String,
+    pub members: Vec<OrgMember>,
+    pub created_at: i64,
+    // Hex pubkey of the key that signs the org's deliveries and this record
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pubkey: Option<String>,
+    // Per-permission role overrides
+    #[serde(default, skip_serializing_if = "RolePolicy::is_empty")]
+    pub permissions: RolePolicy,
+}
+
+impl Organization {
+    pub fn role_of(&self, npub: &str) -> Option<OrgRole> {
+        let npub = PubkeyId::normalize(npub);
+        self.members
+            .iter()
+            .find(|m| PubkeyId::normalize(&m.npub) == npub)
+            .map(|m| m.role)
+    }
+
+    pub fn allows(&self, npub: &str, permission: Permission) -> bool {
+        self.role_of(npub).is_some_and(|role| self.permissions.allows(role, permission))
+    }
+
+    // Whether `actor` may give `npub` the role `role` (None removes them). Only owners can
+    // grant or take away ownership.
+    pub fn member_change_error(&self, actor: &str, npub: &str, role: Option<OrgRole>) -> Option<&'static str> {
+        if !self.allows(actor, Permission::ManageMembers) {
+            return Some("Not allowed to manage members of this organization");
+        }
+        let touches_owner = role == Some(OrgRole::Owner) || self.role_of(npub) == Some(OrgRole::Owner);
+        (touches_owner && self.role_of(actor) != Some(OrgRole::Owner)).then_some("Only owners can grant or revoke ownership")
+    }
+
+    pub fn is_courier(&self, npub: &str) -> bool {
+        self.role_of(npub) == Some(OrgRole::Courier)
+    }
+
+    pub fn couriers(&self) -> impl Iterator<Item = &OrgMember> {
+        self.members.iter().filter(|m| m.role == OrgRole::Courier)
+    }
+
+    // Add a member or change their role; an org must always keep an owner
+    pub fn set_member(&mut self, npub: &str, role: OrgRole, now: i64) -> Result<(), String> {
+        let npub = PubkeyId::normalize(npub);
+        match self.members.iter_mut().find(|m| PubkeyId::normalize(&m.npub) == npub) {
+            Some(member) => member.role = role,
+            None => self.members.push(OrgMember { npub, role, added_at: now }),
+        }
+        self.ensure_owner()
+    }
+
+    pub fn remove_member(&mut self, npub: &str) -> Result<(), String> {
+        let npub = PubkeyId::normalize(npub);
+        let before = self.members.len();
+        self.members.retain(|m| PubkeyId::normalize(&m.npub) != npub);
+        if self.members.len() == before {
+            return Err("Not a member of this organization".to_string());
+        }
+        self.ensure_owner()
+    }
+
+    fn ensure_owner(&self) -> Result<(), String> {
+        if self.members.iter().any(|m| m.role == OrgRole::Owner) {
+            Ok(())
+        } else {
+            Err("An organization needs at least one owner".to_string())
+        }
+    }
+}
+
+// A dispatcher's direct assignment, pending the courier's acknowledgement
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Assignment {
+    pub courier: String,
+    pub dispatcher: String,
+    pub assigned_at: i64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct CourierRollup {
+    pub npub: String,
+    pub accepted_deliveries: u32,
+    pub completed_deliveries: u32,
+    pub earnings_sats: Amount,
+    pub average_rating: Option<f32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct FleetStats {
+    pub organization_id: String,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    pub deliveries_created: u32,
+    pub sats_spent: Amount,
+    pub completed_deliveries: u32,
+    pub earnings_sats: Amount,
+    pub completion_rate: Option<f64>,
+    pub average_rating: Option<f32>,
+    pub couriers: Vec<CourierRollup>,
+}
+
+impl FleetStats {
+    // Deliveries the org dispatched, plus work its couriers carried out, within the range
+    pub fn build(org: &Organization, deliveries: &[DeliveryRequest], from: Option<i64>, to: Option<i64>) -> Self {
+        let mut couriers: Vec<CourierRollup> = org
+            .couriers()
+            .map(|m| CourierRollup { npub: m.npub.clone(), ..Default::default() })
+            .collect();
+        let joined: Vec<i64> = org.couriers().map(|m| m.added_at).collect();
+        let mut ratings: Vec<Vec<f32>> = vec![Vec::new(); couriers.len()];
+
+        let mut stats = FleetStats {
+            organization_id: org.id.clone(),
+            from,
+            to,
+            deliveries_created: 0,
+            sats_spent: Amount::ZERO,
+            completed_deliveries: 0,
+            earnings_sats: Amount::ZERO,
+            completion_rate: None,
+            average_rating: None,
+            couriers: Vec::new(),
+        };
+
+        for delivery in deliveries.iter().filter(|d| in_range(d.created_at, from, to)) {
+            if delivery.organization.as_deref() == Some(org.id.as_str()) {
+                stats.deliveries_created += 1;
+                stats.sats_spent = stats.sats_spent.saturating_add(settled_amount(delivery).unwrap_or_default());
+            }
+
+            let Some(courier) = delivery.accepted_courier().map(PubkeyId::normalize) else {
+                continue;
+            };
+            let Some(idx) = couriers.iter().position(|c| PubkeyId::normalize(&c.npub) == courier) else {
+                continue;
+            };
+            // Only work done while a member of the fleet counts toward it
+            if delivery.created_at < joined[idx] {
+                continue;
+            }
+
+            let rollup = &mut couriers[idx];
+            rollup.accepted_deliveries += 1;
+            if matches!(delivery.status, DeliveryStatus::Completed | DeliveryStatus::Confirmed) {
+                rollup.completed_deliveries += 1;
+            }
+            rollup.earnings_sats = rollup.earnings_sats.saturating_add(settled_amount(delivery).unwrap_or_default());
+            if let Some(rating) = delivery.sender_rating {
+                ratings[idx].push(rating);
+            }
+        }
+
+        let mut accepted = 0;
+        let mut all_ratings = Vec::new();
+        for (rollup, courier_ratings) in couriers.iter_mut().zip(ratings) {
+            accepted += rollup.accepted_deliveries;
+            stats.completed_deliveries += rollup.completed_deliveries;
+            stats.earnings_sats = stats.earnings_sats.saturating_add(rollup.earnings_sats);
+            rollup.average_rating = average(&courier_ratings);
+            all_ratings.extend(courier_ratings);
+        }
+
+        if accepted > 0 {
+            stats.completion_rate = Some(stats.completed_deliveries as f64 / accepted as f64);
+        }
+        stats.average_rating = average(&all_ratings);
+        stats.couriers = couriers;
+
+        stats
+    }
+}
+
+fn average(values: &[f32]) -> Option<f32> {
+    (!values.is_empty()).then(|| values.iter().sum::<f32>() / values.len() as f32)
+}
@@ -0,0 +1,88 @@
+// outbox.rs - NIP-65 relay lists (kind 10002), so events that concern a user also reach the
+// relays they read from, not only the marketplace's own relay set
+use nostr::nips::nip65::{self, RelayMetadata};
+use nostr::Event;
+use std::collections::HashMap;
+
+pub const DEFAULT_MAX_RELAYS_PER_USER: usize = 3;
+pub const DEFAULT_CACHE_SECS: i64 = 3600;
+
+#[derive(Debug, Clone)]
+pub struct OutboxConfig {
+    pub enabled: bool,
+    // Read relays used per participant, in the order they are listed
+    pub max_relays_per_user: usize,
+    pub cache_secs: i64,
+}
+
+impl Default for OutboxConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_relays_per_user: DEFAULT_MAX_RELAYS_PER_USER,
+            cache_secs: DEFAULT_CACHE_SECS,
+        }
+    }
+}
+
+impl OutboxConfig {
+    // On unless OUTBOX_MODEL is false or 0; OUTBOX_MAX_RELAYS and OUTBOX_CACHE_SECS tune it
+    pub fn from_env() -> Self {
+        Self {
+            enabled: !std::env::var("OUTBOX_MODEL").is_ok_and(|v| v == "false" || v == "0"),
+            max_relays_per_user: std::env::var("OUTBOX_MAX_RELAYS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_MAX_RELAYS_PER_USER),
+            cache_secs: std::env::var("OUTBOX_CACHE_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_CACHE_SECS),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RelayList {
+    // Relays the user reads from: where events for them should be published
+    pub read: Vec<String>,
+    // Relays the user publishes to
+    pub write: Vec<String>,
+}
+
+impl RelayList {
+    // An `r` tag without a marker means the relay is used for both
+    pub fn from_event(event: &Event) -> Self {
+        let mut list = Self::default();
+        for (url, metadata) in nip65::extract_relay_list(event) {
+            let url = url.to_string().trim_end_matches('/').to_string();
+            if metadata != &Some(RelayMetadata::Write) && !list.read.contains(&url) {
+                list.read.push(url.clone());
+            }
+            if metadata != &Some(RelayMetadata::Read) && !list.write.contains(&url) {
+                list.write.push(url);
+            }
+        }
+        list
+    }
+}
+
+// Relay lists by hex pubkey. Users without one are cached as empty lists, so they aren't
+// looked up again on every event.
+#[derive(Debug, Default)]
+pub struct RelayListCache {
+    entries: HashMap<String, (RelayList, i64)>,
+}
+
+impl RelayListCache {
+    pub fn get(&self, pubkey: &str, now: i64, max_age_secs: i64) -> Option<&RelayList> {
+        self.entries
+            .get(pubkey)
+            .filter(|(_, fetched_at)| now - fetched_at < max_age_secs)
+            .map(|(list, _)| list)
+    }
+
+    pub fn insert(&mut self, pubkey: String, list: RelayList, now: i64) {
+        self.entries.insert(pubkey, (list, now));
+    }
+}
@@ -0,0 +1,126 @@
+// problem.rs - RFC 7807 problem details used for every error response
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+pub const CONTENT_TYPE: &str = "application/problem+json";
+// Type URIs are relative references under this path
+pub const TYPE_BASE: &str = "/problems/";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProblemType {
+    // Malformed or invalid input
+    InvalidRequest,
+    // The delivery's current state does not allow the operation
+    InvalidTransition,
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    Conflict,
+    Gone,
+    // A per-user quota or rate limit was hit
+    TooManyRequests,
+    // A relay did not answer in time
+    RelayTimeout,
+    // Too few relays accepted a published event
+    RelayQuorum,
+    Internal,
+}
+
+impl ProblemType {
+    pub fn slug(&self) -> &'static str {
+        match self {
+            ProblemType::InvalidRequest => "invalid-request",
+            ProblemType::InvalidTransition => "invalid-transition",
+            ProblemType::Unauthorized => "unauthorized",
+            ProblemType::Forbidden => "forbidden",
+            ProblemType::NotFound => "not-found",
+            ProblemType::Conflict => "conflict",
+            ProblemType::Gone => "gone",
+            ProblemType::TooManyRequests => "too-many-requests",
+            ProblemType::RelayTimeout => "relay-timeout",
+            ProblemType::RelayQuorum => "relay-quorum",
+            ProblemType::Internal => "internal-error",
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            ProblemType::InvalidRequest => "Invalid request",
+            ProblemType::InvalidTransition => "Invalid status transition",
+            ProblemType::Unauthorized => "Unauthorized",
+            ProblemType::Forbidden => "Forbidden",
+            ProblemType::NotFound => "Not found",
+            ProblemType::Conflict => "Conflict",
+            ProblemType::Gone => "Gone",
+            ProblemType::TooManyRequests => "Too many requests",
+            ProblemType::RelayTimeout => "Relay timeout",
+            ProblemType::RelayQuorum => "Relay quorum not met",
+            ProblemType::Internal => "Internal error",
+        }
+    }
+
+    pub fn status(&self) -> u16 {
+        match self {
+            ProblemType::InvalidRequest | ProblemType::InvalidTransition => 400,
+            ProblemType::Unauthorized => 401,
+            ProblemType::Forbidden => 403,
+            ProblemType::NotFound => 404,
+            ProblemType::Conflict => 409,
+            ProblemType::Gone => 410,
+            ProblemType::TooManyRequests => 429,
+            ProblemType::RelayTimeout => 504,
+            ProblemType::RelayQuorum => 502,
+            ProblemType::Internal => 500,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Problem {
+    #[serde(rename = "type")]
+    pub type_uri: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    // The delivery the error concerns, as a resource path
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+    // Extension members, e.g. the fraud flags holding a delivery
+    #[serde(flatten)]
+    #[schema(value_type = Object)]
+    pub extensions: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Problem {
+    pub fn new(kind: ProblemType, detail: impl Into<String>) -> Self {
+        Self {
+            type_uri: format!("{}{}", TYPE_BASE, kind.slug()),
+            title: kind.title().to_string(),
+            status: kind.status(),
+            detail: detail.into(),
+            instance: None,
+            extensions: serde_json::Map::new(),
+        }
+    }
+
+    pub fn for_delivery(mut self, delivery_id: &str) -> Self {
+        self.instance = Some(format!("/api/v1/deliveries/{}", delivery_id));
+        self
+    }
+
+    pub fn with(mut self, key: &str, value: impl Serialize) -> Self {
+        self.extensions.insert(key.to_string(), serde_json::to_value(value).unwrap_or_default());
+        self
+    }
+
+    // Classify an error from the relay or signing layer
+    pub fn from_backend_error(message: &str) -> Self {
+        let lower = message.to_lowercase();
+        let kind = if lower.contains("timeout") || lower.contains("timed out") {
+            ProblemType::RelayTimeout
+        } else {
+            ProblemType::Internal
+        };
+        Self::new(kind, message)
+    }
+}
@@ -0,0 +1,207 @@
+// projection.rs - Current state of a delivery folded from its events: the delivery event, then
+// the latest status update of each kind published at or after it, oldest first. Relay reads, the
+// indexer and the Postgres mirror all go through this one fold.
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use utoipa::ToSchema;
+
+use crate::{Amount, DeliveryRequest, DeliveryStatus, DeliveryUpdate, ProofOfDelivery, RatingBreakdown};
+
+// How far before the last sync each sync looks again, for updates that reach the relays late
+pub const SYNC_OVERLAP_SECS: i64 = 120;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PaymentState {
+    // No bid accepted; nobody is owed anything
+    Unassigned,
+    // A bid was accepted and the courier is paid on completion
+    Committed,
+    // Disputed; held until an admin resolves it
+    Held,
+    // Paid out: completed, confirmed, or forfeited after acceptance
+    Settled,
+}
+
+impl PaymentState {
+    pub fn of(status: &DeliveryStatus, accepted: bool) -> Self {
+        match status {
+            DeliveryStatus::Completed | DeliveryStatus::Confirmed => PaymentState::Settled,
+            DeliveryStatus::Expired if accepted => PaymentState::Settled,
+            DeliveryStatus::Disputed => PaymentState::Held,
+            DeliveryStatus::Accepted | DeliveryStatus::InTransit => PaymentState::Committed,
+            _ => PaymentState::Unassigned,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DeliveryProjection {
+    pub delivery_id: String,
+    pub status: DeliveryStatus,
+    pub accepted_bid: Option<String>,
+    pub completed_at: Option<i64>,
+    // The latest proof any update carried
+    pub proof_of_delivery: Option<ProofOfDelivery>,
+    pub sender_rating: Option<f32>,
+    pub sender_ratings: Option<RatingBreakdown>,
+    pub sender_feedback: Option<String>,
+    pub payment: PaymentState,
+    // What the courier is or was paid; none while unassigned
+    pub payment_amount: Option<Amount>,
+    pub delivery_event_at: i64,
+    // Timestamp of the newest update folded in, or of the delivery event
+    pub updated_at: i64,
+}
+
+impl DeliveryProjection {
+    // The state the delivery event itself describes
+    pub fn from_delivery(delivery: &DeliveryRequest, delivery_event_at: i64) -> Self {
+        let mut projection = Self {
+            delivery_id: delivery.id.clone(),
+            status: delivery.status.clone(),
+            accepted_bid: delivery.accepted_bid.clone(),
+            completed_at: delivery.completed_at,
+            proof_of_delivery: delivery.proof_of_delivery.clone(),
+            sender_rating: delivery.sender_rating,
+            sender_ratings: delivery.sender_ratings,
+            sender_feedback: delivery.sender_feedback.clone(),
+            payment: PaymentState::Unassigned,
+            payment_amount: None,
+            delivery_event_at,
+            updated_at: delivery_event_at,
+        };
+        projection.settle(delivery.offer_amount);
+        projection
+    }
+
+    // Fold the updates published at or after the delivery event onto it, in order
+    pub fn project<'a>(delivery: &DeliveryRequest, delivery_event_at: i64, updates: impl IntoIterator<Item = &'a DeliveryUpdate>) -> Self {
+        let mut updates: Vec<&DeliveryUpdate> = updates.into_iter()
+            .filter(|u| u.timestamp >= delivery_event_at)
+            .collect();
+        // Updates in the same second follow the lifecycle order of their kinds
+        updates.sort_by_key(|u| (u.timestamp, u.kind()));
+
+        let mut projection = Self::from_delivery(delivery, delivery_event_at);
+        for update in updates {
+            projection.fold(update);
+        }
+        projection.settle(delivery.offer_amount);
+        projection
+    }
+
+    // Each update sets the status and only the fields it carries
+    fn fold(&mut self, update: &DeliveryUpdate) {
+        self.status = update.status.clone();
+        if update.proof_of_delivery.is_some() {
+            self.proof_of_delivery = update.proof_of_delivery.clone();
+        }
+        if update.completed_at.is_some() {
+            self.completed_at = update.completed_at;
+        }
+        if update.accepted_bid.is_some() {
+            self.accepted_bid = update.accepted_bid.clone();
+        }
+        if update.sender_rating.is_some() {
+            self.sender_rating = update.sender_rating;
+        }
+        if update.sender_ratings.is_some() {
+            self.sender_ratings = update.sender_ratings;
+        }
+        if update.sender_feedback.is_some() {
+            self.sender_feedback = update.sender_feedback.clone();
+        }
+        self.updated_at = self.updated_at.max(update.timestamp);
+    }
+
+    fn settle(&mut self, offer_amount: Amount) {
+        self.payment = PaymentState::of(&self.status, self.accepted_bid.is_some());
+        self.payment_amount = (self.payment != PaymentState::Unassigned).then_some(offer_amount);
+    }
+
+    // Write the projected lifecycle fields onto a delivery read from its event
+    pub fn apply_to(&self, delivery: &mut DeliveryRequest) {
+        delivery.status = self.status.clone();
+        delivery.accepted_bid = self.accepted_bid.clone();
+        delivery.completed_at = self.completed_at;
+        delivery.proof_of_delivery = self.proof_of_delivery.clone();
+        delivery.sender_rating = self.sender_rating;
+        delivery.sender_ratings = self.sender_ratings;
+        delivery.sender_feedback = self.sender_feedback.clone();
+    }
+}
+
+// Inputs of one delivery's projection: its newest delivery event and the latest update per kind
+#[derive(Debug, Default)]
+struct Entry {
+    delivery: Option<(DeliveryRequest, i64)>,
+    updates: HashMap<u16, DeliveryUpdate>,
+    current: Option<DeliveryProjection>,
+}
+
+impl Entry {
+    fn refold(&mut self) {
+        self.current = self.delivery.as_ref()
+            .map(|(delivery, at)| DeliveryProjection::project(delivery, *at, self.updates.values()));
+    }
+}
+
+// One projection per delivery, kept up to date event by event
+#[derive(Debug, Default)]
+pub struct Projections {
+    entries: HashMap<String, Entry>,
+    // When status updates were last synced from the relays
+    pub synced_until: Option<i64>,
+}
+
+impl Projections {
+    // A delivery event as read from the relays; older versions than the one held are ignored
+    pub fn record_delivery(&mut self, delivery: &DeliveryRequest, delivery_event_at: i64) {
+        let entry = self.entries.entry(delivery.id.clone()).or_default();
+        if entry.delivery.as_ref().is_some_and(|(_, at)| *at > delivery_event_at) {
+            return;
+        }
+        let mut base = delivery.clone();
+        // Bids live in their own events
+        base.bids.clear();
+        entry.delivery = Some((base, delivery_event_at));
+        entry.refold();
+    }
+
+    // A status update of the given event kind; like the events, the latest per kind replaces the rest
+    pub fn record_update(&mut self, delivery_id: &str, kind: u16, update: DeliveryUpdate) {
+        let entry = self.entries.entry(delivery_id.to_string()).or_default();
+        if entry.updates.get(&kind).is_some_and(|held| held.timestamp > update.timestamp) {
+            return;
+        }
+        entry.updates.insert(kind, update);
+        entry.refold();
+    }
+
+    pub fn mark_synced(&mut self, until: i64) {
+        self.synced_until = Some(self.synced_until.map_or(until, |synced| synced.max(until)));
+    }
+
+    pub fn get(&self, delivery_id: &str) -> Option<&DeliveryProjection> {
+        self.entries.get(delivery_id)?.current.as_ref()
+    }
+
+    // The latest update of each kind held for a delivery, oldest first, including any published
+    // before the delivery event was last republished
+    pub fn updates(&self, delivery_id: &str) -> Vec<&DeliveryUpdate> {
+        let mut updates: Vec<&DeliveryUpdate> = self.entries.get(delivery_id)
+            .map(|entry| entry.updates.values().collect())
+            .unwrap_or_default();
+        updates.sort_by_key(|u| (u.timestamp, u.kind()));
+        updates
+    }
+
+    // Record a delivery read from its event and bring it up to date with its projection
+    pub fn project(&mut self, delivery: &mut DeliveryRequest, delivery_event_at: i64) {
+        self.record_delivery(delivery, delivery_event_at);
+        if let Some(projection) = self.get(&delivery.id) {
+            projection.apply_to(delivery);
+        }
+    }
+}
@@ -0,0 +1,250 @@
+// protocol.rs - Machine-readable description of the events this marketplace publishes: kinds,
+// tags and content schemas, for courier apps that talk to relays directly (GET /api/protocol)
+use serde::Serialize;
+use std::collections::BTreeMap;
+use utoipa::openapi::{RefOr, Schema};
+use utoipa::ToSchema;
+
+use crate::{
+    classifieds, courier_lists, nip89, organizations, revisions, rotation, tracking, DeliveryBid, DeliveryRequest,
+    DeliveryUpdate, UserProfile, ACCEPTED_KIND, COMPLETED_KIND, CONFIRMED_KIND, IN_TRANSIT_KIND, STARTED_KIND,
+    TOMBSTONE_KIND,
+};
+
+// Prefix of every identifier this marketplace puts in shared kinds (NIP-78 app data, NIP-51 sets)
+pub const NAMESPACE: &str = "nostr-delivery";
+pub const PROTOCOL_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct TagSpec {
+    pub name: String,
+    pub description: String,
+    pub required: bool,
+    // The tag may appear more than once
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub repeated: bool,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentFormat {
+    Json,
+    // NIP-44 encrypted to the marketplace key; only the marketplace can read it
+    Nip44,
+    Text,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct KindSpec {
+    pub kind: u16,
+    pub name: String,
+    pub description: String,
+    pub content: ContentFormat,
+    // JSON Schema of the content, as a reference into `components.schemas`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schema: Option<String>,
+    pub tags: Vec<TagSpec>,
+}
+
+// Not Debug: utoipa's schema types don't implement it
+#[derive(Clone, Serialize, ToSchema)]
+pub struct Components {
+    #[schema(value_type = Object)]
+    pub schemas: BTreeMap<String, RefOr<Schema>>,
+}
+
+#[derive(Clone, Serialize, ToSchema)]
+pub struct Protocol {
+    pub name: String,
+    pub version: u32,
+    pub namespace: String,
+    // Events are trusted when signed by one of these keys (hex), or by an organization's key
+    // announced under the organization key kind
+    pub authors: Vec<String>,
+    // Kind by name, for quick lookups
+    pub kind_map: BTreeMap<String, u16>,
+    pub kinds: Vec<KindSpec>,
+    // Tags any of the kinds may carry
+    pub common_tags: Vec<TagSpec>,
+    pub components: Components,
+}
+
+impl Protocol {
+    pub fn describe(authors: Vec<String>, classifieds_enabled: bool) -> Self {
+        let kinds = kinds(classifieds_enabled);
+        Self {
+            name: NAMESPACE.to_string(),
+            version: PROTOCOL_VERSION,
+            namespace: NAMESPACE.to_string(),
+            authors,
+            kind_map: kinds.iter().map(|k| (k.name.clone(), k.kind)).collect(),
+            kinds,
+            common_tags: vec![
+                tag("request_id", "ID of the API request that caused the event, for support", false),
+                tag("organization", "Organization whose key signed the event", false),
+                tag("delegation", "NIP-26 delegation from the user the event speaks for", false),
+            ],
+            components: Components { schemas: schemas() },
+        }
+    }
+}
+
+fn tag(name: &str, description: &str, required: bool) -> TagSpec {
+    TagSpec { name: name.to_string(), description: description.to_string(), required, repeated: false }
+}
+
+fn repeated(name: &str, description: &str) -> TagSpec {
+    TagSpec { repeated: true, ..tag(name, description, false) }
+}
+
+fn spec(kind: u16, name: &str, description: &str, content: ContentFormat, schema: Option<&str>, tags: Vec<TagSpec>) -> KindSpec {
+    KindSpec {
+        kind,
+        name: name.to_string(),
+        description: description.to_string(),
+        content,
+        schema: schema.map(|s| format!("#/components/schemas/{}", s)),
+        tags,
+    }
+}
+
+fn status_spec(kind: u16, status: &str) -> KindSpec {
+    spec(
+        kind,
+        &format!("status: {}", status),
+        "Status change of a delivery, replaceable per delivery",
+        ContentFormat::Json,
+        Some("DeliveryUpdate"),
+        vec![
+            tag("d", "Delivery ID", true),
+            tag("delivery_id", "Delivery ID", true),
+            tag("status", "New status, lowercase", true),
+            tag("timestamp", "Unix time of the change", true),
+        ],
+    )
+}
+
+// Every kind this marketplace publishes, in kind order
+pub fn kinds(classifieds_enabled: bool) -> Vec<KindSpec> {
+    let mut kinds = vec![
+        spec(35000, "delivery", "Delivery request, replaceable; the latest version is the current state. Private deliveries and drafts carry only `d`, `status` and `encrypted`, with NIP-44 content.", ContentFormat::Json, Some("DeliveryRequest"), vec![
+            tag("d", "Delivery ID", true),
+            tag("status", "Delivery status, lowercase", true),
+            tag("sender", "Sender npub", false),
+            tag("amount", "Offer in sats", false),
+            tag("created_at", "Unix time the delivery was created", false),
+            tag("min_courier_reputation", "Lowest reputation allowed to bid", false),
+            tag("min_completed_deliveries", "Fewest completed deliveries allowed to bid", false),
+            repeated("allowed_courier", "Courier npub allowed to bid; anyone may when absent"),
+            repeated("imeta", "Package photo: `url <url>` and `x <sha256>`"),
+            tag("encrypted", "`nip44` when the content is encrypted", false),
+            tag("L", &format!("`{}`; required on deliveries senders publish with their own key", NAMESPACE), false),
+        ]),
+        spec(35001, "bid", "Courier's bid on a delivery. Couriers may publish bids signed with their own key; the marketplace checks them and republishes accepted ones.", ContentFormat::Json, Some("DeliveryBid"), vec![
+            tag("d", "Bid ID", true),
+            tag("delivery_id", "Delivery ID", true),
+            tag("courier", "Courier npub", true),
+            tag("amount", "Bid in sats", true),
+            tag("L", &format!("`{}`; required on bids couriers publish with their own key", NAMESPACE), false),
+        ]),
+        status_spec(ACCEPTED_KIND, "accepted"),
+        status_spec(STARTED_KIND, "started"),
+        status_spec(IN_TRANSIT_KIND, "in transit"),
+        status_spec(COMPLETED_KIND, "completed"),
+        status_spec(CONFIRMED_KIND, "confirmed"),
+        spec(35007, "location ping", "Courier position during a delivery", ContentFormat::Json, Some("LocationPing"), vec![
+            tag("d", "`<delivery ID>:<unix millis>`", true),
+            tag("delivery_id", "Delivery ID", true),
+            tag("courier", "Courier npub", true),
+        ]),
+        spec(organizations::ASSIGNMENT_KIND, "assignment", "Dispatcher assigning an organization delivery; content is `{delivery_id, state, assignment}`", ContentFormat::Json, Some("Assignment"), vec![
+            tag("d", "`<delivery ID>:<assigned at>`", true),
+            tag("delivery_id", "Delivery ID", true),
+            tag("courier", "Assigned courier npub", true),
+            tag("dispatcher", "Dispatcher npub", true),
+            tag("state", "`assigned`, `accepted` or `declined`", true),
+            tag("p", "Assigned courier, hex", false),
+        ]),
+        spec(35009, "user profile", "Marketplace profile: reputation, ratings and earnings", ContentFormat::Json, Some("UserProfile"), vec![
+            tag("d", "User npub", true),
+            tag("reputation", "Reputation score", true),
+            tag("completed_deliveries", "Completed deliveries as courier", true),
+            tag("t", "`leaderboard` when the courier opted in", false),
+        ]),
+        spec(organizations::ORGANIZATION_KIND, "organization", "Organization and its members", ContentFormat::Json, Some("Organization"), vec![
+            tag("d", "Organization ID", true),
+            tag("name", "Organization name", true),
+            repeated("member", "Member npub and role"),
+        ]),
+        spec(revisions::REVISION_KIND, "revision", "Edit to a delivery with field-level diffs", ContentFormat::Json, Some("DeliveryRevision"), vec![
+            tag("d", "`<delivery ID>:<revision>`", true),
+            tag("delivery_id", "Delivery ID", true),
+            repeated("changed", "Name of a changed field"),
+            tag("encrypted", "`nip44` for revisions of private deliveries", false),
+        ]),
+        spec(TOMBSTONE_KIND, "deletion", "Delivery deleted or restored; content is `{delivery_id, action, at}`", ContentFormat::Json, None, vec![
+            tag("d", "`<delivery ID>:<unix time>`", true),
+            tag("delivery_id", "Delivery ID", true),
+            tag("action", "`deleted` or `restored`", true),
+        ]),
+        spec(rotation::ROTATION_KIND, "key rotation", "Signed by a retiring marketplace key, naming its successor", ContentFormat::Text, None, vec![
+            tag("d", "Retiring key, hex", true),
+            tag("p", "Successor key, hex", true),
+            tag("rotated_at", "Unix time of the rotation", true),
+            tag("grace_until", "Events signed by the retiring key are trusted until then", true),
+        ]),
+        spec(organizations::ORG_KEY_KIND, "organization key", "Organization signing key, for the marketplace only", ContentFormat::Nip44, None, vec![
+            tag("d", "Organization ID", true),
+            tag("p", "Organization public key, hex", true),
+            tag("encrypted", "`nip44`", true),
+        ]),
+        spec(courier_lists::FOLLOW_SET_KIND, "courier list", "NIP-51 follow set of a sender's favorite couriers (public `p` tags) or blocked couriers (encrypted content)", ContentFormat::Nip44, None, vec![
+            tag("d", &format!("`{}/favorite-couriers/<sender>` or `{}/blocked-couriers/<sender>`", NAMESPACE, NAMESPACE), true),
+            tag("title", "List name and sender", true),
+            repeated("p", "Courier, hex"),
+        ]),
+        spec(30078, "user settings", "NIP-78 app data: user settings, recipient contacts and API keys", ContentFormat::Nip44, None, vec![
+            tag("d", &format!("Starts with `{}/`", NAMESPACE), true),
+        ]),
+        spec(nip89::HANDLER_INFO_KIND, "handler announcement", "NIP-89 handler information for the marketplace kinds", ContentFormat::Json, None, vec![
+            tag("d", "Handler identifier", true),
+            repeated("k", "Handled kind"),
+            repeated("web", "Frontend URL template and the NIP-19 entity it takes"),
+        ]),
+    ];
+    if classifieds_enabled {
+        kinds.push(spec(classifieds::CLASSIFIED_LISTING_KIND, "classified listing", "NIP-99 listing mirroring an open delivery", ContentFormat::Text, None, vec![
+            tag("d", "Delivery ID", true),
+            tag("title", "Listing title", true),
+            tag("summary", "One-line summary", true),
+            tag("published_at", "Unix time the delivery was created", true),
+            tag("price", "Offer and `SATS`", true),
+            tag("location", "Pickup address", true),
+            tag("status", "`active` or `sold`", true),
+            tag("t", "`delivery`", true),
+            tag("delivery_id", "Delivery ID", true),
+            tag("g", "Geohash of the pickup", false),
+        ]));
+    }
+    kinds.sort_by_key(|k| k.kind);
+    kinds
+}
+
+// Content schemas and everything they reference
+pub fn schemas() -> BTreeMap<String, RefOr<Schema>> {
+    let mut schemas = Vec::new();
+    collect::<DeliveryRequest>(&mut schemas);
+    collect::<DeliveryBid>(&mut schemas);
+    collect::<DeliveryUpdate>(&mut schemas);
+    collect::<tracking::LocationPing>(&mut schemas);
+    collect::<organizations::Assignment>(&mut schemas);
+    collect::<UserProfile>(&mut schemas);
+    collect::<organizations::Organization>(&mut schemas);
+    collect::<revisions::DeliveryRevision>(&mut schemas);
+    schemas.into_iter().collect()
+}
+
+fn collect<T: ToSchema>(schemas: &mut Vec<(String, RefOr<Schema>)>) {
+    schemas.push((T::name().into_owned(), T::schema()));
+    T::schemas(schemas);
+}
@@ -0,0 +1,71 @@
+// proxy.rs - SOCKS5 proxies for relay connections, e.g. Tor for .onion relays or to hide the
+// node's IP
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+#[derive(Debug, Clone, Default)]
+pub struct ProxyConfig {
+    pub global: Option<SocketAddr>,
+    // Only send .onion relays through the global proxy
+    pub onion_only: bool,
+    // Per-relay overrides; None connects directly
+    pub per_relay: HashMap<String, Option<SocketAddr>>,
+}
+
+impl ProxyConfig {
+    // NOSTR_PROXY (host:port), NOSTR_PROXY_ONION_ONLY and NOSTR_RELAY_PROXIES, a comma-separated
+    // list of relay=host:port or relay=direct
+    pub fn from_env() -> Result<Self, String> {
+        let global = match std::env::var("NOSTR_PROXY").ok().filter(|v| !v.is_empty()) {
+            Some(addr) => Some(parse_addr("NOSTR_PROXY", &addr)?),
+            None => None,
+        };
+
+        let mut per_relay = HashMap::new();
+        for entry in std::env::var("NOSTR_RELAY_PROXIES").unwrap_or_default().split(',') {
+            let entry = entry.trim();
+            if entry.is_empty() {
+                continue;
+            }
+            let (relay, proxy) = entry
+                .rsplit_once('=')
+                .ok_or_else(|| format!("NOSTR_RELAY_PROXIES entry {} must be relay=host:port or relay=direct", entry))?;
+            let proxy = match proxy.trim() {
+                "direct" => None,
+                addr => Some(parse_addr("NOSTR_RELAY_PROXIES", addr)?),
+            };
+            per_relay.insert(normalize_url(relay), proxy);
+        }
+
+        Ok(Self {
+            global,
+            onion_only: std::env::var("NOSTR_PROXY_ONION_ONLY").is_ok_and(|v| v == "true" || v == "1"),
+            per_relay,
+        })
+    }
+
+    // Proxy to reach `url` through, if any
+    pub fn proxy_for(&self, url: &str) -> Option<SocketAddr> {
+        if let Some(proxy) = self.per_relay.get(&normalize_url(url)) {
+            return *proxy;
+        }
+        if self.onion_only && !is_onion(url) {
+            return None;
+        }
+        self.global
+    }
+}
+
+fn parse_addr(var: &str, addr: &str) -> Result<SocketAddr, String> {
+    addr.trim().parse().map_err(|e| format!("Invalid {} proxy {}: {}", var, addr, e))
+}
+
+fn normalize_url(url: &str) -> String {
+    url.trim().trim_end_matches('/').to_lowercase()
+}
+
+pub fn is_onion(url: &str) -> bool {
+    let host = url.split("://").nth(1).unwrap_or(url);
+    let host = host.split(['/', ':']).next().unwrap_or_default();
+    host.ends_with(".onion")
+}
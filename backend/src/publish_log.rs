@@ -0,0 +1,188 @@
+// publish_log.rs - What each relay answered to the events we published: OK, or the rejection or
+// NOTICE it sent instead. Recent publishes are kept for the audit trail and counted per relay.
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use utoipa::ToSchema;
+
+pub const DEFAULT_CAPACITY: usize = 1000;
+
+// PUBLISH_QUORUM: relays that must accept an event before publishing it succeeds (default 1).
+// Capped at the number of relays it was sent to.
+pub fn quorum_from_env() -> usize {
+    std::env::var("PUBLISH_QUORUM")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|quorum| *quorum > 0)
+        .unwrap_or(1)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct RelayAck {
+    pub relay: String,
+    pub accepted: bool,
+    // The relay's OK message or NOTICE, or why the event never reached it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct PublishRecord {
+    // Unknown only for a gift-wrapped DM that failed before it was sent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub event_id: Option<String>,
+    pub kind: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delivery_id: Option<String>,
+    pub published_at: i64,
+    pub acks: Vec<RelayAck>,
+    // Set when the publish failed as a whole, e.g. no relay accepted it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl PublishRecord {
+    pub fn accepted(&self) -> usize {
+        self.acks.iter().filter(|ack| ack.accepted).count()
+    }
+}
+
+impl PublishRecord {
+    // Err when fewer than `quorum` of the relays it went to accepted the event
+    pub fn check_quorum(&self, quorum: usize) -> Result<(), QuorumError> {
+        let required = quorum.min(self.acks.len());
+        let accepted = self.accepted();
+        if accepted >= required {
+            return Ok(());
+        }
+        Err(QuorumError {
+            event_id: self.event_id.clone(),
+            kind: self.kind,
+            required,
+            accepted,
+            acks: self.acks.clone(),
+        })
+    }
+}
+
+// Too few relays accepted an event. Those that did keep it, so the publish may be retried.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct QuorumError {
+    pub event_id: Option<String>,
+    pub kind: u16,
+    pub required: usize,
+    pub accepted: usize,
+    pub acks: Vec<RelayAck>,
+}
+
+impl std::fmt::Display for QuorumError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} of {} required relays accepted the kind {} event",
+            self.accepted, self.required, self.kind
+        )
+    }
+}
+
+impl std::error::Error for QuorumError {}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct RelayCounts {
+    pub relay: String,
+    pub accepted: u64,
+    pub rejected: u64,
+}
+
+// Totals since startup
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct PublishMetrics {
+    pub published: u64,
+    // Publishes no relay accepted
+    pub failed: u64,
+    pub relays: Vec<RelayCounts>,
+}
+
+#[derive(Debug)]
+pub struct PublishLog {
+    capacity: usize,
+    records: VecDeque<PublishRecord>,
+    published: u64,
+    failed: u64,
+    relays: BTreeMap<String, RelayCounts>,
+}
+
+impl PublishLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            records: VecDeque::new(),
+            published: 0,
+            failed: 0,
+            relays: BTreeMap::new(),
+        }
+    }
+
+    // PUBLISH_LOG_SIZE: publishes kept for the audit trail (default 1000)
+    pub fn from_env() -> Self {
+        Self::new(
+            std::env::var("PUBLISH_LOG_SIZE")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_CAPACITY),
+        )
+    }
+
+    pub fn record(&mut self, record: PublishRecord) {
+        self.published += 1;
+        if record.accepted() == 0 {
+            self.failed += 1;
+        }
+        for ack in &record.acks {
+            let counts = self.relays.entry(ack.relay.clone()).or_insert_with(|| RelayCounts {
+                relay: ack.relay.clone(),
+                ..Default::default()
+            });
+            if ack.accepted {
+                counts.accepted += 1;
+            } else {
+                counts.rejected += 1;
+            }
+        }
+
+        if self.capacity == 0 {
+            return;
+        }
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+        }
+        self.records.push_back(record);
+    }
+
+    // Newest first, optionally only those of one delivery or event
+    pub fn recent(&self, delivery_id: Option<&str>, event_id: Option<&str>, limit: usize) -> Vec<PublishRecord> {
+        self.records
+            .iter()
+            .rev()
+            .filter(|r| delivery_id.is_none_or(|id| r.delivery_id.as_deref() == Some(id)))
+            .filter(|r| event_id.is_none_or(|id| r.event_id.as_deref() == Some(id)))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    pub fn metrics(&self) -> PublishMetrics {
+        PublishMetrics {
+            published: self.published,
+            failed: self.failed,
+            relays: self.relays.values().cloned().collect(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+}
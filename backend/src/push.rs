@@ -0,0 +1,177 @@
+// push.rs - Mobile push (FCM and UnifiedPush) for messages couriers need to see right away
+use nostr::hashes::{sha256, Hash};
+use serde::Serialize;
+use std::net::IpAddr;
+
+use crate::settings::{PushDevice, PushPlatform};
+
+pub type PushError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PushMessage {
+    pub title: String,
+    pub body: String,
+    // Opened when the notification is tapped
+    pub url: String,
+}
+
+impl PushMessage {
+    // The same message as a Nostr DM, for couriers without a reachable device
+    pub fn text(&self) -> String {
+        format!("{}: {} {}", self.title, self.body, self.url)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PushOutcome {
+    Sent,
+    // The token or endpoint is no longer valid and the device should be forgotten
+    Expired,
+}
+
+// OAuth access token for the FCM HTTP v1 API
+#[derive(Debug, Clone)]
+enum AccessToken {
+    Static(String),
+    // Re-read on every send, so a sidecar (e.g. `gcloud auth print-access-token`) can refresh it
+    File(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct FcmConfig {
+    project_id: String,
+    token: AccessToken,
+}
+
+impl FcmConfig {
+    fn access_token(&self) -> Result<String, PushError> {
+        match &self.token {
+            AccessToken::Static(token) => Ok(token.clone()),
+            AccessToken::File(path) => Ok(std::fs::read_to_string(path)
+                .map_err(|e| format!("Failed to read FCM access token from {}: {}", path, e))?
+                .trim()
+                .to_string()),
+        }
+    }
+}
+
+pub struct PushGateway {
+    client: reqwest::Client,
+    pub fcm: Option<FcmConfig>,
+}
+
+impl PushGateway {
+    // FCM needs FCM_PROJECT_ID and FCM_ACCESS_TOKEN or FCM_ACCESS_TOKEN_FILE; UnifiedPush
+    // endpoints need no configuration
+    pub fn from_env() -> Result<Self, String> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let token = match (var("FCM_ACCESS_TOKEN"), var("FCM_ACCESS_TOKEN_FILE")) {
+            (Some(token), _) => Some(AccessToken::Static(token)),
+            (None, Some(path)) => Some(AccessToken::File(path)),
+            (None, None) => None,
+        };
+        let fcm = match (var("FCM_PROJECT_ID"), token) {
+            (Some(project_id), Some(token)) => Some(FcmConfig { project_id, token }),
+            (None, None) => None,
+            _ => return Err("FCM_PROJECT_ID and FCM_ACCESS_TOKEN (or FCM_ACCESS_TOKEN_FILE) must be set together".to_string()),
+        };
+
+        let client = reqwest::Client::builder()
+            .user_agent(concat!("nostr-delivery-backend/", env!("CARGO_PKG_VERSION")))
+            .timeout(std::time::Duration::from_secs(10))
+            .build()
+            .unwrap_or_default();
+        Ok(Self { client, fcm })
+    }
+
+    pub fn supports(&self, platform: PushPlatform) -> bool {
+        match platform {
+            PushPlatform::Fcm => self.fcm.is_some(),
+            PushPlatform::UnifiedPush => true,
+        }
+    }
+
+    pub async fn send(&self, device: &PushDevice, message: &PushMessage) -> Result<PushOutcome, PushError> {
+        let response = match device.platform {
+            PushPlatform::Fcm => {
+                let fcm = self.fcm.as_ref().ok_or("FCM is not configured")?;
+                let body = serde_json::json!({
+                    "message": {
+                        "token": device.token,
+                        "notification": { "title": message.title, "body": message.body },
+                        "data": { "url": message.url },
+                        "android": { "priority": "high" },
+                        "apns": { "headers": { "apns-priority": "10" } }
+                    }
+                });
+                self.client
+                    .post(format!("https://fcm.googleapis.com/v1/projects/{}/messages:send", fcm.project_id))
+                    .bearer_auth(fcm.access_token()?)
+                    .json(&body)
+                    .send()
+                    .await?
+            }
+            // The distributor forwards the raw body to the app
+            PushPlatform::UnifiedPush => {
+                self.client
+                    .post(&device.token)
+                    .header("Urgency", "high")
+                    .header("TTL", "300")
+                    .json(message)
+                    .send()
+                    .await?
+            }
+        };
+
+        // FCM answers 404 for unregistered tokens; UnifiedPush endpoints 404 or 410 once removed
+        match response.status().as_u16() {
+            404 | 410 => Ok(PushOutcome::Expired),
+            _ => {
+                response.error_for_status()?;
+                Ok(PushOutcome::Sent)
+            }
+        }
+    }
+}
+
+pub fn device_id(token: &str) -> String {
+    sha256::Hash::hash(token.trim().as_bytes()).to_string()[..16].to_string()
+}
+
+// FCM tokens are opaque; UnifiedPush tokens are the endpoint the distributor gave the app
+pub fn validate_token(platform: PushPlatform, token: &str) -> Result<(), String> {
+    let token = token.trim();
+    if token.is_empty() || token.len() > 4096 {
+        return Err("Push token is empty or too long".to_string());
+    }
+    match platform {
+        PushPlatform::Fcm if token.chars().any(|c| c.is_whitespace() || c.is_control()) => Err("Invalid FCM token".to_string()),
+        PushPlatform::UnifiedPush => match reqwest::Url::parse(token) {
+            Ok(url) if url.scheme() == "https" && !is_internal_host(&url) => Ok(()),
+            Ok(url) if url.scheme() == "https" => Err("UnifiedPush endpoints must be on a public host".to_string()),
+            _ => Err("UnifiedPush tokens must be the https endpoint URL".to_string()),
+        },
+        _ => Ok(()),
+    }
+}
+
+// Endpoints on this machine or a private network, which the server must not be made to call
+fn is_internal_host(url: &reqwest::Url) -> bool {
+    let Some(host) = url.host_str() else {
+        return true;
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']').trim_end_matches('.').to_lowercase();
+    if host == "localhost" || [".localhost", ".local", ".internal"].iter().any(|suffix| host.ends_with(suffix)) {
+        return true;
+    }
+    let ip = match host.parse::<IpAddr>() {
+        Ok(IpAddr::V6(v6)) => v6.to_ipv4_mapped().map_or(IpAddr::V6(v6), IpAddr::V4),
+        Ok(ip) => ip,
+        Err(_) => return false,
+    };
+    match ip {
+        IpAddr::V4(v4) => v4.is_loopback() || v4.is_private() || v4.is_link_local() || v4.is_unspecified() || v4.is_broadcast(),
+        // Loopback, unspecified, unique local (fc00::/7) and link-local (fe80::/10)
+        IpAddr::V6(v6) => v6.is_loopback() || v6.is_unspecified() || (v6.segments()[0] & 0xfe00) == 0xfc00 || (v6.segments()[0] & 0xffc0) == 0xfe80,
+    }
+}
@@ -0,0 +1,47 @@
+// qr.rs - QR code rendering
+use qrcode::render::svg;
+use qrcode::QrCode;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QrFormat {
+    Png,
+    Svg,
+}
+
+impl QrFormat {
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw {
+            "png" => Some(QrFormat::Png),
+            "svg" => Some(QrFormat::Svg),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            QrFormat::Png => "image/png",
+            QrFormat::Svg => "image/svg+xml",
+        }
+    }
+}
+
+// Render `data` as a QR code image in the requested format
+pub fn render(data: &str, format: QrFormat) -> Result<Vec<u8>, String> {
+    let code = QrCode::new(data.as_bytes()).map_err(|e| e.to_string())?;
+
+    match format {
+        QrFormat::Svg => Ok(code
+            .render::<svg::Color>()
+            .min_dimensions(256, 256)
+            .build()
+            .into_bytes()),
+        QrFormat::Png => {
+            let image = code.render::<image::Luma<u8>>().min_dimensions(256, 256).build();
+            let mut png = std::io::Cursor::new(Vec::new());
+            image
+                .write_to(&mut png, image::ImageFormat::Png)
+                .map_err(|e| e.to_string())?;
+            Ok(png.into_inner())
+        }
+    }
+}
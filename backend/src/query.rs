@@ -0,0 +1,161 @@
+// query.rs - Reconciliation shared by every relay read path. Relays may return several
+// versions of a replaceable event (or copies from other authors), so reads keep only the
+// newest event per kind and `d` tag from an authorized author.
+use nostr::{Event, EventId, Kind, PublicKey};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+
+use crate::{delegation, PubkeyId};
+
+// Value of the first tag with this name
+pub fn tag_value<'a>(event: &'a Event, name: &str) -> Option<&'a str> {
+    event.tags.iter().find_map(|tag| {
+        let tag = tag.as_slice();
+        (tag.len() >= 2 && tag[0] == name).then(|| tag[1].as_str())
+    })
+}
+
+pub fn has_tag(event: &Event, name: &str, value: &str) -> bool {
+    event.tags.iter().any(|tag| {
+        let tag = tag.as_slice();
+        tag.len() >= 2 && tag[0] == name && tag[1] == value
+    })
+}
+
+// The `d` tag, empty when absent (as relays treat it)
+pub fn identifier(event: &Event) -> &str {
+    tag_value(event, "d").unwrap_or("")
+}
+
+// Delivery an event belongs to: delivery events are keyed by the delivery ID, the events
+// around them carry a delivery_id tag
+pub fn delivery_id(event: &Event) -> Option<&str> {
+    match event.kind.as_u16() {
+        35000 => Some(identifier(event)),
+        _ => tag_value(event, "delivery_id"),
+    }
+}
+
+// Whether an event may be attributed to `npub`. Events carrying a NIP-26 delegation speak
+// only for their delegator; otherwise the marketplace's own record of who acted stands.
+pub fn attributed_to(event: &Event, npub: &str) -> bool {
+    match delegation::delegator(event) {
+        Some(delegator) => PubkeyId::parse(npub).is_ok_and(|pk| *pk == delegator),
+        None => true,
+    }
+}
+
+// Authors whose events the marketplace trusts: its own key plus any configured extras
+#[derive(Debug, Clone)]
+pub struct Authors {
+    keys: HashSet<PublicKey>,
+    // Keys trusted only until a Unix timestamp, e.g. a rotated-out system key
+    expiring: HashMap<PublicKey, i64>,
+    // Organization signing keys by the organization they sign for, added as they are issued
+    organizations: Arc<RwLock<HashMap<PublicKey, String>>>,
+}
+
+impl Authors {
+    pub fn new(system_pubkey: PublicKey, extra: impl IntoIterator<Item = PublicKey>) -> Self {
+        let mut keys: HashSet<PublicKey> = extra.into_iter().collect();
+        keys.insert(system_pubkey);
+        Self { keys, expiring: HashMap::new(), organizations: Arc::default() }
+    }
+
+    pub fn trust_organization_key(&self, pubkey: PublicKey, org_id: &str) {
+        self.organizations.write().unwrap_or_else(|e| e.into_inner()).insert(pubkey, org_id.to_string());
+    }
+
+    // Organization a key signs for, if it is an organization key
+    pub fn organization_of(&self, pubkey: &PublicKey) -> Option<String> {
+        self.organizations.read().unwrap_or_else(|e| e.into_inner()).get(pubkey).cloned()
+    }
+
+    // Whether an event's signer is trusted for it: organization keys only for events tagged
+    // with their own organization
+    pub fn trusts(&self, event: &Event) -> bool {
+        match self.organization_of(&event.pubkey) {
+            Some(org_id) => tag_value(event, "organization") == Some(org_id.as_str()),
+            None => self.contains(&event.pubkey),
+        }
+    }
+
+    pub fn trust_until(&mut self, pubkey: PublicKey, until: i64) {
+        if !self.keys.contains(&pubkey) {
+            self.expiring.insert(pubkey, until);
+        }
+    }
+
+    // Extra authors from TRUSTED_PUBLISHERS (comma-separated npub or hex), e.g. earlier
+    // marketplace keys whose events should still be read
+    pub fn from_env(system_pubkey: PublicKey) -> Result<Self, String> {
+        let mut extra = Vec::new();
+        for key in std::env::var("TRUSTED_PUBLISHERS").unwrap_or_default().split(',') {
+            let key = key.trim();
+            if key.is_empty() {
+                continue;
+            }
+            extra.push(PublicKey::parse(key).map_err(|e| format!("Invalid TRUSTED_PUBLISHERS key {}: {}", key, e))?);
+        }
+        Ok(Self::new(system_pubkey, extra))
+    }
+
+    pub fn contains(&self, pubkey: &PublicKey) -> bool {
+        self.keys.contains(pubkey)
+            || self.expiring.get(pubkey).is_some_and(|until| *until > chrono::Utc::now().timestamp())
+            || self.organizations.read().unwrap_or_else(|e| e.into_inner()).contains_key(pubkey)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = PublicKey> {
+        let now = chrono::Utc::now().timestamp();
+        let mut keys: Vec<PublicKey> = self.keys.iter().copied().collect();
+        keys.extend(self.expiring.iter().filter(|(_, until)| **until > now).map(|(key, _)| *key));
+        keys.extend(self.organizations.read().unwrap_or_else(|e| e.into_inner()).keys().copied());
+        keys.into_iter()
+    }
+}
+
+// Newest authorized event per kind and `d` tag. Ties on created_at go to the lowest event
+// ID (NIP-01), so every replica reconciles the same way. Oldest first.
+pub fn latest_per_identifier(events: impl IntoIterator<Item = Event>, authors: &Authors) -> Vec<Event> {
+    let mut latest: HashMap<(Kind, String), Event> = HashMap::new();
+
+    for event in events {
+        if !authors.trusts(&event) {
+            continue;
+        }
+        let key = (event.kind, identifier(&event).to_string());
+        let newer = latest.get(&key).is_none_or(|current| supersedes(&event, current));
+        if newer {
+            latest.insert(key, event);
+        }
+    }
+
+    let mut events: Vec<Event> = latest.into_values().collect();
+    events.sort_by_key(|e| (e.created_at, e.id));
+    events
+}
+
+// The newest event per author and `d` tag, oldest first, for events signed by users themselves
+// rather than by a trusted key
+pub fn latest_per_author(events: impl IntoIterator<Item = Event>) -> Vec<Event> {
+    let mut latest: HashMap<(PublicKey, String), Event> = HashMap::new();
+    for event in events {
+        let key = (event.pubkey, identifier(&event).to_string());
+        if latest.get(&key).is_none_or(|current| supersedes(&event, current)) {
+            latest.insert(key, event);
+        }
+    }
+    let mut events: Vec<Event> = latest.into_values().collect();
+    events.sort_by_key(|e| (e.created_at, e.id));
+    events
+}
+
+pub fn supersedes(candidate: &Event, current: &Event) -> bool {
+    candidate.created_at > current.created_at
+        || (candidate.created_at == current.created_at && lower_id(&candidate.id, &current.id))
+}
+
+fn lower_id(a: &EventId, b: &EventId) -> bool {
+    a.as_bytes() < b.as_bytes()
+}
@@ -0,0 +1,74 @@
+// quotes.rs - Delivery quotes: distance, travel time and a suggested price for a create request,
+// with a signed token that creates the delivery exactly as quoted
+use nostr::base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use nostr::base64::Engine;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{codes, Amount};
+
+pub const QUOTE_TTL_SECS: i64 = 900;
+// Price per km while there are no accepted deliveries to learn from
+pub const DEFAULT_SATS_PER_KM: f64 = 200.0;
+// Floor for short trips
+pub const MIN_SUGGESTED_SATS: u64 = 1000;
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Quote {
+    // Pass as `quote_token` to POST /api/v1/deliveries before `expires_at`
+    pub quote_token: String,
+    pub expires_at: i64,
+    pub distance_meters: Option<f64>,
+    // Pickup to dropoff at a typical courier speed
+    pub eta_secs: Option<i64>,
+    // Distance times the median accepted price per km over the last 30 days
+    pub suggested_amount: Amount,
+    // What the delivery will offer: the request's `offer_amount`, or the suggestion when that was 0
+    pub offer_amount: Amount,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuoteError {
+    Invalid,
+    Expired,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Sealed<T> {
+    expires_at: i64,
+    terms: T,
+}
+
+pub fn suggested_amount(distance_meters: Option<f64>, sats_per_km: Option<f64>) -> Amount {
+    let km = distance_meters.unwrap_or(0.0) / 1000.0;
+    let rate = sats_per_km.filter(|rate| rate.is_finite() && *rate > 0.0).unwrap_or(DEFAULT_SATS_PER_KM);
+    let sats = ((km * rate).round() as u64).clamp(MIN_SUGGESTED_SATS, Amount::MAX_SATS);
+    Amount::from_sats(sats).unwrap_or_default()
+}
+
+pub fn eta_secs(distance_meters: Option<f64>) -> Option<i64> {
+    distance_meters.map(|meters| (meters / crate::notify::DEFAULT_SPEED_MPS).round() as i64)
+}
+
+// "<payload>.<mac>": the terms in the clear (base64url JSON) and an HMAC over them, so the token
+// carries everything needed to create the delivery and can't be altered
+pub fn seal<T: Serialize>(secret: &[u8], terms: &T, expires_at: i64) -> Result<String, serde_json::Error> {
+    let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&Sealed { expires_at, terms })?);
+    let mac = codes::derive_token(secret, "quote", &payload);
+    Ok(format!("{}.{}", payload, mac))
+}
+
+pub fn open<T: DeserializeOwned>(secret: &[u8], token: &str, now: i64) -> Result<T, QuoteError> {
+    let (payload, mac) = token.trim().split_once('.').ok_or(QuoteError::Invalid)?;
+    if !codes::codes_match(&codes::derive_token(secret, "quote", payload), mac) {
+        return Err(QuoteError::Invalid);
+    }
+
+    let bytes = URL_SAFE_NO_PAD.decode(payload).map_err(|_| QuoteError::Invalid)?;
+    let sealed: Sealed<T> = serde_json::from_slice(&bytes).map_err(|_| QuoteError::Invalid)?;
+    if sealed.expires_at < now {
+        return Err(QuoteError::Expired);
+    }
+    Ok(sealed.terms)
+}
@@ -0,0 +1,61 @@
+// rbac.rs - Which organization roles may perform which actions. Each organization can override
+// the defaults per permission; owners are always allowed everything.
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use utoipa::ToSchema;
+
+use crate::organizations::OrgRole;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    // Create deliveries attributed to the organization
+    CreateDeliveries,
+    AssignCouriers,
+    // Settle disputes on the organization's deliveries
+    ResolveDisputes,
+    // Add, remove and change the role of non-owner members
+    ManageMembers,
+}
+
+impl Permission {
+    pub const ALL: [Permission; 4] = [
+        Permission::CreateDeliveries,
+        Permission::AssignCouriers,
+        Permission::ResolveDisputes,
+        Permission::ManageMembers,
+    ];
+
+    pub fn default_roles(self) -> &'static [OrgRole] {
+        match self {
+            Permission::CreateDeliveries => &[OrgRole::Admin, OrgRole::Dispatcher, OrgRole::Sender],
+            Permission::AssignCouriers => &[OrgRole::Admin, OrgRole::Dispatcher],
+            Permission::ResolveDisputes => &[OrgRole::Admin, OrgRole::Arbitrator],
+            Permission::ManageMembers => &[OrgRole::Admin],
+        }
+    }
+}
+
+// Roles granted each permission; permissions not listed keep their default roles
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(transparent)]
+pub struct RolePolicy(pub BTreeMap<Permission, Vec<OrgRole>>);
+
+impl RolePolicy {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn roles(&self, permission: Permission) -> &[OrgRole] {
+        self.0.get(&permission).map(Vec::as_slice).unwrap_or(permission.default_roles())
+    }
+
+    pub fn allows(&self, role: OrgRole, permission: Permission) -> bool {
+        role == OrgRole::Owner || self.roles(permission).contains(&role)
+    }
+
+    // Every permission with the roles it is granted to, defaults filled in
+    pub fn effective(&self) -> BTreeMap<Permission, Vec<OrgRole>> {
+        Permission::ALL.iter().map(|p| (*p, self.roles(*p).to_vec())).collect()
+    }
+}
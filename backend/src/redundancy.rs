@@ -0,0 +1,128 @@
+// redundancy.rs - Checks that the latest state events of active deliveries are held by enough
+// relays, so losing one relay's data doesn't lose marketplace state
+use nostr::{Event, EventId, Kind};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use utoipa::ToSchema;
+
+use crate::query::delivery_id;
+use crate::{organizations, DeliveryRequest, DeliveryStatus};
+
+pub const DEFAULT_MIN_RELAYS: usize = 2;
+pub const DEFAULT_INTERVAL_SECS: u64 = 900;
+
+// Delivery, bid, status and assignment events. Pings, revisions and tombstones are history
+// rather than state.
+pub fn state_kinds() -> Vec<Kind> {
+    (35000..=35006)
+        .chain([organizations::ASSIGNMENT_KIND])
+        .map(Kind::Custom)
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct RedundancyConfig {
+    // Copies wanted of each event, capped at the number of configured relays
+    pub min_relays: usize,
+    // 0 disables the background check
+    pub interval_secs: u64,
+}
+
+impl RedundancyConfig {
+    // REDUNDANCY_MIN_RELAYS (default 2) and REDUNDANCY_INTERVAL_SECS (default 900)
+    pub fn from_env() -> Self {
+        Self {
+            min_relays: std::env::var("REDUNDANCY_MIN_RELAYS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_MIN_RELAYS),
+            interval_secs: std::env::var("REDUNDANCY_INTERVAL_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_INTERVAL_SECS),
+        }
+    }
+}
+
+// Deliveries whose events still change, or that are still being settled
+pub fn is_active(delivery: &DeliveryRequest) -> bool {
+    matches!(
+        delivery.status,
+        DeliveryStatus::Draft | DeliveryStatus::Open | DeliveryStatus::Accepted | DeliveryStatus::InTransit | DeliveryStatus::Completed | DeliveryStatus::Disputed
+    )
+}
+
+pub fn concerns(event: &Event, delivery_ids: &HashSet<String>) -> bool {
+    delivery_id(event).is_some_and(|id| delivery_ids.contains(id))
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct RelayCoverage {
+    pub url: String,
+    // Latest state events the relay holds, out of `expected`
+    pub held: usize,
+    pub expected: usize,
+    pub coverage: f64,
+    // Set when the relay couldn't be queried; it then counts as holding nothing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct RedundancyReport {
+    pub checked_at: i64,
+    pub min_relays: usize,
+    pub active_deliveries: usize,
+    pub events_checked: usize,
+    // Events found on fewer than min_relays relays
+    pub under_replicated: usize,
+    pub republished: usize,
+    pub republish_failures: usize,
+    pub relays: Vec<RelayCoverage>,
+}
+
+// An event missing from some relays, and the relays to send it to
+#[derive(Debug, Clone)]
+pub struct Repair {
+    pub event: Event,
+    pub missing_from: Vec<String>,
+}
+
+// Count each relay's copies of the latest events and list the events held by fewer than
+// `min_relays` of them. `held` maps relay URL to the IDs it returned.
+pub fn assess(
+    latest: &[Event],
+    held: &HashMap<String, HashSet<EventId>>,
+    min_relays: usize,
+) -> (Vec<RelayCoverage>, Vec<Repair>) {
+    let mut urls: Vec<&String> = held.keys().collect();
+    urls.sort();
+    let min_relays = min_relays.min(urls.len());
+
+    let coverage = urls
+        .iter()
+        .map(|url| {
+            let count = latest.iter().filter(|e| held[*url].contains(&e.id)).count();
+            RelayCoverage {
+                url: url.to_string(),
+                held: count,
+                expected: latest.len(),
+                coverage: if latest.is_empty() { 1.0 } else { count as f64 / latest.len() as f64 },
+                error: None,
+            }
+        })
+        .collect();
+
+    let repairs = latest
+        .iter()
+        .filter_map(|event| {
+            let (present, missing): (Vec<&String>, Vec<&String>) = urls.iter().partition(|url| held[**url].contains(&event.id));
+            (present.len() < min_relays).then(|| Repair {
+                event: event.clone(),
+                missing_from: missing.into_iter().cloned().collect(),
+            })
+        })
+        .collect();
+
+    (coverage, repairs)
+}
@@ -0,0 +1,103 @@
+// relist.rs - Failed deliveries (cancelled after acceptance, or disputes resolved against the
+// courier) re-listed as a fresh attempt, linked to the attempts before and after it
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{Amount, DeliveryRequest, DeliveryStatus};
+
+// RELIST_BUMP_PERCENT: default increase of the offer on each re-list (default 0)
+pub fn bump_percent_from_env() -> f64 {
+    std::env::var("RELIST_BUMP_PERCENT")
+        .ok()
+        .and_then(|v| v.parse::<f64>().ok())
+        .filter(|percent| percent.is_finite() && *percent >= 0.0)
+        .unwrap_or(0.0)
+}
+
+// Rounded up, so a bump never leaves the offer unchanged
+pub fn bumped(amount: Amount, percent: f64) -> Amount {
+    let sats = (amount.sats() as f64 * (1.0 + percent / 100.0)).ceil() as u64;
+    Amount::from_sats(sats).unwrap_or(amount)
+}
+
+// Expired after a courier had taken it on, and not re-listed yet
+pub fn is_relistable(delivery: &DeliveryRequest) -> bool {
+    delivery.status == DeliveryStatus::Expired && delivery.accepted_bid.is_some() && delivery.relisted_as.is_none()
+}
+
+// The next attempt: same stops, packages and terms, open again with no bids
+pub fn next_attempt(failed: &DeliveryRequest, id: String, now: i64, bump_percent: f64) -> DeliveryRequest {
+    DeliveryRequest {
+        id,
+        offer_amount: bumped(failed.offer_amount, bump_percent),
+        expires_at: Some(now + 604800),
+        status: DeliveryStatus::Open,
+        bids: vec![],
+        accepted_bid: None,
+        accepted_at: None,
+        listed_amount: None,
+        created_at: now,
+        proof_of_delivery: None,
+        sender_feedback: None,
+        sender_rating: None,
+        sender_ratings: None,
+        courier_ratings: None,
+        recipient_confirmation: None,
+        completed_at: None,
+        picked_up_at: None,
+        disputed_at: None,
+        assignment: None,
+        deleted_at: None,
+        deleted_from: None,
+        failed_attempts: vec![],
+        returning: false,
+        no_shows: vec![],
+        fraud_flags: vec![],
+        contact_reveal: None,
+        instructions_reveal: None,
+        relisted_from: Some(failed.id.clone()),
+        relisted_as: None,
+        local_times: None,
+        ..failed.clone()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Attempt {
+    pub delivery_id: String,
+    pub status: DeliveryStatus,
+    pub offer_amount: Amount,
+    // Courier of the accepted bid, if the attempt got that far
+    pub courier: Option<String>,
+    pub created_at: i64,
+}
+
+// Every attempt in the chain `delivery_id` belongs to, first to latest
+pub fn attempts(delivery_id: &str, deliveries: &[DeliveryRequest]) -> Vec<Attempt> {
+    let find = |id: &str| deliveries.iter().find(|d| d.id == id);
+    let Some(mut first) = find(delivery_id) else {
+        return Vec::new();
+    };
+    // Bounded by the number of deliveries in case of a malformed loop
+    for _ in 0..deliveries.len() {
+        match first.relisted_from.as_deref().and_then(find) {
+            Some(previous) => first = previous,
+            None => break,
+        }
+    }
+
+    let mut chain = Vec::new();
+    let mut current = Some(first);
+    while let Some(delivery) = current.filter(|_| chain.len() < deliveries.len()) {
+        chain.push(Attempt {
+            delivery_id: delivery.id.clone(),
+            status: delivery.status.clone(),
+            offer_amount: delivery.offer_amount,
+            courier: delivery.accepted_courier().map(String::from),
+            created_at: delivery.created_at,
+        });
+        // Each attempt records `relisted_from` when it's created; the failed one's `relisted_as` may lag in the index
+        current = deliveries.iter().find(|d| d.relisted_from.as_deref() == Some(delivery.id.as_str()));
+    }
+    chain
+}
@@ -0,0 +1,33 @@
+// request_id.rs - X-Request-Id correlation between HTTP requests, logs, errors and events
+use std::future::Future;
+
+pub const HEADER: &str = "x-request-id";
+const MAX_LEN: usize = 128;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+// ID of the request being served by the current task, if any
+pub fn current() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+// Run a request handler with its ID available to everything it calls
+pub async fn scope<F: Future>(id: String, f: F) -> F::Output {
+    REQUEST_ID.scope(id, f).await
+}
+
+// Keep a client-supplied ID if it is short and plain enough to echo into logs and tags
+pub fn accept(incoming: &str) -> Option<String> {
+    let incoming = incoming.trim();
+    let valid = !incoming.is_empty()
+        && incoming.len() <= MAX_LEN
+        && incoming.chars().all(|c| c.is_ascii_alphanumeric() || "-_.:".contains(c));
+    valid.then(|| incoming.to_string())
+}
+
+pub fn generate() -> String {
+    let bytes: [u8; 16] = nostr::secp256k1::rand::random();
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
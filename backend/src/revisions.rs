@@ -0,0 +1,79 @@
+// revisions.rs - Delivery edit history with field-level diffs
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::DeliveryRequest;
+
+pub const REVISION_KIND: u16 = 35011;
+
+// Fields senders can edit on an open delivery
+pub const EDITABLE_FIELDS: &[&str] = &[
+    "pickup",
+    "dropoff",
+    "packages",
+    "offer_amount",
+    "insurance_amount",
+    "time_window",
+    "min_courier_reputation",
+    "min_completed_deliveries",
+    "allowed_couriers",
+];
+
+// Changes to these invalidate the terms couriers bid on
+pub const MATERIAL_FIELDS: &[&str] = &["offer_amount", "packages", "dropoff"];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct FieldChange {
+    pub field: String,
+    pub before: serde_json::Value,
+    pub after: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DeliveryRevision {
+    pub delivery_id: String,
+    // 1 for the first edit after creation
+    pub revision: u32,
+    pub created_at: i64,
+    pub changes: Vec<FieldChange>,
+}
+
+impl DeliveryRevision {
+    pub fn is_material(&self) -> bool {
+        self.changes.iter().any(|c| MATERIAL_FIELDS.contains(&c.field.as_str()))
+    }
+
+    pub fn identifier(&self) -> String {
+        format!("{}:{}", self.delivery_id, self.revision)
+    }
+}
+
+// Editable fields that differ between two versions of a delivery
+// Stop contacts and instructions are sealed and never recorded in revisions
+fn without_sealed(mut delivery: serde_json::Value) -> serde_json::Value {
+    for stop in ["pickup", "dropoff"] {
+        if let Some(location) = delivery.get_mut(stop).and_then(serde_json::Value::as_object_mut) {
+            location.remove("contact");
+            location.remove("instructions");
+        }
+    }
+    delivery
+}
+
+pub fn diff(before: &DeliveryRequest, after: &DeliveryRequest) -> Vec<FieldChange> {
+    let before = without_sealed(serde_json::to_value(before).unwrap_or_default());
+    let after = without_sealed(serde_json::to_value(after).unwrap_or_default());
+
+    EDITABLE_FIELDS
+        .iter()
+        .filter_map(|field| {
+            let old = before.get(*field).cloned().unwrap_or(serde_json::Value::Null);
+            let new = after.get(*field).cloned().unwrap_or(serde_json::Value::Null);
+            (old != new).then(|| FieldChange {
+                field: field.to_string(),
+                before: old,
+                after: new,
+            })
+        })
+        .collect()
+}
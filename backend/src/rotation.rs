@@ -0,0 +1,100 @@
+// rotation.rs - System key rotation: an announcement signed by the old key naming its successor,
+// re-signing of live state under the new key, and an encrypted (NIP-49) backup of both keys
+use nostr::nips::nip44;
+use nostr::{Event, EventBuilder, Keys, Kind, PublicKey, Tag, TagKind, Timestamp, ToBech32};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use crate::query::{self, identifier, tag_value};
+use crate::{courier_lists, redundancy};
+
+// Replaceable per old key (`d` is its hex pubkey)
+pub const ROTATION_KIND: u16 = 35014;
+pub const DEFAULT_GRACE_SECS: i64 = 30 * 86400;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rotation {
+    pub old: PublicKey,
+    pub new: PublicKey,
+    pub rotated_at: i64,
+    // Events signed by the old key are trusted until then
+    pub grace_until: i64,
+}
+
+impl Rotation {
+    pub fn builder(&self) -> EventBuilder {
+        let tags = vec![
+            Tag::identifier(self.old.to_hex()),
+            Tag::public_key(self.new),
+            Tag::custom(TagKind::Custom("rotated_at".into()), vec![self.rotated_at.to_string()]),
+            Tag::custom(TagKind::Custom("grace_until".into()), vec![self.grace_until.to_string()]),
+        ];
+        EventBuilder::new(Kind::Custom(ROTATION_KIND), "System key rotated", tags)
+    }
+
+    // Only announcements signed by the key being rotated away count
+    pub fn from_event(event: &Event) -> Option<Self> {
+        if event.kind.as_u16() != ROTATION_KIND || identifier(event) != event.pubkey.to_hex() || event.verify().is_err() {
+            return None;
+        }
+        Some(Self {
+            old: event.pubkey,
+            new: PublicKey::parse(tag_value(event, "p")?).ok()?,
+            rotated_at: tag_value(event, "rotated_at")?.parse().ok()?,
+            grace_until: tag_value(event, "grace_until")?.parse().ok()?,
+        })
+    }
+}
+
+// Content NIP-44-encrypted to the system key: private deliveries and revisions, private
+// courier lists and user settings
+pub fn is_encrypted_to_self(event: &Event) -> bool {
+    let kind = event.kind.as_u16();
+    tag_value(event, "encrypted").is_some()
+        || (kind == courier_lists::FOLLOW_SET_KIND && !event.content.is_empty())
+        || event.kind == Kind::ApplicationSpecificData
+}
+
+// State to carry over to the new key: every encrypted event (only the new key will be able to
+// read it), the state events of active deliveries, and profiles, organizations and lists
+pub fn needs_resign(event: &Event, active_deliveries: &HashSet<String>) -> bool {
+    if is_encrypted_to_self(event) {
+        return true;
+    }
+    match query::delivery_id(event) {
+        Some(id) => active_deliveries.contains(id) && redundancy::state_kinds().contains(&event.kind),
+        None => true,
+    }
+}
+
+// The same event signed by `new_keys` at `created_at`, re-encrypted to the new key if needed
+pub fn resign(event: &Event, old_keys: &Keys, new_keys: &Keys, created_at: Timestamp) -> Result<Event, Box<dyn std::error::Error>> {
+    let content = if is_encrypted_to_self(event) {
+        let plaintext = nip44::decrypt(old_keys.secret_key(), &old_keys.public_key(), &event.content)?;
+        nip44::encrypt(new_keys.secret_key(), &new_keys.public_key(), plaintext, nip44::Version::V2)?
+    } else {
+        event.content.clone()
+    };
+
+    Ok(EventBuilder::new(event.kind, content, event.tags.iter().cloned())
+        .custom_created_at(created_at)
+        .sign_with_keys(new_keys)?)
+}
+
+// Both keys encrypted with the backup password (ncryptsec, NIP-49)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyBackup {
+    pub old: String,
+    pub new: String,
+    pub rotated_at: i64,
+}
+
+impl KeyBackup {
+    pub fn new(old_keys: &Keys, new_keys: &Keys, password: &str, rotated_at: i64) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self {
+            old: old_keys.secret_key().encrypt(password)?.to_bech32()?,
+            new: new_keys.secret_key().encrypt(password)?.to_bech32()?,
+            rotated_at,
+        })
+    }
+}
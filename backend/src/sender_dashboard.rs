@@ -0,0 +1,109 @@
+// sender_dashboard.rs - Everything the web dashboard shows a sender, computed in one pass over
+// their deliveries: what's under way, bids to act on, completions to confirm and this month's spend
+use chrono::{DateTime, Datelike, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::analytics::SpendingReport;
+use crate::{Amount, BidOrder, DeliveryBid, DeliveryRequest, DeliveryStatus};
+
+// Statuses listed as active, in lifecycle order
+pub const ACTIVE_STATUSES: [DeliveryStatus; 4] = [
+    DeliveryStatus::Open,
+    DeliveryStatus::Accepted,
+    DeliveryStatus::InTransit,
+    DeliveryStatus::Disputed,
+];
+
+// How many completions awaiting confirmation are listed, newest first
+pub const RECENT_COMPLETIONS: usize = 20;
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct StatusGroup {
+    pub status: DeliveryStatus,
+    pub deliveries: Vec<DeliveryRequest>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PendingBid {
+    pub delivery_id: String,
+    pub bid: DeliveryBid,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct MonthToDate {
+    // Start of the current calendar month, UTC
+    pub from: i64,
+    pub deliveries_created: u32,
+    pub deliveries_settled: u32,
+    pub sats_spent: Amount,
+    // Offers on accepted deliveries not yet settled
+    pub pending_sats: Amount,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SenderDashboard {
+    pub npub: String,
+    // One group per active status, empty groups included
+    pub active: Vec<StatusGroup>,
+    // Bids on open deliveries the sender can accept, best first per delivery
+    pub pending_bids: Vec<PendingBid>,
+    // Completed deliveries the sender hasn't confirmed yet, most recent first
+    pub awaiting_confirmation: Vec<DeliveryRequest>,
+    pub month_to_date: MonthToDate,
+    pub generated_at: i64,
+}
+
+impl SenderDashboard {
+    pub fn build(npub: &str, deliveries: &[DeliveryRequest], now: i64) -> Self {
+        let own: Vec<&DeliveryRequest> = deliveries.iter().filter(|d| d.sender == npub).collect();
+
+        let active = ACTIVE_STATUSES.iter()
+            .map(|status| StatusGroup {
+                status: status.clone(),
+                deliveries: own.iter().filter(|d| d.status == *status).map(|d| (*d).clone()).collect(),
+            })
+            .collect();
+
+        // Stale bids wait on the courier to re-confirm, not on the sender
+        let pending_bids = own.iter()
+            .filter(|d| d.status == DeliveryStatus::Open && d.accepted_bid.is_none())
+            .flat_map(|d| d.ranked_bids(BidOrder::default()).into_iter().map(move |i| (d, &d.bids[i])))
+            .filter(|(_, bid)| !bid.stale)
+            .map(|(d, bid)| PendingBid { delivery_id: d.id.clone(), bid: bid.clone() })
+            .collect();
+
+        let mut awaiting_confirmation: Vec<DeliveryRequest> = own.iter()
+            .filter(|d| d.status == DeliveryStatus::Completed)
+            .map(|d| (*d).clone())
+            .collect();
+        awaiting_confirmation.sort_by_key(|d| std::cmp::Reverse(d.completed_at.unwrap_or(d.created_at)));
+        awaiting_confirmation.truncate(RECENT_COMPLETIONS);
+
+        let from = month_start(now);
+        let spending = SpendingReport::build(npub, deliveries, Some(from), Some(now));
+
+        Self {
+            npub: npub.to_string(),
+            active,
+            pending_bids,
+            awaiting_confirmation,
+            month_to_date: MonthToDate {
+                from,
+                deliveries_created: spending.deliveries_created,
+                deliveries_settled: spending.deliveries_settled,
+                sats_spent: spending.total_sats_spent,
+                pending_sats: spending.pending_sats,
+            },
+            generated_at: now,
+        }
+    }
+}
+
+// First second of the calendar month `now` falls in, UTC
+fn month_start(now: i64) -> i64 {
+    let now = DateTime::from_timestamp(now, 0).unwrap_or_default();
+    Utc.with_ymd_and_hms(now.year(), now.month(), 1, 0, 0, 0)
+        .single()
+        .map_or(0, |start| start.timestamp())
+}
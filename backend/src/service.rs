@@ -0,0 +1,566 @@
+// service.rs - The delivery lifecycle (create, bid, accept, complete, confirm, cancel) apart from
+// HTTP parsing and relay I/O. The rule functions check a transition and apply it to a delivery
+// without touching storage; DeliveryService runs them against a DeliveryStore and saves the result.
+use async_trait::async_trait;
+
+use crate::commitments::{Conflict, ConflictMode};
+use crate::fraud::FraudFlag;
+use crate::problem::{Problem, ProblemType};
+use crate::state::AppState;
+use crate::{
+    DeliveryBid, DeliveryRequest, DeliveryStatus, DeliveryUpdate, ProofOfDelivery, RatingBreakdown,
+    RatingSummary, UserProfile,
+};
+
+pub type StoreError = Box<dyn std::error::Error>;
+
+// Reads and writes the lifecycle needs, plus the checks that depend on stored state. AppState
+// implements it over the relays, postgres::PostgresStore over the relational mirror.
+#[async_trait(?Send)]
+pub trait DeliveryStore {
+    async fn profile(&self, npub: &str) -> Result<UserProfile, StoreError>;
+    // Profile with reputation recomputed from the courier's rated deliveries
+    async fn courier_profile(&self, npub: &str) -> UserProfile;
+    // Every known delivery, for rating history
+    async fn history(&self) -> Result<Vec<DeliveryRequest>, StoreError>;
+    async fn bid_rejection(&self, delivery: &DeliveryRequest, courier: &str, profile: &UserProfile, etas: (i64, i64), now: i64) -> Result<Option<Problem>, StoreError>;
+    async fn commitment_conflicts(&self, courier: &str, etas: (i64, i64)) -> Result<Vec<Conflict>, StoreError>;
+    async fn completion_flags(&self, delivery: &DeliveryRequest) -> Vec<FraudFlag>;
+    // Moderated review text, or None when it is held
+    async fn moderate_review(&self, delivery: &DeliveryRequest, text: &str) -> Option<String>;
+
+    async fn save_new_delivery(&self, delivery: &DeliveryRequest) -> Result<(), StoreError>;
+    async fn save_delivery(&self, delivery: &DeliveryRequest) -> Result<(), StoreError>;
+    async fn save_bid(&self, delivery: &DeliveryRequest, bid: &DeliveryBid) -> Result<(), StoreError>;
+    async fn save_status_update(&self, delivery: &DeliveryRequest, update: &DeliveryUpdate) -> Result<(), StoreError>;
+    async fn save_profile(&self, profile: &UserProfile) -> Result<(), StoreError>;
+}
+
+#[derive(Debug)]
+pub enum ServiceError {
+    // A business rule refused the change
+    Refused(Box<Problem>),
+    Store(StoreError),
+}
+
+impl From<String> for ServiceError {
+    fn from(e: String) -> Self {
+        ServiceError::Store(e.into())
+    }
+}
+
+impl From<StoreError> for ServiceError {
+    fn from(e: StoreError) -> Self {
+        ServiceError::Store(e)
+    }
+}
+
+impl std::fmt::Display for ServiceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ServiceError::Refused(problem) => f.write_str(&problem.detail),
+            ServiceError::Store(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ServiceError {}
+
+fn refuse(problem: Option<Problem>) -> Result<(), ServiceError> {
+    match problem {
+        Some(problem) => Err(ServiceError::Refused(Box::new(problem))),
+        None => Ok(()),
+    }
+}
+
+// Why the delivery can't be created, if it can't: a new delivery starts open, or as a draft,
+// with none of the fields the marketplace sets later
+pub fn new_delivery_error(delivery: &DeliveryRequest) -> Option<Problem> {
+    if !matches!(delivery.status, DeliveryStatus::Open | DeliveryStatus::Draft)
+        || !delivery.bids.is_empty()
+        || delivery.accepted_bid.is_some()
+        || delivery.completed_at.is_some()
+    {
+        return Some(Problem::new(ProblemType::InvalidRequest, "Only new open deliveries and drafts can be created"));
+    }
+    None
+}
+
+// Overlapping an accepted delivery makes it likely one of them gets cancelled later
+pub fn conflict_error(conflicts: &[Conflict], mode: ConflictMode) -> Option<Problem> {
+    if conflicts.is_empty() || mode != ConflictMode::Reject {
+        return None;
+    }
+    let ids: Vec<&str> = conflicts.iter().map(|c| c.delivery_id.as_str()).collect();
+    let message = format!("Bid overlaps deliveries the courier has already accepted: {}", ids.join(", "));
+    Some(Problem::new(ProblemType::Conflict, message))
+}
+
+// Copy the courier's standing onto their bid, as senders see it when choosing
+pub fn apply_profile(bid: &mut DeliveryBid, profile: &UserProfile) {
+    bid.reputation = profile.reputation;
+    bid.completed_deliveries = profile.completed_deliveries;
+    bid.recent_rating = profile.recent_rating;
+    bid.ratings = profile.ratings;
+}
+
+pub fn accept_error(delivery: &DeliveryRequest, bid_index: usize) -> Option<Problem> {
+    let Some(bid) = delivery.bids.get(bid_index) else {
+        return Some(Problem::new(ProblemType::InvalidRequest, "Invalid bid index"));
+    };
+    if !delivery.allows_courier(&bid.courier) {
+        return Some(Problem::new(ProblemType::Forbidden, "Courier is not on this delivery's allowlist"));
+    }
+    if bid.stale {
+        return Some(Problem::new(ProblemType::Conflict, "Bid was placed on earlier terms; the courier must re-confirm it"));
+    }
+    None
+}
+
+// Accept the bid at `bid_index`, which accept_error has cleared
+pub fn accept(delivery: &mut DeliveryRequest, bid_index: usize, now: i64) -> DeliveryUpdate {
+    let bid = &delivery.bids[bid_index];
+    let update = DeliveryUpdate::accepted(&bid.id, now);
+    delivery.listed_amount = Some(delivery.offer_amount);
+    delivery.offer_amount = bid.amount;
+    delivery.accepted_bid = Some(bid.id.clone());
+    delivery.accepted_at = Some(now);
+    delivery.status = DeliveryStatus::Accepted;
+    delivery.assignment = None;
+    update
+}
+
+pub fn completion_error(delivery: &DeliveryRequest) -> Option<Problem> {
+    if delivery.status != DeliveryStatus::Accepted && delivery.status != DeliveryStatus::InTransit {
+        return Some(Problem::new(ProblemType::InvalidTransition, "Can only complete accepted or in-transit deliveries"));
+    }
+    None
+}
+
+// Whether a plain status update may move the delivery to `status`. Accepting a bid, and rating
+// a confirmation, go through their own endpoints.
+pub fn status_change_error(delivery: &DeliveryRequest, status: &DeliveryStatus) -> Option<Problem> {
+    match status {
+        DeliveryStatus::InTransit if delivery.status == DeliveryStatus::Accepted => None,
+        DeliveryStatus::InTransit => Some(Problem::new(ProblemType::InvalidTransition, "Can only start transit on accepted deliveries")),
+        DeliveryStatus::Completed => completion_error(delivery),
+        DeliveryStatus::Confirmed if delivery.status == DeliveryStatus::Completed => confirmation_error(delivery, None),
+        DeliveryStatus::Confirmed => Some(Problem::new(ProblemType::InvalidTransition, "Can only confirm completed deliveries")),
+        _ => Some(Problem::new(ProblemType::InvalidTransition, "Status can only be set to in_transit, completed or confirmed")),
+    }
+}
+
+// Packages that need a signature can't be completed by the courier without one
+pub fn signature_error(delivery: &DeliveryRequest, proof: &ProofOfDelivery) -> Option<Problem> {
+    if delivery.packages.iter().any(|pkg| pkg.requires_signature) && proof.signature_name.is_none() {
+        return Some(Problem::new(ProblemType::InvalidRequest, "Signature required for this delivery"));
+    }
+    None
+}
+
+pub fn complete(delivery: &mut DeliveryRequest, proof: ProofOfDelivery, now: i64) -> DeliveryUpdate {
+    delivery.proof_of_delivery = Some(proof);
+    delivery.status = DeliveryStatus::Completed;
+    delivery.completed_at = Some(now);
+    DeliveryUpdate::completed(delivery.proof_of_delivery.clone(), now, now)
+}
+
+// The ratings a confirmation records: `ratings`, or the single score on every dimension
+pub fn confirmation_ratings(rating: Option<f32>, ratings: Option<RatingBreakdown>) -> Option<RatingBreakdown> {
+    ratings.or(rating.map(RatingBreakdown::uniform))
+}
+
+pub fn confirmation_error(delivery: &DeliveryRequest, ratings: Option<RatingBreakdown>) -> Option<Problem> {
+    if !delivery.fraud_flags.is_empty() {
+        return Some(Problem::new(ProblemType::Conflict, "Delivery is held for fraud review").with("fraud_flags", &delivery.fraud_flags));
+    }
+    if let Some(Err(reason)) = ratings.map(|r| r.validate()) {
+        return Some(Problem::new(ProblemType::InvalidRequest, reason));
+    }
+    None
+}
+
+pub fn confirm(delivery: &mut DeliveryRequest, ratings: Option<RatingBreakdown>, feedback: Option<String>, now: i64) -> DeliveryUpdate {
+    let rating = ratings.map(|r| r.overall());
+    delivery.status = DeliveryStatus::Confirmed;
+    delivery.sender_feedback = feedback.clone();
+    delivery.sender_rating = rating;
+    delivery.sender_ratings = ratings;
+    DeliveryUpdate::confirmed(now).rating(rating, ratings).feedback(feedback)
+}
+
+// Credit the courier of a confirmed delivery with the payment and the sender's rating, weighed
+// against their earlier ratings in `history`
+pub fn credit_confirmed(courier: &mut UserProfile, delivery: &DeliveryRequest, history: &[DeliveryRequest], now: i64) -> Result<(), String> {
+    if let (Some(rating), Some(npub)) = (delivery.sender_rating, delivery.accepted_courier()) {
+        let earlier: Vec<DeliveryRequest> = history.iter().filter(|d| d.id != delivery.id).cloned().collect();
+        let mut ratings = RatingSummary::courier_ratings(npub, &earlier);
+        ratings.push((rating, now));
+        courier.apply_rating_summary(&RatingSummary::from_ratings(&ratings, now));
+    }
+    if let Some(ratings) = delivery.sender_ratings {
+        courier.ratings = Some(RatingBreakdown::accumulate(courier.ratings, courier.rating_count, ratings));
+        courier.rating_count += 1;
+    }
+    courier.completed_deliveries += 1;
+    courier.credit_earnings(delivery.offer_amount)
+}
+
+pub fn cancel_error(delivery: &DeliveryRequest) -> Option<Problem> {
+    if delivery.status != DeliveryStatus::Accepted && delivery.status != DeliveryStatus::InTransit {
+        return Some(Problem::new(ProblemType::InvalidTransition, "Can only cancel accepted deliveries"));
+    }
+    None
+}
+
+pub struct DeliveryService<'a> {
+    store: Box<dyn DeliveryStore + 'a>,
+    conflict_mode: ConflictMode,
+}
+
+impl<'a> DeliveryService<'a> {
+    pub fn new(store: impl DeliveryStore + 'a, conflict_mode: ConflictMode) -> Self {
+        Self { store: Box::new(store), conflict_mode }
+    }
+
+    pub async fn create(&self, delivery: &DeliveryRequest) -> Result<(), ServiceError> {
+        refuse(new_delivery_error(delivery))?;
+        self.store.save_new_delivery(delivery).await?;
+        Ok(())
+    }
+
+    // A courier's bid, with any accepted deliveries it overlaps (allowed unless the conflict
+    // mode rejects them)
+    pub async fn bid(
+        &self,
+        delivery: &DeliveryRequest,
+        mut bid: DeliveryBid,
+        now: i64,
+    ) -> Result<(DeliveryBid, Vec<Conflict>), ServiceError> {
+        let profile = self.store.courier_profile(&bid.courier).await;
+        let etas = (bid.pickup_eta.unwrap_or(now), bid.dropoff_eta.unwrap_or(now));
+        refuse(self.store.bid_rejection(delivery, &bid.courier, &profile, etas, now).await?)?;
+        let conflicts = self.store.commitment_conflicts(&bid.courier, etas).await?;
+        refuse(conflict_error(&conflicts, self.conflict_mode))?;
+
+        apply_profile(&mut bid, &profile);
+        self.store.save_bid(delivery, &bid).await?;
+        Ok((bid, conflicts))
+    }
+
+    pub async fn accept(&self, mut delivery: DeliveryRequest, bid_index: usize, now: i64) -> Result<DeliveryRequest, ServiceError> {
+        refuse(accept_error(&delivery, bid_index))?;
+        let update = accept(&mut delivery, bid_index, now);
+        self.store.save_delivery(&delivery).await?;
+        self.store.save_status_update(&delivery, &update).await?;
+        Ok(delivery)
+    }
+
+    // Flagged completions are held for admin review before the sender can confirm
+    pub async fn complete(&self, mut delivery: DeliveryRequest, proof: ProofOfDelivery, now: i64) -> Result<DeliveryRequest, ServiceError> {
+        refuse(completion_error(&delivery))?;
+        let update = complete(&mut delivery, proof, now);
+        delivery.fraud_flags = self.store.completion_flags(&delivery).await;
+        if !delivery.fraud_flags.is_empty() {
+            log::warn!("Delivery {} flagged for review: {:?}", delivery.id, delivery.fraud_flags);
+        }
+        self.store.save_delivery(&delivery).await?;
+        self.store.save_status_update(&delivery, &update).await?;
+        Ok(delivery)
+    }
+
+    // Returns the confirmed delivery and whether the review was held for moderation
+    pub async fn confirm(
+        &self,
+        mut delivery: DeliveryRequest,
+        rating: Option<f32>,
+        ratings: Option<RatingBreakdown>,
+        feedback: Option<&str>,
+        now: i64,
+    ) -> Result<(DeliveryRequest, bool), ServiceError> {
+        let ratings = confirmation_ratings(rating, ratings);
+        refuse(confirmation_error(&delivery, ratings))?;
+        let moderated = match feedback {
+            Some(text) => self.store.moderate_review(&delivery, text).await,
+            None => None,
+        };
+        let review_held = feedback.is_some() && moderated.is_none();
+        let update = confirm(&mut delivery, ratings, moderated, now);
+
+        if let Some(npub) = delivery.accepted_courier() {
+            let mut courier = self.store.profile(npub).await.unwrap_or_default();
+            let history = match delivery.sender_rating {
+                Some(_) => self.store.history().await.unwrap_or_default(),
+                None => Vec::new(),
+            };
+            credit_confirmed(&mut courier, &delivery, &history, now)?;
+            self.store.save_profile(&courier).await?;
+        }
+
+        self.store.save_delivery(&delivery).await?;
+        self.store.save_status_update(&delivery, &update).await?;
+        Ok((delivery, review_held))
+    }
+
+    // The courier keeps the offer; the delivery expires
+    pub async fn cancel(&self, mut delivery: DeliveryRequest) -> Result<DeliveryRequest, ServiceError> {
+        refuse(cancel_error(&delivery))?;
+        if let Some(npub) = delivery.accepted_courier() {
+            let mut courier = self.store.profile(npub).await.unwrap_or_default();
+            courier.credit_earnings(delivery.offer_amount)?;
+            self.store.save_profile(&courier).await?;
+        }
+        delivery.status = DeliveryStatus::Expired;
+        self.store.save_delivery(&delivery).await?;
+        Ok(delivery)
+    }
+}
+
+#[async_trait(?Send)]
+impl<T: DeliveryStore + ?Sized> DeliveryStore for &T {
+    async fn profile(&self, npub: &str) -> Result<UserProfile, StoreError> {
+        (**self).profile(npub).await
+    }
+
+    async fn courier_profile(&self, npub: &str) -> UserProfile {
+        (**self).courier_profile(npub).await
+    }
+
+    async fn history(&self) -> Result<Vec<DeliveryRequest>, StoreError> {
+        (**self).history().await
+    }
+
+    async fn bid_rejection(&self, delivery: &DeliveryRequest, courier: &str, profile: &UserProfile, etas: (i64, i64), now: i64) -> Result<Option<Problem>, StoreError> {
+        (**self).bid_rejection(delivery, courier, profile, etas, now).await
+    }
+
+    async fn commitment_conflicts(&self, courier: &str, etas: (i64, i64)) -> Result<Vec<Conflict>, StoreError> {
+        (**self).commitment_conflicts(courier, etas).await
+    }
+
+    async fn completion_flags(&self, delivery: &DeliveryRequest) -> Vec<FraudFlag> {
+        (**self).completion_flags(delivery).await
+    }
+
+    async fn moderate_review(&self, delivery: &DeliveryRequest, text: &str) -> Option<String> {
+        (**self).moderate_review(delivery, text).await
+    }
+
+    async fn save_new_delivery(&self, delivery: &DeliveryRequest) -> Result<(), StoreError> {
+        (**self).save_new_delivery(delivery).await
+    }
+
+    async fn save_delivery(&self, delivery: &DeliveryRequest) -> Result<(), StoreError> {
+        (**self).save_delivery(delivery).await
+    }
+
+    async fn save_bid(&self, delivery: &DeliveryRequest, bid: &DeliveryBid) -> Result<(), StoreError> {
+        (**self).save_bid(delivery, bid).await
+    }
+
+    async fn save_status_update(&self, delivery: &DeliveryRequest, update: &DeliveryUpdate) -> Result<(), StoreError> {
+        (**self).save_status_update(delivery, update).await
+    }
+
+    async fn save_profile(&self, profile: &UserProfile) -> Result<(), StoreError> {
+        (**self).save_profile(profile).await
+    }
+}
+
+#[async_trait(?Send)]
+impl DeliveryStore for AppState {
+    async fn profile(&self, npub: &str) -> Result<UserProfile, StoreError> {
+        self.get_user_profile(npub).await
+    }
+
+    async fn courier_profile(&self, npub: &str) -> UserProfile {
+        let mut profile = self.get_user_profile(npub).await.unwrap_or_default();
+        self.refresh_reputation(&mut profile).await;
+        profile
+    }
+
+    async fn history(&self) -> Result<Vec<DeliveryRequest>, StoreError> {
+        self.indexed_deliveries().await
+    }
+
+    async fn bid_rejection(&self, delivery: &DeliveryRequest, courier: &str, profile: &UserProfile, etas: (i64, i64), now: i64) -> Result<Option<Problem>, StoreError> {
+        AppState::bid_rejection(self, delivery, courier, profile, etas, now).await
+    }
+
+    async fn commitment_conflicts(&self, courier: &str, etas: (i64, i64)) -> Result<Vec<Conflict>, StoreError> {
+        AppState::commitment_conflicts(self, courier, etas).await
+    }
+
+    async fn completion_flags(&self, delivery: &DeliveryRequest) -> Vec<FraudFlag> {
+        let pings = self.get_location_pings(&delivery.id).await.unwrap_or_default();
+        let others = self.indexed_deliveries().await.unwrap_or_default();
+        self.fraud_check.check(delivery, &pings, &others)
+    }
+
+    async fn moderate_review(&self, delivery: &DeliveryRequest, text: &str) -> Option<String> {
+        AppState::moderate_review(self, &delivery.id, &delivery.sender, text).await
+    }
+
+    async fn save_new_delivery(&self, delivery: &DeliveryRequest) -> Result<(), StoreError> {
+        self.publish_new_delivery(delivery).await
+    }
+
+    async fn save_delivery(&self, delivery: &DeliveryRequest) -> Result<(), StoreError> {
+        self.publish_delivery(delivery).await
+    }
+
+    async fn save_bid(&self, delivery: &DeliveryRequest, bid: &DeliveryBid) -> Result<(), StoreError> {
+        self.publish_bid(delivery, bid).await
+    }
+
+    async fn save_status_update(&self, delivery: &DeliveryRequest, update: &DeliveryUpdate) -> Result<(), StoreError> {
+        self.publish_status_update(delivery, update).await
+    }
+
+    async fn save_profile(&self, profile: &UserProfile) -> Result<(), StoreError> {
+        self.publish_user_profile(profile).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{BidBuilder, DeliveryBuilder};
+    use crate::Amount;
+
+    const SENDER: &str = "npub_sender";
+    const COURIER: &str = "npub_courier";
+    const NOW: i64 = 1_700_000_000;
+
+    fn refused_as(problem: Option<Problem>, kind: ProblemType) -> bool {
+        problem.is_some_and(|p| p.type_uri == Problem::new(kind, "").type_uri)
+    }
+
+    fn sats(sats: u64) -> Amount {
+        Amount::from_sats(sats).unwrap()
+    }
+
+    fn with_bid() -> DeliveryRequest {
+        DeliveryBuilder::new(SENDER)
+            .offer_amount(sats(5000))
+            .bid(BidBuilder::new(COURIER).id("bid_1").amount(sats(4500)).build())
+            .build()
+    }
+
+    fn accepted() -> DeliveryRequest {
+        DeliveryBuilder::new(SENDER).accepted(BidBuilder::new(COURIER).build()).build()
+    }
+
+    fn completed() -> DeliveryRequest {
+        let mut delivery = accepted();
+        complete(&mut delivery, ProofOfDelivery::new(NOW), NOW);
+        delivery
+    }
+
+    #[test]
+    fn accept_takes_the_bid_and_keeps_the_listed_offer() {
+        let mut delivery = with_bid();
+        assert!(accept_error(&delivery, 0).is_none());
+
+        let update = accept(&mut delivery, 0, NOW);
+        assert_eq!(delivery.status, DeliveryStatus::Accepted);
+        assert_eq!(delivery.accepted_bid.as_deref(), Some("bid_1"));
+        assert_eq!(delivery.accepted_at, Some(NOW));
+        assert_eq!(delivery.offer_amount, sats(4500));
+        assert_eq!(delivery.listed_amount, Some(sats(5000)));
+        assert_eq!(update.status, DeliveryStatus::Accepted);
+        assert_eq!(update.accepted_bid.as_deref(), Some("bid_1"));
+    }
+
+    #[test]
+    fn accept_refuses_unknown_stale_and_disallowed_bids() {
+        assert!(refused_as(accept_error(&with_bid(), 1), ProblemType::InvalidRequest));
+
+        let mut stale = with_bid();
+        stale.bids[0].stale = true;
+        assert!(refused_as(accept_error(&stale, 0), ProblemType::Conflict));
+
+        let mut allowlisted = with_bid();
+        allowlisted.allowed_couriers = vec!["npub_other".to_string()];
+        assert!(refused_as(accept_error(&allowlisted, 0), ProblemType::Forbidden));
+    }
+
+    #[test]
+    fn complete_records_the_proof() {
+        let mut delivery = accepted();
+        assert!(completion_error(&delivery).is_none());
+
+        let proof = ProofOfDelivery::new(NOW).signature_name(Some("Alice".to_string()));
+        let update = complete(&mut delivery, proof, NOW);
+        assert_eq!(delivery.status, DeliveryStatus::Completed);
+        assert_eq!(delivery.completed_at, Some(NOW));
+        assert_eq!(delivery.proof_of_delivery.and_then(|p| p.signature_name).as_deref(), Some("Alice"));
+        assert_eq!(update.status, DeliveryStatus::Completed);
+    }
+
+    #[test]
+    fn complete_refuses_deliveries_not_under_way() {
+        for status in [DeliveryStatus::Open, DeliveryStatus::Completed, DeliveryStatus::Confirmed, DeliveryStatus::Expired] {
+            let delivery = DeliveryBuilder::new(SENDER).status(status).build();
+            assert!(refused_as(completion_error(&delivery), ProblemType::InvalidTransition));
+        }
+
+        let in_transit = DeliveryBuilder::new(SENDER).status(DeliveryStatus::InTransit).build();
+        assert!(completion_error(&in_transit).is_none());
+    }
+
+    #[test]
+    fn signature_packages_need_a_signature_name() {
+        let mut delivery = accepted();
+        delivery.packages[0].requires_signature = true;
+        assert!(refused_as(signature_error(&delivery, &ProofOfDelivery::new(NOW)), ProblemType::InvalidRequest));
+
+        let signed = ProofOfDelivery::new(NOW).signature_name(Some("Alice".to_string()));
+        assert!(signature_error(&delivery, &signed).is_none());
+    }
+
+    #[test]
+    fn confirm_records_the_rating_and_feedback() {
+        let mut delivery = completed();
+        let ratings = confirmation_ratings(Some(4.0), None);
+        assert!(confirmation_error(&delivery, ratings).is_none());
+
+        let update = confirm(&mut delivery, ratings, Some("Quick".to_string()), NOW);
+        assert_eq!(delivery.status, DeliveryStatus::Confirmed);
+        assert_eq!(delivery.sender_rating, Some(4.0));
+        assert_eq!(delivery.sender_ratings, Some(RatingBreakdown::uniform(4.0)));
+        assert_eq!(delivery.sender_feedback.as_deref(), Some("Quick"));
+        assert_eq!(update.sender_rating, Some(4.0));
+    }
+
+    #[test]
+    fn confirm_refuses_flagged_deliveries_and_out_of_range_ratings() {
+        let mut flagged = completed();
+        flagged.fraud_flags = vec![FraudFlag::SelfDealing];
+        assert!(refused_as(confirmation_error(&flagged, None), ProblemType::Conflict));
+
+        let ratings = confirmation_ratings(Some(6.0), None);
+        assert!(refused_as(confirmation_error(&completed(), ratings), ProblemType::InvalidRequest));
+    }
+
+    #[test]
+    fn status_changes_follow_the_lifecycle() {
+        assert!(status_change_error(&accepted(), &DeliveryStatus::InTransit).is_none());
+        assert!(status_change_error(&accepted(), &DeliveryStatus::Completed).is_none());
+        assert!(status_change_error(&completed(), &DeliveryStatus::Confirmed).is_none());
+
+        let open = DeliveryBuilder::new(SENDER).build();
+        assert!(refused_as(status_change_error(&open, &DeliveryStatus::InTransit), ProblemType::InvalidTransition));
+        assert!(refused_as(status_change_error(&open, &DeliveryStatus::Completed), ProblemType::InvalidTransition));
+        assert!(refused_as(status_change_error(&accepted(), &DeliveryStatus::Confirmed), ProblemType::InvalidTransition));
+        assert!(refused_as(status_change_error(&accepted(), &DeliveryStatus::Accepted), ProblemType::InvalidTransition));
+    }
+
+    #[test]
+    fn conflicts_only_refuse_bids_in_reject_mode() {
+        let conflicts = vec![Conflict { delivery_id: "delivery_1".to_string(), status: DeliveryStatus::Accepted, starts_at: NOW, ends_at: NOW + 3600 }];
+        assert!(refused_as(conflict_error(&conflicts, ConflictMode::Reject), ProblemType::Conflict));
+        assert!(conflict_error(&conflicts, ConflictMode::Warn).is_none());
+        assert!(conflict_error(&[], ConflictMode::Reject).is_none());
+    }
+}
@@ -0,0 +1,178 @@
+// service_area.rs - Operator-defined service area polygons (GeoJSON)
+use serde_json::Value;
+
+use crate::GeoPoint;
+
+// Polygon as [lng, lat] rings: the first is the outer boundary, the rest are holes
+type Polygon = Vec<Vec<(f64, f64)>>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ServiceAreaMode {
+    Reject,
+    Flag,
+}
+
+impl ServiceAreaMode {
+    pub fn from_env() -> Self {
+        match std::env::var("SERVICE_AREA_MODE").as_deref() {
+            Ok("flag") => Self::Flag,
+            _ => Self::Reject,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ServiceArea {
+    polygons: Vec<Polygon>,
+    // As configured, returned by the admin API
+    pub geojson: Value,
+}
+
+impl ServiceArea {
+    // Accepts a Polygon, MultiPolygon, Feature or FeatureCollection
+    pub fn from_geojson(geojson: Value) -> Result<Self, String> {
+        let mut polygons = Vec::new();
+        collect_polygons(&geojson, &mut polygons)?;
+        if polygons.is_empty() {
+            return Err("GeoJSON contains no polygons".to_string());
+        }
+        Ok(Self { polygons, geojson })
+    }
+
+    // SERVICE_AREA_FILE: path to a GeoJSON file loaded at startup
+    pub fn from_env() -> Result<Option<Self>, String> {
+        let Ok(path) = std::env::var("SERVICE_AREA_FILE") else {
+            return Ok(None);
+        };
+        let raw = std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path, e))?;
+        let geojson = serde_json::from_str(&raw).map_err(|e| format!("{}: {}", path, e))?;
+        Self::from_geojson(geojson).map(Some)
+    }
+
+    pub fn contains(&self, point: &GeoPoint) -> bool {
+        self.polygons.iter().any(|polygon| {
+            let mut rings = polygon.iter();
+            rings.next().is_some_and(|outer| ring_contains(outer, point))
+                && !rings.any(|hole| ring_contains(hole, point))
+        })
+    }
+}
+
+fn collect_polygons(value: &Value, polygons: &mut Vec<Polygon>) -> Result<(), String> {
+    match value["type"].as_str() {
+        Some("FeatureCollection") => {
+            for feature in value["features"].as_array().ok_or("FeatureCollection without features")? {
+                collect_polygons(feature, polygons)?;
+            }
+        }
+        Some("Feature") => collect_polygons(&value["geometry"], polygons)?,
+        Some("Polygon") => polygons.push(parse_polygon(&value["coordinates"])?),
+        Some("MultiPolygon") => {
+            for polygon in value["coordinates"].as_array().ok_or("MultiPolygon without coordinates")? {
+                polygons.push(parse_polygon(polygon)?);
+            }
+        }
+        Some(other) => return Err(format!("Unsupported GeoJSON type '{}'", other)),
+        None => return Err("GeoJSON object without a type".to_string()),
+    }
+    Ok(())
+}
+
+fn parse_polygon(value: &Value) -> Result<Polygon, String> {
+    let rings = value.as_array().ok_or("Polygon coordinates must be an array of rings")?;
+    rings
+        .iter()
+        .map(|ring| {
+            let positions = ring.as_array().ok_or("Polygon ring must be an array of positions")?;
+            if positions.len() < 4 {
+                return Err("Polygon ring needs at least 4 positions".to_string());
+            }
+            positions
+                .iter()
+                .map(|pos| match (pos[0].as_f64(), pos[1].as_f64()) {
+                    (Some(lng), Some(lat)) => Ok((lng, lat)),
+                    _ => Err("Position must be [lng, lat]".to_string()),
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// Ray casting point-in-polygon test
+fn ring_contains(ring: &[(f64, f64)], point: &GeoPoint) -> bool {
+    let (x, y) = (point.lng, point.lat);
+    let mut inside = false;
+    let mut j = ring.len() - 1;
+    for i in 0..ring.len() {
+        let (xi, yi) = ring[i];
+        let (xj, yj) = ring[j];
+        if (yi > y) != (yj > y) && x < (xj - xi) * (y - yi) / (yj - yi) + xi {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn point(lng: f64, lat: f64) -> GeoPoint {
+        GeoPoint { lat, lng }
+    }
+
+    fn square(min: f64, max: f64) -> Value {
+        json!([[min, min], [max, min], [max, max], [min, max], [min, min]])
+    }
+
+    #[test]
+    fn polygon_contains_points_inside_its_outer_ring() {
+        let area = ServiceArea::from_geojson(json!({ "type": "Polygon", "coordinates": [square(0.0, 10.0)] })).unwrap();
+
+        assert!(area.contains(&point(5.0, 5.0)));
+        assert!(area.contains(&point(9.999, 0.001)));
+        assert!(!area.contains(&point(10.001, 5.0)));
+        assert!(!area.contains(&point(-5.0, 5.0)));
+    }
+
+    #[test]
+    fn holes_are_outside_the_area() {
+        let area = ServiceArea::from_geojson(json!({
+            "type": "Polygon",
+            "coordinates": [square(0.0, 10.0), square(4.0, 6.0)],
+        }))
+        .unwrap();
+
+        assert!(area.contains(&point(2.0, 2.0)));
+        assert!(!area.contains(&point(5.0, 5.0)));
+    }
+
+    #[test]
+    fn any_polygon_of_a_multipolygon_or_collection_counts() {
+        let multi = json!({ "type": "MultiPolygon", "coordinates": [[square(0.0, 1.0)], [square(20.0, 21.0)]] });
+        let collection = json!({
+            "type": "FeatureCollection",
+            "features": [{ "type": "Feature", "properties": {}, "geometry": multi.clone() }],
+        });
+
+        for geojson in [multi, collection] {
+            let area = ServiceArea::from_geojson(geojson).unwrap();
+            assert!(area.contains(&point(0.5, 0.5)));
+            assert!(area.contains(&point(20.5, 20.5)));
+            assert!(!area.contains(&point(10.0, 10.0)));
+        }
+    }
+
+    #[test]
+    fn malformed_geojson_is_refused() {
+        let refused = |geojson: Value| ServiceArea::from_geojson(geojson).is_err();
+
+        assert!(refused(json!({ "type": "FeatureCollection", "features": [] })));
+        assert!(refused(json!({ "type": "Polygon", "coordinates": [[[0, 0], [1, 0], [0, 0]]] })));
+        assert!(refused(json!({ "type": "Polygon", "coordinates": [[[0, 0], [1, 0], [1, 1], ["a", 0]]] })));
+        assert!(refused(json!({ "type": "Point", "coordinates": [0, 0] })));
+        assert!(refused(json!({ "coordinates": [] })));
+    }
+}
@@ -0,0 +1,104 @@
+// settings.rs - Per-user preferences stored as NIP-78 application data
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::delegation::Delegation;
+use crate::i18n::Language;
+use crate::Location;
+
+// Prefix of the `d` tag identifying settings events (NIP-78 app data)
+pub const APP_DATA_PREFIX: &str = "nostr-delivery/settings";
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(default)]
+pub struct NotificationSettings {
+    pub new_bids: bool,
+    pub status_updates: bool,
+    pub email: Option<String>,
+    // Email `email` when a courier is found, picks up and delivers (needs SMTP configured)
+    pub email_updates: bool,
+}
+
+impl Default for NotificationSettings {
+    fn default() -> Self {
+        Self {
+            new_bids: true,
+            status_updates: true,
+            email: None,
+            email_updates: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum UnitSystem {
+    #[default]
+    Metric,
+    Imperial,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum PushPlatform {
+    Fcm,
+    UnifiedPush,
+}
+
+// A phone registered for push notifications
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct PushDevice {
+    // Derived from the token, so registering the same token again replaces it
+    pub id: String,
+    pub platform: PushPlatform,
+    // FCM registration token, or the UnifiedPush endpoint URL
+    pub token: String,
+    pub name: Option<String>,
+    pub registered_at: i64,
+}
+
+impl PushDevice {
+    // The device as listed back to its owner, without its token
+    pub fn redacted(&self) -> Self {
+        Self { token: String::new(), ..self.clone() }
+    }
+}
+
+// Entries kept in a user's address book
+pub const MAX_SAVED_ADDRESSES: usize = 100;
+
+// An address-book entry; deliveries can name it by `id` instead of repeating the location
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SavedAddress {
+    pub id: String,
+    // "Home", "Warehouse", ...
+    pub label: String,
+    pub location: Location,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+#[serde(default)]
+pub struct UserSettings {
+    pub notifications: NotificationSettings,
+    pub default_pickup: Option<Location>,
+    pub preferred_couriers: Vec<String>,
+    pub unit_system: UnitSystem,
+    // Language of notifications and status labels; unset follows the request's Accept-Language
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<Language>,
+    // NIP-26 delegation to the marketplace key, set through the delegation endpoint
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delegation: Option<Delegation>,
+    // Push devices, managed through the devices endpoint
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub devices: Vec<PushDevice>,
+    // Address book, managed through the addresses endpoint
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub addresses: Vec<SavedAddress>,
+}
+
+// `d` tag for a user's settings; `token` hides the npub from relay observers
+pub fn app_data_identifier(token: &str) -> String {
+    format!("{}/{}", APP_DATA_PREFIX, token)
+}
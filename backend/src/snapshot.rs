@@ -0,0 +1,82 @@
+// snapshot.rs - Whole-marketplace snapshots for migrations and disaster recovery: every event the
+// marketplace signed, plus a signed manifest so truncated or altered files are rejected on import
+use nostr::hashes::{sha256, Hash};
+use nostr::{Event, EventBuilder, Kind, Tag, TagKind};
+use serde::{Deserialize, Serialize};
+
+use crate::{courier_lists, organizations, sync};
+
+pub const SNAPSHOT_VERSION: u32 = 1;
+// Kind of the manifest event. It is only ever stored in snapshot files, never published.
+pub const MANIFEST_KIND: u16 = 35013;
+
+// Deliveries and their lifecycle, bids, profiles and organizations, plus settings, courier lists
+// and organization keys
+pub fn snapshot_kinds() -> Vec<Kind> {
+    sync::DELIVERY_EVENT_KINDS
+        .map(Kind::Custom)
+        .chain([
+            Kind::ApplicationSpecificData,
+            Kind::Custom(courier_lists::FOLLOW_SET_KIND),
+            Kind::Custom(organizations::ORG_KEY_KIND),
+        ])
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    pub created_at: i64,
+    pub manifest: Event,
+    // Oldest first
+    pub events: Vec<Event>,
+}
+
+impl Snapshot {
+    // Events must already be sorted; the manifest is signed over them in order
+    pub fn new(created_at: i64, manifest: Event, events: Vec<Event>) -> Self {
+        Self { version: SNAPSHOT_VERSION, created_at, manifest, events }
+    }
+
+    // Check the manifest's signature and digest, then every event's signature
+    pub fn verify(&self) -> Result<(), String> {
+        if self.version != SNAPSHOT_VERSION {
+            return Err(format!("Unsupported snapshot version {}", self.version));
+        }
+        if self.manifest.kind != Kind::Custom(MANIFEST_KIND) {
+            return Err("Snapshot manifest has the wrong kind".to_string());
+        }
+        self.manifest.verify().map_err(|e| format!("Invalid manifest signature: {}", e))?;
+        if self.manifest.content != digest(&self.events) {
+            return Err("Snapshot events don't match the manifest".to_string());
+        }
+        for event in &self.events {
+            event.verify().map_err(|e| format!("Invalid signature on event {}: {}", event.id, e))?;
+        }
+        Ok(())
+    }
+}
+
+// Events oldest first, ties broken by ID, so the digest doesn't depend on relay order
+pub fn sort_events(events: &mut [Event]) {
+    events.sort_by_key(|e| (e.created_at, e.id));
+}
+
+// SHA-256 over the event IDs in order
+pub fn digest(events: &[Event]) -> String {
+    let mut ids = Vec::with_capacity(events.len() * 32);
+    for event in events {
+        ids.extend_from_slice(event.id.as_bytes());
+    }
+    sha256::Hash::hash(&ids).to_string()
+}
+
+// Unsigned manifest for `events`, to be signed by the marketplace key
+pub fn manifest(events: &[Event], created_at: i64) -> EventBuilder {
+    let tags = vec![
+        Tag::custom(TagKind::Custom("version".into()), vec![SNAPSHOT_VERSION.to_string()]),
+        Tag::custom(TagKind::Custom("count".into()), vec![events.len().to_string()]),
+        Tag::custom(TagKind::Custom("snapshot_at".into()), vec![created_at.to_string()]),
+    ];
+    EventBuilder::new(Kind::Custom(MANIFEST_KIND), digest(events), tags)
+}
@@ -0,0 +1,102 @@
+// start_timeout.rs - Accepted deliveries the courier never starts: a reminder to both parties once
+// the start deadline passes, then back to Open with a no-show recorded against the courier
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{DeliveryRequest, DeliveryStatus, TimeWindow};
+
+#[derive(Debug, Clone)]
+pub struct StartTimeoutConfig {
+    // Time allowed after acceptance, or after the committed pickup if that's later; 0 disables
+    pub timeout_secs: i64,
+    // Time between the reminder and the revert
+    pub grace_secs: i64,
+    pub interval_secs: u64,
+}
+
+impl StartTimeoutConfig {
+    // START_TIMEOUT_SECS (default 3600), START_TIMEOUT_GRACE_SECS (default 900) and
+    // START_TIMEOUT_CHECK_SECS (default 60)
+    pub fn from_env() -> Self {
+        let secs = |name: &str, default: i64| {
+            std::env::var(name).ok().and_then(|v| v.parse::<i64>().ok()).filter(|s| *s >= 0).unwrap_or(default)
+        };
+        Self {
+            timeout_secs: secs("START_TIMEOUT_SECS", 3600),
+            grace_secs: secs("START_TIMEOUT_GRACE_SECS", 900),
+            interval_secs: secs("START_TIMEOUT_CHECK_SECS", 60).max(1) as u64,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.timeout_secs > 0
+    }
+
+    // When an accepted delivery should have been started by. Deliveries accepted before
+    // `accepted_at` was recorded count from the accepted bid.
+    pub fn deadline(&self, delivery: &DeliveryRequest) -> Option<i64> {
+        if delivery.status != DeliveryStatus::Accepted {
+            return None;
+        }
+        let accepted = delivery.accepted_bid.as_ref()?;
+        let bid = delivery.bids.iter().find(|b| &b.id == accepted)?;
+        let accepted_at = delivery.accepted_at.unwrap_or(bid.created_at);
+        // A delivery accepted well ahead of its window isn't late until the pickup comes round
+        let pickup = bid.pickup_eta.or_else(|| TimeWindow::parse(&delivery.time_window).start);
+        Some(accepted_at.max(pickup.unwrap_or(accepted_at)) + self.timeout_secs)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StartCheck {
+    OnTime,
+    // Past the deadline: remind both parties
+    Overdue { deadline: i64 },
+    // Past the grace period as well: revert to Open
+    NoShow,
+}
+
+impl StartTimeoutConfig {
+    pub fn check(&self, delivery: &DeliveryRequest, now: i64) -> StartCheck {
+        match self.deadline(delivery) {
+            Some(deadline) if now >= deadline + self.grace_secs => StartCheck::NoShow,
+            Some(deadline) if now >= deadline => StartCheck::Overdue { deadline },
+            _ => StartCheck::OnTime,
+        }
+    }
+}
+
+// A courier who accepted the delivery and never started it
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct NoShow {
+    pub courier: String,
+    pub bid_id: String,
+    pub accepted_at: Option<i64>,
+    pub reverted_at: i64,
+}
+
+// The delivery open again for bids, with the no-show recorded. None unless it is Accepted.
+pub fn reopen(delivery: &DeliveryRequest, now: i64) -> Option<DeliveryRequest> {
+    if delivery.status != DeliveryStatus::Accepted {
+        return None;
+    }
+    let bid_id = delivery.accepted_bid.clone()?;
+    let courier = delivery.accepted_courier()?.to_string();
+
+    let mut reopened = delivery.clone();
+    reopened.no_shows.push(NoShow {
+        courier,
+        bid_id,
+        accepted_at: delivery.accepted_at,
+        reverted_at: now,
+    });
+    reopened.status = DeliveryStatus::Open;
+    reopened.accepted_bid = None;
+    reopened.accepted_at = None;
+    // Back on the market at the sender's own offer
+    if let Some(listed) = reopened.listed_amount.take() {
+        reopened.offer_amount = listed;
+    }
+    reopened.expires_at = delivery.expires_at.map(|at| at.max(now + 604800));
+    Some(reopened)
+}
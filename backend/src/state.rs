@@ -0,0 +1,2899 @@
+// state.rs - Application state: the Nostr client, indexer snapshot and runtime configuration
+use chrono::Utc;
+use nostr_sdk::prelude::*;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::*;
+
+// Snapshot of relay state maintained by the background indexer
+#[derive(Default)]
+pub struct DeliveryIndex {
+    pub deliveries: Vec<DeliveryRequest>,
+    pub market_stats: analytics::MarketStats,
+    pub leaderboards: analytics::Leaderboards,
+    // Per-delivery change times, carried across passes for /sync
+    pub changes: sync::ChangeLog,
+    pub indexed_at: i64,
+}
+
+// Application State with Nostr Client
+pub struct AppState {
+    pub nostr_client: Arc<Client>,
+    // Local keys: the system signing key, or only the NIP-46 client key when a bunker signs
+    pub system_keys: Keys,
+    pub system_pubkey: PublicKey,
+    // Authors whose delivery events are trusted on read
+    pub authors: query::Authors,
+    pub bunker: Option<bunker::BunkerConfig>,
+    pub signing_timeout: Duration,
+    pub index: tokio::sync::RwLock<DeliveryIndex>,
+    pub index_interval_secs: u64,
+    pub readiness: health::ReadinessConfig,
+    pub geocoder: Option<Arc<dyn geocode::Geocoder>>,
+    // Resolvers for stops given as a what3words address (only with W3W_API_KEY) or a plus code
+    pub what3words: Option<Arc<dyn location_codes::CodeResolver>>,
+    pub plus_codes: Arc<dyn location_codes::CodeResolver>,
+    pub frontend_url: String,
+    pub federation: federation::FederationConfig,
+    pub federated: tokio::sync::RwLock<std::collections::HashMap<String, DeliveryRequest>>,
+    pub public_url: String,
+    pub service_area: tokio::sync::RwLock<Option<service_area::ServiceArea>>,
+    pub service_area_mode: service_area::ServiceAreaMode,
+    // Whether bids overlapping the courier's accepted deliveries are refused or only flagged
+    pub bid_conflict_mode: commitments::ConflictMode,
+    // How much of the stop contacts shipping labels print
+    pub label_contacts: label::ContactRedaction,
+    // Percent added to the offer when a failed delivery is re-listed, unless the request says otherwise
+    pub relist_bump_percent: f64,
+    pub admin_token: Option<String>,
+    pub classifieds_enabled: bool,
+    pub listing_interest: tokio::sync::RwLock<Vec<classifieds::ListingInterest>>,
+    pub review_filter: Arc<dyn moderation::ReviewFilter>,
+    pub held_reviews: tokio::sync::RwLock<Vec<moderation::HeldReview>>,
+    pub blocklist: tokio::sync::RwLock<admin::Blocklist>,
+    pub escalations: tokio::sync::RwLock<Vec<admin::Escalation>>,
+    pub fraud_check: fraud::FraudCheck,
+    // What follows a failed dropoff attempt
+    pub attempt_policy: delivery_attempts::AttemptPolicy,
+    // Wrong dropoff codes submitted per delivery
+    pub dropoff_code_attempts: tokio::sync::RwLock<codes::Attempts>,
+    // How long an accepted delivery may go unstarted before it is re-opened
+    pub start_timeout: start_timeout::StartTimeoutConfig,
+    // Overdue deliveries whose parties were already reminded
+    pub start_reminded: tokio::sync::RwLock<std::collections::HashSet<String>>,
+    // Thresholds for deliveries stuck in one status, and who hears about them
+    pub stuck: stuck::StuckConfig,
+    // Deliveries (and the status they're stuck in) already alerted on
+    pub stuck_alerted: tokio::sync::RwLock<std::collections::HashSet<String>>,
+    // Whether the indexer lists deliveries senders publish to relays themselves
+    pub native_deliveries: bool,
+    // Sender-signed delivery events already listed or rejected
+    pub native_deliveries_seen: tokio::sync::RwLock<std::collections::HashSet<String>>,
+    // Whether the indexer merges bids couriers publish to relays themselves
+    pub native_bids: bool,
+    // Courier-signed bid events already merged or rejected, with their created_at; pruned once the
+    // sync cursor has passed them
+    pub native_bids_seen: tokio::sync::RwLock<std::collections::HashMap<String, i64>>,
+    // Bid events up to this time were all read; later passes only fetch newer ones
+    pub native_bids_synced: tokio::sync::RwLock<Option<i64>>,
+    pub tracking_max_gap_secs: i64,
+    pub notify_tracking_anomalies: bool,
+    pub pickup_proximity: Option<tracking::PickupProximity>,
+    pub delete_grace_secs: i64,
+    pub started_at: i64,
+    // Local primary relay; marketplace reads go only to it, writes also go to the mirrors
+    pub embedded_relay: Option<embedded_relay::EmbeddedRelay>,
+    pub mirrors: Vec<String>,
+    pub proxies: proxy::ProxyConfig,
+    pub outbox: outbox::OutboxConfig,
+    pub relay_lists: tokio::sync::RwLock<outbox::RelayListCache>,
+    pub redundancy: redundancy::RedundancyConfig,
+    pub redundancy_report: tokio::sync::RwLock<Option<redundancy::RedundancyReport>>,
+    // Periodic background work (indexer, compaction, timeouts, alerts, redundancy)
+    pub jobs: jobs::Scheduler,
+    // Deliveries, bids and profiles read recently or published by us
+    pub cache: cache::ReadCache,
+    // Relational copy of published events, for queries and reports (DATABASE_URL)
+    pub postgres: Option<postgres::PostgresMirror>,
+    // Current state of each delivery, folded from its status updates as they're synced
+    pub projections: tokio::sync::RwLock<projection::Projections>,
+    // Each relay's answer to recent publishes, and totals per relay
+    pub publish_log: tokio::sync::RwLock<publish_log::PublishLog>,
+    // Relays that must accept an event for a publish to succeed
+    pub publish_quorum: usize,
+    pub compaction: compaction::CompactionConfig,
+    // Decrypted organization signing keys by org ID
+    pub org_keys: tokio::sync::RwLock<std::collections::HashMap<String, Keys>>,
+    // NIP-26 delegations by user npub, loaded from their settings on first use
+    pub delegations: tokio::sync::RwLock<std::collections::HashMap<String, Option<delegation::Delegation>>>,
+    // Key for the codes, tokens, quotes and settings identifiers derived in `codes`
+    code_secret: Vec<u8>,
+    pub auth: auth::AuthConfig,
+    pub auth_challenges: tokio::sync::RwLock<auth::Challenges>,
+    // Checked from the (synchronous) AuthenticatedUser extractor
+    pub http_auth_replays: std::sync::Mutex<auth::HttpAuthReplays>,
+    // Loaded from relays on first use
+    pub api_keys: tokio::sync::RwLock<Option<Vec<api_keys::ApiKey>>>,
+    pub mailer: Option<Arc<email::Mailer>>,
+    pub recipient_notifications: Option<notify::RecipientNotifications>,
+    // Deliveries whose recipient was already told the courier is close
+    pub eta_notified: tokio::sync::RwLock<std::collections::HashSet<String>>,
+    pub push: push::PushGateway,
+    pub uploads: uploads::UploadStore,
+}
+
+impl AppState {
+    pub async fn new(relay_urls: Vec<String>) -> Result<Self, Box<dyn std::error::Error>> {
+        // System keys for signing events: SYSTEM_SECRET_KEY (nsec or hex), or a fresh key per run
+        let system_keys = match std::env::var("SYSTEM_SECRET_KEY").ok().filter(|k| !k.is_empty()) {
+            Some(key) => Keys::parse(&key).map_err(|e| format!("Invalid SYSTEM_SECRET_KEY: {}", e))?,
+            None => Keys::generate(),
+        };
+        let signing_timeout = bunker::signing_timeout_from_env();
+        let bunker = bunker::BunkerConfig::from_env(&system_keys, signing_timeout)?;
+
+        // Create Nostr client, signing either locally or through the remote signer
+        let client = match &bunker {
+            Some(bunker) => Client::new(bunker.connect()?),
+            None => Client::new(system_keys.clone()),
+        };
+
+        let embedded_relay = match embedded_relay::EmbeddedRelayConfig::from_env()? {
+            Some(config) => {
+                let relay = embedded_relay::EmbeddedRelay::start(&config).await?;
+                eprintln!("🗄️  Embedded relay on {} ({} events in {})", relay.url(), relay.database.event_count().await?, config.path.display());
+                Some(relay)
+            }
+            None => None,
+        };
+
+        // Add relays
+        if let Some(relay) = &embedded_relay {
+            client.add_relay(relay.url()).await?;
+        }
+        let proxies = proxy::ProxyConfig::from_env()?;
+        let mut proxied = 0;
+        for url in &relay_urls {
+            match proxies.proxy_for(url) {
+                Some(addr) => {
+                    let opts = RelayOptions::new().connection_mode(ConnectionMode::proxy(addr));
+                    client.pool().add_relay(url, opts).await?;
+                    proxied += 1;
+                }
+                None => {
+                    client.add_relay(url).await?;
+                }
+            }
+        }
+
+        // Connect to relays
+        client.connect().await;
+
+        // Wait a bit for connections to establish, longer when some go through a proxy
+        let connect_wait = if proxied > 0 { 10 } else { 2 };
+        tokio::time::sleep(Duration::from_secs(connect_wait)).await;
+        if proxied > 0 {
+            eprintln!("🧅 {} of {} relays connect through a SOCKS5 proxy", proxied, relay_urls.len());
+        }
+
+        // stderr, so CLI subcommands can write their results to stdout
+        eprintln!("📡 Connected to {} relays", client.relays().await.len());
+        let signer = client.signer().await?;
+        let system_pubkey = tokio::time::timeout(signing_timeout, signer.get_public_key()).await??;
+        if bunker.is_some() {
+            eprintln!("🔐 Signing via NIP-46 remote signer");
+        }
+        eprintln!("🔑 System pubkey: {}", system_pubkey.to_bech32()?);
+
+        // Keep reading events signed by keys rotated to this one, until their grace period ends
+        let mut authors = query::Authors::from_env(system_pubkey)?;
+        let filter = Filter::new().kind(Kind::Custom(rotation::ROTATION_KIND)).pubkey(system_pubkey);
+        let now = Utc::now().timestamp();
+        for event in client.fetch_events(vec![filter], Some(Duration::from_secs(5))).await?.iter() {
+            if let Some(rotation) = rotation::Rotation::from_event(event).filter(|r| r.new == system_pubkey && r.grace_until > now) {
+                eprintln!("🔁 Trusting rotated-out key {} until {}", rotation.old.to_bech32()?, rotation.grace_until);
+                authors.trust_until(rotation.old, rotation.grace_until);
+            }
+        }
+
+        // Organization keys issued by the marketplace sign for their own organization only
+        let filter = Filter::new().kind(Kind::Custom(organizations::ORG_KEY_KIND)).authors(authors.keys());
+        let events = client.fetch_events(vec![filter], Some(Duration::from_secs(5))).await?;
+        for event in query::latest_per_identifier(events, &authors) {
+            if let Some(pubkey) = query::tag_value(&event, "p").and_then(|p| PublicKey::parse(p).ok()) {
+                authors.trust_organization_key(pubkey, query::identifier(&event));
+            }
+        }
+
+        // Dropoff and pickup codes, recipient links, quotes and session tokens outlive the system
+        // key: CODE_SECRET, or a secret kept on the relays, which key rotation re-encrypts
+        let code_secret = match std::env::var("CODE_SECRET").ok().filter(|s| !s.is_empty()) {
+            Some(secret) => secret.into_bytes(),
+            None => load_code_secret(&client, system_pubkey, &system_keys, signing_timeout).await?,
+        };
+        let auth = auth::AuthConfig::from_env(&code_secret);
+        let index_interval_secs = std::env::var("INDEX_INTERVAL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(60);
+        let geocoder = geocode::from_env();
+        let public_url = std::env::var("PUBLIC_URL")
+            .unwrap_or_else(|_| "http://localhost:8080".to_string())
+            .trim_end_matches('/')
+            .to_string();
+
+        Ok(Self {
+            nostr_client: Arc::new(client),
+            system_keys,
+            system_pubkey,
+            authors,
+            bunker,
+            signing_timeout,
+            index: tokio::sync::RwLock::new(DeliveryIndex::default()),
+            index_interval_secs,
+            readiness: health::ReadinessConfig::from_env(index_interval_secs),
+            what3words: location_codes::what3words_from_env(),
+            plus_codes: Arc::new(location_codes::PlusCodeResolver::new(geocoder.clone())),
+            geocoder,
+            frontend_url: std::env::var("FRONTEND_URL")
+                .unwrap_or_else(|_| "http://localhost:3000".to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            federation: federation::FederationConfig::from_env()?,
+            federated: tokio::sync::RwLock::new(std::collections::HashMap::new()),
+            uploads: uploads::UploadStore::from_env(&public_url),
+            public_url,
+            service_area: tokio::sync::RwLock::new(service_area::ServiceArea::from_env()?),
+            service_area_mode: service_area::ServiceAreaMode::from_env(),
+            bid_conflict_mode: commitments::ConflictMode::from_env(),
+            label_contacts: label::ContactRedaction::from_env(),
+            relist_bump_percent: relist::bump_percent_from_env(),
+            admin_token: std::env::var("ADMIN_TOKEN").ok().filter(|t| !t.is_empty()),
+            classifieds_enabled: std::env::var("NIP99_CLASSIFIEDS").is_ok_and(|v| v == "true" || v == "1"),
+            listing_interest: tokio::sync::RwLock::new(Vec::new()),
+            review_filter: moderation::from_env(),
+            held_reviews: tokio::sync::RwLock::new(Vec::new()),
+            blocklist: tokio::sync::RwLock::new(admin::Blocklist::from_env(Utc::now().timestamp())),
+            escalations: tokio::sync::RwLock::new(Vec::new()),
+            fraud_check: fraud::FraudCheck::from_env(),
+            attempt_policy: delivery_attempts::AttemptPolicy::from_env()?,
+            dropoff_code_attempts: tokio::sync::RwLock::new(codes::Attempts::default()),
+            start_timeout: start_timeout::StartTimeoutConfig::from_env(),
+            start_reminded: tokio::sync::RwLock::new(std::collections::HashSet::new()),
+            stuck: stuck::StuckConfig::from_env()?,
+            stuck_alerted: tokio::sync::RwLock::new(std::collections::HashSet::new()),
+            native_deliveries: native_deliveries::enabled_from_env(),
+            native_deliveries_seen: tokio::sync::RwLock::new(std::collections::HashSet::new()),
+            native_bids: native_bids::enabled_from_env(),
+            native_bids_seen: tokio::sync::RwLock::new(std::collections::HashMap::new()),
+            native_bids_synced: tokio::sync::RwLock::new(None),
+            tracking_max_gap_secs: std::env::var("TRACKING_MAX_GAP_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(tracking::DEFAULT_MAX_TRACKING_GAP_SECS),
+            notify_tracking_anomalies: std::env::var("TRACKING_ANOMALY_NOTIFY").is_ok_and(|v| v == "true" || v == "1"),
+            pickup_proximity: tracking::PickupProximity::from_env(),
+            delete_grace_secs: std::env::var("DELETE_GRACE_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_DELETE_GRACE_SECS),
+            started_at: Utc::now().timestamp(),
+            embedded_relay,
+            mirrors: relay_urls,
+            proxies,
+            outbox: outbox::OutboxConfig::from_env(),
+            relay_lists: tokio::sync::RwLock::new(outbox::RelayListCache::default()),
+            redundancy: redundancy::RedundancyConfig::from_env(),
+            redundancy_report: tokio::sync::RwLock::new(None),
+            jobs: jobs::Scheduler::from_env(),
+            cache: cache::ReadCache::from_env(),
+            postgres: postgres::PostgresMirror::from_env().await?,
+            projections: tokio::sync::RwLock::new(projection::Projections::default()),
+            publish_log: tokio::sync::RwLock::new(publish_log::PublishLog::from_env()),
+            publish_quorum: publish_log::quorum_from_env(),
+            compaction: compaction::CompactionConfig::from_env(),
+            org_keys: tokio::sync::RwLock::new(std::collections::HashMap::new()),
+            delegations: tokio::sync::RwLock::new(std::collections::HashMap::new()),
+            code_secret,
+            auth,
+            auth_challenges: tokio::sync::RwLock::new(auth::Challenges::default()),
+            http_auth_replays: std::sync::Mutex::new(auth::HttpAuthReplays::default()),
+            api_keys: tokio::sync::RwLock::new(None),
+            mailer: email::EmailConfig::from_env()?.map(|config| Arc::new(email::Mailer::new(config))),
+            recipient_notifications: notify::RecipientNotifications::from_env(),
+            eta_notified: tokio::sync::RwLock::new(std::collections::HashSet::new()),
+            push: push::PushGateway::from_env()?,
+        })
+    }
+
+    // Events signed by the marketplace, from the embedded relay when there is one
+    pub async fn fetch_marketplace_events(&self, filter: Filter, timeout: Duration) -> Result<Events, Box<dyn std::error::Error>> {
+        let events = match &self.embedded_relay {
+            Some(relay) => self.nostr_client.fetch_events_from([relay.url()], vec![filter], Some(timeout)).await?,
+            None => self.nostr_client.fetch_events(vec![filter], Some(timeout)).await?,
+        };
+        Ok(events)
+    }
+
+    // Every event matching `filter` on the relays, paging back with `until` past relay result limits
+    pub async fn fetch_all_events(&self, filter: Filter) -> Result<Vec<Event>, Box<dyn std::error::Error>> {
+        let mut events: std::collections::HashMap<EventId, Event> = std::collections::HashMap::new();
+        let mut until: Option<Timestamp> = None;
+        loop {
+            let mut page = filter.clone().limit(500);
+            if let Some(until) = until {
+                page = page.until(until);
+            }
+            let batch = self.nostr_client.fetch_events(vec![page], Some(Duration::from_secs(20))).await?;
+
+            let mut added = 0;
+            let mut oldest = None;
+            for event in batch.into_iter() {
+                oldest = Some(oldest.map_or(event.created_at, |t: Timestamp| t.min(event.created_at)));
+                if events.insert(event.id, event).is_none() {
+                    added += 1;
+                }
+            }
+            // `until` is inclusive, so a page of only already-seen events means we're done
+            match oldest {
+                Some(oldest) if added > 0 => until = Some(oldest),
+                _ => break,
+            }
+        }
+        Ok(events.into_values().collect())
+    }
+
+    // Copy the marketplace's events from the mirrors into the embedded relay, so history
+    // published before it was enabled (or while it was down) can be read locally
+    pub async fn backfill_embedded_relay(&self) -> Result<usize, Box<dyn std::error::Error>> {
+        let Some(relay) = &self.embedded_relay else {
+            return Ok(0);
+        };
+        if self.mirrors.is_empty() {
+            return Ok(0);
+        }
+
+        let filter = Filter::new().authors(self.authors.keys());
+        let events = self.nostr_client.fetch_events_from(self.mirrors.clone(), vec![filter], Some(Duration::from_secs(30))).await?;
+
+        let mut stored = 0;
+        for event in events.iter() {
+            if relay.database.save_event(event).await? {
+                stored += 1;
+            }
+        }
+        Ok(stored)
+    }
+
+    // Compare each relay's copies of the latest state events for active deliveries, and send
+    // events held by fewer than REDUNDANCY_MIN_RELAYS relays to the relays missing them
+    pub async fn check_redundancy(&self) -> Result<redundancy::RedundancyReport, Box<dyn std::error::Error>> {
+        let active: Vec<DeliveryRequest> = self.indexed_deliveries().await?
+            .into_iter()
+            .filter(redundancy::is_active)
+            .collect();
+        let ids: std::collections::HashSet<String> = active.iter().map(|d| d.id.clone()).collect();
+        // No state event of an active delivery predates the delivery itself
+        let since = active.iter().map(|d| d.created_at).min().unwrap_or_else(|| Utc::now().timestamp());
+        let filter = Filter::new()
+            .kinds(redundancy::state_kinds())
+            .authors(self.authors.keys())
+            .since(Timestamp::from(since.max(0) as u64));
+
+        let mut held = std::collections::HashMap::new();
+        let mut errors = std::collections::HashMap::new();
+        let mut found = Vec::new();
+        for url in self.nostr_client.relays().await.into_keys() {
+            let mut ids_held = std::collections::HashSet::new();
+            if !ids.is_empty() {
+                match self.nostr_client.fetch_events_from([url.clone()], vec![filter.clone()], Some(Duration::from_secs(20))).await {
+                    Ok(events) => {
+                        for event in events.into_iter().filter(|e| redundancy::concerns(e, &ids)) {
+                            ids_held.insert(event.id);
+                            found.push(event);
+                        }
+                    }
+                    Err(e) => {
+                        errors.insert(url.to_string(), e.to_string());
+                    }
+                }
+            }
+            held.insert(url.to_string(), ids_held);
+        }
+
+        let latest = query::latest_per_identifier(found, &self.authors);
+        let (mut relays, repairs) = redundancy::assess(&latest, &held, self.redundancy.min_relays);
+        for relay in relays.iter_mut() {
+            relay.error = errors.remove(&relay.url);
+        }
+
+        let mut republished = 0;
+        for repair in &repairs {
+            let id = repair.event.id;
+            match self.send_event_to(repair.missing_from.clone(), repair.event.clone()).await {
+                Ok(output) if !output.success.is_empty() => republished += 1,
+                Ok(_) => log::warn!("No relay accepted republished event {}", id),
+                Err(e) => log::warn!("Failed to republish {}: {}", id, e),
+            }
+        }
+
+        let report = redundancy::RedundancyReport {
+            checked_at: Utc::now().timestamp(),
+            min_relays: self.redundancy.min_relays.min(held.len()),
+            active_deliveries: active.len(),
+            events_checked: latest.len(),
+            under_replicated: repairs.len(),
+            republished,
+            republish_failures: repairs.len() - republished,
+            relays,
+        };
+        *self.redundancy_report.write().await = Some(report.clone());
+        Ok(report)
+    }
+
+    // Archive the full history of closed deliveries, republish their final state and delete
+    // superseded versions from relays
+    pub async fn compact_closed_deliveries(&self) -> Result<compaction::CompactionReport, Box<dyn std::error::Error>> {
+        let Some(archive_dir) = &self.compaction.archive_dir else {
+            return Err("COMPACTION_ARCHIVE_DIR is not set".into());
+        };
+
+        let now = Utc::now().timestamp();
+        let closed: Vec<DeliveryRequest> = self.indexed_deliveries().await?
+            .into_iter()
+            .filter(|d| compaction::is_closed(d, now, self.delete_grace_secs))
+            .filter(|d| !compaction::archive_path(archive_dir, &d.id).exists())
+            .collect();
+
+        let mut report = compaction::CompactionReport::default();
+        for chunk in closed.chunks(50) {
+            let ids: std::collections::HashSet<String> = chunk.iter().map(|d| d.id.clone()).collect();
+            let since = chunk.iter().map(|d| d.created_at).min().unwrap_or(0);
+            let filter = Filter::new()
+                .kinds(sync::DELIVERY_EVENT_KINDS.map(Kind::Custom))
+                .authors(self.authors.keys())
+                .since(Timestamp::from(since.max(0) as u64));
+            // Every relay, not just the primary, since each may hold its own old versions
+            let events = self.nostr_client.fetch_events(vec![filter], Some(Duration::from_secs(20))).await?;
+
+            for (delivery_id, events) in compaction::group_by_delivery(events) {
+                if !ids.contains(&delivery_id) {
+                    continue;
+                }
+
+                compaction::archive(archive_dir, &delivery_id, &events)?;
+                report.archived_events += events.len();
+
+                let (latest, superseded) = compaction::split_superseded(&events, &self.authors, &self.system_pubkey);
+                for event in latest {
+                    let id = event.id;
+                    match self.send_event(event).await {
+                        Ok(_) => report.republished += 1,
+                        Err(e) => log::warn!("Failed to republish {}: {}", id, e),
+                    }
+                }
+
+                if self.compaction.delete && !superseded.is_empty() {
+                    let count = superseded.len();
+                    let deletion = self.sign_event(EventBuilder::delete_with_reason(superseded, "superseded by a later version")).await?;
+                    let sent = if self.compaction.delete_relays.is_empty() {
+                        self.send_event(deletion).await
+                    } else {
+                        self.send_event_to(self.compaction.delete_relays.clone(), deletion).await
+                    };
+                    match sent {
+                        Ok(_) => report.deleted += count,
+                        Err(e) => log::warn!("Failed to delete superseded events of {}: {}", delivery_id, e),
+                    }
+                }
+
+                report.deliveries += 1;
+            }
+        }
+
+        Ok(report)
+    }
+
+    // A user's NIP-65 relay list, cached for OUTBOX_CACHE_SECS. Lookup failures aren't cached.
+    pub async fn relay_list(&self, pubkey: &PublicKey) -> outbox::RelayList {
+        let key = pubkey.to_hex();
+        let now = Utc::now().timestamp();
+        if let Some(list) = self.relay_lists.read().await.get(&key, now, self.outbox.cache_secs) {
+            return list.clone();
+        }
+
+        let filter = Filter::new().author(*pubkey).kind(Kind::RelayList).limit(1);
+        let list = match self.nostr_client.fetch_events(vec![filter], Some(Duration::from_secs(5))).await {
+            Ok(events) => events.first().map(outbox::RelayList::from_event).unwrap_or_default(),
+            Err(e) => {
+                log::warn!("Failed to fetch relay list for {}: {}", key, e);
+                return outbox::RelayList::default();
+            }
+        };
+        self.relay_lists.write().await.insert(key, list.clone(), now);
+        list
+    }
+
+    // Relays to publish an event concerning `participants` to: our write relays plus their read
+    // relays. None when the outbox model is off or adds nothing to the configured set.
+    async fn outbox_targets(&self, participants: &[&str]) -> Option<Vec<Url>> {
+        if !self.outbox.enabled {
+            return None;
+        }
+
+        let pool = self.nostr_client.pool();
+        let mut targets: Vec<Url> = pool.relays_with_flag(RelayServiceFlags::WRITE, FlagCheck::All).await.into_keys().collect();
+        let configured = targets.len();
+
+        for participant in participants {
+            let Ok(pubkey) = PubkeyId::parse(participant).map(PublicKey::from) else {
+                continue;
+            };
+            for url in self.relay_list(&pubkey).await.read.iter().take(self.outbox.max_relays_per_user) {
+                let Ok(url) = Url::parse(url) else {
+                    continue;
+                };
+                if targets.contains(&url) {
+                    continue;
+                }
+                match self.add_inbox_relay(&url).await {
+                    Ok(()) => targets.push(url),
+                    Err(e) => log::warn!("Failed to add relay {} for {}: {}", url, participant, e),
+                }
+            }
+        }
+
+        (targets.len() > configured).then_some(targets)
+    }
+
+    // Participants' relays join the pool as inbox relays: written to only when targeted, and not
+    // read from or counted towards readiness
+    async fn add_inbox_relay(&self, url: &Url) -> Result<(), Box<dyn std::error::Error>> {
+        let pool = self.nostr_client.pool();
+        if pool.relay(url).await.is_err() {
+            let mut opts = RelayOptions::new().flags(RelayServiceFlags::PING | RelayServiceFlags::INBOX);
+            if let Some(addr) = self.proxies.proxy_for(url.as_str()) {
+                opts = opts.connection_mode(ConnectionMode::proxy(addr));
+            }
+            pool.add_relay(url, opts).await?;
+        }
+        pool.connect_relay(url, Some(Duration::from_secs(5))).await?;
+        Ok(())
+    }
+
+    // Publish to the configured relays and the read relays of the users the event concerns
+    pub async fn send_to_participants(&self, event: Event, participants: &[&str]) -> Result<(), Box<dyn std::error::Error>> {
+        match self.outbox_targets(participants).await {
+            Some(targets) => self.send_event_to(targets, event).await?,
+            None => self.send_event(event).await?,
+        };
+        Ok(())
+    }
+
+    // NIP-17 DM, also delivered to the receiver's read relays
+    pub async fn send_dm(&self, receiver: PublicKey, message: String) -> Result<(), Box<dyn std::error::Error>> {
+        let result = match self.outbox_targets(&[&receiver.to_hex()]).await {
+            Some(targets) => self.nostr_client.send_private_msg_to(targets, receiver, message, None).await,
+            None => self.nostr_client.send_private_msg(receiver, message, None).await,
+        };
+        // DMs go to the receiver's relays as well; the quorum is for marketplace events
+        self.record_publish(None, Kind::GiftWrap.as_u16(), None, &result).await;
+        result?;
+        Ok(())
+    }
+
+    // Every event goes out through these, so each relay's answer is kept and counted. Relays
+    // are written to concurrently, and the publish fails unless a quorum of them accepted it.
+    pub async fn send_event(&self, event: Event) -> Result<Output<EventId>, Box<dyn std::error::Error>> {
+        let (id, kind, delivery_id) = (event.id, event.kind.as_u16(), query::delivery_id(&event).map(String::from));
+        let mirrored = self.postgres.is_some().then(|| event.clone());
+        let result = self.nostr_client.send_event(event).await;
+        let record = self.record_publish(Some(id), kind, delivery_id, &result).await;
+        let output = result?;
+        record.check_quorum(self.publish_quorum)?;
+        if let Some(event) = mirrored {
+            self.mirror_event(&event).await;
+        }
+        Ok(output)
+    }
+
+    pub async fn send_event_to<I, U>(&self, urls: I, event: Event) -> Result<Output<EventId>, Box<dyn std::error::Error>>
+    where
+        I: IntoIterator<Item = U>,
+        U: TryIntoUrl,
+        nostr_sdk::pool::pool::Error: From<<U as TryIntoUrl>::Err>,
+    {
+        let (id, kind, delivery_id) = (event.id, event.kind.as_u16(), query::delivery_id(&event).map(String::from));
+        let mirrored = self.postgres.is_some().then(|| event.clone());
+        let result = self.nostr_client.send_event_to(urls, event).await;
+        let record = self.record_publish(Some(id), kind, delivery_id, &result).await;
+        let output = result?;
+        record.check_quorum(self.publish_quorum)?;
+        if let Some(event) = mirrored {
+            self.mirror_event(&event).await;
+        }
+        Ok(output)
+    }
+
+    // Copy an accepted delivery, bid, status update or profile event into Postgres. The relays
+    // already hold it, so a failure here is only logged.
+    pub async fn mirror_event(&self, event: &Event) {
+        let Some(db) = &self.postgres else {
+            return;
+        };
+        let kind = event.kind.as_u16();
+        let result = match kind {
+            35000 => match self.parse_delivery_event(event).await {
+                Ok(delivery) => db.record_delivery(&delivery, event).await,
+                Err(e) => Err(e),
+            },
+            35001 => match (query::delivery_id(event), serde_json::from_str::<DeliveryBid>(&event.content)) {
+                (Some(delivery_id), Ok(bid)) => db.record_bid(delivery_id, &bid, event).await,
+                (None, _) => Err("bid without delivery_id".into()),
+                (_, Err(e)) => Err(e.into()),
+            },
+            35009 => match serde_json::from_str::<UserProfile>(&event.content) {
+                Ok(profile) => db.record_profile(&profile, event).await,
+                Err(e) => Err(e.into()),
+            },
+            _ if STATUS_UPDATE_KINDS.contains(&kind) => {
+                match (query::delivery_id(event), serde_json::from_str::<DeliveryUpdate>(&event.content)) {
+                    (Some(delivery_id), Ok(update)) => db.record_status_update(delivery_id, &update, event).await,
+                    (None, _) => Err("status update without delivery_id".into()),
+                    (_, Err(e)) => Err(e.into()),
+                }
+            }
+            _ => Ok(()),
+        };
+        if let Err(e) = result {
+            log::warn!("Failed to mirror kind {} event {} to Postgres: {}", kind, event.id, e);
+        }
+    }
+
+    // Mirror every delivery, bid, status update and profile event on the relays into Postgres,
+    // oldest first; rows only move forward, so this is safe to repeat
+    pub async fn backfill_postgres(&self) -> Result<usize, Box<dyn std::error::Error>> {
+        if self.postgres.is_none() {
+            return Ok(0);
+        }
+        let kinds = [35000, 35001, 35009].into_iter().chain(STATUS_UPDATE_KINDS).map(Kind::Custom);
+        let filter = Filter::new().kinds(kinds).authors(self.authors.keys());
+        let mut events: Vec<Event> = self.fetch_all_events(filter).await?
+            .into_iter()
+            .filter(|event| self.authors.trusts(event))
+            .collect();
+        events.sort_by_key(|event| event.created_at);
+        for event in &events {
+            self.mirror_event(event).await;
+        }
+        Ok(events.len())
+    }
+
+    async fn record_publish(
+        &self,
+        event_id: Option<EventId>,
+        kind: u16,
+        delivery_id: Option<String>,
+        result: &Result<Output<EventId>, nostr_sdk::client::Error>,
+    ) -> publish_log::PublishRecord {
+        let (acks, error) = match result {
+            Ok(output) => {
+                let mut acks: Vec<publish_log::RelayAck> = output.success
+                    .iter()
+                    .map(|url| publish_log::RelayAck { relay: url.to_string(), accepted: true, message: None })
+                    .chain(output.failed.iter().map(|(url, message)| publish_log::RelayAck {
+                        relay: url.to_string(),
+                        accepted: false,
+                        message: message.clone(),
+                    }))
+                    .collect();
+                acks.sort_by(|a, b| a.relay.cmp(&b.relay));
+                (acks, None)
+            }
+            Err(e) => (Vec::new(), Some(e.to_string())),
+        };
+        let event_id = result.as_ref().ok().map(|output| output.val).or(event_id);
+        if let Some(error) = &error {
+            log::warn!("Publishing kind {} event {} failed: {}", kind, event_id.map(|id| id.to_hex()).unwrap_or_default(), error);
+        }
+        let record = publish_log::PublishRecord {
+            event_id: event_id.map(|id| id.to_hex()),
+            kind,
+            delivery_id,
+            published_at: Utc::now().timestamp(),
+            acks,
+            error,
+        };
+        self.publish_log.write().await.record(record.clone());
+        record
+    }
+
+    // Time-critical courier messages go by push to the courier's devices, falling back to a DM
+    // when none is registered or reachable. The message is written in the courier's language.
+    pub async fn notify_courier(&self, courier: &str, message: impl Fn(i18n::Language) -> push::PushMessage) {
+        let npub = PubkeyId::normalize(courier);
+        let (devices, language) = match self.get_user_settings(&npub).await {
+            Ok(user_settings) => (user_settings.devices, user_settings.language.unwrap_or_default()),
+            Err(e) => {
+                log::warn!("Failed to load devices for {}: {}", courier, e);
+                (Vec::new(), i18n::Language::default())
+            }
+        };
+        let message = message(language);
+
+        let mut pushed = false;
+        let mut expired = Vec::new();
+        for device in devices.iter().filter(|d| self.push.supports(d.platform)) {
+            match self.push.send(device, &message).await {
+                Ok(push::PushOutcome::Sent) => pushed = true,
+                Ok(push::PushOutcome::Expired) => expired.push(device.id.clone()),
+                Err(e) => log::warn!("Push to {} device {} failed: {}", courier, device.id, e),
+            }
+        }
+        for id in expired {
+            if let Err(e) = self.remove_push_device(&npub, &id).await {
+                log::warn!("Failed to forget expired device {} of {}: {}", id, courier, e);
+            }
+        }
+        if pushed {
+            return;
+        }
+
+        let Ok(pubkey) = PubkeyId::parse(courier).map(PublicKey::from) else {
+            return;
+        };
+        if let Err(e) = self.send_dm(pubkey, message.text()).await {
+            log::warn!("Failed to notify courier {}: {}", courier, e);
+        }
+    }
+
+    // Register a device, replacing any earlier registration of the same token
+    pub async fn add_push_device(&self, npub: &str, device: settings::PushDevice) -> Result<(), Box<dyn std::error::Error>> {
+        let npub = PubkeyId::normalize(npub);
+        let mut user_settings = self.get_user_settings(&npub).await?;
+        user_settings.devices.retain(|d| d.id != device.id);
+        user_settings.devices.push(device);
+        self.publish_user_settings(&npub, &user_settings).await
+    }
+
+    // False when no device has this ID
+    pub async fn remove_push_device(&self, npub: &str, device_id: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let npub = PubkeyId::normalize(npub);
+        let mut user_settings = self.get_user_settings(&npub).await?;
+        let count = user_settings.devices.len();
+        user_settings.devices.retain(|d| d.id != device_id);
+        if user_settings.devices.len() == count {
+            return Ok(false);
+        }
+        self.publish_user_settings(&npub, &user_settings).await?;
+        Ok(true)
+    }
+
+    // False when the address book is full
+    pub async fn add_saved_address(&self, npub: &str, address: settings::SavedAddress) -> Result<bool, Box<dyn std::error::Error>> {
+        let npub = PubkeyId::normalize(npub);
+        let mut user_settings = self.get_user_settings(&npub).await?;
+        if user_settings.addresses.len() >= settings::MAX_SAVED_ADDRESSES {
+            return Ok(false);
+        }
+        user_settings.addresses.push(address);
+        self.publish_user_settings(&npub, &user_settings).await?;
+        Ok(true)
+    }
+
+    // False when no saved address has this ID
+    pub async fn remove_saved_address(&self, npub: &str, address_id: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let npub = PubkeyId::normalize(npub);
+        let mut user_settings = self.get_user_settings(&npub).await?;
+        let count = user_settings.addresses.len();
+        user_settings.addresses.retain(|a| a.id != address_id);
+        if user_settings.addresses.len() == count {
+            return Ok(false);
+        }
+        self.publish_user_settings(&npub, &user_settings).await?;
+        Ok(true)
+    }
+
+    // Per-dependency readiness: relay connections, indexer freshness and the payment provider
+    pub async fn readiness(&self) -> health::Readiness {
+        let mut relays = Vec::new();
+        for relay in self.nostr_client.relays().await.values() {
+            relays.push(health::RelayHealth {
+                connected: relay.status() == RelayStatus::Connected,
+                proxied: !matches!(relay.connection_mode(), ConnectionMode::Direct),
+                latency_ms: relay.stats().latency().await.map(|l| l.as_millis() as u64),
+            });
+        }
+
+        let mut checks = vec![
+            self.readiness.relay_check(&relays),
+            self.readiness.index_check(self.index.read().await.indexed_at, Utc::now().timestamp()),
+        ];
+        if let Some(check) = self.readiness.payment_check().await {
+            checks.push(check);
+        }
+
+        health::Readiness::from_checks(checks)
+    }
+
+    // Record a queue item for a senior operator; re-escalating replaces the note
+    pub async fn escalate(&self, item: admin::QueueItem, id: &str, note: Option<String>) -> admin::Escalation {
+        log::warn!("Escalated {:?} {}: {}", item, id, note.as_deref().unwrap_or("-"));
+        let escalation = admin::Escalation {
+            item,
+            id: id.to_string(),
+            note,
+            escalated_at: Utc::now().timestamp(),
+        };
+        let mut escalations = self.escalations.write().await;
+        escalations.retain(|e| !(e.item == escalation.item && e.id == escalation.id));
+        escalations.push(escalation.clone());
+        escalation
+    }
+
+    pub async fn clear_escalation(&self, item: admin::QueueItem, id: &str) {
+        self.escalations.write().await.retain(|e| !(e.item == item && e.id == id));
+    }
+
+    // Run review text through the moderation filters; flagged text is held and None returned
+    pub async fn moderate_review(&self, delivery_id: &str, author: &str, text: &str) -> Option<String> {
+        let (text, reason) = match self.review_filter.review(text).await {
+            Ok(moderation::ReviewDecision::Publish(text)) => return Some(text),
+            Ok(moderation::ReviewDecision::Hold { text, reason }) => (text, reason),
+            Err(e) => {
+                log::warn!("Review moderation failed for {}: {}", delivery_id, e);
+                (text.to_string(), "moderation unavailable".to_string())
+            }
+        };
+
+        self.held_reviews.write().await.push(moderation::HeldReview {
+            id: unique_timestamp_id("review"),
+            delivery_id: delivery_id.to_string(),
+            author: author.to_string(),
+            text,
+            reason,
+            held_at: Utc::now().timestamp(),
+        });
+        if let Err(e) = self.save_held_reviews().await {
+            log::warn!("Failed to store held review for {}: {}", delivery_id, e);
+        }
+        None
+    }
+
+    // Pubkeys blocked through the admin API before the last restart
+    pub async fn load_blocklist(&self) -> Result<usize, Box<dyn std::error::Error>> {
+        let filter = Filter::new()
+            .kind(Kind::ApplicationSpecificData)
+            .author(self.system_pubkey)
+            .identifier(admin::BLOCKLIST_IDENTIFIER);
+        let events = self.fetch_marketplace_events(filter, Duration::from_secs(5)).await?;
+
+        let entries: Vec<admin::BlockedPubkey> = match query::latest_per_identifier(events, &self.authors).pop() {
+            Some(event) => serde_json::from_str(&self.decrypt_from_self(&event.content).await?)?,
+            None => Vec::new(),
+        };
+        let mut blocklist = self.blocklist.write().await;
+        blocklist.restore(entries);
+        Ok(blocklist.entries().len())
+    }
+
+    // Replace the stored blocklist with the current one, NIP-44 encrypted like API keys
+    pub async fn save_blocklist(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let entries = self.blocklist.read().await.entries().to_vec();
+        let content = self.encrypt_to_self(&serde_json::to_string(&entries)?).await?;
+        let tags = vec![Tag::identifier(admin::BLOCKLIST_IDENTIFIER)];
+
+        let event = self.sign_event(EventBuilder::new(Kind::ApplicationSpecificData, content, tags)).await?;
+        self.send_event(event).await?;
+        Ok(())
+    }
+
+    // Reviews held before the last restart
+    pub async fn load_held_reviews(&self) -> Result<usize, Box<dyn std::error::Error>> {
+        let filter = Filter::new()
+            .kind(Kind::ApplicationSpecificData)
+            .author(self.system_pubkey)
+            .identifier(moderation::HELD_REVIEWS_IDENTIFIER);
+        let events = self.fetch_marketplace_events(filter, Duration::from_secs(5)).await?;
+
+        let held: Vec<moderation::HeldReview> = match query::latest_per_identifier(events, &self.authors).pop() {
+            Some(event) => serde_json::from_str(&self.decrypt_from_self(&event.content).await?)?,
+            None => Vec::new(),
+        };
+        let count = held.len();
+        *self.held_reviews.write().await = held;
+        Ok(count)
+    }
+
+    // Replace the stored held reviews with the current ones, NIP-44 encrypted like API keys
+    pub async fn save_held_reviews(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let held = self.held_reviews.read().await.clone();
+        let content = self.encrypt_to_self(&serde_json::to_string(&held)?).await?;
+        let tags = vec![Tag::identifier(moderation::HELD_REVIEWS_IDENTIFIER)];
+
+        let event = self.sign_event(EventBuilder::new(Kind::ApplicationSpecificData, content, tags)).await?;
+        self.send_event(event).await?;
+        Ok(())
+    }
+
+    // Store a federated marketplace's delivery event, keeping the newest per author and d-tag
+    pub async fn store_federated_event(&self, event: &Event) {
+        if !self.federation.is_federated(&event.pubkey) || !self.federation.kinds.contains(&event.kind) {
+            return;
+        }
+        let Some(delivery) = federation::normalize_event(event) else {
+            log::debug!("Skipping unparseable federated event {}", event.id);
+            return;
+        };
+
+        let mut federated = self.federated.write().await;
+        let key = federation::cache_key(event);
+        let is_newer = federated.get(&key).is_none_or(|existing| existing.created_at <= delivery.created_at);
+        if is_newer {
+            federated.insert(key, delivery);
+        }
+    }
+
+    // Backfill federated deliveries, then follow new ones through a live subscription
+    pub async fn run_federation(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut notifications = self.nostr_client.notifications();
+
+        let events = self.nostr_client.fetch_events(vec![self.federation.filter()], Some(Duration::from_secs(10))).await?;
+        for event in events.iter() {
+            self.store_federated_event(event).await;
+        }
+
+        self.nostr_client.subscribe(vec![self.federation.filter()], None).await?;
+        println!("🌐 Federating deliveries from {} marketplaces", self.federation.marketplaces.len());
+
+        loop {
+            match notifications.recv().await {
+                Ok(RelayPoolNotification::Event { event, .. }) => self.store_federated_event(&event).await,
+                Ok(_) => {}
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
+                    log::warn!("Federation subscription lagged, skipped {} notifications", skipped);
+                }
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => return Ok(()),
+            }
+        }
+    }
+
+    // Read-only lookup of a mirrored delivery by ID
+    pub async fn get_federated_delivery(&self, id: &str) -> Option<DeliveryRequest> {
+        self.federated.read().await.values().find(|d| d.id == id).cloned()
+    }
+
+    // Federated deliveries currently mirrored
+    pub async fn federated_deliveries(&self) -> Vec<DeliveryRequest> {
+        self.federated.read().await.values().cloned().collect()
+    }
+
+    // Sign an event with the system signer, reconnecting a remote signer once on failure or timeout
+    pub async fn sign_event(&self, builder: EventBuilder) -> Result<Event, Box<dyn std::error::Error>> {
+        // Events published while serving a request carry its ID for support correlation
+        let request_id = request_id::current();
+        let builder = match &request_id {
+            Some(id) => builder.add_tags([Tag::custom(TagKind::Custom("request_id".into()), vec![id.clone()])]),
+            None => builder,
+        };
+
+        let first_attempt = tokio::time::timeout(
+            self.signing_timeout,
+            self.nostr_client.sign_event_builder(builder.clone()),
+        ).await;
+
+        let error: Box<dyn std::error::Error> = match first_attempt {
+            Ok(Ok(event)) => {
+                log_signed(&event, request_id.as_deref());
+                return Ok(event);
+            }
+            Ok(Err(e)) => e.into(),
+            Err(e) => e.into(),
+        };
+
+        let Some(bunker) = &self.bunker else {
+            return Err(error);
+        };
+
+        log::warn!("Remote signer request failed ({}), reconnecting", error);
+        self.nostr_client.set_signer(bunker.connect()?).await;
+
+        let event = tokio::time::timeout(
+            self.signing_timeout,
+            self.nostr_client.sign_event_builder(builder),
+        ).await??;
+
+        log_signed(&event, request_id.as_deref());
+        Ok(event)
+    }
+
+    // Sign with an organization's own key, so its events are attributable to it; events of
+    // deliveries without an organization are signed by the system signer
+    pub async fn sign_event_for(&self, builder: EventBuilder, organization: Option<&str>) -> Result<Event, Box<dyn std::error::Error>> {
+        let Some(org_id) = organization else {
+            return self.sign_event(builder).await;
+        };
+        let keys = self.org_signing_keys(org_id).await?;
+
+        let request_id = request_id::current();
+        let mut tags = vec![Tag::custom(TagKind::Custom("organization".into()), vec![org_id.to_string()])];
+        if let Some(id) = &request_id {
+            tags.push(Tag::custom(TagKind::Custom("request_id".into()), vec![id.clone()]));
+        }
+
+        let event = builder.add_tags(tags).sign_with_keys(&keys)?;
+        log_signed(&event, request_id.as_deref());
+        Ok(event)
+    }
+
+    // Sign an event published on a user's behalf, tagged with their NIP-26 delegation when they
+    // have one whose conditions allow it
+    pub async fn sign_event_as(&self, npub: &str, kind: u16, content: String, mut tags: Vec<Tag>) -> Result<Event, Box<dyn std::error::Error>> {
+        let created_at = Timestamp::now();
+        if let Some(delegation) = self.user_delegation(npub).await? {
+            tags.extend(delegation.tag(&self.system_pubkey, kind, created_at.as_u64()));
+        }
+        self.sign_event(EventBuilder::new(Kind::Custom(kind), content, tags).custom_created_at(created_at)).await
+    }
+
+    pub async fn user_delegation(&self, npub: &str) -> Result<Option<delegation::Delegation>, Box<dyn std::error::Error>> {
+        // Only real pubkeys can delegate
+        if PubkeyId::parse(npub).is_err() {
+            return Ok(None);
+        }
+        let npub = PubkeyId::normalize(npub);
+        if let Some(cached) = self.delegations.read().await.get(&npub) {
+            return Ok(cached.clone());
+        }
+
+        let delegation = self.get_user_settings(&npub).await?.delegation;
+        self.delegations.write().await.insert(npub, delegation.clone());
+        Ok(delegation)
+    }
+
+    // Store a verified delegation (or remove it) in the user's settings
+    pub async fn set_user_delegation(&self, npub: &str, delegation: Option<delegation::Delegation>) -> Result<(), Box<dyn std::error::Error>> {
+        let npub = PubkeyId::normalize(npub);
+        let mut user_settings = self.get_user_settings(&npub).await?;
+        user_settings.delegation = delegation.clone();
+        self.publish_user_settings(&npub, &user_settings).await?;
+        self.delegations.write().await.insert(npub, delegation);
+        Ok(())
+    }
+
+    // An organization's signing key, issuing one on first use. Keys are stored on relays
+    // NIP-44-encrypted to the system key, so only the marketplace can load them.
+    pub async fn org_signing_keys(&self, org_id: &str) -> Result<Keys, Box<dyn std::error::Error>> {
+        if let Some(keys) = self.org_keys.read().await.get(org_id) {
+            return Ok(keys.clone());
+        }
+
+        let filter = Filter::new()
+            .kind(Kind::Custom(organizations::ORG_KEY_KIND))
+            .authors(self.authors.keys())
+            .identifier(org_id);
+        let events = self.fetch_marketplace_events(filter, Duration::from_secs(5)).await?;
+
+        let keys = match query::latest_per_identifier(events, &self.authors).pop() {
+            Some(event) => Keys::parse(&self.decrypt_from_self(&event.content).await?)?,
+            None => {
+                let keys = Keys::generate();
+                let tags = vec![
+                    Tag::identifier(org_id.to_string()),
+                    Tag::public_key(keys.public_key()),
+                    Tag::custom(TagKind::Custom("encrypted".into()), vec!["nip44".to_string()]),
+                ];
+                let content = self.encrypt_to_self(&keys.secret_key().to_secret_hex()).await?;
+                let event = self.sign_event(EventBuilder::new(Kind::Custom(organizations::ORG_KEY_KIND), content, tags)).await?;
+                self.send_event(event).await?;
+                keys
+            }
+        };
+
+        self.authors.trust_organization_key(keys.public_key(), org_id);
+        self.org_keys.write().await.insert(org_id.to_string(), keys.clone());
+        Ok(keys)
+    }
+
+    // Public tracking page for a delivery
+    pub fn tracking_url(&self, delivery_id: &str) -> String {
+        format!("{}/track/{}", self.frontend_url, delivery_id)
+    }
+
+    // Token in the recipient's tracking link that lets them confirm receipt
+    pub fn recipient_token(&self, delivery_id: &str) -> String {
+        codes::derive_token(&self.code_secret, "tracking", delivery_id)
+    }
+
+    // Tracking page link sent to the recipient
+    pub fn recipient_tracking_url(&self, delivery_id: &str) -> String {
+        format!("{}?token={}", self.tracking_url(delivery_id), self.recipient_token(delivery_id))
+    }
+
+    // Six-digit code the recipient uses to confirm the handoff at dropoff
+    pub fn dropoff_code(&self, delivery_id: &str) -> String {
+        codes::derive_code(&self.code_secret, "dropoff", delivery_id, 6)
+    }
+
+    // Six-digit code printed on the shipping label, which the courier reads off the package at pickup
+    pub fn pickup_code(&self, delivery_id: &str) -> String {
+        codes::derive_code(&self.code_secret, "pickup", delivery_id, 6)
+    }
+
+    // Token committing to quoted delivery terms until `expires_at`
+    pub fn seal_quote<T: serde::Serialize>(&self, terms: &T, expires_at: i64) -> Result<String, serde_json::Error> {
+        quotes::seal(&self.code_secret, terms, expires_at)
+    }
+
+    pub fn open_quote<T: serde::de::DeserializeOwned>(&self, token: &str) -> Result<T, quotes::QuoteError> {
+        quotes::open(&self.code_secret, token, Utc::now().timestamp())
+    }
+
+    // Link encoded in the dropoff QR code; opening it confirms receipt
+    pub fn dropoff_confirm_url(&self, delivery_id: &str) -> String {
+        format!("{}/confirm/{}?code={}", self.frontend_url, delivery_id, self.dropoff_code(delivery_id))
+    }
+
+    // Fill in missing coordinates using the configured geocoder (no-op when none is configured)
+    pub async fn geocode_location(&self, location: &mut Location) -> Result<(), String> {
+        let Some(geocoder) = &self.geocoder else {
+            return Ok(());
+        };
+        if location.coordinates.is_some() {
+            return Ok(());
+        }
+
+        match geocoder.geocode(&location.address).await {
+            Ok(Some(point)) => {
+                location.coordinates = Some(point);
+                Ok(())
+            }
+            Ok(None) => Err(format!("could not geocode address '{}'", location.address)),
+            Err(e) => Err(format!("geocoding failed: {}", e)),
+        }
+    }
+
+    // Fill in missing coordinates from a stop's what3words address or plus code, normalizing the
+    // code that's echoed back. A stop without a street address shows the code instead.
+    pub async fn resolve_location_code(&self, location: &mut Location) -> Result<(), String> {
+        let (code, resolver) = if let Some(raw) = &location.what3words {
+            let words = location_codes::normalize_what3words(raw)
+                .ok_or_else(|| format!("'{}' is not a what3words address", raw))?;
+            location.what3words = Some(words.clone());
+            (words, self.what3words.as_ref())
+        } else if let Some(raw) = &location.plus_code {
+            let plus_code = location_codes::PlusCode::parse(raw)
+                .ok_or_else(|| format!("'{}' is not a valid plus code", raw))?;
+            location.plus_code = Some(plus_code.to_string());
+            (plus_code.to_string(), Some(&self.plus_codes))
+        } else {
+            return Ok(());
+        };
+
+        if location.address.trim().is_empty() {
+            location.address = match &location.what3words {
+                Some(words) => format!("///{}", words),
+                None => code.clone(),
+            };
+        }
+        if location.coordinates.is_some() {
+            return Ok(());
+        }
+
+        let Some(resolver) = resolver else {
+            return Err(format!("what3words address '{}' can't be resolved: no W3W_API_KEY is configured", code));
+        };
+        match resolver.resolve(&code).await {
+            Ok(Some(point)) => {
+                location.coordinates = Some(point);
+                Ok(())
+            }
+            Ok(None) => Err(format!("could not resolve '{}'", code)),
+            Err(e) => Err(format!("resolving '{}' failed: {}", code, e)),
+        }
+    }
+
+    // Turn what3words addresses and plus codes into coordinates, then update the distance
+    pub async fn resolve_stops(&self, delivery: &mut DeliveryRequest) -> Option<String> {
+        for (stop, location) in [("pickup", &mut delivery.pickup), ("dropoff", &mut delivery.dropoff)] {
+            if let Err(e) = self.resolve_location_code(location).await {
+                return Some(format!("{} {}", stop, e));
+            }
+        }
+        if let (Some(p1), Some(p2)) = (&delivery.pickup.coordinates, &delivery.dropoff.coordinates) {
+            delivery.distance_meters = Some(calculate_distance(p1, p2));
+        }
+        None
+    }
+
+    // Why a new delivery is refused, if it is: the checks every way of creating one goes through
+    pub async fn new_delivery_problem(&self, delivery: &mut DeliveryRequest) -> Result<Option<problem::Problem>, Box<dyn std::error::Error>> {
+        use problem::{Problem, ProblemType};
+
+        // Reputation and history attach to the sender, so it has to be a real key; stored as an npub
+        match PubkeyId::parse(&delivery.sender) {
+            Ok(sender) => delivery.sender = sender.npub(),
+            Err(e) => return Ok(Some(Problem::new(ProblemType::InvalidRequest, format!("Invalid sender: {}", e)))),
+        }
+
+        if self.blocklist.read().await.contains(&delivery.sender) {
+            return Ok(Some(Problem::new(ProblemType::Forbidden, "Sender is blocked from this marketplace")));
+        }
+
+        if let Some(error) = delivery.allowlist_error() {
+            return Ok(Some(Problem::new(ProblemType::InvalidRequest, error)));
+        }
+
+        if let Some(error) = timezone::schedule_error(delivery, Utc::now().timestamp()) {
+            return Ok(Some(Problem::new(ProblemType::InvalidRequest, error)));
+        }
+
+        for (stop, location) in [("pickup", &delivery.pickup), ("dropoff", &delivery.dropoff)] {
+            if let Some(phone) = location.contact.as_ref().and_then(|c| c.phone.as_deref()).filter(|p| !notify::is_valid_phone(p)) {
+                return Ok(Some(Problem::new(ProblemType::InvalidRequest, format!("The {} contact's phone '{}' is not a valid phone number", stop, phone))));
+            }
+        }
+
+        if let Some(error) = self.resolve_stops(delivery).await {
+            return Ok(Some(Problem::new(ProblemType::InvalidRequest, error)));
+        }
+        for (stop, location) in [("pickup", &delivery.pickup), ("dropoff", &delivery.dropoff)] {
+            if location.address.trim().is_empty() && location.coordinates.is_none() {
+                return Ok(Some(Problem::new(ProblemType::InvalidRequest, format!("The {} needs an address, coordinates or a saved address ID", stop))));
+            }
+        }
+
+        if delivery.package_photos.len() > uploads::MAX_PACKAGE_PHOTOS {
+            return Ok(Some(Problem::new(ProblemType::InvalidRequest, format!("At most {} package photos can be attached", uploads::MAX_PACKAGE_PHOTOS))));
+        }
+        for photo in &mut delivery.package_photos {
+            if !self.uploads.exists(&photo.sha256).await.map_err(|e| e.to_string())? {
+                return Ok(Some(Problem::new(ProblemType::InvalidRequest, format!("Package photo '{}' has not been uploaded", photo.sha256))));
+            }
+            photo.url = self.uploads.url(&photo.sha256);
+        }
+
+        // Deliveries attributed to an organization must come from a member allowed to create them
+        if let Some(org_id) = &delivery.organization {
+            let error = match self.get_organization(org_id).await? {
+                None => Some("Organization not found"),
+                Some(org) if !org.allows(&delivery.sender, rbac::Permission::CreateDeliveries) => {
+                    Some("Sender may not create deliveries for this organization")
+                }
+                Some(_) => None,
+            };
+            if let Some(error) = error {
+                return Ok(Some(Problem::new(ProblemType::Forbidden, error)));
+            }
+        }
+
+        if let Err(error) = self.check_service_area(delivery).await {
+            return Ok(Some(Problem::new(ProblemType::InvalidRequest, error)));
+        }
+
+        delivery.local_times = Some(timezone::LocalTimes::for_delivery(delivery));
+        Ok(None)
+    }
+
+    // Rebuild the indexed snapshot and aggregate stats from relays
+    pub async fn refresh_index(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let started = std::time::Instant::now();
+        let mut deliveries = self.get_all_deliveries().await?;
+        if self.native_deliveries {
+            if let Err(e) = self.ingest_native_deliveries(&mut deliveries).await {
+                log::warn!("Ingesting sender-signed deliveries failed: {}", e);
+            }
+        }
+        if self.native_bids {
+            if let Err(e) = self.ingest_native_bids(&mut deliveries).await {
+                log::warn!("Ingesting courier-signed bids failed: {}", e);
+            }
+        }
+        let participants = self.get_leaderboard_participants().await?;
+        let now = Utc::now().timestamp();
+        let market_stats = analytics::MarketStats::compute(&deliveries, now);
+        let leaderboards = analytics::Leaderboards::compute(&deliveries, &participants, now);
+
+        for delivery in &deliveries {
+            self.cache.deliveries.fill(&delivery.id, delivery.clone(), started).await;
+        }
+
+        let mut index = self.index.write().await;
+        let mut changes = std::mem::take(&mut index.changes);
+        changes.update(&deliveries, now);
+        *index = DeliveryIndex {
+            deliveries,
+            market_stats,
+            leaderboards,
+            changes,
+            indexed_at: now,
+        };
+
+        Ok(())
+    }
+
+    // List deliveries senders signed and published to relays themselves. Each is checked like a
+    // delivery created through the API, then republished under the marketplace key so bids,
+    // acceptance and the rest of its lifecycle work as for any other delivery.
+    pub async fn ingest_native_deliveries(&self, deliveries: &mut Vec<DeliveryRequest>) -> Result<usize, Box<dyn std::error::Error>> {
+        let filter = Filter::new()
+            .kind(Kind::Custom(native_deliveries::DELIVERY_KIND))
+            .custom_tag(SingleLetterTag::uppercase(Alphabet::L), [protocol::NAMESPACE])
+            .limit(1000);
+        let events = self.nostr_client.fetch_events(vec![filter], Some(Duration::from_secs(5))).await?;
+
+        let now = Utc::now().timestamp();
+        let mut listed = 0;
+        for event in query::latest_per_author(events.into_iter().filter(|e| !self.authors.trusts(e))) {
+            let event_id = event.id.to_hex();
+            if self.native_deliveries_seen.read().await.contains(&event_id) {
+                continue;
+            }
+            self.native_deliveries_seen.write().await.insert(event_id.clone());
+
+            match self.list_native_delivery(&event, deliveries, now).await {
+                Ok(Some(delivery)) => {
+                    log::info!("Listed sender-signed delivery {} from {}", delivery.id, delivery.sender);
+                    deliveries.push(delivery);
+                    listed += 1;
+                }
+                Ok(None) => {}
+                Err(reason) => log::info!("Rejected delivery event {}: {}", event_id, reason),
+            }
+        }
+        Ok(listed)
+    }
+
+    // None when the delivery is already listed; later changes go through the API
+    async fn list_native_delivery(&self, event: &Event, deliveries: &[DeliveryRequest], now: i64) -> Result<Option<DeliveryRequest>, String> {
+        let mut delivery = native_deliveries::parse(event, now)?;
+        // Any delivery ever published under the ID counts, not only the indexed ones, so the
+        // republish can't replace an older, compacted or deleted delivery
+        let existing = match deliveries.iter().find(|d| d.id == delivery.id) {
+            Some(existing) => Some(existing.clone()),
+            None => self.get_delivery_by_id(&delivery.id).await
+                .map_err(|e| format!("could not check delivery ID {}: {}", delivery.id, e))?,
+        };
+        if let Some(existing) = existing {
+            if existing.sender != delivery.sender {
+                return Err(format!("delivery ID {} belongs to another sender", delivery.id));
+            }
+            return Ok(None);
+        }
+        if let Some(problem) = self.new_delivery_problem(&mut delivery).await.map_err(|e| e.to_string())? {
+            return Err(problem.detail);
+        }
+
+        self.delivery_service().create(&delivery).await.map_err(|e| e.to_string())?;
+        Ok(Some(delivery))
+    }
+
+    // Merge bids couriers signed and published to relays themselves. Each is checked like a bid
+    // placed through the API, then republished as a marketplace bid so it is read like one.
+    pub async fn ingest_native_bids(&self, deliveries: &mut [DeliveryRequest]) -> Result<usize, Box<dyn std::error::Error>> {
+        let mut filter = Filter::new()
+            .kind(Kind::Custom(native_bids::BID_KIND))
+            .custom_tag(SingleLetterTag::uppercase(Alphabet::L), [protocol::NAMESPACE]);
+        if let Some(synced) = *self.native_bids_synced.read().await {
+            filter = filter.since(Timestamp::from((synced - projection::SYNC_OVERLAP_SECS).max(0) as u64));
+        }
+        let events = self.fetch_all_events(filter).await?;
+
+        let now = Utc::now().timestamp();
+        // Bids on deliveries not indexed yet are fetched again next pass, while they're recent
+        let mut synced = now;
+        let mut merged = 0;
+        for event in query::latest_per_author(events.into_iter().filter(|e| !self.authors.trusts(e))) {
+            let event_id = event.id.to_hex();
+            let created_at = event.created_at.as_u64() as i64;
+            if self.native_bids_seen.read().await.contains_key(&event_id) {
+                continue;
+            }
+            let native = match native_bids::NativeBid::parse(&event) {
+                Ok(native) => native,
+                Err(e) => {
+                    log::info!("Ignoring bid event {}: {}", event_id, e);
+                    self.native_bids_seen.write().await.insert(event_id, created_at);
+                    continue;
+                }
+            };
+            // Unknown deliveries may just not be indexed yet, but a bid on one that never shows up
+            // mustn't hold the cursor back for good
+            let Some(index) = deliveries.iter().position(|d| d.id == native.delivery_id) else {
+                if now - created_at < native_bids::UNKNOWN_DELIVERY_RETRY_SECS {
+                    synced = synced.min(created_at);
+                } else {
+                    log::info!("Dropping bid event {}: delivery {} is unknown", event_id, native.delivery_id);
+                    self.native_bids_seen.write().await.insert(event_id, created_at);
+                }
+                continue;
+            };
+
+            let mut delivery = deliveries[index].clone();
+            match self.merge_native_bid(&mut delivery, native.bid, deliveries, now).await {
+                Ok(()) => merged += 1,
+                Err(reason) => log::info!("Rejected bid event {} on {}: {}", event_id, delivery.id, reason),
+            }
+            deliveries[index] = delivery;
+            self.native_bids_seen.write().await.insert(event_id, created_at);
+        }
+        *self.native_bids_synced.write().await = Some(synced);
+        // Events before the next pass's `since` aren't fetched again
+        let refetched_from = synced - projection::SYNC_OVERLAP_SECS;
+        self.native_bids_seen.write().await.retain(|_, created_at| *created_at >= refetched_from);
+        Ok(merged)
+    }
+
+    // `snapshot` stands in for the index, which this pass is still building
+    async fn merge_native_bid(&self, delivery: &mut DeliveryRequest, mut bid: DeliveryBid, snapshot: &[DeliveryRequest], now: i64) -> Result<(), String> {
+        if native_bids::already_merged(&delivery.bids, &bid) {
+            return Ok(());
+        }
+        if delivery.bids.iter().any(|b| b.id == bid.id && b.courier != bid.courier) {
+            return Err(format!("bid ID {} belongs to another courier", bid.id));
+        }
+        if delivery.status != DeliveryStatus::Open {
+            return Err(format!("delivery is {:?}, not open for bids", delivery.status));
+        }
+
+        let mut profile = self.get_user_profile(&bid.courier).await.unwrap_or_default();
+        let ratings = RatingSummary::courier_ratings(&bid.courier, snapshot);
+        if !ratings.is_empty() {
+            profile.apply_rating_summary(&RatingSummary::from_ratings(&ratings, now));
+        }
+        let etas = (bid.pickup_eta.unwrap_or(now), bid.dropoff_eta.unwrap_or(now));
+        if let Some(problem) = self.bid_rejection(delivery, &bid.courier, &profile, etas, now).await.map_err(|e| e.to_string())? {
+            return Err(problem.detail);
+        }
+        let conflicts = commitments::conflicts(&bid.courier, snapshot, etas);
+        if let Some(problem) = service::conflict_error(&conflicts, self.bid_conflict_mode) {
+            return Err(problem.detail);
+        }
+
+        service::apply_profile(&mut bid, &profile);
+        self.publish_bid(delivery, &bid).await.map_err(|e| e.to_string())?;
+
+        delivery.bids.retain(|b| b.id != bid.id);
+        delivery.bids.push(bid);
+        delivery.bids.sort_by_key(|b| b.created_at);
+        Ok(())
+    }
+
+    // The delivery lifecycle rules, run against the relays or, when configured, the Postgres mirror
+    pub fn delivery_service(&self) -> service::DeliveryService<'_> {
+        match &self.postgres {
+            Some(db) => service::DeliveryService::new(postgres::PostgresStore::new(self, db), self.bid_conflict_mode),
+            None => service::DeliveryService::new(self, self.bid_conflict_mode),
+        }
+    }
+
+    // Why `courier` may not bid on the delivery with these ETAs, if they may not. Bids placed
+    // through the API and courier-signed bids from relays go through the same checks.
+    pub async fn bid_rejection(
+        &self,
+        delivery: &DeliveryRequest,
+        courier: &str,
+        profile: &UserProfile,
+        (pickup_eta, dropoff_eta): (i64, i64),
+        now: i64,
+    ) -> Result<Option<problem::Problem>, Box<dyn std::error::Error>> {
+        use problem::{Problem, ProblemType};
+
+        if delivery.status == DeliveryStatus::Draft {
+            return Ok(Some(Problem::new(ProblemType::InvalidTransition, "Delivery is a draft and not open for bids yet")));
+        }
+        if self.blocklist.read().await.contains(courier) {
+            return Ok(Some(Problem::new(ProblemType::Forbidden, "Courier is blocked from this marketplace")));
+        }
+        if !delivery.allows_courier(courier) {
+            return Ok(Some(Problem::new(ProblemType::Forbidden, "This delivery is restricted to an allowlist of couriers")));
+        }
+        let blocked = self.get_courier_list(&delivery.sender, courier_lists::CourierListKind::Blocked).await?;
+        if blocked.contains(&PubkeyId::normalize(courier)) {
+            return Ok(Some(Problem::new(ProblemType::Forbidden, "The sender does not accept bids from this courier")));
+        }
+        if let Some(reason) = delivery.courier_requirement_error(profile) {
+            return Ok(Some(Problem::new(ProblemType::Forbidden, reason)));
+        }
+        if let Some(error) = timezone::eta_error(pickup_eta, dropoff_eta, &TimeWindow::parse(&delivery.time_window), now) {
+            return Ok(Some(Problem::new(ProblemType::InvalidRequest, error)));
+        }
+        Ok(None)
+    }
+
+    // Deliveries from the indexed snapshot, refreshing on demand before the first indexer pass
+    // The courier's accepted deliveries that a bid spanning `span` would clash with
+    pub async fn commitment_conflicts(&self, courier: &str, span: (i64, i64)) -> Result<Vec<commitments::Conflict>, Box<dyn std::error::Error>> {
+        Ok(commitments::conflicts(courier, &self.indexed_deliveries().await?, span))
+    }
+
+    pub async fn indexed_deliveries(&self) -> Result<Vec<DeliveryRequest>, Box<dyn std::error::Error>> {
+        if self.index.read().await.indexed_at == 0 {
+            self.refresh_index().await?;
+        }
+        Ok(self.index.read().await.deliveries.clone())
+    }
+
+    // Recompute a courier's decayed reputation and 90-day rating from delivery history
+    pub async fn refresh_reputation(&self, profile: &mut UserProfile) {
+        let Ok(deliveries) = self.indexed_deliveries().await else {
+            return;
+        };
+        let ratings = RatingSummary::courier_ratings(&profile.npub, &deliveries);
+        if !ratings.is_empty() {
+            profile.apply_rating_summary(&RatingSummary::from_ratings(&ratings, Utc::now().timestamp()));
+        }
+    }
+
+    // Reject or flag deliveries whose pickup or dropoff lies outside the service area
+    pub async fn check_service_area(&self, delivery: &mut DeliveryRequest) -> Result<(), String> {
+        let area = self.service_area.read().await;
+        let Some(area) = area.as_ref() else {
+            return Ok(());
+        };
+
+        let outside = [("Pickup", &delivery.pickup), ("Dropoff", &delivery.dropoff)]
+            .into_iter()
+            .find(|(_, location)| location.coordinates.as_ref().is_some_and(|p| !area.contains(p)))
+            .map(|(label, _)| label);
+
+        if let Some(label) = outside {
+            match self.service_area_mode {
+                service_area::ServiceAreaMode::Reject => return Err(format!("{} is outside the service area", label)),
+                service_area::ServiceAreaMode::Flag => delivery.out_of_service_area = true,
+            }
+        }
+
+        Ok(())
+    }
+
+    // Helper to publish delivery request event
+    pub async fn publish_delivery(&self, delivery: &DeliveryRequest) -> Result<(), Box<dyn std::error::Error>> {
+        let mut delivery = delivery.clone();
+        delivery.local_times = None;
+        self.seal_contacts(&mut delivery).await?;
+        self.seal_instructions(&mut delivery).await?;
+        let delivery = &delivery;
+        let content = serde_json::to_string(delivery)?;
+
+        // Private deliveries and drafts (even deleted ones) expose only their identifier and status to relays
+        let draft = delivery.status == DeliveryStatus::Draft || delivery.deleted_from == Some(DeliveryStatus::Draft);
+        if delivery.private || draft {
+            let tags = vec![
+                Tag::custom(TagKind::Custom("d".into()), vec![delivery.id.clone()]),
+                Tag::custom(TagKind::Custom("status".into()), vec![format!("{:?}", delivery.status).to_lowercase()]),
+                Tag::custom(TagKind::Custom("encrypted".into()), vec!["nip44".to_string()]),
+            ];
+            let content = self.encrypt_to_self(&content).await?;
+
+            let event = self.sign_event_for(EventBuilder::new(Kind::Custom(35000), content, tags), delivery.organization.as_deref()).await?;
+            self.send_event(event).await?;
+            self.cache.deliveries.invalidate(&delivery.id).await;
+
+            return Ok(());
+        }
+
+        let mut tags = vec![
+            Tag::custom(TagKind::Custom("d".into()), vec![delivery.id.clone()]),
+            Tag::custom(TagKind::Custom("sender".into()), vec![delivery.sender.clone()]),
+            Tag::custom(TagKind::Custom("status".into()), vec![format!("{:?}", delivery.status).to_lowercase()]),
+            Tag::custom(TagKind::Custom("amount".into()), vec![delivery.offer_amount.to_string()]),
+            Tag::custom(TagKind::Custom("created_at".into()), vec![delivery.created_at.to_string()]),
+        ];
+        if let Some(min_reputation) = delivery.min_courier_reputation {
+            tags.push(Tag::custom(TagKind::Custom("min_courier_reputation".into()), vec![min_reputation.to_string()]));
+        }
+        if let Some(min_completed) = delivery.min_completed_deliveries {
+            tags.push(Tag::custom(TagKind::Custom("min_completed_deliveries".into()), vec![min_completed.to_string()]));
+        }
+        for courier in &delivery.allowed_couriers {
+            tags.push(Tag::custom(TagKind::Custom("allowed_courier".into()), vec![courier.clone()]));
+        }
+        // NIP-92 media attachments, so other clients can show and verify the package photos
+        for photo in &delivery.package_photos {
+            tags.push(Tag::custom(TagKind::Custom("imeta".into()), vec![format!("url {}", photo.url), format!("x {}", photo.sha256)]));
+        }
+
+        // Organization deliveries are signed by the organization's key, others on the sender's behalf
+        let event = match &delivery.organization {
+            Some(org_id) => self.sign_event_for(EventBuilder::new(Kind::Custom(35000), content, tags), Some(org_id)).await?,
+            None => self.sign_event_as(&delivery.sender, 35000, content, tags).await?,
+        };
+        self.send_event(event).await?;
+        self.cache.deliveries.invalidate(&delivery.id).await;
+
+        if self.classifieds_enabled {
+            if let Err(e) = self.publish_classified_listing(delivery).await {
+                log::warn!("Failed to mirror delivery {} as classified listing: {}", delivery.id, e);
+            }
+        }
+
+        Ok(())
+    }
+
+    // Seal plaintext stop contacts to the marketplace key, merged over any sealed earlier, and
+    // keep a copy encrypted to the accepted courier while the delivery is under way
+    pub async fn seal_contacts(&self, delivery: &mut DeliveryRequest) -> Result<(), Box<dyn std::error::Error>> {
+        let provided = contacts::StopContacts::take(delivery);
+        if !provided.is_empty() {
+            let mut sealed = self.unseal_contacts(delivery).await?;
+            sealed.merge(provided);
+            delivery.sealed_contacts = Some(self.encrypt_to_self(&serde_json::to_string(&sealed)?).await?);
+            delivery.contact_reveal = None;
+        }
+
+        let courier = delivery.accepted_courier().and_then(|c| PubkeyId::parse(c).map(PublicKey::from).ok());
+        let (Some(courier), Some(_), true) = (courier, &delivery.sealed_contacts, contacts::revealable(delivery)) else {
+            delivery.contact_reveal = None;
+            return Ok(());
+        };
+        if delivery.contact_reveal.as_ref().is_some_and(|r| r.courier == courier.to_hex()) {
+            return Ok(());
+        }
+
+        let plaintext = serde_json::to_string(&self.unseal_contacts(delivery).await?)?;
+        delivery.contact_reveal = Some(self.reveal_to(&courier, &plaintext).await?);
+        Ok(())
+    }
+
+    // Seal access instructions like contacts, but reveal them only in transit and drop them
+    // altogether after the handoff
+    pub async fn seal_instructions(&self, delivery: &mut DeliveryRequest) -> Result<(), Box<dyn std::error::Error>> {
+        let provided = contacts::StopInstructions::take(delivery);
+        if contacts::instructions_expired(delivery) {
+            delivery.sealed_instructions = None;
+            delivery.instructions_reveal = None;
+            return Ok(());
+        }
+        if !provided.is_empty() {
+            let mut sealed = self.unseal_instructions(delivery).await?;
+            sealed.merge(provided);
+            delivery.sealed_instructions = Some(self.encrypt_to_self(&serde_json::to_string(&sealed)?).await?);
+            delivery.instructions_reveal = None;
+        }
+
+        let courier = delivery.accepted_courier().and_then(|c| PubkeyId::parse(c).map(PublicKey::from).ok());
+        let (Some(courier), Some(_), true) = (courier, &delivery.sealed_instructions, contacts::instructions_revealable(delivery)) else {
+            delivery.instructions_reveal = None;
+            return Ok(());
+        };
+        if delivery.instructions_reveal.as_ref().is_some_and(|r| r.courier == courier.to_hex()) {
+            return Ok(());
+        }
+
+        let plaintext = serde_json::to_string(&self.unseal_instructions(delivery).await?)?;
+        delivery.instructions_reveal = Some(self.reveal_to(&courier, &plaintext).await?);
+        Ok(())
+    }
+
+    // Encrypt sealed stop details to a courier, who decrypts them against the marketplace key
+    async fn reveal_to(&self, courier: &PublicKey, plaintext: &str) -> Result<contacts::Reveal, Box<dyn std::error::Error>> {
+        let signer = self.nostr_client.signer().await?;
+        let ciphertext = tokio::time::timeout(self.signing_timeout, signer.nip44_encrypt(courier, plaintext)).await??;
+        Ok(contacts::Reveal {
+            courier: courier.to_hex(),
+            pubkey: self.system_pubkey.to_hex(),
+            ciphertext,
+        })
+    }
+
+    // The pickup code encrypted to the accepted courier until the package is picked up
+    pub async fn pickup_code_reveal(&self, delivery: &DeliveryRequest) -> Result<Option<contacts::Reveal>, Box<dyn std::error::Error>> {
+        let courier = delivery.accepted_courier().and_then(|c| PubkeyId::parse(c).map(PublicKey::from).ok());
+        match courier {
+            Some(courier) if delivery.status == DeliveryStatus::Accepted => Ok(Some(self.reveal_to(&courier, &self.pickup_code(&delivery.id)).await?)),
+            _ => Ok(None),
+        }
+    }
+
+    pub async fn unseal_contacts(&self, delivery: &DeliveryRequest) -> Result<contacts::StopContacts, Box<dyn std::error::Error>> {
+        match &delivery.sealed_contacts {
+            Some(sealed) => Ok(serde_json::from_str(&self.decrypt_from_self(sealed).await?)?),
+            None => Ok(contacts::StopContacts::default()),
+        }
+    }
+
+    pub async fn unseal_instructions(&self, delivery: &DeliveryRequest) -> Result<contacts::StopInstructions, Box<dyn std::error::Error>> {
+        match &delivery.sealed_instructions {
+            Some(sealed) => Ok(serde_json::from_str(&self.decrypt_from_self(sealed).await?)?),
+            None => Ok(contacts::StopInstructions::default()),
+        }
+    }
+
+    // Delivery from a kind 35000 event, decrypting private deliveries
+    pub async fn parse_delivery_event(&self, event: &Event) -> Result<DeliveryRequest, Box<dyn std::error::Error>> {
+        let encrypted = event.tags.iter().any(|tag| tag.as_slice().first().is_some_and(|name| name == "encrypted"));
+
+        let mut delivery: DeliveryRequest = if encrypted {
+            serde_json::from_str(&self.decrypt_from_self(&event.content).await?)?
+        } else {
+            serde_json::from_str(&event.content)?
+        };
+
+        // Contacts are only ever published sealed
+        delivery.pickup.contact = None;
+        delivery.dropoff.contact = None;
+        delivery.local_times = Some(timezone::LocalTimes::for_delivery(&delivery));
+
+        // A delegated delivery must be the delegator's own
+        if !query::attributed_to(event, &delivery.sender) {
+            return Err(format!("Delivery {} names a sender other than its delegator", delivery.id).into());
+        }
+
+        // An organization key can't publish deliveries for another organization
+        if let Some(org_id) = self.authors.organization_of(&event.pubkey) {
+            if delivery.organization.as_deref() != Some(org_id.as_str()) {
+                return Err(format!("Delivery {} is signed by the key of organization {}", delivery.id, org_id).into());
+            }
+        }
+        Ok(delivery)
+    }
+
+    // Mirror a delivery as a NIP-99 classified listing (marked sold once no longer open)
+    pub async fn publish_classified_listing(&self, delivery: &DeliveryRequest) -> Result<(), Box<dyn std::error::Error>> {
+        let content = classifieds::listing_content(delivery, &self.frontend_url);
+        let tags = classifieds::listing_tags(delivery);
+
+        let event = self.sign_event(EventBuilder::new(Kind::Custom(classifieds::CLASSIFIED_LISTING_KIND), content, tags)).await?;
+        self.send_event(event).await?;
+
+        Ok(())
+    }
+
+    // Announce this app as a NIP-89 handler so other clients can deep-link our events
+    pub async fn publish_handler_announcement(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let content = nip89::handler_metadata(&self.frontend_url);
+        let tags = nip89::handler_tags(&format!("{}/api/v1/open", self.public_url));
+
+        let event = self.sign_event(EventBuilder::new(Kind::Custom(nip89::HANDLER_INFO_KIND), content, tags)).await?;
+        self.send_event(event).await?;
+
+        Ok(())
+    }
+
+    // Find the delivery a NIP-19 naddr/nevent/note refers to
+    pub async fn resolve_nip19_delivery(&self, entity: Nip19) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        let filter = match entity {
+            Nip19::Coordinate(coordinate) => {
+                if coordinate.kind == Kind::Custom(35000) {
+                    return Ok(Some(coordinate.identifier));
+                }
+                Filter::new()
+                    .kind(coordinate.kind)
+                    .author(coordinate.public_key)
+                    .identifier(coordinate.identifier)
+            }
+            Nip19::Event(event) => Filter::new().id(event.event_id),
+            Nip19::EventId(event_id) => Filter::new().id(event_id),
+            _ => return Ok(None),
+        };
+
+        let events = self.nostr_client.fetch_events(vec![filter], Some(Duration::from_secs(5))).await?;
+
+        Ok(events
+            .into_iter()
+            .filter(|e| nip89::HANDLED_KINDS.contains(&e.kind.as_u16()))
+            .find_map(|e| nip89::delivery_id_for_event(&e)))
+    }
+
+    // Record replies to our classified listings from generic Nostr clients
+    pub async fn run_classified_replies(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut notifications = self.nostr_client.notifications();
+
+        let filter = Filter::new()
+            .kind(Kind::TextNote)
+            .pubkey(self.system_pubkey)
+            .since(Timestamp::now());
+        self.nostr_client.subscribe(vec![filter], None).await?;
+
+        loop {
+            match notifications.recv().await {
+                Ok(RelayPoolNotification::Event { event, .. }) => {
+                    if let Some(interest) = classifieds::parse_interest(&event, &self.system_pubkey) {
+                        let mut recorded = self.listing_interest.write().await;
+                        if !recorded.iter().any(|i| i.event_id == interest.event_id) {
+                            log::info!("Listing interest for {} from {}", interest.delivery_id, interest.from_pubkey);
+                            recorded.push(interest);
+                        }
+                    }
+                }
+                Ok(_) => {}
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
+                    log::warn!("Classified reply subscription lagged, skipped {} notifications", skipped);
+                }
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => return Ok(()),
+            }
+        }
+    }
+
+    // Helper to publish bid event
+    pub async fn publish_bid(&self, delivery: &DeliveryRequest, bid: &DeliveryBid) -> Result<(), Box<dyn std::error::Error>> {
+        let content = serde_json::to_string(bid)?;
+
+        let tags = vec![
+            Tag::custom(TagKind::Custom("d".into()), vec![bid.id.clone()]),
+            Tag::custom(TagKind::Custom("delivery_id".into()), vec![delivery.id.clone()]),
+            Tag::custom(TagKind::Custom("courier".into()), vec![bid.courier.clone()]),
+            Tag::custom(TagKind::Custom("amount".into()), vec![bid.amount.to_string()]),
+        ];
+
+        let event = self.sign_event_as(&bid.courier, 35001, content, tags).await?;
+        self.send_to_participants(event, &[&delivery.sender, &bid.courier]).await?;
+        self.cache.bids.invalidate(&delivery.id).await;
+        self.cache.deliveries.invalidate(&delivery.id).await;
+
+        Ok(())
+    }
+
+    // Helper to publish status update event
+    pub async fn publish_status_update(&self, delivery: &DeliveryRequest, update: &DeliveryUpdate) -> Result<(), Box<dyn std::error::Error>> {
+        let status = &update.status;
+        // Other statuses are carried by the delivery event alone
+        let Some(kind) = update.kind() else {
+            return Ok(());
+        };
+        let content = serde_json::to_string(update)?;
+
+        // One replaceable event per delivery and status kind
+        let tags = vec![
+            Tag::custom(TagKind::Custom("d".into()), vec![delivery.id.clone()]),
+            Tag::custom(TagKind::Custom("delivery_id".into()), vec![delivery.id.clone()]),
+            Tag::custom(TagKind::Custom("status".into()), vec![format!("{:?}", status).to_lowercase()]),
+            Tag::custom(TagKind::Custom("timestamp".into()), vec![update.timestamp.to_string()]),
+        ];
+
+        // Senders accept bids and confirm receipt; the courier reports pickup and dropoff
+        let actor = match status {
+            DeliveryStatus::InTransit | DeliveryStatus::Completed => delivery.accepted_courier(),
+            _ => None,
+        }.unwrap_or(&delivery.sender);
+        let event = self.sign_event_as(actor, kind, content, tags).await?;
+        let mut participants = vec![delivery.sender.as_str()];
+        participants.extend(delivery.accepted_courier());
+        self.send_to_participants(event, &participants).await?;
+        self.projections.write().await.record_update(&delivery.id, kind, update.clone());
+        self.cache.deliveries.invalidate(&delivery.id).await;
+
+        self.email_status_update(delivery, status).await;
+
+        Ok(())
+    }
+
+    // Email the sender, if they opted in, and the recipient about a transition; sent in the
+    // background so a slow SMTP server doesn't hold up the request
+    pub async fn email_status_update(&self, delivery: &DeliveryRequest, status: &DeliveryStatus) {
+        let (Some(mailer), Some(event)) = (&self.mailer, email::EmailEvent::for_status(status)) else {
+            return;
+        };
+        // A returned package never reached the recipient
+        if delivery.returning {
+            return;
+        }
+
+        // The recipient is the sender's customer, so they're written to in the sender's language
+        let mut recipients = Vec::new();
+        let mut language = i18n::Language::default();
+        match self.get_user_settings(&delivery.sender).await {
+            Ok(user_settings) => {
+                language = user_settings.language.unwrap_or_default();
+                if user_settings.notifications.email_updates {
+                    recipients.extend(user_settings.notifications.email);
+                }
+            }
+            Err(e) => log::warn!("Failed to load settings for {}: {}", delivery.sender, e),
+        }
+        if event.for_recipient() {
+            match self.recipient_contact(&delivery.id).await {
+                Ok(contact) => recipients.extend(contact.email),
+                Err(e) => log::warn!("Failed to load recipient contact for {}: {}", delivery.id, e),
+            }
+        }
+
+        let tracking_url = self.recipient_tracking_url(&delivery.id);
+        for to in recipients {
+            let message = email::Email::for_delivery(mailer.config(), event, language, delivery, &tracking_url, to);
+            let mailer = mailer.clone();
+            let delivery_id = delivery.id.clone();
+            tokio::spawn(async move {
+                if let Err(e) = mailer.send(&message).await {
+                    log::warn!("Failed to email {} about {}: {}", message.to, delivery_id, e);
+                }
+            });
+        }
+    }
+
+    fn recipient_contact_identifier(&self, delivery_id: &str) -> String {
+        let token = codes::derive_token(&self.code_secret, "recipient", delivery_id);
+        format!("{}/{}", notify::RECIPIENT_APP_DATA_PREFIX, token)
+    }
+
+    // Recipient contact details stay off the public delivery event, NIP-44 encrypted like settings
+    pub async fn save_recipient_contact(&self, delivery_id: &str, contact: &notify::RecipientContact) -> Result<(), Box<dyn std::error::Error>> {
+        let content = self.encrypt_to_self(&serde_json::to_string(contact)?).await?;
+        let tags = vec![Tag::identifier(self.recipient_contact_identifier(delivery_id))];
+
+        let event = self.sign_event(EventBuilder::new(Kind::ApplicationSpecificData, content, tags)).await?;
+        self.send_event(event).await?;
+        Ok(())
+    }
+
+    pub async fn recipient_contact(&self, delivery_id: &str) -> Result<notify::RecipientContact, Box<dyn std::error::Error>> {
+        let filter = Filter::new()
+            .kind(Kind::ApplicationSpecificData)
+            .author(self.system_pubkey)
+            .identifier(self.recipient_contact_identifier(delivery_id));
+        let events = self.fetch_marketplace_events(filter, Duration::from_secs(5)).await?;
+
+        match query::latest_per_identifier(events, &self.authors).pop() {
+            Some(event) => Ok(serde_json::from_str(&self.decrypt_from_self(&event.content).await?)?),
+            None => Ok(notify::RecipientContact::default()),
+        }
+    }
+
+    // Tell the recipient once that the courier is about to arrive, with the public tracking link
+    pub async fn notify_recipient_eta(&self, delivery: &DeliveryRequest, pings: &[tracking::LocationPing]) {
+        let (Some(notifications), Some(dropoff)) = (&self.recipient_notifications, &delivery.dropoff.coordinates) else {
+            return;
+        };
+        if delivery.status != DeliveryStatus::InTransit || self.eta_notified.read().await.contains(&delivery.id) {
+            return;
+        }
+        let Some(eta) = notify::eta_secs(pings, dropoff) else {
+            return;
+        };
+        if eta > notifications.eta_minutes * 60 {
+            return;
+        }
+
+        let contact = match self.recipient_contact(&delivery.id).await {
+            Ok(contact) if !contact.is_empty() => contact,
+            Ok(_) => return,
+            Err(e) => {
+                log::warn!("Failed to load recipient contact for {}: {}", delivery.id, e);
+                return;
+            }
+        };
+        if !self.eta_notified.write().await.insert(delivery.id.clone()) {
+            return;
+        }
+
+        let language = self.language_of(&delivery.sender).await;
+        let message = i18n::Message::RecipientEta.render(language, &[
+            ("minutes", &((eta + 59) / 60).to_string()),
+            ("tracking_url", &self.recipient_tracking_url(&delivery.id)),
+        ]);
+        for provider in &notifications.providers {
+            match provider.notify(&contact, &message).await {
+                Ok(true) => log::info!("Sent {} arrival notice for {}", provider.channel(), delivery.id),
+                Ok(false) => {}
+                Err(e) => log::warn!("Failed to send {} arrival notice for {}: {}", provider.channel(), delivery.id, e),
+            }
+        }
+    }
+
+    // Helper to publish user profile event
+    pub async fn publish_user_profile(&self, profile: &UserProfile) -> Result<(), Box<dyn std::error::Error>> {
+        let content = serde_json::to_string(profile)?;
+
+        let mut tags = vec![
+            Tag::custom(TagKind::Custom("d".into()), vec![PubkeyId::normalize(&profile.npub)]),
+            Tag::custom(TagKind::Custom("reputation".into()), vec![profile.reputation.to_string()]),
+            Tag::custom(TagKind::Custom("completed_deliveries".into()), vec![profile.completed_deliveries.to_string()]),
+        ];
+        if profile.leaderboard_opt_in {
+            tags.push(Tag::hashtag("leaderboard"));
+        }
+
+        let event = self.sign_event_as(&profile.npub, 35009, content, tags).await?;
+        self.send_event(event).await?;
+        self.cache.profiles.insert(&PubkeyId::normalize(&profile.npub), profile.clone()).await;
+
+        Ok(())
+    }
+
+    // Latest profile per user, keeping those who opted in to leaderboards
+    pub async fn get_leaderboard_participants(&self) -> Result<Vec<UserProfile>, Box<dyn std::error::Error>> {
+        let filter = Filter::new()
+            .kind(Kind::Custom(35009))
+            .authors(self.authors.keys())
+            .limit(1000);
+
+        let events = self.fetch_marketplace_events(filter, Duration::from_secs(5)).await?;
+
+        Ok(query::latest_per_identifier(events, &self.authors)
+            .into_iter()
+            .filter_map(|event| serde_json::from_str::<UserProfile>(&event.content).ok())
+            .filter(|p| p.leaderboard_opt_in)
+            .collect())
+    }
+
+    // Query all deliveries from Nostr
+    pub async fn get_all_deliveries(&self) -> Result<Vec<DeliveryRequest>, Box<dyn std::error::Error>> {
+        let filter = Filter::new()
+            .kind(Kind::Custom(35000))
+            .authors(self.authors.keys())
+            .limit(1000);
+
+        let started = std::time::Instant::now();
+        let events = self.fetch_marketplace_events(filter, Duration::from_secs(5)).await?;
+        if let Err(e) = self.sync_projections().await {
+            log::warn!("Syncing delivery projections failed: {}", e);
+        }
+
+        let mut deliveries = Vec::new();
+        let mut blocked_by_sender: std::collections::HashMap<String, Vec<String>> = std::collections::HashMap::new();
+
+        for event in query::latest_per_identifier(events, &self.authors) {
+            if let Ok(mut delivery) = self.parse_delivery_event(&event).await {
+                // Fetch bids for this delivery, fresh from the relays
+                let bids = self.fetch_bids_for_delivery(&delivery.id).await.unwrap_or_default();
+                self.cache.bids.fill(&delivery.id, bids.clone(), started).await;
+                delivery.bids = bids;
+
+                if !blocked_by_sender.contains_key(&delivery.sender) {
+                    let blocked = self.get_courier_list(&delivery.sender, courier_lists::CourierListKind::Blocked).await
+                        .unwrap_or_default();
+                    blocked_by_sender.insert(delivery.sender.clone(), blocked);
+                }
+                if let Some(blocked) = blocked_by_sender.get(&delivery.sender) {
+                    Self::hide_blocked_bids(&mut delivery, blocked);
+                }
+
+                self.project_delivery(&mut delivery, &event).await;
+
+                deliveries.push(delivery);
+            }
+        }
+
+        Ok(deliveries)
+    }
+
+    // Query specific delivery by ID, from the cache when we have it
+    pub async fn get_delivery_by_id(&self, id: &str) -> Result<Option<DeliveryRequest>, Box<dyn std::error::Error>> {
+        if let Some(delivery) = self.cache.deliveries.get(id).await {
+            return Ok(Some(delivery));
+        }
+        let started = std::time::Instant::now();
+        let delivery = self.fetch_delivery_by_id(id).await?;
+        if let Some(delivery) = &delivery {
+            self.cache.deliveries.fill(id, delivery.clone(), started).await;
+        }
+        Ok(delivery)
+    }
+
+    async fn fetch_delivery_by_id(&self, id: &str) -> Result<Option<DeliveryRequest>, Box<dyn std::error::Error>> {
+        let filter = Filter::new()
+            .kind(Kind::Custom(35000))
+            .authors(self.authors.keys())
+            .custom_tag(SingleLetterTag::lowercase(Alphabet::D), [id]);
+
+        let events = self.fetch_marketplace_events(filter, Duration::from_secs(5)).await?;
+
+        if let Some(event) = query::latest_per_identifier(events, &self.authors).pop() {
+            let mut delivery = self.parse_delivery_event(&event).await?;
+            if let Err(e) = self.sync_projections().await {
+                log::warn!("Syncing delivery projections failed: {}", e);
+            }
+
+            // Fetch bids
+            delivery.bids = self.get_bids_for_delivery(&delivery.id).await.unwrap_or_default();
+
+            let blocked = self.get_courier_list(&delivery.sender, courier_lists::CourierListKind::Blocked).await
+                .unwrap_or_default();
+            Self::hide_blocked_bids(&mut delivery, &blocked);
+
+            self.project_delivery(&mut delivery, &event).await;
+
+            Ok(Some(delivery))
+        } else {
+            Ok(None)
+        }
+    }
+
+    // Bring a delivery read from its event up to date with its projection
+    async fn project_delivery(&self, delivery: &mut DeliveryRequest, delivery_event: &Event) {
+        self.projections.write().await.project(delivery, delivery_event.created_at.as_u64() as i64);
+    }
+
+    // Fold status updates published since the last sync into the projections. The first sync
+    // reads them all; later ones only what's new.
+    pub async fn sync_projections(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let now = Utc::now().timestamp();
+        let mut filter = Filter::new()
+            .kinds(STATUS_UPDATE_KINDS.map(Kind::Custom))
+            .authors(self.authors.keys())
+            .limit(1000);
+        if let Some(synced) = self.projections.read().await.synced_until {
+            filter = filter.since(Timestamp::from((synced - projection::SYNC_OVERLAP_SECS).max(0) as u64));
+        }
+
+        let events = self.fetch_marketplace_events(filter, Duration::from_secs(5)).await?;
+        let mut projections = self.projections.write().await;
+        for event in query::latest_per_identifier(events, &self.authors) {
+            if let Some(delivery_id) = query::delivery_id(&event) {
+                projections.record_update(delivery_id, event.kind.as_u16(), Self::status_update_from_event(&event));
+            }
+        }
+        projections.mark_synced(now);
+        Ok(())
+    }
+
+    // A status update event's content, or just the status its kind stands for
+    fn status_update_from_event(event: &Event) -> DeliveryUpdate {
+        serde_json::from_str(&event.content).unwrap_or_else(|_| {
+            let status = DeliveryUpdate::status_for_kind(event.kind.as_u16()).unwrap_or(DeliveryStatus::Open);
+            DeliveryUpdate::new(status, event.created_at.as_u64() as i64)
+        })
+    }
+
+    // Get bids for a delivery, from the cache when we have them
+    pub async fn get_bids_for_delivery(&self, delivery_id: &str) -> Result<Vec<DeliveryBid>, Box<dyn std::error::Error>> {
+        if let Some(bids) = self.cache.bids.get(delivery_id).await {
+            return Ok(bids);
+        }
+        let started = std::time::Instant::now();
+        let bids = self.fetch_bids_for_delivery(delivery_id).await?;
+        self.cache.bids.fill(delivery_id, bids.clone(), started).await;
+        Ok(bids)
+    }
+
+    async fn fetch_bids_for_delivery(&self, delivery_id: &str) -> Result<Vec<DeliveryBid>, Box<dyn std::error::Error>> {
+        let filter = Filter::new()
+            .kind(Kind::Custom(35001))
+            .authors(self.authors.keys())
+            .limit(1000);
+
+        let events = self.fetch_marketplace_events(filter, Duration::from_secs(5)).await?;
+
+        let mut bids: Vec<DeliveryBid> = query::latest_per_identifier(events, &self.authors)
+            .into_iter()
+            .filter(|event| query::has_tag(event, "delivery_id", delivery_id))
+            .filter_map(|event| {
+                let bid = serde_json::from_str::<DeliveryBid>(&event.content).ok()?;
+                // Delegated bids count only for the courier who delegated
+                query::attributed_to(&event, &bid.courier).then_some(bid)
+            })
+            .collect();
+
+        bids.sort_by_key(|b| b.created_at);
+        Ok(bids)
+    }
+
+    // Get status updates for a delivery
+    pub async fn get_status_updates(&self, delivery_id: &str) -> Result<Vec<DeliveryUpdate>, Box<dyn std::error::Error>> {
+        let filter = Filter::new()
+            .kinds(STATUS_UPDATE_KINDS.map(Kind::Custom))
+            .authors(self.authors.keys())
+            .limit(1000);
+
+        let events = self.fetch_marketplace_events(filter, Duration::from_secs(5)).await?;
+        let events = events.into_iter().filter(|event| query::has_tag(event, "delivery_id", delivery_id));
+
+        let mut updates: Vec<DeliveryUpdate> = query::latest_per_identifier(events, &self.authors)
+            .iter()
+            .map(Self::status_update_from_event)
+            .collect();
+
+        // Updates in the same second follow the lifecycle order of their kinds
+        updates.sort_by_key(|u| (u.timestamp, u.kind()));
+        Ok(updates)
+    }
+
+    // Helper to publish a courier location ping
+    pub async fn publish_location_ping(&self, ping: &tracking::LocationPing) -> Result<(), Box<dyn std::error::Error>> {
+        let content = serde_json::to_string(ping)?;
+
+        let tags = vec![
+            Tag::custom(TagKind::Custom("d".into()), vec![format!("{}:{}", ping.delivery_id, Utc::now().timestamp_millis())]),
+            Tag::custom(TagKind::Custom("delivery_id".into()), vec![ping.delivery_id.clone()]),
+            Tag::custom(TagKind::Custom("courier".into()), vec![ping.courier.clone()]),
+        ];
+
+        let event = self.sign_event(EventBuilder::new(Kind::Custom(35007), content, tags)).await?;
+        self.send_event(event).await?;
+
+        Ok(())
+    }
+
+    // DM the sender about suspicious courier movement
+    pub async fn notify_location_anomalies(&self, delivery: &DeliveryRequest, ping: &tracking::LocationPing) {
+        let Ok(sender) = PubkeyId::parse(&delivery.sender).map(PublicKey::from) else {
+            return;
+        };
+        let details: Vec<String> = ping.anomalies.iter().map(|anomaly| match anomaly {
+            tracking::LocationAnomaly::Teleport { distance_meters, speed_mps } => {
+                format!("jumped {:.0} m at {:.0} m/s", distance_meters, speed_mps)
+            }
+            tracking::LocationAnomaly::Gap { seconds } => format!("no location updates for {} min", seconds / 60),
+        }).collect();
+        let message = format!(
+            "Tracking anomaly on your delivery to {}: courier {}. {}",
+            delivery.dropoff.address, details.join(", "), self.tracking_url(&delivery.id)
+        );
+        if let Err(e) = self.send_dm(sender, message).await {
+            log::warn!("Failed to notify sender {}: {}", delivery.sender, e);
+        }
+    }
+
+    // Tell the sender a dropoff attempt failed and what happens next
+    pub async fn notify_failed_attempt(&self, delivery: &DeliveryRequest, attempt: &delivery_attempts::FailedAttempt) {
+        let Ok(sender) = PubkeyId::parse(&delivery.sender).map(PublicKey::from) else {
+            return;
+        };
+        let next = match attempt.outcome {
+            delivery_attempts::AttemptOutcome::Retry => "the courier will try again",
+            delivery_attempts::AttemptOutcome::Return => "the package is being returned to the pickup",
+            delivery_attempts::AttemptOutcome::PartialPayout => "the delivery has ended with a partial payout to the courier",
+        };
+        let message = format!(
+            "Delivery attempt {} to {} failed ({}); {}. {}",
+            attempt.number, delivery.dropoff.address, attempt.reason.code(), next, self.tracking_url(&delivery.id)
+        );
+        if let Err(e) = self.send_dm(sender, message).await {
+            log::warn!("Failed to notify sender {}: {}", delivery.sender, e);
+        }
+    }
+
+    // Remind both parties of accepted deliveries past their start deadline, and re-open those
+    // that are past the grace period as well. Returns how many were re-opened.
+    pub async fn check_unstarted(&self) -> Result<usize, Box<dyn std::error::Error>> {
+        let now = Utc::now().timestamp();
+        let overdue: Vec<String> = self.indexed_deliveries().await?
+            .into_iter()
+            .filter(|d| self.start_timeout.check(d, now) != start_timeout::StartCheck::OnTime)
+            .map(|d| d.id)
+            .collect();
+        self.start_reminded.write().await.retain(|id| overdue.contains(id));
+
+        let mut reopened = 0;
+        for id in overdue {
+            // The snapshot may lag behind a courier who just started
+            let Some(delivery) = self.get_delivery_by_id(&id).await? else {
+                continue;
+            };
+            match self.start_timeout.check(&delivery, now) {
+                start_timeout::StartCheck::OnTime => {}
+                start_timeout::StartCheck::Overdue { deadline } => {
+                    if self.start_reminded.write().await.insert(id) {
+                        self.remind_unstarted(&delivery, deadline + self.start_timeout.grace_secs - now).await;
+                    }
+                }
+                start_timeout::StartCheck::NoShow => {
+                    self.reopen_unstarted(&delivery, now).await?;
+                    reopened += 1;
+                }
+            }
+        }
+        Ok(reopened)
+    }
+
+    async fn remind_unstarted(&self, delivery: &DeliveryRequest, remaining_secs: i64) {
+        let Some(courier) = delivery.accepted_courier() else {
+            return;
+        };
+        let minutes = ((remaining_secs + 59) / 60).max(1).to_string();
+        let url = self.tracking_url(&delivery.id);
+        self.notify_courier(courier, |language| push::PushMessage {
+            title: i18n::Message::StartReminderTitle.text(language).to_string(),
+            body: i18n::Message::StartReminderBody.render(language, &[
+                ("pickup", &delivery.pickup.address),
+                ("dropoff", &delivery.dropoff.address),
+                ("minutes", &minutes),
+            ]),
+            url: url.clone(),
+        }).await;
+
+        let Ok(sender) = PubkeyId::parse(&delivery.sender).map(PublicKey::from) else {
+            return;
+        };
+        let message = format!(
+            "The courier for your delivery to {} hasn't started it yet; it will be re-opened to other couriers in {} minutes unless they do. {}",
+            delivery.dropoff.address, minutes, url
+        );
+        if let Err(e) = self.send_dm(sender, message).await {
+            log::warn!("Failed to notify sender {}: {}", delivery.sender, e);
+        }
+    }
+
+    // Back to Open with the no-show on the delivery and the courier's profile
+    async fn reopen_unstarted(&self, delivery: &DeliveryRequest, now: i64) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(reopened) = start_timeout::reopen(delivery, now) else {
+            return Ok(());
+        };
+        let Some(no_show) = reopened.no_shows.last().cloned() else {
+            return Ok(());
+        };
+        self.publish_delivery(&reopened).await?;
+        self.start_reminded.write().await.remove(&reopened.id);
+
+        let mut profile = self.get_user_profile(&no_show.courier).await?;
+        profile.no_shows += 1;
+        self.publish_user_profile(&profile).await?;
+        log::info!("Re-opened {} after courier {} never started it", reopened.id, no_show.courier);
+
+        let url = self.tracking_url(&reopened.id);
+        self.notify_courier(&no_show.courier, |language| push::PushMessage {
+            title: i18n::Message::NoShowTitle.text(language).to_string(),
+            body: i18n::Message::NoShowBody.render(language, &[
+                ("pickup", &reopened.pickup.address),
+                ("dropoff", &reopened.dropoff.address),
+            ]),
+            url: url.clone(),
+        }).await;
+
+        if let Ok(sender) = PubkeyId::parse(&reopened.sender).map(PublicKey::from) {
+            let message = format!(
+                "The courier for your delivery to {} never started it, so it is open for bids again; accept another bid to continue. {}",
+                reopened.dropoff.address, url
+            );
+            if let Err(e) = self.send_dm(sender, message).await {
+                log::warn!("Failed to notify sender {}: {}", reopened.sender, e);
+            }
+        }
+        Ok(())
+    }
+
+    // Deliveries past their status's threshold, as of the last index refresh
+    pub async fn stuck_deliveries(&self) -> Result<Vec<stuck::StuckDelivery>, Box<dyn std::error::Error>> {
+        let deliveries = self.indexed_deliveries().await?;
+        Ok(stuck::find(&deliveries, &self.stuck, Utc::now().timestamp()))
+    }
+
+    // Alert on deliveries that became stuck since the last check. Returns how many.
+    pub async fn alert_stuck(&self) -> Result<usize, Box<dyn std::error::Error>> {
+        let stuck = self.stuck_deliveries().await?;
+        let fresh: Vec<&stuck::StuckDelivery> = {
+            let mut alerted = self.stuck_alerted.write().await;
+            // Forget deliveries that moved on, so they alert again if they get stuck later
+            alerted.retain(|key| stuck.iter().any(|s| &s.key() == key));
+            stuck.iter().filter(|s| alerted.insert(s.key())).collect()
+        };
+
+        for item in &fresh {
+            let url = self.tracking_url(&item.delivery_id);
+            if let Some(webhook) = &self.stuck.webhook {
+                if let Err(e) = webhook.send(item, &url).await {
+                    log::warn!("Stuck delivery webhook failed for {}: {}", item.delivery_id, e);
+                }
+            }
+            let message = format!(
+                "Delivery {} has been {} for {} hours (threshold {} hours). {}",
+                item.delivery_id,
+                i18n::status_label(&item.status, i18n::Language::En).to_lowercase(),
+                item.stuck_for_secs / 3600,
+                item.threshold_secs / 3600,
+                url
+            );
+            for operator in &self.stuck.operators {
+                match PubkeyId::parse(operator).map(PublicKey::from) {
+                    Ok(operator) => {
+                        if let Err(e) = self.send_dm(operator, message.clone()).await {
+                            log::warn!("Failed to alert operator about {}: {}", item.delivery_id, e);
+                        }
+                    }
+                    Err(e) => log::warn!("Invalid STUCK_ALERT_NPUBS entry {}: {}", operator, e),
+                }
+            }
+        }
+        Ok(fresh.len())
+    }
+
+    // Get location pings for a delivery, oldest first
+    pub async fn get_location_pings(&self, delivery_id: &str) -> Result<Vec<tracking::LocationPing>, Box<dyn std::error::Error>> {
+        let filter = Filter::new()
+            .kind(Kind::Custom(35007))
+            .authors(self.authors.keys())
+            .limit(5000);
+
+        let events = self.fetch_marketplace_events(filter, Duration::from_secs(5)).await?;
+
+        let mut pings: Vec<tracking::LocationPing> = query::latest_per_identifier(events, &self.authors)
+            .into_iter()
+            .filter_map(|event| serde_json::from_str::<tracking::LocationPing>(&event.content).ok())
+            .filter(|ping| ping.delivery_id == delivery_id)
+            .collect();
+
+        pings.sort_by_key(|p| p.timestamp);
+        Ok(pings)
+    }
+
+    // Get user profile
+    // NIP-44 encryption to the marketplace key itself, for private app data
+    pub async fn encrypt_to_self(&self, plaintext: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let signer = self.nostr_client.signer().await?;
+        Ok(tokio::time::timeout(self.signing_timeout, signer.nip44_encrypt(&self.system_pubkey, plaintext)).await??)
+    }
+
+    pub async fn decrypt_from_self(&self, payload: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let signer = self.nostr_client.signer().await?;
+        Ok(tokio::time::timeout(self.signing_timeout, signer.nip44_decrypt(&self.system_pubkey, payload)).await??)
+    }
+
+    pub async fn get_courier_list(&self, sender: &str, kind: courier_lists::CourierListKind) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let filter = Filter::new()
+            .kind(Kind::Custom(courier_lists::FOLLOW_SET_KIND))
+            .author(self.system_pubkey)
+            .identifier(kind.identifier(sender));
+
+        let events = self.fetch_marketplace_events(filter, Duration::from_secs(5)).await?;
+
+        let Some(event) = query::latest_per_identifier(events, &self.authors).pop() else {
+            return Ok(Vec::new());
+        };
+
+        let mut couriers = courier_lists::public_couriers(&event);
+        if !event.content.is_empty() {
+            let items: Vec<Vec<String>> = serde_json::from_str(&self.decrypt_from_self(&event.content).await?)?;
+            for courier in courier_lists::couriers_from_items(items.iter().map(Vec::as_slice)) {
+                if !couriers.contains(&courier) {
+                    couriers.push(courier);
+                }
+            }
+        }
+
+        Ok(couriers)
+    }
+
+    pub async fn publish_courier_list(&self, sender: &str, kind: courier_lists::CourierListKind, couriers: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+        let items = courier_lists::courier_tags(couriers);
+
+        let (content, public_items) = if kind.is_private() {
+            (self.encrypt_to_self(&serde_json::to_string(&items)?).await?, Vec::new())
+        } else {
+            (String::new(), items)
+        };
+        let tags = courier_lists::list_tags(kind, sender, public_items);
+
+        let event = self.sign_event(EventBuilder::new(Kind::Custom(courier_lists::FOLLOW_SET_KIND), content, tags)).await?;
+        self.send_event(event).await?;
+        // Cached deliveries hide bids from blocked couriers
+        if matches!(kind, courier_lists::CourierListKind::Blocked) {
+            self.cache.deliveries.invalidate_all();
+        }
+
+        Ok(())
+    }
+
+    pub async fn get_courier_lists(&self, sender: &str) -> Result<courier_lists::CourierLists, Box<dyn std::error::Error>> {
+        Ok(courier_lists::CourierLists {
+            favorites: self.get_courier_list(sender, courier_lists::CourierListKind::Favorites).await?,
+            blocked: self.get_courier_list(sender, courier_lists::CourierListKind::Blocked).await?,
+        })
+    }
+
+    // Drop bids from couriers the sender has blocked
+    pub fn hide_blocked_bids(delivery: &mut DeliveryRequest, blocked: &[String]) {
+        if !blocked.is_empty() {
+            delivery.bids.retain(|bid| !blocked.contains(&PubkeyId::normalize(&bid.courier)));
+        }
+    }
+
+    // New deliveries reach favorite (or, if private, allowed) couriers by DM before they are broadcast.
+    // Drafts are only stored; they're offered once published.
+    pub async fn publish_new_delivery(&self, delivery: &DeliveryRequest) -> Result<(), Box<dyn std::error::Error>> {
+        if delivery.status == DeliveryStatus::Draft {
+            return self.publish_delivery(delivery).await;
+        }
+
+        let (recipients, intro) = if delivery.private {
+            (delivery.allowed_couriers.clone(), i18n::Message::PrivateOfferTitle)
+        } else {
+            let favorites = self.get_courier_list(&delivery.sender, courier_lists::CourierListKind::Favorites).await
+                .unwrap_or_default();
+            let favorites = favorites.into_iter().filter(|c| delivery.allows_courier(c)).collect();
+            (favorites, i18n::Message::FavoriteOfferTitle)
+        };
+
+        let amount = delivery.offer_amount.to_string();
+        let url = self.tracking_url(&delivery.id);
+        for courier in recipients {
+            self.notify_courier(&courier, |language| push::PushMessage {
+                title: intro.text(language).to_string(),
+                body: i18n::Message::OfferBody.render(language, &[
+                    ("pickup", &delivery.pickup.address),
+                    ("dropoff", &delivery.dropoff.address),
+                    ("amount", &amount),
+                ]),
+                url: url.clone(),
+            }).await;
+        }
+
+        self.publish_delivery(delivery).await
+    }
+
+    // Publish an edited delivery, recording the change as a revision event. When the terms
+    // couriers bid on changed, their bids go stale and they are asked to re-confirm
+    pub async fn publish_delivery_edit(&self, before: &DeliveryRequest, after: &mut DeliveryRequest) -> Result<Option<revisions::DeliveryRevision>, Box<dyn std::error::Error>> {
+        let changes = revisions::diff(before, after);
+        if changes.is_empty() {
+            self.publish_delivery(after).await?;
+            return Ok(None);
+        }
+
+        let revision = revisions::DeliveryRevision {
+            delivery_id: after.id.clone(),
+            revision: self.get_revisions(&after.id).await?.len() as u32 + 1,
+            created_at: Utc::now().timestamp(),
+            changes,
+        };
+
+        self.publish_delivery(after).await?;
+        self.publish_revision(&revision, after.private).await?;
+        if revision.is_material() {
+            let held: Vec<DeliveryBid> = after.bids.iter_mut()
+                .filter(|b| !b.stale)
+                .map(|b| {
+                    b.stale = true;
+                    b.clone()
+                })
+                .collect();
+            for bid in &held {
+                self.publish_bid(after, bid).await?;
+            }
+            self.notify_bidders_of_revision(after, &revision).await;
+        }
+
+        Ok(Some(revision))
+    }
+
+    pub async fn publish_revision(&self, revision: &revisions::DeliveryRevision, private: bool) -> Result<(), Box<dyn std::error::Error>> {
+        let mut content = serde_json::to_string(revision)?;
+
+        let mut tags = vec![
+            Tag::custom(TagKind::Custom("d".into()), vec![revision.identifier()]),
+            Tag::custom(TagKind::Custom("delivery_id".into()), vec![revision.delivery_id.clone()]),
+        ];
+        if private {
+            content = self.encrypt_to_self(&content).await?;
+            tags.push(Tag::custom(TagKind::Custom("encrypted".into()), vec!["nip44".to_string()]));
+        } else {
+            for change in &revision.changes {
+                tags.push(Tag::custom(TagKind::Custom("changed".into()), vec![change.field.clone()]));
+            }
+        }
+
+        let event = self.sign_event(EventBuilder::new(Kind::Custom(revisions::REVISION_KIND), content, tags)).await?;
+        self.send_event(event).await?;
+
+        Ok(())
+    }
+
+    // Revisions of a delivery, oldest first
+    pub async fn get_revisions(&self, delivery_id: &str) -> Result<Vec<revisions::DeliveryRevision>, Box<dyn std::error::Error>> {
+        let filter = Filter::new()
+            .kind(Kind::Custom(revisions::REVISION_KIND))
+            .author(self.system_pubkey)
+            .limit(5000);
+
+        let events = self.fetch_marketplace_events(filter, Duration::from_secs(5)).await?;
+
+        let mut revisions = Vec::new();
+        for event in query::latest_per_identifier(events, &self.authors) {
+            if !query::has_tag(&event, "delivery_id", delivery_id) {
+                continue;
+            }
+            let encrypted = event.tags.iter().any(|tag| tag.as_slice().first().is_some_and(|name| name == "encrypted"));
+            let content = if encrypted {
+                self.decrypt_from_self(&event.content).await?
+            } else {
+                event.content.clone()
+            };
+            if let Ok(revision) = serde_json::from_str::<revisions::DeliveryRevision>(&content) {
+                revisions.push(revision);
+            }
+        }
+
+        revisions.sort_by_key(|r| r.revision);
+        Ok(revisions)
+    }
+
+    // The delivery event and the bids, status updates and location pings about a delivery, as
+    // the relays hold them now
+    pub async fn fetch_delivery_events(&self, delivery_id: &str) -> Result<Vec<Event>, Box<dyn std::error::Error>> {
+        // The delivery and its status updates are identified by the delivery ID; bids and pings by their own
+        let identified = Filter::new()
+            .kinds([35000].into_iter().chain(STATUS_UPDATE_KINDS).map(Kind::Custom))
+            .authors(self.authors.keys())
+            .custom_tag(SingleLetterTag::lowercase(Alphabet::D), [delivery_id]);
+        let tagged = Filter::new()
+            .kinds([Kind::Custom(35001), Kind::Custom(timeline::LOCATION_PING_KIND)])
+            .authors(self.authors.keys())
+            .limit(1000);
+
+        let (identified, tagged) = futures_util::future::try_join(
+            self.fetch_marketplace_events(identified, Duration::from_secs(5)),
+            self.fetch_marketplace_events(tagged, Duration::from_secs(5)),
+        ).await?;
+        let tagged = tagged.into_iter().filter(|event| query::has_tag(event, "delivery_id", delivery_id));
+        Ok(query::latest_per_identifier(identified.into_iter().chain(tagged), &self.authors))
+    }
+
+    // Events for a delivery newer than `since`, waiting up to `wait` for one to arrive
+    pub async fn wait_for_delivery_events(&self, delivery_id: &str, since: i64, wait: Duration) -> Result<Vec<Event>, Box<dyn std::error::Error>> {
+        // Listen before the catch-up fetch so nothing published in between is missed
+        let mut notifications = self.nostr_client.notifications();
+
+        let filter = Filter::new()
+            .kinds(sync::DELIVERY_EVENT_KINDS.map(Kind::Custom))
+            .authors(self.authors.keys())
+            .since(Timestamp::from((since + 1).max(0) as u64));
+
+        let events = self.fetch_marketplace_events(filter.clone(), Duration::from_secs(5)).await?;
+        let mut matching: Vec<Event> = events.into_iter()
+            .filter(|e| self.authors.trusts(e) && sync::concerns_delivery(e, delivery_id))
+            .collect();
+        if !matching.is_empty() || wait.is_zero() {
+            matching.sort_by_key(|e| e.created_at);
+            return Ok(matching);
+        }
+
+        let subscription = self.nostr_client.subscribe(vec![filter], None).await?;
+        let received = tokio::time::timeout(wait, async {
+            loop {
+                // Raw relay messages, since Event notifications skip events this client published
+                // itself; they also carry replies to other queries, hence the timestamp check
+                match notifications.recv().await {
+                    Ok(RelayPoolNotification::Message { message: RelayMessage::Event { event, .. }, .. }) => {
+                        if event.created_at.as_u64() as i64 > since
+                            && self.authors.trusts(&event)
+                            && sync::concerns_delivery(&event, delivery_id) {
+                            return Some(*event);
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
+                        log::warn!("Long poll for {} lagged, skipped {} notifications", delivery_id, skipped);
+                    }
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        }).await;
+        self.nostr_client.unsubscribe(subscription.val).await;
+
+        Ok(received.ok().flatten().into_iter().collect())
+    }
+
+    pub async fn notify_bidders_of_revision(&self, delivery: &DeliveryRequest, revision: &revisions::DeliveryRevision) {
+        let fields: Vec<&str> = revision.changes.iter()
+            .map(|c| c.field.as_str())
+            .filter(|f| revisions::MATERIAL_FIELDS.contains(f))
+            .collect();
+        let message = format!(
+            "A delivery you bid on changed ({}): {} → {}, {} sats. Your bid is on hold until you re-confirm it: {}",
+            fields.join(", "), delivery.pickup.address, delivery.dropoff.address, delivery.offer_amount, self.tracking_url(&delivery.id)
+        );
+
+        let mut couriers: Vec<&str> = delivery.bids.iter().map(|b| b.courier.as_str()).collect();
+        couriers.sort_unstable();
+        couriers.dedup();
+        for courier in couriers {
+            let Ok(pubkey) = PubkeyId::parse(courier).map(PublicKey::from) else {
+                continue;
+            };
+            if let Err(e) = self.send_dm(pubkey, message.clone()).await {
+                log::warn!("Failed to notify bidder {}: {}", courier, e);
+            }
+        }
+    }
+
+    // Deletion and restore history, kept separately from the replaceable delivery event
+    pub async fn publish_tombstone_event(&self, delivery_id: &str, action: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let now = Utc::now().timestamp();
+        let content = serde_json::json!({
+            "delivery_id": delivery_id,
+            "action": action,
+            "at": now,
+        }).to_string();
+
+        let tags = vec![
+            Tag::custom(TagKind::Custom("d".into()), vec![format!("{}:{}", delivery_id, now)]),
+            Tag::custom(TagKind::Custom("delivery_id".into()), vec![delivery_id.to_string()]),
+            Tag::custom(TagKind::Custom("action".into()), vec![action.to_string()]),
+        ];
+
+        let event = self.sign_event(EventBuilder::new(Kind::Custom(TOMBSTONE_KIND), content, tags)).await?;
+        self.send_event(event).await?;
+
+        Ok(())
+    }
+
+    // Signed by the organization's own key, whose pubkey the record carries
+    pub async fn publish_organization(&self, org: &mut organizations::Organization) -> Result<(), Box<dyn std::error::Error>> {
+        org.pubkey = Some(self.org_signing_keys(&org.id).await?.public_key().to_hex());
+        let content = serde_json::to_string(org)?;
+
+        let mut tags = vec![
+            Tag::custom(TagKind::Custom("d".into()), vec![org.id.clone()]),
+            Tag::custom(TagKind::Custom("name".into()), vec![org.name.clone()]),
+        ];
+        for member in &org.members {
+            tags.push(Tag::custom(TagKind::Custom("member".into()), vec![member.npub.clone(), format!("{:?}", member.role).to_lowercase()]));
+        }
+
+        let event = self.sign_event_for(EventBuilder::new(Kind::Custom(organizations::ORGANIZATION_KIND), content, tags), Some(&org.id)).await?;
+        self.send_event(event).await?;
+
+        Ok(())
+    }
+
+    // Assignment lifecycle event (assigned, accepted, declined) addressed to the courier
+    pub async fn publish_assignment_event(&self, delivery_id: &str, assignment: &organizations::Assignment, state: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let content = serde_json::json!({
+            "delivery_id": delivery_id,
+            "state": state,
+            "assignment": assignment,
+        }).to_string();
+
+        let mut tags = vec![
+            Tag::custom(TagKind::Custom("d".into()), vec![format!("{}:{}", delivery_id, assignment.assigned_at)]),
+            Tag::custom(TagKind::Custom("delivery_id".into()), vec![delivery_id.to_string()]),
+            Tag::custom(TagKind::Custom("courier".into()), vec![assignment.courier.clone()]),
+            Tag::custom(TagKind::Custom("dispatcher".into()), vec![assignment.dispatcher.clone()]),
+            Tag::custom(TagKind::Custom("state".into()), vec![state.to_string()]),
+        ];
+        if let Ok(courier) = PubkeyId::parse(&assignment.courier).map(PublicKey::from) {
+            tags.push(Tag::public_key(courier));
+        }
+
+        let event = self.sign_event(EventBuilder::new(Kind::Custom(organizations::ASSIGNMENT_KIND), content, tags)).await?;
+        self.send_event(event).await?;
+
+        Ok(())
+    }
+
+    pub async fn get_organization(&self, id: &str) -> Result<Option<organizations::Organization>, Box<dyn std::error::Error>> {
+        let filter = Filter::new()
+            .kind(Kind::Custom(organizations::ORGANIZATION_KIND))
+            .authors(self.authors.keys())
+            .identifier(id);
+
+        let events = self.fetch_marketplace_events(filter, Duration::from_secs(5)).await?;
+
+        // An organization key only signs its own organization's record
+        let events = events.into_iter().filter(|e| self.authors.organization_of(&e.pubkey).is_none_or(|org_id| org_id == id));
+        let latest = query::latest_per_identifier(events, &self.authors).pop();
+
+        match latest {
+            Some(event) => Ok(Some(serde_json::from_str(&event.content)?)),
+            None => Ok(None),
+        }
+    }
+
+    // NIP-78 `d` tag for a user's settings
+    pub fn settings_identifier(&self, npub: &str) -> String {
+        let token = codes::derive_token(&self.code_secret, "settings", &PubkeyId::normalize(npub));
+        settings::app_data_identifier(&token)
+    }
+
+    // Settings are NIP-44 encrypted to the marketplace key itself
+    pub async fn publish_user_settings(&self, npub: &str, user_settings: &settings::UserSettings) -> Result<(), Box<dyn std::error::Error>> {
+        let content = self.encrypt_to_self(&serde_json::to_string(user_settings)?).await?;
+
+        let tags = vec![
+            Tag::custom(TagKind::Custom("d".into()), vec![self.settings_identifier(npub)]),
+        ];
+
+        let event = self.sign_event(EventBuilder::new(Kind::ApplicationSpecificData, content, tags)).await?;
+        self.send_event(event).await?;
+
+        Ok(())
+    }
+
+    // Language to write to a user in; English when unset or unreadable
+    pub async fn language_of(&self, npub: &str) -> i18n::Language {
+        self.get_user_settings(&PubkeyId::normalize(npub)).await
+            .ok()
+            .and_then(|user_settings| user_settings.language)
+            .unwrap_or_default()
+    }
+
+    pub async fn get_user_settings(&self, npub: &str) -> Result<settings::UserSettings, Box<dyn std::error::Error>> {
+        let filter = Filter::new()
+            .kind(Kind::ApplicationSpecificData)
+            .author(self.system_pubkey)
+            .identifier(self.settings_identifier(npub));
+
+        let events = self.fetch_marketplace_events(filter, Duration::from_secs(5)).await?;
+
+        let Some(event) = query::latest_per_identifier(events, &self.authors).pop() else {
+            return Ok(settings::UserSettings::default());
+        };
+
+        let plaintext = self.decrypt_from_self(&event.content).await?;
+
+        Ok(serde_json::from_str(&plaintext)?)
+    }
+
+    // Every API key, revoked ones included
+    pub async fn api_keys(&self) -> Result<Vec<api_keys::ApiKey>, Box<dyn std::error::Error>> {
+        if let Some(keys) = self.api_keys.read().await.as_ref() {
+            return Ok(keys.clone());
+        }
+
+        let filter = Filter::new()
+            .kind(Kind::ApplicationSpecificData)
+            .author(self.system_pubkey)
+            .identifier(api_keys::APP_DATA_IDENTIFIER);
+        let events = self.fetch_marketplace_events(filter, Duration::from_secs(5)).await?;
+
+        let keys: Vec<api_keys::ApiKey> = match query::latest_per_identifier(events, &self.authors).pop() {
+            Some(event) => serde_json::from_str(&self.decrypt_from_self(&event.content).await?)?,
+            None => Vec::new(),
+        };
+        *self.api_keys.write().await = Some(keys.clone());
+        Ok(keys)
+    }
+
+    // Replace the stored API keys (hashes only), NIP-44 encrypted like settings
+    pub async fn save_api_keys(&self, keys: Vec<api_keys::ApiKey>) -> Result<(), Box<dyn std::error::Error>> {
+        let content = self.encrypt_to_self(&serde_json::to_string(&keys)?).await?;
+        let tags = vec![Tag::identifier(api_keys::APP_DATA_IDENTIFIER)];
+
+        let event = self.sign_event(EventBuilder::new(Kind::ApplicationSpecificData, content, tags)).await?;
+        self.send_event(event).await?;
+
+        *self.api_keys.write().await = Some(keys);
+        Ok(())
+    }
+
+    // The active key a secret belongs to
+    pub async fn find_api_key(&self, secret: &str) -> Result<Option<api_keys::ApiKey>, Box<dyn std::error::Error>> {
+        Ok(self.api_keys().await?.into_iter().find(|key| key.matches(secret)))
+    }
+
+    pub async fn get_user_profile(&self, npub: &str) -> Result<UserProfile, Box<dyn std::error::Error>> {
+        let key = PubkeyId::normalize(npub);
+        if let Some(profile) = self.cache.profiles.get(&key).await {
+            return Ok(profile);
+        }
+
+        let filter = Filter::new()
+            .kind(Kind::Custom(35009))
+            .authors(self.authors.keys())
+            .custom_tag(SingleLetterTag::lowercase(Alphabet::D), [key.clone()]);
+
+        let started = std::time::Instant::now();
+        let events = self.fetch_marketplace_events(filter, Duration::from_secs(5)).await?;
+
+        if let Some(event) = query::latest_per_identifier(events, &self.authors).pop() {
+            let profile = serde_json::from_str::<UserProfile>(&event.content)?;
+            self.cache.profiles.fill(&key, profile.clone(), started).await;
+            Ok(profile)
+        } else {
+            // Return default profile
+            Ok(UserProfile {
+                npub: npub.to_string(),
+                ..Default::default()
+            })
+        }
+    }
+}
+
+// The stored code secret, or on first start one seeded from the system key, so that codes and
+// links handed out before it existed stay valid
+async fn load_code_secret(client: &Client, system_pubkey: PublicKey, system_keys: &Keys, signing_timeout: Duration) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let signer = client.signer().await?;
+    let filter = Filter::new()
+        .kind(Kind::ApplicationSpecificData)
+        .author(system_pubkey)
+        .identifier(codes::SECRET_APP_DATA_IDENTIFIER);
+    let events = client.fetch_events(vec![filter], Some(Duration::from_secs(5))).await?;
+    if let Some(event) = events.into_iter().max_by_key(|e| e.created_at) {
+        let secret = tokio::time::timeout(signing_timeout, signer.nip44_decrypt(&system_pubkey, &event.content)).await??;
+        return Ok(nostr::util::hex::decode(secret)?);
+    }
+
+    let secret = system_keys.secret_key().as_secret_bytes().to_vec();
+    let content = tokio::time::timeout(signing_timeout, signer.nip44_encrypt(&system_pubkey, &nostr::util::hex::encode(&secret))).await??;
+    let builder = EventBuilder::new(Kind::ApplicationSpecificData, content, [Tag::identifier(codes::SECRET_APP_DATA_IDENTIFIER)]);
+    let event = tokio::time::timeout(signing_timeout, client.sign_event_builder(builder)).await??;
+    client.send_event(event).await?;
+    Ok(secret)
+}
+
+fn log_signed(event: &Event, request_id: Option<&str>) {
+    if let Some(id) = request_id {
+        log::info!("[{}] signed kind {} event {}", id, event.kind.as_u16(), event.id);
+    }
+}
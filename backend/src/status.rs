@@ -0,0 +1,104 @@
+// status.rs - Machine-readable server status for operators (GET /api/admin/status)
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{
+    classifieds, courier_lists, nip89, organizations, publish_log, redundancy, revisions, rotation, ACCEPTED_KIND,
+    COMPLETED_KIND, CONFIRMED_KIND, IN_TRANSIT_KIND, STARTED_KIND, TOMBSTONE_KIND,
+};
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct KindInfo {
+    pub kind: u16,
+    pub name: String,
+}
+
+impl KindInfo {
+    fn new(kind: u16, name: &str) -> Self {
+        Self { kind, name: name.to_string() }
+    }
+}
+
+// Event kinds this marketplace publishes
+pub fn published_kinds(classifieds_enabled: bool) -> Vec<KindInfo> {
+    let mut kinds = vec![
+        KindInfo::new(35000, "delivery"),
+        KindInfo::new(35001, "bid"),
+        KindInfo::new(ACCEPTED_KIND, "status: accepted"),
+        KindInfo::new(STARTED_KIND, "status: started"),
+        KindInfo::new(IN_TRANSIT_KIND, "status: in transit"),
+        KindInfo::new(COMPLETED_KIND, "status: completed"),
+        KindInfo::new(CONFIRMED_KIND, "status: confirmed"),
+        KindInfo::new(35007, "location ping"),
+        KindInfo::new(organizations::ASSIGNMENT_KIND, "assignment"),
+        KindInfo::new(35009, "user profile"),
+        KindInfo::new(organizations::ORGANIZATION_KIND, "organization"),
+        KindInfo::new(revisions::REVISION_KIND, "revision"),
+        KindInfo::new(TOMBSTONE_KIND, "deletion"),
+        KindInfo::new(30078, "user settings"),
+        KindInfo::new(courier_lists::FOLLOW_SET_KIND, "courier list"),
+        KindInfo::new(nip89::HANDLER_INFO_KIND, "handler announcement"),
+        KindInfo::new(rotation::ROTATION_KIND, "key rotation"),
+        KindInfo::new(organizations::ORG_KEY_KIND, "organization key"),
+    ];
+    if classifieds_enabled {
+        kinds.push(KindInfo::new(classifieds::CLASSIFIED_LISTING_KIND, "classified listing"));
+    }
+    kinds
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct RelayInfo {
+    pub url: String,
+    // Initialized, Pending, Connecting, Connected, Disconnected or Terminated
+    pub status: String,
+    // SOCKS5 proxy the relay is reached through
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+}
+
+// Background job runs since startup (jobs::Scheduler)
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct JobMetrics {
+    pub name: String,
+    pub interval_secs: u64,
+    pub runs: u64,
+    pub failures: u64,
+    pub running: bool,
+    pub last_started_at: Option<i64>,
+    pub last_duration_ms: Option<u64>,
+    pub last_error: Option<String>,
+}
+
+// Entries held in memory
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct CacheSizes {
+    pub indexed_deliveries: usize,
+    pub federated_deliveries: usize,
+    pub listing_interest: usize,
+    pub held_reviews: usize,
+    pub publish_log: usize,
+    pub cached_deliveries: usize,
+    pub cached_bids: usize,
+    pub cached_profiles: usize,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ServerStatus {
+    pub version: String,
+    pub system_pubkey: String,
+    pub remote_signer: bool,
+    pub kinds: Vec<KindInfo>,
+    pub federated_kinds: Vec<u16>,
+    pub relays: Vec<RelayInfo>,
+    pub caches: CacheSizes,
+    // Latest redundancy check: per-relay coverage of active deliveries' state events
+    pub redundancy: Option<redundancy::RedundancyReport>,
+    // Relay acknowledgments of everything published since startup
+    pub publishes: publish_log::PublishMetrics,
+    // Runs, failures and timing of each background job
+    pub jobs: Vec<JobMetrics>,
+    pub indexed_at: i64,
+    pub started_at: i64,
+    pub uptime_secs: i64,
+}
@@ -0,0 +1,157 @@
+// stuck.rs - Dead-man alerts: deliveries sitting in a non-terminal state past the operator's
+// threshold for it, listed for admins and pushed to a webhook and/or operators by DM
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{DeliveryRequest, DeliveryStatus};
+
+#[derive(Debug, Clone)]
+pub struct StuckConfig {
+    // Longest time a delivery may stay in each monitored status
+    pub thresholds: Vec<(DeliveryStatus, i64)>,
+    pub interval_secs: u64,
+    pub webhook: Option<StuckWebhook>,
+    // Operators DMed about each stuck delivery
+    pub operators: Vec<String>,
+}
+
+impl StuckConfig {
+    // STUCK_THRESHOLDS ("status=secs,..."; default accepted=86400,intransit=43200,
+    // completed=259200,disputed=259200), STUCK_CHECK_SECS (default 300),
+    // STUCK_ALERT_WEBHOOK_URL with optional STUCK_ALERT_WEBHOOK_TOKEN, and STUCK_ALERT_NPUBS
+    pub fn from_env() -> Result<Self, String> {
+        let thresholds = match std::env::var("STUCK_THRESHOLDS") {
+            Ok(raw) => parse_thresholds(&raw)?,
+            Err(_) => vec![
+                (DeliveryStatus::Accepted, 86400),
+                (DeliveryStatus::InTransit, 43200),
+                (DeliveryStatus::Completed, 259200),
+                (DeliveryStatus::Disputed, 259200),
+            ],
+        };
+        let interval_secs = std::env::var("STUCK_CHECK_SECS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .filter(|s| *s > 0)
+            .unwrap_or(300);
+        let webhook = std::env::var("STUCK_ALERT_WEBHOOK_URL").ok().filter(|u| !u.is_empty()).map(|url| {
+            StuckWebhook::new(&url, std::env::var("STUCK_ALERT_WEBHOOK_TOKEN").ok().filter(|t| !t.is_empty()))
+        });
+        let operators = std::env::var("STUCK_ALERT_NPUBS")
+            .map(|raw| raw.split(',').map(str::trim).filter(|n| !n.is_empty()).map(String::from).collect())
+            .unwrap_or_default();
+        Ok(Self { thresholds, interval_secs, webhook, operators })
+    }
+
+    // Whether anyone is told; the admin list works either way
+    pub fn alerts_enabled(&self) -> bool {
+        !self.thresholds.is_empty() && (self.webhook.is_some() || !self.operators.is_empty())
+    }
+
+    pub fn threshold(&self, status: &DeliveryStatus) -> Option<i64> {
+        self.thresholds.iter().find(|(s, _)| s == status).map(|(_, secs)| *secs)
+    }
+}
+
+fn parse_thresholds(raw: &str) -> Result<Vec<(DeliveryStatus, i64)>, String> {
+    raw.split(',')
+        .filter(|entry| !entry.trim().is_empty())
+        .map(|entry| {
+            let (status, secs) = entry.split_once('=').unwrap_or((entry, ""));
+            let status = serde_json::from_value::<DeliveryStatus>(serde_json::Value::String(status.trim().to_lowercase()))
+                .ok()
+                .filter(|s| !is_terminal(s));
+            status.zip(secs.trim().parse::<i64>().ok().filter(|s| *s > 0))
+                .ok_or_else(|| format!("Invalid STUCK_THRESHOLDS entry '{}'", entry.trim()))
+        })
+        .collect()
+}
+
+fn is_terminal(status: &DeliveryStatus) -> bool {
+    matches!(status, DeliveryStatus::Confirmed | DeliveryStatus::Expired | DeliveryStatus::Deleted)
+}
+
+// When the delivery entered its current status, as far as it records. Deliveries from before
+// a timestamp was kept fall back to an earlier one.
+pub fn status_since(delivery: &DeliveryRequest) -> i64 {
+    let accepted = delivery.accepted_at.unwrap_or(delivery.created_at);
+    match delivery.status {
+        DeliveryStatus::Accepted => accepted,
+        DeliveryStatus::InTransit => delivery.picked_up_at.unwrap_or(accepted),
+        DeliveryStatus::Completed => delivery.completed_at.unwrap_or(accepted),
+        DeliveryStatus::Disputed => delivery.disputed_at.or(delivery.completed_at).unwrap_or(accepted),
+        _ => delivery.created_at,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct StuckDelivery {
+    pub delivery_id: String,
+    pub status: DeliveryStatus,
+    pub since: i64,
+    pub stuck_for_secs: i64,
+    pub threshold_secs: i64,
+    pub sender: String,
+    pub courier: Option<String>,
+}
+
+impl StuckDelivery {
+    // Alerts go out once per delivery and status
+    pub fn key(&self) -> String {
+        format!("{}:{:?}", self.delivery_id, self.status)
+    }
+}
+
+// Deliveries past their status's threshold, longest overdue first
+pub fn find(deliveries: &[DeliveryRequest], config: &StuckConfig, now: i64) -> Vec<StuckDelivery> {
+    let mut stuck: Vec<StuckDelivery> = deliveries
+        .iter()
+        .filter_map(|d| {
+            let threshold_secs = config.threshold(&d.status)?;
+            let since = status_since(d);
+            (now - since > threshold_secs).then(|| StuckDelivery {
+                delivery_id: d.id.clone(),
+                status: d.status.clone(),
+                since,
+                stuck_for_secs: now - since,
+                threshold_secs,
+                sender: d.sender.clone(),
+                courier: d.accepted_courier().map(String::from),
+            })
+        })
+        .collect();
+    stuck.sort_by_key(|s| std::cmp::Reverse(s.stuck_for_secs - s.threshold_secs));
+    stuck
+}
+
+// Alerts POSTed as `{"alert": "stuck_delivery", ...}` with an optional bearer token
+#[derive(Debug, Clone)]
+pub struct StuckWebhook {
+    client: reqwest::Client,
+    url: String,
+    token: Option<String>,
+}
+
+impl StuckWebhook {
+    pub fn new(url: &str, token: Option<String>) -> Self {
+        let client = reqwest::Client::builder()
+            .user_agent(concat!("nostr-delivery-backend/", env!("CARGO_PKG_VERSION")))
+            .timeout(std::time::Duration::from_secs(10))
+            .build()
+            .unwrap_or_default();
+
+        Self { client, url: url.to_string(), token }
+    }
+
+    pub async fn send(&self, stuck: &StuckDelivery, tracking_url: &str) -> Result<(), reqwest::Error> {
+        let mut body = serde_json::to_value(stuck).unwrap_or_default();
+        body["alert"] = "stuck_delivery".into();
+        body["tracking_url"] = tracking_url.into();
+        let mut request = self.client.post(&self.url).json(&body);
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        request.send().await?.error_for_status()?;
+        Ok(())
+    }
+}
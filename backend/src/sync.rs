@@ -0,0 +1,154 @@
+// sync.rs - Change tracking between indexer passes, for delta sync clients
+use nostr::Event;
+use serde::Serialize;
+use std::collections::HashMap;
+use utoipa::ToSchema;
+
+use crate::query::{has_tag, identifier};
+use crate::{DeliveryBid, DeliveryRequest, DeliveryStatus, TOMBSTONE_KIND};
+
+// Marketplace event kinds that describe a delivery or its lifecycle
+pub const DELIVERY_EVENT_KINDS: std::ops::RangeInclusive<u16> = 35000..=TOMBSTONE_KIND;
+pub const MAX_LONG_POLL_SECS: u64 = 30;
+
+// When each delivery (and its status) last changed, as observed by the indexer
+#[derive(Debug, Clone, Default)]
+pub struct ChangeLog {
+    pub delivery_changed_at: HashMap<String, i64>,
+    pub status_changed_at: HashMap<String, i64>,
+    fingerprints: HashMap<String, String>,
+    statuses: HashMap<String, DeliveryStatus>,
+}
+
+// Latest timestamp recorded on the delivery itself
+pub fn last_activity(delivery: &DeliveryRequest) -> i64 {
+    delivery.bids.iter()
+        .map(|b| b.created_at)
+        .chain(delivery.completed_at)
+        .chain(delivery.deleted_at)
+        .fold(delivery.created_at, i64::max)
+}
+
+impl ChangeLog {
+    // Compare a fresh snapshot with the previous one. Deliveries seen for the first time are
+    // dated by their own activity; later changes by the indexer pass that noticed them.
+    pub fn update(&mut self, deliveries: &[DeliveryRequest], now: i64) {
+        for delivery in deliveries {
+            let fingerprint = serde_json::to_string(delivery).unwrap_or_default();
+            let first_seen = !self.fingerprints.contains_key(&delivery.id);
+
+            if first_seen {
+                let at = last_activity(delivery);
+                self.delivery_changed_at.insert(delivery.id.clone(), at);
+                self.status_changed_at.insert(delivery.id.clone(), at);
+            } else {
+                if self.fingerprints.get(&delivery.id) != Some(&fingerprint) {
+                    self.delivery_changed_at.insert(delivery.id.clone(), now);
+                }
+                if self.statuses.get(&delivery.id) != Some(&delivery.status) {
+                    self.status_changed_at.insert(delivery.id.clone(), now);
+                }
+            }
+
+            self.fingerprints.insert(delivery.id.clone(), fingerprint);
+            self.statuses.insert(delivery.id.clone(), delivery.status.clone());
+        }
+    }
+
+    pub fn changed_since(&self, delivery_id: &str, since: i64) -> bool {
+        self.delivery_changed_at.get(delivery_id).is_some_and(|at| *at > since)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct BidChange {
+    pub delivery_id: String,
+    pub bid: DeliveryBid,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct StatusChange {
+    pub delivery_id: String,
+    pub status: DeliveryStatus,
+    pub changed_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SyncResponse {
+    pub since: i64,
+    // Pass as `since` on the next call
+    pub next_since: i64,
+    pub deliveries: Vec<DeliveryRequest>,
+    pub bids: Vec<BidChange>,
+    pub status_updates: Vec<StatusChange>,
+}
+
+impl SyncResponse {
+    pub fn build<'a>(log: &ChangeLog, deliveries: impl IntoIterator<Item = &'a DeliveryRequest>, since: i64, indexed_at: i64) -> Self {
+        let mut response = SyncResponse {
+            since,
+            next_since: indexed_at,
+            deliveries: Vec::new(),
+            bids: Vec::new(),
+            status_updates: Vec::new(),
+        };
+
+        for delivery in deliveries {
+            if !log.changed_since(&delivery.id, since) {
+                continue;
+            }
+
+            response.bids.extend(delivery.bids.iter()
+                .filter(|b| b.created_at > since)
+                .map(|bid| BidChange { delivery_id: delivery.id.clone(), bid: bid.clone() }));
+
+            if let Some(changed_at) = log.status_changed_at.get(&delivery.id).filter(|at| **at > since) {
+                response.status_updates.push(StatusChange {
+                    delivery_id: delivery.id.clone(),
+                    status: delivery.status.clone(),
+                    changed_at: *changed_at,
+                });
+            }
+
+            response.deliveries.push(delivery.clone());
+        }
+
+        response.status_updates.sort_by_key(|s| s.changed_at);
+        response
+    }
+}
+
+// Whether a marketplace event belongs to the delivery: the delivery itself (d = ID) or an
+// event tagged with its ID
+pub fn concerns_delivery(event: &Event, delivery_id: &str) -> bool {
+    DELIVERY_EVENT_KINDS.contains(&event.kind.as_u16())
+        && (identifier(event) == delivery_id || has_tag(event, "delivery_id", delivery_id))
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct EventSummary {
+    pub id: String,
+    pub kind: u16,
+    pub created_at: i64,
+}
+
+impl From<&Event> for EventSummary {
+    fn from(event: &Event) -> Self {
+        Self {
+            id: event.id.to_hex(),
+            kind: event.kind.as_u16(),
+            created_at: event.created_at.as_u64() as i64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PollResponse {
+    pub delivery_id: String,
+    // Empty when the wait timed out
+    pub events: Vec<EventSummary>,
+    // Pass as `since` on the next poll
+    pub next_since: i64,
+    // Current state, included when something changed
+    pub delivery: Option<DeliveryRequest>,
+}
@@ -0,0 +1,238 @@
+// testing.rs - Hermetic end-to-end tests: an in-process relay, an AppState wired to it and
+// DeliveryRequest/DeliveryBid fixtures
+use chrono::Utc;
+use nostr_relay_builder::{LocalRelay, RelayBuilder};
+
+use crate::location_codes;
+use crate::state::AppState;
+use crate::{calculate_distance, Amount, DeliveryBid, DeliveryRequest, DeliveryStatus, GeoPoint, Location, PackageInfo};
+
+// In-memory relay on a random loopback port, shut down when dropped
+pub struct TestRelay {
+    relay: LocalRelay,
+}
+
+impl TestRelay {
+    pub async fn start() -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self {
+            relay: LocalRelay::run(RelayBuilder::default()).await?,
+        })
+    }
+
+    pub fn url(&self) -> String {
+        self.relay.url()
+    }
+
+    // AppState that reads from and publishes to this relay only. Other settings come from
+    // the environment as usual, except geocoding and what3words, which are switched off.
+    pub async fn app_state(&self) -> Result<AppState, Box<dyn std::error::Error>> {
+        let mut state = AppState::new(vec![self.url()]).await?;
+        state.geocoder = None;
+        state.what3words = None;
+        state.plus_codes = std::sync::Arc::new(location_codes::PlusCodeResolver::new(None));
+        Ok(state)
+    }
+}
+
+impl Drop for TestRelay {
+    fn drop(&mut self) {
+        self.relay.shutdown();
+    }
+}
+
+// Open delivery fixture: a 1 km hop across Manhattan with one small package
+pub struct DeliveryBuilder {
+    delivery: DeliveryRequest,
+}
+
+impl DeliveryBuilder {
+    pub fn new(sender: &str) -> Self {
+        let now = Utc::now().timestamp();
+        let pickup = GeoPoint { lat: 40.7580, lng: -73.9855 };
+        let dropoff = GeoPoint { lat: 40.7488, lng: -73.9857 };
+
+        Self {
+            delivery: DeliveryRequest {
+                id: format!("delivery_test_{}", random_suffix()),
+                sender: sender.to_string(),
+                distance_meters: Some(calculate_distance(&pickup, &dropoff)),
+                pickup: Location { address: "Times Square, New York".to_string(), coordinates: Some(pickup), instructions: None, contact: None, what3words: None, plus_code: None },
+                dropoff: Location { address: "Empire State Building, New York".to_string(), coordinates: Some(dropoff), instructions: None, contact: None, what3words: None, plus_code: None },
+                packages: vec![PackageInfo {
+                    size: "small".to_string(),
+                    weight: Some(1.0),
+                    description: "Test parcel".to_string(),
+                    fragile: false,
+                    requires_signature: false,
+                }],
+                package_photos: vec![],
+                offer_amount: Amount::from_sats(5000).unwrap_or_default(),
+                insurance_amount: None,
+                time_window: "asap".to_string(),
+                timezone: None,
+                expires_at: Some(now + 604800),
+                status: DeliveryStatus::Open,
+                bids: vec![],
+                accepted_bid: None,
+                accepted_at: None,
+                listed_amount: None,
+                created_at: now,
+                proof_of_delivery: None,
+                sender_feedback: None,
+                sender_rating: None,
+                sender_ratings: None,
+                courier_ratings: None,
+                recipient_confirmation: None,
+                completed_at: None,
+                picked_up_at: None,
+                disputed_at: None,
+                min_courier_reputation: None,
+                min_completed_deliveries: None,
+                allowed_couriers: vec![],
+                private: false,
+                organization: None,
+                assignment: None,
+                out_of_service_area: false,
+                deleted_at: None,
+                deleted_from: None,
+                auto_relist: false,
+                relisted_from: None,
+                relisted_as: None,
+                failed_attempts: vec![],
+                returning: false,
+                no_shows: vec![],
+                fraud_flags: vec![],
+                origin: None,
+                sealed_contacts: None,
+                contact_reveal: None,
+                sealed_instructions: None,
+                instructions_reveal: None,
+                local_times: None,
+            },
+        }
+    }
+
+    pub fn id(mut self, id: &str) -> Self {
+        self.delivery.id = id.to_string();
+        self
+    }
+
+    pub fn offer_amount(mut self, amount: Amount) -> Self {
+        self.delivery.offer_amount = amount;
+        self
+    }
+
+    pub fn status(mut self, status: DeliveryStatus) -> Self {
+        self.delivery.status = status;
+        self
+    }
+
+    pub fn pickup(mut self, address: &str, point: GeoPoint) -> Self {
+        self.delivery.pickup = Location { address: address.to_string(), coordinates: Some(point), instructions: None, contact: None, what3words: None, plus_code: None };
+        self.update_distance();
+        self
+    }
+
+    pub fn dropoff(mut self, address: &str, point: GeoPoint) -> Self {
+        self.delivery.dropoff = Location { address: address.to_string(), coordinates: Some(point), instructions: None, contact: None, what3words: None, plus_code: None };
+        self.update_distance();
+        self
+    }
+
+    pub fn created_at(mut self, timestamp: i64) -> Self {
+        self.delivery.created_at = timestamp;
+        self
+    }
+
+    pub fn allowed_couriers(mut self, couriers: &[&str]) -> Self {
+        self.delivery.allowed_couriers = couriers.iter().map(|c| c.to_string()).collect();
+        self
+    }
+
+    pub fn bid(mut self, bid: DeliveryBid) -> Self {
+        self.delivery.bids.push(bid);
+        self
+    }
+
+    // Adds the bid and moves the delivery to Accepted with it
+    pub fn accepted(mut self, bid: DeliveryBid) -> Self {
+        self.delivery.accepted_bid = Some(bid.id.clone());
+        self.delivery.status = DeliveryStatus::Accepted;
+        self.delivery.bids.push(bid);
+        self
+    }
+
+    pub fn build(self) -> DeliveryRequest {
+        self.delivery
+    }
+
+    fn update_distance(&mut self) {
+        self.delivery.distance_meters = match (&self.delivery.pickup.coordinates, &self.delivery.dropoff.coordinates) {
+            (Some(p1), Some(p2)) => Some(calculate_distance(p1, p2)),
+            _ => None,
+        };
+    }
+}
+
+// Bid fixture from a courier with a clean record
+pub struct BidBuilder {
+    bid: DeliveryBid,
+}
+
+impl BidBuilder {
+    pub fn new(courier: &str) -> Self {
+        Self {
+            bid: DeliveryBid {
+                id: format!("bid_test_{}", random_suffix()),
+                courier: courier.to_string(),
+                amount: Amount::from_sats(4500).unwrap_or_default(),
+                pickup_eta: Some(Utc::now().timestamp() + 900),
+                dropoff_eta: Some(Utc::now().timestamp() + 1800),
+                reputation: 5.0,
+                completed_deliveries: 0,
+                recent_rating: None,
+                ratings: None,
+                message: None,
+                created_at: Utc::now().timestamp(),
+                stale: false,
+            },
+        }
+    }
+
+    pub fn id(mut self, id: &str) -> Self {
+        self.bid.id = id.to_string();
+        self
+    }
+
+    pub fn amount(mut self, amount: Amount) -> Self {
+        self.bid.amount = amount;
+        self
+    }
+
+    pub fn etas(mut self, pickup_eta: i64, dropoff_eta: i64) -> Self {
+        self.bid.pickup_eta = Some(pickup_eta);
+        self.bid.dropoff_eta = Some(dropoff_eta);
+        self
+    }
+
+    pub fn reputation(mut self, reputation: f32, completed_deliveries: u32) -> Self {
+        self.bid.reputation = reputation;
+        self.bid.completed_deliveries = completed_deliveries;
+        self
+    }
+
+    pub fn message(mut self, message: &str) -> Self {
+        self.bid.message = Some(message.to_string());
+        self
+    }
+
+    pub fn build(self) -> DeliveryBid {
+        self.bid
+    }
+}
+
+// Fixture IDs must not collide when tests create several within the same millisecond
+fn random_suffix() -> String {
+    let bytes: [u8; 6] = nostr::secp256k1::rand::random();
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
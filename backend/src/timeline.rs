@@ -0,0 +1,158 @@
+// timeline.rs - A delivery's history as normalized milestones for tracking UIs, each traced back
+// to the event that recorded it. Milestones kept on the delivery itself (failed attempts, no-shows,
+// disputes) point at the current delivery event, since relays only keep its latest version.
+use nostr::Event;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::delivery_attempts::FailureReason;
+use crate::tracking::LocationPing;
+use crate::{
+    Amount, DeliveryBid, DeliveryRequest, DeliveryStatus, DeliveryUpdate, GeoPoint, ACCEPTED_KIND,
+    COMPLETED_KIND, CONFIRMED_KIND, IN_TRANSIT_KIND,
+};
+
+pub const LOCATION_PING_KIND: u16 = 35007;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MilestoneKind {
+    Created,
+    FirstBid,
+    Accepted,
+    // The accepted courier never started and the delivery reopened
+    NoShow,
+    PickedUp,
+    // A location ping from the courier while in transit
+    InTransit,
+    FailedAttempt,
+    Delivered,
+    RecipientConfirmed,
+    Confirmed,
+    Disputed,
+    Expired,
+    Deleted,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Milestone {
+    pub kind: MilestoneKind,
+    pub at: i64,
+    // The sender or courier the milestone is attributed to; none for the recipient and the marketplace
+    pub actor: Option<String>,
+    // Hex ID of the event that records it; none when no event does
+    pub event_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub amount: Option<Amount>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<GeoPoint>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<FailureReason>,
+}
+
+impl Milestone {
+    fn new(kind: MilestoneKind, at: i64, actor: Option<&str>, event: Option<&Event>) -> Self {
+        Self {
+            kind,
+            at,
+            actor: actor.map(str::to_string),
+            event_id: event.map(|e| e.id.to_hex()),
+            amount: None,
+            location: None,
+            reason: None,
+        }
+    }
+}
+
+// Milestones in order, from the delivery as read and the events about it (the delivery event,
+// bids, status updates and location pings)
+pub fn build(delivery: &DeliveryRequest, events: &[Event]) -> Vec<Milestone> {
+    let of_kind = |kind: u16| events.iter().filter(move |e| e.kind.as_u16() == kind);
+    let delivery_event = of_kind(35000).max_by_key(|e| e.created_at);
+    let sender = Some(delivery.sender.as_str());
+    let courier = delivery.accepted_courier();
+
+    let mut milestones = vec![Milestone::new(MilestoneKind::Created, delivery.created_at, sender, delivery_event)];
+
+    let first_bid = of_kind(35001)
+        .filter_map(|e| Some((serde_json::from_str::<DeliveryBid>(&e.content).ok()?, e)))
+        .min_by_key(|(bid, _)| bid.created_at);
+    if let Some((bid, event)) = first_bid {
+        milestones.push(Milestone {
+            amount: Some(bid.amount),
+            ..Milestone::new(MilestoneKind::FirstBid, bid.created_at, Some(&bid.courier), Some(event))
+        });
+    }
+
+    // Status updates, or the delivery's own record of them when the event is gone
+    let update = |kind: u16| {
+        of_kind(kind)
+            .max_by_key(|e| e.created_at)
+            .map(|e| (serde_json::from_str::<DeliveryUpdate>(&e.content).map_or(e.created_at.as_u64() as i64, |u| u.timestamp), e))
+    };
+    let mut status_milestone = |kind: MilestoneKind, event_kind: u16, fallback: Option<i64>, actor: Option<&str>| {
+        match update(event_kind) {
+            // Updates from before the current acceptance belong to a courier who didn't show
+            Some((at, event)) if at >= delivery.accepted_at.unwrap_or(i64::MIN) => {
+                milestones.push(Milestone::new(kind, at, actor, Some(event)));
+            }
+            _ => {
+                if let Some(at) = fallback {
+                    milestones.push(Milestone::new(kind, at, actor, None));
+                }
+            }
+        }
+    };
+    if delivery.accepted_bid.is_some() {
+        status_milestone(MilestoneKind::Accepted, ACCEPTED_KIND, delivery.accepted_at, sender);
+        if delivery.picked_up_at.is_some() || matches!(delivery.status, DeliveryStatus::InTransit | DeliveryStatus::Completed | DeliveryStatus::Confirmed) {
+            status_milestone(MilestoneKind::PickedUp, IN_TRANSIT_KIND, delivery.picked_up_at, courier);
+        }
+        if delivery.completed_at.is_some() {
+            status_milestone(MilestoneKind::Delivered, COMPLETED_KIND, delivery.completed_at, courier);
+        }
+        if delivery.status == DeliveryStatus::Confirmed {
+            status_milestone(MilestoneKind::Confirmed, CONFIRMED_KIND, None, sender);
+        }
+    }
+
+    for no_show in &delivery.no_shows {
+        milestones.push(Milestone::new(MilestoneKind::NoShow, no_show.reverted_at, Some(&no_show.courier), delivery_event));
+    }
+
+    for event in of_kind(LOCATION_PING_KIND) {
+        let Ok(ping) = serde_json::from_str::<LocationPing>(&event.content) else {
+            continue;
+        };
+        milestones.push(Milestone {
+            location: Some(GeoPoint { lat: ping.lat, lng: ping.lng }),
+            ..Milestone::new(MilestoneKind::InTransit, ping.timestamp, Some(&ping.courier), Some(event))
+        });
+    }
+
+    for attempt in &delivery.failed_attempts {
+        milestones.push(Milestone {
+            location: attempt.location.clone(),
+            reason: Some(attempt.reason),
+            ..Milestone::new(MilestoneKind::FailedAttempt, attempt.at, Some(&attempt.courier), delivery_event)
+        });
+    }
+
+    if let Some(confirmation) = &delivery.recipient_confirmation {
+        milestones.push(Milestone::new(MilestoneKind::RecipientConfirmed, confirmation.confirmed_at, None, delivery_event));
+    }
+    if let Some(at) = delivery.disputed_at {
+        milestones.push(Milestone::new(MilestoneKind::Disputed, at, None, delivery_event));
+    }
+    if delivery.status == DeliveryStatus::Expired {
+        let at = delivery.expires_at.unwrap_or_else(|| delivery_event.map_or(delivery.created_at, |e| e.created_at.as_u64() as i64));
+        milestones.push(Milestone::new(MilestoneKind::Expired, at, None, delivery_event));
+    }
+    if let Some(at) = delivery.deleted_at {
+        milestones.push(Milestone::new(MilestoneKind::Deleted, at, sender, delivery_event));
+    }
+
+    // Same-second milestones keep the lifecycle order they were added in
+    milestones.sort_by_key(|m| m.at);
+    milestones
+}
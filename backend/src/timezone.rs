@@ -0,0 +1,140 @@
+// timezone.rs - Sender time zones: time windows given in local time are resolved in the
+// delivery's zone, and responses carry localized ISO-8601 times next to the Unix timestamps
+use chrono::{DateTime, LocalResult, NaiveDateTime, SecondsFormat, TimeZone};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{DeliveryRequest, TimeWindow};
+
+// Local date-times accepted as time window bounds, without an offset
+const LOCAL_FORMATS: &[&str] = &["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"];
+
+// IANA zone name, e.g. "Europe/Berlin"
+pub fn parse(name: &str) -> Result<Tz, String> {
+    name.trim().parse::<Tz>().map_err(|_| format!("Unknown time zone '{}'", name.trim()))
+}
+
+pub fn format(timestamp: i64, zone: Tz) -> Option<String> {
+    DateTime::from_timestamp(timestamp, 0).map(|dt| dt.with_timezone(&zone).to_rfc3339_opts(SecondsFormat::Secs, false))
+}
+
+// A delivery's timestamps in its sender's zone (UTC when none is set)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct LocalTimes {
+    pub timezone: String,
+    pub created_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completed_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window_start: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window_end: Option<String>,
+}
+
+impl LocalTimes {
+    pub fn for_delivery(delivery: &DeliveryRequest) -> Self {
+        let zone = delivery.timezone.as_deref().and_then(|name| parse(name).ok()).unwrap_or(Tz::UTC);
+        let window = TimeWindow::parse(&delivery.time_window);
+        Self {
+            timezone: zone.name().to_string(),
+            created_at: format(delivery.created_at, zone),
+            expires_at: delivery.expires_at.and_then(|t| format(t, zone)),
+            completed_at: delivery.completed_at.and_then(|t| format(t, zone)),
+            window_start: window.start.and_then(|t| format(t, zone)),
+            window_end: window.end.and_then(|t| format(t, zone)),
+        }
+    }
+}
+
+// A local date-time in `zone`. Times skipped or repeated by a DST change are refused rather
+// than guessed.
+fn resolve_local(value: &str, zone: Tz) -> Option<Result<i64, String>> {
+    let naive = LOCAL_FORMATS.iter().find_map(|f| NaiveDateTime::parse_from_str(value, f).ok())?;
+    Some(match zone.from_local_datetime(&naive) {
+        LocalResult::Single(dt) => Ok(dt.timestamp()),
+        LocalResult::Ambiguous(_, _) => Err(format!("{} occurs twice in {} (clocks go back); give it with an offset", value, zone.name())),
+        LocalResult::None => Err(format!("{} does not exist in {} (clocks go forward)", value, zone.name())),
+    })
+}
+
+// Rewrite a "start/end" window whose bounds are local date-times as the stored UTC interval.
+// Windows already in RFC 3339, and free text like "asap", are kept as they are.
+pub fn normalize_window(raw: &str, zone: Tz) -> Result<String, String> {
+    let Some((start, end)) = raw.trim().split_once('/') else {
+        return Ok(raw.to_string());
+    };
+
+    let mut bounds = [None, None];
+    let mut local = false;
+    for (bound, part) in bounds.iter_mut().zip([start.trim(), end.trim()]) {
+        if part == ".." || part.is_empty() {
+            continue;
+        }
+        if let Ok(dt) = DateTime::parse_from_rfc3339(part) {
+            *bound = Some(dt.timestamp());
+            continue;
+        }
+        match resolve_local(part, zone) {
+            Some(resolved) => {
+                *bound = Some(resolved?);
+                local = true;
+            }
+            None => return Ok(raw.to_string()),
+        }
+    }
+
+    if !local {
+        return Ok(raw.to_string());
+    }
+    let [start, end] = bounds;
+    Ok(TimeWindow { start, end, label: None }.to_raw())
+}
+
+// Problems with a window a sender is setting: one that ends before it starts or has already ended
+pub fn window_error(window: &TimeWindow, now: i64) -> Option<String> {
+    if !window.is_valid() {
+        return Some("Time window must end after it starts".to_string());
+    }
+    match window.end {
+        Some(end) if end < now => Some("Time window has already ended".to_string()),
+        _ => None,
+    }
+}
+
+// Resolve a time window given in the sender's local time, and refuse one that can't be met
+pub fn schedule_error(delivery: &mut DeliveryRequest, now: i64) -> Option<String> {
+    if let Some(name) = &delivery.timezone {
+        let zone = match parse(name) {
+            Ok(zone) => zone,
+            Err(e) => return Some(e),
+        };
+        match normalize_window(&delivery.time_window, zone) {
+            Ok(window) => delivery.time_window = window,
+            Err(e) => return Some(e),
+        }
+    }
+    window_error(&TimeWindow::parse(&delivery.time_window), now)
+}
+
+// Bid ETAs may run a little behind the server's clock
+pub const ETA_CLOCK_SKEW_SECS: i64 = 60;
+
+// Problems with the ETAs of a bid: in the past, out of order, or a handover outside the window
+pub fn eta_error(pickup_eta: i64, dropoff_eta: i64, window: &TimeWindow, now: i64) -> Option<String> {
+    if pickup_eta < now - ETA_CLOCK_SKEW_SECS {
+        return Some("Pickup ETA is in the past".to_string());
+    }
+    if dropoff_eta < pickup_eta {
+        return Some("Dropoff ETA must not be before the pickup ETA".to_string());
+    }
+    if window.start.is_some_and(|start| dropoff_eta < start) {
+        return Some("Dropoff ETA is before the time window starts".to_string());
+    }
+    if window.end.is_some_and(|end| dropoff_eta > end) {
+        return Some("Dropoff ETA is after the time window ends".to_string());
+    }
+    None
+}
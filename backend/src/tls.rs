@@ -0,0 +1,73 @@
+// tls.rs - Native HTTPS: connections are terminated with rustls and forwarded to the HTTP
+// server on a private loopback listener
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::TlsAcceptor;
+
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    pub cert_path: String,
+    pub key_path: String,
+}
+
+impl TlsConfig {
+    // TLS_CERT_PATH and TLS_KEY_PATH (PEM); both or neither must be set
+    pub fn from_env() -> Result<Option<Self>, String> {
+        let cert = std::env::var("TLS_CERT_PATH").ok().filter(|v| !v.is_empty());
+        let key = std::env::var("TLS_KEY_PATH").ok().filter(|v| !v.is_empty());
+        match (cert, key) {
+            (Some(cert_path), Some(key_path)) => Ok(Some(Self { cert_path, key_path })),
+            (None, None) => Ok(None),
+            _ => Err("TLS_CERT_PATH and TLS_KEY_PATH must be set together".to_string()),
+        }
+    }
+
+    pub fn acceptor(&self) -> Result<TlsAcceptor, String> {
+        let certs = CertificateDer::pem_file_iter(&self.cert_path)
+            .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| format!("Failed to read certificate {}: {}", self.cert_path, e))?;
+        let key = PrivateKeyDer::from_pem_file(&self.key_path)
+            .map_err(|e| format!("Failed to read private key {}: {}", self.key_path, e))?;
+
+        let mut config = rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(|e| e.to_string())?
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .map_err(|e| format!("Invalid certificate or key: {}", e))?;
+        // Forwarded to an HTTP/1.1 listener
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+        Ok(TlsAcceptor::from(Arc::new(config)))
+    }
+}
+
+// Accept TLS connections and pipe the decrypted stream to `upstream`
+pub async fn serve(listener: TcpListener, acceptor: TlsAcceptor, upstream: SocketAddr) {
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                log::warn!("TLS accept failed: {}", e);
+                continue;
+            }
+        };
+
+        let acceptor = acceptor.clone();
+        tokio::spawn(async move {
+            if let Err(e) = forward(stream, acceptor, upstream).await {
+                log::debug!("TLS connection from {} closed: {}", peer, e);
+            }
+        });
+    }
+}
+
+async fn forward(stream: TcpStream, acceptor: TlsAcceptor, upstream: SocketAddr) -> std::io::Result<()> {
+    let mut tls = acceptor.accept(stream).await?;
+    let mut plain = TcpStream::connect(upstream).await?;
+    tokio::io::copy_bidirectional(&mut tls, &mut plain).await?;
+    Ok(())
+}
@@ -0,0 +1,166 @@
+// tracking.rs - Courier location pings and track exports
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct LocationPing {
+    pub delivery_id: String,
+    pub courier: String,
+    pub lat: f64,
+    pub lng: f64,
+    pub accuracy: Option<f32>,
+    pub timestamp: i64,
+    // Anomalies relative to the courier's previous ping
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub anomalies: Vec<LocationAnomaly>,
+}
+
+pub const DEFAULT_MAX_TRACKING_GAP_SECS: i64 = 600;
+// Jumps shorter than this are GPS noise, whatever the time between pings
+const MIN_JUMP_METERS: f64 = 100.0;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LocationAnomaly {
+    // Moved faster than the courier could travel
+    Teleport { distance_meters: f64, speed_mps: f64 },
+    // No pings for longer than the allowed gap
+    Gap { seconds: i64 },
+}
+
+pub const DEFAULT_PICKUP_PING_MAX_AGE_SECS: i64 = 300;
+
+// Couriers must have pinged near the pickup recently before going InTransit
+#[derive(Debug, Clone)]
+pub struct PickupProximity {
+    pub max_distance_meters: f64,
+    pub max_age_secs: i64,
+}
+
+impl PickupProximity {
+    // Enabled by PICKUP_PROXIMITY_METERS; ping freshness from PICKUP_PING_MAX_AGE_SECS
+    pub fn from_env() -> Option<Self> {
+        let max_distance_meters = std::env::var("PICKUP_PROXIMITY_METERS")
+            .ok()
+            .and_then(|v| v.parse::<f64>().ok())
+            .filter(|m| *m > 0.0)?;
+        let max_age_secs = std::env::var("PICKUP_PING_MAX_AGE_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_PICKUP_PING_MAX_AGE_SECS);
+
+        Some(Self { max_distance_meters, max_age_secs })
+    }
+
+    pub fn check(&self, pickup: &crate::GeoPoint, pings: &[LocationPing], now: i64) -> Result<(), String> {
+        let recent = pings.iter().rev().find(|p| now - p.timestamp <= self.max_age_secs).ok_or_else(|| {
+            format!("No location ping in the last {} seconds; share your location at the pickup first", self.max_age_secs)
+        })?;
+
+        let distance = crate::calculate_distance(&crate::GeoPoint { lat: recent.lat, lng: recent.lng }, pickup);
+        if distance > self.max_distance_meters {
+            return Err(format!(
+                "Courier is {:.0} m from the pickup; must be within {:.0} m to start transit",
+                distance, self.max_distance_meters
+            ));
+        }
+        Ok(())
+    }
+}
+
+// Distance and speed between two pings, ignoring jumps within GPS noise
+pub fn implied_speed(from: &LocationPing, to: &LocationPing) -> Option<(f64, f64)> {
+    let distance = crate::calculate_distance(
+        &crate::GeoPoint { lat: from.lat, lng: from.lng },
+        &crate::GeoPoint { lat: to.lat, lng: to.lng },
+    );
+    if distance < MIN_JUMP_METERS {
+        return None;
+    }
+    let elapsed = (to.timestamp - from.timestamp).max(1) as f64;
+    Some((distance, distance / elapsed))
+}
+
+pub fn detect_anomalies(previous: &LocationPing, next: &LocationPing, max_speed_mps: f64, max_gap_secs: i64) -> Vec<LocationAnomaly> {
+    let mut anomalies = Vec::new();
+
+    if let Some((distance, speed)) = implied_speed(previous, next) {
+        if speed > max_speed_mps {
+            anomalies.push(LocationAnomaly::Teleport {
+                distance_meters: distance.round(),
+                speed_mps: (speed * 10.0).round() / 10.0,
+            });
+        }
+    }
+
+    let gap = next.timestamp - previous.timestamp;
+    if gap > max_gap_secs {
+        anomalies.push(LocationAnomaly::Gap { seconds: gap });
+    }
+
+    anomalies
+}
+
+fn rfc3339(ts: i64) -> String {
+    chrono::DateTime::from_timestamp(ts, 0)
+        .map(|dt| dt.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+        .unwrap_or_default()
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+// GPX 1.1 document with a single track segment
+pub fn to_gpx(delivery_id: &str, pings: &[LocationPing]) -> String {
+    let mut gpx = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+        "<gpx version=\"1.1\" creator=\"nostr-delivery\" xmlns=\"http://www.topografix.com/GPX/1/1\">\n",
+    ));
+    gpx.push_str(&format!("  <trk>\n    <name>{}</name>\n    <trkseg>\n", xml_escape(delivery_id)));
+    for ping in pings {
+        gpx.push_str(&format!(
+            "      <trkpt lat=\"{}\" lon=\"{}\"><time>{}</time></trkpt>\n",
+            ping.lat,
+            ping.lng,
+            rfc3339(ping.timestamp)
+        ));
+    }
+    gpx.push_str("    </trkseg>\n  </trk>\n</gpx>\n");
+    gpx
+}
+
+// GeoJSON FeatureCollection with the track as a LineString (per-point times in coordTimes)
+pub fn to_geojson(delivery_id: &str, pings: &[LocationPing]) -> serde_json::Value {
+    let coordinates: Vec<[f64; 2]> = pings.iter().map(|p| [p.lng, p.lat]).collect();
+    let times: Vec<String> = pings.iter().map(|p| rfc3339(p.timestamp)).collect();
+    let anomalies: Vec<serde_json::Value> = pings.iter()
+        .filter(|p| !p.anomalies.is_empty())
+        .map(|p| json!({ "at": p.timestamp, "coordinates": [p.lng, p.lat], "anomalies": p.anomalies }))
+        .collect();
+
+    json!({
+        "type": "FeatureCollection",
+        "features": [{
+            "type": "Feature",
+            "geometry": {
+                "type": "LineString",
+                "coordinates": coordinates,
+            },
+            "properties": {
+                "delivery_id": delivery_id,
+                "courier": pings.first().map(|p| p.courier.clone()),
+                "started_at": pings.first().map(|p| p.timestamp),
+                "ended_at": pings.last().map(|p| p.timestamp),
+                "point_count": pings.len(),
+                "coordTimes": times,
+                "anomalies": anomalies,
+            }
+        }]
+    })
+}
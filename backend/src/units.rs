@@ -0,0 +1,99 @@
+// units.rs - Distances and weights in the caller's unit system. Deliveries always store metric
+// (`distance_meters`, package `weight` in kg); responses add converted `display_*` values.
+use serde::Serialize;
+use serde_json::Value;
+use utoipa::ToSchema;
+
+use crate::settings::UnitSystem;
+
+const METERS_PER_MILE: f64 = 1609.344;
+const POUNDS_PER_KG: f64 = 2.204_622_6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, ToSchema)]
+pub struct Quantity {
+    pub value: f64,
+    // "km", "mi", "kg" or "lb"
+    pub unit: &'static str,
+}
+
+fn round2(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+pub fn distance(meters: f64, system: UnitSystem) -> Quantity {
+    match system {
+        UnitSystem::Metric => Quantity { value: round2(meters / 1000.0), unit: "km" },
+        UnitSystem::Imperial => Quantity { value: round2(meters / METERS_PER_MILE), unit: "mi" },
+    }
+}
+
+pub fn weight(kg: f64, system: UnitSystem) -> Quantity {
+    match system {
+        UnitSystem::Metric => Quantity { value: round2(kg), unit: "kg" },
+        UnitSystem::Imperial => Quantity { value: round2(kg * POUNDS_PER_KG), unit: "lb" },
+    }
+}
+
+// Add `display_distance` to a serialized delivery and `display_weight` to each of its packages.
+// Works on sparse fieldsets too: only the fields present are converted.
+pub fn annotate_delivery(delivery: &mut Value, system: UnitSystem) {
+    let Some(object) = delivery.as_object_mut() else {
+        return;
+    };
+
+    if let Some(meters) = object.get("distance_meters").and_then(Value::as_f64) {
+        object.insert("display_distance".to_string(), serde_json::to_value(distance(meters, system)).unwrap_or_default());
+    }
+    if let Some(packages) = object.get_mut("packages").and_then(Value::as_array_mut) {
+        for package in packages.iter_mut().filter_map(Value::as_object_mut) {
+            if let Some(kg) = package.get("weight").and_then(Value::as_f64) {
+                package.insert("display_weight".to_string(), serde_json::to_value(weight(kg, system)).unwrap_or_default());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn distances_convert_and_round_to_two_places() {
+        assert_eq!(distance(1234.0, UnitSystem::Metric), Quantity { value: 1.23, unit: "km" });
+        assert_eq!(distance(1235.0, UnitSystem::Metric), Quantity { value: 1.24, unit: "km" });
+        assert_eq!(distance(METERS_PER_MILE, UnitSystem::Imperial), Quantity { value: 1.0, unit: "mi" });
+        assert_eq!(distance(1000.0, UnitSystem::Imperial), Quantity { value: 0.62, unit: "mi" });
+        assert_eq!(distance(0.0, UnitSystem::Imperial).value, 0.0);
+    }
+
+    #[test]
+    fn weights_convert_and_round_to_two_places() {
+        assert_eq!(weight(2.345, UnitSystem::Metric), Quantity { value: 2.35, unit: "kg" });
+        assert_eq!(weight(1.0, UnitSystem::Imperial), Quantity { value: 2.2, unit: "lb" });
+        assert_eq!(weight(0.001, UnitSystem::Imperial), Quantity { value: 0.0, unit: "lb" });
+    }
+
+    #[test]
+    fn annotates_only_the_fields_present() {
+        let mut delivery = json!({
+            "id": "delivery_1",
+            "distance_meters": 1609.344,
+            "packages": [{ "weight": 1.0 }, { "size": "small" }],
+        });
+        annotate_delivery(&mut delivery, UnitSystem::Imperial);
+
+        assert_eq!(delivery["display_distance"], json!({ "value": 1.0, "unit": "mi" }));
+        assert_eq!(delivery["packages"][0]["display_weight"], json!({ "value": 2.2, "unit": "lb" }));
+        assert!(delivery["packages"][1].get("display_weight").is_none());
+
+        // Sparse fieldsets without distance or packages are left alone
+        let mut sparse = json!({ "id": "delivery_1" });
+        annotate_delivery(&mut sparse, UnitSystem::Metric);
+        assert_eq!(sparse, json!({ "id": "delivery_1" }));
+
+        let mut not_a_delivery = json!([1, 2]);
+        annotate_delivery(&mut not_a_delivery, UnitSystem::Metric);
+        assert_eq!(not_a_delivery, json!([1, 2]));
+    }
+}
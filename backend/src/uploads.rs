@@ -0,0 +1,152 @@
+// uploads.rs - Content-addressed image storage for package photos: files are stored on disk
+// under their sha256, which is what deliveries reference
+use nostr::hashes::{sha256, Hash};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+pub type UploadError = Box<dyn std::error::Error + Send + Sync>;
+
+pub const MAX_UPLOAD_BYTES: usize = 5 * 1024 * 1024;
+pub const MAX_PACKAGE_PHOTOS: usize = 6;
+// Each uploader's quota runs over this window
+pub const QUOTA_WINDOW_SECS: i64 = 86400;
+pub const DEFAULT_QUOTA_FILES: usize = 50;
+pub const DEFAULT_QUOTA_BYTES: usize = 100 * 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Upload {
+    pub sha256: String,
+    pub url: String,
+    pub size: usize,
+    pub content_type: String,
+    // npub of the signed-in user who uploaded it
+    pub uploader: String,
+}
+
+#[derive(Debug)]
+pub enum UploadRefusal {
+    // Not an acceptable image, or it couldn't be stored
+    Invalid(String),
+    // The uploader has used up their quota for now
+    OverQuota(String),
+}
+
+// Files and bytes one uploader stored in the current window
+#[derive(Debug, Clone, Copy)]
+struct Usage {
+    window_start: i64,
+    files: usize,
+    bytes: usize,
+}
+
+// Image types accepted, by their magic bytes rather than the client's Content-Type
+const IMAGE_TYPES: &[(&[u8], &str, &str)] = &[
+    (b"\xFF\xD8\xFF", "image/jpeg", "jpg"),
+    (b"\x89PNG\r\n\x1a\n", "image/png", "png"),
+    (b"GIF87a", "image/gif", "gif"),
+    (b"GIF89a", "image/gif", "gif"),
+];
+
+fn image_type(bytes: &[u8]) -> Option<(&'static str, &'static str)> {
+    if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        return Some(("image/webp", "webp"));
+    }
+    IMAGE_TYPES.iter().find(|(magic, _, _)| bytes.starts_with(magic)).map(|(_, mime, ext)| (*mime, *ext))
+}
+
+pub fn is_sha256(hash: &str) -> bool {
+    hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit() && !c.is_ascii_uppercase())
+}
+
+#[derive(Debug, Clone)]
+pub struct UploadStore {
+    dir: PathBuf,
+    // Base of the public download URLs (PUBLIC_URL)
+    public_url: String,
+    quota_files: usize,
+    quota_bytes: usize,
+    usage: Arc<tokio::sync::Mutex<HashMap<String, Usage>>>,
+}
+
+impl UploadStore {
+    // Files go to UPLOAD_DIR (default: ./uploads). Each uploader may store UPLOAD_QUOTA_FILES
+    // files (default 50) and UPLOAD_QUOTA_BYTES bytes (default 100 MiB) a day.
+    pub fn from_env(public_url: &str) -> Self {
+        let limit = |name: &str, default: usize| std::env::var(name).ok().and_then(|v| v.parse().ok()).filter(|v| *v > 0).unwrap_or(default);
+        Self {
+            dir: PathBuf::from(std::env::var("UPLOAD_DIR").ok().filter(|d| !d.is_empty()).unwrap_or_else(|| "uploads".to_string())),
+            public_url: public_url.trim_end_matches('/').to_string(),
+            quota_files: limit("UPLOAD_QUOTA_FILES", DEFAULT_QUOTA_FILES),
+            quota_bytes: limit("UPLOAD_QUOTA_BYTES", DEFAULT_QUOTA_BYTES),
+            usage: Arc::new(tokio::sync::Mutex::new(HashMap::new())),
+        }
+    }
+
+    // Counts the upload against the uploader's quota, unless it would go over
+    async fn charge(&self, uploader: &str, size: usize, now: i64) -> Result<(), UploadRefusal> {
+        let mut usage = self.usage.lock().await;
+        usage.retain(|_, u| now - u.window_start < QUOTA_WINDOW_SECS);
+        let used = usage.entry(uploader.to_string()).or_insert(Usage { window_start: now, files: 0, bytes: 0 });
+        if used.files + 1 > self.quota_files || used.bytes + size > self.quota_bytes {
+            return Err(UploadRefusal::OverQuota(format!(
+                "Upload quota reached: {} files or {} bytes per {} hours",
+                self.quota_files, self.quota_bytes, QUOTA_WINDOW_SECS / 3600,
+            )));
+        }
+        used.files += 1;
+        used.bytes += size;
+        Ok(())
+    }
+
+    pub fn url(&self, hash: &str) -> String {
+        format!("{}/api/v1/uploads/{}", self.public_url, hash)
+    }
+
+    // Store an image for `uploader`, returning its hash; storing the same bytes again is a no-op.
+    // Whoever first stored a file is recorded next to it, in `<hash>.uploader`.
+    pub async fn put(&self, bytes: &[u8], uploader: &str, now: i64) -> Result<Upload, UploadRefusal> {
+        if bytes.is_empty() || bytes.len() > MAX_UPLOAD_BYTES {
+            return Err(UploadRefusal::Invalid(format!("Uploads must be between 1 byte and {} bytes", MAX_UPLOAD_BYTES)));
+        }
+        let Some((content_type, ext)) = image_type(bytes) else {
+            return Err(UploadRefusal::Invalid("Only JPEG, PNG, GIF and WebP images can be uploaded".to_string()));
+        };
+        self.charge(uploader, bytes.len(), now).await?;
+
+        let hash = sha256::Hash::hash(bytes).to_string();
+        let path = self.dir.join(format!("{}.{}", hash, ext));
+        if tokio::fs::metadata(&path).await.is_err() {
+            let failed = |e: std::io::Error| UploadRefusal::Invalid(format!("Failed to store upload: {}", e));
+            tokio::fs::create_dir_all(&self.dir).await.map_err(failed)?;
+            tokio::fs::write(self.dir.join(format!("{}.uploader", hash)), uploader).await.map_err(failed)?;
+            // Written under a temporary name so a partial file is never served
+            let partial = self.dir.join(format!("{}.part", hash));
+            tokio::fs::write(&partial, bytes).await.map_err(failed)?;
+            tokio::fs::rename(&partial, &path).await.map_err(failed)?;
+        }
+
+        Ok(Upload { url: self.url(&hash), sha256: hash, size: bytes.len(), content_type: content_type.to_string(), uploader: uploader.to_string() })
+    }
+
+    // Bytes and content type of a stored upload
+    pub async fn get(&self, hash: &str) -> Result<Option<(Vec<u8>, &'static str)>, UploadError> {
+        if !is_sha256(hash) {
+            return Ok(None);
+        }
+        for (mime, ext) in [("image/jpeg", "jpg"), ("image/png", "png"), ("image/gif", "gif"), ("image/webp", "webp")] {
+            match tokio::fs::read(self.dir.join(format!("{}.{}", hash, ext))).await {
+                Ok(bytes) => return Ok(Some((bytes, mime))),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(None)
+    }
+
+    pub async fn exists(&self, hash: &str) -> Result<bool, UploadError> {
+        Ok(self.get(hash).await?.is_some())
+    }
+}
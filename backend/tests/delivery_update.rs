@@ -0,0 +1,90 @@
+// Status update payloads as published in kinds 35002-35006 survive a serde round trip
+use nostr_delivery_backend::{
+    DeliveryStatus, DeliveryUpdate, GeoPoint, ProofOfDelivery, RatingBreakdown, ACCEPTED_KIND, COMPLETED_KIND,
+    CONFIRMED_KIND, IN_TRANSIT_KIND, STARTED_KIND,
+};
+use serde_json::{json, Value};
+
+fn round_trip(update: &DeliveryUpdate) -> Value {
+    let encoded = serde_json::to_value(update).unwrap();
+    let decoded: DeliveryUpdate = serde_json::from_value(encoded.clone()).unwrap();
+    assert_eq!(serde_json::to_value(&decoded).unwrap(), encoded);
+    encoded
+}
+
+fn proof() -> ProofOfDelivery {
+    ProofOfDelivery::new(1_700_000_100)
+        .images(vec!["https://example.com/photo.jpg".to_string()])
+        .signature_name(Some("Alice".to_string()))
+        .location(Some(GeoPoint { lat: 40.7128, lng: -74.006 }))
+        .comments(Some("Left with the doorman".to_string()))
+}
+
+#[test]
+fn accepted_carries_only_the_bid() {
+    let encoded = round_trip(&DeliveryUpdate::accepted("bid_1", 1_700_000_000));
+    assert_eq!(encoded, json!({"status": "accepted", "timestamp": 1_700_000_000, "accepted_bid": "bid_1"}));
+}
+
+#[test]
+fn in_transit_is_a_bare_transition() {
+    let encoded = round_trip(&DeliveryUpdate::in_transit(1_700_000_000));
+    assert_eq!(encoded, json!({"status": "intransit", "timestamp": 1_700_000_000}));
+}
+
+#[test]
+fn completed_keeps_the_proof() {
+    let update = DeliveryUpdate::completed(Some(proof()), 1_700_000_100, 1_700_000_200);
+    let encoded = round_trip(&update);
+    assert_eq!(encoded["completed_at"], 1_700_000_100);
+    assert_eq!(encoded["proof_of_delivery"]["signature_name"], "Alice");
+    assert_eq!(encoded["proof_of_delivery"]["location"]["lng"], -74.006);
+    assert!(encoded.get("accepted_bid").is_none());
+}
+
+#[test]
+fn confirmed_keeps_rating_and_feedback() {
+    let ratings = RatingBreakdown { timeliness: 5.0, care: 4.0, communication: 3.0 };
+    let update = DeliveryUpdate::confirmed(1_700_000_300)
+        .rating(Some(4.0), Some(ratings))
+        .feedback(Some("Great".to_string()));
+    let encoded = round_trip(&update);
+    assert_eq!(encoded["sender_rating"], 4.0);
+    assert_eq!(encoded["sender_ratings"]["care"], 4.0);
+    assert_eq!(encoded["sender_feedback"], "Great");
+}
+
+#[test]
+fn reads_updates_with_explicit_nulls() {
+    let update: DeliveryUpdate = serde_json::from_value(json!({
+        "status": "completed",
+        "timestamp": 1_700_000_000,
+        "proof_of_delivery": null,
+        "completed_at": 1_700_000_000,
+        "accepted_bid": null,
+        "sender_rating": null,
+        "sender_feedback": null
+    }))
+    .unwrap();
+    assert_eq!(update.status, DeliveryStatus::Completed);
+    assert_eq!(update.completed_at, Some(1_700_000_000));
+    assert!(update.sender_ratings.is_none());
+}
+
+#[test]
+fn kinds_map_to_statuses_and_back() {
+    for kind in [ACCEPTED_KIND, IN_TRANSIT_KIND, COMPLETED_KIND, CONFIRMED_KIND] {
+        let status = DeliveryUpdate::status_for_kind(kind).unwrap();
+        assert_eq!(DeliveryUpdate::kind_for(&status), Some(kind));
+    }
+    assert_eq!(DeliveryUpdate::status_for_kind(STARTED_KIND), Some(DeliveryStatus::Open));
+    assert_eq!(DeliveryUpdate::kind_for(&DeliveryStatus::Disputed), None);
+    assert_eq!(DeliveryUpdate::status_for_kind(35000), None);
+}
+
+#[test]
+fn proof_round_trips() {
+    let encoded = serde_json::to_value(proof()).unwrap();
+    let decoded: ProofOfDelivery = serde_json::from_value(encoded.clone()).unwrap();
+    assert_eq!(serde_json::to_value(decoded).unwrap(), encoded);
+}
@@ -0,0 +1,45 @@
+// A delivery goes from creation through a bid and its acceptance to completion against the
+// in-process relay, and each step reads back from the relay as published
+use nostr::nips::nip19::ToBech32;
+use nostr::Keys;
+use nostr_delivery_backend::testing::{BidBuilder, DeliveryBuilder, TestRelay};
+use nostr_delivery_backend::{DeliveryStatus, ProofOfDelivery};
+
+fn npub() -> String {
+    Keys::generate().public_key().to_bech32().unwrap()
+}
+
+#[tokio::test]
+async fn create_bid_accept_complete() {
+    let relay = TestRelay::start().await.unwrap();
+    let data = relay.app_state().await.unwrap();
+    let service = data.delivery_service();
+    let (sender, courier) = (npub(), npub());
+
+    let delivery = DeliveryBuilder::new(&sender).build();
+    service.create(&delivery).await.unwrap();
+    let stored = data.get_delivery_by_id(&delivery.id).await.unwrap().expect("created delivery on the relay");
+    assert_eq!(stored.status, DeliveryStatus::Open);
+    assert_eq!(stored.sender, sender);
+
+    let now = chrono::Utc::now().timestamp();
+    let (bid, conflicts) = service.bid(&stored, BidBuilder::new(&courier).build(), now).await.unwrap();
+    assert!(conflicts.is_empty());
+    let stored = data.get_delivery_by_id(&delivery.id).await.unwrap().unwrap();
+    assert_eq!(stored.bids.len(), 1);
+    assert_eq!(stored.bids[0].id, bid.id);
+
+    let accepted = service.accept(stored, 0, now).await.unwrap();
+    assert_eq!(accepted.status, DeliveryStatus::Accepted);
+    let stored = data.get_delivery_by_id(&delivery.id).await.unwrap().unwrap();
+    assert_eq!(stored.status, DeliveryStatus::Accepted);
+    assert_eq!(stored.accepted_bid.as_deref(), Some(bid.id.as_str()));
+    assert_eq!(stored.offer_amount, bid.amount);
+
+    let proof = ProofOfDelivery::new(now).signature_name(Some("Recipient".to_string()));
+    service.complete(stored, proof, now).await.unwrap();
+    let stored = data.get_delivery_by_id(&delivery.id).await.unwrap().unwrap();
+    assert_eq!(stored.status, DeliveryStatus::Completed);
+    assert_eq!(stored.completed_at, Some(now));
+    assert_eq!(stored.proof_of_delivery.and_then(|p| p.signature_name).as_deref(), Some("Recipient"));
+}
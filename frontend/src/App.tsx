@@ -64,7 +64,8 @@ interface DeliveryBid {
   id: string;
   courier: string;
   amount: number;
-  estimated_time: string;
+  pickup_eta?: number; // Unix seconds
+  dropoff_eta?: number;
   reputation: number;
   completed_deliveries: number;
   message?: string;
@@ -630,7 +631,8 @@ export default function DeliveryApp() {
       await api.placeBid(requestId, {
         courier: userProfile.npub,
         amount,
-        estimated_time: '1-2 hours',
+        pickup_eta_minutes: 60,
+        dropoff_eta_minutes: 120,
         message: ''
       });
       alert('Bid placed successfully!');
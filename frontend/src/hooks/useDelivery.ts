@@ -19,7 +19,8 @@ interface DeliveryBid {
   id: string;
   courier: string;
   amount: number;
-  estimated_time: string;
+  pickup_eta?: number; // Unix seconds
+  dropoff_eta?: number;
   reputation: number;
   completed_deliveries: number;
   message?: string;
@@ -184,7 +185,8 @@ export function useDelivery(): UseDeliveryReturn {
         body: JSON.stringify({
           courier: userNpub,
           amount,
-          estimated_time: '1-2 hours',
+          pickup_eta_minutes: 60,
+          dropoff_eta_minutes: 120,
           message
         })
       });
@@ -250,7 +252,8 @@ export function useDelivery(): UseDeliveryReturn {
   // Update delivery status
   const updateStatus = useCallback(async (
     deliveryId: string,
-    status: string
+    status: string,
+    pickupCode?: string
   ): Promise<boolean> => {
     setLoading(true);
     setError(null);
@@ -259,7 +262,7 @@ export function useDelivery(): UseDeliveryReturn {
       const response = await fetch(`${API_URL}/api/deliveries/${deliveryId}/status`, {
         method: 'PATCH',
         headers: { 'Content-Type': 'application/json' },
-        body: JSON.stringify({ status })
+        body: JSON.stringify({ status, pickup_code: pickupCode })
       });
 
       if (!response.ok) {
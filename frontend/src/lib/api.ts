@@ -118,8 +118,8 @@ class ApiClient {
     return this.post(`/api/deliveries/${deliveryId}/accept/${bidIndex}`);
   }
 
-  async updateDeliveryStatus(deliveryId: string, status: string): Promise<any> {
-    return this.patch(`/api/deliveries/${deliveryId}/status`, { status });
+  async updateDeliveryStatus(deliveryId: string, status: string, pickupCode?: string): Promise<any> {
+    return this.patch(`/api/deliveries/${deliveryId}/status`, { status, pickup_code: pickupCode });
   }
 
   async confirmDelivery(deliveryId: string, rating?: number): Promise<any> {
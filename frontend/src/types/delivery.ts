@@ -66,7 +66,8 @@ export interface DeliveryBid {
   id: string;
   courier: string; // npub
   amount: number; // in sats
-  estimated_time: string;
+  pickup_eta?: number; // Unix seconds
+  dropoff_eta?: number;
   reputation: number;
   completed_deliveries: number;
   message?: string;
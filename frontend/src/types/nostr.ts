@@ -149,7 +149,8 @@ export interface DeliveryBidContent {
   delivery_id: string;
   courier: string;
   amount: number;
-  estimated_time: string;
+  pickup_eta?: number; // Unix seconds
+  dropoff_eta?: number;
   message?: string;
   reputation: number;
   completed_deliveries: number;